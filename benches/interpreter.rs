@@ -0,0 +1,39 @@
+//! Interpreter throughput on the `rum::bench_fixtures` programs and midmark:
+//! `cargo bench`. Throughput is reported in instructions per second.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rum::bench::Workload;
+use rum::bench_fixtures;
+use rum::embed::Machine;
+
+/// Runs `program` to Halt with no input and returns the instructions executed.
+fn run(program: &[u32]) -> u64 {
+    let mut machine = Machine::new(program.to_vec());
+    machine
+        .run(None, &mut || None, &mut |byte| {
+            black_box(byte);
+        })
+        .unwrap();
+    machine.executed()
+}
+
+fn fixtures(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fixtures");
+    for fixture in bench_fixtures::all() {
+        group.throughput(Throughput::Elements(run(&fixture.program)));
+        group.bench_function(fixture.name, |b| b.iter(|| run(&fixture.program)));
+    }
+    group.finish();
+}
+
+fn midmark(c: &mut Criterion) {
+    let midmark = Workload::builtin("midmark").unwrap();
+    let mut group = c.benchmark_group("reference");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(run(&midmark.program)));
+    group.bench_function("midmark", |b| b.iter(|| run(&midmark.program)));
+    group.finish();
+}
+
+criterion_group!(benches, fixtures, midmark);
+criterion_main!(benches);
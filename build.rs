@@ -0,0 +1,13 @@
+fn main() {
+    // Node addons leave the N-API symbols for the node binary to provide.
+    #[cfg(feature = "node")]
+    napi_build::setup();
+
+    // protox compiles the service definition without needing protoc installed.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/rum.proto");
+        let descriptors = protox::compile(["proto/rum.proto"], ["proto"]).expect("proto/rum.proto does not compile");
+        tonic_build::configure().build_client(true).compile_fds(descriptors).expect("cannot generate the gRPC service");
+    }
+}
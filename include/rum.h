@@ -0,0 +1,64 @@
+/* C interface to the rum Universal Machine interpreter (see src/ffi.rs). */
+#ifndef RUM_H
+#define RUM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RUM_RUNNING 0
+#define RUM_HALTED 1
+#define RUM_FAULT (-1)
+
+typedef struct RumMachine rum_machine;
+
+/* Returns the next input byte (0-255), or a negative value at end of input. */
+typedef int (*rum_input_fn)(void *user);
+/* Receives each byte the program outputs. */
+typedef void (*rum_output_fn)(void *user, uint8_t byte);
+
+rum_machine *rum_machine_new(void);
+void rum_machine_free(rum_machine *m);
+
+/* Loads a program as big-endian words (a .um file). Returns 0 or -1. */
+int rum_machine_load(rum_machine *m, const uint8_t *bytes, size_t len);
+/* Either callback may be NULL: no input reads as end of input, no output is dropped. */
+void rum_machine_set_io(rum_machine *m, rum_input_fn input, rum_output_fn output, void *user);
+
+/* Both return RUM_RUNNING, RUM_HALTED or RUM_FAULT. A fuel of 0 runs until Halt. */
+int rum_machine_step(rum_machine *m);
+int rum_machine_run(rum_machine *m, uint64_t fuel);
+uint64_t rum_machine_executed(const rum_machine *m);
+
+uint32_t rum_machine_register(const rum_machine *m, uint32_t r);
+int rum_machine_set_register(rum_machine *m, uint32_t r, uint32_t value);
+uint64_t rum_machine_pc(const rum_machine *m);
+
+size_t rum_machine_segment_len(const rum_machine *m, uint32_t segment);
+int rum_machine_read(const rum_machine *m, uint32_t segment, uint32_t offset, uint32_t *out);
+int rum_machine_write(rum_machine *m, uint32_t segment, uint32_t offset, uint32_t value);
+
+/* After RUM_FAULT: the faulting instruction's address (returns 0, or -1 with no
+   fault) and what went wrong (NULL with no fault; valid until the next load or free). */
+int rum_machine_fault_pc(const rum_machine *m, uint64_t *out);
+const char *rum_machine_fault_message(const rum_machine *m);
+
+/* Fuzzing harnesses (see src/fuzz.rs), each a ready LLVMFuzzerTestOneInput body
+   returning 0. Runs are bounded in instructions and memory; faults are ignored. */
+int rum_fuzz_program(const uint8_t *data, size_t size);
+/* Feeds data as input to the program from rum_fuzz_set_program, or else the
+   .um file named by the RUM_FUZZ_PROGRAM environment variable. */
+int rum_fuzz_input(const uint8_t *data, size_t size);
+void rum_fuzz_set_program(const uint8_t *data, size_t size);
+/* The AFL-style edge coverage map of the last harnessed run; sets *size to
+   its length (65536). Only in builds with the edge-coverage feature. */
+const uint8_t *rum_fuzz_edge_map(size_t *size);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
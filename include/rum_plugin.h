@@ -0,0 +1,50 @@
+/* Plugin interface for rum (see src/plugin.rs), loaded with `rum run --plugin lib.so`. */
+#ifndef RUM_PLUGIN_H
+#define RUM_PLUGIN_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RUM_PLUGIN_ABI 1
+
+/* What on_opcode did with an instruction. */
+#define RUM_PLUGIN_HANDLED 0
+#define RUM_PLUGIN_UNHANDLED 1
+#define RUM_PLUGIN_FAULT (-1)
+
+/* Every callback may be NULL. `user` is passed back to each of them. */
+typedef struct RumPlugin {
+    uint32_t abi; /* RUM_PLUGIN_ABI */
+    const char *name;
+    void *user;
+
+    /* Tracer: called before every instruction with its address and word. */
+    void (*on_instruction)(void *user, uint32_t pc, uint32_t instruction);
+
+    /* Opcode extension: called for opcodes 14 and 15, which the UM leaves
+       undefined. May change the registers; returns RUM_PLUGIN_HANDLED,
+       RUM_PLUGIN_UNHANDLED (let the next plugin try) or RUM_PLUGIN_FAULT. */
+    int (*on_opcode)(void *user, uint32_t instruction, uint32_t registers[8]);
+
+    /* Memory-mapped device: Load and Store on segment `device_segment`
+       (0 for none) go to these instead of memory. rum reserves the
+       identifier, so Map Segment never returns it to the program. */
+    uint32_t device_segment;
+    uint32_t (*device_read)(void *user, uint32_t offset);
+    void (*device_write)(void *user, uint32_t offset, uint32_t value);
+
+    /* Called once when the plugin is unloaded. */
+    void (*destroy)(void *user);
+} RumPlugin;
+
+/* The entry point every plugin exports. Returns NULL to refuse to load. */
+const RumPlugin *rum_plugin(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,15 @@
+import { EventEmitter } from "events";
+
+export declare class Machine extends EventEmitter {
+  constructor(program: Uint8Array, options?: { slice?: number });
+  feed(bytes: Uint8Array | string): void;
+  closeInput(): void;
+  run(): Promise<number>;
+  readonly halted: boolean;
+  readonly executed: number;
+  readonly pc: number;
+  readonly registers: number[];
+  segment(index: number): number[];
+  on(event: "output", listener: (bytes: Buffer) => void): this;
+  on(event: "input" | "halt", listener: () => void): this;
+}
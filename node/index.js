@@ -0,0 +1,52 @@
+// Event-driven wrapper around the N-API addon built by `cargo build --release --features node`
+// (copy target/release/librum.so, or rum.dll / librum.dylib, to node/rum.node).
+const { EventEmitter } = require("events");
+const native = require("./rum.node");
+
+// Emits "output" (a Buffer), "input" (waiting for feed) and "halt".
+class Machine extends EventEmitter {
+  constructor(program, { slice = 100000 } = {}) {
+    super();
+    this.inner = new native.Machine(Buffer.from(program));
+    this.slice = slice;
+    this.wake = null;
+  }
+
+  feed(bytes) {
+    this.inner.feed(Buffer.from(bytes));
+    if (this.wake) this.wake();
+  }
+
+  closeInput() {
+    this.inner.closeInput();
+    if (this.wake) this.wake();
+  }
+
+  // Runs until Halt in slices of `slice` instructions, yielding to the event
+  // loop between slices. Resolves with the number of instructions executed.
+  async run() {
+    for (;;) {
+      const { status, output } = this.inner.run(this.slice);
+      if (output.length > 0) this.emit("output", output);
+      if (status === "halted") {
+        this.emit("halt");
+        return this.inner.executed;
+      }
+      if (status === "input") {
+        this.emit("input");
+        await new Promise((resolve) => (this.wake = resolve));
+        this.wake = null;
+      } else {
+        await new Promise(setImmediate);
+      }
+    }
+  }
+
+  get halted() { return this.inner.halted; }
+  get executed() { return this.inner.executed; }
+  get pc() { return this.inner.pc; }
+  get registers() { return this.inner.registers; }
+  segment(index) { return this.inner.segment(index); }
+}
+
+module.exports = { Machine };
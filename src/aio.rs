@@ -0,0 +1,109 @@
+//! Running a machine inside an async server (feature `async`) without
+//! holding an executor thread for the whole run.
+//!
+//! `run_async` executes instructions in batches, yielding to the tokio
+//! scheduler after each one, and does its I/O through `AsyncRead` /
+//! `AsyncWrite`: output is buffered within a batch and written out at its
+//! end, and an Input waits on the reader instead of blocking the thread.
+//!
+//! ```no_run
+//! # async fn serve(socket: impl tokio::io::AsyncRead + tokio::io::AsyncWrite) -> std::io::Result<()> {
+//! let (reader, writer) = tokio::io::split(socket);
+//! let mut machine = rum::embed::Machine::new(rum::load::load(Some("bin/advent.um"))?);
+//! let mut io = rum::aio::AsyncIo::new(tokio::io::BufReader::new(reader), writer);
+//! rum::aio::run_async(&mut machine, None, &mut io).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A fault comes back as the `MachineError` inside the `Ok`, after the
+//! output written before it, as from `embed::Machine::step`.
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::embed::{Machine, Status};
+use crate::error::MachineError;
+
+/// Instructions executed between yields unless `AsyncIo::yield_every` says otherwise.
+pub const YIELD_EVERY: u64 = 16_384;
+
+/// A machine's input and output as async streams.
+pub struct AsyncIo<R, W> {
+    input: R,
+    output: W,
+    yield_every: u64,
+    /// Output of the current batch, not yet written.
+    pending: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> AsyncIo<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output, yield_every: YIELD_EVERY, pending: Vec::new() }
+    }
+
+    /// Yields to the scheduler every `instructions` instructions (at least 1).
+    pub fn yield_every(mut self, instructions: u64) -> Self {
+        self.yield_every = instructions.max(1);
+        self
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.input, self.output)
+    }
+
+    /// The next input byte; a read error is end of input, as for `embed::Io`.
+    async fn read(&mut self) -> Option<u8> {
+        let mut byte = [0];
+        match self.input.read(&mut byte).await {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+
+    async fn flush(&mut self) -> std::io::Result<()> {
+        if !self.pending.is_empty() {
+            self.output.write_all(&self.pending).await?;
+            self.pending.clear();
+        }
+        self.output.flush().await
+    }
+}
+
+/// Executes up to `fuel` instructions of `machine` (all of them when
+/// `None`) with I/O through `io`, yielding every `io.yield_every`
+/// instructions. Stops early on Halt, or with `Status::Running` when the
+/// machine's cancellation token is cancelled (see `cancel`), or with the
+/// `MachineError` of a fault; fails only if writing the output does.
+pub async fn run_async<R, W>(machine: &mut Machine, fuel: Option<u64>, io: &mut AsyncIo<R, W>) -> std::io::Result<Result<Status, MachineError>>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut left = fuel;
+    while left != Some(0) && machine.status() == Status::Running && !machine.cancelled() {
+        let mut batch = left.map_or(io.yield_every, |n| n.min(io.yield_every));
+        let spent = batch;
+        while batch > 0 {
+            let result = if machine.wants_input() {
+                // Show the program's prompt before waiting for the answer.
+                io.flush().await?;
+                let byte = io.read().await;
+                machine.step(&mut || byte, &mut |_| {})
+            } else {
+                machine.step(&mut || None, &mut |byte| io.pending.push(byte))
+            };
+            match result {
+                Ok(Status::Halted) => break,
+                Ok(Status::Running) => batch -= 1,
+                Err(e) => {
+                    io.flush().await?;
+                    return Ok(Err(e));
+                }
+            }
+        }
+        left = left.map(|n| n - (spent - batch));
+        io.flush().await?;
+        tokio::task::yield_now().await;
+    }
+    Ok(Ok(machine.status()))
+}
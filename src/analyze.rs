@@ -0,0 +1,172 @@
+use crate::rumdis::{get, op, Opcode, RA, RB, RC, RL, VL};
+
+/// What is statically known about a register's value.
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum Value {
+    Known(u32),
+    /// Not known, but certainly not segment 0 (e.g. an identifier from MapSeg).
+    NonZero,
+    Unknown,
+}
+
+impl Value {
+    fn is_zero(self) -> Option<bool> {
+        match self {
+            Value::Known(v) => Some(v == 0),
+            Value::NonZero => Some(false),
+            Value::Unknown => None,
+        }
+    }
+
+    /// The most precise value covering both `self` and `other`.
+    fn join(self, other: Value) -> Value {
+        match (self.is_zero(), other.is_zero()) {
+            _ if self == other => self,
+            (Some(false), Some(false)) => Value::NonZero,
+            _ => Value::Unknown,
+        }
+    }
+}
+
+type Registers = [Value; 8];
+
+/// Result of statically analyzing segment 0 of a program.
+///
+/// Register values are tracked as constants (mostly `LoadVal` results) by a
+/// dataflow pass over segment 0, which is enough to resolve the usual
+/// "load target, load 0, LoadProg" jump idiom.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// Addresses that may be executed.
+    pub reachable: Vec<bool>,
+    /// Addresses that may be read or written as data at a known offset.
+    pub data: Vec<bool>,
+    /// Addresses of `LoadProg` instructions whose target could not be resolved.
+    pub dynamic_jumps: Vec<usize>,
+    /// Addresses of loads/stores that may touch segment 0 at an unknown offset.
+    pub dynamic_accesses: Vec<usize>,
+    /// `(site, target)` of the jumps to a known address made while another
+    /// register holds the address after the jump, the way calls pass their
+    /// return address.
+    pub calls: Vec<(usize, usize)>,
+}
+
+impl Analysis {
+    /// Number of leading words that can possibly be used, or `None` if
+    /// an unresolved jump or access means nothing can be proven.
+    pub fn extent(&self) -> Option<usize> {
+        if !self.dynamic_jumps.is_empty() || !self.dynamic_accesses.is_empty() {
+            return None;
+        }
+        Some(self.used_extent())
+    }
+
+    /// Like `extent`, but ignoring unresolved jumps and accesses.
+    pub fn used_extent(&self) -> usize {
+        let last = |marks: &[bool]| marks.iter().rposition(|&m| m).map_or(0, |i| i + 1);
+        last(&self.reachable).max(last(&self.data))
+    }
+}
+
+/// Given a program (the initial contents of segment 0), work out which
+/// words may be executed or accessed, starting from address 0.
+pub fn analyze(program: &[u32]) -> Analysis {
+    let mut analysis = Analysis {
+        reachable: vec![false; program.len()],
+        data: vec![false; program.len()],
+        dynamic_jumps: Vec::new(),
+        dynamic_accesses: Vec::new(),
+        calls: Vec::new(),
+    };
+    // Register facts on entry to each address, widened until nothing changes.
+    let mut entry: Vec<Option<Registers>> = vec![None; program.len()];
+    let mut worklist = Vec::new();
+    // The machine starts with every register zeroed.
+    flow(&mut entry, &mut worklist, 0, [Value::Known(0); 8]);
+
+    while let Some(pc) = worklist.pop() {
+        analysis.reachable[pc] = true;
+        let mut regs = entry[pc].unwrap();
+        let inst = program[pc];
+        let (a, b, c) = (get(&RA, inst) as usize, get(&RB, inst) as usize, get(&RC, inst) as usize);
+        let binary = |f: fn(u32, u32) -> u32, regs: &Registers| match (regs[b], regs[c]) {
+            (Value::Known(x), Value::Known(y)) => Value::Known(f(x, y)),
+            _ => Value::Unknown,
+        };
+        match op(inst) {
+            Some(Opcode::CMov) => match regs[c].is_zero() {
+                Some(true) => {}
+                Some(false) => regs[a] = regs[b],
+                None => regs[a] = regs[a].join(regs[b]),
+            },
+            Some(Opcode::SegLoad) => {
+                access(&mut analysis, pc, regs[b], regs[c]);
+                regs[a] = Value::Unknown;
+            }
+            Some(Opcode::SegStore) => access(&mut analysis, pc, regs[a], regs[b]),
+            Some(Opcode::Add) => regs[a] = binary(u32::wrapping_add, &regs),
+            Some(Opcode::Mul) => regs[a] = binary(u32::wrapping_mul, &regs),
+            Some(Opcode::Div) => regs[a] = match (regs[b], regs[c]) {
+                (Value::Known(x), Value::Known(y)) if y != 0 => Value::Known(x / y),
+                _ => Value::Unknown,
+            },
+            Some(Opcode::BNand) => regs[a] = binary(|x, y| !(x & y), &regs),
+            Some(Opcode::Halt) => continue,
+            Some(Opcode::MapSeg) => regs[b] = Value::NonZero,
+            Some(Opcode::UnmapSeg) | Some(Opcode::Output) => {}
+            Some(Opcode::Input) => regs[c] = Value::Unknown,
+            Some(Opcode::LoadProg) => {
+                match (regs[b].is_zero(), regs[c]) {
+                    // Replacing segment 0 ends the original program.
+                    (Some(false), _) => {}
+                    (Some(true), Value::Known(target)) => {
+                        if (0..8).any(|r| r != c && regs[r] == Value::Known(pc as u32 + 1)) {
+                            analysis.calls.push((pc, target as usize));
+                        }
+                        flow(&mut entry, &mut worklist, target as usize, regs)
+                    }
+                    _ => analysis.dynamic_jumps.push(pc),
+                }
+                continue;
+            }
+            Some(Opcode::LoadVal) => regs[get(&RL, inst) as usize] = Value::Known(get(&VL, inst)),
+            // Executing an invalid opcode fails the machine.
+            None => continue,
+        }
+        flow(&mut entry, &mut worklist, pc + 1, regs);
+    }
+    analysis.dynamic_jumps.sort_unstable();
+    analysis.dynamic_jumps.dedup();
+    analysis.dynamic_accesses.sort_unstable();
+    analysis.dynamic_accesses.dedup();
+    analysis.calls.sort_unstable();
+    analysis.calls.dedup();
+    analysis
+}
+
+/// Merges `regs` into the facts known on entry to address `to`,
+/// queueing it for (re)analysis if they changed.
+fn flow(entry: &mut [Option<Registers>], worklist: &mut Vec<usize>, to: usize, regs: Registers) {
+    let Some(slot) = entry.get_mut(to) else { return };
+    let joined = match slot {
+        Some(old) => std::array::from_fn(|i| old[i].join(regs[i])),
+        None => regs,
+    };
+    if *slot != Some(joined) {
+        *slot = Some(joined);
+        worklist.push(to);
+    }
+}
+
+/// Records a load/store of `segment[offset]` made by the instruction at `pc`.
+fn access(analysis: &mut Analysis, pc: usize, segment: Value, offset: Value) {
+    match (segment.is_zero(), offset) {
+        (Some(false), _) => {}
+        (Some(true), Value::Known(offset)) => {
+            if let Some(mark) = analysis.data.get_mut(offset as usize) {
+                *mark = true;
+            }
+        }
+        _ => analysis.dynamic_accesses.push(pc),
+    }
+}
@@ -0,0 +1,85 @@
+//! Assertions in UM programs: a convention for the two undefined opcodes
+//! that lets a program carry its own unit tests.
+//!
+//! | Word                         | Meaning                                              |
+//! |------------------------------|------------------------------------------------------|
+//! | `14 << 28 \| a << 25 \| value` | assert that register `a` equals `value` (25 bits)  |
+//! | `15 << 28 \| code`             | trap: fail with `code` (25 bits), e.g. from a check the program made itself |
+//!
+//! An assertion that holds does nothing more. A failed one, or a trap,
+//! stops the program with a fault naming the PC, e.g.
+//! `pc 12: assertion failed: r3 is 0x7, expected 0x5`. The `Assertions`
+//! plugin gives a `plugin::Host` this behaviour; `rum run --assertions` and
+//! `rum::testing` attach it, and other hosts can add it themselves. Without
+//! it the opcodes stay invalid, as the spec has them.
+
+use std::fmt;
+
+use crate::plugin::{Extension, Plugin};
+
+const VALUE: u32 = (1 << 25) - 1;
+
+/// The word asserting that register `a` holds `value` (below 2^25).
+pub fn assert_eq(a: u32, value: u32) -> u32 {
+    14 << 28 | (a & 7) << 25 | (value & VALUE)
+}
+
+/// The word that traps with `code` (below 2^25).
+pub fn trap(code: u32) -> u32 {
+    15 << 28 | (code & VALUE)
+}
+
+/// A failed assertion or a trap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub pc: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pc {}: {}", self.pc, self.message)
+    }
+}
+
+/// Carries out `instruction` at `pc` if it is an assertion or a trap:
+/// `None` when it is neither.
+pub fn evaluate(pc: usize, instruction: u32, registers: &[u32; 8]) -> Option<Result<(), Failure>> {
+    let failure = |message| Err(Failure { pc, message });
+    match instruction >> 28 {
+        14 => {
+            let (a, expected) = ((instruction >> 25) & 7, instruction & VALUE);
+            let actual = registers[a as usize];
+            Some(match actual == expected {
+                true => Ok(()),
+                false => failure(format!("assertion failed: r{a} is {actual:#x}, expected {expected:#x}")),
+            })
+        }
+        15 => Some(failure(format!("trap {}", instruction & VALUE))),
+        _ => None,
+    }
+}
+
+/// The plugin carrying out assertions and traps.
+#[derive(Debug, Default)]
+pub struct Assertions {
+    pc: usize,
+}
+
+impl Plugin for Assertions {
+    fn name(&self) -> &str {
+        "assertions"
+    }
+
+    fn on_instruction(&mut self, pc: usize, _instruction: u32) {
+        self.pc = pc;
+    }
+
+    fn on_opcode(&mut self, instruction: u32, registers: &mut [u32; 8]) -> Extension {
+        match evaluate(self.pc, instruction, registers) {
+            None => Extension::Unhandled,
+            Some(Ok(())) => Extension::Handled,
+            Some(Err(failure)) => Extension::Fault(failure.to_string()),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! The segment-0 write barrier: telling, before an instruction runs,
+//! whether it changes the program being executed.
+//!
+//! Two instructions do: a Store into segment 0, and a Load Program from
+//! another segment, which replaces segment 0 with a copy of it. Coverage,
+//! the profiler, self-modification logs and anything caching decoded
+//! instructions all need to see these, so they ask `code_write` rather than
+//! each decoding Stores and Load Programs themselves. Embedders get the
+//! same as a hook: `embed::Machine::step_with_barrier`, or
+//! `Plugin::on_code_write` for a plugin.
+
+use std::ops::Range;
+
+use crate::instruction::Instruction;
+use crate::state::UniversalMachine;
+
+/// A change to segment 0 an instruction is about to make.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodeWrite {
+    /// A Store over the word at `offset`.
+    Store { offset: usize, old: u32, new: u32 },
+    /// Load Program replacing segment 0 with a copy of segment `source`:
+    /// the addresses of the program before (`old`) and after (`new`).
+    Replace { source: u32, old: Range<usize>, new: Range<usize> },
+}
+
+impl CodeWrite {
+    /// The addresses of segment 0 whose contents the write changes, or
+    /// which stop existing: what a cache of segment 0 has to drop.
+    pub fn invalidated(&self) -> Range<usize> {
+        match self {
+            CodeWrite::Store { offset, .. } => *offset..offset + 1,
+            CodeWrite::Replace { old, new, .. } => 0..old.end.max(new.end),
+        }
+    }
+}
+
+/// Given the instruction `inst` about to run in `state`, the change it will
+/// make to segment 0, if any. A Store or Load Program that is going to
+/// fault (an offset past the end, a segment never mapped) makes none.
+pub fn code_write(inst: u32, state: &UniversalMachine) -> Option<CodeWrite> {
+    match Instruction::decode(inst) {
+        Instruction::Store { a, b, c } if state.register(a) == 0 => {
+            let offset = state.register(b) as usize;
+            let old = *state.mapped_memory.first()?.get(offset)?;
+            Some(CodeWrite::Store { offset, old, new: state.register(c) })
+        }
+        Instruction::LoadProgram { b, .. } if state.register(b) != 0 => {
+            let source = state.register(b);
+            let new = state.mapped_memory.get(source as usize)?.len();
+            Some(CodeWrite::Replace { source, old: 0..state.mapped_memory.first()?.len(), new: 0..new })
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,282 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::cache::ProgramCache;
+use crate::check::diff;
+use crate::embed::{Machine, Status as Running};
+use crate::error::MachineError;
+use crate::json::json_string;
+
+/// Instructions executed between checks of a case's timeout.
+const CHECK_EVERY: u64 = 1 << 16;
+
+/// One program to run, as described by a `[[program]]` table in the manifest.
+///
+/// Relative paths are resolved against the manifest's directory.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Case {
+    pub name: String,
+    pub program: PathBuf,
+    pub input: Option<PathBuf>,
+    pub expected: Option<PathBuf>,
+    /// Wall-clock limit in seconds.
+    pub timeout: Option<f64>,
+    pub max_instructions: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Status {
+    Pass,
+    /// The machine halted but its output differs from the expected file.
+    Fail,
+    /// The machine crashed or exited abnormally.
+    Error,
+    /// The machine hit its time or instruction limit.
+    Limit,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Pass => "pass",
+            Status::Fail => "fail",
+            Status::Error => "error",
+            Status::Limit => "limit",
+        }
+    }
+}
+
+/// Result of running a single `Case`.
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub name: String,
+    pub status: Status,
+    pub elapsed: Duration,
+    pub output_bytes: usize,
+    pub detail: String,
+}
+
+/// Parses a batch manifest.
+///
+/// The manifest is a small TOML subset: a list of `[[program]]` tables whose
+/// keys are `name`, `program`, `input`, `expected` (strings), `timeout`
+/// (seconds) and `max_instructions` (integer).
+pub fn parse_manifest(text: &str, base: &Path) -> Result<Vec<Case>, String> {
+    let mut cases: Vec<Case> = Vec::new();
+    for (number, raw) in text.lines().enumerate() {
+        let line = strip_comment(raw).trim();
+        let err = |msg: &str| format!("manifest line {}: {}", number + 1, msg);
+        if line.is_empty() {
+            continue;
+        }
+        if line == "[[program]]" {
+            cases.push(Case::default());
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| err("expected `key = value`"))?;
+        let case = cases.last_mut().ok_or_else(|| err("key outside of a [[program]] table"))?;
+        let value = value.trim();
+        match key.trim() {
+            "name" => case.name = string(value).ok_or_else(|| err("name must be a string"))?,
+            "program" => case.program = base.join(string(value).ok_or_else(|| err("program must be a string"))?),
+            "input" => case.input = Some(base.join(string(value).ok_or_else(|| err("input must be a string"))?)),
+            "expected" => case.expected = Some(base.join(string(value).ok_or_else(|| err("expected must be a string"))?)),
+            "timeout" => case.timeout = Some(value.replace('_', "").parse().map_err(|_| err("timeout must be a number"))?),
+            "max_instructions" => case.max_instructions = Some(value.replace('_', "").parse().map_err(|_| err("max_instructions must be an integer"))?),
+            other => return Err(err(&format!("unknown key `{other}`"))),
+        }
+    }
+    for (i, case) in cases.iter_mut().enumerate() {
+        if case.program.as_os_str().is_empty() {
+            return Err(format!("manifest program #{} has no `program` path", i + 1));
+        }
+        if case.name.is_empty() {
+            case.name = case.program.display().to_string();
+        }
+    }
+    Ok(cases)
+}
+
+/// Drops a trailing `# comment`, ignoring `#` inside strings.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, ch) in line.char_indices() {
+        match ch {
+            '\\' if in_string && !escaped => { escaped = true; continue; }
+            '"' if !escaped => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+/// Given a quoted TOML basic string, return its unescaped contents.
+fn string(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            '\\' => out.push('\\'),
+            '"' => out.push('"'),
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+/// How a case's machine stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exit {
+    Halted,
+    /// It executed `max_instructions`.
+    InstructionLimit,
+    /// It was still running at the `timeout`.
+    Timeout,
+    Faulted(MachineError),
+}
+
+/// What happened when a case's machine was run.
+#[derive(Debug)]
+pub struct Execution {
+    pub exit: Exit,
+    pub output: Vec<u8>,
+    /// Instructions executed.
+    pub executed: u64,
+    pub elapsed: Duration,
+}
+
+/// Runs one case on a machine of its own, enforcing its limits, with its
+/// input file as the whole input and its output collected.
+pub fn execute(case: &Case) -> Result<Execution, String> {
+    let start = Instant::now();
+    let bytes = std::fs::read(&case.program).map_err(|e| format!("cannot read program {}: {e}", case.program.display()))?;
+    let input = match &case.input {
+        Some(path) => std::fs::read(path).map_err(|e| format!("cannot open input {}: {e}", path.display()))?,
+        None => Vec::new(),
+    };
+    // As `rum run` loads programs: a trailing partial word is dropped.
+    let program = ProgramCache::global().words(&bytes).to_vec();
+    let mut machine = Machine::new(program);
+    let mut input = input.into_iter();
+    let mut output = Vec::new();
+    let deadline = case.timeout.map(|secs| start + Duration::from_secs_f64(secs));
+    let exit = loop {
+        let left = case.max_instructions.map_or(u64::MAX, |max| max - machine.executed());
+        if left == 0 {
+            break Exit::InstructionLimit;
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            break Exit::Timeout;
+        }
+        match machine.run(Some(left.min(CHECK_EVERY)), &mut || input.next(), &mut |byte| output.push(byte)) {
+            Ok(Running::Halted) => break Exit::Halted,
+            Ok(Running::Running) => {}
+            Err(e) => break Exit::Faulted(e),
+        }
+    };
+    Ok(Execution { exit, output, executed: machine.executed(), elapsed: start.elapsed() })
+}
+
+/// Runs one case and judges the result against its expected output.
+pub fn run_case(case: &Case) -> Outcome {
+    let outcome = |status, elapsed, output_bytes, detail: String| Outcome {
+        name: case.name.clone(),
+        status,
+        elapsed,
+        output_bytes,
+        detail,
+    };
+    let run = match execute(case) {
+        Ok(run) => run,
+        Err(e) => return outcome(Status::Error, Duration::ZERO, 0, e),
+    };
+    let (status, detail) = judge(case, &run);
+    outcome(status, run.elapsed, run.output.len(), detail)
+}
+
+/// Runs every case, `jobs` at a time, returning outcomes in manifest order.
+///
+/// Each case runs on a machine of its own with its own input and output,
+/// so cases can't see each other's I/O.
+pub fn run_all(cases: &[Case], jobs: usize) -> Vec<Outcome> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; cases.len()]);
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, cases.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(case) = cases.get(i) else { break };
+                let outcome = run_case(case);
+                results.lock().unwrap()[i] = Some(outcome);
+            });
+        }
+    });
+    results.into_inner().unwrap().into_iter().map(Option::unwrap).collect()
+}
+
+fn judge(case: &Case, run: &Execution) -> (Status, String) {
+    match &run.exit {
+        Exit::Halted => {}
+        Exit::InstructionLimit => return (Status::Limit, "instruction limit reached".to_string()),
+        Exit::Timeout => return (Status::Limit, format!("timed out after {}s", case.timeout.unwrap_or_default())),
+        Exit::Faulted(e) => return (Status::Error, format!("faulted after {} instructions: {e}", run.executed)),
+    }
+    let Some(path) = &case.expected else {
+        return (Status::Pass, String::new());
+    };
+    match std::fs::read(path) {
+        Err(e) => (Status::Error, format!("cannot read expected {}: {e}", path.display())),
+        Ok(expected) if expected == run.output => (Status::Pass, String::new()),
+        Ok(expected) => (Status::Fail, diff(&expected, &run.output)),
+    }
+}
+
+/// Renders the human-readable summary table. Only the first line of each
+/// detail is shown; the JSON report has all of it.
+pub fn table(outcomes: &[Outcome]) -> String {
+    let width = outcomes.iter().map(|o| o.name.len()).max().unwrap_or(0).max(4);
+    let mut out = format!("{:<width$}  {:<6}  {:>10}  detail\n", "name", "status", "time (s)");
+    for o in outcomes {
+        out += &format!("{:<width$}  {:<6}  {:>10.3}  {}\n", o.name, o.status.as_str(), o.elapsed.as_secs_f64(), o.detail.lines().next().unwrap_or_default());
+    }
+    let passed = outcomes.iter().filter(|o| o.status == Status::Pass).count();
+    out += &format!("\n{passed}/{} passed\n", outcomes.len());
+    out
+}
+
+/// Renders the machine-readable JSON report.
+pub fn json(outcomes: &[Outcome]) -> String {
+    let passed = outcomes.iter().filter(|o| o.status == Status::Pass).count();
+    let results: Vec<String> = outcomes
+        .iter()
+        .map(|o| {
+            format!(
+                "{{\"name\":{},\"status\":\"{}\",\"seconds\":{:.6},\"output_bytes\":{},\"detail\":{}}}",
+                json_string(&o.name),
+                o.status.as_str(),
+                o.elapsed.as_secs_f64(),
+                o.output_bytes,
+                json_string(&o.detail)
+            )
+        })
+        .collect();
+    format!(
+        "{{\"total\":{},\"passed\":{},\"failed\":{},\"results\":[{}]}}\n",
+        outcomes.len(),
+        passed,
+        outcomes.len() - passed,
+        results.join(",")
+    )
+}
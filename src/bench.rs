@@ -0,0 +1,130 @@
+//! `rum bench`: measuring interpreter speed on reference workloads.
+//!
+//! midmark and sandmark are compiled into the binary, so a benchmark needs
+//! nothing but `rum`. Each run executes the program from scratch with no
+//! input and its output discarded, and is timed from the first instruction
+//! to Halt.
+
+use std::time::{Duration, Instant};
+
+use crate::embed::Machine;
+use crate::report::Table;
+
+/// The reference workloads, by name.
+const BUILTIN: [(&str, &[u8]); 2] = [
+    ("midmark", include_bytes!("../bin/midmark.um")),
+    ("sandmark", include_bytes!("../bin/sandmark.umz")),
+];
+
+/// A program to benchmark.
+#[derive(Debug, Clone)]
+pub struct Workload {
+    pub name: String,
+    pub program: Vec<u32>,
+}
+
+impl Workload {
+    /// Given the contents of a `.um` file, a workload called `name`.
+    pub fn new(name: impl Into<String>, bytes: &[u8]) -> Self {
+        let program = crate::load::words(bytes);
+        Self { name: name.into(), program }
+    }
+
+    /// The built-in workload called `name`.
+    pub fn builtin(name: &str) -> Option<Self> {
+        BUILTIN.iter().find(|(builtin, _)| *builtin == name).map(|(name, bytes)| Self::new(*name, bytes))
+    }
+}
+
+/// Names of the built-in workloads.
+pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+    BUILTIN.iter().map(|(name, _)| *name)
+}
+
+/// Timings of every run of one workload.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub name: String,
+    /// Instructions executed by each run; the program is deterministic, so they are all equal.
+    pub executed: u64,
+    pub runs: Vec<Duration>,
+}
+
+impl Report {
+    fn mips(&self, elapsed: Duration) -> f64 {
+        self.executed as f64 / elapsed.as_secs_f64().max(1e-9) / 1e6
+    }
+
+    /// MIPS of the slowest run.
+    pub fn min_mips(&self) -> f64 {
+        self.runs.iter().map(|&run| self.mips(run)).fold(f64::INFINITY, f64::min)
+    }
+
+    /// MIPS of the fastest run.
+    pub fn max_mips(&self) -> f64 {
+        self.runs.iter().map(|&run| self.mips(run)).fold(0.0, f64::max)
+    }
+
+    /// Instructions over the total time of all runs.
+    pub fn mean_mips(&self) -> f64 {
+        self.mips(self.runs.iter().sum::<Duration>() / self.runs.len().max(1) as u32)
+    }
+}
+
+/// Runs `workload` `runs` times. Fails if the program faults.
+pub fn measure(workload: &Workload, runs: usize) -> Result<Report, String> {
+    let mut report = Report { name: workload.name.clone(), executed: 0, runs: Vec::new() };
+    for _ in 0..runs {
+        let mut machine = Machine::new(workload.program.clone());
+        let start = Instant::now();
+        let status = machine.run(None, &mut || None, &mut |_| {});
+        let elapsed = start.elapsed();
+        if let Err(e) = status {
+            return Err(format!("{} faulted after {} instructions: {e}", workload.name, machine.executed()));
+        }
+        report.executed = machine.executed();
+        report.runs.push(elapsed);
+    }
+    Ok(report)
+}
+
+/// Renders reports as a table of runs, instructions, time and MIPS.
+pub fn table(reports: &[Report]) -> String {
+    let width = reports.iter().map(|r| r.name.len()).max().unwrap_or(0).max("workload".len());
+    let mut out = format!(
+        "{:<width$}  {:>4}  {:>14}  {:>9}  {:>9}  {:>9}  {:>9}\n",
+        "workload", "runs", "instructions", "mean s", "min MIPS", "mean MIPS", "max MIPS"
+    );
+    for report in reports {
+        let mean = report.runs.iter().sum::<Duration>().as_secs_f64() / report.runs.len().max(1) as f64;
+        out += &format!(
+            "{:<width$}  {:>4}  {:>14}  {:>9.3}  {:>9.1}  {:>9.1}  {:>9.1}\n",
+            report.name,
+            report.runs.len(),
+            report.executed,
+            mean,
+            report.min_mips(),
+            report.mean_mips(),
+            report.max_mips()
+        );
+    }
+    out
+}
+
+/// The reports as one table, `bench`, for `--report-format` (see `report`).
+pub fn tables(reports: &[Report]) -> Vec<Table> {
+    let mut table = Table::new("bench", &["workload", "runs", "instructions", "mean_seconds", "min_mips", "mean_mips", "max_mips"]);
+    for report in reports {
+        let mean = report.runs.iter().sum::<Duration>().as_secs_f64() / report.runs.len().max(1) as f64;
+        table.row(vec![
+            report.name.as_str().into(),
+            report.runs.len().into(),
+            report.executed.into(),
+            mean.into(),
+            report.min_mips().into(),
+            report.mean_mips().into(),
+            report.max_mips().into(),
+        ]);
+    }
+    vec![table]
+}
@@ -0,0 +1,65 @@
+//! Synthetic programs for benchmarking the interpreter (`benches/interpreter.rs`).
+//!
+//! Each fixture stresses one thing, so a change to dispatch, memory or I/O
+//! shows up in the fixture that exercises it:
+//!
+//! - `arithmetic_loop`: register arithmetic and a conditional jump, no memory traffic.
+//! - `allocation_churn`: Map Segment, Store and Unmap Segment in a tight loop.
+//! - `output_storm`: one Output per iteration.
+//!
+//! All of them halt, read no input and take an iteration count, so they can
+//! be scaled to whatever run time a benchmark wants.
+
+use crate::conformance::{add, cmov, div, halt, loadprog, map, mul, nand, orth, out, set, store, unmap};
+
+/// A named benchmark program.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    pub name: &'static str,
+    pub program: Vec<u32>,
+}
+
+/// Iterations used by `all`: each fixture executes a few million instructions.
+pub const ITERATIONS: u32 = 500_000;
+
+/// Every fixture at `ITERATIONS` iterations.
+pub fn all() -> Vec<Fixture> {
+    vec![
+        Fixture { name: "arithmetic_loop", program: arithmetic_loop(ITERATIONS) },
+        Fixture { name: "allocation_churn", program: allocation_churn(ITERATIONS) },
+        Fixture { name: "output_storm", program: output_storm(ITERATIONS) },
+    ]
+}
+
+/// Add, Multiply, NAND and Division on registers, `iterations` times.
+pub fn arithmetic_loop(iterations: u32) -> Vec<u32> {
+    counted(iterations, vec![add(7, 7, 1), mul(2, 7, 1), nand(2, 2, 7), add(2, 2, 1), div(7, 7, 5), add(7, 7, 2)])
+}
+
+/// Maps a 16-word segment, stores into it and unmaps it, `iterations` times.
+pub fn allocation_churn(iterations: u32) -> Vec<u32> {
+    counted(iterations, vec![orth(2, 16), map(7, 2), orth(2, 15), store(7, 2, 1), unmap(7)])
+}
+
+/// Outputs `iterations` bytes.
+pub fn output_storm(iterations: u32) -> Vec<u32> {
+    counted(iterations, vec![orth(2, b'.' as u32), out(2)])
+}
+
+/// Wraps `body` in a loop run `iterations` times (at least once).
+///
+/// Register use: r0 stays 0, r1 counts down, r3 and r4 hold the loop and
+/// exit addresses, r5 is all ones and r6 is the jump target. The body may
+/// use r2 and r7 and read the others.
+fn counted(iterations: u32, body: Vec<u32>) -> Vec<u32> {
+    let mut program = set(5, u32::MAX);
+    program.extend(set(1, iterations.max(1)));
+    // r3, r4 and r7 (which `set` left dirty) are set next; the loop starts after them.
+    let start = program.len() as u32 + 3;
+    let exit = start + body.len() as u32 + 4;
+    program.extend([orth(3, start), orth(4, exit), orth(7, 0)]);
+    program.extend(body);
+    // r1 -= 1; jump to the start while r1 != 0, else fall out to Halt.
+    program.extend([add(1, 1, 5), add(6, 4, 0), cmov(6, 3, 1), loadprog(0, 6), halt()]);
+    program
+}
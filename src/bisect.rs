@@ -0,0 +1,274 @@
+//! `rum bisect`: running two interpreter configurations in lockstep and
+//! finding the first instruction on which they disagree, for validating a
+//! new execution backend against one that is trusted.
+//!
+//! Both run the same program on the same input. Every `every`
+//! instructions their PCs, registers, output and whether they halted or
+//! faulted are compared (and their memory, with `memory`). The check is
+//! cheap because it is rare; once one fails, the two are forked from the
+//! last point they agreed and bisected down to the single instruction
+//! after which they differ.
+//!
+//! A configuration is anything implementing `Engine`. Two come with rum:
+//! `rum` (`embed::Machine`, the interpreter proper) and `naive` (the
+//! reference interpreter of `differential`).
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::differential::{Effect, Naive};
+use crate::embed::{Machine, Status};
+use crate::rumdis::describe;
+
+/// Instructions between comparisons unless the caller says otherwise.
+pub const EVERY: u64 = 1 << 16;
+
+/// How a run stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum End {
+    Running,
+    Halted,
+    Faulted,
+}
+
+/// What is compared between the two configurations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct View {
+    pub pc: usize,
+    pub registers: [u32; 8],
+    /// Instructions executed, Halt not included.
+    pub executed: u64,
+    pub output: Vec<u8>,
+    pub end: End,
+}
+
+/// An interpreter configuration driven by the bisector. It reads its
+/// input from the bytes it was created with and keeps its output.
+pub trait Engine {
+    /// Executes up to `n` instructions, stopping early on Halt or a fault.
+    fn advance(&mut self, n: u64);
+    fn view(&self) -> View;
+    /// Mapped segments by identifier.
+    fn memory(&self) -> BTreeMap<u32, Vec<u32>>;
+    /// The word at `pc` in segment 0.
+    fn word(&self, pc: usize) -> Option<u32>;
+    /// An independent copy, to bisect from.
+    fn fork(&self) -> Box<dyn Engine>;
+}
+
+/// The engine called `name` running `program` on `input`: `rum` or `naive`.
+pub fn engine(name: &str, program: &[u32], input: &[u8]) -> Option<Box<dyn Engine>> {
+    match name {
+        "rum" => Some(Box::new(Embedded { machine: Machine::new(program.to_vec()), input: input.to_vec(), read: 0, output: Vec::new(), end: End::Running })),
+        "naive" => Some(Box::new(Reference { naive: Naive::new(program.to_vec()), input: input.to_vec(), read: 0, executed: 0, output: Vec::new(), end: End::Running })),
+        _ => None,
+    }
+}
+
+/// The first instruction after which the configurations disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Instructions both executed before it.
+    pub executed: u64,
+    pub pc: usize,
+    pub word: Option<u32>,
+    pub reason: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let instruction = self.word.map_or("past the end of the program".to_string(), describe);
+        writeln!(f, "diverged on instruction {} (pc {}: {instruction}): {}", self.executed + 1, self.pc, self.reason)
+    }
+}
+
+/// Runs `left` and `right` (named for messages) for at most `fuel`
+/// instructions, comparing them every `every`. `None` when they agree
+/// throughout, memory at the end included.
+pub fn bisect(names: [&str; 2], mut left: Box<dyn Engine>, mut right: Box<dyn Engine>, every: u64, fuel: u64, memory: bool) -> Option<Divergence> {
+    let mut base = (left.fork(), right.fork());
+    let mut result = None;
+    loop {
+        let executed = left.view().executed;
+        let n = every.max(1).min(fuel - executed);
+        left.advance(n);
+        right.advance(n);
+        let (a, b) = (left.view(), right.view());
+        let ended = a.end != End::Running || a.executed >= fuel;
+        if compare(names, &*left, &*right, memory || ended).is_some() {
+            result = Some(narrow(names, &base, n, memory));
+            break;
+        }
+        if ended || b.end != End::Running {
+            break;
+        }
+        base = (left.fork(), right.fork());
+    }
+    result
+}
+
+/// Given `base`, a pair that agrees, and that they disagree `n`
+/// instructions later, the first instruction they disagree after.
+fn narrow(names: [&str; 2], base: &(Box<dyn Engine>, Box<dyn Engine>), n: u64, memory: bool) -> Divergence {
+    let after = |steps: u64| {
+        let (mut left, mut right) = (base.0.fork(), base.1.fork());
+        left.advance(steps);
+        right.advance(steps);
+        (left, right)
+    };
+    // Invariant: they agree after `low` instructions and disagree after `high`.
+    let (mut low, mut high) = (0, n);
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        let (left, right) = after(mid);
+        match compare(names, &*left, &*right, memory || left.view().end != End::Running) {
+            Some(_) => high = mid,
+            None => low = mid,
+        }
+    }
+    let (before, _) = after(low);
+    let (left, right) = after(high);
+    let view = before.view();
+    let reason = compare(names, &*left, &*right, true).unwrap_or_default();
+    Divergence { executed: view.executed, pc: view.pc, word: before.word(view.pc), reason }
+}
+
+/// How `left` and `right` differ, if they do; `memory` compares their segments too.
+fn compare(names: [&str; 2], left: &dyn Engine, right: &dyn Engine, memory: bool) -> Option<String> {
+    let ([l, r], a, b) = (names, left.view(), right.view());
+    if a.end != b.end {
+        return Some(format!("{l} {} but {r} {}", said(a.end), said(b.end)));
+    }
+    if a.output != b.output {
+        let common = a.output.iter().zip(&b.output).take_while(|(x, y)| x == y).count();
+        let byte = |output: &[u8]| output.get(common).map_or("nothing".to_string(), |byte| format!("{byte:#04x}"));
+        return Some(format!("output byte {common} is {} in {l} but {} in {r}", byte(&a.output), byte(&b.output)));
+    }
+    // Where the PC is left after Halt or a fault, and what a faulting
+    // instruction had done by then, is up to the implementation.
+    if a.pc != b.pc && a.end == End::Running {
+        return Some(format!("{l} is at pc {} but {r} at {}", a.pc, b.pc));
+    }
+    if let Some(reg) = (0..8).find(|&reg| a.registers[reg] != b.registers[reg] && a.end != End::Faulted) {
+        return Some(format!("r{reg} is {:#x} in {l} but {:#x} in {r}", a.registers[reg], b.registers[reg]));
+    }
+    if a.executed != b.executed {
+        return Some(format!("{l} executed {} instructions but {r} {}", a.executed, b.executed));
+    }
+    if !memory {
+        return None;
+    }
+    let (ours, theirs) = (left.memory(), right.memory());
+    if !ours.keys().eq(theirs.keys()) {
+        return Some(format!("{l} has segments {:?} mapped but {r} {:?}", ours.keys().collect::<Vec<_>>(), theirs.keys().collect::<Vec<_>>()));
+    }
+    ours.iter().zip(&theirs).find(|((_, x), (_, y))| x != y).map(|((id, x), (_, y))| match (0..x.len().min(y.len())).find(|&i| x[i] != y[i]) {
+        Some(offset) => format!("segment {id} word {offset} is {:#x} in {l} but {:#x} in {r}", x[offset], y[offset]),
+        None => format!("segment {id} is {} words in {l} but {} in {r}", x.len(), y.len()),
+    })
+}
+
+fn said(end: End) -> &'static str {
+    match end {
+        End::Running => "is running",
+        End::Halted => "halted",
+        End::Faulted => "faulted",
+    }
+}
+
+/// `embed::Machine`.
+#[derive(Clone)]
+struct Embedded {
+    machine: Machine,
+    input: Vec<u8>,
+    read: usize,
+    output: Vec<u8>,
+    end: End,
+}
+
+impl Engine for Embedded {
+    fn advance(&mut self, n: u64) {
+        let Embedded { machine, input, read, output, end } = self;
+        for _ in 0..n {
+            if *end != End::Running {
+                return;
+            }
+            let mut next = || {
+                let byte = input.get(*read).copied();
+                *read += usize::from(byte.is_some());
+                byte
+            };
+            *end = match machine.step(&mut next, &mut |byte| output.push(byte)) {
+                Ok(Status::Running) => End::Running,
+                Ok(Status::Halted) => End::Halted,
+                Err(_) => End::Faulted,
+            };
+        }
+    }
+
+    fn view(&self) -> View {
+        let state = &self.machine.state;
+        let registers = std::array::from_fn(|r| state.register(r as u32));
+        View { pc: state.program_counter, registers, executed: self.machine.executed(), output: self.output.clone(), end: self.end }
+    }
+
+    fn memory(&self) -> BTreeMap<u32, Vec<u32>> {
+        let state = &self.machine.state;
+        let free = state.free_segments();
+        (0..state.mapped_memory.len() as u32).filter(|id| !free.contains(id)).map(|id| (id, state.mapped_memory[id as usize].clone())).collect()
+    }
+
+    fn word(&self, pc: usize) -> Option<u32> {
+        self.machine.state.mapped_memory.first()?.get(pc).copied()
+    }
+
+    fn fork(&self) -> Box<dyn Engine> {
+        Box::new(self.clone())
+    }
+}
+
+/// The naive reference interpreter.
+#[derive(Clone)]
+struct Reference {
+    naive: Naive,
+    input: Vec<u8>,
+    read: usize,
+    executed: u64,
+    output: Vec<u8>,
+    end: End,
+}
+
+impl Engine for Reference {
+    fn advance(&mut self, n: u64) {
+        for _ in 0..n {
+            if self.end != End::Running {
+                return;
+            }
+            let mut input = self.input[self.read..].iter().copied().inspect(|_| self.read += 1);
+            match self.naive.step(&mut input) {
+                Ok(Effect::Continued(byte)) => {
+                    self.output.extend(byte);
+                    self.executed += 1;
+                }
+                Ok(Effect::Halted) => self.end = End::Halted,
+                Ok(Effect::Faulted) | Err(_) => self.end = End::Faulted,
+            }
+        }
+    }
+
+    fn view(&self) -> View {
+        View { pc: self.naive.pc as usize, registers: self.naive.registers, executed: self.executed, output: self.output.clone(), end: self.end }
+    }
+
+    fn memory(&self) -> BTreeMap<u32, Vec<u32>> {
+        self.naive.segments.iter().map(|(&id, words)| (id, words.clone())).collect()
+    }
+
+    fn word(&self, pc: usize) -> Option<u32> {
+        self.naive.segments.get(&0)?.get(pc).copied()
+    }
+
+    fn fork(&self) -> Box<dyn Engine> {
+        Box::new(self.clone())
+    }
+}
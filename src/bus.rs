@@ -0,0 +1,212 @@
+//! `rum run --io nats://HOST:PORT/INPUT/OUTPUT`: a program on a message bus.
+//!
+//! The bridge subscribes to the INPUT subject of a NATS server and feeds
+//! every message to the program's Input; what the program writes is
+//! published on OUTPUT. `Framing` says how messages and bytes correspond:
+//!
+//! - `byte`: a message's payload is input as is, and every output byte is a message.
+//! - `line`: a message is input followed by a newline, and each output line
+//!   (without its newline) is a message.
+//! - `length`: a message is input as a 4-byte big-endian length and the
+//!   payload, and the program writes its messages the same way.
+//!
+//! The client speaks just enough of the NATS text protocol for this
+//! (CONNECT, SUB, PUB, MSG, PING/PONG). Input waits for the next message;
+//! it reaches end of input only if the server closes the connection.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+
+use crate::embed::{Machine, Status};
+use crate::error::MachineError;
+
+/// How messages map onto the program's bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    Byte,
+    Line,
+    Length,
+}
+
+impl Framing {
+    pub fn parse(name: &str) -> Result<Framing, String> {
+        match name {
+            "byte" => Ok(Framing::Byte),
+            "line" => Ok(Framing::Line),
+            "length" => Ok(Framing::Length),
+            _ => Err(format!("unknown framing `{name}` (expected byte, line or length)")),
+        }
+    }
+
+    /// The input bytes one message becomes.
+    fn input(self, payload: Vec<u8>) -> Vec<u8> {
+        match self {
+            Framing::Byte => payload,
+            Framing::Line => [payload, vec![b'\n']].concat(),
+            Framing::Length => [(payload.len() as u32).to_be_bytes().to_vec(), payload].concat(),
+        }
+    }
+}
+
+/// A NATS server and the two subjects of a bridged program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub address: String,
+    pub input: String,
+    pub output: String,
+}
+
+impl Endpoint {
+    /// Given `nats://HOST:PORT/INPUT/OUTPUT`, the endpoint it names.
+    pub fn parse(url: &str) -> Result<Endpoint, String> {
+        let bad = || format!("bad NATS endpoint `{url}` (expected nats://HOST:PORT/INPUT/OUTPUT)");
+        let rest = url.strip_prefix("nats://").ok_or_else(bad)?;
+        let mut parts = rest.split('/');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(address), Some(input), Some(output), None) if [address, input, output].iter().all(|p| valid(p)) => {
+                Ok(Endpoint { address: address.to_string(), input: input.to_string(), output: output.to_string() })
+            }
+            _ => Err(bad()),
+        }
+    }
+}
+
+/// Addresses and subjects are single protocol tokens.
+fn valid(token: &str) -> bool {
+    !token.is_empty() && !token.bytes().any(|b| b.is_ascii_whitespace())
+}
+
+/// A connection to the bus, subscribed to the input subject.
+pub struct Bridge {
+    writer: Arc<Mutex<TcpStream>>,
+    messages: Receiver<Vec<u8>>,
+    output: String,
+    framing: Framing,
+}
+
+impl Bridge {
+    /// Connects to the server and subscribes to `endpoint.input`.
+    pub fn connect(endpoint: &Endpoint, framing: Framing) -> std::io::Result<Bridge> {
+        let stream = TcpStream::connect(&endpoint.address)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut info = String::new();
+        reader.read_line(&mut info)?;
+        if !info.starts_with("INFO") {
+            return Err(protocol(format!("expected INFO, got `{}`", info.trim_end())));
+        }
+        let mut writer = stream;
+        write!(writer, "CONNECT {{\"verbose\":false,\"pedantic\":false,\"name\":\"rum\"}}\r\nSUB {} 1\r\nPING\r\n", endpoint.input)?;
+        // The server answers PING only after it has accepted what came before.
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(protocol("the server closed the connection".to_string()));
+            }
+            match line.trim_end() {
+                "PONG" => break,
+                error if error.starts_with("-ERR") => return Err(protocol(error.to_string())),
+                _ => {}
+            }
+        }
+
+        let writer = Arc::new(Mutex::new(writer));
+        let (deliver, messages) = channel();
+        let pong = writer.clone();
+        std::thread::spawn(move || {
+            // Ends, closing the channel, when the connection does.
+            while let Ok(Some(payload)) = next_message(&mut reader, &pong) {
+                if deliver.send(framing.input(payload)).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Bridge { writer, messages, output: endpoint.output.clone(), framing })
+    }
+
+    /// Publishes `payload` on the output subject.
+    pub fn publish(&self, payload: &[u8]) -> std::io::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        write!(writer, "PUB {} {}\r\n", self.output, payload.len())?;
+        writer.write_all(payload)?;
+        writer.write_all(b"\r\n")
+    }
+
+    /// Runs `machine` for up to `fuel` instructions with its I/O on the bus.
+    /// Output still unframed when the run stops (a line without its newline)
+    /// is published as it is, also when the program faults: the fault is
+    /// the inner `Err`, a failure of the bus the outer one.
+    pub fn run(&self, machine: &mut Machine, fuel: Option<u64>) -> std::io::Result<Result<Status, MachineError>> {
+        let mut queued = VecDeque::new();
+        let mut pending = Vec::new();
+        let mut failed = None;
+        let mut input = || {
+            while queued.is_empty() {
+                queued.extend(self.messages.recv().ok()?);
+            }
+            queued.pop_front()
+        };
+        let status = machine.run(fuel, &mut input, &mut |byte| {
+            pending.push(byte);
+            if let Some(message) = self.framed(&mut pending) {
+                if let Err(e) = self.publish(&message) {
+                    failed.get_or_insert(e);
+                }
+            }
+        });
+        if let Some(e) = failed {
+            return Err(e);
+        }
+        if !pending.is_empty() {
+            self.publish(&pending)?;
+        }
+        Ok(status)
+    }
+
+    /// Takes a complete message out of the program's output so far, if there is one.
+    fn framed(&self, pending: &mut Vec<u8>) -> Option<Vec<u8>> {
+        match self.framing {
+            Framing::Byte => Some(std::mem::take(pending)),
+            Framing::Line if pending.last() == Some(&b'\n') => {
+                pending.pop();
+                Some(std::mem::take(pending))
+            }
+            Framing::Length if pending.len() >= 4 => {
+                let length = u32::from_be_bytes(pending[..4].try_into().unwrap()) as usize;
+                (pending.len() == 4 + length).then(|| pending.drain(..).skip(4).collect())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Reads up to the next MSG and returns its payload, answering PINGs on the
+/// way. `None` when the connection is closed.
+fn next_message(reader: &mut impl BufRead, writer: &Mutex<TcpStream>) -> std::io::Result<Option<Vec<u8>>> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["PING"] => writer.lock().unwrap().write_all(b"PONG\r\n")?,
+            // MSG <subject> <sid> [reply-to] <size>
+            ["MSG", .., size] => {
+                let size = size.parse().map_err(|_| protocol(format!("bad MSG line `{}`", line.trim_end())))?;
+                let mut payload = vec![0; size + 2];
+                reader.read_exact(&mut payload)?;
+                payload.truncate(size);
+                return Ok(Some(payload));
+            }
+            [error, ..] if error.starts_with("-ERR") => eprintln!("rum: NATS {}", line.trim_end()),
+            _ => {}
+        }
+    }
+}
+
+fn protocol(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("NATS: {message}"))
+}
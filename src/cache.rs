@@ -0,0 +1,101 @@
+//! Warm starts for programs run again and again in one process: the
+//! servers, which are sent the same program by every client of a class,
+//! `rum batch`, which grades one program against many cases, and the
+//! `testing` harness, which runs one program on many inputs.
+//!
+//! Programs are kept decoded and checked, keyed by the SHA-256 of their
+//! bytes, so loading one seen before skips the byte-swapping and the
+//! whole-words check and only copies the words into the new machine's
+//! segment 0. The cache holds the `CAPACITY` programs loaded last. Its
+//! hits and misses are served with the other metrics at `/metrics` and
+//! printed under `rum batch`'s results.
+//!
+//! `rum run --watch` starts a `rum run` process per edit, which loads its
+//! program once, so it does not go through the cache.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use sha2::{Digest, Sha256};
+
+use crate::load;
+
+/// Programs the global cache keeps.
+pub const CAPACITY: usize = 64;
+
+#[derive(Debug, Default)]
+struct Inner {
+    programs: HashMap<[u8; 32], Arc<[u32]>>,
+    /// Keys in the order they were added, oldest first.
+    order: VecDeque<[u8; 32]>,
+    hits: u64,
+    misses: u64,
+}
+
+/// Decoded programs by the hash of their bytes.
+#[derive(Debug)]
+pub struct ProgramCache {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+/// What the cache has done so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Programs held now.
+    pub programs: usize,
+}
+
+impl ProgramCache {
+    /// A cache keeping the last `capacity` programs.
+    pub fn new(capacity: usize) -> Self {
+        ProgramCache { capacity, inner: Mutex::default() }
+    }
+
+    /// The cache shared by everything in this process.
+    pub fn global() -> &'static ProgramCache {
+        static GLOBAL: OnceLock<ProgramCache> = OnceLock::new();
+        GLOBAL.get_or_init(|| ProgramCache::new(CAPACITY))
+    }
+
+    /// The words of `bytes`, a `.um` program, or an error if it is not a
+    /// whole number of words.
+    pub fn decode(&self, bytes: &[u8]) -> Result<Arc<[u32]>, String> {
+        if !bytes.len().is_multiple_of(4) {
+            return Err("not a whole number of words".to_string());
+        }
+        Ok(self.words(bytes))
+    }
+
+    /// The words of `bytes` as `rum run` loads them: a trailing partial
+    /// word is dropped.
+    pub fn words(&self, bytes: &[u8]) -> Arc<[u32]> {
+        let bytes = &bytes[..bytes.len() / 4 * 4];
+        let key: [u8; 32] = Sha256::digest(bytes).into();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if let Some(program) = inner.programs.get(&key).cloned() {
+                inner.hits += 1;
+                return program;
+            }
+            inner.misses += 1;
+        }
+        let program: Arc<[u32]> = load::words(bytes).into();
+        let mut inner = self.inner.lock().unwrap();
+        if self.capacity > 0 && inner.programs.insert(key, program.clone()).is_none() {
+            inner.order.push_back(key);
+            if inner.order.len() > self.capacity {
+                let oldest = inner.order.pop_front().unwrap();
+                inner.programs.remove(&oldest);
+            }
+        }
+        program
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats { hits: inner.hits, misses: inner.misses, programs: inner.programs.len() }
+    }
+}
@@ -0,0 +1,158 @@
+//! Guard words after every segment (feature `canary`, `rum run --canary`),
+//! to catch code that writes past a segment's end.
+//!
+//! `Checked` pads each mapped segment, the program included, with `WORDS`
+//! words of `PATTERN` and keeps its length in the UM apart. Load and Store
+//! are checked against that length, so programs fault exactly as before;
+//! what lands in the padding came from elsewhere, e.g. an interpreter bug,
+//! a host writing through `mapped_memory` by its real length, or a
+//! snapshot restored with the wrong one. The padding is verified when its
+//! segment is unmapped, when the program halts and on `verify`. `PATTERN`
+//! has opcode 15, so running off the end of the program faults too.
+
+use std::collections::BTreeMap;
+
+use crate::embed::{Machine, Status, Stepper};
+use crate::error::MachineError;
+use crate::instruction::Instruction;
+
+/// Guard words after each segment.
+pub const WORDS: usize = 4;
+/// What the guard words hold.
+pub const PATTERN: u32 = 0xf00d_cafe;
+
+/// A guard word found changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overrun {
+    pub segment: u32,
+    /// Offset of the word from the start of the segment.
+    pub offset: usize,
+    /// What it holds instead of `PATTERN`, `None` if the segment shrank past it.
+    pub found: Option<u32>,
+}
+
+impl std::fmt::Display for Overrun {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.found {
+            Some(word) => write!(f, "segment {} overrun: guard word at offset {} is {word:#010x}", self.segment, self.offset),
+            None => write!(f, "segment {} overrun: guard word at offset {} is gone", self.segment, self.offset),
+        }
+    }
+}
+
+/// An embedded machine with guarded segments.
+pub struct Checked {
+    pub machine: Machine,
+    /// Each mapped segment's length in the UM, without the guard words.
+    lengths: BTreeMap<u32, usize>,
+}
+
+impl Checked {
+    /// Wraps `machine`, guarding the segments it has mapped.
+    pub fn new(mut machine: Machine) -> Self {
+        let mut lengths = BTreeMap::new();
+        for segment in 0..machine.state.mapped_memory.len() as u32 {
+            if !machine.state.free_segments().contains(&segment) {
+                lengths.insert(segment, guard(&mut machine, segment));
+            }
+        }
+        Self { machine, lengths }
+    }
+
+    /// Segment `segment`'s length in the UM, if it is mapped.
+    pub fn len(&self, segment: u32) -> Option<usize> {
+        self.lengths.get(&segment).copied()
+    }
+
+    /// The guard words of `segment`, checked.
+    fn check(&self, segment: u32) -> Result<(), Overrun> {
+        let len = self.lengths[&segment];
+        let words = &self.machine.state.mapped_memory[segment as usize];
+        match (len..len + WORDS).find(|&offset| words.get(offset) != Some(&PATTERN)) {
+            Some(offset) => Err(Overrun { segment, offset, found: words.get(offset).copied() }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks every mapped segment's guard words.
+    pub fn verify(&self) -> Result<(), Overrun> {
+        self.lengths.keys().try_for_each(|&segment| self.check(segment))
+    }
+
+    /// Executes one instruction like `Machine::step`, with Load and Store
+    /// checked against the segments' lengths. An `Overrun` found when a
+    /// segment is unmapped or the program halts faults the program with
+    /// `MachineError::HostFault`.
+    pub fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        if self.machine.status() == Status::Halted {
+            return Ok(Status::Halted);
+        }
+        let state = &self.machine.state;
+        let pc = state.program_counter;
+        let word = *state.mapped_memory.first().and_then(|program| program.get(pc)).ok_or(MachineError::ProgramCounterOutOfBounds { pc })?;
+        let instruction = Instruction::decode(word);
+        let access = match instruction {
+            Instruction::Load { b, c, .. } => Some((state.register(b), state.register(c))),
+            Instruction::Store { a, b, .. } => Some((state.register(a), state.register(b))),
+            _ => None,
+        };
+        if let Some((segment, offset)) = access {
+            match self.len(segment) {
+                Some(len) if (offset as usize) < len => {}
+                Some(_) => return Err(MachineError::OutOfBounds { pc, segment: segment.into(), offset: offset.into() }),
+                None => return Err(MachineError::UnmappedSegment { pc, segment: segment.into() }),
+            }
+        }
+        let unmapped = match instruction {
+            Instruction::Unmap { c } => Some(state.register(c)),
+            _ => None,
+        };
+        if let Some(segment) = unmapped.filter(|segment| self.lengths.contains_key(segment)) {
+            self.check(segment).map_err(|overrun| MachineError::HostFault { pc, message: overrun.to_string() })?;
+            self.lengths.remove(&segment);
+        }
+        let status = self.machine.step(input, output)?;
+        match instruction {
+            Instruction::Halt => self.verify().map_err(|overrun| MachineError::HostFault { pc, message: format!("at exit: {overrun}") })?,
+            Instruction::Map { b, .. } => {
+                let segment = self.machine.state.register(b);
+                let len = guard(&mut self.machine, segment);
+                self.lengths.insert(segment, len);
+            }
+            Instruction::LoadProgram { b, .. } => {
+                let segment = self.machine.state.register(b);
+                if segment != 0 {
+                    self.lengths.insert(0, self.lengths[&segment]);
+                }
+            }
+            _ => {}
+        }
+        Ok(status)
+    }
+}
+
+impl Stepper for Checked {
+    fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        Checked::step(self, input, output)
+    }
+
+    fn status(&self) -> Status {
+        self.machine.status()
+    }
+
+    fn executed(&self) -> u64 {
+        self.machine.executed()
+    }
+
+    fn cancelled(&self) -> bool {
+        self.machine.cancelled()
+    }
+}
+
+/// Pads `segment` with guard words and returns its length without them.
+fn guard(machine: &mut Machine, segment: u32) -> usize {
+    let words = machine.state.segment_mut(segment as usize).unwrap();
+    let len = words.len();
+    words.resize(len + WORDS, PATTERN);
+    len
+}
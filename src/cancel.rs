@@ -0,0 +1,39 @@
+//! Stopping an embedded run from another thread.
+//!
+//! A host (a GUI, a server) hands a `CancellationToken` to the machine with
+//! `embed::Machine::with_cancellation` and keeps a clone. `cancel` on the
+//! clone makes `Machine::run` and `run_io`, and the `Stepper::run` of a host
+//! wrapping the machine (`plugin::Host`, `tasks::Scheduler`, ...), return
+//! `Status::Running` at the next batch boundary, every `CHECK_EVERY` instructions, leaving the
+//! machine intact to inspect, snapshot or resume. A run blocked in its
+//! `input` callback only notices once the callback returns.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Instructions a run executes between looks at its token.
+pub const CHECK_EVERY: u64 = 4096;
+
+/// A flag shared between a run and the threads that may stop it.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks every run holding this token (or a clone) to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Withdraws a cancellation, so a stopped machine can be run again.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
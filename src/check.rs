@@ -0,0 +1,123 @@
+use sha2::{Digest, Sha256};
+
+/// Lines of agreeing output shown before the first difference.
+const CONTEXT: usize = 3;
+/// Lines shown from each side after the first difference.
+const SHOWN: usize = 10;
+
+/// What a program's output is checked against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    /// The exact expected bytes.
+    Output(Vec<u8>),
+    /// Lowercase hex SHA-256 digest of the expected bytes.
+    Sha256(String),
+}
+
+/// Lowercase hex SHA-256 digest of `bytes`.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Output hashed as it is written (`rum run --hash-output sha256`), so
+/// that however long it is it never needs storing.
+#[derive(Default)]
+pub struct OutputDigest {
+    hasher: Sha256,
+    /// Bytes not yet hashed; hashing a byte at a time is slow.
+    buffer: Vec<u8>,
+    bytes: u64,
+}
+
+impl OutputDigest {
+    const BUFFER: usize = 64 * 1024;
+
+    pub fn push(&mut self, byte: u8) {
+        self.buffer.push(byte);
+        self.bytes += 1;
+        if self.buffer.len() == Self::BUFFER {
+            self.hasher.update(&self.buffer);
+            self.buffer.clear();
+        }
+    }
+
+    /// Bytes pushed so far.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Lowercase hex SHA-256 digest of the bytes pushed, as `sha256_hex` gives.
+    pub fn finish(mut self) -> String {
+        self.hasher.update(&self.buffer);
+        hex(&self.hasher.finalize())
+    }
+}
+
+/// Compares `actual` output against `expected`, returning a readable
+/// explanation of the mismatch if there is one.
+pub fn verify(actual: &[u8], expected: &Expected) -> Result<(), String> {
+    match expected {
+        Expected::Output(bytes) if bytes == actual => Ok(()),
+        Expected::Output(bytes) => Err(diff(bytes, actual)),
+        Expected::Sha256(digest) => {
+            let got = sha256_hex(actual);
+            if got.eq_ignore_ascii_case(digest.trim()) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "output digest mismatch ({} bytes of output)\n  expected sha256 {}\n  actual   sha256 {}\n",
+                    actual.len(),
+                    digest.trim(),
+                    got
+                ))
+            }
+        }
+    }
+}
+
+/// Renders where `actual` first departs from `expected`, with a few lines
+/// of agreeing context followed by the differing lines from each side.
+pub fn diff(expected: &[u8], actual: &[u8]) -> String {
+    let at = expected.iter().zip(actual).take_while(|(a, b)| a == b).count();
+    let line = expected[..at].iter().filter(|&&b| b == b'\n').count();
+    let column = at - expected[..at].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+
+    let expected_lines: Vec<&[u8]> = expected.split_inclusive(|&b| b == b'\n').collect();
+    let actual_lines: Vec<&[u8]> = actual.split_inclusive(|&b| b == b'\n').collect();
+
+    let mut out = format!(
+        "output differs at byte {at} (line {}, column {}); expected {} bytes, got {}\n--- expected\n+++ actual\n",
+        line + 1,
+        column + 1,
+        expected.len(),
+        actual.len()
+    );
+    for context in &expected_lines[line.saturating_sub(CONTEXT)..line] {
+        out += &format!("  {}\n", show(context));
+    }
+    for (sign, lines) in [('-', &expected_lines), ('+', &actual_lines)] {
+        let rest = lines.get(line..).unwrap_or_default();
+        for text in rest.iter().take(SHOWN) {
+            out += &format!("{sign} {}\n", show(text));
+        }
+        if rest.len() > SHOWN {
+            out += &format!("{sign} ... {} more lines\n", rest.len() - SHOWN);
+        }
+        if rest.is_empty() {
+            out += &format!("{sign} <end of output>\n");
+        }
+    }
+    out
+}
+
+/// One output line made printable, noting a missing final newline.
+fn show(line: &[u8]) -> String {
+    match line.strip_suffix(b"\n") {
+        Some(body) => String::from_utf8_lossy(body).escape_debug().to_string(),
+        None => format!("{} <no newline>", String::from_utf8_lossy(line).escape_debug()),
+    }
+}
@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+
+use crate::seal::Key;
+use crate::snapshot::{checksum, encode_with, parent, DeltaChain, SnapshotError, DEFAULT_LEVEL};
+use crate::state::UniversalMachine;
+
+/// Writes snapshots into a fixed ring of `keep` files named
+/// `<base>.<slot>.rumstate`, overwriting the oldest each time.
+///
+/// In delta mode slot 0 holds a full snapshot and the following slots hold
+/// deltas chained onto it; once the ring is full a new full snapshot starts
+/// the next chain.
+///
+/// Each snapshot is written to a temporary file and renamed into place, so a
+/// crash mid-write never destroys the previous good checkpoint.
+pub struct Checkpoints {
+    base: PathBuf,
+    keep: usize,
+    next: usize,
+    level: Option<i32>,
+    key: Option<Key>,
+    chain: Option<DeltaChain>,
+}
+
+impl Checkpoints {
+    pub fn new(base: impl Into<PathBuf>, keep: usize) -> Self {
+        Self { base: base.into(), keep: keep.max(1), next: 0, level: Some(DEFAULT_LEVEL), key: None, chain: None }
+    }
+
+    /// Like `new`, but writing delta snapshots between full ones.
+    pub fn with_deltas(base: impl Into<PathBuf>, keep: usize) -> Self {
+        Self { chain: Some(DeltaChain::new(Some(DEFAULT_LEVEL), None)), ..Self::new(base, keep) }
+    }
+
+    /// Sets the zstd level of the snapshots written (`None` for no compression).
+    pub fn compression(mut self, level: Option<i32>) -> Self {
+        self.level = level;
+        if self.chain.is_some() {
+            self.chain = Some(DeltaChain::new(level, self.key.clone()));
+        }
+        self
+    }
+
+    /// Seals the snapshots written with `key` (see `seal`).
+    pub fn key(mut self, key: Option<Key>) -> Self {
+        self.key = key;
+        if self.chain.is_some() {
+            self.chain = Some(DeltaChain::new(self.level, self.key.clone()));
+        }
+        self
+    }
+
+    /// Path of the file used for `slot`.
+    pub fn slot_path(&self, slot: usize) -> PathBuf {
+        let mut name = self.base.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{slot}.rumstate"));
+        self.base.with_file_name(name)
+    }
+
+    /// Saves `state` into the next slot, returning the path written.
+    pub fn save(&mut self, state: &mut UniversalMachine) -> Result<PathBuf, SnapshotError> {
+        let bytes = match self.chain.as_mut() {
+            Some(chain) => {
+                if self.next == 0 {
+                    chain.reset();
+                }
+                chain.next(state)
+            }
+            None => encode_with(state, self.level, self.key.as_ref()),
+        };
+        let path = self.slot_path(self.next);
+        let tmp = path.with_extension("rumstate.tmp");
+        std::fs::write(&tmp, bytes)?;
+        std::fs::rename(&tmp, &path)?;
+        self.next = (self.next + 1) % self.keep;
+        Ok(path)
+    }
+}
+
+/// Given a checkpoint base path, return the most recently written checkpoint, if any.
+pub fn latest(base: &Path, keep: usize) -> Option<PathBuf> {
+    let ring = Checkpoints::new(base, keep);
+    (0..ring.keep)
+        .map(|slot| ring.slot_path(slot))
+        .filter_map(|path| Some((std::fs::metadata(&path).ok()?.modified().ok()?, path)))
+        .max()
+        .map(|(_, path)| path)
+}
+
+/// Given the base path of delta-mode checkpoints, return the files of the
+/// newest complete chain: the full snapshot in slot 0 and every following
+/// delta that still applies to it (leftovers from an older chain are skipped).
+/// Deltas sealed with a key other than `key` end the chain too.
+pub fn latest_chain(base: &Path, keep: usize, key: Option<&Key>) -> Vec<PathBuf> {
+    let ring = Checkpoints::new(base, keep);
+    let mut chain = Vec::new();
+    let mut previous = None;
+    for slot in 0..ring.keep {
+        let path = ring.slot_path(slot);
+        let Ok(bytes) = std::fs::read(&path) else { break };
+        match (checksum(&bytes), parent(&bytes, key)) {
+            (Ok(sum), Ok(link)) if link == previous => previous = Some(sum),
+            _ => break,
+        }
+        chain.push(path);
+    }
+    chain
+}
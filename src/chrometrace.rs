@@ -0,0 +1,176 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::json::Value;
+use crate::rumdis::{get, op, Opcode, RB, RC};
+use crate::state::UniversalMachine;
+
+/// Segment 0 is divided into regions of this many words for the timeline.
+pub const REGION_WORDS: usize = 256;
+/// The program counter is sampled once every this many instructions.
+const SAMPLE_EVERY: u64 = 1 << 12;
+/// Map/unmap events recorded before the trace stops listing them one by one.
+const MAX_MEMORY_EVENTS: u64 = 100_000;
+/// Input that returns sooner than this didn't wait on anybody.
+const MIN_WAIT: Duration = Duration::from_micros(50);
+
+const EXECUTION: u32 = 1;
+const IO: u32 = 2;
+const MEMORY: u32 = 3;
+
+/// Writes a run as Trace Event Format JSON for `chrome://tracing` and Perfetto.
+///
+/// Three tracks: which region of segment 0 the program was executing
+/// (sampled, one slice per stretch spent in the same region), time spent
+/// blocked in Input, and Map/Unmap Segment events with a mapped-memory
+/// counter.
+pub struct ChromeTrace {
+    out: BufWriter<File>,
+    clock: Clock,
+    /// Instructions executed so far, for the virtual clock.
+    count: u64,
+    /// Region being executed: its index, when it started and the instruction count then.
+    region: Option<(usize, f64, u64)>,
+    input_started: Option<Duration>,
+    memory_events: u64,
+    error: Option<std::io::Error>,
+}
+
+impl ChromeTrace {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        Self::with_clock(path, Clock::new(false))
+    }
+
+    /// Like `create`, with timestamps from `clock`.
+    pub fn with_clock(path: &Path, clock: Clock) -> std::io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        // The events are streamed between this head and the tail `finish` writes.
+        write!(out, "{{\"displayTimeUnit\":\"ms\",\"traceEvents\":[")?;
+        let process = Value::object([("ph", "M".into()), ("pid", 1u32.into()), ("name", "process_name".into()), ("args", Value::object([("name", "rum".into())]))]);
+        write!(out, "{process}")?;
+        let mut trace = Self { out, clock, count: 0, region: None, input_started: None, memory_events: 0, error: None };
+        for (tid, name) in [(EXECUTION, "segment 0"), (IO, "I/O"), (MEMORY, "memory")] {
+            trace.event(Value::object([("ph", "M".into()), ("pid", 1u32.into()), ("tid", tid.into()), ("name", "thread_name".into()), ("args", Value::object([("name", name.into())]))]));
+        }
+        Ok(trace)
+    }
+
+    /// Given the instruction about to run from address `pc` (the `count`th), note what it does.
+    pub fn before(&mut self, count: u64, pc: usize, inst: u32, state: &UniversalMachine) {
+        self.count = count;
+        if count.is_multiple_of(SAMPLE_EVERY) {
+            self.sample(count, pc / REGION_WORDS, state);
+        }
+        let reg = |field| state.register(get(field, inst));
+        match op(inst) {
+            Some(Opcode::Input) => self.input_started = Some(self.clock.elapsed(count)),
+            Some(Opcode::UnmapSeg) => self.memory("unmap", Value::object([("segment", reg(&RC).into())])),
+            Some(Opcode::LoadProg) if reg(&RB) != 0 => {
+                let now = self.now();
+                self.event(instant(EXECUTION, now, "program replaced", Some(Value::object([("from", reg(&RB).into())]))));
+            }
+            _ => {}
+        }
+    }
+
+    /// Given the instruction that just ran, note what it did.
+    pub fn after(&mut self, inst: u32, state: &UniversalMachine) {
+        match op(inst) {
+            Some(Opcode::Input) => {
+                let Some(started) = self.input_started.take() else { return };
+                let waited = self.clock.elapsed(self.count + 1) - started;
+                if waited >= MIN_WAIT {
+                    let ts = started.as_secs_f64() * 1e6;
+                    let dur = waited.as_secs_f64() * 1e6;
+                    self.event(slice(IO, ts, dur, "input wait", None));
+                }
+            }
+            Some(Opcode::MapSeg) => {
+                let segment = state.register(get(&RB, inst));
+                let words = state.mapped_memory[segment as usize].len();
+                self.memory("map", Value::object([("segment", segment.into()), ("words", words.into())]));
+            }
+            _ => {}
+        }
+    }
+
+    /// Closes the last slice and the JSON document after `count` instructions.
+    pub fn finish(mut self, count: u64) -> std::io::Result<()> {
+        self.count = count;
+        self.close_region(count);
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        writeln!(self.out, "]}}")?;
+        self.out.flush()
+    }
+
+    fn sample(&mut self, count: u64, region: usize, state: &UniversalMachine) {
+        if self.region.is_none_or(|(current, _, _)| current != region) {
+            self.close_region(count);
+            self.region = Some((region, self.now(), count));
+        }
+        let now = self.now();
+        let mapped = state.mapped_bytes();
+        self.event(Value::object([
+            ("ph", "C".into()),
+            ("pid", 1u32.into()),
+            ("tid", MEMORY.into()),
+            ("ts", micros(now)),
+            ("name", "mapped bytes".into()),
+            ("args", Value::object([("bytes", mapped.into())])),
+        ]));
+    }
+
+    fn close_region(&mut self, count: u64) {
+        let Some((region, started, first)) = self.region.take() else { return };
+        let dur = self.now() - started;
+        let (lo, hi) = (region * REGION_WORDS, (region + 1) * REGION_WORDS);
+        self.event(slice(EXECUTION, started, dur, &format!("{lo:#x}-{hi:#x}"), Some(Value::object([("instructions", (count - first).into())]))));
+    }
+
+    fn memory(&mut self, name: &str, args: Value) {
+        self.memory_events += 1;
+        let now = self.now();
+        if self.memory_events <= MAX_MEMORY_EVENTS {
+            self.event(instant(MEMORY, now, name, Some(args)));
+        } else if self.memory_events == MAX_MEMORY_EVENTS + 1 {
+            self.event(instant(MEMORY, now, "further map/unmap events omitted", None));
+        }
+    }
+
+    fn now(&self) -> f64 {
+        self.clock.elapsed(self.count).as_secs_f64() * 1e6
+    }
+
+    /// Appends one event; the first write error is kept for `finish`.
+    fn event(&mut self, event: Value) {
+        if self.error.is_none() {
+            if let Err(e) = write!(self.out, ",{event}") {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+/// A complete ("X") event: `name` on track `tid` from `ts` for `dur` microseconds.
+fn slice(tid: u32, ts: f64, dur: f64, name: &str, args: Option<Value>) -> Value {
+    let mut fields = vec![("ph", "X".into()), ("pid", 1u32.into()), ("tid", tid.into()), ("ts", micros(ts)), ("dur", micros(dur)), ("name", name.into())];
+    fields.extend(args.map(|args| ("args", args)));
+    Value::object(fields)
+}
+
+/// An instant ("i") event scoped to track `tid`.
+fn instant(tid: u32, ts: f64, name: &str, args: Option<Value>) -> Value {
+    let mut fields = vec![("ph", "i".into()), ("s", "t".into()), ("pid", 1u32.into()), ("tid", tid.into()), ("ts", micros(ts)), ("name", name.into())];
+    fields.extend(args.map(|args| ("args", args)));
+    Value::object(fields)
+}
+
+/// Microseconds rounded to the nanosecond, which is all the viewers show.
+fn micros(us: f64) -> Value {
+    ((us * 1e3).round() / 1e3).into()
+}
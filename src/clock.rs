@@ -0,0 +1,49 @@
+//! The time features read: the wall clock, or with `rum run
+//! --deterministic` a virtual clock driven by the instruction count, so
+//! that progress lines, Chrome trace timestamps, interrupt timers and the
+//! `TIME` syscall come out the same on every run.
+//!
+//! Virtual time advances `NANOS_PER_INSTRUCTION` per instruction executed,
+//! a machine running at 100 MIPS, and its Unix time starts at `EPOCH`.
+//! Time spent waiting for input doesn't count.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Virtual time one instruction takes.
+pub const NANOS_PER_INSTRUCTION: u64 = 10;
+/// The virtual clock's Unix time when the run starts: 2000-01-01T00:00:00Z.
+pub const EPOCH: u64 = 946_684_800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clock {
+    /// Real time since the instant held.
+    Wall(Instant),
+    /// Time derived from the instruction count.
+    Virtual,
+}
+
+impl Clock {
+    /// The virtual clock if `deterministic`, otherwise the wall clock from now.
+    pub fn new(deterministic: bool) -> Self {
+        match deterministic {
+            true => Clock::Virtual,
+            false => Clock::Wall(Instant::now()),
+        }
+    }
+
+    /// Time since the clock started, `executed` instructions into the run.
+    pub fn elapsed(&self, executed: u64) -> Duration {
+        match self {
+            Clock::Wall(start) => start.elapsed(),
+            Clock::Virtual => Duration::from_nanos(executed.saturating_mul(NANOS_PER_INSTRUCTION)),
+        }
+    }
+
+    /// Seconds since the Unix epoch, `executed` instructions into the run.
+    pub fn unix_seconds(&self, executed: u64) -> u64 {
+        match self {
+            Clock::Wall(_) => SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs()),
+            Clock::Virtual => EPOCH + self.elapsed(executed).as_secs(),
+        }
+    }
+}
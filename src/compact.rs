@@ -0,0 +1,65 @@
+use crate::analyze::analyze;
+
+/// How much of a program `rum compact` keeps, and why.
+#[derive(Debug, Clone)]
+pub struct Compaction {
+    /// Number of leading words kept.
+    pub keep: usize,
+    /// Number of words in the original program.
+    pub original: usize,
+    /// Human-readable explanation of the decision.
+    pub report: Vec<String>,
+}
+
+/// Given a program and the extents recorded by coverage traces, decide how
+/// many trailing words can be dropped.
+///
+/// The static analysis alone is used whenever it can account for every jump
+/// and segment-0 access. Otherwise trailing words are only dropped if traces
+/// were supplied, in which case the result is only as good as those runs.
+pub fn compact(program: &[u32], traces: &[usize]) -> Compaction {
+    let analysis = analyze(program);
+    let mut report = Vec::new();
+    let list = |sites: &[usize]| {
+        let shown: Vec<String> = sites.iter().take(8).map(|pc| pc.to_string()).collect();
+        let more = if sites.len() > 8 { ", ..." } else { "" };
+        format!("{}{more}", shown.join(", "))
+    };
+
+    let keep = match analysis.extent() {
+        Some(extent) => {
+            report.push("static analysis resolved every jump and segment-0 access".to_string());
+            extent
+        }
+        None => {
+            if !analysis.dynamic_jumps.is_empty() {
+                report.push(format!("unresolved LoadProg targets at {}", list(&analysis.dynamic_jumps)));
+            }
+            if !analysis.dynamic_accesses.is_empty() {
+                report.push(format!("possible segment-0 accesses at unknown offsets at {}", list(&analysis.dynamic_accesses)));
+            }
+            match traces.iter().max() {
+                Some(&traced) => {
+                    report.push(format!("relying on {} coverage trace(s)", traces.len()));
+                    traced.max(analysis.used_extent())
+                }
+                None => {
+                    report.push("nothing can be proven unreachable; pass --trace to use recorded runs".to_string());
+                    program.len()
+                }
+            }
+        }
+    }
+    .min(program.len());
+
+    if keep < program.len() {
+        report.push(format!(
+            "dropped words {}..{} ({} words, {} bytes)",
+            keep,
+            program.len(),
+            program.len() - keep,
+            (program.len() - keep) * 4
+        ));
+    }
+    Compaction { keep, original: program.len(), report }
+}
@@ -0,0 +1,229 @@
+//! A conformance suite for the UM specification, run by `rum conformance`
+//! and by `cargo test --features conformance`.
+//!
+//! Each case is a small generated program with the input it is given and
+//! what the host checks once it stops: its output, the final registers, or
+//! that it faulted. Together they cover every opcode's edge cases, such as
+//! wrapping arithmetic, NAND identities, Conditional Move on zero, mapping
+//! zero words, end of input and Load Program.
+
+use crate::embed::{Machine, Status};
+
+/// Instructions a case may execute; they are all short and straight-line.
+const FUEL: u64 = 10_000;
+
+/// What must hold once a case's program stops.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expect {
+    /// It halts having written exactly these bytes, with these registers
+    /// (`None` registers are not checked).
+    Halt { output: Vec<u8>, registers: [Option<u32>; 8] },
+    /// It faults before halting.
+    Fault,
+}
+
+/// One test program and its expectation.
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub name: &'static str,
+    pub program: Vec<u32>,
+    pub input: Vec<u8>,
+    pub expect: Expect,
+}
+
+// Instruction encoders, shared with `bench_fixtures` and `gen`. Registers 6 and 7 are scratch for `set`.
+pub(crate) fn op(code: u32, a: u32, b: u32, c: u32) -> u32 {
+    code << 28 | a << 6 | b << 3 | c
+}
+pub(crate) fn cmov(a: u32, b: u32, c: u32) -> u32 { op(0, a, b, c) }
+pub(crate) fn load(a: u32, b: u32, c: u32) -> u32 { op(1, a, b, c) }
+pub(crate) fn store(a: u32, b: u32, c: u32) -> u32 { op(2, a, b, c) }
+pub(crate) fn add(a: u32, b: u32, c: u32) -> u32 { op(3, a, b, c) }
+pub(crate) fn mul(a: u32, b: u32, c: u32) -> u32 { op(4, a, b, c) }
+pub(crate) fn div(a: u32, b: u32, c: u32) -> u32 { op(5, a, b, c) }
+pub(crate) fn nand(a: u32, b: u32, c: u32) -> u32 { op(6, a, b, c) }
+pub(crate) fn halt() -> u32 { op(7, 0, 0, 0) }
+pub(crate) fn map(b: u32, c: u32) -> u32 { op(8, 0, b, c) }
+pub(crate) fn unmap(c: u32) -> u32 { op(9, 0, 0, c) }
+pub(crate) fn out(c: u32) -> u32 { op(10, 0, 0, c) }
+pub(crate) fn input(c: u32) -> u32 { op(11, 0, 0, c) }
+pub(crate) fn loadprog(b: u32, c: u32) -> u32 { op(12, 0, b, c) }
+pub(crate) fn orth(a: u32, value: u32) -> u32 {
+    13 << 28 | a << 25 | value
+}
+
+/// Instructions leaving `value` in register `a`, whatever its size.
+pub(crate) fn set(a: u32, value: u32) -> Vec<u32> {
+    if value < 1 << 25 {
+        return vec![orth(a, value)];
+    }
+    // a = (value >> 16) * 65536 + (value & 0xffff)
+    vec![orth(7, 1 << 16), orth(a, value >> 16), mul(a, a, 7), orth(6, value & 0xffff), add(a, a, 6)]
+}
+
+fn program(parts: impl IntoIterator<Item = Vec<u32>>) -> Vec<u32> {
+    parts.into_iter().flatten().collect()
+}
+
+fn halts(name: &'static str, program: Vec<u32>, registers: [Option<u32>; 8]) -> Case {
+    Case { name, program, input: Vec::new(), expect: Expect::Halt { output: Vec::new(), registers } }
+}
+
+fn faults(name: &'static str, program: Vec<u32>) -> Case {
+    Case { name, program, input: Vec::new(), expect: Expect::Fault }
+}
+
+/// Every case in the suite.
+pub fn cases() -> Vec<Case> {
+    const X: u32 = 0xDEAD_BEEF;
+    let none = [None; 8];
+    let with = |pairs: &[(usize, u32)]| {
+        let mut registers = none;
+        for &(r, value) in pairs {
+            registers[r] = Some(value);
+        }
+        registers
+    };
+    vec![
+        halts("registers start at zero", vec![halt()], with(&[(0, 0), (1, 0), (2, 0), (3, 0), (4, 0), (5, 0), (6, 0), (7, 0)])),
+        halts("orthography loads 25 bits", vec![orth(0, 0x1FF_FFFF), orth(1, 0), halt()], with(&[(0, 0x1FF_FFFF), (1, 0)])),
+        halts("cmov moves when c is nonzero", program([set(1, X), vec![orth(2, 1), cmov(0, 1, 2), halt()]]), with(&[(0, X)])),
+        halts("cmov on zero keeps a", program([set(1, X), vec![orth(0, 5), cmov(0, 1, 2), halt()]]), with(&[(0, 5)])),
+        halts("add wraps", program([set(1, u32::MAX), vec![orth(2, 2), add(0, 1, 2), halt()]]), with(&[(0, 1)])),
+        halts("add of zero", program([set(1, X), vec![add(0, 1, 2), halt()]]), with(&[(0, X)])),
+        halts(
+            "multiply wraps",
+            program([set(1, 1 << 16), set(3, u32::MAX), vec![mul(0, 1, 1), mul(2, 3, 3), halt()]]),
+            with(&[(0, 0), (2, 1)]),
+        ),
+        halts("multiply by zero", program([set(1, X), vec![mul(0, 1, 2), halt()]]), with(&[(0, 0)])),
+        halts("division truncates", vec![orth(1, 7), orth(2, 2), div(0, 1, 2), halt()], with(&[(0, 3)])),
+        halts(
+            "division is unsigned",
+            program([set(1, 0xFFFF_FFFE), vec![orth(2, 2), div(0, 1, 2), halt()]]),
+            with(&[(0, 0x7FFF_FFFF)]),
+        ),
+        halts("division of a smaller number", vec![orth(1, 2), orth(2, 7), div(0, 1, 2), halt()], with(&[(0, 0)])),
+        faults("division by zero faults", vec![orth(1, 7), div(0, 1, 2), halt()]),
+        halts("nand of zeros is all ones", vec![nand(0, 1, 2), halt()], with(&[(0, u32::MAX)])),
+        halts("nand with itself is not", program([set(1, X), vec![nand(0, 1, 1), halt()]]), with(&[(0, !X)])),
+        halts(
+            "nand with all ones is not",
+            program([set(1, X), set(2, u32::MAX), vec![nand(0, 1, 2), halt()]]),
+            with(&[(0, !X)]),
+        ),
+        halts(
+            "nand twice is and",
+            program([set(1, X), set(2, 0x0F0F_0F0F), vec![nand(0, 1, 2), nand(0, 0, 0), halt()]]),
+            with(&[(0, X & 0x0F0F_0F0F)]),
+        ),
+        halts(
+            "mapped words are zero",
+            program([set(1, X), vec![orth(2, 3), map(3, 2), orth(4, 2), load(0, 3, 4), halt()]]),
+            with(&[(0, 0)]),
+        ),
+        halts(
+            "store then load",
+            program([set(1, X), vec![orth(2, 3), map(3, 2), orth(4, 2), store(3, 4, 1), load(0, 3, 4), halt()]]),
+            with(&[(0, X)]),
+        ),
+        halts(
+            "segments are independent",
+            program([set(1, X), vec![orth(2, 1), map(3, 2), map(4, 2), store(3, 5, 1), load(0, 4, 5), halt()]]),
+            with(&[(0, 0)]),
+        ),
+        halts(
+            "mapping zero words gives a fresh identifier",
+            // r3 = id of the empty segment; r0 = (r3 != 0) as 1, via cmov.
+            vec![map(3, 2), orth(1, 1), cmov(0, 1, 3), halt()],
+            with(&[(0, 1)]),
+        ),
+        faults("loading from an empty segment faults", vec![map(3, 2), load(0, 3, 4), halt()]),
+        halts(
+            "identifiers of live segments differ",
+            // r0 = r3 - r4 (as r3 + !r4 + 1), nonzero when they differ; r5 = (r0 != 0).
+            vec![orth(2, 1), map(3, 2), map(4, 2), nand(0, 4, 4), add(0, 0, 3), orth(1, 1), add(0, 0, 1), cmov(5, 1, 0), halt()],
+            with(&[(5, 1)]),
+        ),
+        halts(
+            "a segment mapped after an unmap starts zeroed",
+            program([set(1, X), vec![orth(2, 1), map(3, 2), store(3, 4, 1), unmap(3), map(3, 2), load(0, 3, 4), halt()]]),
+            with(&[(0, 0)]),
+        ),
+        faults("load past the end faults", vec![orth(2, 1), map(3, 2), orth(4, 1), load(0, 3, 4), halt()]),
+        faults("store past the end of segment 0 faults", program([set(4, 1 << 20), vec![store(5, 4, 1), halt()]])),
+        Case {
+            name: "output writes bytes 0 to 255",
+            program: vec![out(0), orth(1, 255), out(1), orth(1, b'U' as u32), out(1), halt()],
+            input: Vec::new(),
+            expect: Expect::Halt { output: vec![0, 255, b'U'], registers: none },
+        },
+        faults("output above 255 faults", vec![orth(1, 256), out(1), halt()]),
+        Case {
+            name: "input reads bytes",
+            program: vec![input(0), input(1), halt()],
+            input: vec![b'A', 0],
+            expect: Expect::Halt { output: Vec::new(), registers: with(&[(0, 65), (1, 0)]) },
+        },
+        halts("input at end of input is all ones", vec![input(0), halt()], with(&[(0, u32::MAX)])),
+        Case {
+            name: "input after the last byte is all ones",
+            program: vec![input(0), input(1), input(2), halt()],
+            input: vec![0xFF],
+            expect: Expect::Halt { output: Vec::new(), registers: with(&[(0, 255), (1, u32::MAX), (2, u32::MAX)]) },
+        },
+        halts(
+            "load program from segment 0 jumps",
+            // Jumps over `orth(0, 1)` at address 3.
+            vec![orth(2, 4), loadprog(1, 2), halt(), orth(0, 1), halt()],
+            with(&[(0, 0)]),
+        ),
+        halts(
+            "load program copies the segment",
+            // Builds [orth(0, 9), halt] in a new segment and runs it from address 0.
+            program([
+                set(1, orth(0, 9)),
+                set(5, halt()),
+                vec![orth(2, 2), map(3, 2), orth(4, 0), store(3, 4, 1), orth(4, 1), store(3, 4, 5), orth(2, 0), loadprog(3, 2)],
+            ]),
+            with(&[(0, 9)]),
+        ),
+        faults("opcode 14 is invalid", vec![op(14, 0, 0, 0)]),
+        faults("opcode 15 is invalid", vec![op(15, 0, 0, 0)]),
+        faults("running off the end of segment 0 faults", vec![orth(0, 1)]),
+    ]
+}
+
+/// Runs `case`, returning what went wrong if it did not behave as expected.
+pub fn check(case: &Case) -> Result<(), String> {
+    let mut machine = Machine::new(case.program.clone());
+    let mut input = case.input.iter().copied();
+    let mut output = Vec::new();
+    let result = machine.run(Some(FUEL), &mut || input.next(), &mut |byte| output.push(byte));
+    match (&case.expect, result) {
+        (Expect::Fault, Err(_)) => Ok(()),
+        (Expect::Fault, Ok(_)) => Err("expected a fault, but it ran on".to_string()),
+        (Expect::Halt { .. }, Err(e)) => Err(format!("faulted after {} instructions: {e}", machine.executed())),
+        (Expect::Halt { .. }, Ok(Status::Running)) => Err(format!("did not halt within {FUEL} instructions")),
+        (Expect::Halt { output: expected, registers }, Ok(Status::Halted)) => {
+            if output != *expected {
+                return Err(format!("output {output:?}, expected {expected:?}"));
+            }
+            let wrong: Vec<String> = (0..8)
+                .filter_map(|r| {
+                    let (want, got) = (registers[r]?, machine.state.register(r as u32));
+                    (want != got).then(|| format!("r{r} = {got:#x}, expected {want:#x}"))
+                })
+                .collect();
+            match wrong.is_empty() {
+                true => Ok(()),
+                false => Err(wrong.join(", ")),
+            }
+        }
+    }
+}
+
+/// Runs every case, returning each one's name and result.
+pub fn run_all() -> Vec<(&'static str, Result<(), String>)> {
+    cases().iter().map(|case| (case.name, check(case))).collect()
+}
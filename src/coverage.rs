@@ -0,0 +1,83 @@
+use std::io::Write;
+use std::path::Path;
+
+use crate::barrier::{code_write, CodeWrite};
+use crate::rumdis::{get, op, Opcode, RB, RC};
+use crate::state::UniversalMachine;
+
+/// Records which words of the original program a run actually touched,
+/// whether executed, loaded or stored.
+///
+/// Recording stops once `LoadProg` replaces segment 0 with another segment,
+/// since the original program is gone from then on.
+pub struct Coverage {
+    touched: Vec<bool>,
+    active: bool,
+}
+
+impl Coverage {
+    pub fn new(program_len: usize) -> Self {
+        Self { touched: vec![false; program_len], active: true }
+    }
+
+    /// Given the instruction about to run from address `pc`, note the words it uses.
+    pub fn record(&mut self, pc: usize, inst: u32, state: &UniversalMachine) {
+        if !self.active {
+            return;
+        }
+        self.mark(pc);
+        let reg = |field| state.register(get(field, inst));
+        if op(inst) == Some(Opcode::SegLoad) && reg(&RB) == 0 {
+            self.mark(reg(&RC) as usize);
+        }
+        match code_write(inst, state) {
+            Some(CodeWrite::Store { offset, .. }) => self.mark(offset),
+            Some(CodeWrite::Replace { .. }) => self.active = false,
+            None => {}
+        }
+    }
+
+    fn mark(&mut self, address: usize) {
+        if let Some(word) = self.touched.get_mut(address) {
+            *word = true;
+        }
+    }
+
+    /// Touched addresses as half-open `(start, end)` ranges.
+    pub fn ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for (address, _) in self.touched.iter().enumerate().filter(|(_, &t)| t) {
+            match ranges.last_mut() {
+                Some((_, end)) if *end == address => *end += 1,
+                _ => ranges.push((address, address + 1)),
+            }
+        }
+        ranges
+    }
+
+    /// Writes the trace file read back by `read_extent`: one `start end` range per line.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(file, "# rum coverage: touched words of the original program (start end)")?;
+        for (start, end) in self.ranges() {
+            writeln!(file, "{start} {end}")?;
+        }
+        file.flush()
+    }
+}
+
+/// Given a coverage trace written by `Coverage::save`, return one past the
+/// highest address it touched.
+pub fn read_extent(path: &Path) -> Result<usize, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut extent = 0;
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let end = line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|end| end.parse::<usize>().ok())
+            .ok_or_else(|| format!("{}: malformed line `{line}`", path.display()))?;
+        extent = extent.max(end);
+    }
+    Ok(extent)
+}
@@ -0,0 +1,72 @@
+//! Virtual cycle accounting (`rum run --cycles MODEL`): each opcode is
+//! given a cost in cycles and the run adds up the cost of every instruction
+//! it executes, so programs can be compared the way an architecture would
+//! be, whatever machine rum runs on.
+//!
+//! A model is `unit`, every opcode one cycle, or a comma-separated list of
+//! `mnemonic=cycles` overriding it, e.g. `mul=4,div=20,map=10,unmap=10`.
+//! The mnemonics are those `rumdis::describe` writes.
+
+use crate::rumdis::MNEMONICS;
+
+/// Cycles taken by each of the 16 opcodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CostModel {
+    costs: [u64; 16],
+}
+
+impl Default for CostModel {
+    /// The `unit` model: one cycle per instruction.
+    fn default() -> Self {
+        Self { costs: [1; 16] }
+    }
+}
+
+impl CostModel {
+    /// Reads a model written as `unit` or `mnemonic=cycles,...`.
+    pub fn parse(text: &str) -> Result<CostModel, String> {
+        let mut model = CostModel::default();
+        if text == "unit" {
+            return Ok(model);
+        }
+        for entry in text.split(',') {
+            let (name, cycles) = entry.split_once('=').ok_or_else(|| format!("--cycles takes `unit` or `mnemonic=cycles,...`, not `{entry}`"))?;
+            let opcode = MNEMONICS[..14].iter().position(|&m| m == name.trim()).ok_or_else(|| format!("--cycles: unknown opcode `{name}`"))?;
+            model.costs[opcode] = cycles.trim().parse().map_err(|_| format!("--cycles: `{cycles}` is not a number of cycles"))?;
+        }
+        Ok(model)
+    }
+
+    /// Cycles one instruction with `opcode` (0 to 15) takes.
+    pub fn cost(&self, opcode: usize) -> u64 {
+        self.costs[opcode]
+    }
+}
+
+/// Virtual cycles spent so far by a run under a `CostModel`.
+#[derive(Debug, Clone)]
+pub struct Cycles {
+    model: CostModel,
+    total: u64,
+}
+
+impl Cycles {
+    pub fn new(model: CostModel) -> Self {
+        Self { model, total: 0 }
+    }
+
+    /// Charges the instruction `inst` about to execute.
+    #[inline]
+    pub fn record(&mut self, inst: u32) {
+        self.total += self.model.costs[(inst >> 28) as usize];
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// `rum: N cycles (M instructions, C cycles per instruction)`, printed when the run ends.
+    pub fn report(&self, executed: u64) -> String {
+        format!("rum: {} cycles ({executed} instructions, {:.2} cycles per instruction)", self.total, self.total as f64 / executed.max(1) as f64)
+    }
+}
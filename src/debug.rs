@@ -0,0 +1,278 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufRead, Write};
+
+use crate::explain::Explain;
+use crate::rumdis::{describe, disassemble, op, Opcode};
+#[cfg(feature = "script")]
+use crate::rumdis::{get, RB, RC};
+#[cfg(feature = "script")]
+use crate::script::{Request, Script};
+use crate::seal::Key;
+use crate::snapshot::{load_snapshot, save_snapshot};
+use crate::state::UniversalMachine;
+
+const HELP: &str = "commands:
+  step [N]          execute N instructions (default 1)
+  continue          run until a breakpoint or halt
+  break ADDR        stop before executing segment-0 address ADDR
+  delete ADDR       remove a breakpoint
+  regs              show registers and the next instruction
+  save NAME|FILE    save the machine to an in-memory slot, or to a .rumstate file
+  restore NAME|FILE go back to a saved slot or file
+  slots             list saved slots
+  quit";
+
+/// Interactive debugger over a loaded machine, reading commands from `commands`.
+///
+/// The program shares the terminal with the prompt: when it executes Input
+/// it reads from stdin like it normally would.
+pub struct Debugger {
+    state: UniversalMachine,
+    breakpoints: BTreeSet<usize>,
+    slots: HashMap<String, UniversalMachine>,
+    halted: bool,
+    executed: u64,
+    /// Set by a script's `pause()`; stops `continue`.
+    paused: bool,
+    /// Print what each instruction did as it executes.
+    explain: bool,
+    /// Seals and opens the snapshots `save` and `restore` use as files.
+    key: Option<Key>,
+    #[cfg(feature = "script")]
+    script: Option<Script>,
+}
+
+impl Debugger {
+    /// Given a machine with its program in segment 0, start debugging at its program counter.
+    pub fn new(state: UniversalMachine) -> Self {
+        Self {
+            state,
+            breakpoints: BTreeSet::new(),
+            slots: HashMap::new(),
+            halted: false,
+            executed: 0,
+            paused: false,
+            explain: false,
+            key: None,
+            #[cfg(feature = "script")]
+            script: None,
+        }
+    }
+
+    /// Prints a plain-English explanation of each instruction as it
+    /// executes (see `explain`).
+    pub fn with_explain(mut self) -> Self {
+        self.explain = true;
+        self
+    }
+
+    /// Seals the snapshots saved to files with `key`, and restores only
+    /// snapshots sealed with it (see `seal`).
+    pub fn with_snapshot_key(mut self, key: Option<Key>) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Runs `script`'s hooks as the program executes (see `script`).
+    #[cfg(feature = "script")]
+    pub fn with_script(mut self, script: Script) -> Self {
+        self.script = Some(script);
+        self
+    }
+
+    /// Reads and executes commands until `quit` or end of input.
+    pub fn repl(&mut self, mut commands: impl BufRead) {
+        self.show();
+        loop {
+            print!("(rum) ");
+            let _ = std::io::stdout().flush();
+            let mut line = String::new();
+            if commands.read_line(&mut line).unwrap_or(0) == 0 {
+                println!();
+                return;
+            }
+            let mut words = line.split_whitespace();
+            let (command, arg) = (words.next().unwrap_or(""), words.next());
+            match (command, arg) {
+                ("", _) => {}
+                ("q" | "quit", _) => return,
+                ("h" | "help", _) => println!("{HELP}"),
+                ("s" | "step", n) => match n.map_or(Ok(1), str::parse::<u64>) {
+                    Ok(n) => {
+                        for _ in 0..n {
+                            if !self.step() {
+                                break;
+                            }
+                        }
+                        self.show();
+                    }
+                    Err(_) => println!("step takes a count"),
+                },
+                ("c" | "continue", _) => {
+                    while self.step() && !self.breakpoints.contains(&self.state.program_counter) && !self.paused {}
+                    self.paused = false;
+                    self.show();
+                }
+                ("b" | "break", Some(addr)) => match parse_addr(addr) {
+                    Some(addr) => { self.breakpoints.insert(addr); }
+                    None => println!("bad address `{addr}`"),
+                },
+                ("d" | "delete", Some(addr)) => match parse_addr(addr) {
+                    Some(addr) => { self.breakpoints.remove(&addr); }
+                    None => println!("bad address `{addr}`"),
+                },
+                ("r" | "regs", _) => self.show(),
+                ("save", Some(name)) => self.save(name),
+                ("restore", Some(name)) => self.restore(name),
+                ("slots", _) => {
+                    let mut names: Vec<_> = self.slots.keys().collect();
+                    names.sort();
+                    for name in names {
+                        println!("{name}  (pc {})", self.slots[name].program_counter);
+                    }
+                }
+                _ => println!("unknown command `{}`; try `help`", line.trim()),
+            }
+        }
+    }
+
+    /// Executes one instruction, returning `false` (and doing nothing)
+    /// once the machine has reached Halt.
+    fn step(&mut self) -> bool {
+        if self.halted {
+            return false;
+        }
+        let instruction = self.state.mapped_memory[0][self.state.program_counter];
+        let explain = self.explain.then(|| Explain::before(instruction, &self.state));
+        if op(instruction) == Some(Opcode::Halt) {
+            if let Some(explain) = explain {
+                println!("{:>6}: {:<24} {}", self.state.program_counter, describe(instruction), explain.after(&self.state));
+            }
+            self.halted = true;
+            return false;
+        }
+        self.before(instruction);
+        let pc = self.state.program_counter;
+        self.state.program_counter += 1;
+        if let Err(e) = disassemble(instruction, &mut self.state) {
+            println!("program failed: {e}");
+            self.state.program_counter = pc;
+            self.halted = true;
+            return false;
+        }
+        self.executed += 1;
+        if let Some(explain) = explain {
+            let _ = std::io::stdout().flush();
+            println!("{pc:>6}: {:<24} {}", describe(instruction), explain.after(&self.state));
+        }
+        self.after(instruction);
+        true
+    }
+
+    /// Script hooks due before `instruction` executes.
+    #[cfg(feature = "script")]
+    fn before(&mut self, instruction: u32) {
+        let Some(mut script) = self.script.take() else { return };
+        let (pc, executed) = (self.state.program_counter, self.executed);
+        let requests = script.on_instruction(&mut self.state, executed, pc, instruction);
+        self.obey(requests);
+        if op(instruction) == Some(Opcode::Output) {
+            let byte = self.state.register(get(&RC, instruction)) as u8;
+            let requests = script.on_output(&mut self.state, executed, byte);
+            self.obey(requests);
+        }
+        self.script = Some(script);
+    }
+
+    /// Script hooks due after `instruction` executed.
+    #[cfg(feature = "script")]
+    fn after(&mut self, instruction: u32) {
+        if op(instruction) != Some(Opcode::MapSeg) {
+            return;
+        }
+        let Some(mut script) = self.script.take() else { return };
+        let segment = self.state.register(get(&RB, instruction));
+        let words = self.state.mapped_memory[segment as usize].len() as u32;
+        let requests = script.on_map(&mut self.state, self.executed, segment, words);
+        self.obey(requests);
+        self.script = Some(script);
+    }
+
+    #[cfg(feature = "script")]
+    fn obey(&mut self, requests: Result<Vec<Request>, String>) {
+        match requests {
+            Ok(requests) => {
+                for request in requests {
+                    match request {
+                        Request::Break(addr) => { self.breakpoints.insert(addr); }
+                        Request::Clear(addr) => { self.breakpoints.remove(&addr); }
+                        Request::Pause => self.paused = true,
+                    }
+                }
+            }
+            Err(e) => {
+                let _ = std::io::stdout().flush();
+                println!("script error in {e}");
+                self.paused = true;
+            }
+        }
+    }
+
+    #[cfg(not(feature = "script"))]
+    fn before(&mut self, _instruction: u32) {}
+
+    #[cfg(not(feature = "script"))]
+    fn after(&mut self, _instruction: u32) {}
+
+    fn show(&self) {
+        let _ = std::io::stdout().flush();
+        let regs: Vec<String> = (0..8).map(|r| format!("r{r}={:#x}", self.state.register(r))).collect();
+        println!("{}", regs.join(" "));
+        let pc = self.state.program_counter;
+        let next = self.state.mapped_memory[0].get(pc).map_or("<out of range>".to_string(), |&i| describe(i));
+        let status = if self.halted { " (halted)" } else { "" };
+        println!("pc {pc}: {next}{status}");
+    }
+
+    /// Names containing a path separator or ending in `.rumstate` are files;
+    /// anything else is an in-memory slot for this session.
+    fn save(&mut self, name: &str) {
+        if is_file(name) {
+            match save_snapshot(&self.state, name, self.key.as_ref()) {
+                Ok(()) => println!("saved to {name}"),
+                Err(e) => println!("cannot save {name}: {e}"),
+            }
+        } else {
+            self.slots.insert(name.to_string(), self.state.clone());
+            println!("saved slot {name}");
+        }
+    }
+
+    fn restore(&mut self, name: &str) {
+        let restored = if is_file(name) {
+            load_snapshot(name, self.key.as_ref()).map_err(|e| format!("cannot restore {name}: {e}"))
+        } else {
+            self.slots.get(name).cloned().ok_or_else(|| format!("no slot named {name}"))
+        };
+        match restored {
+            Ok(state) => {
+                self.state = state;
+                self.halted = false;
+                self.paused = false;
+                self.show();
+            }
+            Err(e) => println!("{e}"),
+        }
+    }
+}
+
+fn is_file(name: &str) -> bool {
+    name.contains(std::path::MAIN_SEPARATOR) || name.contains('/') || name.ends_with(".rumstate")
+}
+
+fn parse_addr(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
@@ -0,0 +1,65 @@
+//! Host-callback devices: a segment whose Loads and Stores call Rust
+//! closures of the embedding application instead of touching memory.
+//!
+//! Closures attached with `Host::with_device` are how a host hands a UM
+//! program a database, a sensor or a game engine without a plugin type of
+//! its own or a new opcode: the program stores a request at some offset,
+//! loads the answer back, and the closures decide what both mean.
+//!
+//! ```
+//! use std::cell::Cell;
+//! use std::rc::Rc;
+//!
+//! use rum::embed::Machine;
+//! use rum::plugin::Host;
+//!
+//! let clock = Rc::new(Cell::new(0));
+//! let set = clock.clone();
+//! let host = Host::new(Machine::new(vec![0x7000_0000])).with_device(1000, move |_| clock.get(), move |_, value| set.set(value)).unwrap();
+//! assert_eq!(host.plugins().collect::<Vec<_>>(), ["device 1000"]);
+//! ```
+//!
+//! The segment identifier is the host's to choose. Attaching the device
+//! reserves it (see `UniversalMachine::reserve_segment`): Map Segment never
+//! returns it and Unmap Segment on it faults, so the program's own memory
+//! and the device cannot be confused. Segment 0, which holds the program,
+//! and segments already mapped are refused.
+
+use crate::plugin::Plugin;
+
+/// The plugin behind `Host::with_device`.
+pub struct Device<L, S> {
+    name: String,
+    segment: u32,
+    load: L,
+    store: S,
+}
+
+impl<L: FnMut(u32) -> u32, S: FnMut(u32, u32)> Device<L, S> {
+    /// A device serving `segment`: a Load at an offset returns `load(offset)`,
+    /// a Store calls `store(offset, value)`. Fails on segment 0, the program's.
+    pub fn new(segment: u32, load: L, store: S) -> Result<Self, String> {
+        if segment == 0 {
+            return Err("segment 0 holds the program and cannot be a device".to_string());
+        }
+        Ok(Device { name: format!("device {segment}"), segment, load, store })
+    }
+}
+
+impl<L: FnMut(u32) -> u32, S: FnMut(u32, u32)> Plugin for Device<L, S> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn device_segment(&self) -> Option<u32> {
+        Some(self.segment)
+    }
+
+    fn device_read(&mut self, offset: u32) -> u32 {
+        (self.load)(offset)
+    }
+
+    fn device_write(&mut self, offset: u32, value: u32) {
+        (self.store)(offset, value)
+    }
+}
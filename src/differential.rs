@@ -0,0 +1,323 @@
+//! Differential testing for `rum differential`: running a program on this
+//! interpreter and on a reference implementation and reporting the first
+//! point where they disagree.
+//!
+//! The reference is either
+//!
+//! - `Reference::Naive`, a deliberately plain interpreter in this module
+//!   that shares nothing with `state` and `rumdis` but instruction
+//!   decoding. It is compared after every instruction (PC, registers,
+//!   output, Halt or fault) and on the memory left at the end, so the
+//!   report points at the instruction that went wrong.
+//! - `Reference::Command`, any UM implementation run as `COMMAND program.um`
+//!   with the input on stdin. Only its output can be compared; the report
+//!   shows what this interpreter executed up to the first differing byte.
+//!
+//! The spec leaves segment identifiers to the implementation. The naive
+//! interpreter hands them out as rum does (most recently unmapped first)
+//! so that registers holding them compare equal.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::check::diff;
+use crate::embed::{Machine, Status};
+use crate::instruction::Instruction;
+use crate::rumdis::describe;
+
+/// Instructions shown before the point of divergence.
+pub const TRACE: usize = 16;
+/// Instructions compared when the caller sets no limit.
+pub const FUEL: u64 = 1_000_000_000;
+
+/// What to compare against.
+#[derive(Debug, Clone)]
+pub enum Reference {
+    Naive,
+    Command(PathBuf),
+}
+
+/// Where the two implementations parted.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// Instructions this interpreter executed before the divergence.
+    pub executed: u64,
+    pub reason: String,
+    /// The last instructions this interpreter executed, oldest first.
+    pub trace: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "diverged after {} instructions: {}", self.executed, self.reason)?;
+        if !self.trace.is_empty() {
+            writeln!(f, "last instructions executed by rum:")?;
+        }
+        for line in &self.trace {
+            writeln!(f, "  {line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `program` with `input` on this interpreter and `reference` for at
+/// most `fuel` instructions. `Ok(None)` when they agree; `Err` when the
+/// reference could not be run.
+pub fn compare(program: &[u32], input: &[u8], reference: &Reference, fuel: u64) -> Result<Option<Divergence>, String> {
+    match reference {
+        Reference::Naive => Ok(against_naive(program, input, fuel)),
+        Reference::Command(command) => against_command(program, input, command, fuel),
+    }
+}
+
+/// The last `TRACE` instructions executed: count, PC and word. Only
+/// rendered on a divergence, so recording stays cheap.
+#[derive(Default)]
+struct Trace(VecDeque<(u64, usize, u32)>);
+
+impl Trace {
+    fn record(&mut self, machine: &Machine) {
+        let pc = machine.state.program_counter;
+        if let Some(&word) = machine.state.mapped_memory.first().and_then(|program| program.get(pc)) {
+            if self.0.len() == TRACE {
+                self.0.pop_front();
+            }
+            self.0.push_back((machine.executed(), pc, word));
+        }
+    }
+
+    fn lines(self) -> Vec<String> {
+        self.0.into_iter().map(|(count, pc, word)| format!("{count:>10}  {pc:>8}  {}", describe(word))).collect()
+    }
+}
+
+/// How one instruction ended, on either side.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Effect {
+    Continued(Option<u8>),
+    Halted,
+    Faulted,
+}
+
+fn step(machine: &mut Machine, input: &mut impl Iterator<Item = u8>) -> Effect {
+    let mut output = None;
+    match machine.step(&mut || input.next(), &mut |byte| output = Some(byte)) {
+        Ok(Status::Running) => Effect::Continued(output),
+        Ok(Status::Halted) => Effect::Halted,
+        Err(_) => Effect::Faulted,
+    }
+}
+
+fn against_naive(program: &[u32], input: &[u8], fuel: u64) -> Option<Divergence> {
+    let mut machine = Machine::new(program.to_vec());
+    let mut naive = Naive::new(program.to_vec());
+    let (mut ours, mut theirs) = (input.iter().copied(), input.iter().copied());
+    let mut trace = Trace::default();
+    let diverged = |machine: &Machine, trace: Trace, reason: String| {
+        Some(Divergence { executed: machine.executed(), reason, trace: trace.lines() })
+    };
+    while machine.executed() < fuel {
+        trace.record(&machine);
+        let pc = machine.state.program_counter;
+        let (rum, reference) = (step(&mut machine, &mut ours), naive.step(&mut theirs));
+        let reference_effect = match &reference {
+            Ok(effect) => effect,
+            Err(_) => &Effect::Faulted,
+        };
+        if rum != *reference_effect {
+            let fault = reference.as_ref().err().map_or(String::new(), |e| format!(" ({e})"));
+            return diverged(&machine, trace, format!("at pc {pc}, rum {} but the reference {}{fault}", said(&rum), said(reference_effect)));
+        }
+        if rum == Effect::Halted || rum == Effect::Faulted {
+            break;
+        }
+        if machine.state.program_counter != naive.pc as usize {
+            let reason = format!("after pc {pc}, rum is at pc {} but the reference at {}", machine.state.program_counter, naive.pc);
+            return diverged(&machine, trace, reason);
+        }
+        if let Some(r) = (0..8).find(|&r| machine.state.register(r) != naive.registers[r as usize]) {
+            let reason = format!("after pc {pc}, r{r} is {:#x} in rum but {:#x} in the reference", machine.state.register(r), naive.registers[r as usize]);
+            return diverged(&machine, trace, reason);
+        }
+    }
+    memory_difference(&machine, &naive).and_then(|reason| diverged(&machine, trace, reason))
+}
+
+fn said(effect: &Effect) -> String {
+    match effect {
+        Effect::Continued(None) => "continued".to_string(),
+        Effect::Continued(Some(byte)) => format!("output {byte:#04x}"),
+        Effect::Halted => "halted".to_string(),
+        Effect::Faulted => "faulted".to_string(),
+    }
+}
+
+/// The first segment or word that differs between the two memories.
+fn memory_difference(machine: &Machine, naive: &Naive) -> Option<String> {
+    let state = &machine.state;
+    let free = state.free_segments();
+    let mapped = (0..state.mapped_memory.len() as u32).filter(|id| !free.contains(id));
+    let mut ours: Vec<u32> = mapped.collect();
+    let mut theirs: Vec<u32> = naive.segments.keys().copied().collect();
+    ours.sort();
+    theirs.sort();
+    if ours != theirs {
+        return Some(format!("rum has segments {ours:?} mapped but the reference {theirs:?}"));
+    }
+    for id in ours {
+        let (a, b) = (&state.mapped_memory[id as usize], &naive.segments[&id]);
+        if a.len() != b.len() {
+            return Some(format!("segment {id} is {} words in rum but {} in the reference", a.len(), b.len()));
+        }
+        if let Some(offset) = (0..a.len()).find(|&i| a[i] != b[i]) {
+            return Some(format!("segment {id} word {offset} is {:#x} in rum but {:#x} in the reference", a[offset], b[offset]));
+        }
+    }
+    None
+}
+
+fn against_command(program: &[u32], input: &[u8], command: &PathBuf, fuel: u64) -> Result<Option<Divergence>, String> {
+    let path = std::env::temp_dir().join(format!("rum-differential-{}.um", std::process::id()));
+    let bytes: Vec<u8> = program.iter().flat_map(|word| word.to_be_bytes()).collect();
+    std::fs::write(&path, bytes).map_err(|e| format!("{}: {e}", path.display()))?;
+    let reference = run_command(command, &path, input);
+    let _ = std::fs::remove_file(&path);
+    let reference = reference?;
+
+    let mut machine = Machine::new(program.to_vec());
+    let mut ours = input.iter().copied();
+    let mut output = Vec::new();
+    let mut trace = Trace::default();
+    let mut end = Effect::Continued(None);
+    while machine.executed() < fuel {
+        trace.record(&machine);
+        end = step(&mut machine, &mut ours);
+        match end {
+            Effect::Continued(Some(byte)) => output.push(byte),
+            Effect::Continued(None) => {}
+            Effect::Halted | Effect::Faulted => break,
+        }
+        if !reference.starts_with(&output) {
+            break;
+        }
+    }
+    if output == reference {
+        return Ok(None);
+    }
+    let ended = match end {
+        Effect::Halted => "halted",
+        Effect::Faulted => "faulted",
+        Effect::Continued(_) if machine.executed() >= fuel => "ran out of fuel",
+        Effect::Continued(_) => "was still running",
+    };
+    if reference.starts_with(&output) {
+        let reason = format!("rum {ended} after {} bytes of output; the reference wrote {}", output.len(), reference.len());
+        return Ok(Some(Divergence { executed: machine.executed(), reason, trace: trace.lines() }));
+    }
+    Ok(Some(Divergence { executed: machine.executed(), reason: diff(&reference, &output), trace: trace.lines() }))
+}
+
+/// Output of `command program` given `input`.
+fn run_command(command: &PathBuf, program: &PathBuf, input: &[u8]) -> Result<Vec<u8>, String> {
+    let name = command.display();
+    let mut child = Command::new(command)
+        .arg(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("{name}: {e}"))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    // Written from a thread so a reference that outputs before reading all of its input can't deadlock.
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let result = child.wait_with_output().map_err(|e| format!("{name}: {e}"))?;
+    let _ = writer.join();
+    Ok(result.stdout)
+}
+
+/// The reference interpreter: the spec, one instruction at a time, with
+/// segments in a map and every fault checked for.
+#[derive(Clone)]
+pub(crate) struct Naive {
+    pub(crate) registers: [u32; 8],
+    pub(crate) segments: HashMap<u32, Vec<u32>>,
+    free: Vec<u32>,
+    next: u32,
+    pub(crate) pc: u32,
+}
+
+impl Naive {
+    pub(crate) fn new(program: Vec<u32>) -> Self {
+        Self { registers: [0; 8], segments: HashMap::from([(0, program)]), free: Vec::new(), next: 1, pc: 0 }
+    }
+
+    fn segment(&mut self, id: u32) -> Result<&mut Vec<u32>, String> {
+        self.segments.get_mut(&id).ok_or(format!("segment {id} is not mapped"))
+    }
+
+    pub(crate) fn step(&mut self, input: &mut impl Iterator<Item = u8>) -> Result<Effect, String> {
+        let pc = self.pc;
+        let word = *self.segment(0)?.get(pc as usize).ok_or(format!("pc {pc} is past the end of the program"))?;
+        self.pc += 1;
+        let r = &mut self.registers;
+        match Instruction::decode(word) {
+            Instruction::CMov { a, b, c } => {
+                if r[c as usize] != 0 {
+                    r[a as usize] = r[b as usize];
+                }
+            }
+            Instruction::Load { a, b, c } => {
+                let (id, offset) = (r[b as usize], r[c as usize]);
+                let value = *self.segment(id)?.get(offset as usize).ok_or(format!("offset {offset} is outside segment {id}"))?;
+                self.registers[a as usize] = value;
+            }
+            Instruction::Store { a, b, c } => {
+                let (id, offset, value) = (r[a as usize], r[b as usize], r[c as usize]);
+                *self.segment(id)?.get_mut(offset as usize).ok_or(format!("offset {offset} is outside segment {id}"))? = value;
+            }
+            Instruction::Add { a, b, c } => r[a as usize] = r[b as usize].wrapping_add(r[c as usize]),
+            Instruction::Mul { a, b, c } => r[a as usize] = r[b as usize].wrapping_mul(r[c as usize]),
+            Instruction::Div { a, b, c } => {
+                r[a as usize] = r[b as usize].checked_div(r[c as usize]).ok_or("division by zero")?;
+            }
+            Instruction::Nand { a, b, c } => r[a as usize] = !(r[b as usize] & r[c as usize]),
+            Instruction::Halt => return Ok(Effect::Halted),
+            Instruction::Map { b, c } => {
+                let words = r[c as usize] as usize;
+                let id = self.free.pop().unwrap_or_else(|| {
+                    self.next += 1;
+                    self.next - 1
+                });
+                self.segments.insert(id, vec![0; words]);
+                self.registers[b as usize] = id;
+            }
+            Instruction::Unmap { c } => {
+                let id = r[c as usize];
+                if id == 0 || self.segments.remove(&id).is_none() {
+                    return Err(format!("unmapping segment {id}"));
+                }
+                self.free.push(id);
+            }
+            Instruction::Output { c } => {
+                let value = r[c as usize];
+                return Ok(Effect::Continued(Some(u8::try_from(value).map_err(|_| format!("output of {value}"))?)));
+            }
+            Instruction::Input { c } => r[c as usize] = input.next().map_or(!0, u32::from),
+            Instruction::LoadProgram { b, c } => {
+                let (id, target) = (r[b as usize], r[c as usize]);
+                if id != 0 {
+                    let program = self.segment(id)?.clone();
+                    self.segments.insert(0, program);
+                }
+                self.pc = target;
+            }
+            Instruction::Orthography { a, value } => r[a as usize] = value,
+            Instruction::Invalid(word) => return Err(format!("invalid opcode {}", word >> 28)),
+        }
+        Ok(Effect::Continued(None))
+    }
+}
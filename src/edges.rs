@@ -0,0 +1,91 @@
+//! AFL-style edge coverage of UM programs (feature `edge-coverage`).
+//!
+//! At every dispatch the interpreter hashes the PC into a 16-bit location
+//! and bumps the map entry for the (previous location, location) edge, the
+//! way AFL instrumentation does for native code:
+//!
+//! ```text
+//! cur = hash(pc); map[cur ^ prev] += 1; prev = cur >> 1;
+//! ```
+//!
+//! There is one map per process. Hosts fuzzing in-process call `reset`
+//! before each run and read the map after it (`snapshot`, or
+//! `rum_fuzz_edge_map` from C); `fuzz::run_bounded` resets it itself. Under
+//! afl-fuzz, `attach_afl` switches the map to the shared memory named by
+//! `__AFL_SHM_ID`, so `rum run` reports the UM program's coverage as if it
+//! were an instrumented binary (`rum run` attaches on its own).
+//!
+//! Counts saturate at 255. Machines running on several threads at once
+//! share the map and mix their edges, as threads do under AFL.
+
+use std::sync::atomic::{AtomicPtr, AtomicU8, AtomicUsize, Ordering::Relaxed};
+
+/// Entries in the map, the size AFL uses by default.
+pub const MAP_SIZE: usize = 1 << 16;
+
+static AREA: AtomicPtr<AtomicU8> = AtomicPtr::new(std::ptr::null_mut());
+static PREV: AtomicUsize = AtomicUsize::new(0);
+
+fn area() -> &'static [AtomicU8] {
+    let mut area = AREA.load(Relaxed);
+    if area.is_null() {
+        let own: &'static mut [AtomicU8] = Box::leak((0..MAP_SIZE).map(|_| AtomicU8::new(0)).collect());
+        area = match AREA.compare_exchange(std::ptr::null_mut(), own.as_mut_ptr(), Relaxed, Relaxed) {
+            Ok(_) => own.as_mut_ptr(),
+            // Another thread got there first; the leaked map is only ever 64 KiB.
+            Err(theirs) => theirs,
+        };
+    }
+    // SAFETY: AREA only ever holds a leaked map or an attached shared memory area, both MAP_SIZE long and never freed.
+    unsafe { std::slice::from_raw_parts(area, MAP_SIZE) }
+}
+
+/// Records that the instruction at `pc` is about to execute.
+#[inline]
+pub(crate) fn record(pc: usize) {
+    let cur = ((pc as u32).wrapping_mul(0x9e37_79b1) >> 16) as usize;
+    let entry = &area()[cur ^ PREV.load(Relaxed)];
+    entry.store(entry.load(Relaxed).saturating_add(1), Relaxed);
+    PREV.store(cur >> 1, Relaxed);
+}
+
+/// Clears the map and the previous location, ready for a new run.
+pub fn reset() {
+    for entry in area() {
+        entry.store(0, Relaxed);
+    }
+    PREV.store(0, Relaxed);
+}
+
+/// A copy of the map.
+pub fn snapshot() -> Vec<u8> {
+    area().iter().map(|entry| entry.load(Relaxed)).collect()
+}
+
+/// Distinct edges hit since the last `reset`, up to hash collisions.
+pub fn edges_hit() -> usize {
+    area().iter().filter(|entry| entry.load(Relaxed) != 0).count()
+}
+
+/// The map's address, for hosts reading it in place (see `rum_fuzz_edge_map`).
+pub fn as_ptr() -> *const u8 {
+    area().as_ptr().cast()
+}
+
+/// Moves the map into afl-fuzz's shared memory when `__AFL_SHM_ID` names
+/// one. Returns whether it did.
+#[cfg(unix)]
+pub fn attach_afl() -> Result<bool, String> {
+    let Ok(id) = std::env::var("__AFL_SHM_ID") else {
+        return Ok(false);
+    };
+    let id: libc::c_int = id.parse().map_err(|_| format!("__AFL_SHM_ID is not a number: {id}"))?;
+    // SAFETY: shmat either fails or maps the segment afl-fuzz created, which is at least MAP_SIZE bytes.
+    let shared = unsafe { libc::shmat(id, std::ptr::null(), 0) };
+    if shared as isize == -1 {
+        return Err(format!("shmat({id}): {}", std::io::Error::last_os_error()));
+    }
+    AREA.store(shared.cast(), Relaxed);
+    PREV.store(0, Relaxed);
+    Ok(true)
+}
@@ -0,0 +1,219 @@
+//! Running a machine inside a host application.
+//!
+//! `rumdis::run` runs a program to the end on stdin/stdout. An embedded
+//! machine instead executes as many instructions as it is told to, hands
+//! I/O to the caller, and reports Halt as a status and a fault as a
+//! `MachineError`. This is the layer the language bindings are built on.
+
+use std::cell::RefCell;
+
+use crate::barrier::{code_write, CodeWrite};
+use crate::cancel::{CancellationToken, CHECK_EVERY};
+use crate::error::MachineError;
+use crate::rumdis::{disassemble, get, op, Opcode, RC};
+use crate::state::UniversalMachine;
+
+/// Where an embedded machine stands after executing instructions.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Status {
+    /// More instructions can be executed.
+    Running,
+    /// The program executed Halt; further steps do nothing.
+    Halted,
+}
+
+/// A machine driven one instruction at a time: the hosts around an
+/// embedded `Machine` (`plugin::Host`, `interrupt::Controller`,
+/// `tasks::Scheduler`, `syscall::Syscalls`, `canary::Checked`) and
+/// `um64::Machine64`, which all run the same way.
+pub trait Stepper {
+    /// Executes one instruction, with Input and Output as in `Machine::step`.
+    fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError>;
+    fn status(&self) -> Status;
+    /// Instructions executed so far.
+    fn executed(&self) -> u64;
+    /// True when the embedded machine's cancellation token has been cancelled.
+    fn cancelled(&self) -> bool;
+
+    /// Executes up to `fuel` instructions (all of them when `None`), stopping
+    /// early on Halt or a fault, or with `Status::Running` once cancelled,
+    /// checked every `CHECK_EVERY` instructions as `Machine::run` does.
+    fn run(&mut self, fuel: Option<u64>, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        let mut left = fuel;
+        let mut steps: u64 = 0;
+        while left != Some(0) {
+            if steps.is_multiple_of(CHECK_EVERY) && self.cancelled() {
+                return Ok(self.status());
+            }
+            if self.step(input, output)? == Status::Halted {
+                return Ok(Status::Halted);
+            }
+            left = left.map(|n| n - 1);
+            steps += 1;
+        }
+        Ok(self.status())
+    }
+}
+
+/// A machine's input and output as one object, for hosts that would rather
+/// not pass two closures (see `Machine::run_io`; `testing::TestIo` is one
+/// for tests).
+pub trait Io {
+    /// The next input byte, `Ok(None)` at end of input. The program sees an
+    /// error as end of input, as it does when reading stdin fails.
+    fn read(&mut self) -> std::io::Result<Option<u8>>;
+    fn write(&mut self, byte: u8);
+}
+
+/// A machine driven by its host one instruction, or a budget of instructions, at a time.
+#[derive(Debug, Clone)]
+pub struct Machine {
+    pub state: UniversalMachine,
+    halted: bool,
+    executed: u64,
+    cancel: Option<CancellationToken>,
+}
+
+impl Machine {
+    /// Given program words, create a machine ready to execute them from address 0.
+    pub fn new(program: Vec<u32>) -> Self {
+        let mut state = UniversalMachine::new();
+        state.mapped_memory.push(program);
+        Self::from_state(state)
+    }
+
+    /// Wraps a machine whose program is already in segment 0, e.g. one loaded from a snapshot.
+    pub fn from_state(state: UniversalMachine) -> Self {
+        Self { state, halted: false, executed: 0, cancel: None }
+    }
+
+    /// Lets `token` stop `run` and `run_io` from another thread (see `cancel`).
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// True when the machine's token has been cancelled.
+    pub fn cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+
+    /// Instructions executed so far (Halt not included).
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    pub fn status(&self) -> Status {
+        match self.halted {
+            true => Status::Halted,
+            false => Status::Running,
+        }
+    }
+
+    /// True when the next instruction to execute is Input, so a host that
+    /// has no input yet can wait instead of handing the program end of input.
+    pub fn wants_input(&self) -> bool {
+        let state = &self.state;
+        let next = state.mapped_memory.first().and_then(|program| program.get(state.program_counter));
+        !self.halted && next.is_some_and(|&inst| op(inst) == Some(Opcode::Input))
+    }
+
+    /// Bytes of mapped memory the machine would hold after its next
+    /// instruction, if that instruction is Map Segment. Hosts enforcing a
+    /// memory limit check this before stepping.
+    pub fn mapping(&self) -> Option<u64> {
+        let state = &self.state;
+        let instruction = *state.mapped_memory.first()?.get(state.program_counter)?;
+        (op(instruction) == Some(Opcode::MapSeg))
+            .then(|| state.mapped_bytes() as u64 + state.register(get(&RC, instruction)) as u64 * 4)
+    }
+
+    /// Executes one instruction. Input asks `input` for a byte (`None` is end
+    /// of input), Output hands its byte to `output`. A program that faults
+    /// (invalid opcode, bad segment access, division by zero, output above
+    /// 255, running off the end of segment 0) returns the `MachineError`.
+    pub fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        if self.halted {
+            return Ok(Status::Halted);
+        }
+        heartbeat!(self.executed, self.state);
+        let pc = self.state.program_counter;
+        #[cfg(feature = "edge-coverage")]
+        crate::edges::record(pc);
+        let instruction = *self.state.mapped_memory.first().and_then(|program| program.get(pc)).ok_or(MachineError::ProgramCounterOutOfBounds { pc })?;
+        match op(instruction) {
+            Some(Opcode::Halt) => {
+                info!(executed = self.executed, "halted");
+                self.halted = true;
+                return Ok(Status::Halted);
+            }
+            Some(Opcode::Input) => {
+                self.state.program_counter += 1;
+                let value = input().map_or(!0, u32::from);
+                self.state.load_value(get(&RC, instruction), value);
+            }
+            Some(Opcode::Output) => {
+                let value = self.state.register(get(&RC, instruction));
+                let byte = u8::try_from(value).map_err(|_| MachineError::OutputOutOfRange { pc, value: value.into() })?;
+                self.state.program_counter += 1;
+                output(byte);
+                self.state.usage.output_bytes += 1;
+            }
+            _ => {
+                self.state.program_counter += 1;
+                disassemble(instruction, &mut self.state)?;
+            }
+        }
+        self.executed += 1;
+        Ok(Status::Running)
+    }
+
+    /// `step`, first handing `barrier` the change the instruction is about
+    /// to make to segment 0, if it makes one (see `barrier`).
+    pub fn step_with_barrier(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8), barrier: &mut dyn FnMut(&CodeWrite)) -> Result<Status, MachineError> {
+        if !self.halted {
+            if let Some(write) = self.state.mapped_memory.first().and_then(|program| program.get(self.state.program_counter)).and_then(|&inst| code_write(inst, &self.state)) {
+                barrier(&write);
+            }
+        }
+        self.step(input, output)
+    }
+
+    /// `step` with input and output through `io`.
+    pub fn step_io(&mut self, io: &mut dyn Io) -> Result<Status, MachineError> {
+        let io = RefCell::new(io);
+        self.step(&mut || io.borrow_mut().read().ok().flatten(), &mut |byte| io.borrow_mut().write(byte))
+    }
+
+    /// `run` with input and output through `io`.
+    pub fn run_io(&mut self, fuel: Option<u64>, io: &mut dyn Io) -> Result<Status, MachineError> {
+        let io = RefCell::new(io);
+        self.run(fuel, &mut || io.borrow_mut().read().ok().flatten(), &mut |byte| io.borrow_mut().write(byte))
+    }
+
+    /// Moves past the next instruction and counts it as executed, for a
+    /// caller that has carried it out itself (see `plugin::Host`).
+    pub(crate) fn retire(&mut self) {
+        self.state.program_counter += 1;
+        self.executed += 1;
+    }
+
+    /// Executes up to `fuel` instructions (all of them when `None`), stopping
+    /// early on Halt or a fault, or with `Status::Running` once the machine's
+    /// cancellation token is cancelled.
+    pub fn run(&mut self, fuel: Option<u64>, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        let mut left = fuel;
+        let mut steps: u64 = 0;
+        while left != Some(0) {
+            if steps.is_multiple_of(CHECK_EVERY) && self.cancelled() {
+                return Ok(self.status());
+            }
+            if self.step(input, output)? == Status::Halted {
+                return Ok(Status::Halted);
+            }
+            left = left.map(|n| n - 1);
+            steps += 1;
+        }
+        Ok(self.status())
+    }
+}
@@ -0,0 +1,135 @@
+//! The bit layout of UM instruction words, for tools that read or write
+//! them (assemblers, fuzzers, visualizers) without repeating the shifts.
+//!
+//! ```text
+//!  31    28 27                        9 8   6 5   3 2   0
+//! | opcode |          unused           |  A  |  B  |  C  |   standard
+//!  31    28 27 25 24                                    0
+//! |   13   |  A  |             value (25 bits)          |   Load Value
+//! ```
+//!
+//! A `Field` is a run of bits; `get` and `set` read and write one in a
+//! word. `InstructionWord` has a typed accessor for each field, and
+//! `encode` / `encode_value` build words back up.
+
+use crate::rumdis::{op, Opcode};
+
+/// A bit field of an instruction word: `width` bits from bit `lsb` up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    width: u32,
+    lsb: u32,
+}
+
+impl Field {
+    pub const fn new(width: u32, lsb: u32) -> Self {
+        Self { width, lsb }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn lsb(&self) -> u32 {
+        self.lsb
+    }
+
+    /// The largest value the field holds.
+    pub fn max(&self) -> u32 {
+        mask(self.width)
+    }
+
+    /// The bits of a word the field occupies.
+    pub fn mask(&self) -> u32 {
+        mask(self.width) << self.lsb
+    }
+}
+
+/// Register A of the standard format.
+pub static RA: Field = Field {width: 3, lsb: 6};
+/// Register B of the standard format.
+pub static RB: Field = Field {width: 3, lsb: 3};
+/// Register C of the standard format.
+pub static RC: Field = Field {width: 3, lsb: 0};
+/// Register A of Load Value.
+pub static RL: Field = Field {width: 3, lsb: 25};
+/// The value of Load Value.
+pub static VL: Field = Field {width: 25, lsb: 0};
+/// The opcode.
+pub static OP: Field = Field {width: 4, lsb: 28};
+
+fn mask(bits: u32) -> u32 { (1 << bits) - 1 }
+
+/// Given a `field` and `instruction`, extract
+/// that field from the instruction as a u32
+pub fn get(field: &Field, instruction: u32) -> u32 {
+    (instruction >> field.lsb) & mask(field.width)
+}
+
+/// `instruction` with `field` set to `value`, taken modulo the field's size.
+pub fn set(field: &Field, instruction: u32, value: u32) -> u32 {
+    instruction & !field.mask() | (value & mask(field.width)) << field.lsb
+}
+
+/// The standard-format word for `opcode` with registers `a`, `b` and `c`
+/// (modulo 8) and the unused bits zero. For Load Value use `encode_value`.
+pub fn encode(opcode: Opcode, a: u32, b: u32, c: u32) -> u32 {
+    [(&OP, opcode as u32), (&RA, a), (&RB, b), (&RC, c)].into_iter().fold(0, |word, (field, value)| set(field, word, value))
+}
+
+/// The Load Value word loading `value` (modulo 2^25) into register `a`.
+pub fn encode_value(a: u32, value: u32) -> u32 {
+    set(&VL, set(&RL, set(&OP, 0, Opcode::LoadVal as u32), a), value)
+}
+
+/// An instruction word, with an accessor for each of its fields. Which
+/// fields mean anything depends on the opcode; the accessors read their
+/// bits regardless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InstructionWord(pub u32);
+
+impl InstructionWord {
+    /// The opcode, or `None` for the undefined opcodes 14 and 15.
+    pub fn opcode(self) -> Option<Opcode> {
+        op(self.0)
+    }
+
+    /// The opcode's four bits, 0 to 15.
+    pub fn opcode_bits(self) -> u32 {
+        get(&OP, self.0)
+    }
+
+    pub fn ra(self) -> u32 {
+        get(&RA, self.0)
+    }
+
+    pub fn rb(self) -> u32 {
+        get(&RB, self.0)
+    }
+
+    pub fn rc(self) -> u32 {
+        get(&RC, self.0)
+    }
+
+    /// Load Value's register.
+    pub fn rl(self) -> u32 {
+        get(&RL, self.0)
+    }
+
+    /// Load Value's value.
+    pub fn value(self) -> u32 {
+        get(&VL, self.0)
+    }
+}
+
+impl From<u32> for InstructionWord {
+    fn from(word: u32) -> Self {
+        InstructionWord(word)
+    }
+}
+
+impl From<InstructionWord> for u32 {
+    fn from(word: InstructionWord) -> Self {
+        word.0
+    }
+}
@@ -0,0 +1,74 @@
+//! What Input does at the end of input (`rum run --eof VALUE --after-eof
+//! repeat|terminal|fail`). UM programs disagree: the spec loads all ones
+//! into the register, some programs were written against interpreters that
+//! give 0 or fail, and interactive ones fed a script on stdin want the
+//! terminal once the script runs out.
+//!
+//! - `repeat` (the default) gives `value` at the end of input and on every
+//!   read after it.
+//! - `terminal` switches stdin to the controlling terminal at the end of
+//!   input and carries on reading there, so `rum run --after-eof terminal
+//!   game.um < walkthrough.txt` replays the walkthrough and then hands the
+//!   game over. When that ends too, or there is no terminal, it repeats.
+//! - `fail` gives `value` once and faults the machine if it reads again.
+
+/// The behaviour chosen for the end of input, and whether it was reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Eof {
+    /// Loaded into the register at the end of input.
+    pub value: u32,
+    pub after: AfterEof,
+    /// The end of input has been read.
+    pub(crate) reached: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AfterEof {
+    Repeat,
+    Terminal,
+    Fail,
+}
+
+impl Default for Eof {
+    fn default() -> Self {
+        Self { value: !0, after: AfterEof::Repeat, reached: false }
+    }
+}
+
+impl AfterEof {
+    pub fn parse(text: &str) -> Result<AfterEof, String> {
+        match text {
+            "repeat" => Ok(AfterEof::Repeat),
+            "terminal" => Ok(AfterEof::Terminal),
+            "fail" => Ok(AfterEof::Fail),
+            _ => Err(format!("--after-eof takes repeat, terminal or fail, not `{text}`")),
+        }
+    }
+}
+
+/// Given `--eof`'s argument (decimal or `0x` hex), the value it names.
+pub fn parse_value(text: &str) -> Result<u32, String> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .map_err(|_| format!("--eof takes a word such as 0xffffffff or 0, not `{text}`"))
+}
+
+/// Makes the controlling terminal the process's stdin.
+#[cfg(unix)]
+pub(crate) fn reopen_terminal() -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let tty = std::fs::File::open("/dev/tty")?;
+    // SAFETY: both descriptors are open; dup2 only replaces stdin.
+    if unsafe { libc::dup2(tty.as_raw_fd(), libc::STDIN_FILENO) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn reopen_terminal() -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "reopening the terminal needs a Unix system"))
+}
@@ -0,0 +1,81 @@
+//! Machine failures: what the UM spec calls a program failing, as a value
+//! the host can look at rather than a panic.
+//!
+//! `disassemble` and `rumdis::run` return a `MachineError` when the program
+//! faults, carrying the address of the instruction that did, so a host can
+//! report it and carry on; `rum` itself prints it and exits with
+//! `FAULT_EXIT_CODE`. The embedded `Machine` and the hosts built on it
+//! return it from `step` and `run` the same way.
+
+use std::fmt;
+
+/// Why the program failed; `pc` is the address of the failing instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineError {
+    /// Opcode 14 or 15, with no extension giving it a meaning.
+    InvalidOpcode { pc: usize, instruction: u32 },
+    /// The program counter points past the end of segment 0.
+    ProgramCounterOutOfBounds { pc: usize },
+    /// Load, Store, Unmap Segment or Load Program named a segment that was
+    /// never mapped.
+    UnmappedSegment { pc: usize, segment: u64 },
+    /// Load or Store past the end of a segment (an unmapped one has no words).
+    OutOfBounds { pc: usize, segment: u64, offset: u64 },
+    DivisionByZero { pc: usize },
+    /// Output of a value above 255.
+    OutputOutOfRange { pc: usize, value: u64 },
+    /// Input again after the end of input, with `--after-eof fail`.
+    InputAfterEnd { pc: usize },
+    /// A `--deny` rule refused the instruction (see `policy`).
+    PolicyViolation { pc: usize, message: String },
+    /// Something the host added failed the program: a plugin's opcode, a
+    /// missing or failing syscall service, a canary check.
+    HostFault { pc: usize, message: String },
+    /// The run could not open something it was asked to write to or through
+    /// (a replay log, an event log, output filters); `pc` is where it was to
+    /// start. Not the program's fault.
+    Io { pc: usize, message: String },
+}
+
+impl MachineError {
+    /// The address of the instruction that failed.
+    pub fn pc(&self) -> usize {
+        match self {
+            MachineError::InvalidOpcode { pc, .. }
+            | MachineError::ProgramCounterOutOfBounds { pc }
+            | MachineError::UnmappedSegment { pc, .. }
+            | MachineError::OutOfBounds { pc, .. }
+            | MachineError::DivisionByZero { pc }
+            | MachineError::OutputOutOfRange { pc, .. }
+            | MachineError::InputAfterEnd { pc }
+            | MachineError::PolicyViolation { pc, .. }
+            | MachineError::HostFault { pc, .. }
+            | MachineError::Io { pc, .. } => *pc,
+        }
+    }
+
+    /// What went wrong, without the address.
+    pub fn message(&self) -> String {
+        match self {
+            MachineError::InvalidOpcode { instruction, .. } => format!("invalid opcode {} ({instruction:#010x})", instruction >> 28),
+            MachineError::ProgramCounterOutOfBounds { .. } => "program counter outside the program".to_string(),
+            MachineError::UnmappedSegment { segment, .. } => format!("segment {segment} is not mapped"),
+            MachineError::OutOfBounds { segment, offset, .. } => format!("offset {offset} is past the end of segment {segment}"),
+            MachineError::DivisionByZero { .. } => "division by zero".to_string(),
+            MachineError::OutputOutOfRange { value, .. } => format!("output of {value}, above 255"),
+            MachineError::InputAfterEnd { .. } => "input after the end of input".to_string(),
+            MachineError::PolicyViolation { message, .. } | MachineError::HostFault { message, .. } | MachineError::Io { message, .. } => message.clone(),
+        }
+    }
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MachineError::Io { message, .. } => f.write_str(message),
+            _ => write!(f, "{} at pc {}", self.message(), self.pc()),
+        }
+    }
+}
+
+impl std::error::Error for MachineError {}
@@ -0,0 +1,319 @@
+//! The event log of `rum run --event-log FILE`: what a run did to the world
+//! and to its memory, one record per event, for looking into very long runs
+//! after the fact with `rum log query`.
+//!
+//! The log is written as the run goes and only ever appended to, so a run
+//! that is killed leaves everything up to its last buffered record. It
+//! starts with `MAGIC` and a u16 version, then holds records of a one-byte
+//! kind, the instructions executed before the event (u64) and the PC of its
+//! instruction (u32), followed by the kind's fields, all big-endian:
+//!
+//! ```text
+//! 'i'  input     value read (u32; 0xffffffff or the --eof value at the end)
+//! 'o'  output    byte written (u32)
+//! 'm'  map       segment (u32), words (u32)
+//! 'u'  unmap     segment (u32)
+//! 'l'  load      source segment (u32), words (u32), new PC (u32)
+//! 'f'  fault     message length (u32), message (UTF-8)
+//! 'h'  halt
+//! 'x'  limit     the instruction limit stopped the run
+//! ```
+//!
+//! Load Program is only logged when it replaces the program: the jumps it
+//! makes within segment 0 would swamp everything else. A fault is only
+//! logged when the run loop catches it (it does when the log is on).
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::rumdis::{get, op, Opcode, RB, RC};
+use crate::state::UniversalMachine;
+
+pub const MAGIC: &[u8; 8] = b"RUMEVLOG";
+
+/// Format version written by this crate.
+pub const VERSION: u16 = 1;
+
+/// What happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Input(u32),
+    Output(u32),
+    Map { segment: u32, words: u32 },
+    Unmap { segment: u32 },
+    Load { segment: u32, words: u32, target: u32 },
+    Fault(String),
+    Halt,
+    Limit,
+}
+
+impl Kind {
+    /// The name `rum log query --kind` and its reports use.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Input(_) => "input",
+            Kind::Output(_) => "output",
+            Kind::Map { .. } => "map",
+            Kind::Unmap { .. } => "unmap",
+            Kind::Load { .. } => "load",
+            Kind::Fault(_) => "fault",
+            Kind::Halt => "halt",
+            Kind::Limit => "limit",
+        }
+    }
+
+    /// The names of all kinds, as `name` gives them.
+    pub const NAMES: [&'static str; 8] = ["input", "output", "map", "unmap", "load", "fault", "halt", "limit"];
+}
+
+/// One record of the log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// Instructions executed before the event's instruction.
+    pub executed: u64,
+    pub pc: u32,
+    pub kind: Kind,
+}
+
+impl Event {
+    /// The segment the event concerns, if any.
+    pub fn segment(&self) -> Option<u32> {
+        match self.kind {
+            Kind::Map { segment, .. } | Kind::Unmap { segment } | Kind::Load { segment, .. } => Some(segment),
+            _ => None,
+        }
+    }
+
+    /// The words mapped or loaded, if any.
+    pub fn words(&self) -> Option<u32> {
+        match self.kind {
+            Kind::Map { words, .. } | Kind::Load { words, .. } => Some(words),
+            _ => None,
+        }
+    }
+
+    /// One line for `rum log query`.
+    pub fn describe(&self) -> String {
+        let what = match &self.kind {
+            Kind::Input(value) | Kind::Output(value) if *value < 0x80 && (*value as u8).is_ascii_graphic() => {
+                format!("{value:#04x} '{}'", *value as u8 as char)
+            }
+            Kind::Input(u32::MAX) => "end of input".to_string(),
+            Kind::Input(value) | Kind::Output(value) => format!("{value:#04x}"),
+            Kind::Map { segment, words } => format!("segment {segment}, {words} words ({} bytes)", *words as u64 * 4),
+            Kind::Unmap { segment } => format!("segment {segment}"),
+            Kind::Load { segment, words, target } => format!("segment {segment} ({words} words) as the program, from {target}"),
+            Kind::Fault(message) => message.clone(),
+            Kind::Halt => String::new(),
+            Kind::Limit => "instruction limit reached".to_string(),
+        };
+        format!("{:>14}  pc {:<8}  {:<6}  {what}", self.executed, self.pc, self.kind.name()).trim_end().to_string()
+    }
+}
+
+/// Writes the log of a run.
+#[derive(Debug)]
+pub struct EventLog {
+    out: BufWriter<File>,
+    executed: u64,
+    pc: u32,
+    /// The first write error; the rest of the log is lost with it.
+    error: Option<std::io::Error>,
+}
+
+impl EventLog {
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_be_bytes())?;
+        Ok(Self { out, executed: 0, pc: 0, error: None })
+    }
+
+    /// Given the instruction about to run, `executed` instructions in,
+    /// logs what is known before it runs.
+    pub fn before(&mut self, executed: u64, pc: usize, inst: u32, state: &UniversalMachine) {
+        (self.executed, self.pc) = (executed, pc as u32);
+        let reg = |field| state.register(get(field, inst));
+        match op(inst) {
+            Some(Opcode::Output) => self.record(Kind::Output(reg(&RC))),
+            Some(Opcode::UnmapSeg) => self.record(Kind::Unmap { segment: reg(&RC) }),
+            Some(Opcode::LoadProg) if reg(&RB) != 0 => {
+                let segment = reg(&RB);
+                let words = state.mapped_memory.get(segment as usize).map_or(0, Vec::len) as u32;
+                self.record(Kind::Load { segment, words, target: reg(&RC) });
+            }
+            _ => {}
+        }
+    }
+
+    /// Given the instruction that just ran, logs what it did.
+    pub fn after(&mut self, inst: u32, state: &UniversalMachine) {
+        let reg = |field| state.register(get(field, inst));
+        match op(inst) {
+            Some(Opcode::Input) => self.record(Kind::Input(reg(&RC))),
+            Some(Opcode::MapSeg) => {
+                let segment = reg(&RB);
+                let words = state.mapped_memory[segment as usize].len() as u32;
+                self.record(Kind::Map { segment, words });
+            }
+            _ => {}
+        }
+    }
+
+    /// Logs how the run ended, `end` (a halt, fault or the limit) at `pc`
+    /// after `executed` instructions, and flushes the log.
+    pub fn finish(mut self, executed: u64, pc: usize, end: Kind) -> std::io::Result<()> {
+        (self.executed, self.pc) = (executed, pc as u32);
+        self.record(end);
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.out.flush()
+    }
+
+    fn record(&mut self, kind: Kind) {
+        if self.error.is_some() {
+            return;
+        }
+        let mut record = Vec::with_capacity(32);
+        record.push(tag(&kind));
+        record.extend(self.executed.to_be_bytes());
+        record.extend(self.pc.to_be_bytes());
+        match &kind {
+            Kind::Input(value) | Kind::Output(value) => record.extend(value.to_be_bytes()),
+            Kind::Map { segment, words } => {
+                record.extend(segment.to_be_bytes());
+                record.extend(words.to_be_bytes());
+            }
+            Kind::Unmap { segment } => record.extend(segment.to_be_bytes()),
+            Kind::Load { segment, words, target } => {
+                record.extend(segment.to_be_bytes());
+                record.extend(words.to_be_bytes());
+                record.extend(target.to_be_bytes());
+            }
+            Kind::Fault(message) => {
+                record.extend((message.len() as u32).to_be_bytes());
+                record.extend(message.as_bytes());
+            }
+            Kind::Halt | Kind::Limit => {}
+        }
+        if let Err(e) = self.out.write_all(&record) {
+            self.error = Some(e);
+        }
+    }
+}
+
+fn tag(kind: &Kind) -> u8 {
+    match kind {
+        Kind::Input(_) => b'i',
+        Kind::Output(_) => b'o',
+        Kind::Map { .. } => b'm',
+        Kind::Unmap { .. } => b'u',
+        Kind::Load { .. } => b'l',
+        Kind::Fault(_) => b'f',
+        Kind::Halt => b'h',
+        Kind::Limit => b'x',
+    }
+}
+
+/// Reads a log back one event at a time, so logs larger than memory can be queried.
+pub struct Reader<R: Read> {
+    input: R,
+    done: bool,
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Reader::new(BufReader::new(file)).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+impl<R: Read> Reader<R> {
+    /// Checks the header of the log `input` and starts reading after it.
+    pub fn new(mut input: R) -> Result<Self, String> {
+        let mut header = [0; 10];
+        input.read_exact(&mut header).map_err(|_| "not a rum event log")?;
+        if &header[..8] != MAGIC {
+            return Err("not a rum event log".to_string());
+        }
+        let version = u16::from_be_bytes([header[8], header[9]]);
+        if version != VERSION {
+            return Err(format!("unsupported event log version {version}"));
+        }
+        Ok(Self { input, done: false })
+    }
+
+    fn read(&mut self) -> Result<Option<Event>, String> {
+        let mut tag = [0];
+        if self.input.read(&mut tag).map_err(|e| e.to_string())? == 0 {
+            return Ok(None);
+        }
+        let cut = |_| "the log ends in the middle of a record: the run was cut short".to_string();
+        let mut word = || -> Result<u32, String> {
+            let mut bytes = [0; 4];
+            self.input.read_exact(&mut bytes).map_err(cut)?;
+            Ok(u32::from_be_bytes(bytes))
+        };
+        let executed = (word()? as u64) << 32 | word()? as u64;
+        let pc = word()?;
+        let kind = match tag[0] {
+            b'i' => Kind::Input(word()?),
+            b'o' => Kind::Output(word()?),
+            b'm' => Kind::Map { segment: word()?, words: word()? },
+            b'u' => Kind::Unmap { segment: word()? },
+            b'l' => Kind::Load { segment: word()?, words: word()?, target: word()? },
+            b'f' => {
+                let mut message = vec![0; word()? as usize];
+                self.input.read_exact(&mut message).map_err(cut)?;
+                Kind::Fault(String::from_utf8_lossy(&message).into_owned())
+            }
+            b'h' => Kind::Halt,
+            b'x' => Kind::Limit,
+            other => return Err(format!("the log is corrupt (unknown record `{}`)", other as char)),
+        };
+        Ok(Some(Event { executed, pc, kind }))
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<Event, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let event = self.read().transpose();
+        // Nothing after a bad record can be trusted.
+        self.done = !matches!(event, Some(Ok(_)));
+        event
+    }
+}
+
+/// Which events `rum log query` prints.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    /// Kinds by name; empty for all of them.
+    pub kinds: Vec<String>,
+    /// Only events after this many instructions.
+    pub after: Option<u64>,
+    /// Only events before this many instructions.
+    pub before: Option<u64>,
+    /// Only maps and loads of more than this many bytes.
+    pub larger_than: Option<u64>,
+    pub segment: Option<u32>,
+    pub pc: Option<u32>,
+}
+
+impl Query {
+    pub fn matches(&self, event: &Event) -> bool {
+        (self.kinds.is_empty() || self.kinds.iter().any(|kind| kind == event.kind.name()))
+            && self.after.is_none_or(|after| event.executed > after)
+            && self.before.is_none_or(|before| event.executed < before)
+            && self.larger_than.is_none_or(|bytes| event.words().is_some_and(|words| words as u64 * 4 > bytes))
+            && self.segment.is_none_or(|segment| event.segment() == Some(segment))
+            && self.pc.is_none_or(|pc| event.pc == pc)
+    }
+}
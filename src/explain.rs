@@ -0,0 +1,89 @@
+//! Plain-English explanations of instructions for `--explain`, aimed at
+//! people learning the UM: what an instruction did to the machine, with the
+//! values it read and wrote, e.g.
+//!
+//! ```text
+//! r3 := NAND(r1, r2) = NAND(0xff00ff00, 0x0000ff00) = 0xffff00ff (was 0x0)
+//! ```
+//!
+//! The registers (and the word a Store overwrites) are noted with
+//! `Explain::before`, and `after` describes the effect once the instruction
+//! has run.
+
+use crate::instruction::Instruction;
+use crate::state::UniversalMachine;
+
+/// An instruction about to run, with the state it reads.
+#[derive(Debug, Clone)]
+pub struct Explain {
+    instruction: Instruction,
+    registers: [u32; 8],
+    pc: usize,
+    /// The word a Store is about to overwrite.
+    old: Option<u32>,
+    /// Words in the segment a Load Program copies.
+    words: usize,
+}
+
+impl Explain {
+    /// Notes the instruction `inst` about to run in `state`.
+    pub fn before(inst: u32, state: &UniversalMachine) -> Self {
+        let instruction = Instruction::decode(inst);
+        let registers = std::array::from_fn(|r| state.register(r as u32));
+        let old = match instruction {
+            Instruction::Store { a, b, .. } => state.mapped_memory.get(registers[a as usize] as usize).and_then(|seg| seg.get(registers[b as usize] as usize)).copied(),
+            _ => None,
+        };
+        let words = match instruction {
+            Instruction::LoadProgram { b, .. } => state.mapped_memory.get(registers[b as usize] as usize).map_or(0, Vec::len),
+            _ => 0,
+        };
+        Self { instruction, registers, pc: state.program_counter, old, words }
+    }
+
+    /// What the instruction did, given `state` after it ran.
+    pub fn after(&self, state: &UniversalMachine) -> String {
+        let was = |r: u32| self.registers[r as usize];
+        let now = |r: u32| state.register(r);
+        let set = |r: u32| format!("{:#x} (was {:#x})", now(r), was(r));
+        match self.instruction {
+            Instruction::CMov { a, b, c } if was(c) != 0 => format!("r{c} = {:#x} is not 0, so r{a} := r{b} = {}", was(c), set(a)),
+            Instruction::CMov { a, c, .. } => format!("r{c} is 0, so r{a} keeps {:#x}", was(a)),
+            Instruction::Load { a, b, c } => format!("r{a} := segment r{b} at offset r{c} = [{}][{}] = {}", was(b), was(c), set(a)),
+            Instruction::Store { a, b, c } => format!(
+                "segment r{a} at offset r{b} := r{c}: [{}][{}] = {:#x} (was {:#x})",
+                was(a),
+                was(b),
+                was(c),
+                self.old.unwrap_or(0)
+            ),
+            Instruction::Add { a, b, c } => format!("r{a} := r{b} + r{c} = {:#x} + {:#x} = {} (mod 2^32)", was(b), was(c), set(a)),
+            Instruction::Mul { a, b, c } => format!("r{a} := r{b} * r{c} = {:#x} * {:#x} = {} (mod 2^32)", was(b), was(c), set(a)),
+            Instruction::Div { a, b, c } => format!("r{a} := r{b} / r{c} = {:#x} / {:#x} = {} (rounded down)", was(b), was(c), set(a)),
+            Instruction::Nand { a, b, c } => format!("r{a} := NAND(r{b}, r{c}) = NAND({:#010x}, {:#010x}) = {}", was(b), was(c), set(a)),
+            Instruction::Halt => "halt: the machine stops".to_string(),
+            Instruction::Map { b, c } => format!("map a new segment of r{c} = {} words, all 0; its identifier goes in r{b} = {}", was(c), set(b)),
+            Instruction::Unmap { c } => format!("unmap segment r{c} = {}; its identifier can be mapped again", was(c)),
+            Instruction::Output { c } => format!("output r{c} = {:#x}{}", was(c), printable(was(c))),
+            Instruction::Input { c } if now(c) == !0 => format!("input: end of input, so r{c} := 0xffffffff (was {:#x})", was(c)),
+            Instruction::Input { c } => format!("input{}: r{c} := {}", printable(now(c)), set(c)),
+            Instruction::LoadProgram { b, c } if was(b) == 0 => format!("jump: pc := r{c} = {} (was {})", was(c), self.pc + 1),
+            Instruction::LoadProgram { b, c } => format!(
+                "replace segment 0 with a copy of segment r{b} = {} ({} words), then pc := r{c} = {}",
+                was(b),
+                self.words,
+                was(c)
+            ),
+            Instruction::Orthography { a, value } => format!("r{a} := {value} = {}", set(a)),
+            Instruction::Invalid(word) => format!("opcode {} is not a UM instruction: the machine faults", word >> 28),
+        }
+    }
+}
+
+/// ` ('A')` for a printable byte, nothing otherwise.
+fn printable(value: u32) -> String {
+    match u8::try_from(value) {
+        Ok(byte) if byte.is_ascii_graphic() || byte == b' ' => format!(" ('{}')", byte as char),
+        _ => String::new(),
+    }
+}
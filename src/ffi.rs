@@ -0,0 +1,283 @@
+//! C ABI for embedding the interpreter, declared in `include/rum.h`.
+//!
+//! A `rum_machine` is an opaque pointer to an `embed::Machine` plus the
+//! host's I/O callbacks. Every function taking one expects a pointer
+//! returned by `rum_machine_new` and not yet passed to `rum_machine_free`.
+//! Faults inside the program are reported as `RUM_FAULT`; the machine is
+//! unusable afterwards, and `rum_machine_fault_pc` and
+//! `rum_machine_fault_message` tell what went wrong.
+
+use std::ffi::{c_char, c_int, c_void, CString};
+
+use crate::embed::{Machine, Status};
+use crate::error::MachineError;
+
+pub const RUM_RUNNING: c_int = 0;
+pub const RUM_HALTED: c_int = 1;
+pub const RUM_FAULT: c_int = -1;
+
+/// Returns the next input byte (0-255), or a negative value at end of input.
+pub type InputFn = Option<unsafe extern "C" fn(user: *mut c_void) -> c_int>;
+/// Receives each byte the program outputs.
+pub type OutputFn = Option<unsafe extern "C" fn(user: *mut c_void, byte: u8)>;
+
+pub struct RumMachine {
+    machine: Machine,
+    input: InputFn,
+    output: OutputFn,
+    user: *mut c_void,
+    fault: Option<Fault>,
+}
+
+/// The error that ended the last run, kept for the fault accessors.
+struct Fault {
+    pc: u64,
+    message: CString,
+}
+
+impl RumMachine {
+    /// Runs `f` against the machine with the host callbacks wired up as its I/O.
+    fn drive(&mut self, f: impl FnOnce(&mut Machine, &mut dyn FnMut() -> Option<u8>, &mut dyn FnMut(u8)) -> Result<Status, MachineError>) -> c_int {
+        if self.fault.is_some() {
+            return RUM_FAULT;
+        }
+        let (input, output, user) = (self.input, self.output, self.user);
+        let mut read = || {
+            // SAFETY: the host promised the callback and user pointer are valid (see rum_machine_set_io).
+            let value = input.map_or(-1, |input| unsafe { input(user) });
+            u8::try_from(value).ok()
+        };
+        let mut write = |byte| {
+            if let Some(output) = output {
+                // SAFETY: as above.
+                unsafe { output(user, byte) }
+            }
+        };
+        match f(&mut self.machine, &mut read, &mut write) {
+            Ok(Status::Running) => RUM_RUNNING,
+            Ok(Status::Halted) => RUM_HALTED,
+            Err(e) => {
+                // A plugin's message could hold a NUL, which C cannot take.
+                let message = CString::new(e.message().replace('\0', "")).unwrap_or_default();
+                self.fault = Some(Fault { pc: e.pc() as u64, message });
+                RUM_FAULT
+            }
+        }
+    }
+}
+
+/// Borrows the machine behind a handle, or `None` for a null handle.
+///
+/// # Safety
+///
+/// `m` must be null or a live handle from `rum_machine_new`.
+unsafe fn machine<'a>(m: *mut RumMachine) -> Option<&'a mut RumMachine> {
+    m.as_mut()
+}
+
+/// Creates a machine with no program loaded. Free it with `rum_machine_free`.
+#[no_mangle]
+pub extern "C" fn rum_machine_new() -> *mut RumMachine {
+    Box::into_raw(Box::new(RumMachine {
+        machine: Machine::new(Vec::new()),
+        input: None,
+        output: None,
+        user: std::ptr::null_mut(),
+        fault: None,
+    }))
+}
+
+/// Destroys a machine. Null is ignored.
+///
+/// # Safety
+///
+/// `m` must be null or a live handle, and is dangling afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_free(m: *mut RumMachine) {
+    if !m.is_null() {
+        drop(Box::from_raw(m));
+    }
+}
+
+/// Resets the machine and loads a program given as big-endian words, the
+/// contents of a `.um` file. Returns 0, or -1 if `len` is not a multiple of 4.
+///
+/// # Safety
+///
+/// `m` must be a live handle and `bytes` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_load(m: *mut RumMachine, bytes: *const u8, len: usize) -> c_int {
+    let Some(m) = machine(m) else { return -1 };
+    if !len.is_multiple_of(4) || (bytes.is_null() && len > 0) {
+        return -1;
+    }
+    let bytes = if len == 0 { &[][..] } else { std::slice::from_raw_parts(bytes, len) };
+    let program = crate::load::words(bytes);
+    m.machine = Machine::new(program);
+    m.fault = None;
+    0
+}
+
+/// Installs the I/O callbacks, both called with `user`. Without an input
+/// callback the program sees end of input; without an output callback its
+/// output is dropped.
+///
+/// # Safety
+///
+/// `m` must be a live handle; the callbacks must stay callable with `user`
+/// for as long as the machine runs.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_set_io(m: *mut RumMachine, input: InputFn, output: OutputFn, user: *mut c_void) {
+    if let Some(m) = machine(m) {
+        m.input = input;
+        m.output = output;
+        m.user = user;
+    }
+}
+
+/// Executes one instruction, returning `RUM_RUNNING`, `RUM_HALTED` or `RUM_FAULT`.
+///
+/// # Safety
+///
+/// `m` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_step(m: *mut RumMachine) -> c_int {
+    let Some(m) = machine(m) else { return RUM_FAULT };
+    m.drive(|machine, input, output| machine.step(input, output))
+}
+
+/// Executes up to `fuel` instructions (0 means until Halt). Returns like
+/// `rum_machine_step`; `RUM_RUNNING` means the fuel ran out.
+///
+/// # Safety
+///
+/// `m` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_run(m: *mut RumMachine, fuel: u64) -> c_int {
+    let Some(m) = machine(m) else { return RUM_FAULT };
+    let fuel = (fuel > 0).then_some(fuel);
+    m.drive(|machine, input, output| machine.run(fuel, input, output))
+}
+
+/// Instructions executed since the program was loaded.
+///
+/// # Safety
+///
+/// `m` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_executed(m: *const RumMachine) -> u64 {
+    m.as_ref().map_or(0, |m| m.machine.executed())
+}
+
+/// Value of register `r` (0-7); out of range registers read as 0.
+///
+/// # Safety
+///
+/// `m` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_register(m: *const RumMachine, r: u32) -> u32 {
+    match m.as_ref() {
+        Some(m) if r < 8 => m.machine.state.register(r),
+        _ => 0,
+    }
+}
+
+/// Sets register `r` (0-7). Returns 0, or -1 if `r` is out of range.
+///
+/// # Safety
+///
+/// `m` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_set_register(m: *mut RumMachine, r: u32, value: u32) -> c_int {
+    match machine(m) {
+        Some(m) if r < 8 => {
+            m.machine.state.load_value(r, value);
+            0
+        }
+        _ => -1,
+    }
+}
+
+/// Address in segment 0 of the next instruction.
+///
+/// # Safety
+///
+/// `m` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_pc(m: *const RumMachine) -> u64 {
+    m.as_ref().map_or(0, |m| m.machine.state.program_counter as u64)
+}
+
+/// Number of words in segment `segment`; 0 for unmapped or unknown segments.
+///
+/// # Safety
+///
+/// `m` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_segment_len(m: *const RumMachine, segment: u32) -> usize {
+    m.as_ref().and_then(|m| m.machine.state.mapped_memory.get(segment as usize)).map_or(0, Vec::len)
+}
+
+/// Reads the word at `segment[offset]` into `*out`. Returns 0, or -1 if it does not exist.
+///
+/// # Safety
+///
+/// `m` must be a live handle and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_read(m: *const RumMachine, segment: u32, offset: u32, out: *mut u32) -> c_int {
+    let word = m.as_ref().and_then(|m| m.machine.state.mapped_memory.get(segment as usize)?.get(offset as usize));
+    match (word, out.as_mut()) {
+        (Some(&word), Some(out)) => {
+            *out = word;
+            0
+        }
+        _ => -1,
+    }
+}
+
+/// Writes `value` to `segment[offset]`. Returns 0, or -1 if it does not exist.
+///
+/// # Safety
+///
+/// `m` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_write(m: *mut RumMachine, segment: u32, offset: u32, value: u32) -> c_int {
+    let word = machine(m).and_then(|m| m.machine.state.segment_mut(segment as usize)?.get_mut(offset as usize));
+    match word {
+        Some(word) => {
+            *word = value;
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Writes the address of the faulting instruction to `*out`. Returns 0, or
+/// -1 if the machine has not faulted since it was loaded.
+///
+/// # Safety
+///
+/// `m` must be a live handle and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_fault_pc(m: *const RumMachine, out: *mut u64) -> c_int {
+    match (m.as_ref().and_then(|m| m.fault.as_ref()), out.as_mut()) {
+        (Some(fault), Some(out)) => {
+            *out = fault.pc;
+            0
+        }
+        _ => -1,
+    }
+}
+
+/// What the fault was, as a NUL-terminated string such as "division by
+/// zero", or null if the machine has not faulted since it was loaded. The
+/// string belongs to the machine and stays valid until the next
+/// `rum_machine_load` or `rum_machine_free`.
+///
+/// # Safety
+///
+/// `m` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn rum_machine_fault_message(m: *const RumMachine) -> *const c_char {
+    m.as_ref().and_then(|m| m.fault.as_ref()).map_or(std::ptr::null(), |fault| fault.message.as_ptr())
+}
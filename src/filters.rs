@@ -0,0 +1,244 @@
+//! Output filters (`rum run --output-filter`): a pipeline between the
+//! program's Output instructions and stdout that reshapes what the user
+//! sees, leaving `--hash-output` digests, run directory transcripts and
+//! `--inspect` to see the bytes as the program wrote them.
+//!
+//! Each filter implements `OutputFilter`, taking bytes one at a time and
+//! passing on any number of its own; a `Pipeline` chains them, each
+//! feeding the next. The filters named on the command line are:
+//!
+//! - `strip-ansi`: drops ANSI escape sequences (colours, cursor movement),
+//!   for terminal programs whose output is saved or diffed;
+//! - `uppercase`: ASCII letters in upper case;
+//! - `timestamp`: starts every line with the seconds since the run started,
+//!   `[   1.204] `, by the virtual clock with `--deterministic`;
+//! - `tee:FILE`: copies what reaches it to FILE and passes it on, so
+//!   `tee:raw.txt,strip-ansi` keeps the raw output while the terminal gets
+//!   the plain one.
+//!
+//! Several can be given at once, separated by commas, and `--output-filter`
+//! can be repeated; they apply in order. Embedders build a `Pipeline` with
+//! `Pipeline::with`, adding their own filters.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::clock::Clock;
+
+/// A stage of the output pipeline.
+pub trait OutputFilter {
+    /// Takes `byte`, written `executed` instructions into the run, and
+    /// appends what it passes on to `out`.
+    fn filter(&mut self, byte: u8, executed: u64, out: &mut Vec<u8>);
+
+    /// Called once when the run ends, to pass on anything held back.
+    fn finish(&mut self, _out: &mut Vec<u8>) {}
+}
+
+/// Drops ANSI escape sequences: CSI (`ESC [` parameters and a final byte),
+/// OSC (`ESC ]` up to BEL or `ESC \`) and two-byte `ESC X` sequences.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StripAnsi {
+    state: Escape,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Escape {
+    #[default]
+    Text,
+    /// After ESC.
+    Start,
+    /// Inside `ESC [`.
+    Csi,
+    /// Inside `ESC ]`.
+    Osc,
+    /// After ESC inside `ESC ]`, which ends it with `\`.
+    OscEsc,
+}
+
+impl OutputFilter for StripAnsi {
+    fn filter(&mut self, byte: u8, _: u64, out: &mut Vec<u8>) {
+        self.state = match (self.state, byte) {
+            (Escape::Text, 0x1b) => Escape::Start,
+            (Escape::Text, _) => {
+                out.push(byte);
+                Escape::Text
+            }
+            (Escape::Start, b'[') => Escape::Csi,
+            (Escape::Start, b']') => Escape::Osc,
+            (Escape::Start, _) => Escape::Text,
+            (Escape::Csi, 0x40..=0x7e) => Escape::Text,
+            (Escape::Csi, _) => Escape::Csi,
+            (Escape::Osc, 0x07) => Escape::Text,
+            (Escape::Osc, 0x1b) => Escape::OscEsc,
+            (Escape::Osc, _) => Escape::Osc,
+            (Escape::OscEsc, _) => Escape::Text,
+        };
+    }
+}
+
+/// ASCII letters in upper case.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Uppercase;
+
+impl OutputFilter for Uppercase {
+    fn filter(&mut self, byte: u8, _: u64, out: &mut Vec<u8>) {
+        out.push(byte.to_ascii_uppercase());
+    }
+}
+
+/// Starts every line with the time since the run started.
+#[derive(Debug, Clone, Copy)]
+pub struct Timestamp {
+    clock: Clock,
+    line_start: bool,
+}
+
+impl Timestamp {
+    pub fn new(clock: Clock) -> Self {
+        Self { clock, line_start: true }
+    }
+}
+
+impl OutputFilter for Timestamp {
+    fn filter(&mut self, byte: u8, executed: u64, out: &mut Vec<u8>) {
+        if self.line_start {
+            let _ = write!(out, "[{:>8.3}] ", self.clock.elapsed(executed).as_secs_f64());
+        }
+        out.push(byte);
+        self.line_start = byte == b'\n';
+    }
+}
+
+/// Copies everything to a file and passes it on.
+#[derive(Debug)]
+pub struct Tee {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl Tee {
+    pub fn create(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let file = BufWriter::new(File::create(&path)?);
+        Ok(Self { path, file })
+    }
+}
+
+impl OutputFilter for Tee {
+    fn filter(&mut self, byte: u8, _: u64, out: &mut Vec<u8>) {
+        // A failed write shows up again when the file is flushed at the end.
+        let _ = self.file.write_all(&[byte]);
+        out.push(byte);
+    }
+
+    fn finish(&mut self, _: &mut Vec<u8>) {
+        if let Err(e) = self.file.flush() {
+            eprintln!("rum: cannot write output to {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// Filters applied one after the other.
+#[derive(Default)]
+pub struct Pipeline {
+    filters: Vec<Box<dyn OutputFilter>>,
+    /// What the stage being run passes on, and what it was given.
+    scratch: (Vec<u8>, Vec<u8>),
+    /// Instructions executed when the last byte was pushed.
+    executed: u64,
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pipeline({} filters)", self.filters.len())
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `filter` at the end of the pipeline.
+    pub fn with(mut self, filter: impl OutputFilter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// The pipeline of `specs`, in order, timestamps read from `clock`.
+    pub fn build(specs: &[Spec], clock: Clock) -> std::io::Result<Self> {
+        let mut pipeline = Pipeline::new();
+        for spec in specs {
+            pipeline.filters.push(match spec {
+                Spec::StripAnsi => Box::new(StripAnsi::default()),
+                Spec::Uppercase => Box::new(Uppercase),
+                Spec::Timestamp => Box::new(Timestamp::new(clock)),
+                Spec::Tee(path) => Box::new(Tee::create(path).map_err(|e| std::io::Error::new(e.kind(), format!("{}: {e}", path.display())))?),
+            });
+        }
+        Ok(pipeline)
+    }
+
+    /// Runs `byte` through every filter, returning what comes out the end.
+    pub fn push(&mut self, byte: u8, executed: u64) -> &[u8] {
+        self.executed = executed;
+        let (out, given) = &mut self.scratch;
+        out.clear();
+        out.push(byte);
+        for filter in &mut self.filters {
+            std::mem::swap(out, given);
+            out.clear();
+            for &byte in given.iter() {
+                filter.filter(byte, executed, out);
+            }
+        }
+        out
+    }
+
+    /// Ends the run: lets every filter pass on what it held back, returning
+    /// what comes out the end.
+    pub fn finish(&mut self) -> Vec<u8> {
+        // What a filter releases still goes through the ones after it.
+        let mut carried = Vec::new();
+        for filter in &mut self.filters {
+            let mut out = Vec::new();
+            for &byte in &carried {
+                filter.filter(byte, self.executed, &mut out);
+            }
+            filter.finish(&mut out);
+            carried = out;
+        }
+        carried
+    }
+}
+
+/// A filter named on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Spec {
+    StripAnsi,
+    Uppercase,
+    Timestamp,
+    Tee(PathBuf),
+}
+
+impl Spec {
+    /// Parses `--output-filter`'s argument: filters separated by commas.
+    pub fn parse_list(text: &str) -> Result<Vec<Spec>, String> {
+        text.split(',').map(|name| Spec::parse(name.trim())).collect()
+    }
+
+    pub fn parse(name: &str) -> Result<Spec, String> {
+        match name {
+            "strip-ansi" => Ok(Spec::StripAnsi),
+            "uppercase" => Ok(Spec::Uppercase),
+            "timestamp" => Ok(Spec::Timestamp),
+            _ => match name.strip_prefix("tee:") {
+                Some(path) if !path.is_empty() => Ok(Spec::Tee(path.into())),
+                _ => Err(format!("unknown output filter `{name}` (have strip-ansi, uppercase, timestamp and tee:FILE)")),
+            },
+        }
+    }
+}
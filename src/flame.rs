@@ -0,0 +1,165 @@
+//! Call stacks of UM programs, guessed from their Load Program history, and
+//! folded-stack output for flamegraphs (`rum profile --folded`).
+//!
+//! The UM has no call instruction: compiled programs call by jumping with
+//! Load Program to a constant address and return by jumping to an address
+//! computed at run time, the word after the call. So the stack follows
+//! which registers hold Load Value constants, and classifies every jump
+//! within segment 0 that does not simply fall through:
+//!
+//! - a jump to a computed address that lands on the return address of a
+//!   frame on the stack is a return, and pops that frame and those above;
+//!   any word an indirect jump lands on becomes a known return site;
+//! - a jump to a constant address from a word followed by a known return
+//!   site is a call, and pushes a frame for its target;
+//! - anything else is a branch, and leaves the stack alone.
+//!
+//! Return sites are learned as the program runs, so the first call from
+//! each site is taken for a branch; over a long run that is noise. Frames
+//! are named after their entry address, `fn_4652`, under a root named
+//! `start`. Once Load Program replaces segment 0 the addresses mean
+//! something else, and every later sample is charged to one frame,
+//! `replaced`.
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+use crate::instruction::Instruction;
+use crate::state::UniversalMachine;
+
+/// Frames kept at most; deeper calls drop the outermost frames.
+pub const MAX_DEPTH: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Frame {
+    entry: usize,
+    ret: usize,
+}
+
+/// The guessed call stack of a running program.
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+    /// Registers holding a Load Value constant, one bit each.
+    constant: u8,
+    return_sites: HashSet<usize>,
+    replaced: bool,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Follows the instruction `inst` about to run from `pc` in `state`.
+    pub fn step(&mut self, pc: usize, inst: u32, state: &UniversalMachine) {
+        let bit = |r: u32| 1u8 << r;
+        match Instruction::decode(inst) {
+            Instruction::Orthography { a, .. } => self.constant |= bit(a),
+            Instruction::CMov { a, b, c } if state.register(c) != 0 => {
+                self.constant = (self.constant & !bit(a)) | ((self.constant >> b & 1) << a);
+            }
+            Instruction::CMov { .. } => {}
+            Instruction::Load { a, .. }
+            | Instruction::Add { a, .. }
+            | Instruction::Mul { a, .. }
+            | Instruction::Div { a, .. }
+            | Instruction::Nand { a, .. } => self.constant &= !bit(a),
+            Instruction::Map { b, .. } => self.constant &= !bit(b),
+            Instruction::Input { c } => self.constant &= !bit(c),
+            Instruction::LoadProgram { b, c } => match state.register(b) {
+                0 => self.jump(pc, state.register(c) as usize, self.constant & bit(c) == 0),
+                _ => self.replace(),
+            },
+            _ => {}
+        }
+    }
+
+    /// Notes a jump from `from` to `to` within segment 0, to a computed
+    /// address if `indirect`.
+    pub fn jump(&mut self, from: usize, to: usize, indirect: bool) {
+        if self.replaced || to == from + 1 {
+            return;
+        }
+        if indirect {
+            self.return_sites.insert(to);
+            if let Some(depth) = self.frames.iter().rposition(|frame| frame.ret == to) {
+                self.frames.truncate(depth);
+            }
+        } else if self.return_sites.contains(&(from + 1)) {
+            if self.frames.len() == MAX_DEPTH {
+                self.frames.remove(0);
+            }
+            self.frames.push(Frame { entry: to, ret: from + 1 });
+        }
+    }
+
+    /// Notes that Load Program replaced segment 0.
+    pub fn replace(&mut self) {
+        self.replaced = true;
+        self.frames.clear();
+    }
+
+    /// Entry addresses of the frames, outermost first (the root not included).
+    pub fn entries(&self) -> Vec<usize> {
+        self.frames.iter().map(|frame| frame.entry).collect()
+    }
+
+    /// The stack as folded-stack frame names, root first.
+    pub fn names(&self) -> Vec<String> {
+        match self.replaced {
+            true => vec!["replaced".to_string()],
+            false => std::iter::once("start".to_string()).chain(self.frames.iter().map(|frame| format!("fn_{}", frame.entry))).collect(),
+        }
+    }
+}
+
+/// Samples of the call stack taken every `every` instructions.
+#[derive(Debug, Clone)]
+pub struct Samples {
+    every: u64,
+    countdown: u64,
+    /// Entry addresses by stack, `None` for `replaced`.
+    stacks: BTreeMap<Option<Vec<usize>>, u64>,
+}
+
+impl Samples {
+    pub fn new(every: u64) -> Self {
+        let every = every.max(1);
+        Self { every, countdown: every, stacks: BTreeMap::new() }
+    }
+
+    /// Called once per instruction; samples `stack` every `every` calls.
+    #[inline]
+    pub fn tick(&mut self, stack: &CallStack) {
+        self.countdown -= 1;
+        if self.countdown == 0 {
+            self.countdown = self.every;
+            let key = (!stack.replaced).then(|| stack.entries());
+            *self.stacks.entry(key).or_default() += 1;
+        }
+    }
+
+    /// Samples taken.
+    pub fn total(&self) -> u64 {
+        self.stacks.values().sum()
+    }
+
+    /// Writes the samples in the folded format `inferno-flamegraph` and
+    /// `flamegraph.pl` read: `start;fn_4226;fn_4652 123` per line.
+    pub fn write_folded(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        for (stack, count) in &self.stacks {
+            match stack {
+                None => writeln!(out, "replaced {count}")?,
+                Some(entries) => {
+                    write!(out, "start")?;
+                    for entry in entries {
+                        write!(out, ";fn_{entry}")?;
+                    }
+                    writeln!(out, " {count}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+//! Floating-point extension opcodes (feature `extensions`, `rum run
+//! --extensions float`).
+//!
+//! Opcode 15, undefined in the UM, is the escape prefix; bits 24 to 27 pick
+//! the operation and the usual A, B and C fields (bits 6-8, 3-5 and 0-2)
+//! name registers. A float is an IEEE 754 double held in a register pair:
+//! an even register with the high word and the odd one after it with the
+//! low word, so `r2` names `r2:r3`.
+//!
+//! | Operation | Code | Effect                                        |
+//! |-----------|------|-----------------------------------------------|
+//! | `fadd`    | 0    | pair A = pair B + pair C                      |
+//! | `fsub`    | 1    | pair A = pair B - pair C                      |
+//! | `fmul`    | 2    | pair A = pair B * pair C                      |
+//! | `fdiv`    | 3    | pair A = pair B / pair C                      |
+//! | `itof`    | 4    | pair A = register B, unsigned, as a float     |
+//! | `ftoi`    | 5    | register A = pair B truncated, saturating at 0 and 2^32-1 (NaN is 0) |
+//!
+//! An odd register where a pair is expected, or another operation code,
+//! faults. The `Float` plugin gives a `plugin::Host` the operations;
+//! without it the opcode stays invalid, as the spec has them. It cannot
+//! be attached with `assertions`, which gives opcode 15 another meaning.
+
+use crate::plugin::{Extension, Plugin};
+
+/// The escape opcode.
+pub const OPCODE: u32 = 15;
+
+/// A float operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    FromInt,
+    ToInt,
+}
+
+impl Op {
+    pub const ALL: [Op; 6] = [Op::Add, Op::Sub, Op::Mul, Op::Div, Op::FromInt, Op::ToInt];
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Op::Add => "fadd",
+            Op::Sub => "fsub",
+            Op::Mul => "fmul",
+            Op::Div => "fdiv",
+            Op::FromInt => "itof",
+            Op::ToInt => "ftoi",
+        }
+    }
+}
+
+/// The word for `op` with register fields `a`, `b` and `c`.
+pub fn encode(op: Op, a: u32, b: u32, c: u32) -> u32 {
+    OPCODE << 28 | (op as u32) << 24 | (a & 7) << 6 | (b & 7) << 3 | (c & 7)
+}
+
+/// The operation and register fields of `word`, if it is a float instruction.
+pub fn decode(word: u32) -> Option<(Op, u32, u32, u32)> {
+    if word >> 28 != OPCODE {
+        return None;
+    }
+    let op = *Op::ALL.get((word >> 24 & 15) as usize)?;
+    Some((op, word >> 6 & 7, word >> 3 & 7, word & 7))
+}
+
+/// The float in the pair starting at `r`.
+pub fn pair(registers: &[u32; 8], r: u32) -> f64 {
+    f64::from_bits((registers[r as usize] as u64) << 32 | registers[r as usize + 1] as u64)
+}
+
+/// Puts `value` in the pair starting at `r`.
+pub fn set_pair(registers: &mut [u32; 8], r: u32, value: f64) {
+    let bits = value.to_bits();
+    registers[r as usize] = (bits >> 32) as u32;
+    registers[r as usize + 1] = bits as u32;
+}
+
+/// Carries out the float instruction `word` on `registers`: `None` when it
+/// is not one, `Some(Err)` when it faults.
+pub fn execute(word: u32, registers: &mut [u32; 8]) -> Option<Result<(), String>> {
+    if word >> 28 != OPCODE {
+        return None;
+    }
+    let Some((op, a, b, c)) = decode(word) else {
+        return Some(Err(format!("unknown float operation {}", word >> 24 & 15)));
+    };
+    let pairs: &[u32] = match op {
+        Op::FromInt => &[a],
+        Op::ToInt => &[b],
+        _ => &[a, b, c],
+    };
+    if let Some(r) = pairs.iter().find(|&&r| r % 2 == 1) {
+        return Some(Err(format!("{}: r{r} is odd, so it cannot name a register pair", op.mnemonic())));
+    }
+    match op {
+        Op::Add => set_pair(registers, a, pair(registers, b) + pair(registers, c)),
+        Op::Sub => set_pair(registers, a, pair(registers, b) - pair(registers, c)),
+        Op::Mul => set_pair(registers, a, pair(registers, b) * pair(registers, c)),
+        Op::Div => set_pair(registers, a, pair(registers, b) / pair(registers, c)),
+        Op::FromInt => set_pair(registers, a, registers[b as usize] as f64),
+        // `as` truncates and saturates, with NaN as 0.
+        Op::ToInt => registers[a as usize] = pair(registers, b) as u32,
+    }
+    Some(Ok(()))
+}
+
+/// The plugin carrying out float instructions.
+#[derive(Debug, Default)]
+pub struct Float;
+
+impl Plugin for Float {
+    fn name(&self) -> &str {
+        "float"
+    }
+
+    fn on_opcode(&mut self, instruction: u32, registers: &mut [u32; 8]) -> Extension {
+        match execute(instruction, registers) {
+            None => Extension::Unhandled,
+            Some(Ok(())) => Extension::Handled,
+            Some(Err(message)) => Extension::Fault(message),
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Harnesses for fuzzing the interpreter, exported with C linkage so a
+//! libFuzzer (or AFL++, honggfuzz) driver can link the library directly:
+//!
+//! ```c
+//! int LLVMFuzzerTestOneInput(const uint8_t *data, size_t size) {
+//!     return rum_fuzz_program(data, size);
+//! }
+//! ```
+//!
+//! Each run is bounded by `FUEL` instructions and `MEMORY_BYTES` of mapped
+//! memory. Program faults are the expected outcome of most inputs and come
+//! back from the machine as `Outcome::Faulted`; what is left to find is a
+//! crash, hang, abort or panic of rum itself, which reaches the fuzzer as
+//! it is.
+
+use std::ffi::c_int;
+use std::sync::Mutex;
+
+use crate::embed::{Machine, Status};
+
+/// Instructions a single input may execute.
+pub const FUEL: u64 = 100_000;
+/// Mapped memory a single input may use; a larger Map Segment ends the run.
+pub const MEMORY_BYTES: u64 = 64 << 20;
+
+/// How a harnessed run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Halted,
+    OutOfFuel,
+    OutOfMemory,
+    Faulted,
+}
+
+static PROGRAM: Mutex<Option<Vec<u32>>> = Mutex::new(None);
+
+/// Runs `program` with `input` as its whole input, within the harness bounds.
+pub fn run_bounded(program: Vec<u32>, input: &[u8]) -> Outcome {
+    #[cfg(feature = "edge-coverage")]
+    crate::edges::reset();
+    let mut machine = Machine::new(program);
+    let mut input = input.iter().copied();
+    while machine.executed() < FUEL {
+        if machine.mapping().is_some_and(|wanted| wanted > MEMORY_BYTES) {
+            return Outcome::OutOfMemory;
+        }
+        match machine.step(&mut || input.next(), &mut |_| {}) {
+            Ok(Status::Halted) => return Outcome::Halted,
+            Ok(Status::Running) => {}
+            Err(_) => return Outcome::Faulted,
+        }
+    }
+    Outcome::OutOfFuel
+}
+
+/// Runs `data` as a program (trailing bytes short of a word are ignored) with no input.
+pub fn fuzz_program(data: &[u8]) -> Outcome {
+    run_bounded(words(data), &[])
+}
+
+/// Runs the program set with `set_program` (or named by `RUM_FUZZ_PROGRAM`) with `data` as input.
+///
+/// Aborts the process when no program was set and `RUM_FUZZ_PROGRAM` is
+/// not a readable file: there is nothing to fuzz.
+pub fn fuzz_input(data: &[u8]) -> Outcome {
+    let program = PROGRAM
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| {
+            let path = std::env::var("RUM_FUZZ_PROGRAM").unwrap_or_else(|_| die("no program: call rum_fuzz_set_program or set RUM_FUZZ_PROGRAM"));
+            words(&std::fs::read(&path).unwrap_or_else(|e| die(&format!("{path}: {e}"))))
+        })
+        .clone();
+    run_bounded(program, data)
+}
+
+// Aborts rather than panicking, which would unwind into the C driver.
+fn die(message: &str) -> ! {
+    eprintln!("rum: {message}");
+    std::process::abort()
+}
+
+/// Sets the program `fuzz_input` runs.
+pub fn set_program(data: &[u8]) {
+    *PROGRAM.lock().unwrap() = Some(words(data));
+}
+
+fn words(data: &[u8]) -> Vec<u32> {
+    crate::load::words(data)
+}
+
+/// Bytes from a fuzzer, which may hand over a null pointer for an empty input.
+///
+/// # Safety
+///
+/// `data` must be null or point to `size` readable bytes.
+unsafe fn bytes<'a>(data: *const u8, size: usize) -> &'a [u8] {
+    match data.is_null() || size == 0 {
+        true => &[],
+        false => std::slice::from_raw_parts(data, size),
+    }
+}
+
+/// `LLVMFuzzerTestOneInput` body running `data` as a program. Always returns 0.
+///
+/// # Safety
+///
+/// `data` must be null or point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rum_fuzz_program(data: *const u8, size: usize) -> c_int {
+    fuzz_program(bytes(data, size));
+    0
+}
+
+/// `LLVMFuzzerTestOneInput` body feeding `data` to a fixed program. Always returns 0.
+///
+/// Without a program set by `rum_fuzz_set_program` the file named by
+/// `RUM_FUZZ_PROGRAM` is loaded on the first call; if there is neither the
+/// process aborts.
+///
+/// # Safety
+///
+/// `data` must be null or point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rum_fuzz_input(data: *const u8, size: usize) -> c_int {
+    fuzz_input(bytes(data, size));
+    0
+}
+
+/// Sets the program `rum_fuzz_input` runs, as the contents of a `.um` file;
+/// typically called from `LLVMFuzzerInitialize`.
+///
+/// # Safety
+///
+/// `data` must be null or point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rum_fuzz_set_program(data: *const u8, size: usize) {
+    set_program(bytes(data, size));
+}
+
+/// The edge coverage map of the last run (see `edges`), `edges::MAP_SIZE`
+/// bytes, for drivers that feed it to their fuzzer. The map is cleared at
+/// the start of each harnessed run.
+///
+/// # Safety
+///
+/// `size` must be null or point to a writable `size_t`.
+#[cfg(feature = "edge-coverage")]
+#[no_mangle]
+pub unsafe extern "C" fn rum_fuzz_edge_map(size: *mut usize) -> *const u8 {
+    if !size.is_null() {
+        *size = crate::edges::MAP_SIZE;
+    }
+    crate::edges::as_ptr()
+}
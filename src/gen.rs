@@ -0,0 +1,163 @@
+//! Random, structurally valid UM programs for fuzzing and differential
+//! testing.
+//!
+//! ```
+//! let config = rum::gen::Config::default();
+//! let program = rum::gen::generate(42, &config);
+//! assert_eq!(program, rum::gen::generate(42, &config));
+//! ```
+//!
+//! The same seed and `Config` always give the same program. Every program
+//! halts within `Config::fuel` instructions on any input and never faults:
+//!
+//! - It is a sequence of blocks of straight-line steps. Divisors are nonzero
+//!   constants, Loads and Stores stay inside segments the program mapped,
+//!   and each Map Segment is unmapped by the same step.
+//! - A block may loop a bounded number of times, counted down in r4.
+//! - After a block, the program may jump forward to the start of a later
+//!   block (or the final Halt) depending on a data register.
+//!
+//! Data lives in r0-r3, and anything may be read. r4 is the loop counter,
+//! r5 holds jump targets and mapped segments, r6 is a temporary and r7
+//! the identifier of a 64-word scratch segment mapped at the start.
+
+use crate::conformance::{add, cmov, div, halt, input, load, loadprog, map, mul, nand, orth, out, store, unmap};
+
+/// Shape of the generated programs.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Number of blocks.
+    pub blocks: usize,
+    /// Maximum steps per block.
+    pub steps: usize,
+    /// Maximum times a looping block runs.
+    pub iterations: u32,
+    /// Percent chance that a block loops.
+    pub loops: u32,
+    /// Percent chance of a conditional jump after a block.
+    pub jumps: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { blocks: 8, steps: 16, iterations: 8, loops: 30, jumps: 30 }
+    }
+}
+
+/// Words of the longest step (the Map/Store/Load/Unmap one).
+const LONGEST_STEP: u64 = 6;
+/// Words of a loop's counter setup and countdown-and-jump tail.
+const LOOP_WORDS: u64 = 1 + 8;
+/// Words of a conditional forward jump.
+const JUMP_WORDS: u64 = 5;
+/// Words of the scratch segment in r7.
+const SCRATCH_WORDS: u32 = 64;
+
+impl Config {
+    /// Instructions within which every program generated with this
+    /// configuration halts.
+    pub fn fuel(&self) -> u64 {
+        let body = self.steps as u64 * LONGEST_STEP + LOOP_WORDS;
+        2 + self.blocks as u64 * (body * self.iterations.max(1) as u64 + JUMP_WORDS) + 1
+    }
+}
+
+/// splitmix64: small, fast and stable across versions, so seeds stay reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u32 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (z ^ (z >> 31)) as u32
+    }
+
+    /// Uniform-enough in `0..n`; `n` must be nonzero.
+    fn below(&mut self, n: u32) -> u32 {
+        self.next() % n
+    }
+
+    fn percent(&mut self, chance: u32) -> bool {
+        self.below(100) < chance
+    }
+
+    /// A data register to write.
+    fn data(&mut self) -> u32 {
+        self.below(4)
+    }
+
+    /// Any register to read.
+    fn any(&mut self) -> u32 {
+        self.below(8)
+    }
+}
+
+/// The program for `seed` under `config`.
+pub fn generate(seed: u64, config: &Config) -> Vec<u32> {
+    let mut rng = Rng(seed);
+    let mut program = vec![orth(6, SCRATCH_WORDS), map(7, 6)];
+    let mut starts = Vec::new();
+    // (word to patch, block it jumps to); targets are only known once every block is emitted.
+    let mut jumps = Vec::new();
+    for block in 0..config.blocks {
+        starts.push(program.len() as u32);
+        let looped = config.iterations > 1 && rng.percent(config.loops);
+        if looped {
+            program.push(orth(4, 1 + rng.below(config.iterations)));
+        }
+        let start = program.len() as u32;
+        for _ in 0..rng.below(config.steps as u32 + 1) {
+            step(&mut rng, &mut program);
+        }
+        if looped {
+            // r4 -= 1; jump back to the start while r4 != 0.
+            let after = program.len() as u32 + 8;
+            program.extend([orth(6, 0), nand(6, 6, 6), add(4, 4, 6), orth(5, after), orth(6, start), cmov(5, 6, 4), orth(6, 0), loadprog(6, 5)]);
+        }
+        if rng.percent(config.jumps) {
+            let target = block + 1 + rng.below((config.blocks - block) as u32) as usize;
+            let after = program.len() as u32 + 5;
+            jumps.push((program.len() + 1, target));
+            program.extend([orth(5, after), orth(6, 0), cmov(5, 6, rng.data()), orth(6, 0), loadprog(6, 5)]);
+        }
+    }
+    starts.push(program.len() as u32);
+    program.push(halt());
+    for (at, target) in jumps {
+        program[at] = orth(6, starts[target]);
+    }
+    program
+}
+
+/// The program for `seed` under the default `Config`.
+pub fn program(seed: u64) -> Vec<u32> {
+    generate(seed, &Config::default())
+}
+
+/// Appends one fault-free straight-line step.
+fn step(rng: &mut Rng, program: &mut Vec<u32>) {
+    match rng.below(10) {
+        0 => program.push(orth(rng.data(), rng.below(1 << 25))),
+        1 => program.push(cmov(rng.data(), rng.any(), rng.any())),
+        2 => program.push(add(rng.data(), rng.any(), rng.any())),
+        3 => program.push(mul(rng.data(), rng.any(), rng.any())),
+        4 => program.push(nand(rng.data(), rng.any(), rng.any())),
+        5 => program.extend([orth(6, 1 + rng.below((1 << 25) - 1)), div(rng.data(), rng.any(), 6)]),
+        // The low byte of a register: r6 = x & 255.
+        6 => program.extend([orth(6, 255), nand(6, rng.any(), 6), nand(6, 6, 6), out(6)]),
+        7 => program.push(input(rng.data())),
+        8 => {
+            let offset = rng.below(SCRATCH_WORDS);
+            match rng.below(2) {
+                0 => program.extend([orth(6, offset), load(rng.data(), 7, 6)]),
+                _ => program.extend([orth(6, offset), store(7, 6, rng.any())]),
+            }
+        }
+        _ => {
+            let words = 1 + rng.below(16);
+            program.extend([orth(6, words), map(5, 6), orth(6, rng.below(words)), store(5, 6, rng.any()), load(rng.data(), 5, 6), unmap(5)]);
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! `rum serve-grpc`: the streaming execution service in `proto/rum.proto`.
+//!
+//! Each `Execute` call runs one program on a blocking worker thread. Input
+//! streamed by the client is queued for the program, output is streamed
+//! back in chunks, and the call ends with a single `Finished` message
+//! saying why the program stopped. The server's `Limits` apply to every
+//! session; a client may ask for tighter ones in `Start`, never looser.
+//! Sessions are tracked in the `metrics` registry under `grpc`.
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::Sender;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::cache::ProgramCache;
+use crate::embed::{self, Machine};
+use crate::metrics::{self, Registry};
+
+/// Generated from `proto/rum.proto`.
+pub mod proto {
+    tonic::include_proto!("rum");
+}
+
+use proto::executor_server::{Executor, ExecutorServer};
+use proto::{execute_request, execute_response, ExecuteRequest, ExecuteResponse, Finished, Reason, Start};
+
+/// Output is sent once this much has accumulated, or sooner when the
+/// program waits for input or finishes.
+const CHUNK: usize = 4096;
+/// Instructions between checks of the clock.
+const CHECK_EVERY: u64 = 1 << 14;
+
+/// Per-session resource limits; `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    pub max_instructions: Option<u64>,
+    pub max_memory_bytes: Option<u64>,
+    pub timeout: Option<Duration>,
+}
+
+impl Limits {
+    /// The tighter of these limits and the ones a client asked for (0 means none asked).
+    fn narrowed(&self, start: &Start) -> Limits {
+        fn min<T: Ord>(ours: Option<T>, theirs: Option<T>) -> Option<T> {
+            match (ours, theirs) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+        let asked = |n: u64| (n > 0).then_some(n);
+        Limits {
+            max_instructions: min(self.max_instructions, asked(start.max_instructions)),
+            max_memory_bytes: min(self.max_memory_bytes, asked(start.max_memory_bytes)),
+            timeout: min(self.timeout, asked(start.timeout_ms).map(Duration::from_millis)),
+        }
+    }
+}
+
+/// The `Executor` service.
+#[derive(Debug)]
+pub struct Service {
+    limits: Limits,
+}
+
+impl Service {
+    pub fn new(limits: Limits) -> Self {
+        Self { limits }
+    }
+}
+
+/// Input as the worker sees it: bytes, or the end of input.
+type Input = Option<Vec<u8>>;
+
+#[tonic::async_trait]
+impl Executor for Service {
+    type ExecuteStream = ReceiverStream<Result<ExecuteResponse, Status>>;
+
+    async fn execute(&self, request: Request<Streaming<ExecuteRequest>>) -> Result<Response<Self::ExecuteStream>, Status> {
+        let mut requests = request.into_inner();
+        let start = match requests.message().await? {
+            Some(ExecuteRequest { kind: Some(execute_request::Kind::Start(start)) }) => start,
+            _ => return Err(Status::invalid_argument("the first message must be `start`")),
+        };
+        let program = ProgramCache::global()
+            .decode(&start.program)
+            .map_err(|_| Status::invalid_argument("program length is not a whole number of words"))?
+            .to_vec();
+        let limits = self.limits.narrowed(&start);
+
+        let (input_tx, input_rx) = mpsc::channel::<Input>();
+        let (output_tx, output_rx) = tokio::sync::mpsc::channel(16);
+        tokio::spawn(async move {
+            // The client closing its side of the stream also ends the input.
+            while let Ok(Some(message)) = requests.message().await {
+                let input = match message.kind {
+                    Some(execute_request::Kind::Input(bytes)) => Some(bytes),
+                    Some(execute_request::Kind::CloseInput(true)) => None,
+                    _ => continue,
+                };
+                if input_tx.send(input).is_err() {
+                    break;
+                }
+            }
+        });
+        tokio::task::spawn_blocking(move || {
+            let mut session = Session::new(Machine::new(program), limits, input_rx, output_tx);
+            let finished = session.run();
+            session.finish(finished);
+        });
+        Ok(Response::new(ReceiverStream::new(output_rx)))
+    }
+}
+
+/// One program running on a worker thread.
+struct Session {
+    machine: Machine,
+    limits: Limits,
+    deadline: Option<Instant>,
+    input: mpsc::Receiver<Input>,
+    queue: VecDeque<u8>,
+    input_closed: bool,
+    output: Sender<Result<ExecuteResponse, Status>>,
+    pending: Vec<u8>,
+    peak_memory: u64,
+    cancelled: bool,
+    tracked: metrics::Session,
+}
+
+impl Session {
+    fn new(machine: Machine, limits: Limits, input: mpsc::Receiver<Input>, output: Sender<Result<ExecuteResponse, Status>>) -> Self {
+        let peak_memory = machine.state.mapped_bytes() as u64;
+        Self {
+            machine,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            limits,
+            input,
+            queue: VecDeque::new(),
+            input_closed: false,
+            output,
+            pending: Vec::new(),
+            peak_memory,
+            cancelled: false,
+            tracked: Registry::global().open("grpc"),
+        }
+    }
+
+    /// Executes until the program stops for any reason, program faults included.
+    fn run(&mut self) -> (Reason, String) {
+        loop {
+            if self.machine.status() == embed::Status::Halted {
+                return (Reason::Halted, String::new());
+            }
+            if self.cancelled {
+                return (Reason::Cancelled, String::new());
+            }
+            let executed = self.machine.executed();
+            if self.limits.max_instructions.is_some_and(|max| executed >= max) {
+                return (Reason::InstructionLimit, format!("stopped after {executed} instructions"));
+            }
+            if executed.is_multiple_of(CHECK_EVERY) {
+                self.tracked.update(executed, self.machine.state.mapped_bytes());
+                if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return (Reason::Timeout, format!("stopped after {executed} instructions"));
+                }
+            }
+            if let Some(stop) = self.check_map() {
+                return stop;
+            }
+            if self.queue.is_empty() && !self.input_closed && self.machine.wants_input() {
+                self.flush();
+                self.tracked.waiting(true);
+                let stop = self.wait_for_input();
+                self.tracked.waiting(false);
+                if let Some(stop) = stop {
+                    return stop;
+                }
+                continue;
+            }
+            let (queue, pending) = (&mut self.queue, &mut self.pending);
+            if let Err(e) = self.machine.step(&mut || queue.pop_front(), &mut |byte| pending.push(byte)) {
+                return (Reason::Fault, format!("program faulted after {} instructions: {e}", self.machine.executed()));
+            }
+            if self.pending.len() >= CHUNK {
+                self.flush();
+            }
+        }
+    }
+
+    /// Refuses a Map Segment that would take the machine past its memory limit.
+    fn check_map(&mut self) -> Option<(Reason, String)> {
+        let max = self.limits.max_memory_bytes?;
+        let wanted = self.machine.mapping()?;
+        if wanted > max {
+            return Some((Reason::MemoryLimit, format!("mapping would use {wanted} bytes, the limit is {max}")));
+        }
+        self.peak_memory = self.peak_memory.max(wanted);
+        None
+    }
+
+    fn wait_for_input(&mut self) -> Option<(Reason, String)> {
+        let received = match self.deadline {
+            Some(deadline) => self.input.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => self.input.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(Some(bytes)) => self.queue.extend(bytes),
+            Ok(None) | Err(RecvTimeoutError::Disconnected) => self.input_closed = true,
+            Err(RecvTimeoutError::Timeout) => return Some((Reason::Timeout, "timed out waiting for input".to_string())),
+        }
+        None
+    }
+
+    fn send(&mut self, kind: execute_response::Kind) {
+        if self.output.blocking_send(Ok(ExecuteResponse { kind: Some(kind) })).is_err() {
+            self.cancelled = true;
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.pending.is_empty() {
+            let chunk = std::mem::take(&mut self.pending);
+            self.send(execute_response::Kind::Output(chunk));
+        }
+    }
+
+    fn finish(&mut self, (reason, message): (Reason, String)) {
+        self.flush();
+        self.tracked.update(self.machine.executed(), self.machine.state.mapped_bytes());
+        let kind = match reason {
+            Reason::Halted => None,
+            Reason::InstructionLimit => Some("instruction_limit"),
+            Reason::MemoryLimit => Some("memory_limit"),
+            Reason::Timeout => Some("timeout"),
+            Reason::Fault => Some("fault"),
+            Reason::Cancelled => Some("cancelled"),
+        };
+        if let Some(kind) = kind {
+            self.tracked.error(kind);
+        }
+        let finished = Finished {
+            reason: reason.into(),
+            executed: self.machine.executed(),
+            peak_memory_bytes: self.peak_memory.max(self.machine.state.mapped_bytes() as u64),
+            message,
+        };
+        self.send(execute_response::Kind::Finished(finished));
+    }
+}
+
+/// Serves the `Executor` service on `addr` until the process is killed.
+pub fn serve(addr: SocketAddr, limits: Limits) -> Result<(), Box<dyn std::error::Error>> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    eprintln!("rum: serving gRPC on {addr}");
+    runtime.block_on(tonic::transport::Server::builder().add_service(ExecutorServer::new(Service::new(limits))).serve(addr))?;
+    Ok(())
+}
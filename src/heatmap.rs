@@ -0,0 +1,101 @@
+//! A self-contained HTML heatmap of a profile (`rum profile --html`).
+//!
+//! Segment 0 is drawn as a grid, one cell per word and 128 words to a row,
+//! coloured by how often the word executed on a log scale; hovering a cell
+//! shows its address, disassembly and count. The page needs nothing but a
+//! browser: the counts and disassembly are embedded in it as JSON, and a
+//! few lines of script draw them on a canvas.
+
+use std::fmt::Write;
+
+use crate::json::json_string;
+use crate::profile::Profile;
+use crate::rumdis::describe;
+
+/// Words per row of the grid.
+pub const ROW: usize = 128;
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>rum heatmap: TITLE</title>
+<style>
+body { font: 14px sans-serif; margin: 1em; }
+canvas { image-rendering: pixelated; border: 1px solid #ccc; }
+#tip { position: fixed; pointer-events: none; background: #fff; border: 1px solid #888; padding: 2px 6px; font-family: monospace; display: none; }
+.scale { display: inline-block; width: 200px; height: 12px; background: linear-gradient(to right, #eee, #ffd24d, #e8590c, #a00); vertical-align: middle; }
+</style>
+</head>
+<body>
+<h1>TITLE</h1>
+<p>SUMMARY</p>
+<p>never executed <span class="scale"></span> most executed (log scale); hover a word for its disassembly</p>
+<canvas id="map"></canvas>
+<div id="tip"></div>
+<script>
+const counts = COUNTS;
+const code = CODE;
+const row = ROW, cell = 6, total = TOTAL;
+const max = counts.reduce((a, b) => Math.max(a, b), 1);
+const canvas = document.getElementById("map"), tip = document.getElementById("tip");
+canvas.width = row * cell;
+canvas.height = Math.ceil(counts.length / row) * cell;
+const ctx = canvas.getContext("2d");
+const stops = [[238, 238, 238], [255, 210, 77], [232, 89, 12], [170, 0, 0]];
+function colour(n) {
+  if (n === 0) return "rgb(250,250,250)";
+  const t = Math.log(n + 1) / Math.log(max + 1) * (stops.length - 1);
+  const i = Math.min(Math.floor(t), stops.length - 2), f = t - i;
+  const c = stops[i].map((v, k) => Math.round(v + (stops[i + 1][k] - v) * f));
+  return "rgb(" + c.join(",") + ")";
+}
+counts.forEach((n, pc) => {
+  ctx.fillStyle = colour(n);
+  ctx.fillRect((pc % row) * cell, Math.floor(pc / row) * cell, cell, cell);
+});
+canvas.addEventListener("mousemove", e => {
+  const r = canvas.getBoundingClientRect();
+  const pc = Math.floor((e.clientY - r.top) / cell) * row + Math.floor((e.clientX - r.left) / cell);
+  if (pc < 0 || pc >= counts.length) { tip.style.display = "none"; return; }
+  const share = (counts[pc] * 100 / Math.max(1, total)).toFixed(2);
+  tip.textContent = pc + ": " + code[pc] + " — " + counts[pc] + " (" + share + "%)";
+  tip.style.left = e.clientX + 12 + "px";
+  tip.style.top = e.clientY + 12 + "px";
+  tip.style.display = "block";
+});
+canvas.addEventListener("mouseleave", () => tip.style.display = "none");
+</script>
+</body>
+</html>
+"#;
+
+/// The heatmap page for `profile`, headed `title` (e.g. the program's name).
+pub fn html(profile: &Profile, title: &str) -> String {
+    let len = profile.program_len();
+    let mut counts = String::from("[");
+    let mut code = String::from("[");
+    for pc in 0..len {
+        let separator = if pc > 0 { "," } else { "" };
+        let _ = write!(counts, "{separator}{}", profile.count(pc));
+        let _ = write!(code, "{separator}{}", json_string(&describe(profile.word(pc))));
+    }
+    counts.push(']');
+    code.push(']');
+    let unit = match profile.interval {
+        Some(_) => "samples",
+        None => "instructions",
+    };
+    let summary = format!("{} {unit}; {} of {len} words of segment 0 executed", profile.total(), profile.words_executed());
+    // The title goes in last, so nothing in it is taken for a placeholder.
+    PAGE.replace("SUMMARY", &summary)
+        .replace("COUNTS", &counts)
+        .replace("CODE", &code.replace("</", "<\\/"))
+        .replace("ROW", &ROW.to_string())
+        .replace("TOTAL", &profile.total().to_string())
+        .replace("TITLE", &escape(title))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,128 @@
+//! Executed-opcode counts (feature `opcode-histogram`).
+//!
+//! The interpreter bumps one counter per instruction at dispatch, so the
+//! feature costs an array increment and nothing when it is off. `rum run
+//! --histogram` prints the counts at exit, with the share of arithmetic,
+//! memory, control and I/O instructions, to tell what a workload is bound
+//! by; `--histogram-json FILE` writes them for other tools.
+
+use std::fmt::Write;
+
+use crate::json::Value;
+use crate::report::Table;
+
+pub use crate::rumdis::MNEMONICS;
+
+/// Which kind of work an opcode does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    /// Conditional Move, Add, Multiply, Divide, NAND and Load Value.
+    Arithmetic,
+    /// Segmented Load and Store, Map and Unmap.
+    Memory,
+    /// Halt and Load Program.
+    Control,
+    /// Output and Input.
+    Io,
+    Invalid,
+}
+
+impl Class {
+    pub const ALL: [Class; 5] = [Class::Arithmetic, Class::Memory, Class::Control, Class::Io, Class::Invalid];
+
+    pub fn of(opcode: usize) -> Class {
+        match opcode {
+            0 | 3..=6 | 13 => Class::Arithmetic,
+            1 | 2 | 8 | 9 => Class::Memory,
+            7 | 12 => Class::Control,
+            10 | 11 => Class::Io,
+            _ => Class::Invalid,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Class::Arithmetic => "arithmetic",
+            Class::Memory => "memory",
+            Class::Control => "control",
+            Class::Io => "io",
+            Class::Invalid => "invalid",
+        }
+    }
+}
+
+/// Instructions executed per opcode.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Histogram {
+    counts: [u64; 16],
+}
+
+impl Histogram {
+    /// Counts the instruction `inst` about to execute.
+    #[inline]
+    pub fn record(&mut self, inst: u32) {
+        self.counts[(inst >> 28) as usize] += 1;
+    }
+
+    /// Instructions executed with `opcode` (0 to 15).
+    pub fn count(&self, opcode: usize) -> u64 {
+        self.counts[opcode]
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Instructions executed of the `class`.
+    pub fn class(&self, class: Class) -> u64 {
+        (0..16).filter(|&opcode| Class::of(opcode) == class).map(|opcode| self.counts[opcode]).sum()
+    }
+
+    /// The counts as a table, most executed first, then the classes.
+    /// Opcodes that never ran are left out.
+    pub fn table(&self) -> String {
+        let total = self.total().max(1) as f64;
+        let mut opcodes: Vec<usize> = (0..16).filter(|&opcode| self.counts[opcode] > 0).collect();
+        opcodes.sort_by(|&a, &b| self.counts[b].cmp(&self.counts[a]).then(a.cmp(&b)));
+        let mut text = format!("{:<12} {:>14} {:>7}\n", "opcode", "count", "%");
+        for opcode in opcodes {
+            let count = self.counts[opcode];
+            let _ = writeln!(text, "{:<12} {count:>14} {:>6.2}%", MNEMONICS[opcode], count as f64 * 100.0 / total);
+        }
+        for class in Class::ALL {
+            let count = self.class(class);
+            if count > 0 || class != Class::Invalid {
+                let _ = writeln!(text, "{:<12} {count:>14} {:>6.2}%", format!("[{}]", class.name()), count as f64 * 100.0 / total);
+            }
+        }
+        text
+    }
+
+    /// `{"executed": N, "opcodes": {"cmov": N, ...}, "classes": {"arithmetic": N, ...}}`,
+    /// every opcode and class included.
+    pub fn to_json(&self) -> Value {
+        let opcodes = (0..16).map(|opcode| (MNEMONICS[opcode], Value::Number(self.counts[opcode] as f64)));
+        let classes = Class::ALL.map(|class| (class.name(), Value::Number(self.class(class) as f64)));
+        Value::object([
+            ("executed", Value::Number(self.total() as f64)),
+            ("opcodes", Value::object(opcodes)),
+            ("classes", Value::object(classes)),
+        ])
+    }
+
+    /// The counts as tables for `--report-format` (see `report`):
+    /// `opcodes` and `classes`, every one included, in numeric order.
+    pub fn tables(&self) -> Vec<Table> {
+        let total = self.total().max(1) as f64;
+        let mut opcodes = Table::new("opcodes", &["opcode", "mnemonic", "class", "count", "share"]);
+        for (opcode, &count) in self.counts.iter().enumerate() {
+            opcodes.row(vec![opcode.into(), MNEMONICS[opcode].into(), Class::of(opcode).name().into(), count.into(), (count as f64 / total).into()]);
+        }
+        let mut classes = Table::new("classes", &["class", "count", "share"]);
+        for class in Class::ALL {
+            let count = self.class(class);
+            classes.row(vec![class.name().into(), count.into(), (count as f64 / total).into()]);
+        }
+        vec![opcodes, classes]
+    }
+}
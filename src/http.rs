@@ -0,0 +1,248 @@
+//! `rum serve-http`: one-shot runs over HTTP for graders and online judges.
+//!
+//! ```text
+//! POST /run?input=north%0A&max_instructions=1000000&timeout_ms=500
+//! <program bytes>
+//! ```
+//!
+//! The body is a `.um` program; its input comes from the percent-encoded
+//! `input` query parameter and ends there. The response is JSON:
+//!
+//! ```text
+//! {"status":"halted","output":"...","executed":87,"elapsed_ms":0.2,"peak_memory_bytes":40,"message":""}
+//! ```
+//!
+//! `status` is `halted`, `instruction_limit`, `memory_limit`, `timeout` or
+//! `fault`; `output` holds one character U+0000-U+00FF per output byte.
+//! Every request runs under the server's `Limits`, which the query may only
+//! tighten. `GET /metrics` serves the Prometheus metrics of `metrics`.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::cache::ProgramCache;
+use crate::embed::{Machine, Status};
+use crate::json::{self, Value};
+use crate::metrics::{self, Registry, Session};
+
+/// Instructions between checks of the clock.
+const CHECK_EVERY: u64 = 1 << 14;
+/// Longest request head (request line and headers) accepted.
+const MAX_HEAD: usize = 16 * 1024;
+
+/// Limits applied to every request.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_instructions: Option<u64>,
+    pub max_memory_bytes: Option<u64>,
+    pub timeout: Option<Duration>,
+    /// Largest program accepted, in bytes.
+    pub max_program_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_instructions: None,
+            max_memory_bytes: Some(256 << 20),
+            timeout: Some(Duration::from_secs(10)),
+            max_program_bytes: 64 << 20,
+        }
+    }
+}
+
+/// What one run did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub status: &'static str,
+    pub output: Vec<u8>,
+    pub executed: u64,
+    pub elapsed: Duration,
+    pub peak_memory_bytes: u64,
+    pub message: String,
+}
+
+impl Report {
+    pub fn to_json(&self) -> Value {
+        Value::object([
+            ("status", self.status.into()),
+            ("output", json::bytes_to_string(&self.output).into()),
+            ("executed", self.executed.into()),
+            ("elapsed_ms", (self.elapsed.as_secs_f64() * 1000.0).into()),
+            ("peak_memory_bytes", self.peak_memory_bytes.into()),
+            ("message", self.message.clone().into()),
+        ])
+    }
+}
+
+/// Runs `program` to completion on `input` within `limits`.
+pub fn run_limited(program: Vec<u32>, input: &[u8], limits: &Limits) -> Report {
+    run_tracked(program, input, limits, &Session::detached())
+}
+
+/// `run_limited`, reporting progress and errors to `session`.
+fn run_tracked(program: Vec<u32>, input: &[u8], limits: &Limits, session: &Session) -> Report {
+    let started = Instant::now();
+    let mut machine = Machine::new(program);
+    let mut queue: VecDeque<u8> = input.iter().copied().collect();
+    let mut output = Vec::new();
+    let mut peak = machine.state.mapped_bytes() as u64;
+
+    let (status, message) = loop {
+        if machine.status() == Status::Halted {
+            break ("halted", String::new());
+        }
+        let executed = machine.executed();
+        if limits.max_instructions.is_some_and(|max| executed >= max) {
+            break ("instruction_limit", format!("stopped after {executed} instructions"));
+        }
+        if executed.is_multiple_of(CHECK_EVERY) {
+            session.update(executed, machine.state.mapped_bytes());
+            if limits.timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+                break ("timeout", format!("stopped after {executed} instructions"));
+            }
+        }
+        if let Some(wanted) = machine.mapping() {
+            if let Some(max) = limits.max_memory_bytes.filter(|&max| wanted > max) {
+                break ("memory_limit", format!("mapping would use {wanted} bytes, the limit is {max}"));
+            }
+            peak = peak.max(wanted);
+        }
+        if let Err(e) = machine.step(&mut || queue.pop_front(), &mut |byte| output.push(byte)) {
+            break ("fault", format!("program faulted after {} instructions: {e}", machine.executed()));
+        }
+    };
+    session.update(machine.executed(), machine.state.mapped_bytes());
+    if status != "halted" {
+        session.error(status);
+    }
+    Report {
+        status,
+        output,
+        executed: machine.executed(),
+        elapsed: started.elapsed(),
+        peak_memory_bytes: peak.max(machine.state.mapped_bytes() as u64),
+        message,
+    }
+}
+
+/// Accepts connections on `addr`, each handled on its own thread.
+pub fn serve(addr: impl ToSocketAddrs, limits: Limits) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("rum: serving HTTP on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        std::thread::spawn(move || {
+            if let Err(e) = handle(stream, &limits) {
+                eprintln!("rum: http: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Serves a single request on `stream`, then closes it.
+fn handle(stream: TcpStream, limits: &Limits) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match respond(&mut reader, limits) {
+        Ok(Reply::Run(report)) => metrics::response("200 OK", "application/json", &format!("{}\n", report.to_json())),
+        Ok(Reply::Metrics(text)) => metrics::response("200 OK", metrics::CONTENT_TYPE, &text),
+        Err((status, message)) => {
+            Registry::global().error("http", "bad_request");
+            metrics::response(status, "application/json", &format!("{}\n", Value::object([("error", message.into())])))
+        }
+    };
+    let mut stream = stream;
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+type Failure = (&'static str, String);
+
+enum Reply {
+    Run(Report),
+    Metrics(String),
+}
+
+fn respond(reader: &mut impl BufRead, limits: &Limits) -> Result<Reply, Failure> {
+    let bad = |message: &str| ("400 Bad Request", message.to_string());
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") && !head.ends_with(b"\n\n") {
+        let read = reader.read_until(b'\n', &mut head).map_err(|e| bad(&e.to_string()))?;
+        if read == 0 || head.len() > MAX_HEAD {
+            return Err(bad("incomplete or oversized request head"));
+        }
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.lines();
+    let mut request = lines.next().unwrap_or_default().split_whitespace();
+    let (method, target) = (request.next().unwrap_or_default(), request.next().unwrap_or_default());
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if (method, path) == ("GET", "/metrics") {
+        return Ok(Reply::Metrics(Registry::global().render()));
+    }
+    if path != "/run" {
+        return Err(("404 Not Found", format!("no route {path}")));
+    }
+    if method != "POST" {
+        return Err(("405 Method Not Allowed", "use POST /run".to_string()));
+    }
+    let length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .ok_or(("411 Length Required", "Content-Length is required".to_string()))?;
+    if length > limits.max_program_bytes {
+        return Err(("413 Payload Too Large", format!("programs are limited to {} bytes", limits.max_program_bytes)));
+    }
+    if !length.is_multiple_of(4) {
+        return Err(bad("program length is not a whole number of words"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|e| bad(&e.to_string()))?;
+    let program = ProgramCache::global().words(&body).to_vec();
+
+    let mut limits = *limits;
+    let mut input = Vec::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value).ok_or_else(|| bad(&format!("bad percent-encoding in `{key}`")))?;
+        let number = || String::from_utf8_lossy(&value).parse::<u64>().map_err(|_| bad(&format!("{key} needs a number")));
+        match key {
+            "input" => input = value.clone(),
+            "max_instructions" => limits.max_instructions = Some(tighter(limits.max_instructions, number()?)),
+            "max_memory_bytes" => limits.max_memory_bytes = Some(tighter(limits.max_memory_bytes, number()?)),
+            "timeout_ms" => {
+                let asked = Duration::from_millis(number()?);
+                limits.timeout = Some(limits.timeout.map_or(asked, |ours| ours.min(asked)));
+            }
+            _ => return Err(bad(&format!("unknown parameter `{key}`"))),
+        }
+    }
+    let session = Registry::global().open("http");
+    Ok(Reply::Run(run_tracked(program, &input, &limits, &session)))
+}
+
+fn tighter(ours: Option<u64>, asked: u64) -> u64 {
+    ours.map_or(asked, |ours| ours.min(asked))
+}
+
+/// Decodes `%XX` escapes and `+` (a space) in a query value.
+fn percent_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'%' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                out.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            b'+' => out.push(b' '),
+            b => out.push(b),
+        }
+    }
+    Some(out)
+}
@@ -0,0 +1,183 @@
+//! The `.rumimg` machine image: a program together with everything needed
+//! to run it the intended way, as one file to share. `rum pack` writes
+//! one, `rum run image.rumimg` runs it and `rum info` describes it.
+//!
+//! ```text
+//! magic       8 bytes   "RUMIMAGE"
+//! version     u16       format version of the payload (see `VERSION`)
+//! flags       u16       bit 0: payload is zstd-compressed
+//! length      u64       payload length in bytes
+//! checksum    32 bytes  SHA-256 of the payload as stored
+//! payload               sections: a one-byte tag, a u64 body length, the body
+//! ```
+//!
+//! Integers are big-endian, as in `.rumstate` snapshots, and the sections
+//! are:
+//!
+//! ```text
+//! 'P'  the program, big-endian words as in a .um file
+//! 'O'  `rum run` options, each followed by a NUL byte
+//! 'I'  input fed to the program before stdin (omitted when empty)
+//! 'S'  a complete .rumstate snapshot to resume instead of starting the
+//!      program (omitted when there is none)
+//! 'd'  a one-line description, UTF-8 (omitted when empty)
+//! ```
+//!
+//! The options are the ones that configure devices, extensions and limits,
+//! e.g. `--syscalls`, `--max-instructions 5000000000`, `--deny out`, and may
+//! end with `--` and the program's arguments. Options given on the command
+//! line go after them, so they can add to and override what the image says.
+//! As with snapshots, readers skip sections with a lowercase tag they do
+//! not know, and an unknown uppercase tag is an error.
+
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+pub const MAGIC: &[u8; 8] = b"RUMIMAGE";
+
+/// Payload version written by this crate.
+pub const VERSION: u16 = 1;
+
+/// Header flag: the payload is compressed.
+const FLAG_COMPRESSED: u16 = 1;
+
+const HEADER_LEN: usize = 8 + 2 + 2 + 8 + 32;
+
+/// A machine image.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+    pub program: Vec<u32>,
+    /// `rum run` options, one argument per entry.
+    pub options: Vec<String>,
+    /// Input the program reads before stdin.
+    pub input: Vec<u8>,
+    /// A `.rumstate` snapshot to resume, as its file's bytes.
+    pub snapshot: Option<Vec<u8>>,
+    pub description: String,
+}
+
+impl Image {
+    /// The image as a `.rumimg` file.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        section(&mut payload, b'P', &self.program.iter().flat_map(|word| word.to_be_bytes()).collect::<Vec<u8>>());
+        section(&mut payload, b'O', &self.options.iter().flat_map(|option| option.bytes().chain([0])).collect::<Vec<u8>>());
+        if !self.input.is_empty() {
+            section(&mut payload, b'I', &self.input);
+        }
+        if let Some(snapshot) = &self.snapshot {
+            section(&mut payload, b'S', snapshot);
+        }
+        if !self.description.is_empty() {
+            section(&mut payload, b'd', self.description.as_bytes());
+        }
+        let payload = zstd::encode_all(&payload[..], crate::snapshot::DEFAULT_LEVEL).expect("compressing in memory");
+        let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+        out.extend(MAGIC);
+        out.extend(VERSION.to_be_bytes());
+        out.extend(FLAG_COMPRESSED.to_be_bytes());
+        out.extend((payload.len() as u64).to_be_bytes());
+        out.extend(Sha256::digest(&payload));
+        out.extend(payload);
+        out
+    }
+
+    /// Reads an image back, verifying its checksum.
+    pub fn decode(bytes: &[u8]) -> Result<Image, String> {
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            return Err("not a rum machine image".to_string());
+        }
+        let version = u16::from_be_bytes([bytes[8], bytes[9]]);
+        if version != VERSION {
+            return Err(format!("unsupported image version {version}"));
+        }
+        let flags = u16::from_be_bytes([bytes[10], bytes[11]]);
+        if flags & !FLAG_COMPRESSED != 0 {
+            return Err(format!("unsupported image flags {flags:#x}"));
+        }
+        let length = u64::from_be_bytes(bytes[12..20].try_into().unwrap());
+        let stored = &bytes[HEADER_LEN..];
+        if stored.len() as u64 != length {
+            return Err("image is corrupt (truncated)".to_string());
+        }
+        if Sha256::digest(stored)[..] != bytes[20..HEADER_LEN] {
+            return Err("image is corrupt (checksum mismatch)".to_string());
+        }
+        let payload = match flags & FLAG_COMPRESSED {
+            0 => stored.to_vec(),
+            _ => zstd::decode_all(stored).map_err(|e| format!("image is corrupt ({e})"))?,
+        };
+
+        let mut image = Image::default();
+        let mut program = None;
+        let mut rest = &payload[..];
+        while let [tag, tail @ ..] = rest {
+            let length = tail.get(..8).ok_or("image is corrupt (section header)")?;
+            let length = u64::from_be_bytes(length.try_into().unwrap()) as usize;
+            let end = length.checked_add(8).filter(|&end| end <= tail.len()).ok_or("image is corrupt (section length)")?;
+            let body = &tail[8..end];
+            rest = &tail[end..];
+            match tag {
+                b'P' => {
+                    if !body.len().is_multiple_of(4) {
+                        return Err("image is corrupt (program is not whole words)".to_string());
+                    }
+                    program = Some(crate::load::words(body));
+                }
+                b'O' => {
+                    let text = std::str::from_utf8(body).map_err(|_| "image is corrupt (options are not UTF-8)")?;
+                    image.options = text.split_terminator('\0').map(str::to_string).collect();
+                }
+                b'I' => image.input = body.to_vec(),
+                b'S' => image.snapshot = Some(body.to_vec()),
+                b'd' => image.description = String::from_utf8_lossy(body).into_owned(),
+                tag if tag.is_ascii_lowercase() => {}
+                tag => return Err(format!("image has an unknown section `{}`", *tag as char)),
+            }
+        }
+        image.program = program.ok_or("image has no program")?;
+        Ok(image)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        std::fs::write(path, self.encode()).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Image, String> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Image::decode(&bytes).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// The `rum run` arguments for the image with `extra` given on the
+    /// command line: the image's options, then `extra`'s, then the program
+    /// arguments of both after a single `--`.
+    pub fn arguments(&self, extra: &[String]) -> Vec<String> {
+        let split = |args: &[String]| match args.iter().position(|arg| arg == "--") {
+            Some(at) => (args[..at].to_vec(), args[at + 1..].to_vec()),
+            None => (args.to_vec(), Vec::new()),
+        };
+        let (mut options, mut program_args) = split(&self.options);
+        let (extra_options, extra_program_args) = split(extra);
+        options.extend(extra_options);
+        program_args.extend(extra_program_args);
+        if !program_args.is_empty() {
+            options.push("--".to_string());
+            options.extend(program_args);
+        }
+        options
+    }
+}
+
+/// True if `path` names a machine image.
+pub fn is_image(path: impl AsRef<Path>) -> bool {
+    path.as_ref().extension().is_some_and(|extension| extension == "rumimg")
+}
+
+fn section(out: &mut Vec<u8>, tag: u8, body: &[u8]) {
+    out.push(tag);
+    out.extend((body.len() as u64).to_be_bytes());
+    out.extend(body);
+}
@@ -0,0 +1,228 @@
+//! `rum run --inspect`: letting `rum inspect` and `rum top` look at a run
+//! in progress from another terminal.
+//!
+//! The run listens on a Unix domain socket named after its process id in
+//! the temporary directory (see `socket`). Each connection gets one line
+//! of JSON describing the run as of its latest report, then is closed:
+//!
+//! ```text
+//! {"pid":4242,"program":"bin/sandmark.umz","pc":1807,"instructions":1302806528,"mips":61.2,
+//!  "mapped_bytes":188416,"segments":42,"waiting":false,"uptime_seconds":21.3,"output":"..."}
+//! ```
+//!
+//! The run loop reports every `PUBLISH_EVERY` instructions, and when the
+//! program blocks on input; `output` is the last `RECENT_OUTPUT` bytes
+//! written. A connection is answered by a thread of its own, so a slow
+//! client never holds up the program.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::json::{self, Value};
+use crate::state::UniversalMachine;
+
+/// Instructions the run loop executes between reports.
+pub const PUBLISH_EVERY: u64 = 1 << 16;
+
+/// Output bytes kept for `output`.
+pub const RECENT_OUTPUT: usize = 512;
+
+/// Shortest span the instruction rate is measured over.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct Shared {
+    program: String,
+    started: Instant,
+    pc: AtomicU64,
+    executed: AtomicU64,
+    mapped_bytes: AtomicU64,
+    segments: AtomicU64,
+    waiting: AtomicBool,
+    /// When the current rate window opened, the instruction count then, and
+    /// the rate over the previous window.
+    rate: Mutex<(Instant, u64, f64)>,
+    output: Mutex<VecDeque<u8>>,
+}
+
+/// The serving end of `--inspect`, fed by the run loop.
+#[derive(Debug, Clone)]
+pub struct Inspector {
+    path: PathBuf,
+    shared: Arc<Shared>,
+}
+
+impl Inspector {
+    /// Starts answering on this process's socket for a run of `program`.
+    #[cfg(unix)]
+    pub fn serve(program: &str) -> std::io::Result<Inspector> {
+        Self::serve_at(socket(std::process::id()), program)
+    }
+
+    /// Same as `serve`, on the socket at `path`.
+    #[cfg(unix)]
+    pub fn serve_at(path: impl Into<PathBuf>, program: &str) -> std::io::Result<Inspector> {
+        use std::io::Write;
+        use std::os::unix::fs::FileTypeExt;
+        use std::os::unix::net::UnixListener;
+
+        let path = path.into();
+        // A socket left behind by an earlier process with the same id would make bind fail.
+        if std::fs::symlink_metadata(&path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        let now = Instant::now();
+        let shared = Arc::new(Shared {
+            program: program.to_string(),
+            started: now,
+            pc: AtomicU64::new(0),
+            executed: AtomicU64::new(0),
+            mapped_bytes: AtomicU64::new(0),
+            segments: AtomicU64::new(0),
+            waiting: AtomicBool::new(false),
+            rate: Mutex::new((now, 0, 0.0)),
+            output: Mutex::new(VecDeque::with_capacity(RECENT_OUTPUT)),
+        });
+        let inspector = Inspector { path, shared };
+        let serving = inspector.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let line = format!("{}\n", serving.snapshot());
+                std::thread::spawn(move || stream.write_all(line.as_bytes()));
+            }
+        });
+        Ok(inspector)
+    }
+
+    #[cfg(not(unix))]
+    pub fn serve(_: &str) -> std::io::Result<Inspector> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "--inspect needs a Unix system"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reports the run having executed `executed` instructions.
+    pub fn publish(&self, executed: u64, state: &UniversalMachine) {
+        let shared = &*self.shared;
+        shared.pc.store(state.program_counter as u64, Ordering::Relaxed);
+        shared.executed.store(executed, Ordering::Relaxed);
+        shared.mapped_bytes.store(state.mapped_bytes() as u64, Ordering::Relaxed);
+        shared.segments.store((state.mapped_memory.len() - state.free_segments().len()) as u64, Ordering::Relaxed);
+        shared.waiting.store(false, Ordering::Relaxed);
+        let mut rate = shared.rate.lock().unwrap();
+        let since = rate.0.elapsed();
+        if since >= RATE_WINDOW {
+            *rate = (Instant::now(), executed, (executed - rate.1) as f64 / since.as_secs_f64() / 1e6);
+        }
+    }
+
+    /// Reports the program about to block reading input.
+    pub fn waiting(&self, executed: u64, state: &UniversalMachine) {
+        self.publish(executed, state);
+        self.shared.waiting.store(true, Ordering::Relaxed);
+    }
+
+    /// Records a byte of output.
+    pub fn output(&self, byte: u8) {
+        let mut output = self.shared.output.lock().unwrap();
+        if output.len() == RECENT_OUTPUT {
+            output.pop_front();
+        }
+        output.push_back(byte);
+    }
+
+    /// The run as last reported, as served to clients.
+    pub fn snapshot(&self) -> Value {
+        let shared = &*self.shared;
+        let waiting = shared.waiting.load(Ordering::Relaxed);
+        // Nothing runs while the program waits, whatever the last window measured.
+        let mips = if waiting { 0.0 } else { shared.rate.lock().unwrap().2 };
+        let output: Vec<u8> = shared.output.lock().unwrap().iter().copied().collect();
+        Value::object([
+            ("pid", std::process::id().into()),
+            ("program", shared.program.as_str().into()),
+            ("pc", shared.pc.load(Ordering::Relaxed).into()),
+            ("instructions", shared.executed.load(Ordering::Relaxed).into()),
+            ("mips", ((mips * 10.0).round() / 10.0).into()),
+            ("mapped_bytes", shared.mapped_bytes.load(Ordering::Relaxed).into()),
+            ("segments", shared.segments.load(Ordering::Relaxed).into()),
+            ("waiting", waiting.into()),
+            ("uptime_seconds", ((shared.started.elapsed().as_secs_f64() * 10.0).round() / 10.0).into()),
+            ("output", json::bytes_to_string(&output).into()),
+        ])
+    }
+
+    /// Stops answering: removes the socket, so the run no longer shows up.
+    pub fn close(&self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// The socket of the run with process id `pid`.
+pub fn socket(pid: u32) -> PathBuf {
+    std::env::temp_dir().join(format!("rum-inspect-{pid}.sock"))
+}
+
+/// The sockets of the inspectable runs on this machine by process id,
+/// including any left behind by runs that were killed.
+pub fn sockets() -> Vec<(u32, PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(std::env::temp_dir()) else { return Vec::new() };
+    let mut found: Vec<(u32, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let pid = name.to_str()?.strip_prefix("rum-inspect-")?.strip_suffix(".sock")?.parse().ok()?;
+            Some((pid, entry.path()))
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+/// Asks the run listening at `path` how it is doing.
+#[cfg(unix)]
+pub fn query(path: impl AsRef<Path>) -> Result<Value, String> {
+    use std::io::{BufRead, BufReader};
+
+    let stream = std::os::unix::net::UnixStream::connect(path).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(Duration::from_secs(5))).map_err(|e| e.to_string())?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).map_err(|e| e.to_string())?;
+    json::parse(&line)
+}
+
+#[cfg(not(unix))]
+pub fn query(_: impl AsRef<Path>) -> Result<Value, String> {
+    Err("inspecting runs needs a Unix system".to_string())
+}
+
+/// The `rum top` table of `runs`, as answered by `query`.
+pub fn table(runs: &[Value]) -> String {
+    let mut out = format!("{:>8}  {:>12}  {:>16}  {:>8}  {:>10}  {:>8}  {:<7}  {}\n", "PID", "PC", "INSTRUCTIONS", "MIPS", "MAPPED", "SEGMENTS", "STATE", "PROGRAM");
+    for run in runs {
+        let number = |key| run.get(key).and_then(Value::as_u64).unwrap_or(0);
+        let mips = match run.get("mips") {
+            Some(Value::Number(mips)) => *mips,
+            _ => 0.0,
+        };
+        let state = if run.get("waiting").and_then(Value::as_bool) == Some(true) { "input" } else { "running" };
+        out += &format!(
+            "{:>8}  {:>12}  {:>16}  {:>8.1}  {:>10}  {:>8}  {:<7}  {}\n",
+            number("pid"),
+            number("pc"),
+            number("instructions"),
+            mips,
+            number("mapped_bytes"),
+            number("segments"),
+            state,
+            run.get("program").and_then(Value::as_str).unwrap_or("")
+        );
+    }
+    out
+}
@@ -0,0 +1,305 @@
+//! Decoded instructions, and generators of instructions and well-formed
+//! programs for property tests (`proptest` feature) and fuzzers
+//! (`arbitrary` feature).
+//!
+//! A well-formed program (`well_formed`) is straight-line code ending in
+//! Halt that cannot fault: every divisor is a fresh nonzero constant,
+//! every Output a byte, every Load and Store in range of a scratch
+//! segment mapped up front, and every Map is followed by its Unmap. It
+//! always halts, after about as many instructions as it has words.
+
+use crate::encoding::{encode, encode_value, get, RA, RB, RC, RL, VL};
+use crate::rumdis::{op, Opcode};
+
+/// One UM instruction with its operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    CMov { a: u32, b: u32, c: u32 },
+    Load { a: u32, b: u32, c: u32 },
+    Store { a: u32, b: u32, c: u32 },
+    Add { a: u32, b: u32, c: u32 },
+    Mul { a: u32, b: u32, c: u32 },
+    Div { a: u32, b: u32, c: u32 },
+    Nand { a: u32, b: u32, c: u32 },
+    Halt,
+    Map { b: u32, c: u32 },
+    Unmap { c: u32 },
+    Output { c: u32 },
+    Input { c: u32 },
+    LoadProgram { b: u32, c: u32 },
+    Orthography { a: u32, value: u32 },
+    /// Opcode 14 or 15; the whole word.
+    Invalid(u32),
+}
+
+impl Instruction {
+    /// Decodes `word`. Bits the instruction doesn't use are ignored.
+    pub fn decode(word: u32) -> Instruction {
+        let (a, b, c) = (get(&RA, word), get(&RB, word), get(&RC, word));
+        match op(word) {
+            Some(Opcode::CMov) => Instruction::CMov { a, b, c },
+            Some(Opcode::SegLoad) => Instruction::Load { a, b, c },
+            Some(Opcode::SegStore) => Instruction::Store { a, b, c },
+            Some(Opcode::Add) => Instruction::Add { a, b, c },
+            Some(Opcode::Mul) => Instruction::Mul { a, b, c },
+            Some(Opcode::Div) => Instruction::Div { a, b, c },
+            Some(Opcode::BNand) => Instruction::Nand { a, b, c },
+            Some(Opcode::Halt) => Instruction::Halt,
+            Some(Opcode::MapSeg) => Instruction::Map { b, c },
+            Some(Opcode::UnmapSeg) => Instruction::Unmap { c },
+            Some(Opcode::Output) => Instruction::Output { c },
+            Some(Opcode::Input) => Instruction::Input { c },
+            Some(Opcode::LoadProg) => Instruction::LoadProgram { b, c },
+            Some(Opcode::LoadVal) => Instruction::Orthography { a: get(&RL, word), value: get(&VL, word) },
+            None => Instruction::Invalid(word),
+        }
+    }
+
+    /// The word for this instruction, with unused bits zero. Register
+    /// numbers are taken modulo 8 and values modulo 2^25.
+    pub fn encode(self) -> u32 {
+        match self {
+            Instruction::CMov { a, b, c } => encode(Opcode::CMov, a, b, c),
+            Instruction::Load { a, b, c } => encode(Opcode::SegLoad, a, b, c),
+            Instruction::Store { a, b, c } => encode(Opcode::SegStore, a, b, c),
+            Instruction::Add { a, b, c } => encode(Opcode::Add, a, b, c),
+            Instruction::Mul { a, b, c } => encode(Opcode::Mul, a, b, c),
+            Instruction::Div { a, b, c } => encode(Opcode::Div, a, b, c),
+            Instruction::Nand { a, b, c } => encode(Opcode::BNand, a, b, c),
+            Instruction::Halt => encode(Opcode::Halt, 0, 0, 0),
+            Instruction::Map { b, c } => encode(Opcode::MapSeg, 0, b, c),
+            Instruction::Unmap { c } => encode(Opcode::UnmapSeg, 0, 0, c),
+            Instruction::Output { c } => encode(Opcode::Output, 0, 0, c),
+            Instruction::Input { c } => encode(Opcode::Input, 0, 0, c),
+            Instruction::LoadProgram { b, c } => encode(Opcode::LoadProg, 0, b, c),
+            Instruction::Orthography { a, value } => encode_value(a, value),
+            Instruction::Invalid(word) => word,
+        }
+    }
+}
+
+impl std::str::FromStr for Instruction {
+    type Err = String;
+
+    /// Reads an instruction as `rumdis::describe` writes it, e.g.
+    /// `add r1, r2, r3`, `load r1, r2[r3]`, `loadval r4, 0x41` or
+    /// `.word 0xe0000000`.
+    fn from_str(text: &str) -> Result<Instruction, String> {
+        let spaced = text.replace([',', '[', ']'], " ");
+        let mut words = spaced.split_whitespace();
+        let mnemonic = words.next().ok_or("empty instruction")?;
+        let operands: Vec<&str> = words.collect();
+        let register = |i: usize| -> Result<u32, String> {
+            let operand = operands.get(i).ok_or_else(|| format!("`{mnemonic}` needs more operands"))?;
+            match operand.strip_prefix('r').and_then(|n| n.parse().ok()) {
+                Some(r) if r < 8 => Ok(r),
+                _ => Err(format!("`{operand}` is not a register r0-r7")),
+            }
+        };
+        let number = |i: usize| -> Result<u32, String> {
+            let operand = operands.get(i).ok_or_else(|| format!("`{mnemonic}` needs more operands"))?;
+            let value = match operand.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => operand.parse(),
+            };
+            value.map_err(|_| format!("`{operand}` is not a number"))
+        };
+        let count = match mnemonic {
+            "halt" => 0,
+            "unmap" | "out" | "in" | ".word" => 1,
+            "map" | "loadprog" | "loadval" => 2,
+            _ => 3,
+        };
+        if operands.len() > count {
+            return Err(format!("`{mnemonic}` takes {count} operands"));
+        }
+        Ok(match mnemonic {
+            "cmov" => Instruction::CMov { a: register(0)?, b: register(1)?, c: register(2)? },
+            "load" => Instruction::Load { a: register(0)?, b: register(1)?, c: register(2)? },
+            "store" => Instruction::Store { a: register(0)?, b: register(1)?, c: register(2)? },
+            "add" => Instruction::Add { a: register(0)?, b: register(1)?, c: register(2)? },
+            "mul" => Instruction::Mul { a: register(0)?, b: register(1)?, c: register(2)? },
+            "div" => Instruction::Div { a: register(0)?, b: register(1)?, c: register(2)? },
+            "nand" => Instruction::Nand { a: register(0)?, b: register(1)?, c: register(2)? },
+            "halt" => Instruction::Halt,
+            "map" => Instruction::Map { b: register(0)?, c: register(1)? },
+            "unmap" => Instruction::Unmap { c: register(0)? },
+            "out" => Instruction::Output { c: register(0)? },
+            "in" => Instruction::Input { c: register(0)? },
+            "loadprog" => Instruction::LoadProgram { b: register(0)?, c: register(1)? },
+            "loadval" => match number(1)? {
+                value if value < 1 << 25 => Instruction::Orthography { a: register(0)?, value },
+                value => return Err(format!("{value} does not fit in 25 bits")),
+            },
+            ".word" => Instruction::decode(number(0)?),
+            _ => return Err(format!("unknown instruction `{mnemonic}`")),
+        })
+    }
+}
+
+impl From<Instruction> for u32 {
+    fn from(instruction: Instruction) -> u32 {
+        instruction.encode()
+    }
+}
+
+/// Building well-formed programs out of `Step`s, shared by both generators.
+#[cfg(any(feature = "proptest", feature = "arbitrary"))]
+mod steps {
+    use super::Instruction;
+
+    /// Words of the scratch segment that Loads and Stores of a well-formed program use.
+    const SCRATCH_WORDS: u32 = 64;
+    /// Register holding the scratch segment's identifier; never written after the prologue.
+    const SCRATCH: u32 = 7;
+    /// Register for the constants steps load; the others (0-5) are the program's.
+    const TEMP: u32 = 6;
+
+    /// One fault-free step of a well-formed program. Operands are reduced
+    /// into range when the step is expanded, so any values will do.
+    #[derive(Debug, Clone, Copy)]
+    pub(super) enum Step {
+        Orthography { a: u32, value: u32 },
+        CMov { a: u32, b: u32, c: u32 },
+        Add { a: u32, b: u32, c: u32 },
+        Mul { a: u32, b: u32, c: u32 },
+        Nand { a: u32, b: u32, c: u32 },
+        Div { a: u32, b: u32, divisor: u32 },
+        Output { byte: u8 },
+        Input { c: u32 },
+        Load { a: u32, offset: u32 },
+        Store { offset: u32, c: u32 },
+        MapUnmap { words: u32 },
+    }
+
+    impl Step {
+        pub(super) fn expand(self, program: &mut Vec<Instruction>) {
+            let own = |r: u32| r % TEMP;
+            let any = |r: u32| r % 8;
+            let constant = |value: u32| Instruction::Orthography { a: TEMP, value };
+            match self {
+                Step::Orthography { a, value } => program.push(Instruction::Orthography { a: own(a), value: value & ((1 << 25) - 1) }),
+                Step::CMov { a, b, c } => program.push(Instruction::CMov { a: own(a), b: any(b), c: any(c) }),
+                Step::Add { a, b, c } => program.push(Instruction::Add { a: own(a), b: any(b), c: any(c) }),
+                Step::Mul { a, b, c } => program.push(Instruction::Mul { a: own(a), b: any(b), c: any(c) }),
+                Step::Nand { a, b, c } => program.push(Instruction::Nand { a: own(a), b: any(b), c: any(c) }),
+                Step::Div { a, b, divisor } => {
+                    program.extend([constant(divisor % ((1 << 25) - 1) + 1), Instruction::Div { a: own(a), b: any(b), c: TEMP }])
+                }
+                Step::Output { byte } => program.extend([constant(byte as u32), Instruction::Output { c: TEMP }]),
+                Step::Input { c } => program.push(Instruction::Input { c: own(c) }),
+                Step::Load { a, offset } => {
+                    program.extend([constant(offset % SCRATCH_WORDS), Instruction::Load { a: own(a), b: SCRATCH, c: TEMP }])
+                }
+                Step::Store { offset, c } => {
+                    program.extend([constant(offset % SCRATCH_WORDS), Instruction::Store { a: SCRATCH, b: TEMP, c: any(c) }])
+                }
+                Step::MapUnmap { words } => program.extend([
+                    constant(words % 1024),
+                    Instruction::Map { b: TEMP, c: TEMP },
+                    Instruction::Unmap { c: TEMP },
+                ]),
+            }
+        }
+    }
+
+    /// The well-formed program made of `steps`: a prologue mapping the scratch
+    /// segment, the steps, and Halt.
+    pub(super) fn assemble(steps: &[Step]) -> Vec<u32> {
+        let mut program = vec![
+            Instruction::Orthography { a: TEMP, value: SCRATCH_WORDS },
+            Instruction::Map { b: SCRATCH, c: TEMP },
+        ];
+        for step in steps {
+            step.expand(&mut program);
+        }
+        program.push(Instruction::Halt);
+        program.into_iter().map(Instruction::encode).collect()
+    }
+}
+
+#[cfg(feature = "proptest")]
+pub mod strategy {
+    //! proptest strategies: `instruction()` for any instruction and
+    //! `well_formed(steps)` for well-formed programs of up to `steps` steps.
+
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use super::steps::{assemble, Step};
+    use super::Instruction;
+
+    /// Any instruction, including the invalid opcodes 14 and 15.
+    pub fn instruction() -> impl Strategy<Value = Instruction> {
+        (0u32..16, 0u32..8, 0u32..8, 0u32..8, 0u32..(1 << 25), any::<u32>()).prop_map(|(code, a, b, c, value, word)| {
+            match code {
+                13 => Instruction::Orthography { a, value },
+                14 | 15 => Instruction::Invalid(code << 28 | word & ((1 << 28) - 1)),
+                _ => Instruction::decode(code << 28 | a << 6 | b << 3 | c),
+            }
+        })
+    }
+
+    fn step() -> impl Strategy<Value = Step> {
+        (0u32..11, any::<u32>(), any::<u32>(), any::<u32>()).prop_map(|(kind, x, y, z)| match kind {
+            0 => Step::Orthography { a: x, value: y },
+            1 => Step::CMov { a: x, b: y, c: z },
+            2 => Step::Add { a: x, b: y, c: z },
+            3 => Step::Mul { a: x, b: y, c: z },
+            4 => Step::Nand { a: x, b: y, c: z },
+            5 => Step::Div { a: x, b: y, divisor: z },
+            6 => Step::Output { byte: x as u8 },
+            7 => Step::Input { c: x },
+            8 => Step::Load { a: x, offset: y },
+            9 => Step::Store { offset: x, c: y },
+            _ => Step::MapUnmap { words: x },
+        })
+    }
+
+    /// A well-formed program (see the module docs) of up to `steps` steps.
+    pub fn well_formed(steps: usize) -> impl Strategy<Value = Vec<u32>> {
+        vec(step(), 0..=steps).prop_map(|steps| assemble(&steps))
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+mod fuzzing {
+    use arbitrary::{Arbitrary, Result, Unstructured};
+
+    use super::steps::{assemble, Step};
+    use super::Instruction;
+
+    impl<'a> Arbitrary<'a> for Instruction {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(Instruction::decode(u.arbitrary()?))
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for super::WellFormed {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            let mut steps = Vec::new();
+            while !u.is_empty() && steps.len() < 1024 {
+                let (x, y, z): (u32, u32, u32) = u.arbitrary()?;
+                steps.push(match u.int_in_range(0..=10)? {
+                    0 => Step::Orthography { a: x, value: y },
+                    1 => Step::CMov { a: x, b: y, c: z },
+                    2 => Step::Add { a: x, b: y, c: z },
+                    3 => Step::Mul { a: x, b: y, c: z },
+                    4 => Step::Nand { a: x, b: y, c: z },
+                    5 => Step::Div { a: x, b: y, divisor: z },
+                    6 => Step::Output { byte: x as u8 },
+                    7 => Step::Input { c: x },
+                    8 => Step::Load { a: x, offset: y },
+                    9 => Step::Store { offset: x, c: y },
+                    _ => Step::MapUnmap { words: x },
+                });
+            }
+            Ok(super::WellFormed(assemble(&steps)))
+        }
+    }
+}
+
+/// A well-formed program, as produced by `Arbitrary` for fuzz targets.
+#[cfg(feature = "arbitrary")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WellFormed(pub Vec<u32>);
@@ -0,0 +1,254 @@
+//! Host events delivered to UM programs (`rum run --interrupts`), so an
+//! interactive program can react to a timer or to input arriving without
+//! busy-polling Input.
+//!
+//! It is an opt-in extension. The host maps a control segment, identifier
+//! `SEGMENT` (1), before the program starts, so the program's own segments
+//! get 2 and up, and raises events (`TIMER`, `INPUT`) from any thread with a
+//! `Raiser`. At the next instruction boundary pending events are delivered
+//! in one of two ways:
+//!
+//! - `Delivery::Flag`: they are ORed into word `EVENTS` of the control
+//!   segment; the program polls it with Load and clears what it has
+//!   handled with Store.
+//! - `Delivery::Vector(handler)`: unless a handler is already running, the
+//!   machine saves the PC at `RESUME` and the registers at `REGISTERS`
+//!   onwards, sets `EVENTS` to the events delivered and continues at
+//!   `handler`. The handler returns by storing anything at word `RETURN`,
+//!   which restores the registers and PC from the segment (changed there,
+//!   they resume changed). Events raised meanwhile wait for the return.
+//!
+//! ```text
+//! word 0      EVENTS     event bits
+//! word 1      RESUME     the interrupted PC (vector)
+//! words 2-9   REGISTERS  r0 to r7 when interrupted (vector)
+//! word 10     RETURN     a Store here returns from the handler (vector)
+//! ```
+//!
+//! `timer` raises `TIMER` by the wall clock from a thread; `virtual_timer`
+//! raises it by the virtual clock (see `clock`), at the same instruction
+//! counts on every run.
+
+use std::io::Read;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use crate::clock::{Clock, NANOS_PER_INSTRUCTION};
+use crate::embed::{Machine, Status, Stepper};
+use crate::error::MachineError;
+use crate::instruction::Instruction;
+
+/// The control segment's identifier.
+pub const SEGMENT: u32 = 1;
+pub const EVENTS: usize = 0;
+pub const RESUME: usize = 1;
+pub const REGISTERS: usize = 2;
+pub const RETURN: usize = 10;
+/// Words in the control segment.
+pub const LEN: usize = 11;
+
+/// A timer expired.
+pub const TIMER: u32 = 1;
+/// Input arrived that Input can read without blocking.
+pub const INPUT: u32 = 2;
+
+/// How pending events reach the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Set bits in the `EVENTS` word.
+    Flag,
+    /// Call the handler at this address.
+    Vector(u32),
+}
+
+impl Delivery {
+    /// `flag`, or a handler address.
+    pub fn parse(text: &str) -> Result<Delivery, String> {
+        match text {
+            "flag" => Ok(Delivery::Flag),
+            _ => text.parse().map(Delivery::Vector).map_err(|_| format!("--interrupts takes `flag` or a handler address, not `{text}`")),
+        }
+    }
+}
+
+/// Raises events for a `Controller`, from any thread.
+#[derive(Debug, Clone)]
+pub struct Raiser(Weak<AtomicU32>);
+
+impl Raiser {
+    /// Makes `events` pending. Returns false once the controller is gone.
+    pub fn raise(&self, events: u32) -> bool {
+        match self.0.upgrade() {
+            Some(pending) => {
+                pending.fetch_or(events, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// An embedded machine that takes host events.
+pub struct Controller {
+    pub machine: Machine,
+    pending: Arc<AtomicU32>,
+    delivery: Delivery,
+    handling: bool,
+    /// The virtual timer's interval and when it next expires.
+    ticks: Option<(Duration, Duration)>,
+    /// Times the handler was called, or the flag word updated.
+    pub delivered: u64,
+}
+
+impl Controller {
+    /// Wraps `machine`, mapping the control segment and reserving it, so
+    /// the program cannot unmap it. Fails when the program has mapped
+    /// segments already.
+    pub fn new(mut machine: Machine, delivery: Delivery) -> Result<Self, String> {
+        if machine.state.mapped_memory.len() != SEGMENT as usize {
+            return Err(format!("the control segment must be the first one mapped, but {} segments are", machine.state.mapped_memory.len()));
+        }
+        machine.state.mapped_memory.push(vec![0; LEN]);
+        machine.state.reserve_segment(SEGMENT);
+        Ok(Self { machine, pending: Arc::new(AtomicU32::new(0)), delivery, handling: false, ticks: None, delivered: 0 })
+    }
+
+    pub fn raiser(&self) -> Raiser {
+        Raiser(Arc::downgrade(&self.pending))
+    }
+
+    /// Raises `TIMER` every `interval` of virtual time, checked at
+    /// instruction boundaries.
+    pub fn virtual_timer(&mut self, interval: Duration) {
+        let interval = interval.max(Duration::from_nanos(NANOS_PER_INSTRUCTION));
+        self.ticks = Some((interval, Clock::Virtual.elapsed(self.machine.executed()) + interval));
+    }
+
+    /// True while a vectored handler runs.
+    pub fn handling(&self) -> bool {
+        self.handling
+    }
+
+    /// The control segment; reserved, it stays mapped at its full length
+    /// unless the host changes it.
+    fn control(&mut self) -> Option<&mut [u32; LEN]> {
+        self.machine.state.segment_mut(SEGMENT as usize)?.get_mut(..LEN)?.try_into().ok()
+    }
+
+    /// Delivers pending events, at an instruction boundary.
+    fn deliver(&mut self) {
+        if let Some((interval, next)) = &mut self.ticks {
+            let now = Clock::Virtual.elapsed(self.machine.executed());
+            if now >= *next {
+                self.pending.fetch_or(TIMER, Ordering::Relaxed);
+                while *next <= now {
+                    *next += *interval;
+                }
+            }
+        }
+        if self.handling || self.pending.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let events = self.pending.swap(0, Ordering::Relaxed);
+        self.delivered += 1;
+        let state = &self.machine.state;
+        let (pc, registers): (u32, [u32; 8]) = (state.program_counter as u32, std::array::from_fn(|r| state.register(r as u32)));
+        let delivery = self.delivery;
+        let Some(control) = self.control() else {
+            return;
+        };
+        match delivery {
+            Delivery::Flag => control[EVENTS] |= events,
+            Delivery::Vector(handler) => {
+                control[EVENTS] = events;
+                control[RESUME] = pc;
+                control[REGISTERS..REGISTERS + 8].copy_from_slice(&registers);
+                self.machine.state.program_counter = handler as usize;
+                self.handling = true;
+            }
+        }
+    }
+
+    /// Executes one instruction like `Machine::step`, after delivering any
+    /// pending events.
+    pub fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        if self.machine.status() == Status::Halted {
+            return Ok(Status::Halted);
+        }
+        self.deliver();
+        let state = &self.machine.state;
+        let pc = state.program_counter;
+        let word = *state.mapped_memory.first().and_then(|program| program.get(pc)).ok_or(MachineError::ProgramCounterOutOfBounds { pc })?;
+        let returns = match Instruction::decode(word) {
+            Instruction::Store { a, b, .. } => self.handling && state.register(a) == SEGMENT && state.register(b) == RETURN as u32,
+            _ => false,
+        };
+        if !returns {
+            return self.machine.step(input, output);
+        }
+        self.machine.retire();
+        let Some(&mut control) = self.control() else {
+            return Ok(Status::Running);
+        };
+        for r in 0..8 {
+            self.machine.state.load_value(r as u32, control[REGISTERS + r]);
+        }
+        self.machine.state.program_counter = control[RESUME] as usize;
+        self.handling = false;
+        Ok(Status::Running)
+    }
+}
+
+impl Stepper for Controller {
+    fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        Controller::step(self, input, output)
+    }
+
+    fn status(&self) -> Status {
+        self.machine.status()
+    }
+
+    fn executed(&self) -> u64 {
+        self.machine.executed()
+    }
+
+    fn cancelled(&self) -> bool {
+        self.machine.cancelled()
+    }
+}
+
+/// Raises `TIMER` every `interval` from a background thread, until the
+/// controller is gone.
+pub fn timer(raiser: Raiser, interval: Duration) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval);
+        if !raiser.raise(TIMER) {
+            break;
+        }
+    });
+}
+
+/// Reads `source` on a background thread, raising `INPUT` whenever bytes
+/// arrive, and returns the input function that hands them to the program.
+/// It blocks only when nothing has arrived yet.
+pub fn input_events(mut source: impl Read + Send + 'static, raiser: Raiser) -> impl FnMut() -> Option<u8> {
+    let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+    std::thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        while let Ok(n @ 1..) = source.read(&mut buffer) {
+            if sender.send(buffer[..n].to_vec()).is_err() {
+                break;
+            }
+            raiser.raise(INPUT);
+        }
+    });
+    let mut chunk = Vec::new().into_iter();
+    move || {
+        chunk.next().or_else(|| {
+            chunk = receiver.recv().ok()?.into_iter();
+            chunk.next()
+        })
+    }
+}
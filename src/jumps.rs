@@ -0,0 +1,91 @@
+//! Dynamic jump-target profiles: how often each Load Program within
+//! segment 0 jumped from where to where (`rum profile --jumps`).
+//!
+//! The file is meant as profile-guided input for compiling UM programs
+//! ahead of time or just in time: hot edges tell block layout what to
+//! place together, and sites with a single dominant target which calls to
+//! inline. It is plain text, one edge per line, hottest first:
+//!
+//! ```text
+//! # rum jumps: Load Program edges within segment 0 (from to count)
+//! 4586 4580 1406870
+//! 10520 10521 194803
+//! ```
+//!
+//! Addresses are word offsets in the original segment 0. Edges stop being
+//! recorded once Load Program replaces segment 0 from another segment.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+/// The first line of a jump profile.
+pub const HEADER: &str = "# rum jumps: Load Program edges within segment 0 (from to count)";
+
+/// Jump counts by `(from, to)` edge.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JumpProfile {
+    edges: HashMap<(usize, usize), u64>,
+}
+
+impl JumpProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts one jump from `from` to `to`.
+    pub fn record(&mut self, from: usize, to: usize) {
+        *self.edges.entry((from, to)).or_default() += 1;
+    }
+
+    /// Times `from` jumped to `to`.
+    pub fn count(&self, from: usize, to: usize) -> u64 {
+        self.edges.get(&(from, to)).copied().unwrap_or(0)
+    }
+
+    /// `(from, to, count)` for every edge, hottest first, then by address.
+    pub fn edges(&self) -> Vec<(usize, usize, u64)> {
+        let mut edges: Vec<_> = self.edges.iter().map(|(&(from, to), &count)| (from, to, count)).collect();
+        edges.sort_by(|a, b| b.2.cmp(&a.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+        edges
+    }
+
+    /// Targets of the jump at `from` with their counts, most taken first.
+    pub fn targets(&self, from: usize) -> Vec<(usize, u64)> {
+        self.edges().into_iter().filter(|&(site, _, _)| site == from).map(|(_, to, count)| (to, count)).collect()
+    }
+
+    /// Writes the profile read back by `read`.
+    pub fn write(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(out, "{HEADER}")?;
+        for (from, to, count) in self.edges() {
+            writeln!(out, "{from} {to} {count}")?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()
+    }
+
+    /// Parses a profile written by `write`. Blank lines and `#` comments
+    /// are skipped, and repeated edges add up.
+    pub fn read(text: &str) -> Result<Self, String> {
+        let mut profile = Self::new();
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let fields: Vec<u64> = line.split_whitespace().map(str::parse).collect::<Result<_, _>>().map_err(|_| format!("malformed line `{line}`"))?;
+            let [from, to, count] = fields[..] else {
+                return Err(format!("malformed line `{line}`"));
+            };
+            *profile.edges.entry((from as usize, to as usize)).or_default() += count;
+        }
+        Ok(profile)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::read(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
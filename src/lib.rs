@@ -1,5 +1,188 @@
+#[macro_use]
+mod telemetry;
+
 pub mod load;
 
+pub mod cache;
+
+pub mod requires;
+
 pub mod state;
 
-pub mod rumdis;
\ No newline at end of file
+pub mod rumdis;
+
+pub mod error;
+
+pub mod encoding;
+
+pub mod instruction;
+
+pub mod gen;
+
+pub mod differential;
+
+pub mod bisect;
+
+pub mod mutate;
+
+pub mod minimize;
+
+pub mod watch;
+
+pub mod batch;
+
+pub mod bench;
+
+pub mod bench_fixtures;
+
+pub mod progress;
+
+pub mod clock;
+
+pub mod throttle;
+
+pub mod eof;
+
+pub mod policy;
+
+pub mod filters;
+
+pub mod cycles;
+
+pub mod barrier;
+
+pub mod summary;
+
+pub mod workdir;
+
+pub mod inspect;
+
+pub mod analyze;
+
+pub mod coverage;
+
+pub mod profile;
+
+pub mod flame;
+
+pub mod memprofile;
+
+pub mod jumps;
+
+pub mod symbols;
+
+pub mod routines;
+
+pub mod selfmod;
+
+pub mod heatmap;
+
+pub mod taint;
+
+pub mod chrometrace;
+
+pub mod events;
+
+pub mod compact;
+
+pub mod check;
+
+pub mod testing;
+
+pub mod oracle;
+
+pub mod assertions;
+
+pub mod conformance;
+
+pub mod snapshot;
+
+pub mod seal;
+
+pub mod image;
+
+pub mod checkpoint;
+
+pub mod debug;
+
+pub mod repl;
+
+pub mod explain;
+
+pub mod memimage;
+
+pub mod segimage;
+
+pub mod replay;
+
+pub mod statediff;
+
+pub mod embed;
+
+pub mod cancel;
+
+pub mod stream;
+
+pub mod ffi;
+
+pub mod fuzz;
+
+pub mod json;
+
+pub mod report;
+
+pub mod metrics;
+
+pub mod rpc;
+
+pub mod sessions;
+
+pub mod http;
+
+pub mod plugin;
+
+pub mod device;
+
+pub mod interrupt;
+
+pub mod tasks;
+
+pub mod syscall;
+
+pub mod um64;
+
+pub mod transport;
+
+pub mod bus;
+
+pub mod sandbox;
+
+#[cfg(feature = "edge-coverage")]
+pub mod edges;
+
+#[cfg(feature = "opcode-histogram")]
+pub mod histogram;
+
+#[cfg(feature = "extensions")]
+pub mod float;
+
+#[cfg(feature = "canary")]
+pub mod canary;
+
+#[cfg(feature = "script")]
+pub mod script;
+
+#[cfg(feature = "python")]
+pub mod python;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "node")]
+pub mod node;
+
+#[cfg(feature = "async")]
+pub mod aio;
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
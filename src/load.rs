@@ -1,19 +1,28 @@
 use std::convert::TryInto;
 
-pub fn load(input: Option<&str>) -> Vec<u32> {
+/// Reads the program at `input` (stdin without one) as big-endian words.
+pub fn load(input: Option<&str>) -> std::io::Result<Vec<u32>> {
     let mut raw_reader: Box<dyn std::io::BufRead> = match input {
         None => Box::new(std::io::BufReader::new(std::io::stdin())),
         Some(filename) => Box::new(std::io::BufReader::new(
-        std::fs::File::open(filename).unwrap(),
+        std::fs::File::open(filename)?,
         )),
     };
     let mut buf = Vec::<u8>::new();
-    raw_reader.read_to_end(&mut buf).unwrap();
-    let instructions: Vec<u32> = buf
-        .chunks_exact(4)
-        .map(|x| u32::from_be_bytes(x.try_into().unwrap()))
-        .collect();
-    instructions
+    raw_reader.read_to_end(&mut buf)?;
+    let instructions = words(&buf);
+    info!(path = input.unwrap_or("<stdin>"), words = instructions.len(), "program loaded");
+    Ok(instructions)
 }
 
+/// The big-endian words of `bytes`, a `.um` program; a trailing partial
+/// word is dropped.
+pub fn words(bytes: &[u8]) -> Vec<u32> {
+    bytes.chunks_exact(4).map(|word| u32::from_be_bytes(word.try_into().unwrap())).collect()
+}
 
+/// Writes `instructions` to `filename` as big-endian words, the format `load` reads.
+pub fn store(filename: &str, instructions: &[u32]) -> std::io::Result<()> {
+    let bytes: Vec<u8> = instructions.iter().flat_map(|word| word.to_be_bytes()).collect();
+    std::fs::write(filename, bytes)
+}
@@ -1,10 +1,1845 @@
 use std::env;
-use rum::{load, rumdis};
+use std::io::Write;
+use std::path::Path;
+use std::process::exit;
+use rum::{batch, bench, bus, check, embed, conformance, checkpoint, compact, coverage, debug, differential, gen, inspect, interrupt, load, memimage, segimage, http, metrics, mutate, profile, replay, report, requires, rpc, rumdis, sandbox, snapshot, statediff, taint, transport, watch};
+use rum::embed::Stepper;
+use rum::rumdis::RunOptions;
 use rum::state::UniversalMachine;
 
+const USAGE: &str = "usage: rum [run] [--watch] [--max-instructions N] [--max-mips N] [--cycles MODEL] [--progress] [--deterministic] [--summary json] [--hash-output sha256 [--tee]] [--stdin TEXT] [--eof VALUE] [--after-eof repeat|terminal|fail] [--deny RULE]... [--output-filter FILTER]... [--coverage FILE]
+                 [--chrome-trace FILE] [--event-log FILE] [--checkpoint-every N [--checkpoint-file BASE] [--checkpoint-keep K] [--checkpoint-delta] [--checkpoint-level L]]
+                 [--resume SNAPSHOT|latest] [--snapshot-key FILE] [--record LOG] [--workdir DIR] [--inspect] [--histogram [--report-format text|json|csv]] [--histogram-json FILE] [--plugin LIB]... [--assertions] [--extensions float] [--interrupts flag|HANDLER [--timer-ms N]] [--tasks [--spawn ADDR]...] [--syscalls [--arg ARG]...] [--arch um|um64] [--canary] [--io stdio|unix:PATH|nats://HOST:PORT/IN/OUT [--framing byte|line|length]] [--sandbox] [program.um | image.rumimg] [-- ARG...]
+       rum batch manifest.toml [--report report.json] [--jobs N]
+       rum bench [--runs N] [--workload midmark|sandmark|all]... [--report-format text|json|csv] [program.um]...
+       rum compact program.um -o out.um [--trace coverage.txt]...
+       rum conformance
+       rum differential [--reference naive|COMMAND] [--input FILE] [--max-instructions N] (program.um | --generate N)
+       rum bisect [--left rum|naive] [--right rum|naive] [--input FILE] [--every N] [--memory] [--max-instructions N] program.um
+       rum mutate program.um --cases DIR [--report-format text|json|csv]
+       rum minimize program.um --input FILE (--fault | --output-contains TEXT) [--max-instructions N] [-o OUT]
+       rum profile [--input FILE] [--max-instructions N] [--top N] [--report-format text|json|csv] [--memory] [--jumps FILE] [--self-modifying FILE] [--html FILE] [--folded FILE [--sample-every N]] [--sample-us N] [--symbols FILE] program.um
+       rum routines program.um [--jumps FILE] [-o OUT.sym]
+       rum taint [--input FILE] [--max-instructions N] [--report-format text|json|csv] program.um
+       rum info [--report-format text|json|csv] (program.um | image.rumimg)
+       rum pack program.um [--input FILE] [--snapshot SNAPSHOT] [--description TEXT] -o IMAGE.rumimg [-- RUN-OPTION...]
+       rum check program.um (--expected FILE | --sha256 HEX) [--input FILE] [--timeout SECS] [--max-instructions N]
+       rum debug [--stdin TEXT] [--script FILE] [--explain] (program.um | --resume SNAPSHOT)
+       rum repl [--stdin TEXT] [--load SNAPSHOT] [--explain]
+       rum dump-mem (SNAPSHOT | program.um) [--segment N] [--format bin|hex] -o DIR
+       rum render-mem (SNAPSHOT | program.um) [--segment N] [--width W] [--palette gray|heat] [--scale log|linear] -o OUT.png
+       rum import-mem seg-0.bin [seg-N.hex]... [--pc N] -o SNAPSHOT
+       rum replay LOG [--to N] [--save SNAPSHOT]
+       rum log query LOG [--kind KIND,...] [--after N] [--before N] [--larger-than BYTES] [--segment N] [--pc N] [--limit N] [--report-format text|json|csv]
+       rum statediff BEFORE.rumstate AFTER.rumstate
+       rum inspect [PID | SOCKET]
+       rum top [--interval SECS] [PID | SOCKET]...
+       rum serve-rpc [--listen ADDR [--max-sessions N] [--idle-timeout SECS] [--snapshot-dir DIR]] [--snapshot-key FILE] [--metrics ADDR]
+       rum serve-grpc [--listen ADDR] [--max-instructions N] [--max-memory BYTES] [--timeout SECS] [--metrics ADDR]
+       rum serve-http [--listen ADDR] [--max-instructions N] [--max-memory BYTES] [--timeout SECS] [--max-program BYTES]";
+
 fn main() {
-    let input = env::args().nth(1);
-    let instructions = load::load(input.as_deref());
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("run") => run(&args[1..]),
+        Some("batch") => batch(&args[1..]),
+        Some("bench") => bench(&args[1..]),
+        Some("compact") => compact(&args[1..]),
+        Some("check") => check(&args[1..]),
+        Some("info") => info(&args[1..]),
+        Some("pack") => pack(&args[1..]),
+        Some("conformance") => conformance(&args[1..]),
+        Some("differential") => differential(&args[1..]),
+        Some("bisect") => bisect(&args[1..]),
+        Some("mutate") => mutate(&args[1..]),
+        Some("minimize") => minimize(&args[1..]),
+        Some("profile") => profile(&args[1..]),
+        Some("routines") => routines(&args[1..]),
+        Some("taint") => taint(&args[1..]),
+        Some("debug") => debug(&args[1..]),
+        Some("repl") => repl(&args[1..]),
+        Some("dump-mem") => dump_mem(&args[1..]),
+        Some("render-mem") => render_mem(&args[1..]),
+        Some("import-mem") => import_mem(&args[1..]),
+        Some("replay") => replay(&args[1..]),
+        Some("log") => log(&args[1..]),
+        Some("statediff") => state_diff(&args[1..]),
+        Some("inspect") => inspect(&args[1..]),
+        Some("top") => top(&args[1..]),
+        Some("serve-rpc") => serve_rpc(&args[1..]),
+        Some("serve-grpc") => serve_grpc(&args[1..]),
+        Some("serve-http") => serve_http(&args[1..]),
+        _ => run(&args),
+    }
+}
+
+/// `rum [run] [options] [program.um | image.rumimg]`, see `USAGE` for the options.
+///
+/// Without a program the instructions are read from stdin. A machine image
+/// brings its own options, which the ones given here follow (see `image`).
+fn run(args: &[String]) {
+    let options_end = args.iter().position(|arg| arg == "--").unwrap_or(args.len());
+    match args[..options_end].iter().position(rum::image::is_image) {
+        Some(at) => {
+            let image = rum::image::Image::load(&args[at]).unwrap_or_else(|e| fail(&e));
+            let extra: Vec<String> = args[..at].iter().chain(&args[at + 1..]).cloned().collect();
+            run_program(&image.arguments(&extra), Some((&args[at], image)))
+        }
+        None => run_program(args, None),
+    }
+}
+
+/// `run` with its arguments, and the image they came from, if any.
+fn run_program(args: &[String], image: Option<(&str, rum::image::Image)>) {
+    let mut watch = false;
+    // The options `--watch` passes on to each run as given: all but itself and the program.
+    let mut watched = Vec::new();
+    let mut options = RunOptions { snapshot_key: env_snapshot_key(), ..RunOptions::default() };
+    let mut primed = Vec::new();
+    let mut resume = None;
+    let mut program = None;
+    let mut plugins = Vec::new();
+    let mut assertions = false;
+    let mut io = transport::Io::Stdio;
+    let mut framing = None;
+    let mut sandboxed = false;
+    let mut interrupts = None;
+    let mut timer = None;
+    let mut extensions = Vec::new();
+    let mut tasks = false;
+    let mut spawn = Vec::new();
+    let mut syscalls = false;
+    let mut program_args = Vec::new();
+    let mut um64 = false;
+    let mut canary = false;
+    let mut workdir = None;
+    let mut inspected = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let rest = args.as_slice();
+        match arg.as_str() {
+            "--watch" => {
+                watch = true;
+                continue;
+            }
+            "--plugin" => plugins.push(value(args.next(), arg)),
+            "--assertions" => assertions = true,
+            "--sandbox" => sandboxed = true,
+            "--interrupts" => interrupts = Some(interrupt::Delivery::parse(value(args.next(), arg)).unwrap_or_else(|e| usage(&e))),
+            "--timer-ms" => timer = Some(std::time::Duration::from_millis(number(args.next(), arg))),
+            "--extensions" => extensions.extend(value(args.next(), arg).split(',')),
+            "--tasks" => tasks = true,
+            "--spawn" => spawn.push(number(args.next(), arg)),
+            "--syscalls" => syscalls = true,
+            "--canary" => canary = true,
+            "--arg" => program_args.push(value(args.next(), arg).to_string()),
+            "--arch" => {
+                um64 = match value(args.next(), arg) {
+                    "um" => false,
+                    "um64" => true,
+                    other => usage(&format!("--arch takes um or um64, not `{other}`")),
+                }
+            }
+            "--io" => io = transport::Io::parse(value(args.next(), arg)).unwrap_or_else(|e| usage(&e)),
+            "--framing" => framing = Some(bus::Framing::parse(value(args.next(), arg)).unwrap_or_else(|e| usage(&e))),
+            "--stdin" => primed.extend(unescape(value(args.next(), arg)).unwrap_or_else(|e| usage(&e))),
+            "--max-instructions" => options.max_instructions = Some(number(args.next(), arg)),
+            "--progress" => options.progress = true,
+            "--max-mips" => options.max_mips = Some(number::<f64>(args.next(), arg)).filter(|&mips| mips > 0.0).or_else(|| usage("--max-mips needs a rate above 0")),
+            "--cycles" => options.cycles = Some(rum::cycles::CostModel::parse(value(args.next(), arg)).unwrap_or_else(|e| usage(&e))),
+            "--eof" => options.eof.value = rum::eof::parse_value(value(args.next(), arg)).unwrap_or_else(|e| usage(&e)),
+            "--after-eof" => options.eof.after = rum::eof::AfterEof::parse(value(args.next(), arg)).unwrap_or_else(|e| usage(&e)),
+            "--output-filter" => options.output_filters.extend(rum::filters::Spec::parse_list(value(args.next(), arg)).unwrap_or_else(|e| usage(&e))),
+            "--deny" => options.policy.get_or_insert_with(Default::default).deny(value(args.next(), arg)).unwrap_or_else(|e| usage(&e)),
+            "--coverage" => options.coverage = Some(value(args.next(), arg).into()),
+            "--chrome-trace" => options.chrome_trace = Some(value(args.next(), arg).into()),
+            "--event-log" => options.event_log = Some(value(args.next(), arg).into()),
+            "--checkpoint-every" => options.checkpoint_every = Some(number(args.next(), arg)),
+            "--checkpoint-file" => options.checkpoint_file = value(args.next(), arg).into(),
+            "--checkpoint-keep" => options.checkpoint_keep = number(args.next(), arg),
+            "--checkpoint-delta" => options.checkpoint_delta = true,
+            "--checkpoint-level" => options.checkpoint_level = Some(number(args.next(), arg)).filter(|&level| level != 0),
+            "--record" => options.record = Some(value(args.next(), arg).into()),
+            "--workdir" => workdir = Some(value(args.next(), arg)),
+            "--inspect" => inspected = true,
+            "--deterministic" => options.deterministic = true,
+            "--summary" => {
+                options.summary = match value(args.next(), arg) {
+                    "json" => true,
+                    other => usage(&format!("--summary takes json, not `{other}`")),
+                }
+            }
+            "--hash-output" => {
+                options.hash_output = match value(args.next(), arg) {
+                    "sha256" => true,
+                    other => usage(&format!("--hash-output takes sha256, not `{other}`")),
+                }
+            }
+            "--tee" => options.tee_output = true,
+            "--histogram" => options.histogram = true,
+            "--histogram-json" => options.histogram_json = Some(value(args.next(), arg).into()),
+            "--report-format" => options.report_format = report_format(args.next(), arg),
+            "--resume" => resume = Some(value(args.next(), arg).to_string()),
+            "--snapshot-key" => options.snapshot_key = Some(snapshot_key(value(args.next(), arg))),
+            // Everything after `--` is for the program, as if given with --arg.
+            "--" => program_args.extend(args.by_ref().cloned()),
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            path => {
+                program = Some(path);
+                continue;
+            }
+        }
+        watched.push(arg.clone());
+        watched.extend(rest[..rest.len() - args.as_slice().len()].iter().cloned());
+    }
+
+    let (image_path, image) = image.unzip();
+    if let Some(image) = &image {
+        if let Some(path) = program {
+            usage(&format!("a machine image carries its program; `{path}` is not an option"));
+        }
+        if image.snapshot.is_some() && resume.is_some() {
+            usage("the machine image resumes its own snapshot; drop --resume");
+        }
+        if um64 || matches!(io, transport::Io::Nats(_)) {
+            usage("a machine image runs on the 32-bit UM, without --watch or --io nats://");
+        }
+        primed.splice(0..0, image.input.iter().copied());
+    }
+    let image_snapshot = image.as_ref().and_then(|image| image.snapshot.clone());
+    let resuming = resume.is_some() || image_snapshot.is_some();
+    if (options.histogram || options.histogram_json.is_some()) && !cfg!(feature = "opcode-histogram") {
+        fail("--histogram needs rum built with the opcode-histogram feature");
+    }
+    // Options of `rumdis::run_with`, which the other kinds of run don't go through.
+    let plain_run = !(!plugins.is_empty() || assertions || !extensions.is_empty() || interrupts.is_some() || tasks || syscalls || um64 || canary || matches!(io, transport::Io::Nats(_)));
+    let plain_only = [
+        (options.summary, "--summary"),
+        (options.max_mips.is_some(), "--max-mips"),
+        (options.cycles.is_some(), "--cycles"),
+        (options.eof.value != rum::eof::Eof::default().value, "--eof"),
+        (options.eof.after != rum::eof::Eof::default().after, "--after-eof"),
+        (options.policy.is_some(), "--deny"),
+        (!options.output_filters.is_empty(), "--output-filter"),
+        (options.event_log.is_some(), "--event-log"),
+        (workdir.is_some(), "--workdir"),
+        (inspected, "--inspect"),
+        (options.hash_output, "--hash-output"),
+    ];
+    if let Some((_, option)) = plain_only.iter().find(|&&(given, _)| given && !plain_run) {
+        usage(&format!("{option} only applies to plain runs, without --plugin, --assertions, --extensions, --interrupts, --tasks, --syscalls, --arch um64, --canary or --io nats://"));
+    }
+    if inspected && sandboxed {
+        usage("--inspect and --sandbox don't combine: the sandbox shuts out inspecting clients");
+    }
+    if options.tee_output && !options.hash_output {
+        usage("--tee needs --hash-output");
+    }
+    if watch && io != transport::Io::Stdio {
+        usage("--watch needs the terminal for I/O");
+    }
+    let teed = options.output_filters.iter().any(|filter| matches!(filter, rum::filters::Spec::Tee(_)));
+    if sandboxed && (!plugins.is_empty() || options.coverage.is_some() || options.chrome_trace.is_some() || options.checkpoint_every.is_some() || options.record.is_some() || options.histogram_json.is_some() || options.event_log.is_some() || teed) {
+        usage("--sandbox cannot write files or load plugins; drop --plugin, --coverage, --chrome-trace, --event-log, --checkpoint-every, --record, --histogram-json and --output-filter tee:");
+    }
+    if let Some(path) = program.filter(|_| resume.is_none()) {
+        let mut configured: Vec<requires::Extension> = plugins.iter().map(requires::Extension::plugin).collect();
+        let flags = [
+            (syscalls, requires::Extension::Syscalls),
+            (extensions.contains(&"float"), requires::Extension::Float),
+            (assertions, requires::Extension::Assertions),
+            (interrupts.is_some(), requires::Extension::Interrupts),
+            (tasks, requires::Extension::Tasks),
+            (um64, requires::Extension::Um64),
+        ];
+        configured.extend(flags.into_iter().filter_map(|(on, extension)| on.then_some(extension)));
+        requires::check(path, &configured).unwrap_or_else(|e| fail(&e));
+    }
+    if timer.is_some() && interrupts.is_none() {
+        usage("--timer-ms needs --interrupts");
+    }
+    if !spawn.is_empty() && !tasks {
+        usage("--spawn needs --tasks");
+    }
+    if !program_args.is_empty() && !syscalls {
+        usage("--arg and arguments after -- need --syscalls");
+    }
+    // Each run is a child `rum run` given the same options, which checks how they combine.
+    if watch {
+        if resume.is_some() {
+            usage("--watch starts the program afresh on every change; drop --resume");
+        }
+        let Some(path) = program else {
+            usage("--watch needs a program file");
+        };
+        let Err(e) = watch::watch(path, &watched);
+        fail(&e);
+    }
+    if let Some(base) = workdir {
+        if sandboxed || options.record.is_some() || options.checkpoint_file != RunOptions::default().checkpoint_file {
+            usage("--workdir keeps the replay log and checkpoints itself; drop --record, --checkpoint-file and --sandbox");
+        }
+        let command: Vec<String> = env::args().skip(1).collect();
+        let dir = rum::workdir::Workdir::create(base, &command).unwrap_or_else(|e| fail(&format!("{base}: {e}")));
+        options.record = Some(dir.record());
+        options.checkpoint_file = dir.checkpoints();
+        options.workdir = Some(dir);
+    }
+    if inspected {
+        let name = resume.as_deref().or(program).or(image_path).unwrap_or("stdin");
+        let inspector = inspect::Inspector::serve(name).unwrap_or_else(|e| fail(&format!("--inspect: {e}")));
+        eprintln!("rum: inspect this run with `rum inspect {}` or `rum top {0}`", std::process::id());
+        options.inspect = Some(inspector);
+    }
+    if let transport::Io::Nats(endpoint) = &io {
+        if sandboxed || resuming || !plugins.is_empty() || !primed.is_empty() || options.progress
+            || options.coverage.is_some() || options.chrome_trace.is_some() || options.checkpoint_every.is_some() || options.record.is_some()
+        {
+            usage("--io nats:// only combines with --max-instructions and --framing");
+        }
+        return run_bus(load_program(program), endpoint, framing.unwrap_or(bus::Framing::Line), options.max_instructions);
+    }
+    if framing.is_some() {
+        usage("--framing needs --io nats://...");
+    }
+    if um64 {
+        if sandboxed || resuming || !plugins.is_empty() || assertions || !extensions.is_empty() || interrupts.is_some() || tasks || syscalls || io != transport::Io::Stdio
+            || options.coverage.is_some() || options.chrome_trace.is_some() || options.checkpoint_every.is_some() || options.record.is_some() || options.histogram
+        {
+            usage("--arch um64 only combines with --stdin and --max-instructions");
+        }
+        return run_um64(program, primed, options.max_instructions);
+    }
+    // Read the program first: without a file it comes from stdin.
+    let instructions = (!resuming).then(|| match &image {
+        Some(image) => image.program.clone(),
+        None => load_program(program),
+    });
+    io.attach().unwrap_or_else(|e| fail(&format!("--io: {e}")));
+    #[cfg(all(feature = "edge-coverage", unix))]
+    if let Err(e) = rum::edges::attach_afl() {
+        fail(&format!("edge coverage: {e}"));
+    }
+
+    if tasks {
+        if sandboxed || resuming || !plugins.is_empty() || assertions || !extensions.is_empty() || interrupts.is_some() || syscalls || options.coverage.is_some() || options.chrome_trace.is_some() || options.checkpoint_every.is_some() || options.record.is_some() {
+            usage("--tasks only combines with --spawn, --stdin and --max-instructions");
+        }
+        return run_tasks(instructions.unwrap(), &spawn, primed, options.max_instructions);
+    }
+    if let Some(delivery) = interrupts {
+        if sandboxed || resuming || !plugins.is_empty() || assertions || !extensions.is_empty() || syscalls || options.coverage.is_some() || options.chrome_trace.is_some() || options.checkpoint_every.is_some() || options.record.is_some() {
+            usage("--interrupts only combines with --timer-ms, --stdin and --max-instructions");
+        }
+        return run_interrupts(instructions.unwrap(), delivery, timer, primed, &options);
+    }
+    if syscalls {
+        if sandboxed || resuming || !plugins.is_empty() || assertions || !extensions.is_empty() || options.coverage.is_some() || options.chrome_trace.is_some() || options.checkpoint_every.is_some() || options.record.is_some() {
+            usage("--syscalls only combines with --arg, --stdin and --max-instructions");
+        }
+        return run_syscalls(instructions.unwrap(), program_args, primed, &options);
+    }
+    if canary {
+        if sandboxed || resuming || !plugins.is_empty() || assertions || !extensions.is_empty() || options.coverage.is_some() || options.chrome_trace.is_some() || options.checkpoint_every.is_some() || options.record.is_some() {
+            usage("--canary only combines with --stdin and --max-instructions");
+        }
+        return run_canary(instructions.unwrap(), primed, options.max_instructions);
+    }
+
+    if !plugins.is_empty() || assertions || !extensions.is_empty() {
+        if sandboxed || resuming || options.coverage.is_some() || options.chrome_trace.is_some() || options.checkpoint_every.is_some() || options.record.is_some() {
+            usage("--plugin, --assertions and --extensions only combine with --stdin and --max-instructions");
+        }
+        let mut builtins: Vec<Box<dyn rum::plugin::Plugin>> = Vec::new();
+        if assertions {
+            builtins.push(Box::new(rum::assertions::Assertions::default()));
+        }
+        for name in extensions {
+            match name {
+                "float" if assertions => usage("--extensions float and --assertions both use opcode 15"),
+                "float" => builtins.push(float_extension()),
+                _ => usage(&format!("unknown extension `{name}` (have float)")),
+            }
+        }
+        return run_plugins(instructions.unwrap(), builtins, &plugins, primed, options.max_instructions);
+    }
+
+    if let Some(bytes) = image_snapshot {
+        let mut state = snapshot::decode(&bytes, options.snapshot_key.as_ref()).unwrap_or_else(|e| fail(&format!("{}: {e}", image_path.unwrap_or_default())));
+        state.prime_input(&primed);
+        if sandboxed {
+            enter_sandbox();
+        }
+        return finish_run(rumdis::resume_with(&mut state, &options));
+    }
+    if let Some(path) = resume {
+        // `--resume latest` picks the newest checkpoint (or delta chain) in the ring.
+        let chain = match path.as_str() {
+            "latest" if options.checkpoint_delta => checkpoint::latest_chain(&options.checkpoint_file, options.checkpoint_keep, options.snapshot_key.as_ref()),
+            "latest" => checkpoint::latest(&options.checkpoint_file, options.checkpoint_keep).into_iter().collect(),
+            _ => vec![path.into()],
+        };
+        if chain.is_empty() {
+            fail("no checkpoint to resume from");
+        }
+        let mut state = snapshot::load_chain(&chain, options.snapshot_key.as_ref()).unwrap_or_else(|e| fail(&format!("{}: {e}", chain[0].display())));
+        state.prime_input(&primed);
+        if sandboxed {
+            enter_sandbox();
+        }
+        return finish_run(rumdis::resume_with(&mut state, &options));
+    }
+
+    let mut state = UniversalMachine::new();
+    state.prime_input(&primed);
+    if sandboxed {
+        enter_sandbox();
+    }
+    finish_run(rumdis::run_with(&mut state, instructions.unwrap(), &options))
+}
+
+/// Exits with rum's status for a run stopped by its instruction limit; a
+/// run that halted just returns.
+fn finish_run(result: Result<rumdis::RunResult, rumdis::MachineError>) {
+    match result {
+        Ok(rumdis::RunResult::Halted) => {}
+        Ok(result) => exit(result.exit_code()),
+        Err(rumdis::MachineError::Io { message, .. }) => fail(&message),
+        Err(e) => fault(&e),
+    }
+}
+
+/// The status a host run (`--plugin`, `--tasks`, ...) stopped with; a fault
+/// is reported and exits as in `finish_run`.
+fn host_status(result: Result<rum::embed::Status, rumdis::MachineError>) -> rum::embed::Status {
+    result.unwrap_or_else(|e| fault(&e))
+}
+
+/// Runs `machine` on stdout and stdin, after the `--stdin` bytes in
+/// `primed`, for at most `max_instructions`, as host runs do.
+fn run_stdio(machine: &mut impl Stepper, primed: Vec<u8>, max_instructions: Option<u64>) -> Result<rum::embed::Status, rumdis::MachineError> {
+    use std::io::Read;
+    let mut primed = primed.into_iter();
+    let mut stdin = std::io::stdin().lock();
+    let mut input = || {
+        primed.next().or_else(|| {
+            let _ = std::io::stdout().flush();
+            let mut byte = [0];
+            (stdin.read(&mut byte).ok() == Some(1)).then_some(byte[0])
+        })
+    };
+    let result = machine.run(max_instructions, &mut input, &mut |byte| {
+        let _ = std::io::stdout().write_all(&[byte]);
+    });
+    let _ = std::io::stdout().flush();
+    result
+}
+
+/// Whether a host run stopped with `status` at its instruction limit, as
+/// it is reported before exiting with `LIMIT_EXIT_CODE`.
+fn limit_reached(status: rum::embed::Status, executed: u64) -> bool {
+    let limited = status == rum::embed::Status::Running;
+    if limited {
+        eprintln!("rum: instruction limit of {executed} reached");
+    }
+    limited
+}
+
+fn fault(e: &rumdis::MachineError) -> ! {
+    eprintln!("rum: {e}");
+    exit(rumdis::FAULT_EXIT_CODE)
+}
+
+/// The snapshot key in the file at `path` (see `seal`).
+fn snapshot_key(path: &str) -> rum::seal::Key {
+    rum::seal::Key::load(path).unwrap_or_else(|e| fail(&e))
+}
+
+/// The snapshot key given in the environment, if any: every command that
+/// reads or writes snapshots seals and opens them with it.
+fn env_snapshot_key() -> Option<rum::seal::Key> {
+    rum::seal::Key::from_env().unwrap_or_else(|e| fail(&e))
+}
+
+fn enter_sandbox() {
+    match sandbox::enter() {
+        Ok(true) => {}
+        Ok(false) => eprintln!("rum: Landlock is not available, sandboxing with seccomp only"),
+        Err(e) => fail(&format!("cannot enter the sandbox: {e}")),
+    }
+}
+
+/// Runs `program` with its input and output on the NATS subjects of `endpoint`.
+fn run_bus(program: Vec<u32>, endpoint: &bus::Endpoint, framing: bus::Framing, max_instructions: Option<u64>) {
+    let bridge = bus::Bridge::connect(endpoint, framing).unwrap_or_else(|e| fail(&format!("{}: {e}", endpoint.address)));
+    let mut machine = rum::embed::Machine::new(program);
+    let status = host_status(bridge.run(&mut machine, max_instructions).unwrap_or_else(|e| fail(&format!("{}: {e}", endpoint.address))));
+    if limit_reached(status, machine.executed()) {
+        exit(rumdis::LIMIT_EXIT_CODE);
+    }
+}
+
+/// Runs `program` on stdin/stdout with the shared-object plugins at `paths`
+/// attached, after the built-in ones (assertions, extensions) in `builtins`.
+fn run_plugins(program: Vec<u32>, builtins: Vec<Box<dyn rum::plugin::Plugin>>, paths: &[&str], primed: Vec<u8>, max_instructions: Option<u64>) {
+    let mut host = rum::plugin::Host::new(rum::embed::Machine::new(program));
+    for plugin in builtins {
+        host.add(plugin);
+    }
+    for path in paths {
+        host.add(load_plugin(path));
+    }
+    let result = run_stdio(&mut host, primed, max_instructions);
+    let executed = host.machine.executed();
+    // Unload the plugins first; `exit` skips destructors.
+    drop(host);
+    if limit_reached(host_status(result), executed) {
+        exit(rumdis::LIMIT_EXIT_CODE);
+    }
+}
+
+/// Runs `program` on stdin/stdout taking host events (see `rum::interrupt`):
+/// input arriving, and a timer every `timer` if given. With
+/// `--deterministic` the timer follows the virtual clock, and input counts
+/// as arrived from the start instead of as it comes.
+fn run_interrupts(program: Vec<u32>, delivery: interrupt::Delivery, timer: Option<std::time::Duration>, primed: Vec<u8>, options: &RunOptions) {
+    let mut controller = interrupt::Controller::new(rum::embed::Machine::new(program), delivery).unwrap_or_else(|e| fail(&e));
+    let source = std::io::Read::chain(std::io::Cursor::new(primed), std::io::stdin());
+    let mut input: Box<dyn FnMut() -> Option<u8>> = match options.deterministic {
+        true => {
+            if let Some(interval) = timer {
+                controller.virtual_timer(interval);
+            }
+            controller.raiser().raise(interrupt::INPUT);
+            let mut bytes = std::io::Read::bytes(source);
+            Box::new(move || bytes.next().and_then(Result::ok))
+        }
+        false => {
+            if let Some(interval) = timer {
+                interrupt::timer(controller.raiser(), interval);
+            }
+            Box::new(interrupt::input_events(source, controller.raiser()))
+        }
+    };
+    let mut stdout = std::io::stdout().lock();
+    let status = host_status(controller.run(options.max_instructions, &mut input, &mut |byte| {
+        let _ = stdout.write_all(&[byte]);
+        let _ = stdout.flush();
+    }));
+    let _ = stdout.flush();
+    if limit_reached(status, controller.machine.executed()) {
+        exit(rumdis::LIMIT_EXIT_CODE);
+    }
+}
+
+/// Runs the `.um64` program at `path` (stdin without one) on stdin/stdout
+/// (see `rum::um64`).
+fn run_um64(path: Option<&str>, primed: Vec<u8>, max_instructions: Option<u64>) {
+    use std::io::Read;
+    let program = match path {
+        Some(path) => rum::um64::load(path),
+        None => {
+            let mut bytes = Vec::new();
+            std::io::stdin().read_to_end(&mut bytes).unwrap_or_else(|e| fail(&format!("<stdin>: {e}")));
+            rum::um64::parse(&bytes)
+        }
+    };
+    let mut machine = rum::um64::Machine64::new(program.unwrap_or_else(|e| fail(&e)));
+    let status = host_status(run_stdio(&mut machine, primed, max_instructions));
+    if limit_reached(status, machine.executed()) {
+        exit(rumdis::LIMIT_EXIT_CODE);
+    }
+}
+
+/// Runs `program` on stdin/stdout with the standard syscall services (see
+/// `rum::syscall`) and `args` as its arguments, exiting with the code it
+/// exits with.
+fn run_syscalls(program: Vec<u32>, args: Vec<String>, primed: Vec<u8>, options: &RunOptions) {
+    let mut syscalls = rum::syscall::Syscalls::new(rum::embed::Machine::new(program));
+    syscalls.register_standard(args, rum::clock::Clock::new(options.deterministic));
+    let status = host_status(run_stdio(&mut syscalls, primed, options.max_instructions));
+    if limit_reached(status, syscalls.machine.executed()) {
+        exit(rumdis::LIMIT_EXIT_CODE);
+    }
+    if let Some(code) = syscalls.exit_code() {
+        exit(code as i32);
+    }
+}
+
+/// Runs `program` on stdin/stdout as cooperative contexts (see
+/// `rum::tasks`): one at 0 and one at each address in `spawn`.
+fn run_tasks(program: Vec<u32>, spawn: &[usize], primed: Vec<u8>, max_instructions: Option<u64>) {
+    let mut scheduler = rum::tasks::Scheduler::new(rum::embed::Machine::new(program)).unwrap_or_else(|e| fail(&e));
+    for &pc in spawn {
+        scheduler.spawn(pc, [0; 8]);
+    }
+    let status = host_status(run_stdio(&mut scheduler, primed, max_instructions));
+    if limit_reached(status, scheduler.machine.executed()) {
+        exit(rumdis::LIMIT_EXIT_CODE);
+    }
+}
+
+/// Runs `program` on stdin/stdout with guard words after every segment
+/// (see `rum::canary`).
+#[cfg(feature = "canary")]
+fn run_canary(program: Vec<u32>, primed: Vec<u8>, max_instructions: Option<u64>) {
+    let mut checked = rum::canary::Checked::new(rum::embed::Machine::new(program));
+    let status = host_status(run_stdio(&mut checked, primed, max_instructions));
+    if limit_reached(status, checked.machine.executed()) {
+        if let Err(overrun) = checked.verify() {
+            fail(&overrun.to_string());
+        }
+        exit(rumdis::LIMIT_EXIT_CODE);
+    }
+}
+
+#[cfg(not(feature = "canary"))]
+fn run_canary(_: Vec<u32>, _: Vec<u8>, _: Option<u64>) {
+    fail("--canary needs rum built with the canary feature")
+}
+
+#[cfg(feature = "extensions")]
+fn float_extension() -> Box<dyn rum::plugin::Plugin> {
+    Box::new(rum::float::Float)
+}
+
+#[cfg(not(feature = "extensions"))]
+fn float_extension() -> Box<dyn rum::plugin::Plugin> {
+    fail("--extensions needs rum built with the extensions feature")
+}
+
+#[cfg(feature = "plugins")]
+fn load_plugin(path: &str) -> Box<dyn rum::plugin::Plugin> {
+    rum::plugin::load(path).unwrap_or_else(|e| fail(&format!("{path}: {e}")))
+}
+
+#[cfg(not(feature = "plugins"))]
+fn load_plugin(_: &str) -> Box<dyn rum::plugin::Plugin> {
+    fail("this rum was built without the `plugins` feature")
+}
+
+/// `rum batch manifest.toml [--report report.json] [--jobs N]`
+///
+/// Prints a summary table and writes a JSON report (by default next to the
+/// manifest). Up to `--jobs` programs run at once. Exits nonzero if any
+/// program did not pass.
+fn batch(args: &[String]) {
+    let mut manifest = None;
+    let mut report = None;
+    let mut jobs = 1;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--report" => report = Some(value(args.next(), arg).to_string()),
+            "--jobs" | "-j" => jobs = number(args.next(), arg),
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            path => manifest = Some(Path::new(path)),
+        }
+    }
+    let manifest = manifest.unwrap_or_else(|| usage("batch needs a manifest file"));
+    let text = std::fs::read_to_string(manifest).unwrap_or_else(|e| fail(&format!("{}: {e}", manifest.display())));
+    let base = manifest.parent().unwrap_or(Path::new(""));
+    let cases = batch::parse_manifest(&text, base).unwrap_or_else(|e| fail(&e));
+
+    let outcomes = batch::run_all(&cases, jobs);
+    print!("{}", batch::table(&outcomes));
+    let cache = rum::cache::ProgramCache::global().stats();
+    println!("program cache: {} hits, {} misses", cache.hits, cache.misses);
+
+    let report = report.unwrap_or_else(|| manifest.with_extension("report.json").display().to_string());
+    std::fs::write(&report, batch::json(&outcomes)).unwrap_or_else(|e| fail(&format!("{report}: {e}")));
+
+    if outcomes.iter().any(|o| o.status != batch::Status::Pass) {
+        exit(1);
+    }
+}
+
+/// `rum bench [--runs N] [--workload midmark|sandmark|all]... [--report-format text|json|csv] [program.um]...`
+///
+/// Runs each workload N times (default 3) and prints min/mean/max MIPS.
+/// Without workloads or programs it runs the built-in midmark.
+/// `--report-format json|csv` prints the table as data (see `rum::report`).
+fn bench(args: &[String]) {
+    let mut runs = 3;
+    let mut workloads = Vec::new();
+    let mut format = report::Format::Text;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--runs" => runs = number(args.next(), arg),
+            "--report-format" => format = report_format(args.next(), arg),
+            "--workload" => match value(args.next(), arg) {
+                "all" => workloads.extend(bench::builtin_names().filter_map(bench::Workload::builtin)),
+                name => workloads.push(bench::Workload::builtin(name).unwrap_or_else(|| {
+                    usage(&format!("no built-in workload `{name}` (have {})", bench::builtin_names().collect::<Vec<_>>().join(", ")))
+                })),
+            },
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            path => {
+                let bytes = std::fs::read(path).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+                workloads.push(bench::Workload::new(path, &bytes));
+            }
+        }
+    }
+    if runs == 0 {
+        usage("--runs must be at least 1");
+    }
+    if workloads.is_empty() {
+        workloads.push(bench::Workload::builtin("midmark").unwrap());
+    }
+    let times = match runs {
+        1 => "once".to_string(),
+        n => format!("{n} times"),
+    };
+    let mut reports = Vec::new();
+    for workload in &workloads {
+        eprintln!("rum: running {} {times}", workload.name);
+        reports.push(bench::measure(workload, runs).unwrap_or_else(|e| fail(&e)));
+    }
+    print!("{}", report::render(format, &bench::tables(&reports), || bench::table(&reports)));
+}
+
+/// Expands `\n`, `\r`, `\t`, `\0`, `\\` and `\xHH` escapes in a `--stdin` argument.
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut bytes = text.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'n') => out.push(b'\n'),
+            Some(b'r') => out.push(b'\r'),
+            Some(b't') => out.push(b'\t'),
+            Some(b'0') => out.push(0),
+            Some(b'\\') => out.push(b'\\'),
+            Some(b'x') => {
+                let hex = [bytes.next(), bytes.next()];
+                let hex = match hex {
+                    [Some(hi), Some(lo)] => String::from_utf8(vec![hi, lo]).ok(),
+                    _ => None,
+                };
+                match hex.and_then(|h| u8::from_str_radix(&h, 16).ok()) {
+                    Some(byte) => out.push(byte),
+                    None => return Err("`\\x` needs two hex digits".to_string()),
+                }
+            }
+            Some(other) => return Err(format!("unknown escape `\\{}` in --stdin", other as char)),
+            None => return Err("--stdin ends with a lone `\\`".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+/// `rum compact program.um -o out.um [--trace coverage.txt]...`
+///
+/// Drops trailing words that can never be executed or read, printing a
+/// report of what was removed. Traces come from `rum run --coverage`.
+fn compact(args: &[String]) {
+    let mut program = None;
+    let mut output = None;
+    let mut traces = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(value(args.next(), arg)),
+            "--trace" => {
+                let path = Path::new(value(args.next(), arg));
+                traces.push(coverage::read_extent(path).unwrap_or_else(|e| fail(&e)));
+            }
+            flag if flag.starts_with('-') => usage(&format!("unknown option `{flag}`")),
+            path => program = Some(path),
+        }
+    }
+    let program = program.unwrap_or_else(|| usage("compact needs a program file"));
+    let output = output.unwrap_or_else(|| usage("compact needs -o OUTPUT"));
+
+    let instructions = load_program(Some(program));
+    let result = compact::compact(&instructions, &traces);
+    for line in &result.report {
+        println!("{line}");
+    }
+    println!("kept {} of {} words", result.keep, result.original);
+    load::store(output, &instructions[..result.keep]).unwrap_or_else(|e| fail(&format!("{output}: {e}")));
+}
+
+/// `rum conformance`
+///
+/// Runs the UM spec conformance suite against this interpreter, printing
+/// one line per case. Exits nonzero if any case fails.
+fn conformance(args: &[String]) {
+    if let Some(arg) = args.first() {
+        usage(&format!("conformance takes no arguments, got `{arg}`"));
+    }
+    let results = conformance::run_all();
+    for (name, result) in &results {
+        match result {
+            Ok(()) => println!("ok    {name}"),
+            Err(e) => println!("FAIL  {name}: {e}"),
+        }
+    }
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    println!("{} of {} cases passed", results.len() - failed, results.len());
+    if failed > 0 {
+        exit(1);
+    }
+}
+
+/// `rum differential [--reference naive|COMMAND] [--input FILE] [--max-instructions N] (program.um | --generate N)`
+///
+/// Runs the program on rum and on the reference (the built-in naive
+/// interpreter by default, or `COMMAND program.um`) and reports where they
+/// first disagree. `--generate N` compares the programs `rum::gen` makes
+/// from seeds 0 to N-1 instead. Exits 1 on a divergence.
+fn differential(args: &[String]) {
+    let mut reference = differential::Reference::Naive;
+    let mut input = Vec::new();
+    let mut fuel = None;
+    let mut seeds = None;
+    let mut program = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--reference" => {
+                reference = match value(args.next(), arg) {
+                    "naive" => differential::Reference::Naive,
+                    command => differential::Reference::Command(command.into()),
+                }
+            }
+            "--input" => {
+                let path = value(args.next(), arg);
+                input = std::fs::read(path).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+            }
+            "--max-instructions" => fuel = Some(number(args.next(), arg)),
+            "--generate" => seeds = Some(number(args.next(), arg)),
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            path => program = Some(path),
+        }
+    }
+    let programs: Vec<(String, Vec<u32>, u64)> = match (program, seeds) {
+        (Some(path), None) => vec![(path.to_string(), load_program(Some(path)), fuel.unwrap_or(differential::FUEL))],
+        (None, Some(seeds)) => {
+            let config = gen::Config::default();
+            (0..seeds).map(|seed| (format!("seed {seed}"), gen::generate(seed, &config), fuel.unwrap_or(config.fuel()))).collect()
+        }
+        _ => usage("differential needs a program file or --generate N"),
+    };
+    let mut diverged = 0;
+    for (name, program, fuel) in &programs {
+        match differential::compare(program, &input, &reference, *fuel).unwrap_or_else(|e| fail(&e)) {
+            None if programs.len() == 1 => println!("ok: rum and the reference agree"),
+            None => {}
+            Some(divergence) => {
+                diverged += 1;
+                print!("{name}: {divergence}");
+            }
+        }
+    }
+    if programs.len() > 1 {
+        println!("{} of {} programs agree", programs.len() - diverged, programs.len());
+    }
+    if diverged > 0 {
+        exit(1);
+    }
+}
+
+/// `rum bisect [--left rum|naive] [--right rum|naive] [--input FILE] [--every N] [--memory] [--max-instructions N] program.um`
+///
+/// Runs the program on two configurations in lockstep, comparing them
+/// every N instructions, and prints the first instruction they disagree
+/// after. Exits 1 if they do.
+fn bisect(args: &[String]) {
+    let mut names = ["rum", "naive"];
+    let mut input = Vec::new();
+    let mut every = rum::bisect::EVERY;
+    let mut memory = false;
+    let mut fuel = differential::FUEL;
+    let mut program = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--left" => names[0] = value(args.next(), arg),
+            "--right" => names[1] = value(args.next(), arg),
+            "--input" => {
+                let path = value(args.next(), arg);
+                input = std::fs::read(path).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+            }
+            "--every" => every = number(args.next(), arg),
+            "--memory" => memory = true,
+            "--max-instructions" => fuel = number(args.next(), arg),
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            path => program = Some(path),
+        }
+    }
+    let program = load_program(Some(program.unwrap_or_else(|| usage("bisect needs a program file"))));
+    let [left, right] = names.map(|name| rum::bisect::engine(name, &program, &input).unwrap_or_else(|| usage(&format!("unknown configuration `{name}` (have rum and naive)"))));
+    match rum::bisect::bisect(names, left, right, every, fuel, memory) {
+        None => println!("ok: {} and {} agree", names[0], names[1]),
+        Some(divergence) => {
+            print!("{divergence}");
+            exit(1);
+        }
+    }
+}
+
+/// `rum mutate program.um --cases DIR [--report-format text|json|csv]`
+///
+/// Mutation-tests the program against the cases in DIR (each NAME.expected,
+/// with NAME.in as input) and lists the mutants no case killed.
+/// `--report-format json|csv` prints them as data (see `rum::report`).
+fn mutate(args: &[String]) {
+    let mut program = None;
+    let mut cases = None;
+    let mut format = report::Format::Text;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cases" => cases = Some(value(args.next(), arg)),
+            "--report-format" => format = report_format(args.next(), arg),
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            path => program = Some(path),
+        }
+    }
+    let program = program.unwrap_or_else(|| usage("mutate needs a program file"));
+    let dir = cases.unwrap_or_else(|| usage("mutate needs --cases DIR"));
+    let cases = mutate::read_cases(dir).unwrap_or_else(|e| fail(&format!("{dir}: {e}")));
+    if cases.is_empty() {
+        fail(&format!("{dir}: no .expected files"));
+    }
+    let instructions = load_program(Some(program));
+    let report = mutate::run(&instructions, &cases, &mut |n, total| {
+        if n % 100 == 0 {
+            eprintln!("rum: mutant {} of {total}", n + 1);
+        }
+    })
+    .unwrap_or_else(|e| fail(&e));
+    if format != report::Format::Text {
+        print!("{}", report::render(format, &report.tables(cases.len()), String::new));
+        return;
+    }
+    for mutant in &report.survivors {
+        println!("survived  {mutant}");
+    }
+    println!(
+        "{} mutants over {} cases: {} killed, {} survived (score {:.1}%); {} words never executed",
+        report.total(),
+        cases.len(),
+        report.killed,
+        report.survivors.len(),
+        report.score(),
+        report.uncovered
+    );
+}
+
+/// `rum minimize program.um --input FILE (--fault | --output-contains TEXT) [--max-instructions N] [-o OUT]`
+///
+/// Writes the smallest input found that still makes the program fault
+/// where FILE does, or write TEXT, to OUT (by default FILE.min).
+fn minimize(args: &[String]) {
+    let mut program = None;
+    let mut input = None;
+    let mut target = None;
+    let mut fuel = rum::minimize::FUEL;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input = Some(value(args.next(), arg)),
+            "--fault" => target = Some(rum::minimize::Target::Fault),
+            "--output-contains" => target = Some(rum::minimize::Target::OutputContains(unescape(value(args.next(), arg)).unwrap_or_else(|e| usage(&e)))),
+            "--max-instructions" => fuel = number(args.next(), arg),
+            "-o" | "--output" => output = Some(value(args.next(), arg).to_string()),
+            flag if flag.starts_with('-') => usage(&format!("unknown option `{flag}`")),
+            path => program = Some(path),
+        }
+    }
+    let program = load_program(Some(program.unwrap_or_else(|| usage("minimize needs a program file"))));
+    let path = input.unwrap_or_else(|| usage("minimize needs --input FILE"));
+    let target = target.unwrap_or_else(|| usage("minimize needs --fault or --output-contains TEXT"));
+    let output = output.unwrap_or_else(|| format!("{path}.min"));
+    let input = std::fs::read(path).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+
+    let minimized = rum::minimize::minimize(&program, &input, &target, fuel).unwrap_or_else(|e| fail(&e));
+    std::fs::write(&output, &minimized.input).unwrap_or_else(|e| fail(&format!("{output}: {e}")));
+    println!(
+        "minimized {path} from {} to {} bytes in {} runs ({}); written to {output}",
+        input.len(),
+        minimized.input.len(),
+        minimized.runs,
+        rum::minimize::describe(&minimized.run.outcome)
+    );
+}
+
+/// `rum profile [--input FILE] [--max-instructions N] [--top N] [--report-format text|json|csv] [--memory] [--jumps FILE] [--self-modifying FILE] [--html FILE] [--folded FILE [--sample-every N]] [--sample-us N] program.um`
+///
+/// Runs the program with its output on stdout, then prints its `--top`
+/// (default 20) most executed addresses to stderr. Input comes from
+/// `--input FILE`, or stdin without it. `--folded FILE` writes call stack
+/// samples (one every `--sample-every`, default 100, instructions) for
+/// flamegraph tools. `--sample-us N` samples the PC every N microseconds
+/// instead of counting every instruction, for runs too long to count.
+/// `--memory` adds a report of the busiest allocation sites, and `--jumps
+/// FILE` writes Load Program edge counts (see `rum::jumps`).
+/// `--self-modifying FILE` logs Stores over code that has executed and
+/// adds a report of the rewritten regions (see `rum::selfmod`). `--html
+/// FILE` writes the counts as a heatmap page. `--report-format json|csv` prints
+/// the report as data instead (see `rum::report`), every executed address
+/// included unless `--top` is given.
+fn profile(args: &[String]) {
+    let mut input = None;
+    let mut fuel = None;
+    let mut top = None;
+    let mut format = report::Format::Text;
+    let mut folded = None;
+    let mut every = 100;
+    let mut interval = None;
+    let mut memory = false;
+    let mut jumps = None;
+    let mut rewrites = None;
+    let mut html = None;
+    let mut symbols = None;
+    let mut program = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => {
+                let path = value(args.next(), arg);
+                input = Some(std::fs::read(path).unwrap_or_else(|e| fail(&format!("{path}: {e}"))));
+            }
+            "--max-instructions" => fuel = Some(number(args.next(), arg)),
+            "--top" => top = Some(number(args.next(), arg)),
+            "--report-format" => format = report_format(args.next(), arg),
+            "--folded" => folded = Some(value(args.next(), arg)),
+            "--memory" => memory = true,
+            "--jumps" => jumps = Some(value(args.next(), arg)),
+            "--self-modifying" => rewrites = Some(value(args.next(), arg)),
+            "--html" => html = Some(value(args.next(), arg)),
+            "--symbols" => {
+                let path = value(args.next(), arg);
+                symbols = Some(rum::symbols::Symbols::load(Path::new(path)).unwrap_or_else(|e| fail(&e)));
+            }
+            "--sample-every" => every = number(args.next(), arg),
+            "--sample-us" => interval = Some(std::time::Duration::from_micros(number(args.next(), arg))),
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            path => program = Some(path),
+        }
+    }
+    let path = program.unwrap_or_else(|| usage("profile needs a program file"));
+    let program = load_program(Some(path));
+    if (folded.is_some() || memory || jumps.is_some() || rewrites.is_some()) && interval.is_some() {
+        usage("--folded, --memory, --jumps and --self-modifying need every instruction; drop --sample-us");
+    }
+    let mut read = input_source(input);
+    let mut stdout = std::io::stdout().lock();
+    let mut write = |byte| {
+        let _ = stdout.write_all(&[byte]);
+        if byte == b'\n' {
+            let _ = stdout.flush();
+        }
+    };
+    let (profile, result) = match interval {
+        Some(interval) => profile::sample(program, fuel, interval, &mut read, &mut write),
+        None => {
+            let mut recording = profile::Profile::new(&program);
+            if folded.is_some() {
+                recording = recording.sample_stacks(every);
+            }
+            if memory {
+                recording = recording.track_memory();
+            }
+            if jumps.is_some() {
+                recording = recording.track_jumps();
+            }
+            if rewrites.is_some() {
+                recording = recording.track_self_modification();
+            }
+            profile::profile_with(recording, program, fuel, &mut read, &mut write)
+        }
+    };
+    let _ = stdout.flush();
+    eprint!(
+        "{}",
+        report::render(format, &profile_tables(&profile, symbols.as_ref(), top), || {
+            let top = top.unwrap_or(20);
+            let mut text = profile::report(&profile, top);
+            if let Some(symbols) = &symbols {
+                text += &format!("\n{}", profile::routine_report(&profile, symbols, top));
+            }
+            if let Some(memory) = profile.memory() {
+                text += &format!("\n{}", memory.report(top));
+            }
+            if let Some(rewrites) = profile.self_modification() {
+                text += &format!("\n{}", rewrites.report());
+            }
+            text
+        })
+    );
+    if let (Some(path), Some(edges)) = (jumps, profile.jumps()) {
+        edges.save(Path::new(path)).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+    }
+    if let (Some(path), Some(rewrites)) = (rewrites, profile.self_modification()) {
+        rewrites.save(Path::new(path)).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+    }
+    if let Some(page) = html {
+        let name = Path::new(path).file_name().map_or(path.into(), |name| name.to_string_lossy());
+        std::fs::write(page, rum::heatmap::html(&profile, &name)).unwrap_or_else(|e| fail(&format!("{page}: {e}")));
+    }
+    if let (Some(path), Some(samples)) = (folded, profile.samples()) {
+        let written = std::fs::File::create(path).and_then(|file| samples.write_folded(&mut std::io::BufWriter::new(file)));
+        match written {
+            Ok(()) => eprintln!("rum: {} stack samples written to {path}", samples.total()),
+            Err(e) => fail(&format!("{path}: {e}")),
+        }
+    }
+    match result {
+        Ok(embed::Status::Halted) => {}
+        Ok(embed::Status::Running) => eprintln!("rum: stopped after {} instructions without halting", profile.executed),
+        Err(e) => fail(&format!("faulted after {} instructions: {e}", profile.executed)),
+    }
+}
+
+/// `profile::tables` with the routines table after the hot one, given symbols.
+fn profile_tables(profile: &profile::Profile, symbols: Option<&rum::symbols::Symbols>, top: Option<usize>) -> Vec<report::Table> {
+    let mut tables = profile::tables(profile, top);
+    if let Some(symbols) = symbols {
+        tables.insert(1, profile::routine_table(profile, symbols, top));
+    }
+    tables
+}
+
+/// `rum routines program.um [--jumps FILE] [-o OUT.sym]`
+///
+/// Infers the program's routines (see `routines`) and writes them as a
+/// symbol file, on stdout without `-o`.
+fn routines(args: &[String]) {
+    let mut jumps = None;
+    let mut output = None;
+    let mut program = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--jumps" => jumps = Some(rum::jumps::JumpProfile::load(Path::new(value(args.next(), arg))).unwrap_or_else(|e| fail(&e))),
+            "-o" | "--output" => output = Some(value(args.next(), arg)),
+            flag if flag.starts_with('-') => usage(&format!("unknown option `{flag}`")),
+            path => program = Some(path),
+        }
+    }
+    let program = load_program(Some(program.unwrap_or_else(|| usage("routines needs a program file"))));
+    let symbols = rum::routines::infer(&program, jumps.as_ref());
+    match output {
+        Some(path) => {
+            symbols.save(Path::new(path)).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+            eprintln!("rum: {} routines written to {path}", symbols.routines().len());
+        }
+        None => symbols.write(&mut std::io::stdout().lock()).unwrap_or_else(|e| fail(&e.to_string())),
+    }
+}
+
+/// `rum taint [--input FILE] [--max-instructions N] [--report-format text|json|csv] program.um`
+///
+/// Runs the program with its output on stdout, tracking which input bytes
+/// each value was computed from (see `rum::taint`), then prints the output
+/// bytes and branches that depended on input to stderr. Input comes from
+/// `--input FILE`, or stdin without it.
+fn taint(args: &[String]) {
+    let mut input = None;
+    let mut fuel = None;
+    let mut format = report::Format::Text;
+    let mut program = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => {
+                let path = value(args.next(), arg);
+                input = Some(std::fs::read(path).unwrap_or_else(|e| fail(&format!("{path}: {e}"))));
+            }
+            "--max-instructions" => fuel = Some(number(args.next(), arg)),
+            "--report-format" => format = report_format(args.next(), arg),
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            path => program = Some(path),
+        }
+    }
+    let program = load_program(Some(program.unwrap_or_else(|| usage("taint needs a program file"))));
+    let mut read = input_source(input);
+    let mut stdout = std::io::stdout().lock();
+    let (tracker, result) = taint::track(program, fuel, &mut read, &mut |byte| {
+        let _ = stdout.write_all(&[byte]);
+    });
+    let _ = stdout.flush();
+    eprint!("{}", report::render(format, &tracker.tables(), || tracker.report()));
+    match result {
+        Ok(embed::Status::Halted) => {}
+        Ok(embed::Status::Running) => eprintln!("rum: stopped after {} instructions without halting", fuel.unwrap_or_default()),
+        Err(e) => fail(&format!("faulted: {e}")),
+    }
+}
+
+/// Input for `profile` and `taint`: the bytes given, or stdin.
+fn input_source(input: Option<Vec<u8>>) -> Box<dyn FnMut() -> Option<u8>> {
+    match input {
+        Some(bytes) => {
+            let mut bytes = bytes.into_iter();
+            Box::new(move || bytes.next())
+        }
+        None => {
+            let mut stdin = std::io::stdin().lock();
+            Box::new(move || {
+                let mut byte = [0];
+                match std::io::Read::read(&mut stdin, &mut byte) {
+                    Ok(1) => Some(byte[0]),
+                    _ => None,
+                }
+            })
+        }
+    }
+}
+
+/// `rum check program.um (--expected FILE | --sha256 HEX) [--input FILE] [--timeout SECS] [--max-instructions N]`
+///
+/// Exits 0 if the output matches, otherwise prints a diff (or digests) and exits 1.
+fn check(args: &[String]) {
+    let mut case = batch::Case::default();
+    let mut expected = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => case.input = Some(value(args.next(), arg).into()),
+            "--expected" => {
+                let path = value(args.next(), arg);
+                let bytes = std::fs::read(path).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+                expected = Some(check::Expected::Output(bytes));
+            }
+            "--sha256" => expected = Some(check::Expected::Sha256(value(args.next(), arg).to_string())),
+            "--timeout" => case.timeout = Some(number(args.next(), arg)),
+            "--max-instructions" => case.max_instructions = Some(number(args.next(), arg)),
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            path => case.program = path.into(),
+        }
+    }
+    if case.program.as_os_str().is_empty() {
+        usage("check needs a program file");
+    }
+    let expected = expected.unwrap_or_else(|| usage("check needs --expected or --sha256"));
+
+    let run = batch::execute(&case).unwrap_or_else(|e| fail(&e));
+    match run.exit {
+        batch::Exit::Halted => {}
+        batch::Exit::InstructionLimit => fail("instruction limit reached"),
+        batch::Exit::Timeout => fail(&format!("timed out after {}s", case.timeout.unwrap_or_default())),
+        batch::Exit::Faulted(e) => fail(&format!("faulted after {} instructions: {e}", run.executed)),
+    }
+    match check::verify(&run.output, &expected) {
+        Ok(()) => println!("ok: output matches ({} bytes, {:.3}s)", run.output.len(), run.elapsed.as_secs_f64()),
+        Err(report) => {
+            print!("{report}");
+            exit(1);
+        }
+    }
+}
+
+/// `rum info [--report-format text|json|csv] (program.um | image.rumimg)`
+///
+/// Says how to run a program: its size, the extensions its `.requires`
+/// sidecar declares, the arguments it takes and the command line that
+/// puts them together (see `requires`).
+fn info(args: &[String]) {
+    let mut format = report::Format::Text;
+    let mut program = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--report-format" => format = report_format(args.next(), arg),
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            path => program = Some(path),
+        }
+    }
+    let program = program.unwrap_or_else(|| usage("info needs a program file"));
+    if rum::image::is_image(program) {
+        return image_info(program, format);
+    }
+    let bytes = std::fs::metadata(program).unwrap_or_else(|e| fail(&format!("{program}: {e}"))).len();
+    let extensions = requires::read(program).unwrap_or_else(|e| fail(&e));
+    let arguments = requires::read_arguments(program).unwrap_or_else(|e| fail(&e));
+    let command = requires::command(program, &extensions, arguments.as_deref());
+    let names: Vec<String> = extensions.iter().map(requires::Extension::to_string).collect();
+
+    let mut table = report::Table::new("info", &["program", "words", "requires", "arguments", "command"]);
+    table.row(vec![program.into(), (bytes / 4).into(), names.join(" ").into(), arguments.clone().map_or(report::Cell::None, Into::into), command.as_str().into()]);
+    print!(
+        "{}",
+        report::render(format, &[table], || {
+            let mut out = format!("program    {program}\nwords      {}", bytes / 4);
+            if bytes % 4 != 0 {
+                out += &format!(" (and {} stray bytes: not a UM program?)", bytes % 4);
+            }
+            out += "\nrequires   ";
+            out += &match names.is_empty() {
+                true => "nothing".to_string(),
+                false => format!("{} (declared in {})", names.join(", "), requires::sidecar(program).display()),
+            };
+            out += "\narguments  ";
+            out += match arguments.as_deref() {
+                None => "none",
+                Some("") => "yes, undescribed",
+                Some(synopsis) => synopsis,
+            };
+            out += &format!("\nrun with   {command}\n");
+            out
+        })
+    );
+}
+
+/// `rum info` on a machine image: what it holds and how it runs.
+fn image_info(path: &str, format: report::Format) {
+    let image = rum::image::Image::load(path).unwrap_or_else(|e| fail(&e));
+    let options = image.options.join(" ");
+    let key = env_snapshot_key();
+    let snapshot = image.snapshot.as_ref().map(|bytes| snapshot::decode(bytes, key.as_ref()).unwrap_or_else(|e| fail(&format!("{path}: {e}"))));
+    let command = format!("rum run {path}");
+
+    let mut table = report::Table::new("image", &["image", "description", "words", "options", "input_bytes", "snapshot_pc", "command"]);
+    let pc = snapshot.as_ref().map_or(report::Cell::None, |state| state.program_counter.into());
+    table.row(vec![path.into(), image.description.as_str().into(), image.program.len().into(), options.as_str().into(), image.input.len().into(), pc, command.as_str().into()]);
+    print!(
+        "{}",
+        report::render(format, &[table], || {
+            let mut out = format!("image      {path}\n");
+            if !image.description.is_empty() {
+                out += &format!("about      {}\n", image.description);
+            }
+            out += &format!("words      {}\n", image.program.len());
+            out += &format!("options    {}\n", if options.is_empty() { "none" } else { &options });
+            out += &format!("input      {} bytes before stdin\n", image.input.len());
+            out += &match &snapshot {
+                Some(state) => format!("resumes    a snapshot at pc {}\n", state.program_counter),
+                None => "resumes    nothing: the program starts afresh\n".to_string(),
+            };
+            out += &format!("run with   {command}\n");
+            out
+        })
+    );
+}
+
+/// `rum pack program.um [--input FILE] [--snapshot SNAPSHOT] [--description TEXT] -o IMAGE.rumimg [-- RUN-OPTION...]`
+///
+/// Bundles a program with its input, a snapshot to resume and the `rum run`
+/// options after `--` into one machine image (see `image`).
+fn pack(args: &[String]) {
+    let mut image = rum::image::Image::default();
+    let mut program = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => {
+                let path = value(args.next(), arg);
+                image.input = std::fs::read(path).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+            }
+            "--snapshot" => {
+                let path = value(args.next(), arg);
+                let bytes = std::fs::read(path).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+                // Only a full snapshot stands on its own.
+                snapshot::decode(&bytes, env_snapshot_key().as_ref()).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+                image.snapshot = Some(bytes);
+            }
+            "--description" => image.description = value(args.next(), arg).to_string(),
+            "-o" | "--output" => output = Some(value(args.next(), arg)),
+            "--" => image.options = args.by_ref().cloned().collect(),
+            flag if flag.starts_with('-') => usage(&format!("unknown option `{flag}`")),
+            path => program = Some(path),
+        }
+    }
+    let program = program.unwrap_or_else(|| usage("pack needs a program file"));
+    let output = output.unwrap_or_else(|| usage("pack needs -o IMAGE.rumimg"));
+    if !rum::image::is_image(output) {
+        usage("the image must be named *.rumimg for rum run to recognize it");
+    }
+    if let Some(option) = image.options.iter().find(|option| ["--resume", "--watch", "--arch"].contains(&option.as_str())) {
+        usage(&format!("{option} cannot be packed into an image"));
+    }
+    image.program = load_program(Some(program));
+    image.save(output).unwrap_or_else(|e| fail(&e));
+    eprintln!("rum: packed {program} into {output}");
+}
+
+/// `rum debug [--stdin TEXT] [--script FILE] (program.um | --resume SNAPSHOT)`
+fn debug(args: &[String]) {
+    let mut primed = Vec::new();
+    let mut state = None;
+    let mut script = None;
+    let mut explain = false;
+    let key = env_snapshot_key();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stdin" => primed.extend(unescape(value(args.next(), arg)).unwrap_or_else(|e| usage(&e))),
+            "--script" => script = Some(value(args.next(), arg)),
+            "--explain" => explain = true,
+            "--resume" => {
+                let path = value(args.next(), arg);
+                state = Some(snapshot::load_snapshot(path, key.as_ref()).unwrap_or_else(|e| fail(&format!("{path}: {e}"))));
+            }
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            path => {
+                let mut machine = UniversalMachine::new();
+                machine.mapped_memory.push(load_program(Some(path)));
+                state = Some(machine);
+            }
+        }
+    }
+    let mut state = state.unwrap_or_else(|| usage("debug needs a program file or --resume"));
+    state.prime_input(&primed);
+    let mut debugger = debug::Debugger::new(state).with_snapshot_key(key);
+    if explain {
+        debugger = debugger.with_explain();
+    }
+    let mut debugger = match script {
+        Some(path) => with_script(debugger, path),
+        None => debugger,
+    };
+    debugger.repl(std::io::stdin().lock());
+}
+
+#[cfg(feature = "script")]
+fn with_script(debugger: debug::Debugger, path: &str) -> debug::Debugger {
+    debugger.with_script(rum::script::Script::load(path).unwrap_or_else(|e| fail(&format!("{path}: {e}"))))
+}
+
+#[cfg(not(feature = "script"))]
+fn with_script(_: debug::Debugger, _: &str) -> debug::Debugger {
+    fail("this rum was built without the `script` feature")
+}
+
+/// `rum repl [--stdin TEXT] [--load SNAPSHOT] [--explain]`
+fn repl(args: &[String]) {
+    let mut primed = Vec::new();
     let mut state = UniversalMachine::new();
-    rumdis::run(&mut state, instructions.clone())
+    let mut explain = false;
+    let key = env_snapshot_key();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stdin" => primed.extend(unescape(value(args.next(), arg)).unwrap_or_else(|e| usage(&e))),
+            "--explain" => explain = true,
+            "--load" => {
+                let path = value(args.next(), arg);
+                state = snapshot::load_snapshot(path, key.as_ref()).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+            }
+            other => usage(&format!("unknown option `{other}`")),
+        }
+    }
+    state.prime_input(&primed);
+    let mut repl = rum::repl::Repl::new(state).with_snapshot_key(key);
+    if explain {
+        repl = repl.with_explain();
+    }
+    if let Err(e) = repl.run(std::io::stdin().lock(), &mut std::io::stdout()) {
+        fail(&e.to_string());
+    }
+}
+
+/// `rum dump-mem (SNAPSHOT | program.um) [--segment N] [--format bin|hex] -o DIR`
+fn dump_mem(args: &[String]) {
+    let mut source = None;
+    let mut segment = None;
+    let mut format = memimage::Format::Bin;
+    let mut dir = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--segment" => segment = Some(number(args.next(), arg)),
+            "--format" => format = match value(args.next(), arg) {
+                "bin" => memimage::Format::Bin,
+                "hex" => memimage::Format::Hex,
+                other => usage(&format!("unknown format `{other}`")),
+            },
+            "-o" | "--output" => dir = Some(Path::new(value(args.next(), arg))),
+            flag if flag.starts_with('-') => usage(&format!("unknown option `{flag}`")),
+            path => source = Some(path),
+        }
+    }
+    let source = source.unwrap_or_else(|| usage("dump-mem needs a snapshot or program"));
+    let dir = dir.unwrap_or_else(|| usage("dump-mem needs -o DIR"));
+    for path in memimage::export(&snapshot_or_program(source), segment, dir, format).unwrap_or_else(|e| fail(&e)) {
+        println!("{}", path.display());
+    }
+}
+
+/// `rum render-mem (SNAPSHOT | program.um) [--segment N] [--width W] [--palette gray|heat] [--scale log|linear] -o OUT.png`
+///
+/// Draws a segment (0 by default) one pixel per word, see `segimage`.
+fn render_mem(args: &[String]) {
+    let mut source = None;
+    let mut segment = 0;
+    let mut width = None;
+    let mut palette = segimage::Palette::Gray;
+    let mut scale = segimage::Scale::Log;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--segment" => segment = number(args.next(), arg),
+            "--width" => width = Some(number(args.next(), arg)),
+            "--palette" => palette = segimage::Palette::parse(value(args.next(), arg)).unwrap_or_else(|e| usage(&e)),
+            "--scale" => scale = segimage::Scale::parse(value(args.next(), arg)).unwrap_or_else(|e| usage(&e)),
+            "-o" | "--output" => output = Some(value(args.next(), arg)),
+            flag if flag.starts_with('-') => usage(&format!("unknown option `{flag}`")),
+            path => source = Some(path),
+        }
+    }
+    let source = source.unwrap_or_else(|| usage("render-mem needs a snapshot or program"));
+    let output = output.unwrap_or_else(|| usage("render-mem needs -o OUT.png"));
+
+    let state = snapshot_or_program(source);
+    let words = match state.mapped_memory.get(segment) {
+        Some(words) if !state.free_segments().contains(&(segment as u32)) => words,
+        _ => fail(&format!("segment {segment} is not mapped")),
+    };
+    let width = width.unwrap_or_else(|| segimage::default_width(words.len()));
+    let image = segimage::render(words, width, palette, scale).unwrap_or_else(|e| fail(&format!("segment {segment}: {e}")));
+    std::fs::write(output, image).unwrap_or_else(|e| fail(&format!("{output}: {e}")));
+    println!("{output}: {width}x{} pixels", words.len().div_ceil(width));
+}
+
+/// The machine in a snapshot, or one about to run a program.
+fn snapshot_or_program(source: &str) -> UniversalMachine {
+    // A plain program is just segment 0 of a machine that hasn't started yet.
+    match snapshot::load_snapshot(source, env_snapshot_key().as_ref()) {
+        Ok(state) => state,
+        Err(snapshot::SnapshotError::NotASnapshot) => {
+            let mut state = UniversalMachine::new();
+            state.mapped_memory.push(load_program(Some(source)));
+            state
+        }
+        Err(e) => fail(&format!("{source}: {e}")),
+    }
+}
+
+/// `rum import-mem seg-0.bin [seg-N.hex]... [--pc N] -o SNAPSHOT`
+fn import_mem(args: &[String]) {
+    let mut files = Vec::new();
+    let mut pc = 0;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--pc" => pc = number(args.next(), arg),
+            "-o" | "--output" => output = Some(value(args.next(), arg)),
+            flag if flag.starts_with('-') => usage(&format!("unknown option `{flag}`")),
+            path => files.push(path.into()),
+        }
+    }
+    let output = output.unwrap_or_else(|| usage("import-mem needs -o SNAPSHOT"));
+    let state = memimage::import(&files, pc).unwrap_or_else(|e| fail(&e));
+    snapshot::save_snapshot(&state, output, env_snapshot_key().as_ref()).unwrap_or_else(|e| fail(&format!("{output}: {e}")));
+}
+
+/// `rum replay LOG [--to N] [--save SNAPSHOT]`
+///
+/// Re-executes a recorded run up to instruction N (default: to the end),
+/// writing its output to stdout and optionally saving the machine there.
+fn replay(args: &[String]) {
+    let mut log = None;
+    let mut to = None;
+    let mut save = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--to" => to = Some(number(args.next(), arg)),
+            "--save" => save = Some(value(args.next(), arg)),
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            path => log = Some(path),
+        }
+    }
+    let path = log.unwrap_or_else(|| usage("replay needs a log file"));
+    let key = env_snapshot_key();
+    let log = replay::ReplayLog::load(path, key.as_ref()).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+
+    let mut replay = replay::Replay::new(&log);
+    let result = replay.run_to(to.or(log.end).unwrap_or(u64::MAX));
+    std::io::stdout().write_all(&replay.output).unwrap_or_else(|e| fail(&format!("cannot write output: {e}")));
+    eprintln!("rum: replayed {} instructions{}", replay.count(), if replay.halted() { " (halted)" } else { "" });
+    if let Some(save) = save {
+        snapshot::save_snapshot(replay.state(), save, key.as_ref()).unwrap_or_else(|e| fail(&format!("{save}: {e}")));
+    }
+    if let Err(e) = result {
+        fail(&e.to_string());
+    }
+}
+
+/// `rum log query LOG [--kind KIND,...] [--after N] [--before N] [--larger-than BYTES] [--segment N] [--pc N] [--limit N] [--report-format text|json|csv]`
+///
+/// Prints the events of a `rum run --event-log` log that match every
+/// filter given, e.g. `--kind map --larger-than 1048576 --after 1000000000`
+/// for the big allocations late in a run.
+fn log(args: &[String]) {
+    match args.first().map(String::as_str) {
+        Some("query") => log_query(&args[1..]),
+        _ => usage("log needs a command: query"),
+    }
+}
+
+fn log_query(args: &[String]) {
+    let mut query = rum::events::Query::default();
+    let mut limit = None;
+    let mut format = report::Format::Text;
+    let mut log = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--kind" => {
+                for kind in value(args.next(), arg).split(',').map(str::trim) {
+                    if !rum::events::Kind::NAMES.contains(&kind) {
+                        usage(&format!("unknown event kind `{kind}` (have {})", rum::events::Kind::NAMES.join(", ")));
+                    }
+                    query.kinds.push(kind.to_string());
+                }
+            }
+            "--after" => query.after = Some(number(args.next(), arg)),
+            "--before" => query.before = Some(number(args.next(), arg)),
+            "--larger-than" => query.larger_than = Some(number(args.next(), arg)),
+            "--segment" => query.segment = Some(number(args.next(), arg)),
+            "--pc" => query.pc = Some(number(args.next(), arg)),
+            "--limit" => limit = Some(number(args.next(), arg)),
+            "--report-format" => format = report_format(args.next(), arg),
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            path => log = Some(path),
+        }
+    }
+    let path = log.unwrap_or_else(|| usage("log query needs a log file"));
+    let reader = rum::events::Reader::open(path).unwrap_or_else(|e| fail(&e));
+
+    let mut matched = Vec::new();
+    let mut error = None;
+    for event in reader {
+        match event {
+            Ok(event) if query.matches(&event) => matched.push(event),
+            Ok(_) => {}
+            Err(e) => error = Some(e),
+        }
+        if limit == Some(matched.len()) {
+            break;
+        }
+    }
+    let mut table = report::Table::new("events", &["instruction", "pc", "event", "segment", "words", "value", "message"]);
+    for event in &matched {
+        let value = match &event.kind {
+            rum::events::Kind::Input(value) | rum::events::Kind::Output(value) | rum::events::Kind::Load { target: value, .. } => Some(*value as u64),
+            _ => None,
+        };
+        let message = match &event.kind {
+            rum::events::Kind::Fault(message) => report::Cell::from(message.as_str()),
+            _ => report::Cell::None,
+        };
+        table.row(vec![event.executed.into(), (event.pc as u64).into(), event.kind.name().into(), event.segment().map(u64::from).into(), event.words().map(u64::from).into(), value.into(), message]);
+    }
+    print!(
+        "{}",
+        report::render(format, &[table], || match matched.is_empty() {
+            true => "no events match\n".to_string(),
+            false => matched.iter().map(|event| event.describe() + "\n").collect(),
+        })
+    );
+    if let Some(e) = error {
+        fail(&format!("{path}: {e}"));
+    }
+}
+
+/// `rum statediff BEFORE.rumstate AFTER.rumstate`
+///
+/// Exits 1 if the states differ, like `diff`.
+fn state_diff(args: &[String]) {
+    let [before, after] = args else { usage("statediff needs two snapshots") };
+    let key = env_snapshot_key();
+    let load = |path: &String| snapshot::load_snapshot(path, key.as_ref()).unwrap_or_else(|e| fail(&format!("{path}: {e}")));
+    let diff = statediff::diff(&load(before), &load(after));
+    print!("{diff}");
+    if !diff.is_empty() {
+        exit(1);
+    }
+}
+
+/// `rum inspect [PID | SOCKET]`
+///
+/// Prints the state of a run started with `--inspect` as a line of JSON,
+/// or of every such run on the machine.
+fn inspect(args: &[String]) {
+    match args {
+        [] => {
+            for (_, path) in inspect::sockets() {
+                // Sockets nobody answers on are left over from killed runs.
+                if let Ok(run) = inspect::query(&path) {
+                    println!("{run}");
+                }
+            }
+        }
+        [target] => println!("{}", inspect::query(inspected_socket(target)).unwrap_or_else(|e| fail(&format!("{target}: {e} (is it running with --inspect?)")))),
+        _ => usage("inspect takes one process id or socket"),
+    }
+}
+
+/// `rum top [--interval SECS] [PID | SOCKET]...`
+///
+/// Refreshes a table of the given runs started with `--inspect`, or of all
+/// of them, every SECS seconds (1 by default) until interrupted. With a
+/// single run its recent output is shown below the table.
+fn top(args: &[String]) {
+    let mut interval = 1.0;
+    let mut targets = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--interval" => interval = Some(number::<f64>(args.next(), arg)).filter(|&secs| secs > 0.0).unwrap_or_else(|| usage("--interval needs a time above 0")),
+            flag if flag.starts_with("--") => usage(&format!("unknown option `{flag}`")),
+            target => targets.push(inspected_socket(target)),
+        }
+    }
+    let tty = std::io::IsTerminal::is_terminal(&std::io::stdout());
+    loop {
+        let sockets = match targets.is_empty() {
+            true => inspect::sockets().into_iter().map(|(_, path)| path).collect(),
+            false => targets.clone(),
+        };
+        let runs: Vec<rum::json::Value> = sockets.iter().filter_map(|path| inspect::query(path).ok()).collect();
+        let mut screen = inspect::table(&runs);
+        if let [run] = &runs[..] {
+            screen += &format!("\n{}\n", run.get("output").and_then(rum::json::Value::as_str).unwrap_or(""));
+        }
+        if tty {
+            print!("\x1b[H\x1b[2J");
+        }
+        print!("{screen}");
+        std::io::stdout().flush().unwrap_or_else(|e| fail(&format!("cannot write output: {e}")));
+        if !targets.is_empty() && runs.is_empty() {
+            fail("the runs have ended");
+        }
+        std::thread::sleep(std::time::Duration::from_secs_f64(interval));
+    }
+}
+
+/// The socket of a run given as a process id or a socket path.
+fn inspected_socket(target: &str) -> std::path::PathBuf {
+    match target.parse() {
+        Ok(pid) => inspect::socket(pid),
+        Err(_) => target.into(),
+    }
+}
+
+/// `rum serve-rpc [--listen ADDR [--max-sessions N] [--idle-timeout SECS] [--snapshot-dir DIR]] [--snapshot-key FILE] [--metrics ADDR]`
+///
+/// Speaks JSON-RPC on stdio, or on every connection to ADDR (see `rpc`),
+/// as sessions that are limited, closed when idle and saved on shutdown
+/// (see `sessions`).
+fn serve_rpc(args: &[String]) {
+    let mut listen = None;
+    let mut limits = rum::sessions::Limits { snapshot_key: env_snapshot_key(), ..rum::sessions::Limits::default() };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => listen = Some(value(args.next(), arg)),
+            "--max-sessions" => limits.max_sessions = Some(number(args.next(), arg)),
+            "--idle-timeout" => limits.idle_timeout = Some(std::time::Duration::from_secs_f64(number(args.next(), arg))),
+            "--snapshot-dir" => limits.snapshot_dir = Some(value(args.next(), arg).into()),
+            "--snapshot-key" => limits.snapshot_key = Some(snapshot_key(value(args.next(), arg))),
+            "--metrics" => serve_metrics(value(args.next(), arg)),
+            other => usage(&format!("unknown option `{other}`")),
+        }
+    }
+    if listen.is_none() && (limits.max_sessions.is_some() || limits.idle_timeout.is_some() || limits.snapshot_dir.is_some()) {
+        usage("--max-sessions, --idle-timeout and --snapshot-dir need --listen");
+    }
+    if let Some(dir) = &limits.snapshot_dir {
+        std::fs::create_dir_all(dir).unwrap_or_else(|e| fail(&format!("{}: {e}", dir.display())));
+    }
+    let result = match listen {
+        Some(addr) => {
+            let manager = rum::sessions::Manager::new(limits);
+            rum::sessions::shutdown_on_signals(&manager);
+            rpc::listen(addr, &manager)
+        }
+        None => rpc::serve(std::io::stdin().lock(), std::io::stdout().lock(), limits.snapshot_key),
+    };
+    result.unwrap_or_else(|e| fail(&e.to_string()));
+}
+
+/// `rum serve-grpc [--listen ADDR] [--max-instructions N] [--max-memory BYTES] [--timeout SECS] [--metrics ADDR]`
+///
+/// The limits apply to each session; clients may only tighten them.
+#[cfg(feature = "grpc")]
+fn serve_grpc(args: &[String]) {
+    let mut listen = "127.0.0.1:50051";
+    let mut limits = rum::grpc::Limits::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => listen = value(args.next(), arg),
+            "--max-instructions" => limits.max_instructions = Some(number(args.next(), arg)),
+            "--max-memory" => limits.max_memory_bytes = Some(number(args.next(), arg)),
+            "--timeout" => limits.timeout = Some(std::time::Duration::from_secs_f64(number(args.next(), arg))),
+            "--metrics" => serve_metrics(value(args.next(), arg)),
+            other => usage(&format!("unknown option `{other}`")),
+        }
+    }
+    let addr = listen.parse().unwrap_or_else(|_| usage(&format!("bad address `{listen}`")));
+    rum::grpc::serve(addr, limits).unwrap_or_else(|e| fail(&e.to_string()));
+}
+
+#[cfg(not(feature = "grpc"))]
+fn serve_grpc(_: &[String]) {
+    fail("this rum was built without the `grpc` feature")
+}
+
+/// `rum serve-http [--listen ADDR] [--max-instructions N] [--max-memory BYTES] [--timeout SECS] [--max-program BYTES]`
+///
+/// Memory is limited to 256 MiB and time to 10 seconds per request unless told otherwise.
+/// Metrics are served on the same address, at `/metrics`.
+fn serve_http(args: &[String]) {
+    let mut listen = "127.0.0.1:8080";
+    let mut limits = http::Limits::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => listen = value(args.next(), arg),
+            "--max-instructions" => limits.max_instructions = Some(number(args.next(), arg)),
+            "--max-memory" => limits.max_memory_bytes = Some(number(args.next(), arg)),
+            "--timeout" => limits.timeout = Some(std::time::Duration::from_secs_f64(number(args.next(), arg))),
+            "--max-program" => limits.max_program_bytes = number(args.next(), arg),
+            other => usage(&format!("unknown option `{other}`")),
+        }
+    }
+    http::serve(listen, limits).unwrap_or_else(|e| fail(&e.to_string()));
+}
+
+/// Starts `GET /metrics` on `addr` alongside a server.
+fn serve_metrics(addr: &str) {
+    metrics::serve(addr).unwrap_or_else(|e| fail(&format!("metrics on {addr}: {e}")));
+}
+
+fn value<'a>(arg: Option<&'a String>, flag: &str) -> &'a str {
+    arg.map(String::as_str).unwrap_or_else(|| usage(&format!("{flag} needs a value")))
+}
+
+fn number<T: std::str::FromStr>(arg: Option<&String>, flag: &str) -> T {
+    value(arg, flag).parse().unwrap_or_else(|_| usage(&format!("{flag} needs a number")))
+}
+
+fn report_format(arg: Option<&String>, flag: &str) -> report::Format {
+    report::Format::parse(value(arg, flag)).unwrap_or_else(|e| usage(&e))
+}
+
+fn usage(message: &str) -> ! {
+    eprintln!("rum: {message}");
+    eprintln!("{USAGE}");
+    exit(2)
+}
+
+/// The program at `path` (stdin without one), failing when it cannot be read.
+fn load_program(path: Option<&str>) -> Vec<u32> {
+    load::load(path).unwrap_or_else(|e| fail(&format!("{}: {e}", path.unwrap_or("<stdin>"))))
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("rum: {message}");
+    exit(1)
 }
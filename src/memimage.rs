@@ -0,0 +1,104 @@
+use std::path::{Path, PathBuf};
+
+use crate::state::UniversalMachine;
+
+/// Encoding of a dumped segment file.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Format {
+    /// Big-endian words, the same layout as a `.um` program.
+    Bin,
+    /// One word per line as eight hex digits.
+    Hex,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Bin => "bin",
+            Format::Hex => "hex",
+        }
+    }
+
+    /// Given a file path, guess its format from the extension.
+    pub fn of(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "bin" | "um" => Some(Format::Bin),
+            "hex" => Some(Format::Hex),
+            _ => None,
+        }
+    }
+}
+
+/// Encodes `words` as the contents of a segment file.
+pub fn encode(words: &[u32], format: Format) -> Vec<u8> {
+    match format {
+        Format::Bin => words.iter().flat_map(|w| w.to_be_bytes()).collect(),
+        Format::Hex => words.iter().map(|w| format!("{w:08x}\n")).collect::<String>().into_bytes(),
+    }
+}
+
+/// Decodes a segment file produced by `encode` (or by any other tool).
+pub fn decode(bytes: &[u8], format: Format) -> Result<Vec<u32>, String> {
+    match format {
+        Format::Bin if !bytes.len().is_multiple_of(4) => Err(format!("{} bytes is not a whole number of words", bytes.len())),
+        Format::Bin => Ok(crate::load::words(bytes)),
+        Format::Hex => {
+            let text = std::str::from_utf8(bytes).map_err(|_| "hex dump is not text".to_string())?;
+            text.split_whitespace()
+                .map(|w| u32::from_str_radix(w.trim_start_matches("0x"), 16).map_err(|_| format!("bad hex word `{w}`")))
+                .collect()
+        }
+    }
+}
+
+/// Name of the file segment `index` is dumped to, e.g. `seg-3.hex`.
+pub fn file_name(index: usize, format: Format) -> String {
+    format!("seg-{index}.{}", format.extension())
+}
+
+/// Given a path like `dir/seg-3.hex`, return the segment index it names.
+pub fn segment_index(path: &Path) -> Option<usize> {
+    path.file_stem()?.to_str()?.strip_prefix("seg-")?.parse().ok()
+}
+
+/// Writes segment `only` (or every mapped segment) of `state` into `dir`,
+/// returning the files written.
+pub fn export(state: &UniversalMachine, only: Option<usize>, dir: &Path, format: Format) -> Result<Vec<PathBuf>, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    let free = state.free_segments();
+    let indices: Vec<usize> = match only {
+        Some(index) if index < state.mapped_memory.len() && !free.contains(&(index as u32)) => vec![index],
+        Some(index) => return Err(format!("segment {index} is not mapped")),
+        None => (0..state.mapped_memory.len()).filter(|&i| !free.contains(&(i as u32))).collect(),
+    };
+    let mut written = Vec::new();
+    for index in indices {
+        let path = dir.join(file_name(index, format));
+        std::fs::write(&path, encode(&state.mapped_memory[index], format)).map_err(|e| format!("{}: {e}", path.display()))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Builds a fresh machine whose segments are loaded from `files`, each named
+/// `seg-<index>.<bin|hex>`. Indices not provided are left unmapped, and the
+/// machine starts at `program_counter` with zeroed registers.
+pub fn import(files: &[PathBuf], program_counter: usize) -> Result<UniversalMachine, String> {
+    let mut segments: Vec<Option<Vec<u32>>> = Vec::new();
+    for path in files {
+        let index = segment_index(path).ok_or_else(|| format!("{}: expected a name like seg-<index>.hex", path.display()))?;
+        let format = Format::of(path).ok_or_else(|| format!("{}: expected a .bin or .hex file", path.display()))?;
+        let bytes = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let words = decode(&bytes, format).map_err(|e| format!("{}: {e}", path.display()))?;
+        if segments.len() <= index {
+            segments.resize(index + 1, None);
+        }
+        segments[index] = Some(words);
+    }
+    if segments.first().is_none_or(Option::is_none) {
+        return Err("segment 0 (the program) is required".to_string());
+    }
+    let free: Vec<u32> = (0..segments.len()).filter(|&i| segments[i].is_none()).map(|i| i as u32).rev().collect();
+    let memory = segments.into_iter().map(Option::unwrap_or_default).collect();
+    Ok(UniversalMachine::from_parts([0; 8], memory, free, program_counter, Vec::new()))
+}
@@ -0,0 +1,199 @@
+//! Memory behaviour of a UM program by allocation site, for `rum profile
+//! --memory`.
+//!
+//! Every Map Segment is charged to its PC, the allocation site: how many
+//! segments it mapped and of what sizes, how many of them reused a freed
+//! identifier (a free-list hit), and how long, in instructions, the ones
+//! since unmapped lived. Across the whole run it tracks the peak of live
+//! bytes in mapped segments (segment 0 aside). Sites are keyed by address
+//! in segment 0 at the time, so after Load Program replaces segment 0 the
+//! same address can stand for different code.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::instruction::Instruction;
+use crate::report::Table;
+use crate::rumdis::describe;
+use crate::state::UniversalMachine;
+
+/// What one allocation site did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Site {
+    pub pc: usize,
+    /// The Map Segment instruction at `pc`.
+    pub word: u32,
+    pub maps: u64,
+    /// Maps that reused an unmapped identifier.
+    pub reused: u64,
+    /// Words requested in all, and the smallest and largest request.
+    pub words: u64,
+    pub min_words: u64,
+    pub max_words: u64,
+    /// Segments from here since unmapped, and their lifetimes added up.
+    pub freed: u64,
+    pub lifetimes: u64,
+}
+
+impl Site {
+    /// Mean lifetime in instructions of the segments freed so far.
+    pub fn mean_lifetime(&self) -> Option<u64> {
+        (self.freed > 0).then(|| self.lifetimes / self.freed)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Live {
+    site: usize,
+    words: u64,
+    mapped_at: u64,
+}
+
+/// Allocation statistics of one run.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryProfile {
+    sites: HashMap<usize, Site>,
+    live: HashMap<u32, Live>,
+    /// The Map Segment about to run: site, words and whether it reuses an identifier.
+    pending: Option<(usize, u64, bool)>,
+    executed: u64,
+    live_bytes: u64,
+    pub peak_bytes: u64,
+    /// Instructions executed when the peak was reached.
+    pub peak_at: u64,
+    pub unmaps: u64,
+}
+
+impl MemoryProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notes the instruction `inst` about to run from `pc` in `state`.
+    pub fn before(&mut self, pc: usize, inst: u32, state: &UniversalMachine) {
+        self.executed += 1;
+        match Instruction::decode(inst) {
+            Instruction::Map { c, .. } => {
+                let words = state.register(c) as u64;
+                self.pending = Some((pc, words, !state.free_segments().is_empty()));
+                let site = self.sites.entry(pc).or_insert_with(|| Site { pc, word: inst, min_words: u64::MAX, ..Site::default() });
+                site.maps += 1;
+                site.words += words;
+                site.min_words = site.min_words.min(words);
+                site.max_words = site.max_words.max(words);
+            }
+            Instruction::Unmap { c } => {
+                self.unmaps += 1;
+                if let Some(live) = self.live.remove(&state.register(c)) {
+                    self.live_bytes -= live.words * 4;
+                    let site = self.sites.get_mut(&live.site).unwrap();
+                    site.freed += 1;
+                    site.lifetimes += self.executed - live.mapped_at;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Completes a Map Segment noted by `before` once it has run, reading
+    /// the identifier it got from `state`.
+    pub fn after(&mut self, inst: u32, state: &UniversalMachine) {
+        let (Some((site, words, reused)), Instruction::Map { b, .. }) = (self.pending.take(), Instruction::decode(inst)) else {
+            return;
+        };
+        if reused {
+            self.sites.get_mut(&site).unwrap().reused += 1;
+        }
+        self.live.insert(state.register(b), Live { site, words, mapped_at: self.executed });
+        self.live_bytes += words * 4;
+        if self.live_bytes > self.peak_bytes {
+            self.peak_bytes = self.live_bytes;
+            self.peak_at = self.executed;
+        }
+    }
+
+    /// Segments mapped during the run and still mapped.
+    pub fn live_segments(&self) -> usize {
+        self.live.len()
+    }
+
+    pub fn maps(&self) -> u64 {
+        self.sites.values().map(|site| site.maps).sum()
+    }
+
+    /// The sites, most maps first (lower addresses first among equals).
+    pub fn sites(&self) -> Vec<&Site> {
+        let mut sites: Vec<&Site> = self.sites.values().collect();
+        sites.sort_by(|a, b| b.maps.cmp(&a.maps).then(a.pc.cmp(&b.pc)));
+        sites
+    }
+
+    /// The `top` busiest allocation sites as a table, followed by a summary.
+    pub fn report(&self, top: usize) -> String {
+        let mut text = format!(
+            "{:>10} {:>7} {:>10} {:>9} {:>9} {:>10} {:>8}  instruction\n",
+            "maps", "reused", "mean words", "max words", "freed", "mean life", "address"
+        );
+        for site in self.sites().into_iter().take(top) {
+            let life = site.mean_lifetime().map_or("-".to_string(), |life| life.to_string());
+            let _ = writeln!(
+                text,
+                "{:>10} {:>6.1}% {:>10} {:>9} {:>9} {life:>10} {:>8}  {}",
+                site.maps,
+                site.reused as f64 * 100.0 / site.maps as f64,
+                site.words / site.maps,
+                site.max_words,
+                site.freed,
+                site.pc,
+                describe(site.word)
+            );
+        }
+        let maps = self.maps();
+        let reused: u64 = self.sites.values().map(|site| site.reused).sum();
+        let _ = writeln!(
+            text,
+            "{maps} maps from {} sites, {} unmaps; free list hit rate {:.1}%; peak {} live bytes after {} instructions; {} segments still mapped",
+            self.sites.len(),
+            self.unmaps,
+            reused as f64 * 100.0 / maps.max(1) as f64,
+            self.peak_bytes,
+            self.peak_at,
+            self.live_segments()
+        );
+        text
+    }
+
+    /// The report as tables for `--report-format` (see `report`):
+    /// `allocation_sites`, the `top` busiest sites (all of them if `None`),
+    /// and `memory`, one row of totals.
+    pub fn tables(&self, top: Option<usize>) -> Vec<Table> {
+        let mut sites = Table::new(
+            "allocation_sites",
+            &["address", "maps", "reused", "words", "min_words", "max_words", "freed", "mean_lifetime", "instruction"],
+        );
+        for site in self.sites().into_iter().take(top.unwrap_or(usize::MAX)) {
+            sites.row(vec![
+                site.pc.into(),
+                site.maps.into(),
+                site.reused.into(),
+                site.words.into(),
+                site.min_words.into(),
+                site.max_words.into(),
+                site.freed.into(),
+                site.mean_lifetime().into(),
+                describe(site.word).into(),
+            ]);
+        }
+        let mut summary = Table::new("memory", &["maps", "sites", "unmaps", "reused", "peak_bytes", "peak_at", "live_segments"]);
+        summary.row(vec![
+            self.maps().into(),
+            self.sites.len().into(),
+            self.unmaps.into(),
+            self.sites.values().map(|site| site.reused).sum::<u64>().into(),
+            self.peak_bytes.into(),
+            self.peak_at.into(),
+            self.live_segments().into(),
+        ]);
+        vec![sites, summary]
+    }
+}
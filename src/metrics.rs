@@ -0,0 +1,224 @@
+//! Prometheus metrics for the server modes.
+//!
+//! Each server opens a `Session` per machine it runs and keeps it up to
+//! date as the machine executes; `render` turns the registry into the text
+//! exposition format served at `/metrics`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::cache::ProgramCache;
+
+#[derive(Debug)]
+struct Live {
+    server: &'static str,
+    started: Instant,
+    executed: u64,
+    mapped_bytes: u64,
+    /// When the machine started waiting for input, if it is waiting.
+    waiting: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_id: u64,
+    live: HashMap<u64, Live>,
+    /// Per server: sessions opened and instructions executed by closed sessions.
+    opened: BTreeMap<&'static str, u64>,
+    finished_instructions: BTreeMap<&'static str, u64>,
+    errors: BTreeMap<(&'static str, &'static str), u64>,
+}
+
+/// Counters and live sessions of every server in the process.
+#[derive(Debug, Default)]
+pub struct Registry {
+    inner: Mutex<Inner>,
+}
+
+impl Registry {
+    /// The registry shared by the servers in this process.
+    pub fn global() -> &'static Registry {
+        static GLOBAL: OnceLock<Registry> = OnceLock::new();
+        GLOBAL.get_or_init(Registry::default)
+    }
+
+    /// Starts tracking a machine run by `server` (`http`, `rpc`, `grpc`).
+    pub fn open(&'static self, server: &'static str) -> Session {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let id = inner.next_id;
+        inner.live.insert(id, Live { server, started: Instant::now(), executed: 0, mapped_bytes: 0, waiting: None });
+        *inner.opened.entry(server).or_default() += 1;
+        Session { registry: Some(self), id, server }
+    }
+
+    /// Counts an error of `kind` (e.g. `fault`, `timeout`, `bad_request`) in `server`.
+    pub fn error(&self, server: &'static str, kind: &'static str) {
+        *self.inner.lock().unwrap().errors.entry((server, kind)).or_default() += 1;
+    }
+
+    /// The registry in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        let mut active: BTreeMap<&str, u64> = inner.opened.keys().map(|&server| (server, 0)).collect();
+        let mut waiting = active.clone();
+        let mut instructions = inner.finished_instructions.clone();
+        for live in inner.live.values() {
+            *active.entry(live.server).or_default() += 1;
+            *waiting.entry(live.server).or_default() += live.waiting.is_some() as u64;
+            *instructions.entry(live.server).or_default() += live.executed;
+        }
+        let per_server = [
+            ("rum_sessions_active", "gauge", "Machines currently open.", &active),
+            ("rum_sessions_waiting", "gauge", "Open machines blocked waiting for input.", &waiting),
+            ("rum_sessions_total", "counter", "Machines opened since the server started.", &inner.opened),
+            ("rum_instructions_total", "counter", "Instructions executed by all machines.", &instructions),
+        ];
+        for (name, kind, help, values) in per_server {
+            family(&mut out, name, kind, help);
+            for (server, n) in values {
+                let _ = writeln!(out, "{name}{{server=\"{server}\"}} {n}");
+            }
+        }
+
+        family(&mut out, "rum_errors_total", "counter", "Runs that ended in an error, by kind.");
+        for ((server, kind), n) in &inner.errors {
+            let _ = writeln!(out, "rum_errors_total{{server=\"{server}\",kind=\"{kind}\"}} {n}");
+        }
+
+        let cache = ProgramCache::global().stats();
+        family(&mut out, "rum_program_cache_hits_total", "counter", "Programs loaded from the program cache without decoding.");
+        let _ = writeln!(out, "rum_program_cache_hits_total {}", cache.hits);
+        family(&mut out, "rum_program_cache_misses_total", "counter", "Program loads that missed the program cache and decoded the program.");
+        let _ = writeln!(out, "rum_program_cache_misses_total {}", cache.misses);
+        family(&mut out, "rum_program_cache_programs", "gauge", "Programs held in the program cache.");
+        let _ = writeln!(out, "rum_program_cache_programs {}", cache.programs);
+
+        let mut sessions: Vec<(&u64, &Live)> = inner.live.iter().collect();
+        sessions.sort_by_key(|&(id, _)| id);
+        let per_session: [(&str, &str, Gauge); 4] = [
+            ("rum_session_instructions", "Instructions executed by an open machine.", |live| live.executed as f64),
+            ("rum_session_mips", "Millions of instructions per second since the machine was opened.", |live| {
+                live.executed as f64 / live.started.elapsed().as_secs_f64().max(1e-9) / 1e6
+            }),
+            ("rum_session_mapped_bytes", "Bytes held by the mapped segments of an open machine.", |live| live.mapped_bytes as f64),
+            ("rum_session_waiting_seconds", "How long an open machine has been blocked waiting for input, 0 while it executes.", |live| {
+                live.waiting.map_or(0.0, |since| since.elapsed().as_secs_f64())
+            }),
+        ];
+        for (name, help, value) in per_session {
+            family(&mut out, name, "gauge", help);
+            for (id, live) in &sessions {
+                let _ = writeln!(out, "{name}{{server=\"{}\",session=\"{id}\"}} {}", live.server, value(live));
+            }
+        }
+        out
+    }
+}
+
+/// Reads one per-session gauge.
+type Gauge = fn(&Live) -> f64;
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+}
+
+/// A machine tracked by a `Registry`, removed from it when dropped.
+#[derive(Debug)]
+pub struct Session {
+    registry: Option<&'static Registry>,
+    id: u64,
+    server: &'static str,
+}
+
+impl Session {
+    /// A session that records nothing, for runs outside a server.
+    pub fn detached() -> Self {
+        Session { registry: None, id: 0, server: "" }
+    }
+
+    /// Records the machine's progress so far.
+    pub fn update(&self, executed: u64, mapped_bytes: usize) {
+        if let Some(registry) = self.registry {
+            if let Some(live) = registry.inner.lock().unwrap().live.get_mut(&self.id) {
+                live.executed = executed;
+                live.mapped_bytes = mapped_bytes as u64;
+            }
+        }
+    }
+
+    /// Records whether the machine is blocked waiting for input.
+    pub fn waiting(&self, waiting: bool) {
+        if let Some(registry) = self.registry {
+            if let Some(live) = registry.inner.lock().unwrap().live.get_mut(&self.id) {
+                live.waiting = match (waiting, live.waiting) {
+                    (true, since) => since.or_else(|| Some(Instant::now())),
+                    (false, _) => None,
+                };
+            }
+        }
+    }
+
+    /// Counts an error of `kind` against this session's server.
+    pub fn error(&self, kind: &'static str) {
+        if let Some(registry) = self.registry {
+            registry.error(self.server, kind);
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Some(registry) = self.registry {
+            let mut inner = registry.inner.lock().unwrap();
+            if let Some(live) = inner.live.remove(&self.id) {
+                *inner.finished_instructions.entry(live.server).or_default() += live.executed;
+            }
+        }
+    }
+}
+
+/// Serves `GET /metrics` for the global registry on `addr`, on a background thread.
+pub fn serve(addr: impl ToSocketAddrs) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("rum: serving metrics on http://{}/metrics", listener.local_addr()?);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+            let Ok(reader) = stream.try_clone() else { continue };
+            let mut request = String::new();
+            let mut reader = BufReader::new(reader);
+            if reader.read_line(&mut request).is_err() {
+                continue;
+            }
+            // Drain the headers; the request line is all that matters.
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+                line.clear();
+            }
+            let (status, body) = match request.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+                ["GET", "/metrics"] => ("200 OK", Registry::global().render()),
+                _ => ("404 Not Found", "only GET /metrics is served here\n".to_string()),
+            };
+            let _ = write!(&stream, "{}", response(status, CONTENT_TYPE, &body));
+        }
+    });
+    Ok(())
+}
+
+/// Content type of the text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// A complete HTTP/1.1 response that closes the connection.
+pub(crate) fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
@@ -0,0 +1,149 @@
+//! Input minimization for `rum minimize`: the smallest input that still
+//! makes a program do what a larger one did.
+//!
+//! The behaviour kept is either the program's fault, which only counts as
+//! reproduced by a fault at the same address with the same message so
+//! the search doesn't wander onto a different bug, or output containing
+//! some text, however the run ends. Runs are in-process and
+//! deterministic given their input.
+//!
+//! The input is first cut to the bytes the program actually read, and is
+//! cut again after every reduction that works. Then delta debugging
+//! (ddmin) removes ever smaller chunks: halves, then quarters, and so on
+//! down to single bytes, until no single byte can go. Reduced inputs can
+//! send a program into a loop the original never reached, so each run gets
+//! ten times the instructions the original took (at least 1000).
+
+use std::collections::HashSet;
+
+use crate::embed::{Machine, Status};
+
+/// Instructions the original run may take unless the caller says otherwise.
+pub const FUEL: u64 = 1_000_000_000;
+
+/// What the minimized input has to keep doing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// Fault where the original input faults.
+    Fault,
+    /// Write output containing these bytes.
+    OutputContains(Vec<u8>),
+}
+
+/// How one run ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Halted,
+    Fault { pc: usize, message: String },
+    /// The run used up its instructions.
+    Limit,
+}
+
+/// One run of the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    pub outcome: Outcome,
+    pub output: Vec<u8>,
+    /// Input bytes the program read.
+    pub consumed: usize,
+    pub executed: u64,
+}
+
+/// Runs `program` on `input` for at most `fuel` instructions.
+pub fn run(program: &[u32], input: &[u8], fuel: u64) -> Run {
+    let mut machine = Machine::new(program.to_vec());
+    let mut consumed = 0;
+    let mut output = Vec::new();
+    let mut read = || {
+        let byte = input.get(consumed).copied();
+        consumed += usize::from(byte.is_some());
+        byte
+    };
+    let outcome = match machine.run(Some(fuel), &mut read, &mut |byte| output.push(byte)) {
+        Ok(Status::Halted) => Outcome::Halted,
+        Ok(Status::Running) => Outcome::Limit,
+        Err(e) => Outcome::Fault { pc: e.pc(), message: e.message() },
+    };
+    Run { outcome, output, consumed, executed: machine.executed() }
+}
+
+/// The result of minimizing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Minimized {
+    pub input: Vec<u8>,
+    /// The run of the minimized input.
+    pub run: Run,
+    /// Runs it took, the original's included.
+    pub runs: u64,
+}
+
+/// Minimizes `input` for `program` keeping `target`, giving the original
+/// run at most `fuel` instructions. Fails if the original input doesn't
+/// hit the target.
+pub fn minimize(program: &[u32], input: &[u8], target: &Target, fuel: u64) -> Result<Minimized, String> {
+    let original = run(program, input, fuel);
+    match target {
+        Target::Fault if !matches!(original.outcome, Outcome::Fault { .. }) => {
+            return Err(format!("the program doesn't fault on this input ({})", describe(&original.outcome)))
+        }
+        Target::OutputContains(text) if !contains(&original.output, text) => {
+            return Err(format!("the program's output doesn't contain the target ({})", describe(&original.outcome)))
+        }
+        _ => {}
+    }
+    let outcome = original.outcome.clone();
+    let hits = |run: &Run| match target {
+        Target::Fault => run.outcome == outcome,
+        Target::OutputContains(text) => contains(&run.output, text),
+    };
+
+    let fuel = original.executed.saturating_mul(10).max(1000);
+    let mut runs = 1;
+    // Inputs that didn't reproduce it, which ddmin can come back to.
+    let mut missed = HashSet::new();
+    let mut best = (input[..original.consumed].to_vec(), original);
+    let mut chunks = 2;
+    while !best.0.is_empty() {
+        let len = best.0.len();
+        chunks = chunks.min(len);
+        let size = len.div_ceil(chunks);
+        let mut reduced = false;
+        for start in (0..len).step_by(size) {
+            let candidate: Vec<u8> = best.0[..start].iter().chain(&best.0[(start + size).min(len)..]).copied().collect();
+            if missed.contains(&candidate) {
+                continue;
+            }
+            runs += 1;
+            let attempt = run(program, &candidate, fuel);
+            if !hits(&attempt) {
+                missed.insert(candidate);
+            } else {
+                best = (candidate[..attempt.consumed].to_vec(), attempt);
+                chunks = (chunks - 1).max(2);
+                reduced = true;
+                break;
+            }
+        }
+        if !reduced {
+            if chunks == len {
+                break;
+            }
+            chunks = (chunks * 2).min(len);
+        }
+    }
+    let (input, run) = best;
+    Ok(Minimized { input, run, runs })
+}
+
+fn contains(output: &[u8], text: &[u8]) -> bool {
+    text.is_empty() || output.windows(text.len()).any(|window| window == text)
+}
+
+/// How a run ended, for messages.
+pub fn describe(outcome: &Outcome) -> String {
+    match outcome {
+        Outcome::Halted => "it halts".to_string(),
+        Outcome::Fault { pc, message } => format!("it faults at pc {pc}: {message}"),
+        Outcome::Limit => "it runs out of instructions".to_string(),
+    }
+}
@@ -0,0 +1,230 @@
+//! Mutation testing for `rum mutate`: how much of a UM program do its test
+//! cases actually check?
+//!
+//! Each mutant changes one instruction of segment 0 that the cases execute:
+//!
+//! - opcode: a three-register instruction (Conditional Move through NAND)
+//!   becomes each of the others, operands kept;
+//! - registers: two distinct operand registers of an instruction swap;
+//! - constant: a Load Value's constant becomes one more, one less, or 0.
+//!
+//! A mutant is killed when some case no longer produces its expected
+//! output: different output, a fault, or not halting within ten times the
+//! instructions the original needed. Survivors are changes no case
+//! noticed, i.e. behaviour the cases don't pin down. Words the cases never
+//! execute (before any Load Program replaces segment 0) are not mutated
+//! and are reported as uncovered instead.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+
+use crate::barrier::{code_write, CodeWrite};
+use crate::embed::{Machine, Status};
+use crate::error::MachineError;
+use crate::instruction::Instruction;
+use crate::report::Table;
+use crate::rumdis::describe;
+
+/// Instructions the unmutated program may take on one case.
+pub const FUEL: u64 = 1_000_000_000;
+
+/// A test case: input and the output it must produce.
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub name: String,
+    pub input: Vec<u8>,
+    pub expected: Vec<u8>,
+}
+
+/// Every `NAME.expected` in `dir`, with `NAME.in` as input (none if
+/// missing), in name order.
+pub fn read_cases(dir: impl AsRef<Path>) -> std::io::Result<Vec<Case>> {
+    let mut cases = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "expected") {
+            let input = match std::fs::read(path.with_extension("in")) {
+                Ok(bytes) => bytes,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e),
+            };
+            let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            cases.push(Case { name, input, expected: std::fs::read(&path)? });
+        }
+    }
+    cases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(cases)
+}
+
+/// The kind of change a mutant makes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Opcode,
+    Registers,
+    Constant,
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Operator::Opcode => "opcode",
+            Operator::Registers => "registers",
+            Operator::Constant => "constant",
+        })
+    }
+}
+
+/// One changed instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mutant {
+    pub pc: usize,
+    pub original: u32,
+    pub word: u32,
+    pub operator: Operator,
+}
+
+impl fmt::Display for Mutant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pc {}: {} -> {} ({})", self.pc, describe(self.original), describe(self.word), self.operator)
+    }
+}
+
+/// The mutants of the instruction `word` at `pc`.
+pub fn mutants_of(pc: usize, word: u32) -> Vec<Mutant> {
+    let mutant = |operator, instruction: Instruction| Mutant { pc, original: word, word: instruction.encode(), operator };
+    let mut mutants = Vec::new();
+    let instruction = Instruction::decode(word);
+    let three = match instruction {
+        Instruction::CMov { a, b, c }
+        | Instruction::Load { a, b, c }
+        | Instruction::Store { a, b, c }
+        | Instruction::Add { a, b, c }
+        | Instruction::Mul { a, b, c }
+        | Instruction::Div { a, b, c }
+        | Instruction::Nand { a, b, c } => Some((a, b, c)),
+        _ => None,
+    };
+    if let Some((a, b, c)) = three {
+        for code in 0..7u32 {
+            let other = Instruction::decode(code << 28 | a << 6 | b << 3 | c);
+            if other != instruction {
+                mutants.push(mutant(Operator::Opcode, other));
+            }
+        }
+        for (x, y, z) in [(b, a, c), (c, b, a), (a, c, b)] {
+            if (x, y, z) != (a, b, c) {
+                let code = word >> 28;
+                mutants.push(mutant(Operator::Registers, Instruction::decode(code << 28 | x << 6 | y << 3 | z)));
+            }
+        }
+    }
+    match instruction {
+        Instruction::Map { b, c } if b != c => mutants.push(mutant(Operator::Registers, Instruction::Map { b: c, c: b })),
+        Instruction::LoadProgram { b, c } if b != c => mutants.push(mutant(Operator::Registers, Instruction::LoadProgram { b: c, c: b })),
+        Instruction::Orthography { a, value } => {
+            let mask = (1 << 25) - 1;
+            let mut values = vec![(value + 1) & mask, value.wrapping_sub(1) & mask];
+            if value != 0 {
+                values.push(0);
+            }
+            for changed in values {
+                mutants.push(mutant(Operator::Constant, Instruction::Orthography { a, value: changed }));
+            }
+        }
+        _ => {}
+    }
+    mutants
+}
+
+/// The result of a mutation run.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub killed: usize,
+    pub survivors: Vec<Mutant>,
+    /// Words of segment 0 no case executed.
+    pub uncovered: usize,
+}
+
+impl Report {
+    pub fn total(&self) -> usize {
+        self.killed + self.survivors.len()
+    }
+
+    /// Killed mutants as a percentage of all of them (100 when there are none).
+    pub fn score(&self) -> f64 {
+        match self.total() {
+            0 => 100.0,
+            total => self.killed as f64 * 100.0 / total as f64,
+        }
+    }
+
+    /// The result as tables for `--report-format` (see `report`):
+    /// `survivors`, one row per surviving mutant, and `summary`, one row of
+    /// totals over `cases` cases.
+    pub fn tables(&self, cases: usize) -> Vec<Table> {
+        let mut survivors = Table::new("survivors", &["address", "operator", "original", "mutant"]);
+        for mutant in &self.survivors {
+            survivors.row(vec![mutant.pc.into(), mutant.operator.to_string().into(), describe(mutant.original).into(), describe(mutant.word).into()]);
+        }
+        let mut summary = Table::new("summary", &["mutants", "cases", "killed", "survived", "score_percent", "uncovered"]);
+        summary.row(vec![self.total().into(), cases.into(), self.killed.into(), self.survivors.len().into(), self.score().into(), self.uncovered.into()]);
+        vec![survivors, summary]
+    }
+}
+
+/// How a case ran: its output if it halted, and the instructions it took.
+/// With `covered`, adds the PCs it executed while segment 0 was still the
+/// original program.
+fn run_case(program: Vec<u32>, case: &Case, fuel: u64, mut covered: Option<&mut BTreeSet<usize>>) -> (Option<Vec<u8>>, u64) {
+    let mut machine = Machine::new(program);
+    let mut input = case.input.iter().copied();
+    let mut output = Vec::new();
+    let mut finished = || {
+        while machine.executed() < fuel {
+            if let Some(pcs) = covered.as_deref_mut() {
+                let state = &machine.state;
+                pcs.insert(state.program_counter);
+                let word = state.mapped_memory[0].get(state.program_counter).copied();
+                if matches!(word.and_then(|word| code_write(word, state)), Some(CodeWrite::Replace { .. })) {
+                    covered = None;
+                }
+            }
+            if machine.step(&mut || input.next(), &mut |byte| output.push(byte))? == Status::Halted {
+                return Ok(true);
+            }
+        }
+        Ok::<_, MachineError>(false)
+    };
+    let halted = matches!(finished(), Ok(true));
+    (halted.then_some(output), machine.executed())
+}
+
+/// Checks that `program` passes every case, then runs every mutant of the
+/// instructions they execute against them. `progress` is called with each
+/// mutant's index and the number of mutants as it starts.
+pub fn run(program: &[u32], cases: &[Case], progress: &mut dyn FnMut(usize, usize)) -> Result<Report, String> {
+    let mut covered = BTreeSet::new();
+    let mut fuel = Vec::new();
+    for case in cases {
+        match run_case(program.to_vec(), case, FUEL, Some(&mut covered)) {
+            (Some(output), executed) if output == case.expected => fuel.push(executed.saturating_mul(10).saturating_add(1000)),
+            (Some(output), _) => return Err(format!("{}: the unmutated program fails: {}", case.name, crate::check::diff(&case.expected, &output))),
+            (None, executed) => return Err(format!("{}: the unmutated program faults or does not halt ({executed} instructions)", case.name)),
+        }
+    }
+    let covered: Vec<usize> = covered.into_iter().filter(|&pc| pc < program.len()).collect();
+    let mutants: Vec<Mutant> = covered.iter().flat_map(|&pc| mutants_of(pc, program[pc])).collect();
+    let mut report = Report { killed: 0, survivors: Vec::new(), uncovered: program.len() - covered.len() };
+    for (n, mutant) in mutants.iter().enumerate() {
+        progress(n, mutants.len());
+        let mut mutated = program.to_vec();
+        mutated[mutant.pc] = mutant.word;
+        let killed = cases.iter().zip(&fuel).any(|(case, &fuel)| run_case(mutated.clone(), case, fuel, None).0.as_ref() != Some(&case.expected));
+        match killed {
+            true => report.killed += 1,
+            false => report.survivors.push(*mutant),
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,116 @@
+//! N-API addon for Node.js, built with the `node` feature. `node/index.js`
+//! wraps it in an `EventEmitter` that runs the machine in slices of fuel,
+//! yielding to the event loop in between and emitting `output` events.
+
+use std::collections::VecDeque;
+
+use napi::bindgen_prelude::Buffer;
+use napi::{Error, Result};
+use napi_derive::napi;
+
+use crate::embed::{self, Status};
+
+/// What one `run` call did.
+#[napi(object)]
+pub struct Slice {
+    /// `"running"` (fuel ran out), `"input"` (waiting for `feed`) or `"halted"`.
+    pub status: String,
+    /// Bytes output during the slice.
+    pub output: Buffer,
+}
+
+#[napi]
+pub struct Machine {
+    machine: embed::Machine,
+    input: VecDeque<u8>,
+    input_closed: bool,
+    faulted: bool,
+}
+
+#[napi]
+impl Machine {
+    /// Given the contents of a `.um` file, create a machine ready to run it.
+    #[napi(constructor)]
+    pub fn new(program: Buffer) -> Result<Self> {
+        if !program.len().is_multiple_of(4) {
+            return Err(Error::from_reason("program length is not a whole number of words"));
+        }
+        let words = crate::load::words(&program);
+        Ok(Self { machine: embed::Machine::new(words), input: VecDeque::new(), input_closed: false, faulted: false })
+    }
+
+    /// Queues bytes for the program's Input instruction.
+    #[napi]
+    pub fn feed(&mut self, input: Buffer) {
+        self.input.extend(input.iter());
+    }
+
+    /// Once the queue is drained, the program reads end of input instead of waiting.
+    #[napi]
+    pub fn close_input(&mut self) {
+        self.input_closed = true;
+    }
+
+    /// Executes up to `fuel` instructions, stopping early on Halt or when
+    /// the program wants input that has not been fed yet.
+    #[napi]
+    pub fn run(&mut self, fuel: u32) -> Result<Slice> {
+        if self.faulted {
+            return Err(Error::from_reason("the machine faulted earlier"));
+        }
+        let mut output = Vec::new();
+        let mut status = self.machine.status();
+        let mut waiting = false;
+        for _ in 0..fuel {
+            if self.input.is_empty() && !self.input_closed && self.machine.wants_input() {
+                waiting = true;
+                break;
+            }
+            let queue = &mut self.input;
+            match self.machine.step(&mut || queue.pop_front(), &mut |byte| output.push(byte)) {
+                Ok(Status::Halted) => {
+                    status = Status::Halted;
+                    break;
+                }
+                Ok(Status::Running) => {}
+                Err(e) => {
+                    self.faulted = true;
+                    return Err(Error::from_reason(format!("program faulted after {} instructions: {e}", self.machine.executed())));
+                }
+            }
+        }
+        let status = match (status, waiting) {
+            (Status::Halted, _) => "halted",
+            (_, true) => "input",
+            _ => "running",
+        };
+        Ok(Slice { status: status.to_string(), output: output.into() })
+    }
+
+    #[napi(getter)]
+    pub fn halted(&self) -> bool {
+        self.machine.status() == Status::Halted
+    }
+
+    /// Instructions executed so far.
+    #[napi(getter)]
+    pub fn executed(&self) -> f64 {
+        self.machine.executed() as f64
+    }
+
+    #[napi(getter)]
+    pub fn pc(&self) -> u32 {
+        self.machine.state.program_counter as u32
+    }
+
+    #[napi(getter)]
+    pub fn registers(&self) -> Vec<u32> {
+        (0..8).map(|r| self.machine.state.register(r)).collect()
+    }
+
+    /// Words of segment `index` (empty if unmapped or unknown).
+    #[napi]
+    pub fn segment(&self, index: u32) -> Vec<u32> {
+        self.machine.state.mapped_memory.get(index as usize).cloned().unwrap_or_default()
+    }
+}
@@ -0,0 +1,235 @@
+//! An exhaustive semantic oracle for single instructions, for tests.
+//!
+//! `check` runs every instruction (all 14 opcodes, every combination of
+//! register fields, with the bits they don't use filled in) from every
+//! assignment of `VALUES` to the registers it names, over a tiny machine:
+//! the instruction at address 0 of segment 0, a 4-word segment 1 and a
+//! free segment 2. Each result is compared with a model written straight
+//! from the spec, one that decodes with its own shifts and masks and keeps
+//! segments in a map, so a bit-field or wrapping mistake in `rumdis` or
+//! `state` shows up as a `Mismatch` naming the instruction and registers.
+//!
+//! Where the spec says the machine may fail (a bad segment or offset,
+//! division by zero, Output above 255, unmapping segment 0 or an unmapped
+//! segment, opcodes 14 and 15) anything the interpreter does is accepted.
+//! The spec also leaves segment identifiers to the implementation, so Map
+//! Segment is only held to picking one that is neither 0 nor in use. Maps
+//! larger than `MAX_MAP` words are skipped rather than allocated.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::embed::{Machine, Status};
+use crate::rumdis::describe;
+use crate::state::UniversalMachine;
+
+/// Register values tried: small numbers (valid segments, offsets and
+/// bytes, and one just past them) and the extremes wrapping arithmetic
+/// gets wrong.
+pub const VALUES: [u32; 6] = [0, 1, 2, 4, 0x8000_0000, 0xffff_ffff];
+/// Load Value constants tried.
+pub const CONSTANTS: [u32; 4] = [0, 1, 0x155_5555, 0x1ff_ffff];
+/// The byte Input reads, when it doesn't see end of input.
+pub const INPUT: u8 = 0x5a;
+/// Largest Map Segment checked, in words.
+pub const MAX_MAP: u32 = 1 << 16;
+
+/// An instruction the interpreter got wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub word: u32,
+    /// Registers before it executed.
+    pub registers: [u32; 8],
+    pub reason: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers: Vec<String> = self.registers.iter().enumerate().map(|(r, value)| format!("r{r}={value:#x}")).collect();
+        write!(f, "{} ({:#010x}) with {}: {}", describe(self.word), self.word, registers.join(" "), self.reason)
+    }
+}
+
+/// Checks every instruction against the model over `values`, returning
+/// how many cases ran, or the first mismatch.
+pub fn check(values: &[u32]) -> Result<u64, Mismatch> {
+    let mut cases = 0;
+    for opcode in 0..13 {
+        for fields in 0..512_u32 {
+            let (a, b, c) = (fields >> 6, fields >> 3 & 7, fields & 7);
+            // Bits 9 to 27 are unused; fill them with a pattern varying by operands.
+            let word = opcode << 28 | (fields.wrapping_mul(0x9e37) << 9 & 0x0fff_fe00) | fields;
+            for &va in values {
+                for &vb in values {
+                    for &vc in values {
+                        // Sentinels no value collides with, so reading the wrong register shows.
+                        let mut registers: [u32; 8] = std::array::from_fn(|r| 0x5eed_0000 + r as u32);
+                        registers[a as usize] = va;
+                        registers[b as usize] = vb;
+                        registers[c as usize] = vc;
+                        for input in [Some(INPUT), None] {
+                            cases += u64::from(case(word, registers, input)?);
+                            if opcode != 11 {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    for a in 0..8 {
+        for &value in &CONSTANTS {
+            let registers = std::array::from_fn(|r| 0x5eed_0000 + r as u32);
+            cases += u64::from(case(13 << 28 | a << 25 | value, registers, None)?);
+        }
+    }
+    Ok(cases)
+}
+
+/// Runs `word` from `registers` on both; false if the case was skipped.
+fn case(word: u32, registers: [u32; 8], input: Option<u8>) -> Result<bool, Mismatch> {
+    let mismatch = |reason: String| Mismatch { word, registers, reason };
+    let segments = BTreeMap::from([(0, vec![word, 7 << 28]), (1, vec![0x0bad_f00d, 1, 2, 3])]);
+    if word >> 28 == 8 && registers[(word & 7) as usize] > MAX_MAP {
+        return Ok(false);
+    }
+
+    let memory = vec![segments[&0].clone(), segments[&1].clone(), Vec::new()];
+    let mut machine = Machine::from_state(UniversalMachine::from_parts(registers, memory, vec![2], 0, Vec::new()));
+    let mut output = None;
+    let status = machine.step(&mut || input, &mut |byte| output = Some(byte));
+
+    let mut model = Model { registers, segments, pc: 0 };
+    // The identifier rum picked, held to being free.
+    let fresh = machine.state.register(word >> 3 & 7);
+    let expected = model.step(word, input, fresh);
+    let status = match (expected, status) {
+        (Effect::Undefined, _) => return Ok(true),
+        (_, Err(_)) => return Err(mismatch("rum faulted on an instruction the spec defines".to_string())),
+        (expected, Ok(status)) => {
+            if let Effect::BadIdentifier = expected {
+                return Err(mismatch(format!("Map Segment picked identifier {fresh}, which is 0 or already mapped")));
+            }
+            if (status == Status::Halted) != (expected == Effect::Halted) {
+                return Err(mismatch(format!("rum {} but the spec says it {}", halted(status == Status::Halted), halted(expected == Effect::Halted))));
+            }
+            if let Effect::Continued(byte) = expected {
+                if output != byte {
+                    return Err(mismatch(format!("rum wrote {output:?} but the spec {byte:?}")));
+                }
+            }
+            status
+        }
+    };
+    if status == Status::Halted {
+        return Ok(true);
+    }
+
+    let state = &machine.state;
+    if state.program_counter != model.pc as usize {
+        return Err(mismatch(format!("pc is {} in rum but {} in the model", state.program_counter, model.pc)));
+    }
+    if let Some(r) = (0..8).find(|&r| state.register(r as u32) != model.registers[r]) {
+        return Err(mismatch(format!("r{r} is {:#x} in rum but {:#x} in the model", state.register(r as u32), model.registers[r])));
+    }
+    let free = state.free_segments();
+    let mapped: BTreeMap<u32, Vec<u32>> = (0..state.mapped_memory.len() as u32)
+        .filter(|id| !free.contains(id))
+        .map(|id| (id, state.mapped_memory[id as usize].clone()))
+        .collect();
+    if mapped != model.segments {
+        return Err(mismatch(format!("rum's segments are {mapped:?} but the model's {:?}", model.segments)));
+    }
+    Ok(true)
+}
+
+fn halted(halted: bool) -> &'static str {
+    if halted { "halted" } else { "continued" }
+}
+
+/// What one instruction does according to the spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Effect {
+    Continued(Option<u8>),
+    Halted,
+    /// The spec lets the machine fail here.
+    Undefined,
+    /// Map Segment was given an identifier that is 0 or in use.
+    BadIdentifier,
+}
+
+/// The machine as the spec describes it.
+struct Model {
+    registers: [u32; 8],
+    segments: BTreeMap<u32, Vec<u32>>,
+    pc: u32,
+}
+
+impl Model {
+    /// Executes `word`; Input reads `input`, and Map Segment is to use the
+    /// identifier `fresh`.
+    fn step(&mut self, word: u32, input: Option<u8>, fresh: u32) -> Effect {
+        let opcode = word >> 28;
+        let a = (word >> 6 & 7) as usize;
+        let b = (word >> 3 & 7) as usize;
+        let c = (word & 7) as usize;
+        let r = self.registers;
+        self.pc += 1;
+        match opcode {
+            0 => {
+                if r[c] != 0 {
+                    self.registers[a] = r[b];
+                }
+            }
+            1 => match self.segments.get(&r[b]).and_then(|segment| segment.get(r[c] as usize)) {
+                Some(&value) => self.registers[a] = value,
+                None => return Effect::Undefined,
+            },
+            2 => match self.segments.get_mut(&r[a]).and_then(|segment| segment.get_mut(r[b] as usize)) {
+                Some(word) => *word = r[c],
+                None => return Effect::Undefined,
+            },
+            3 => self.registers[a] = ((r[b] as u64 + r[c] as u64) % (1 << 32)) as u32,
+            4 => self.registers[a] = ((r[b] as u64 * r[c] as u64) % (1 << 32)) as u32,
+            5 => match r[c] {
+                0 => return Effect::Undefined,
+                divisor => self.registers[a] = r[b] / divisor,
+            },
+            6 => self.registers[a] = !(r[b] & r[c]),
+            7 => return Effect::Halted,
+            8 => {
+                if fresh == 0 || self.segments.contains_key(&fresh) {
+                    return Effect::BadIdentifier;
+                }
+                self.segments.insert(fresh, vec![0; r[c] as usize]);
+                self.registers[b] = fresh;
+            }
+            9 => {
+                if r[c] == 0 || self.segments.remove(&r[c]).is_none() {
+                    return Effect::Undefined;
+                }
+            }
+            10 => match u8::try_from(r[c]) {
+                Ok(byte) => return Effect::Continued(Some(byte)),
+                Err(_) => return Effect::Undefined,
+            },
+            11 => self.registers[c] = input.map_or(0xffff_ffff, u32::from),
+            12 => {
+                if r[b] != 0 {
+                    match self.segments.get(&r[b]) {
+                        Some(segment) => {
+                            let copy = segment.clone();
+                            self.segments.insert(0, copy);
+                        }
+                        None => return Effect::Undefined,
+                    }
+                }
+                self.pc = r[c];
+            }
+            13 => self.registers[(word >> 25 & 7) as usize] = word & 0x1ff_ffff,
+            _ => return Effect::Undefined,
+        }
+        Effect::Continued(None)
+    }
+}
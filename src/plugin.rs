@@ -0,0 +1,298 @@
+//! Plugins: tracers, extra opcodes and memory-mapped devices added from outside `rum`.
+//!
+//! A Rust crate implements `Plugin` and adds it to a `Host`. With the
+//! `plugins` feature a shared object exporting `rum_plugin` (see
+//! `include/rum_plugin.h`) can be loaded the same way with `load`.
+//!
+//! Before every instruction each plugin's `on_instruction` sees it. Opcodes
+//! 14 and 15, undefined in the UM, are offered to the plugins in order until
+//! one handles them. Load and Store on a segment a plugin claims with
+//! `device_segment` go to that plugin instead of memory. Stores into
+//! segment 0 and Load Programs replacing it are shown to `on_code_write`
+//! first (see `barrier`).
+
+use crate::barrier::{code_write, CodeWrite};
+use crate::device::Device;
+use crate::embed::{Machine, Status, Stepper};
+use crate::error::MachineError;
+use crate::rumdis::{get, op, Opcode, RA, RB, RC};
+
+/// What a plugin did with an extended opcode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Extension {
+    /// Executed; the machine moves on to the next instruction.
+    Handled,
+    /// Not this plugin's opcode; the next plugin is asked.
+    Unhandled,
+    /// The program faults with this message.
+    Fault(String),
+}
+
+/// Hooks a plugin can provide; every one has a default that does nothing.
+pub trait Plugin {
+    fn name(&self) -> &str;
+
+    /// Called before every instruction with its address and word.
+    fn on_instruction(&mut self, _pc: usize, _instruction: u32) {}
+
+    /// Called before an instruction that changes segment 0, after `on_instruction`.
+    fn on_code_write(&mut self, _write: &CodeWrite) {}
+
+    /// Offered opcodes 14 and 15, with the registers to read and change.
+    fn on_opcode(&mut self, _instruction: u32, _registers: &mut [u32; 8]) -> Extension {
+        Extension::Unhandled
+    }
+
+    /// Segment identifier whose Loads and Stores this plugin serves.
+    fn device_segment(&self) -> Option<u32> {
+        None
+    }
+
+    fn device_read(&mut self, _offset: u32) -> u32 {
+        0
+    }
+
+    fn device_write(&mut self, _offset: u32, _value: u32) {}
+}
+
+/// An embedded machine with plugins attached.
+pub struct Host {
+    pub machine: Machine,
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+impl Host {
+    pub fn new(machine: Machine) -> Self {
+        Self { machine, plugins: Vec::new() }
+    }
+
+    /// Attaches `plugin` after the ones already attached, reserving its
+    /// device segment, if it has one, so Map Segment never returns it.
+    pub fn add(&mut self, plugin: Box<dyn Plugin>) {
+        if let Some(segment) = plugin.device_segment() {
+            self.machine.state.reserve_segment(segment);
+        }
+        self.plugins.push(plugin);
+    }
+
+    /// Serves Loads on `segment` with `load` and Stores with `store` (see
+    /// `device`). Fails on segment 0 and on a segment already mapped or
+    /// serving another device.
+    pub fn with_device(mut self, segment: u32, load: impl FnMut(u32) -> u32 + 'static, store: impl FnMut(u32, u32) + 'static) -> Result<Self, String> {
+        let device = Device::new(segment, load, store)?;
+        if self.machine.state.is_mapped(segment) {
+            return Err(format!("segment {segment} is already mapped"));
+        }
+        self.add(Box::new(device));
+        Ok(self)
+    }
+
+    /// Names of the attached plugins, in order.
+    pub fn plugins(&self) -> impl Iterator<Item = &str> {
+        self.plugins.iter().map(|plugin| plugin.name())
+    }
+
+    /// Executes one instruction like `Machine::step`, with the plugins' hooks
+    /// applied. A plugin reporting `Extension::Fault` faults the program with
+    /// `MachineError::HostFault`, an extended opcode no plugin handles with
+    /// `MachineError::InvalidOpcode`.
+    pub fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        if self.machine.status() == Status::Halted {
+            return Ok(Status::Halted);
+        }
+        let state = &self.machine.state;
+        let pc = state.program_counter;
+        let instruction = *state.mapped_memory.first().and_then(|program| program.get(pc)).ok_or(MachineError::ProgramCounterOutOfBounds { pc })?;
+        for plugin in &mut self.plugins {
+            plugin.on_instruction(pc, instruction);
+        }
+        if let Some(write) = code_write(instruction, state) {
+            for plugin in &mut self.plugins {
+                plugin.on_code_write(&write);
+            }
+        }
+        let register = |field| state.register(get(field, instruction));
+        match op(instruction) {
+            None => self.extend(instruction)?,
+            Some(Opcode::SegLoad) => match self.device(register(&RB)) {
+                Some(device) => {
+                    let value = self.plugins[device].device_read(register(&RC));
+                    self.machine.state.load_value(get(&RA, instruction), value);
+                    self.machine.retire();
+                }
+                None => return self.machine.step(input, output),
+            },
+            Some(Opcode::SegStore) => match self.device(register(&RA)) {
+                Some(device) => {
+                    let (offset, value) = (register(&RB), register(&RC));
+                    self.plugins[device].device_write(offset, value);
+                    self.machine.retire();
+                }
+                None => return self.machine.step(input, output),
+            },
+            _ => return self.machine.step(input, output),
+        }
+        Ok(Status::Running)
+    }
+
+    /// Index of the plugin serving `segment`, if any.
+    fn device(&self, segment: u32) -> Option<usize> {
+        self.plugins.iter().position(|plugin| plugin.device_segment() == Some(segment))
+    }
+
+    fn extend(&mut self, instruction: u32) -> Result<(), MachineError> {
+        let pc = self.machine.state.program_counter;
+        let mut registers: [u32; 8] = std::array::from_fn(|r| self.machine.state.register(r as u32));
+        for plugin in &mut self.plugins {
+            match plugin.on_opcode(instruction, &mut registers) {
+                Extension::Unhandled => continue,
+                Extension::Handled => {
+                    for (r, &value) in registers.iter().enumerate() {
+                        self.machine.state.load_value(r as u32, value);
+                    }
+                    self.machine.retire();
+                    return Ok(());
+                }
+                Extension::Fault(message) => return Err(MachineError::HostFault { pc, message: format!("plugin {}: {message}", plugin.name()) }),
+            }
+        }
+        error!(instruction, pc, "invalid opcode");
+        Err(MachineError::InvalidOpcode { pc, instruction })
+    }
+}
+
+impl Stepper for Host {
+    fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        Host::step(self, input, output)
+    }
+
+    fn status(&self) -> Status {
+        self.machine.status()
+    }
+
+    fn executed(&self) -> u64 {
+        self.machine.executed()
+    }
+
+    fn cancelled(&self) -> bool {
+        self.machine.cancelled()
+    }
+}
+
+#[cfg(feature = "plugins")]
+pub use native::load;
+
+#[cfg(feature = "plugins")]
+mod native {
+    use std::ffi::{c_char, c_int, c_void, CStr};
+
+    use libloading::Library;
+
+    use super::{Extension, Plugin};
+
+    /// Must match `RUM_PLUGIN_ABI` in `include/rum_plugin.h`.
+    const ABI: u32 = 1;
+    const HANDLED: c_int = 0;
+    const UNHANDLED: c_int = 1;
+
+    /// `RumPlugin` from `include/rum_plugin.h`.
+    #[repr(C)]
+    struct RawPlugin {
+        abi: u32,
+        name: *const c_char,
+        user: *mut c_void,
+        on_instruction: Option<unsafe extern "C" fn(*mut c_void, u32, u32)>,
+        on_opcode: Option<unsafe extern "C" fn(*mut c_void, u32, *mut u32) -> c_int>,
+        device_segment: u32,
+        device_read: Option<unsafe extern "C" fn(*mut c_void, u32) -> u32>,
+        device_write: Option<unsafe extern "C" fn(*mut c_void, u32, u32)>,
+        destroy: Option<unsafe extern "C" fn(*mut c_void)>,
+    }
+
+    /// A plugin in a shared object, kept loaded for as long as it is attached.
+    struct Native {
+        raw: *const RawPlugin,
+        name: String,
+        // Dropped last, after `destroy` has run.
+        _library: Library,
+    }
+
+    /// Loads the shared object at `path` and returns the plugin it exports.
+    pub fn load(path: &str) -> Result<Box<dyn Plugin>, String> {
+        // SAFETY: loading a library runs its initialisers; the user chose to trust it.
+        let library = unsafe { Library::new(path) }.map_err(|e| e.to_string())?;
+        // SAFETY: `rum_plugin` has the signature declared in include/rum_plugin.h.
+        let entry = unsafe { library.get::<unsafe extern "C" fn() -> *const RawPlugin>(b"rum_plugin\0") }
+            .map_err(|e| e.to_string())?;
+        // SAFETY: as above; the returned struct lives as long as the library.
+        let raw = unsafe { entry() };
+        // SAFETY: null or a valid `RumPlugin`.
+        let plugin = unsafe { raw.as_ref() }.ok_or("the plugin refused to load")?;
+        if plugin.abi != ABI {
+            return Err(format!("plugin ABI {} is not supported (rum speaks {ABI})", plugin.abi));
+        }
+        let name = match plugin.name.is_null() {
+            true => path.to_string(),
+            // SAFETY: a non-null name is a NUL-terminated string.
+            false => unsafe { CStr::from_ptr(plugin.name) }.to_string_lossy().into_owned(),
+        };
+        Ok(Box::new(Native { raw, name, _library: library }))
+    }
+
+    impl Native {
+        fn raw(&self) -> &RawPlugin {
+            // SAFETY: checked non-null in `load`, and the library is still loaded.
+            unsafe { &*self.raw }
+        }
+    }
+
+    // SAFETY (for every callback below): the plugin declared these
+    // functions and `user` together, and they stay valid while it is loaded.
+    impl Plugin for Native {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn on_instruction(&mut self, pc: usize, instruction: u32) {
+            let raw = self.raw();
+            if let Some(hook) = raw.on_instruction {
+                unsafe { hook(raw.user, pc as u32, instruction) }
+            }
+        }
+
+        fn on_opcode(&mut self, instruction: u32, registers: &mut [u32; 8]) -> Extension {
+            let raw = self.raw();
+            match raw.on_opcode.map(|hook| unsafe { hook(raw.user, instruction, registers.as_mut_ptr()) }) {
+                None | Some(UNHANDLED) => Extension::Unhandled,
+                Some(HANDLED) => Extension::Handled,
+                Some(code) => Extension::Fault(format!("opcode {} failed ({code})", instruction >> 28)),
+            }
+        }
+
+        fn device_segment(&self) -> Option<u32> {
+            Some(self.raw().device_segment).filter(|&segment| segment != 0)
+        }
+
+        fn device_read(&mut self, offset: u32) -> u32 {
+            let raw = self.raw();
+            raw.device_read.map_or(0, |hook| unsafe { hook(raw.user, offset) })
+        }
+
+        fn device_write(&mut self, offset: u32, value: u32) {
+            let raw = self.raw();
+            if let Some(hook) = raw.device_write {
+                unsafe { hook(raw.user, offset, value) }
+            }
+        }
+    }
+
+    impl Drop for Native {
+        fn drop(&mut self) {
+            let raw = self.raw();
+            if let Some(destroy) = raw.destroy {
+                // SAFETY: see the `Plugin` impl; called exactly once.
+                unsafe { destroy(raw.user) }
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Opcode-level access control (`rum run --deny RULE`), for hosts running
+//! untrusted programs: the machine faults on the first instruction the
+//! policy forbids, before executing it.
+//!
+//! A rule is either an opcode, which the program may not execute at all, or
+//! `opcode-over=N`, which lets it execute N of them and faults on the next,
+//! e.g. `--deny in --deny out --deny map-over=1048576` for a compute-only
+//! run that maps at most a million segments. Several rules can also be
+//! given at once, separated by commas. Opcodes are named as
+//! `rumdis::describe` writes them, or by the longer `mapseg`, `unmapseg`,
+//! `segload`, `segstore`, `input` and `output`.
+
+use crate::rumdis::MNEMONICS;
+
+/// Names accepted besides the mnemonics, with their opcodes.
+const ALIASES: [(&str, usize); 6] = [("segload", 1), ("segstore", 2), ("mapseg", 8), ("unmapseg", 9), ("output", 10), ("input", 11)];
+
+/// How many instructions with each opcode a program may execute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    /// `None` for no limit; `Some(0)` denies the opcode.
+    quotas: [Option<u64>; 16],
+}
+
+impl Default for Policy {
+    /// The policy allowing everything.
+    fn default() -> Self {
+        Self { quotas: [None; 16] }
+    }
+}
+
+impl Policy {
+    /// Adds the rules in `--deny`'s argument, tightening any given before.
+    pub fn deny(&mut self, rules: &str) -> Result<(), String> {
+        for rule in rules.split(',').map(str::trim) {
+            let (name, quota) = match rule.split_once("-over=") {
+                Some((name, quota)) => (name, quota.trim().parse().map_err(|_| format!("--deny: `{quota}` is not a number of instructions"))?),
+                None => (rule, 0),
+            };
+            let opcode = opcode(name.trim()).ok_or_else(|| format!("--deny: unknown opcode `{name}`"))?;
+            self.quotas[opcode] = Some(self.quotas[opcode].map_or(quota, |old| old.min(quota)));
+        }
+        Ok(())
+    }
+
+    /// Instructions with `opcode` (0 to 15) allowed, `None` if there is no limit.
+    pub fn quota(&self, opcode: usize) -> Option<u64> {
+        self.quotas[opcode]
+    }
+}
+
+fn opcode(name: &str) -> Option<usize> {
+    MNEMONICS[..14].iter().position(|&m| m == name).or_else(|| ALIASES.iter().find(|&&(alias, _)| alias == name).map(|&(_, opcode)| opcode))
+}
+
+/// A `Policy` applied to a run, counting the instructions it limits.
+#[derive(Debug, Clone)]
+pub struct Guard {
+    policy: Policy,
+    counts: [u64; 16],
+}
+
+impl Guard {
+    pub fn new(policy: Policy) -> Self {
+        Self { policy, counts: [0; 16] }
+    }
+
+    /// Admits the instruction `inst` about to execute, or says why the policy forbids it.
+    #[inline]
+    pub fn check(&mut self, inst: u32) -> Result<(), String> {
+        let opcode = (inst >> 28) as usize;
+        let Some(quota) = self.policy.quotas[opcode] else { return Ok(()) };
+        if self.counts[opcode] == quota {
+            let name = MNEMONICS[opcode];
+            return Err(match quota {
+                0 => format!("policy violation: `{name}` is denied"),
+                _ => format!("policy violation: more than {quota} `{name}` instructions"),
+            });
+        }
+        self.counts[opcode] += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,421 @@
+//! Instruction-level profiling for `rum profile`: how often each word of
+//! the program executes.
+//!
+//! Counts are kept per segment-0 address while segment 0 is still the
+//! program that was loaded. Once Load Program replaces it with another
+//! segment, addresses no longer name the same code, so later instructions
+//! are only counted in total.
+//!
+//! With `sample_stacks`, the profile also samples the call stack guessed
+//! from the program's jumps (see `flame`), for flamegraphs; with
+//! `track_memory`, it follows allocations by site (see `memprofile`); with
+//! `track_jumps`, it counts Load Program edges (see `jumps`); with
+//! `track_self_modification`, it logs Stores over executed code (see
+//! `selfmod`).
+//!
+//! Counting every instruction slows a run down several times. `sample`
+//! instead has the interpreter mirror its PC into an atomic that a helper
+//! thread reads on a timer, which costs a store per instruction. Its counts
+//! are samples rather than executions, and measure wall-clock time, so
+//! waiting for input shows up too; `report` reads both kinds alike.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+use std::time::Duration;
+
+use crate::barrier::{code_write, CodeWrite};
+use crate::embed::{Machine, Status};
+use crate::error::MachineError;
+use crate::flame::{CallStack, Samples};
+use crate::instruction::Instruction;
+use crate::jumps::JumpProfile;
+use crate::memprofile::MemoryProfile;
+use crate::report::{Cell, Table};
+use crate::rumdis::describe;
+use crate::selfmod::SelfModification;
+use crate::state::UniversalMachine;
+use crate::symbols::{Routine, Symbols};
+
+/// Execution counts of one run, or PC samples for `sample`.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    counts: Vec<u64>,
+    /// The word last executed at each address, which differs from the
+    /// loaded one where the program modified itself.
+    words: Vec<u32>,
+    /// Instructions executed in all.
+    pub executed: u64,
+    /// Instructions executed (samples taken, for `sample`) after segment 0
+    /// was replaced.
+    pub replaced: u64,
+    /// The sampling interval, if the counts are samples.
+    pub interval: Option<Duration>,
+    active: bool,
+    stack: CallStack,
+    samples: Option<Samples>,
+    memory: Option<MemoryProfile>,
+    jumps: Option<JumpProfile>,
+    rewrites: Option<SelfModification>,
+}
+
+impl Profile {
+    pub fn new(program: &[u32]) -> Self {
+        Self {
+            counts: vec![0; program.len()],
+            words: program.to_vec(),
+            executed: 0,
+            replaced: 0,
+            interval: None,
+            active: true,
+            stack: CallStack::new(),
+            samples: None,
+            memory: None,
+            jumps: None,
+            rewrites: None,
+        }
+    }
+
+    /// Also samples the call stack every `every` instructions.
+    pub fn sample_stacks(mut self, every: u64) -> Self {
+        self.samples = Some(Samples::new(every));
+        self
+    }
+
+    /// The call stack samples, if `sample_stacks` asked for them.
+    pub fn samples(&self) -> Option<&Samples> {
+        self.samples.as_ref()
+    }
+
+    /// Also follows Map and Unmap Segment by allocation site.
+    pub fn track_memory(mut self) -> Self {
+        self.memory = Some(MemoryProfile::new());
+        self
+    }
+
+    /// The allocation statistics, if `track_memory` asked for them.
+    pub fn memory(&self) -> Option<&MemoryProfile> {
+        self.memory.as_ref()
+    }
+
+    /// Also counts Load Program edges within segment 0.
+    pub fn track_jumps(mut self) -> Self {
+        self.jumps = Some(JumpProfile::new());
+        self
+    }
+
+    /// The jump counts, if `track_jumps` asked for them.
+    pub fn jumps(&self) -> Option<&JumpProfile> {
+        self.jumps.as_ref()
+    }
+
+    /// Also logs Stores into segment 0 over words that have executed.
+    pub fn track_self_modification(mut self) -> Self {
+        self.rewrites = Some(SelfModification::new());
+        self
+    }
+
+    /// The rewrites of executed code, if `track_self_modification` asked for them.
+    pub fn self_modification(&self) -> Option<&SelfModification> {
+        self.rewrites.as_ref()
+    }
+
+    /// Counts the instruction about to run from `pc`.
+    pub fn record(&mut self, pc: usize, inst: u32, state: &UniversalMachine) {
+        self.executed += 1;
+        if let Some(samples) = self.samples.as_mut() {
+            samples.tick(&self.stack);
+            self.stack.step(pc, inst, state);
+        }
+        if let Some(memory) = self.memory.as_mut() {
+            memory.before(pc, inst, state);
+        }
+        if !self.active {
+            self.replaced += 1;
+            return;
+        }
+        if let Some(count) = self.counts.get_mut(pc) {
+            *count += 1;
+            self.words[pc] = inst;
+        }
+        if let Some(rewrites) = self.rewrites.as_mut() {
+            rewrites.before(self.executed - 1, pc, inst, state, &self.counts);
+        }
+        if let Instruction::LoadProgram { b, c } = Instruction::decode(inst) {
+            match state.register(b) {
+                0 => {
+                    if let Some(jumps) = self.jumps.as_mut() {
+                        jumps.record(pc, state.register(c) as usize);
+                    }
+                }
+                _ => self.active = false,
+            }
+        }
+    }
+
+    /// Called after `inst`, passed to `record` before, has run.
+    pub fn after(&mut self, inst: u32, state: &UniversalMachine) {
+        if let Some(memory) = self.memory.as_mut() {
+            memory.after(inst, state);
+        }
+    }
+
+    /// Times the instruction at `pc` of the original program executed (or
+    /// was sampled).
+    pub fn count(&self, pc: usize) -> u64 {
+        self.counts.get(pc).copied().unwrap_or(0)
+    }
+
+    /// Words in the original program.
+    pub fn program_len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// The word last executed at `pc`.
+    pub fn word(&self, pc: usize) -> u32 {
+        self.words[pc]
+    }
+
+    /// What the percentages are of: instructions executed, or samples taken.
+    pub fn total(&self) -> u64 {
+        match self.interval {
+            Some(_) => self.counts.iter().sum::<u64>() + self.replaced,
+            None => self.executed,
+        }
+    }
+
+    /// Words of the original program that executed (were sampled) at least once.
+    pub fn words_executed(&self) -> usize {
+        self.counts.iter().filter(|&&count| count > 0).count()
+    }
+
+    /// The `n` most executed addresses with their counts, hottest first
+    /// (lower addresses first among equals).
+    pub fn hottest(&self, n: usize) -> Vec<(usize, u64)> {
+        let mut hot: Vec<(usize, u64)> = self.counts.iter().copied().enumerate().filter(|&(_, count)| count > 0).collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot.truncate(n);
+        hot
+    }
+}
+
+/// Runs `program` for at most `fuel` instructions (unbounded if `None`),
+/// counting what it executes. Also returns how it stopped: `Err` if it
+/// faulted.
+pub fn profile(
+    program: Vec<u32>,
+    fuel: Option<u64>,
+    input: &mut dyn FnMut() -> Option<u8>,
+    output: &mut dyn FnMut(u8),
+) -> (Profile, Result<Status, MachineError>) {
+    let profile = Profile::new(&program);
+    profile_with(profile, program, fuel, input, output)
+}
+
+/// Same as `profile`, but recording into `profile`, e.g. one sampling stacks.
+pub fn profile_with(
+    mut profile: Profile,
+    program: Vec<u32>,
+    fuel: Option<u64>,
+    input: &mut dyn FnMut() -> Option<u8>,
+    output: &mut dyn FnMut(u8),
+) -> (Profile, Result<Status, MachineError>) {
+    let mut machine = Machine::new(program);
+    let mut run = || {
+        while fuel.is_none_or(|fuel| machine.executed() < fuel) {
+            let state = &machine.state;
+            let pc = state.program_counter;
+            let inst = *state.mapped_memory[0].get(pc).ok_or(MachineError::ProgramCounterOutOfBounds { pc })?;
+            profile.record(pc, inst, state);
+            if machine.step(input, output)? == Status::Halted {
+                return Ok(Status::Halted);
+            }
+            profile.after(inst, &machine.state);
+        }
+        Ok(Status::Running)
+    };
+    let result = run();
+    (profile, result)
+}
+
+/// The PC mirror's value once segment 0 has been replaced.
+const REPLACED: usize = usize::MAX;
+
+/// Runs `program` like `profile`, but samples the PC about every
+/// `interval` from a helper thread instead of counting each instruction.
+pub fn sample(
+    program: Vec<u32>,
+    fuel: Option<u64>,
+    interval: Duration,
+    input: &mut dyn FnMut() -> Option<u8>,
+    output: &mut dyn FnMut(u8),
+) -> (Profile, Result<Status, MachineError>) {
+    let mut profile = Profile::new(&program);
+    profile.interval = Some(interval);
+    let mut machine = Machine::new(program);
+    let mirror = AtomicUsize::new(0);
+    let done = AtomicBool::new(false);
+    let len = profile.counts.len();
+    let (result, (counts, replaced)) = std::thread::scope(|scope| {
+        let sampler = scope.spawn(|| {
+            let (mut counts, mut replaced) = (vec![0; len], 0);
+            while !done.load(Relaxed) {
+                std::thread::sleep(interval);
+                match mirror.load(Relaxed) {
+                    REPLACED => replaced += 1,
+                    pc => counts[pc] += 1,
+                }
+            }
+            (counts, replaced)
+        });
+        let mut run = || {
+            let mut original = true;
+            while fuel.is_none_or(|fuel| machine.executed() < fuel) {
+                if original {
+                    let state = &machine.state;
+                    let inst = state.mapped_memory[0].get(state.program_counter).copied();
+                    original = !matches!(inst.and_then(|inst| code_write(inst, state)), Some(CodeWrite::Replace { .. }));
+                    mirror.store(if original && inst.is_some() { state.program_counter } else { REPLACED }, Relaxed);
+                }
+                if machine.step(input, output)? == Status::Halted {
+                    return Ok(Status::Halted);
+                }
+            }
+            Ok(Status::Running)
+        };
+        let result = run();
+        done.store(true, Relaxed);
+        (result, sampler.join().unwrap())
+    });
+    profile.counts = counts;
+    profile.replaced = replaced;
+    // Counting the Halt, as `record` does.
+    profile.executed = machine.executed() + u64::from(result == Ok(Status::Halted));
+    (profile, result)
+}
+
+/// The `top` hottest addresses as a table, with disassembly and each
+/// address's share of all instructions executed (or samples taken),
+/// followed by a summary.
+pub fn report(profile: &Profile, top: usize) -> String {
+    let mut text = String::new();
+    let total = profile.total().max(1) as f64;
+    let unit = match profile.interval {
+        Some(_) => "samples",
+        None => "count",
+    };
+    let mut cumulative = 0;
+    let _ = writeln!(text, "{unit:>12} {:>7} {:>7} {:>8}  instruction", "%", "cum %", "address");
+    for (pc, count) in profile.hottest(top) {
+        cumulative += count;
+        let (share, running) = (count as f64 * 100.0 / total, cumulative as f64 * 100.0 / total);
+        let _ = writeln!(text, "{count:>12} {share:>6.2}% {running:>6.2}% {pc:>8}  {}", describe(profile.word(pc)));
+    }
+    let (words, len) = (profile.words_executed(), profile.counts.len());
+    let _ = match profile.interval {
+        Some(interval) => writeln!(
+            text,
+            "{} samples, one about every {} µs, over {} instructions; {words} of {len} words sampled",
+            profile.total(),
+            interval.as_micros(),
+            profile.executed
+        ),
+        None => writeln!(text, "{} instructions; {words} of {len} words executed", profile.executed),
+    };
+    if profile.replaced > 0 {
+        let what = match profile.interval {
+            Some(_) => "samples were taken",
+            None => "instructions ran",
+        };
+        let _ = writeln!(text, "{} {what} after Load Program replaced segment 0 and are not attributed", profile.replaced);
+    }
+    text
+}
+
+/// Counts (or samples) added up by routine of `symbols`, hottest first
+/// (lower entries first among equals), then the addresses outside every
+/// routine as `None`, if any executed.
+pub fn by_routine<'a>(profile: &Profile, symbols: &'a Symbols) -> Vec<(Option<&'a Routine>, u64)> {
+    let mut counts: Vec<u64> = vec![0; symbols.routines().len()];
+    let mut outside = 0;
+    for (pc, &count) in profile.counts.iter().enumerate().filter(|&(_, &count)| count > 0) {
+        match symbols.position(pc) {
+            Some(i) => counts[i] += count,
+            None => outside += count,
+        }
+    }
+    let mut routines: Vec<(Option<&Routine>, u64)> = symbols.routines().iter().zip(counts).filter(|&(_, count)| count > 0).map(|(routine, count)| (Some(routine), count)).collect();
+    routines.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    if outside > 0 {
+        routines.push((None, outside));
+    }
+    routines
+}
+
+/// The `top` hottest routines of `symbols` as a table, like `report`.
+pub fn routine_report(profile: &Profile, symbols: &Symbols, top: usize) -> String {
+    let total = profile.total().max(1) as f64;
+    let unit = match profile.interval {
+        Some(_) => "samples",
+        None => "count",
+    };
+    let mut text = format!("{unit:>12} {:>7} {:>7} {:>8} {:>6}  routine\n", "%", "cum %", "entry", "size");
+    let mut cumulative = 0;
+    for (routine, count) in by_routine(profile, symbols).into_iter().take(top) {
+        cumulative += count;
+        let (share, running) = (count as f64 * 100.0 / total, cumulative as f64 * 100.0 / total);
+        let _ = match routine {
+            Some(routine) => writeln!(text, "{count:>12} {share:>6.2}% {running:>6.2}% {:>8} {:>6}  {}", routine.entry, routine.size, routine.name),
+            None => writeln!(text, "{count:>12} {share:>6.2}% {running:>6.2}% {:>8} {:>6}  (outside every routine)", "-", "-"),
+        };
+    }
+    text
+}
+
+/// `routines`, the `top` hottest routines of `symbols` (all if `None`), for `--report-format`.
+pub fn routine_table(profile: &Profile, symbols: &Symbols, top: Option<usize>) -> Table {
+    let total = profile.total().max(1) as f64;
+    let mut table = Table::new("routines", &["routine", "entry", "size", "count", "share", "cumulative_share"]);
+    let mut cumulative = 0;
+    for (routine, count) in by_routine(profile, symbols).into_iter().take(top.unwrap_or(usize::MAX)) {
+        cumulative += count;
+        let (name, entry, size) = match routine {
+            Some(routine) => (Cell::from(routine.name.as_str()), routine.entry.into(), routine.size.into()),
+            None => (Cell::None, Cell::None, Cell::None),
+        };
+        table.row(vec![name, entry, size, count.into(), (count as f64 / total).into(), (cumulative as f64 / total).into()]);
+    }
+    table
+}
+
+/// The report as tables for `--report-format` (see `report`): `hot`, the
+/// `top` most executed addresses (every executed one if `None`), and
+/// `summary`, one row of totals, followed by the memory tables if the
+/// profile tracked memory and the rewritten regions if it tracked
+/// self-modification.
+pub fn tables(profile: &Profile, top: Option<usize>) -> Vec<Table> {
+    let total = profile.total().max(1) as f64;
+    let mut hot = Table::new("hot", &["address", "count", "share", "cumulative_share", "instruction"]);
+    let mut cumulative = 0;
+    for (pc, count) in profile.hottest(top.unwrap_or(usize::MAX)) {
+        cumulative += count;
+        hot.row(vec![pc.into(), count.into(), (count as f64 / total).into(), (cumulative as f64 / total).into(), describe(profile.word(pc)).into()]);
+    }
+    let mut summary = Table::new("summary", &["unit", "executed", "counted", "words", "words_executed", "replaced", "interval_us"]);
+    summary.row(vec![
+        Cell::from(if profile.interval.is_some() { "samples" } else { "instructions" }),
+        profile.executed.into(),
+        profile.total().into(),
+        profile.program_len().into(),
+        profile.words_executed().into(),
+        profile.replaced.into(),
+        profile.interval.map(|interval| interval.as_micros() as u64).into(),
+    ]);
+    let mut tables = vec![hot, summary];
+    if let Some(memory) = profile.memory() {
+        tables.extend(memory.tables(top));
+    }
+    if let Some(rewrites) = profile.self_modification() {
+        tables.extend(rewrites.tables());
+    }
+    tables
+}
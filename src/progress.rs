@@ -0,0 +1,104 @@
+use std::io::{stderr, IsTerminal, Write};
+use std::time::Duration;
+
+use crate::clock::Clock;
+use crate::state::UniversalMachine;
+
+/// The clock is only consulted once every this many instructions.
+pub const CHECK_EVERY: u64 = 1 << 20;
+
+/// Minimum time between two progress lines, on the reporter's clock.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Periodic `--progress` reporter writing to stderr.
+///
+/// On a terminal the report is a single line rewritten in place;
+/// otherwise (e.g. redirected to a log) each report is its own line.
+pub struct Progress {
+    clock: Clock,
+    last: Duration,
+    last_count: u64,
+    /// Time spent waiting for input as of the previous line.
+    last_wait: Duration,
+    tty: bool,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self::with_clock(Clock::new(false))
+    }
+
+    /// A reporter timing the run by `clock`.
+    pub fn with_clock(clock: Clock) -> Self {
+        Self { clock, last: Duration::ZERO, last_count: 0, last_wait: Duration::ZERO, tty: stderr().is_terminal() }
+    }
+
+    /// Given the number of instructions executed so far, print a report
+    /// if at least `INTERVAL` has passed since the previous one. MIPS leave
+    /// out time spent waiting for input, which is reported apart.
+    pub fn tick(&mut self, count: u64, state: &UniversalMachine) {
+        let now = self.clock.elapsed(count);
+        let since = now - self.last;
+        if since < INTERVAL {
+            return;
+        }
+        // The virtual clock doesn't run while waiting.
+        let wait = match self.clock {
+            Clock::Wall(_) => state.usage().input_wait,
+            Clock::Virtual => Duration::ZERO,
+        };
+        let busy = since.saturating_sub(wait.saturating_sub(self.last_wait)).max(Duration::from_nanos(1));
+        let mips = (count - self.last_count) as f64 / busy.as_secs_f64() / 1e6;
+        let mut line = format!(
+            "[rum] {} instructions, {:.1} MIPS, {} mapped, {:.0}s elapsed",
+            count,
+            mips,
+            human_bytes(state.mapped_bytes()),
+            now.as_secs_f64()
+        );
+        if !wait.is_zero() {
+            line += &format!(", {:.0}s waiting for input", wait.as_secs_f64());
+        }
+        self.print(&line);
+        self.last = now;
+        self.last_count = count;
+        self.last_wait = wait;
+    }
+
+    /// Called when the program is about to block reading input: reports it
+    /// if a report is due, so a program waiting for the user can be told
+    /// from one stuck in a loop.
+    pub fn waiting(&mut self, count: u64, state: &UniversalMachine) {
+        let now = self.clock.elapsed(count);
+        if now - self.last < INTERVAL {
+            return;
+        }
+        self.print(&format!("[rum] {count} instructions, {} mapped, waiting for input", human_bytes(state.mapped_bytes())));
+        self.last = now;
+        self.last_count = count;
+        self.last_wait = state.usage().input_wait;
+    }
+
+    fn print(&self, line: &str) {
+        let mut err = stderr().lock();
+        let _ = if self.tty { write!(err, "\r{line}\x1b[K") } else { writeln!(err, "{line}") };
+        let _ = err.flush();
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn human_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
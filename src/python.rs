@@ -0,0 +1,150 @@
+//! The `rum` Python module, built with the `python` feature.
+//!
+//! ```python
+//! import rum
+//! m = rum.Machine.load("bin/cat.um")
+//! m.run(input=b"hello\n")          # -> b"hello\n"
+//! m.halted, m.executed, m.registers
+//! ```
+
+// The code generated by `#[pymethods]` converts PyErr into itself.
+#![allow(clippy::useless_conversion)]
+
+use std::collections::VecDeque;
+
+use pyo3::exceptions::{PyIndexError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::embed::{self, Status};
+use crate::error::MachineError;
+
+/// A Universal Machine. Input is queued with `feed` (or the `input`
+/// argument of `run`/`step`); once the queue is empty the program sees end
+/// of input. Output is returned by the call that produced it.
+#[pyclass(module = "rum")]
+pub struct Machine {
+    machine: embed::Machine,
+    input: VecDeque<u8>,
+    faulted: bool,
+}
+
+impl Machine {
+    fn drive(&mut self, input: Option<&[u8]>, f: impl FnOnce(&mut embed::Machine, &mut dyn FnMut() -> Option<u8>, &mut dyn FnMut(u8)) -> Result<Status, MachineError>) -> PyResult<Vec<u8>> {
+        if self.faulted {
+            return Err(PyRuntimeError::new_err("the machine faulted earlier"));
+        }
+        self.input.extend(input.unwrap_or_default());
+        let mut output = Vec::new();
+        let (machine, queue) = (&mut self.machine, &mut self.input);
+        if let Err(e) = f(machine, &mut || queue.pop_front(), &mut |byte| output.push(byte)) {
+            self.faulted = true;
+            return Err(PyRuntimeError::new_err(format!("program faulted after {} instructions: {e}", self.machine.executed())));
+        }
+        Ok(output)
+    }
+}
+
+#[pymethods]
+impl Machine {
+    /// Machine(program: bytes) -- the contents of a `.um` file.
+    #[new]
+    fn new(program: &[u8]) -> PyResult<Self> {
+        if !program.len().is_multiple_of(4) {
+            return Err(PyValueError::new_err("program length is not a whole number of words"));
+        }
+        let words = crate::load::words(program);
+        Ok(Self { machine: embed::Machine::new(words), input: VecDeque::new(), faulted: false })
+    }
+
+    /// Machine.load(path) -- reads a `.um` file.
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<Self> {
+        Self::new(&std::fs::read(path).map_err(|e| PyValueError::new_err(format!("{path}: {e}")))?)
+    }
+
+    /// Queues bytes for the program's Input instruction.
+    fn feed(&mut self, input: &[u8]) {
+        self.input.extend(input);
+    }
+
+    /// Runs until Halt, or for at most `fuel` instructions, returning the output.
+    #[pyo3(signature = (input=None, fuel=None))]
+    fn run<'py>(&mut self, py: Python<'py>, input: Option<&[u8]>, fuel: Option<u64>) -> PyResult<Bound<'py, PyBytes>> {
+        let output = self.drive(input, |machine, input, output| machine.run(fuel, input, output))?;
+        Ok(PyBytes::new_bound(py, &output))
+    }
+
+    /// Executes one instruction, returning any byte it output.
+    #[pyo3(signature = (input=None))]
+    fn step<'py>(&mut self, py: Python<'py>, input: Option<&[u8]>) -> PyResult<Bound<'py, PyBytes>> {
+        let output = self.drive(input, |machine, input, output| machine.step(input, output))?;
+        Ok(PyBytes::new_bound(py, &output))
+    }
+
+    #[getter]
+    fn halted(&self) -> bool {
+        self.machine.status() == Status::Halted
+    }
+
+    /// Instructions executed so far.
+    #[getter]
+    fn executed(&self) -> u64 {
+        self.machine.executed()
+    }
+
+    #[getter]
+    fn pc(&self) -> usize {
+        self.machine.state.program_counter
+    }
+
+    /// The eight registers as a list.
+    #[getter]
+    fn registers(&self) -> Vec<u32> {
+        (0..8).map(|r| self.machine.state.register(r)).collect()
+    }
+
+    fn set_register(&mut self, r: u32, value: u32) -> PyResult<()> {
+        if r >= 8 {
+            return Err(PyIndexError::new_err("registers are numbered 0-7"));
+        }
+        self.machine.state.load_value(r, value);
+        Ok(())
+    }
+
+    /// Number of segments, mapped or not.
+    #[getter]
+    fn segments(&self) -> usize {
+        self.machine.state.mapped_memory.len()
+    }
+
+    /// Words of segment `index` as a list (empty if unmapped).
+    fn segment(&self, index: usize) -> PyResult<Vec<u32>> {
+        self.machine.state.mapped_memory.get(index).cloned().ok_or_else(|| PyIndexError::new_err(format!("no segment {index}")))
+    }
+
+    fn read(&self, segment: usize, offset: usize) -> PyResult<u32> {
+        let word = self.machine.state.mapped_memory.get(segment).and_then(|s| s.get(offset));
+        word.copied().ok_or_else(|| PyIndexError::new_err(format!("no word {segment}[{offset}]")))
+    }
+
+    fn write(&mut self, segment: usize, offset: usize, value: u32) -> PyResult<()> {
+        let word = self.machine.state.segment_mut(segment).and_then(|s| s.get_mut(offset));
+        *word.ok_or_else(|| PyIndexError::new_err(format!("no word {segment}[{offset}]")))? = value;
+        Ok(())
+    }
+}
+
+/// rum.run(program, input=b"", fuel=None) -- runs a program once and returns its output.
+#[pyfunction]
+#[pyo3(signature = (program, input=None, fuel=None))]
+fn run<'py>(py: Python<'py>, program: &[u8], input: Option<&[u8]>, fuel: Option<u64>) -> PyResult<Bound<'py, PyBytes>> {
+    Machine::new(program)?.run(py, input, fuel)
+}
+
+#[pymodule]
+fn rum(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Machine>()?;
+    m.add_function(wrap_pyfunction!(run, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,245 @@
+//! `rum repl`: a scratchpad for the instruction set. Each line typed is
+//! either an instruction, written as `rumdis::describe` writes them
+//! (`loadval r1, 65`, `out r1`, ...), which executes at once against a live
+//! machine, or a command inspecting it.
+//!
+//! Instructions are appended to segment 0 and run from there, so the
+//! session's segment 0 is the program typed so far and a saved session is
+//! an ordinary snapshot, one `rum debug --resume` can step through again.
+//! A Load Program jump moves the program counter but the next line typed
+//! still runs next. A fault leaves the machine as it was before the
+//! instruction; Halt stops it until `reset`.
+//!
+//! The program's output is shown after each instruction rather than mixed
+//! into the prompt, and Input reads what `feed` queued, seeing end of input
+//! when there is none.
+
+use std::io::{BufRead, Write};
+
+use crate::error::MachineError;
+use crate::explain::Explain;
+use crate::instruction::Instruction;
+use crate::rumdis::{describe, disassemble};
+use crate::seal::Key;
+use crate::snapshot::{load_snapshot, save_snapshot};
+use crate::state::UniversalMachine;
+
+const HELP: &str = "type an instruction to execute it, e.g. `loadval r1, 65` then `out r1`; commands:
+  regs              show registers and the program counter
+  mem SEG [OFF [N]] show N words (default 8) of segment SEG from offset OFF
+  segments          list mapped segments and their sizes
+  list              disassemble segment 0, the instructions entered so far
+  feed TEXT         queue TEXT and a newline for Input to read
+  save FILE         save the session as a snapshot
+  load FILE         continue from a snapshot
+  reset             start again with a fresh machine
+  quit";
+
+/// A live machine taking one instruction at a time.
+pub struct Repl {
+    state: UniversalMachine,
+    halted: bool,
+    executed: u64,
+    /// Explain each instruction instead of listing what changed.
+    explain: bool,
+    /// Seals and opens the snapshots of `save` and `load` (see `seal`).
+    key: Option<Key>,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new(UniversalMachine::new())
+    }
+}
+
+impl Repl {
+    /// Continues the session held by `state`; an empty machine starts a new one.
+    pub fn new(mut state: UniversalMachine) -> Self {
+        if state.mapped_memory.is_empty() {
+            state.mapped_memory.push(Vec::new());
+        }
+        Self { state, halted: false, executed: 0, explain: false, key: None }
+    }
+
+    /// Explains each instruction in plain English (see `explain`) instead
+    /// of listing the registers and segments it changed.
+    pub fn with_explain(mut self) -> Self {
+        self.explain = true;
+        self
+    }
+
+    /// Seals the snapshots `save` writes with `key`, and has `load` open only
+    /// snapshots sealed with it.
+    pub fn with_snapshot_key(mut self, key: Option<Key>) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// The machine as it stands.
+    pub fn state(&self) -> &UniversalMachine {
+        &self.state
+    }
+
+    /// Reads and executes lines from `commands` until `quit` or end of
+    /// input, answering on `out`.
+    pub fn run(&mut self, mut commands: impl BufRead, out: &mut dyn Write) -> std::io::Result<()> {
+        loop {
+            write!(out, "(um) ")?;
+            out.flush()?;
+            let mut line = String::new();
+            if commands.read_line(&mut line)? == 0 {
+                return writeln!(out);
+            }
+            let line = line.trim();
+            let (command, rest) = line.split_once(char::is_whitespace).map_or((line, ""), |(c, r)| (c, r.trim()));
+            let args: Vec<&str> = rest.split_whitespace().collect();
+            match command {
+                "" => {}
+                "q" | "quit" => return Ok(()),
+                "h" | "help" => writeln!(out, "{HELP}")?,
+                "r" | "regs" => self.show(out)?,
+                "mem" => self.memory(&args, out)?,
+                "segments" => {
+                    for (seg, words) in self.state.mapped_memory.iter().enumerate() {
+                        if !self.state.free_segments().contains(&(seg as u32)) {
+                            writeln!(out, "segment {seg}: {} words", words.len())?;
+                        }
+                    }
+                }
+                "list" => {
+                    for (addr, &word) in self.state.mapped_memory[0].iter().enumerate() {
+                        let marker = if addr == self.state.program_counter { "=>" } else { "  " };
+                        writeln!(out, "{marker} {addr:>5}: {}", describe(word))?;
+                    }
+                }
+                "feed" => {
+                    self.state.prime_input(format!("{rest}\n").as_bytes());
+                    writeln!(out, "{} bytes of input queued", self.state.pending_input())?;
+                }
+                "save" if args.len() == 1 => match save_snapshot(&self.state, args[0], self.key.as_ref()) {
+                    Ok(()) => writeln!(out, "saved to {}", args[0])?,
+                    Err(e) => writeln!(out, "cannot save {}: {e}", args[0])?,
+                },
+                "load" if args.len() == 1 => match load_snapshot(args[0], self.key.as_ref()) {
+                    Ok(state) => {
+                        *self = Repl { explain: self.explain, key: self.key.take(), ..Repl::new(state) };
+                        self.show(out)?;
+                    }
+                    Err(e) => writeln!(out, "cannot load {}: {e}", args[0])?,
+                },
+                "reset" => {
+                    *self = Repl { explain: self.explain, key: self.key.take(), ..Repl::default() };
+                    writeln!(out, "fresh machine")?;
+                }
+                _ => match line.parse::<Instruction>() {
+                    Ok(instruction) => self.execute(instruction, out)?,
+                    Err(e) => writeln!(out, "{e}; try `help`")?,
+                },
+            }
+        }
+    }
+
+    /// Appends `instruction` to segment 0 and executes it there.
+    fn execute(&mut self, instruction: Instruction, out: &mut dyn Write) -> std::io::Result<()> {
+        if self.halted {
+            return writeln!(out, "the machine has halted; `reset` to start again");
+        }
+        let word = instruction.encode();
+        if instruction == Instruction::Halt {
+            if self.explain {
+                writeln!(out, "{}", Explain::before(word, &self.state).after(&self.state))?;
+            }
+            self.state.mapped_memory[0].push(word);
+            self.state.program_counter = self.state.mapped_memory[0].len() - 1;
+            self.halted = true;
+            return writeln!(out, "halted after {} instructions", self.executed);
+        }
+        let before = self.state.clone();
+        self.state.mapped_memory[0].push(word);
+        self.state.program_counter = self.state.mapped_memory[0].len() - 1;
+        let explain = Explain::before(word, &self.state);
+        let pc = self.state.program_counter;
+        self.state.program_counter += 1;
+        let mut output = Vec::new();
+        let state = &mut self.state;
+        let result = match instruction {
+            Instruction::Output { c } => {
+                let value = state.register(c);
+                u8::try_from(value).map(|byte| output.push(byte)).map_err(|_| MachineError::OutputOutOfRange { pc, value: value.into() })
+            }
+            Instruction::Input { c } if state.pending_input() == 0 => {
+                state.load_value(c, !0);
+                Ok(())
+            }
+            // Halt never gets here: it is handled before the instruction runs.
+            _ => disassemble(word, state).map(drop),
+        };
+        match result {
+            Ok(_) => {
+                self.executed += 1;
+                if self.explain {
+                    return writeln!(out, "{}", explain.after(&self.state));
+                }
+                if !output.is_empty() {
+                    writeln!(out, "output: {:?}", String::from_utf8_lossy(&output))?;
+                }
+                self.changes(&before, out)
+            }
+            Err(e) => {
+                self.state = before;
+                writeln!(out, "fault: {e}; the machine is unchanged")
+            }
+        }
+    }
+
+    /// Reports the registers, program counter and segments `before` differs in.
+    fn changes(&self, before: &UniversalMachine, out: &mut dyn Write) -> std::io::Result<()> {
+        let changed: Vec<String> = (0..8)
+            .filter(|&r| self.state.register(r) != before.register(r))
+            .map(|r| format!("r{r}={:#x}", self.state.register(r)))
+            .collect();
+        if !changed.is_empty() {
+            writeln!(out, "{}", changed.join(" "))?;
+        }
+        if self.state.program_counter != self.state.mapped_memory[0].len() {
+            writeln!(out, "pc {}", self.state.program_counter)?;
+        }
+        for (seg, words) in self.state.mapped_memory.iter().enumerate().skip(1) {
+            if before.mapped_memory.get(seg) != Some(words) {
+                writeln!(out, "segment {seg}: {} words", words.len())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn show(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        let regs: Vec<String> = (0..8).map(|r| format!("r{r}={:#x}", self.state.register(r))).collect();
+        writeln!(out, "{}", regs.join(" "))?;
+        let status = if self.halted { " (halted)" } else { "" };
+        writeln!(out, "pc {}, {} instructions executed{status}", self.state.program_counter, self.executed)
+    }
+
+    fn memory(&self, args: &[&str], out: &mut dyn Write) -> std::io::Result<()> {
+        let numbers: Option<Vec<usize>> = args.iter().map(|arg| parse_number(arg)).collect();
+        let (seg, offset, count) = match numbers.as_deref() {
+            Some([seg]) => (*seg, 0, 8),
+            Some([seg, offset]) => (*seg, *offset, 8),
+            Some([seg, offset, count]) => (*seg, *offset, *count),
+            _ => return writeln!(out, "mem takes a segment, and optionally an offset and a count"),
+        };
+        let Some(words) = self.state.mapped_memory.get(seg) else {
+            return writeln!(out, "no segment {seg}");
+        };
+        for (addr, word) in words.iter().enumerate().skip(offset).take(count) {
+            writeln!(out, "{seg}[{addr}] = {word:#010x}")?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
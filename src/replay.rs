@@ -0,0 +1,216 @@
+//! Deterministic replay logs (`.rumreplay`).
+//!
+//! A log is the snapshot the run started from followed by every value the
+//! Input instruction produced, tagged with the index of the instruction that
+//! read it. Everything else a UM program does is deterministic, so that is
+//! enough to re-execute the run exactly, up to any instruction index.
+//!
+//! ```text
+//! magic     8 bytes  "RUMREPLY"
+//! length    u64      length of the starting snapshot
+//! snapshot  a complete `.rumstate` snapshot
+//! records   'I' u64 index, u32 value   -- one per executed Input
+//!           'E' u64 count              -- run ended after `count` instructions
+//! ```
+//!
+//! Records are appended as the run goes, so a log cut short by a crash
+//! still replays up to the last input it saw.
+//!
+//! With a snapshot key (see `seal`) the starting snapshot is sealed like any
+//! other, but the input records are not: a log holds whatever the program
+//! read in the clear.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::rumdis::{disassemble, get, op, MachineError, Opcode, RC};
+use crate::seal::Key;
+use crate::snapshot::{decode, encode, SnapshotError};
+use crate::state::UniversalMachine;
+
+pub const MAGIC: &[u8; 8] = b"RUMREPLY";
+
+/// Appends a replay log while the machine runs.
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    /// Starts a log at `path` whose run begins from `state`, its snapshot
+    /// sealed with `key` if one is given.
+    pub fn create(path: impl AsRef<Path>, state: &UniversalMachine, key: Option<&Key>) -> std::io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let snapshot = encode(state, key);
+        out.write_all(MAGIC)?;
+        out.write_all(&(snapshot.len() as u64).to_be_bytes())?;
+        out.write_all(&snapshot)?;
+        out.flush()?;
+        Ok(Self { out })
+    }
+
+    /// Records that the instruction at `index` read `value` (a byte, or all ones at EOF).
+    pub fn input(&mut self, index: u64, value: u32) -> std::io::Result<()> {
+        self.out.write_all(b"I")?;
+        self.out.write_all(&index.to_be_bytes())?;
+        self.out.write_all(&value.to_be_bytes())?;
+        // Inputs are rare next to other instructions; flushing keeps the log useful after a crash.
+        self.out.flush()
+    }
+
+    /// Records that the run ended after `count` instructions.
+    pub fn finish(mut self, count: u64) -> std::io::Result<()> {
+        self.out.write_all(b"E")?;
+        self.out.write_all(&count.to_be_bytes())?;
+        self.out.flush()
+    }
+}
+
+/// A parsed replay log.
+#[derive(Debug, Clone)]
+pub struct ReplayLog {
+    pub start: UniversalMachine,
+    /// `(instruction index, value read)` for every Input, in order.
+    pub inputs: Vec<(u64, u32)>,
+    /// Instructions executed by the recorded run, if it ended cleanly.
+    pub end: Option<u64>,
+}
+
+/// Why a log could not be loaded or replayed.
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Snapshot(SnapshotError),
+    Malformed(&'static str),
+    /// The replayed run executed Input at an index the log did not record,
+    /// meaning the program or interpreter behaves differently now.
+    Diverged { index: u64 },
+    /// The program failed, as it did in the recorded run.
+    Fault(MachineError),
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "{e}"),
+            ReplayError::Snapshot(e) => write!(f, "{e}"),
+            ReplayError::Malformed(what) => write!(f, "replay log is corrupt ({what})"),
+            ReplayError::Diverged { index } => write!(f, "replay diverged from the log at instruction {index}"),
+            ReplayError::Fault(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl ReplayLog {
+    pub fn load(path: impl AsRef<Path>, key: Option<&Key>) -> Result<Self, ReplayError> {
+        Self::parse(&std::fs::read(path).map_err(ReplayError::Io)?, key)
+    }
+
+    /// Parses a log whose starting snapshot opens with `key`.
+    pub fn parse(bytes: &[u8], key: Option<&Key>) -> Result<Self, ReplayError> {
+        let rest = bytes.strip_prefix(MAGIC).ok_or(ReplayError::Malformed("not a replay log"))?;
+        let (len, rest) = split_u64(rest).ok_or(ReplayError::Malformed("truncated header"))?;
+        if rest.len() < len as usize {
+            return Err(ReplayError::Malformed("truncated snapshot"));
+        }
+        let (snapshot, mut rest) = rest.split_at(len as usize);
+        let start = decode(snapshot, key).map_err(ReplayError::Snapshot)?;
+
+        let mut inputs = Vec::new();
+        let mut end = None;
+        while let Some((&tag, body)) = rest.split_first() {
+            match tag {
+                b'I' => {
+                    // A record cut off by a crash is simply the end of the log.
+                    let Some((index, body)) = split_u64(body) else { break };
+                    let Some(value) = body.get(..4) else { break };
+                    inputs.push((index, u32::from_be_bytes(value.try_into().unwrap())));
+                    rest = &body[4..];
+                }
+                b'E' => {
+                    end = split_u64(body).map(|(count, _)| count);
+                    break;
+                }
+                _ => return Err(ReplayError::Malformed("unknown record")),
+            }
+        }
+        Ok(Self { start, inputs, end })
+    }
+}
+
+fn split_u64(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let head = bytes.get(..8)?;
+    Some((u64::from_be_bytes(head.try_into().unwrap()), &bytes[8..]))
+}
+
+/// Re-executes a logged run, feeding Input from the log and collecting
+/// output instead of printing it.
+pub struct Replay<'a> {
+    log: &'a ReplayLog,
+    state: UniversalMachine,
+    count: u64,
+    next_input: usize,
+    halted: bool,
+    pub output: Vec<u8>,
+}
+
+impl<'a> Replay<'a> {
+    pub fn new(log: &'a ReplayLog) -> Self {
+        Self { log, state: log.start.clone(), count: 0, next_input: 0, halted: false, output: Vec::new() }
+    }
+
+    /// The machine as of the current instruction index.
+    pub fn state(&self) -> &UniversalMachine {
+        &self.state
+    }
+
+    /// Number of instructions executed so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Executes until `index` instructions have run in total, or the machine halts.
+    /// Going backwards means starting over from the log's snapshot.
+    pub fn run_to(&mut self, index: u64) -> Result<(), ReplayError> {
+        if index < self.count {
+            *self = Replay::new(self.log);
+        }
+        while self.count < index && !self.halted {
+            let pc = self.state.program_counter;
+            let instruction = *self.state.mapped_memory.first().and_then(|program| program.get(pc)).ok_or(ReplayError::Fault(MachineError::ProgramCounterOutOfBounds { pc }))?;
+            match op(instruction) {
+                Some(Opcode::Halt) => {
+                    self.halted = true;
+                    break;
+                }
+                Some(Opcode::Input) => {
+                    let value = match self.log.inputs.get(self.next_input) {
+                        Some(&(at, value)) if at == self.count => value,
+                        _ => return Err(ReplayError::Diverged { index: self.count }),
+                    };
+                    self.next_input += 1;
+                    self.state.program_counter += 1;
+                    self.state.load_value(get(&RC, instruction), value);
+                }
+                Some(Opcode::Output) => {
+                    let value = self.state.register(get(&RC, instruction));
+                    let byte = u8::try_from(value).map_err(|_| ReplayError::Fault(MachineError::OutputOutOfRange { pc, value: value.into() }))?;
+                    self.state.program_counter += 1;
+                    self.output.push(byte);
+                }
+                _ => {
+                    self.state.program_counter += 1;
+                    disassemble(instruction, &mut self.state).map_err(ReplayError::Fault)?;
+                }
+            }
+            self.count += 1;
+        }
+        Ok(())
+    }
+}
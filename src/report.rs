@@ -0,0 +1,162 @@
+//! Machine-readable forms of rum's stats reports, for `--report-format
+//! json|csv` on `rum profile`, `rum taint`, `rum bench`, `rum mutate`,
+//! `rum info`, `rum log query` and `rum run --histogram`.
+//!
+//! Each report is a list of named tables, each a list of rows with the same
+//! columns. Cells are integers, decimals or text, and never missing: where
+//! a value does not apply it is empty text in CSV and `null` in JSON.
+//!
+//! - JSON is one object per report, each table a member holding an array of
+//!   row objects keyed by column name:
+//!   `{"hot": [{"address": 4583, "count": 1444494, ...}, ...], "summary": [...]}`.
+//! - CSV is the tables one after another, each introduced by a `# NAME`
+//!   line and a header row of column names, with a blank line between them.
+//!
+//! The tables and columns of each report are listed in the README under
+//! "Report formats"; columns are only ever added, at the end.
+
+use std::fmt::Write;
+
+use crate::json::Value;
+
+/// How a report is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// The human-oriented tables.
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Result<Format, String> {
+        match name {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("unknown report format `{name}` (have text, json, csv)")),
+        }
+    }
+}
+
+/// One value of a table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Int(u64),
+    Float(f64),
+    Text(String),
+    /// A value that does not apply to this row.
+    None,
+}
+
+impl From<u64> for Cell {
+    fn from(n: u64) -> Self {
+        Cell::Int(n)
+    }
+}
+
+impl From<usize> for Cell {
+    fn from(n: usize) -> Self {
+        Cell::Int(n as u64)
+    }
+}
+
+impl From<f64> for Cell {
+    fn from(x: f64) -> Self {
+        Cell::Float(x)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(s: &str) -> Self {
+        Cell::Text(s.to_string())
+    }
+}
+
+impl From<String> for Cell {
+    fn from(s: String) -> Self {
+        Cell::Text(s)
+    }
+}
+
+impl<T: Into<Cell>> From<Option<T>> for Cell {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Cell::None, Into::into)
+    }
+}
+
+impl Cell {
+    fn json(&self) -> Value {
+        match self {
+            Cell::Int(n) => Value::Number(*n as f64),
+            Cell::Float(x) => Value::Number(*x),
+            Cell::Text(s) => Value::String(s.clone()),
+            Cell::None => Value::Null,
+        }
+    }
+
+    fn csv(&self) -> String {
+        match self {
+            Cell::Int(n) => n.to_string(),
+            Cell::Float(x) => x.to_string(),
+            Cell::Text(s) if s.contains([',', '"', '\n', '\r']) => format!("\"{}\"", s.replace('"', "\"\"")),
+            Cell::Text(s) => s.clone(),
+            Cell::None => String::new(),
+        }
+    }
+}
+
+/// A named table of rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub name: &'static str,
+    pub columns: Vec<&'static str>,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new(name: &'static str, columns: &[&'static str]) -> Self {
+        Self { name, columns: columns.to_vec(), rows: Vec::new() }
+    }
+
+    /// Adds a row; it must have a cell for every column.
+    pub fn row(&mut self, cells: Vec<Cell>) {
+        assert_eq!(cells.len(), self.columns.len(), "row of {} for table {}", cells.len(), self.name);
+        self.rows.push(cells);
+    }
+}
+
+/// `tables` as one JSON object (see the module documentation).
+pub fn json(tables: &[Table]) -> Value {
+    Value::object(tables.iter().map(|table| {
+        let rows = table.rows.iter().map(|row| Value::object(table.columns.iter().copied().zip(row.iter().map(Cell::json)))).collect();
+        (table.name, Value::Array(rows))
+    }))
+}
+
+/// `tables` as CSV sections (see the module documentation).
+pub fn csv(tables: &[Table]) -> String {
+    let mut out = String::new();
+    for (i, table) in tables.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let _ = writeln!(out, "# {}", table.name);
+        let _ = writeln!(out, "{}", table.columns.join(","));
+        for row in &table.rows {
+            let _ = writeln!(out, "{}", row.iter().map(Cell::csv).collect::<Vec<_>>().join(","));
+        }
+    }
+    out
+}
+
+/// `tables` in `format`; `text` is the human-oriented form, used as is
+/// for `Format::Text`.
+pub fn render(format: Format, tables: &[Table], text: impl FnOnce() -> String) -> String {
+    match format {
+        Format::Text => text(),
+        Format::Json => format!("{}\n", json(tables)),
+        Format::Csv => csv(tables),
+    }
+}
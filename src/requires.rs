@@ -0,0 +1,180 @@
+//! Declaring the opt-in extensions a program needs, so a run without them
+//! fails before it starts instead of faulting on opcode 15 halfway through.
+//!
+//! The declaration is a sidecar file next to the program, `NAME.requires`
+//! for `NAME.um`, which leaves the program itself a plain UM binary any
+//! interpreter runs. It lists one extension per line; blank lines and
+//! anything after `#` are ignored:
+//!
+//! ```text
+//! # adventure.requires
+//! syscalls
+//! float
+//! plugin:framebuffer    # a --plugin whose file is (lib)framebuffer.so
+//! ```
+//!
+//! The extensions are `syscalls`, `float`, `assertions`, `interrupts`,
+//! `tasks`, `um64` (the 64-bit architecture) and `plugin:NAME`.
+//!
+//! A program that reads arguments (the `ARGC` and `ARG` syscalls) says so
+//! with an `args` line, optionally followed by a synopsis of them, which
+//! `rum info` shows next to the command to run it. It implies `syscalls`:
+//!
+//! ```text
+//! args INPUT [OUTPUT]
+//! ```
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// An extension a program can require.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Extension {
+    Syscalls,
+    Float,
+    Assertions,
+    Interrupts,
+    Tasks,
+    Um64,
+    /// A plugin, by its file name without the `lib` prefix and extension.
+    Plugin(String),
+}
+
+impl Extension {
+    pub fn parse(text: &str) -> Result<Extension, String> {
+        match text {
+            "syscalls" => Ok(Extension::Syscalls),
+            "float" => Ok(Extension::Float),
+            "assertions" => Ok(Extension::Assertions),
+            "interrupts" => Ok(Extension::Interrupts),
+            "tasks" => Ok(Extension::Tasks),
+            "um64" => Ok(Extension::Um64),
+            _ => match text.strip_prefix("plugin:") {
+                Some(name) if !name.is_empty() => Ok(Extension::Plugin(name.to_string())),
+                _ => Err(format!("unknown extension `{text}` (have syscalls, float, assertions, interrupts, tasks, um64 and plugin:NAME)")),
+            },
+        }
+    }
+
+    /// The plugin extension provided by loading the shared object at `path`.
+    pub fn plugin(path: impl AsRef<Path>) -> Extension {
+        let stem = path.as_ref().file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        Extension::Plugin(stem.strip_prefix("lib").unwrap_or(&stem).to_string())
+    }
+
+    /// The `rum run` option that provides it.
+    pub fn option(&self) -> String {
+        match self {
+            Extension::Syscalls => "--syscalls".to_string(),
+            Extension::Float => "--extensions float".to_string(),
+            Extension::Assertions => "--assertions".to_string(),
+            Extension::Interrupts => "--interrupts".to_string(),
+            Extension::Tasks => "--tasks".to_string(),
+            Extension::Um64 => "--arch um64".to_string(),
+            Extension::Plugin(name) => format!("--plugin with the {name} plugin"),
+        }
+    }
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Extension::Syscalls => write!(f, "syscalls"),
+            Extension::Float => write!(f, "float"),
+            Extension::Assertions => write!(f, "assertions"),
+            Extension::Interrupts => write!(f, "interrupts"),
+            Extension::Tasks => write!(f, "tasks"),
+            Extension::Um64 => write!(f, "um64"),
+            Extension::Plugin(name) => write!(f, "plugin:{name}"),
+        }
+    }
+}
+
+/// The sidecar declaring what `program` requires.
+pub fn sidecar(program: impl AsRef<Path>) -> PathBuf {
+    program.as_ref().with_extension("requires")
+}
+
+/// The entries of a declaration with their line numbers, comments and blank lines skipped.
+fn entries(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines().enumerate().map(|(n, line)| (n + 1, line.split('#').next().unwrap_or("").trim())).filter(|(_, entry)| !entry.is_empty())
+}
+
+/// The synopsis of an `args` entry, if `entry` is one.
+fn synopsis(entry: &str) -> Option<&str> {
+    let rest = entry.strip_prefix("args")?;
+    (rest.is_empty() || rest.starts_with(char::is_whitespace)).then(|| rest.trim())
+}
+
+/// Parses a declaration, giving the line of the first bad entry.
+pub fn parse(text: &str) -> Result<Vec<Extension>, String> {
+    let mut extensions = Vec::new();
+    for (n, entry) in entries(text) {
+        let extension = match synopsis(entry) {
+            Some(_) => Extension::Syscalls,
+            None => Extension::parse(entry).map_err(|e| format!("line {n}: {e}"))?,
+        };
+        if !extensions.contains(&extension) {
+            extensions.push(extension);
+        }
+    }
+    Ok(extensions)
+}
+
+/// The synopsis of the arguments a declaration says the program takes:
+/// `None` without an `args` line, empty for one without a synopsis.
+pub fn arguments(text: &str) -> Option<String> {
+    entries(text).find_map(|(_, entry)| synopsis(entry)).map(str::to_string)
+}
+
+/// What `program` requires: nothing if it has no sidecar.
+pub fn read(program: impl AsRef<Path>) -> Result<Vec<Extension>, String> {
+    let path = sidecar(program);
+    match std::fs::read_to_string(&path) {
+        Ok(text) => parse(&text).map_err(|e| format!("{}: {e}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("{}: {e}", path.display())),
+    }
+}
+
+/// The arguments `program` takes, as `arguments`: `None` if it has no sidecar.
+pub fn read_arguments(program: impl AsRef<Path>) -> Result<Option<String>, String> {
+    let path = sidecar(program);
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Ok(arguments(&text)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{}: {e}", path.display())),
+    }
+}
+
+/// Checks that every extension `program` requires is among `configured`,
+/// naming the missing ones and the options that provide them.
+pub fn check(program: impl AsRef<Path>, configured: &[Extension]) -> Result<(), String> {
+    let program = program.as_ref();
+    let missing: Vec<Extension> = read(program)?.into_iter().filter(|extension| !configured.contains(extension)).collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<String> = missing.iter().map(Extension::to_string).collect();
+    let options: Vec<String> = missing.iter().map(Extension::option).collect();
+    Err(format!("{} requires {} (declared in {}); run it with {}", program.display(), names.join(", "), sidecar(program).display(), options.join(" and ")))
+}
+
+/// The `rum run` command line that runs `program` with `extensions`, and
+/// its `arguments` after `--` if it takes any.
+pub fn command(program: impl AsRef<Path>, extensions: &[Extension], arguments: Option<&str>) -> String {
+    let mut words = vec!["rum run".to_string()];
+    for extension in extensions {
+        words.push(match extension {
+            Extension::Plugin(name) => format!("--plugin lib{name}.so"),
+            other => other.option(),
+        });
+    }
+    words.push(program.as_ref().display().to_string());
+    match arguments {
+        Some("") => words.push("-- ARGS...".to_string()),
+        Some(synopsis) => words.push(format!("-- {synopsis}")),
+        None => {}
+    }
+    words.join(" ")
+}
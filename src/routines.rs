@@ -0,0 +1,74 @@
+//! Routine inference for `rum routines`: where the routines of segment 0
+//! start, how long they are and who calls them, as a symbol file (see
+//! `symbols`).
+//!
+//! The UM has no call instruction, so calls are recognized by how they are
+//! made, from two sources:
+//!
+//! - statically (`analyze`), a jump to a known address made while another
+//!   register holds the address after the jump, to return to;
+//! - dynamically, from a jump profile (`rum profile --jumps`), a site whose
+//!   jumps are matched by as many jumps back to the word after it, give or
+//!   take a tenth for calls still open when the run stopped. A loop's back
+//!   edge fails this, as it is left only once.
+//!
+//! The targets of calls, and address 0 as `start`, are entry points. A
+//! routine runs up to the next one, less trailing words that are neither
+//! reachable nor seen in the profile, which are taken for data. Routines
+//! are named after their entry, `fn_4652`, as in flamegraphs.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::analyze::analyze;
+use crate::jumps::JumpProfile;
+use crate::symbols::{Routine, Symbols};
+
+/// The routines of `program`, from static analysis and, if given, the
+/// jump profile of a run.
+pub fn infer(program: &[u32], jumps: Option<&JumpProfile>) -> Symbols {
+    let analysis = analyze(program);
+    let mut code = analysis.reachable.clone();
+    // Callers of each entry point.
+    let mut entries: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::from([(0, BTreeSet::new())]);
+    for &(site, target) in &analysis.calls {
+        entries.entry(target).or_default().insert(site);
+    }
+    if let Some(jumps) = jumps {
+        let edges = jumps.edges();
+        // Jumps (falling through aside) out of each site and into each address.
+        let (mut out, mut into) = (HashMap::new(), HashMap::new());
+        for &(from, to, count) in edges.iter().filter(|&&(from, to, _)| to != from + 1) {
+            *out.entry(from).or_insert(0u64) += count;
+            *into.entry(to).or_insert(0u64) += count;
+        }
+        let returned = |site: usize| {
+            let (calls, returns) = (out[&site], into.get(&(site + 1)).copied().unwrap_or(0));
+            returns > 0 && calls.abs_diff(returns) <= calls / 10
+        };
+        for &(from, to, _) in edges.iter().filter(|&&(from, to, _)| to != from + 1) {
+            for address in [from, to] {
+                if let Some(mark) = code.get_mut(address) {
+                    *mark = true;
+                }
+            }
+            if returned(from) {
+                entries.entry(to).or_default().insert(from);
+            }
+        }
+    }
+    entries.retain(|&entry, _| entry < program.len());
+
+    let starts: Vec<usize> = entries.keys().copied().collect();
+    let routines = entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, (entry, callers))| {
+            let end = starts.get(i + 1).copied().unwrap_or(program.len());
+            // Keep the entry even if nothing else of it is known to be code.
+            let size = code[entry..end].iter().rposition(|&mark| mark).map_or(1, |last| last + 1);
+            let name = if entry == 0 { "start".to_string() } else { format!("fn_{entry}") };
+            Routine { entry, size, name, callers: callers.into_iter().collect() }
+        })
+        .collect();
+    Symbols::new(routines)
+}
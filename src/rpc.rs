@@ -0,0 +1,674 @@
+//! `rum serve-rpc`: machine lifecycle over JSON-RPC 2.0.
+//!
+//! Requests and responses are one JSON object per line, on stdio or on
+//! each TCP connection accepted with `--listen`. Every connection has its
+//! own set of machines, named by the ids `create` returns; over TCP the
+//! connections are sessions of a `sessions::Manager`, which limits how many
+//! are open and closes idle ones.
+//!
+//! | method            | params                                   | result |
+//! |-------------------|------------------------------------------|--------|
+//! | `create`          | one of `path`, `snapshot`, `program`     | `{machine}` |
+//! | `load`            | `machine` + one of the above             | `null` |
+//! | `destroy`         | `machine`                                | `null` |
+//! | `step`            | `machine`, `count` (1)                   | stop report |
+//! | `run`             | `machine`, `fuel` (unlimited)            | stop report |
+//! | `feed`            | `machine`, `input`, `close` (false)      | `null` |
+//! | `getRegisters`    | `machine`                                | `[r0..r7]` |
+//! | `setRegister`     | `machine`, `register`, `value`           | `null` |
+//! | `readMemory`      | `machine`, `segment`, `offset`, `count`  | `[words]` |
+//! | `writeMemory`     | `machine`, `segment`, `offset`, `words`  | `null` |
+//! | `setBreakpoint`   | `machine`, `address`                     | `null` |
+//! | `clearBreakpoint` | `machine`, `address`                     | `null` |
+//! | `setWatchpoint`   | `machine`, `segment`, `offset`, `access` (`write`) | `null` |
+//! | `clearWatchpoint` | `machine`, `segment`, `offset`           | `null` |
+//! | `listBreakpoints` | `machine`                                | `{breakpoints, watchpoints}` |
+//! | `getState`        | `machine`                                | machine state |
+//! | `listSegments`    | `machine`                                | `[{segment, words}]` |
+//! | `disassemble`     | `machine`, `address` (pc), `count` (1)   | `[{address, word, text}]` |
+//! | `saveState`       | `machine`, `name`                        | `null` |
+//! | `restoreState`    | `machine`, `name`                        | machine state |
+//! | `subscribe`       | `machine`, `enabled` (true)              | `null` |
+//!
+//! A stop report is `{reason, pc, executed, output}` where `reason` is
+//! `halted`, `fuel`, `breakpoint`, `watchpoint`, `input` (the program
+//! wants input that has not been fed, and `close` was not given) or
+//! `shutdown` (the server is shutting down, see `sessions`). A
+//! watchpoint report also has `watch: {segment, offset, access, old, new}`.
+//! Watchpoints fire on Load (`read`), Store (`write`) or both (`access`),
+//! and like breakpoints only stop `run`. Machine state is `{status, pc,
+//! executed, registers, next, wantsInput, mappedBytes}` with `status` one
+//! of `running`, `waiting` (blocked on input that has not been fed, as
+//! the `input` stop reason), `halted` or `faulted`. Slots from
+//! `saveState` belong to the machine and hold its memory, registers and
+//! pending input. Byte strings (`input`, `output`) are JSON strings with one character U+0000-U+00FF per byte.
+//! Once a machine is subscribed its output is sent as `output`
+//! notifications (`{machine, data}`) ahead of the response instead.
+//!
+//! Every machine is tracked in the `metrics` registry under `rpc`, and
+//! error responses are counted by code; `--metrics ADDR` serves them.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::cache::ProgramCache;
+use crate::embed::{Machine, Status};
+use crate::error::MachineError;
+use crate::json::{self, Value};
+use crate::rumdis::{describe, get, op, Opcode, RA, RB, RC};
+use crate::metrics::{self, Registry};
+use crate::seal::Key;
+use crate::sessions::{self, Manager, Ticket};
+use crate::snapshot::{load_snapshot, save_snapshot};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The program faulted, or the machine is unusable.
+const MACHINE_ERROR: i64 = -32000;
+/// The server turned the connection away (see `sessions`).
+const SERVER_BUSY: i64 = -32001;
+
+/// How long a write to a client may block before the session is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Reply = Result<Value, (i64, String)>;
+
+fn invalid(message: impl Into<String>) -> (i64, String) {
+    (INVALID_PARAMS, message.into())
+}
+
+/// Which memory accesses a watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    Any,
+}
+
+impl Access {
+    fn parse(name: &str) -> Option<Access> {
+        match name {
+            "read" => Some(Access::Read),
+            "write" => Some(Access::Write),
+            "access" => Some(Access::Any),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Any => "access",
+        }
+    }
+
+    fn covers(self, write: bool) -> bool {
+        match self {
+            Access::Read => !write,
+            Access::Write => write,
+            Access::Any => true,
+        }
+    }
+}
+
+/// A watched word about to be touched by the next instruction.
+struct Touch {
+    segment: u32,
+    offset: u32,
+    write: bool,
+    old: Option<u32>,
+}
+
+/// A machine and what the client has set up around it.
+struct Entry {
+    machine: Machine,
+    input: VecDeque<u8>,
+    input_closed: bool,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeMap<(u32, u32), Access>,
+    slots: HashMap<String, (Machine, VecDeque<u8>, bool)>,
+    subscribed: bool,
+    faulted: bool,
+    tracked: metrics::Session,
+}
+
+impl Entry {
+    fn new(machine: Machine) -> Self {
+        let entry = Self {
+            machine,
+            input: VecDeque::new(),
+            input_closed: false,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            slots: HashMap::new(),
+            subscribed: false,
+            faulted: false,
+            tracked: Registry::global().open("rpc"),
+        };
+        entry.track();
+        entry
+    }
+
+    /// True when the program wants input and none has been fed.
+    fn waiting(&self) -> bool {
+        self.input.is_empty() && !self.input_closed && !self.faulted && self.machine.wants_input()
+    }
+
+    /// Records the machine's progress so far in the metrics registry.
+    fn track(&self) {
+        self.tracked.update(self.machine.executed(), self.machine.state.mapped_bytes());
+        self.tracked.waiting(self.waiting());
+    }
+
+    /// The watched word the next instruction loads or stores, if any.
+    fn touch(&self) -> Option<Touch> {
+        let state = &self.machine.state;
+        let instruction = *state.mapped_memory.first()?.get(state.program_counter)?;
+        let register = |field| state.register(get(field, instruction));
+        let (segment, offset, write) = match op(instruction)? {
+            Opcode::SegLoad => (register(&RB), register(&RC), false),
+            Opcode::SegStore => (register(&RA), register(&RB), true),
+            _ => return None,
+        };
+        let access = self.watchpoints.get(&(segment, offset))?;
+        let old = state.mapped_memory.get(segment as usize).and_then(|words| words.get(offset as usize)).copied();
+        access.covers(write).then_some(Touch { segment, offset, write, old })
+    }
+
+    fn state(&self) -> Value {
+        let machine = &self.machine;
+        let status = match (self.faulted, machine.status()) {
+            (true, _) => "faulted",
+            (false, Status::Halted) => "halted",
+            (false, Status::Running) if self.waiting() => "waiting",
+            (false, Status::Running) => "running",
+        };
+        let pc = machine.state.program_counter;
+        let next = machine.state.mapped_memory.first().and_then(|program| program.get(pc));
+        Value::object([
+            ("status", status.into()),
+            ("pc", pc.into()),
+            ("executed", machine.executed().into()),
+            ("registers", (0..8).map(|r| machine.state.register(r)).collect::<Vec<_>>().into()),
+            ("next", next.map_or(Value::Null, |&word| describe(word).into())),
+            ("wantsInput", machine.wants_input().into()),
+            ("mappedBytes", machine.state.mapped_bytes().into()),
+        ])
+    }
+}
+
+/// The machines of one connection.
+#[derive(Default)]
+pub struct Session {
+    machines: HashMap<u64, Entry>,
+    next_id: u64,
+    /// Set to stop running machines with reason `shutdown`.
+    interrupt: Option<Arc<AtomicBool>>,
+    /// Seals the snapshots saved and opens the ones `create` and `load` read.
+    key: Option<Key>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops `run` and `step` with reason `shutdown` once `flag` is set.
+    pub fn interrupt_on(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = Some(flag);
+    }
+
+    /// Seals and opens this session's snapshots with `key` (see `seal`).
+    pub fn snapshot_key(&mut self, key: Option<Key>) {
+        self.key = key;
+    }
+
+    /// Saves every machine that can still run, neither halted nor faulted,
+    /// to `path(id)`, returning `{machine, path}` for each one saved.
+    fn save_active(&self, path: impl Fn(u64) -> PathBuf) -> Vec<Value> {
+        let mut ids: Vec<u64> = self.machines.keys().copied().collect();
+        ids.sort_unstable();
+        let mut saved = Vec::new();
+        for id in ids {
+            let entry = &self.machines[&id];
+            if entry.faulted || entry.machine.status() == Status::Halted {
+                continue;
+            }
+            let path = path(id);
+            match save_snapshot(&entry.machine.state, &path, self.key.as_ref()) {
+                Ok(()) => saved.push(Value::object([("machine", id.into()), ("path", path.display().to_string().into())])),
+                Err(e) => eprintln!("rum: cannot save machine {id} to {}: {e}", path.display()),
+            }
+        }
+        saved
+    }
+
+    /// Handles one line of input, returning the lines to send back:
+    /// notifications first, then the response (none for a notification).
+    pub fn handle_line(&mut self, line: &str) -> Vec<Value> {
+        let request = match json::parse(line) {
+            Ok(request) => request,
+            Err(e) => {
+                Registry::global().error("rpc", error_kind(PARSE_ERROR));
+                return vec![response(Value::Null, Err((PARSE_ERROR, e)))];
+            }
+        };
+        let id = request.get("id").cloned();
+        let mut notifications = Vec::new();
+        let reply = match request.get("method").and_then(Value::as_str) {
+            Some(method) => {
+                let params = request.get("params").cloned().unwrap_or(Value::Object(Vec::new()));
+                self.call(method, &params, &mut notifications)
+            }
+            None => Err((INVALID_REQUEST, "missing method".to_string())),
+        };
+        if let Err((code, _)) = &reply {
+            Registry::global().error("rpc", error_kind(*code));
+        }
+        if let Some(id) = id {
+            notifications.push(response(id, reply));
+        }
+        notifications
+    }
+
+    fn call(&mut self, method: &str, params: &Value, notifications: &mut Vec<Value>) -> Reply {
+        match method {
+            "create" => {
+                let machine = machine_from(params, self.key.as_ref())?;
+                self.next_id += 1;
+                self.machines.insert(self.next_id, Entry::new(machine));
+                Ok(Value::object([("machine", self.next_id.into())]))
+            }
+            "load" => {
+                let machine = machine_from(params, self.key.as_ref())?;
+                *self.entry(params)? = Entry::new(machine);
+                Ok(Value::Null)
+            }
+            "destroy" => {
+                let id = number(params, "machine")?;
+                self.machines.remove(&id).ok_or_else(|| invalid(format!("no machine {id}")))?;
+                Ok(Value::Null)
+            }
+            "step" => {
+                let count = optional(params, "count")?.unwrap_or(1);
+                self.execute(params, Some(count), false, notifications)
+            }
+            "run" => {
+                let fuel = optional(params, "fuel")?;
+                self.execute(params, fuel, true, notifications)
+            }
+            "feed" => {
+                let entry = self.entry(params)?;
+                let input = params.get("input").and_then(Value::as_str).unwrap_or("");
+                entry.input.extend(json::string_to_bytes(input).map_err(invalid)?);
+                if params.get("close").and_then(Value::as_bool) == Some(true) {
+                    entry.input_closed = true;
+                }
+                entry.track();
+                Ok(Value::Null)
+            }
+            "getRegisters" => {
+                let state = &self.entry(params)?.machine.state;
+                Ok((0..8).map(|r| state.register(r)).collect::<Vec<_>>().into())
+            }
+            "setRegister" => {
+                let (register, value) = (number(params, "register")?, number(params, "value")?);
+                let value = u32::try_from(value).map_err(|_| invalid("value does not fit in a word"))?;
+                if register >= 8 {
+                    return Err(invalid("registers are numbered 0-7"));
+                }
+                self.entry(params)?.machine.state.load_value(register as u32, value);
+                Ok(Value::Null)
+            }
+            "readMemory" => {
+                let (segment, offset, count) = (number(params, "segment")?, number(params, "offset")?, number(params, "count")?);
+                let words = &self.entry(params)?.machine.state.mapped_memory;
+                let words = words.get(segment as usize).and_then(|s| s.get(offset as usize..offset.checked_add(count)? as usize));
+                let words = words.ok_or_else(|| invalid("range is outside the segment"))?;
+                Ok(words.to_vec().into())
+            }
+            "writeMemory" => {
+                let (segment, offset) = (number(params, "segment")?, number(params, "offset")?);
+                let words: Vec<u32> = params
+                    .get("words")
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| invalid("missing words"))?
+                    .iter()
+                    .map(|w| w.as_u64().and_then(|w| u32::try_from(w).ok()))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| invalid("words must be 32-bit numbers"))?;
+                let state = &mut self.entry(params)?.machine.state;
+                let end = offset as usize + words.len();
+                let target = state.segment_mut(segment as usize).and_then(|s| s.get_mut(offset as usize..end));
+                target.ok_or_else(|| invalid("range is outside the segment"))?.copy_from_slice(&words);
+                Ok(Value::Null)
+            }
+            "setBreakpoint" | "clearBreakpoint" => {
+                let address = number(params, "address")? as usize;
+                let breakpoints = &mut self.entry(params)?.breakpoints;
+                if method == "setBreakpoint" {
+                    breakpoints.insert(address);
+                } else {
+                    breakpoints.remove(&address);
+                }
+                Ok(Value::Null)
+            }
+            "setWatchpoint" => {
+                let (segment, offset) = (word(params, "segment")?, word(params, "offset")?);
+                let name = params.get("access").and_then(Value::as_str).unwrap_or("write");
+                let access = Access::parse(name).ok_or_else(|| invalid("access must be read, write or access"))?;
+                self.entry(params)?.watchpoints.insert((segment, offset), access);
+                Ok(Value::Null)
+            }
+            "clearWatchpoint" => {
+                let (segment, offset) = (word(params, "segment")?, word(params, "offset")?);
+                self.entry(params)?.watchpoints.remove(&(segment, offset));
+                Ok(Value::Null)
+            }
+            "listBreakpoints" => {
+                let entry = self.entry(params)?;
+                let watchpoints = entry.watchpoints.iter().map(|(&(segment, offset), access)| {
+                    Value::object([("segment", segment.into()), ("offset", offset.into()), ("access", access.name().into())])
+                });
+                Ok(Value::object([
+                    ("breakpoints", entry.breakpoints.iter().copied().collect::<Vec<_>>().into()),
+                    ("watchpoints", Value::Array(watchpoints.collect())),
+                ]))
+            }
+            "getState" => Ok(self.entry(params)?.state()),
+            "listSegments" => {
+                let state = &self.entry(params)?.machine.state;
+                let free: BTreeSet<u32> = state.free_segments().iter().copied().collect();
+                let segments = state.mapped_memory.iter().enumerate().filter(|(id, _)| !free.contains(&(*id as u32)));
+                let segments = segments.map(|(id, words)| Value::object([("segment", id.into()), ("words", words.len().into())]));
+                Ok(Value::Array(segments.collect()))
+            }
+            "disassemble" => {
+                let state = &self.entry(params)?.machine.state;
+                let address = optional(params, "address")?.map_or(state.program_counter, |a| a as usize);
+                let count = optional(params, "count")?.unwrap_or(1) as usize;
+                let program = state.mapped_memory.first().map_or(&[][..], Vec::as_slice);
+                let words = program.iter().enumerate().skip(address).take(count);
+                let listing = words.map(|(address, &word)| {
+                    Value::object([("address", address.into()), ("word", word.into()), ("text", describe(word).into())])
+                });
+                Ok(Value::Array(listing.collect()))
+            }
+            "saveState" => {
+                let name = params.get("name").and_then(Value::as_str).ok_or_else(|| invalid("missing name"))?.to_string();
+                let entry = self.entry(params)?;
+                if entry.faulted {
+                    return Err((MACHINE_ERROR, "the machine faulted earlier".to_string()));
+                }
+                let slot = (entry.machine.clone(), entry.input.clone(), entry.input_closed);
+                entry.slots.insert(name, slot);
+                Ok(Value::Null)
+            }
+            "restoreState" => {
+                let name = params.get("name").and_then(Value::as_str).ok_or_else(|| invalid("missing name"))?;
+                let entry = self.entry(params)?;
+                let (machine, input, input_closed) = entry.slots.get(name).cloned().ok_or_else(|| invalid(format!("no slot named {name}")))?;
+                (entry.machine, entry.input, entry.input_closed, entry.faulted) = (machine, input, input_closed, false);
+                entry.track();
+                Ok(entry.state())
+            }
+            "subscribe" => {
+                let enabled = params.get("enabled").and_then(Value::as_bool).unwrap_or(true);
+                self.entry(params)?.subscribed = enabled;
+                Ok(Value::Null)
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{method}`"))),
+        }
+    }
+
+    fn entry(&mut self, params: &Value) -> Result<&mut Entry, (i64, String)> {
+        let id = number(params, "machine")?;
+        self.machines.get_mut(&id).ok_or_else(|| invalid(format!("no machine {id}")))
+    }
+
+    /// Executes up to `fuel` instructions. `run` stops at breakpoints (after
+    /// the first instruction, so it can continue from one) and after an
+    /// instruction that hit a watchpoint; `step` doesn't.
+    fn execute(&mut self, params: &Value, fuel: Option<u64>, breaks: bool, notifications: &mut Vec<Value>) -> Reply {
+        let id = number(params, "machine")?;
+        let interrupt = self.interrupt.clone();
+        let entry = self.entry(params)?;
+        if entry.faulted {
+            return Err((MACHINE_ERROR, "the machine faulted earlier".to_string()));
+        }
+        let mut output = Vec::new();
+        let mut reason = "fuel";
+        let mut watch = None;
+        let mut run = || {
+            let mut left = fuel;
+            while left != Some(0) {
+                if entry.machine.status() == Status::Halted {
+                    reason = "halted";
+                    break;
+                }
+                if interrupt.as_ref().is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+                    reason = "shutdown";
+                    break;
+                }
+                if entry.input.is_empty() && !entry.input_closed && entry.machine.wants_input() {
+                    reason = "input";
+                    break;
+                }
+                let touch = breaks.then(|| entry.touch()).flatten();
+                let queue = &mut entry.input;
+                entry.machine.step(&mut || queue.pop_front(), &mut |byte| output.push(byte))?;
+                left = left.map(|n| n - 1);
+                if let Some(touch) = touch {
+                    watch = Some(watched(&touch, &entry.machine));
+                    reason = "watchpoint";
+                    break;
+                }
+                if breaks && entry.breakpoints.contains(&entry.machine.state.program_counter) {
+                    reason = "breakpoint";
+                    break;
+                }
+            }
+            if entry.machine.status() == Status::Halted {
+                reason = "halted";
+            }
+            Ok::<_, MachineError>(())
+        };
+        let result = run();
+        entry.track();
+        let data = Value::String(json::bytes_to_string(&output));
+        if entry.subscribed && !output.is_empty() {
+            notifications.push(Value::object([
+                ("jsonrpc", "2.0".into()),
+                ("method", "output".into()),
+                ("params", Value::object([("machine", id.into()), ("data", data.clone())])),
+            ]));
+        }
+        if let Err(e) = result {
+            entry.faulted = true;
+            return Err((MACHINE_ERROR, format!("program faulted after {} instructions: {e}", entry.machine.executed())));
+        }
+        let mut report = vec![
+            ("reason", reason.into()),
+            ("pc", entry.machine.state.program_counter.into()),
+            ("executed", entry.machine.executed().into()),
+            ("output", if entry.subscribed { Value::String(String::new()) } else { data }),
+        ];
+        report.extend(watch.map(|watch| ("watch", watch)));
+        Ok(Value::object(report))
+    }
+}
+
+/// The `watch` field of a stop report, once the touching instruction has executed.
+fn watched(touch: &Touch, machine: &Machine) -> Value {
+    let state = &machine.state;
+    let new = match touch.write {
+        true => state.mapped_memory.get(touch.segment as usize).and_then(|words| words.get(touch.offset as usize)).copied(),
+        false => touch.old,
+    };
+    Value::object([
+        ("segment", touch.segment.into()),
+        ("offset", touch.offset.into()),
+        ("access", if touch.write { "write" } else { "read" }.into()),
+        ("old", touch.old.map_or(Value::Null, Value::from)),
+        ("new", new.map_or(Value::Null, Value::from)),
+    ])
+}
+
+/// The `kind` label an error code is counted under.
+fn error_kind(code: i64) -> &'static str {
+    match code {
+        PARSE_ERROR => "parse_error",
+        INVALID_REQUEST => "invalid_request",
+        METHOD_NOT_FOUND => "method_not_found",
+        INVALID_PARAMS => "invalid_params",
+        _ => "machine_error",
+    }
+}
+
+fn response(id: Value, reply: Reply) -> Value {
+    let outcome = match reply {
+        Ok(result) => ("result", result),
+        Err((code, message)) => ("error", Value::object([("code", Value::Number(code as f64)), ("message", message.into())])),
+    };
+    Value::object([("jsonrpc", "2.0".into()), ("id", id), outcome])
+}
+
+fn optional(params: &Value, key: &str) -> Result<Option<u64>, (i64, String)> {
+    match params.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value.as_u64().map(Some).ok_or_else(|| invalid(format!("{key} must be a whole number"))),
+    }
+}
+
+fn number(params: &Value, key: &str) -> Result<u64, (i64, String)> {
+    optional(params, key)?.ok_or_else(|| invalid(format!("missing {key}")))
+}
+
+fn word(params: &Value, key: &str) -> Result<u32, (i64, String)> {
+    u32::try_from(number(params, key)?).map_err(|_| invalid(format!("{key} does not fit in a word")))
+}
+
+/// Builds a machine from a `.um` `path`, a `.rumstate` `snapshot` or a `program` array of words.
+fn machine_from(params: &Value, key: Option<&Key>) -> Result<Machine, (i64, String)> {
+    if let Some(path) = params.get("path").and_then(Value::as_str) {
+        let bytes = std::fs::read(path).map_err(|e| invalid(format!("{path}: {e}")))?;
+        let program = ProgramCache::global().decode(&bytes).map_err(|e| invalid(format!("{path}: {e}")))?;
+        return Ok(Machine::new(program.to_vec()));
+    }
+    if let Some(path) = params.get("snapshot").and_then(Value::as_str) {
+        return load_snapshot(path, key).map(Machine::from_state).map_err(|e| invalid(format!("{path}: {e}")));
+    }
+    if let Some(words) = params.get("program").and_then(Value::as_array) {
+        let words = words.iter().map(|w| w.as_u64().and_then(|w| u32::try_from(w).ok())).collect::<Option<_>>();
+        return words.map(Machine::new).ok_or_else(|| invalid("program must be an array of 32-bit words"));
+    }
+    Err(invalid("expected path, snapshot or program"))
+}
+
+/// Serves one client: requests from `input`, responses to `output`, until
+/// end of input, with snapshots sealed and opened with `key`.
+pub fn serve(input: impl BufRead, mut output: impl Write, key: Option<Key>) -> std::io::Result<()> {
+    let mut session = Session::new();
+    session.snapshot_key(key);
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        for reply in session.handle_line(&line) {
+            writeln!(output, "{reply}")?;
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// Accepts TCP connections on `addr` as sessions of `manager`, serving
+/// each on its own thread, until `manager` shuts down and they have closed.
+pub fn listen(addr: impl ToSocketAddrs, manager: &Manager) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("rum: serving JSON-RPC on {}", listener.local_addr()?);
+    accept(listener, manager)
+}
+
+/// Same as `listen`, on a listener already bound.
+pub fn accept(listener: TcpListener, manager: &Manager) -> std::io::Result<()> {
+    // Polled, so a shutdown is noticed without waiting for a connection.
+    listener.set_nonblocking(true)?;
+    while !manager.closing() {
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(sessions::POLL);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        stream.set_nonblocking(false)?;
+        match manager.admit() {
+            Ok(ticket) => {
+                let manager = manager.clone();
+                std::thread::spawn(move || connection(stream, ticket, &manager));
+            }
+            Err(message) => {
+                Registry::global().error("rpc", "session_limit");
+                let _ = writeln!(stream, "{}", response(Value::Null, Err((SERVER_BUSY, message))));
+            }
+        }
+    }
+    drop(listener);
+    manager.wait();
+    Ok(())
+}
+
+/// Serves one session until the client hangs up, it goes idle or the server shuts down.
+fn connection(stream: TcpStream, ticket: Ticket, manager: &Manager) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(sessions::POLL))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut output = stream;
+    let mut session = Session::new();
+    session.interrupt_on(ticket.interrupt());
+    session.snapshot_key(manager.limits().snapshot_key.clone());
+    let mut line = String::new();
+    let mut last_request = Instant::now();
+    loop {
+        match reader.read_line(&mut line) {
+            Ok(0) => return Ok(()),
+            // A line cut short by the timeout is kept and finished by the next read.
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) => return Err(e),
+            Ok(_) => {
+                if !line.trim().is_empty() {
+                    for reply in session.handle_line(line.trim_end()) {
+                        writeln!(output, "{reply}")?;
+                    }
+                    output.flush()?;
+                }
+                line.clear();
+                last_request = Instant::now();
+            }
+        }
+        let limits = manager.limits();
+        let (reason, snapshots) = if ticket.closing() {
+            let saved = limits.snapshot_dir.as_ref().map(|dir| session.save_active(|id| sessions::snapshot_path(dir, ticket.id(), id)));
+            ("shutdown", saved.unwrap_or_default())
+        } else if limits.idle_timeout.is_some_and(|idle| last_request.elapsed() >= idle) {
+            ("idle", Vec::new())
+        } else {
+            continue;
+        };
+        let params = Value::object([("reason", reason.into()), ("snapshots", Value::Array(snapshots))]);
+        let notification = Value::object([("jsonrpc", "2.0".into()), ("method", "closing".into()), ("params", params)]);
+        writeln!(output, "{notification}")?;
+        return output.flush();
+    }
+}
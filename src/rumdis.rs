@@ -17,60 +17,450 @@ pub enum Opcode {
     LoadVal,
 }
 
-
-use core::panic;
-
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::check::OutputDigest;
+use crate::checkpoint::Checkpoints;
+use crate::clock::Clock;
+use crate::chrometrace::ChromeTrace;
+use crate::coverage::Coverage;
+use crate::cycles::{CostModel, Cycles};
+pub use crate::encoding::{get, Field, OP, RA, RB, RC, RL, VL};
+use crate::eof::Eof;
+pub use crate::error::MachineError;
+use crate::events::{EventLog, Kind};
+use crate::filters::{Pipeline, Spec};
+use crate::inspect::{Inspector, PUBLISH_EVERY};
+use crate::policy::{Guard, Policy};
+use crate::replay::Recorder;
+use crate::progress::{Progress, CHECK_EVERY};
+use crate::seal::Key;
 use crate::state::UniversalMachine;
+use crate::summary::{Exit, Summary};
+use crate::throttle::Throttle;
+use crate::workdir::Workdir;
 type Umi = u32;
-pub struct Field {
-    width: u32,
-    lsb: u32,
+/// Given an instruction word, extract the opcode
+pub fn op(instruction: Umi) -> Option<Opcode> {
+    FromPrimitive::from_u32(get(&OP, instruction))
 }
 
-static RA: Field = Field {width: 3, lsb: 6};
-static RB: Field = Field {width: 3, lsb: 3};
-static RC: Field = Field {width: 3, lsb: 0};
-static RL: Field = Field {width: 3, lsb: 25};
-static VL: Field = Field {width: 25, lsb: 0};
-static OP: Field = Field {width: 4, lsb: 28};
+/// Mnemonics by opcode, as `describe` writes them.
+pub const MNEMONICS: [&str; 16] = [
+    "cmov", "load", "store", "add", "mul", "div", "nand", "halt", "map", "unmap", "out", "in", "loadprog", "loadval", "op14", "op15",
+];
+
+/// Exit status used when a run is stopped by one of its limits.
+pub const LIMIT_EXIT_CODE: i32 = 124;
 
-fn mask(bits: u32) -> u32 { (1 << bits) - 1 }
+/// Exit status used when the program fails (a `MachineError`), as it was
+/// when failures were panics.
+pub const FAULT_EXIT_CODE: i32 = 101;
 
-/// Given a `field` and `instruction`, extract
-/// that field from the instruction as a u32
-pub fn get(field: &Field, instruction: Umi) -> u32 {
-    (instruction >> field.lsb) & mask(field.width)
+/// What executing one instruction means for the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepResult {
+    /// Carry on with the next instruction.
+    Continue,
+    /// The program executed Halt.
+    Halt,
 }
 
-/// Given an instruction word, extract the opcode
-fn op(instruction: Umi) -> Option<Opcode> {
-    FromPrimitive::from_u32((instruction >> OP.lsb) & mask(OP.width))
+/// What `UniversalMachine::step` executed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// The instruction's address.
+    pub pc: usize,
+    pub instruction: u32,
+    pub opcode: Opcode,
+    /// Registers the instruction read, in operand order (A, B, C).
+    pub read: Vec<u32>,
+    /// The register it wrote, if any: a Conditional Move whose C is zero writes none.
+    pub written: Option<u32>,
+    /// The instruction was Halt.
+    pub halted: bool,
+}
+
+/// How a run ended, when the program did not fail (see `MachineError`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunResult {
+    Halted,
+    /// `RunOptions::max_instructions` stopped the run.
+    InstructionLimit,
+}
+
+impl RunResult {
+    /// rum's exit status for it.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            RunResult::Halted => 0,
+            RunResult::InstructionLimit => LIMIT_EXIT_CODE,
+        }
+    }
+}
+
+/// Knobs for a single run of the machine.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Stop the machine after executing this many instructions.
+    pub max_instructions: Option<u64>,
+    /// Periodically report instruction count, MIPS and memory use on stderr.
+    pub progress: bool,
+    /// Write a coverage trace of the original program here when the run ends.
+    pub coverage: Option<PathBuf>,
+    /// Write a Chrome trace-event (Perfetto) timeline of the run here.
+    pub chrome_trace: Option<PathBuf>,
+    /// Write a rotating checkpoint snapshot every this many instructions.
+    pub checkpoint_every: Option<u64>,
+    /// Base path of the checkpoint files (see `Checkpoints`).
+    pub checkpoint_file: PathBuf,
+    /// Number of checkpoint files kept in rotation.
+    pub checkpoint_keep: usize,
+    /// Write delta checkpoints (only modified segments) between full ones.
+    pub checkpoint_delta: bool,
+    /// zstd level for checkpoints; `None` writes them uncompressed.
+    pub checkpoint_level: Option<i32>,
+    /// Record a deterministic replay log of the run here.
+    pub record: Option<PathBuf>,
+    /// Print executed-opcode counts on stderr when the run ends (feature `opcode-histogram`).
+    pub histogram: bool,
+    /// Write executed-opcode counts here as JSON when the run ends (feature `opcode-histogram`).
+    pub histogram_json: Option<PathBuf>,
+    /// How `histogram` prints the counts.
+    pub report_format: crate::report::Format,
+    /// Time progress and the Chrome trace by the virtual clock (see `clock`).
+    pub deterministic: bool,
+    /// Print a JSON summary of the run on stderr when it ends (see `summary`).
+    pub summary: bool,
+    /// Hash the output instead of writing it, and print its SHA-256 digest on stderr when the run ends.
+    pub hash_output: bool,
+    /// With `hash_output`, write the output as well.
+    pub tee_output: bool,
+    /// Run no faster than this many million instructions per second (see `throttle`).
+    pub max_mips: Option<f64>,
+    /// Count virtual cycles under this cost model and print them on stderr when the run ends (see `cycles`).
+    pub cycles: Option<CostModel>,
+    /// What Input does at the end of stdin (see `eof`).
+    pub eof: Eof,
+    /// Collect the output, stats and a core dump on failure in this run
+    /// directory (see `workdir`); `record` and `checkpoint_file` point into it too.
+    pub workdir: Option<Workdir>,
+    /// Answer `rum inspect` and `rum top` about the run (see `inspect`).
+    pub inspect: Option<Inspector>,
+    /// Fault on the opcodes this denies or limits (see `policy`).
+    pub policy: Option<Policy>,
+    /// Pass the output through these filters on its way to stdout (see `filters`).
+    pub output_filters: Vec<Spec>,
+    /// Log I/O, memory and program events of the run here (see `events`).
+    pub event_log: Option<PathBuf>,
+    /// Seal checkpoints, the replay log's snapshot and core dumps with this key (see `seal`).
+    pub snapshot_key: Option<Key>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            max_instructions: None,
+            progress: false,
+            coverage: None,
+            chrome_trace: None,
+            checkpoint_every: None,
+            checkpoint_file: PathBuf::from("rum-checkpoint"),
+            checkpoint_keep: 3,
+            checkpoint_delta: false,
+            checkpoint_level: Some(crate::snapshot::DEFAULT_LEVEL),
+            record: None,
+            histogram: false,
+            histogram_json: None,
+            report_format: crate::report::Format::Text,
+            deterministic: false,
+            summary: false,
+            hash_output: false,
+            tee_output: false,
+            max_mips: None,
+            cycles: None,
+            eof: Eof::default(),
+            workdir: None,
+            inspect: None,
+            policy: None,
+            output_filters: Vec::new(),
+            event_log: None,
+            snapshot_key: None,
+        }
+    }
 }
 
-pub fn run(state: &mut UniversalMachine, instr: Vec<u32>){
+/// Runs `instr` on stdin and stdout until it halts or fails, returning to
+/// the caller, which decides whether to exit.
+pub fn run(state: &mut UniversalMachine, instr: Vec<u32>) -> Result<RunResult, MachineError> {
+    run_with(state, instr, &RunOptions::default())
+}
+
+/// Same as `run`, but honouring the limits in `options`.
+pub fn run_with(state: &mut UniversalMachine, instr: Vec<u32>, options: &RunOptions) -> Result<RunResult, MachineError> {
     state.mapped_memory.push(instr);
-    //let mut count = 0;
-    loop {
-        //count+=1;
-        let instruction = state.mapped_memory.get(0).unwrap().get(state.program_counter).unwrap();
+    resume_with(state, options)
+}
+
+/// Continues executing a machine whose program is already loaded in
+/// segment 0, e.g. one restored from a snapshot.
+pub fn resume_with(state: &mut UniversalMachine, options: &RunOptions) -> Result<RunResult, MachineError> {
+    state.set_eof(options.eof);
+    let mut coverage = options.coverage.as_ref().map(|_| Coverage::new(state.mapped_memory[0].len()));
+    let mut trace = options.chrome_trace.as_ref().and_then(|path| {
+        ChromeTrace::with_clock(path, Clock::new(options.deterministic)).map_err(|e| eprintln!("rum: cannot write trace to {}: {e}", path.display())).ok()
+    });
+    let mut checkpoints = match options.checkpoint_delta {
+        true => Checkpoints::with_deltas(&options.checkpoint_file, options.checkpoint_keep),
+        false => Checkpoints::new(&options.checkpoint_file, options.checkpoint_keep),
+    }
+    .compression(options.checkpoint_level)
+    .key(options.snapshot_key.clone());
+    let pc = state.program_counter;
+    let mut recorder = options
+        .record
+        .as_ref()
+        .map(|path| Recorder::create(path, state, options.snapshot_key.as_ref()).map_err(|e| MachineError::Io { pc, message: format!("cannot record to {}: {e}", path.display()) }))
+        .transpose()?;
+    #[cfg(feature = "opcode-histogram")]
+    let mut histogram = crate::histogram::Histogram::default();
+    let mut count: u64 = 0;
+    let mut progress = options.progress.then(|| Progress::with_clock(Clock::new(options.deterministic)));
+    let summary = (options.summary || options.workdir.is_some()).then(|| Summary::new(state, Clock::new(options.deterministic)));
+    let mut digest = options.hash_output.then(OutputDigest::default);
+    let mut throttle = options.max_mips.map(Throttle::new);
+    let mut cycles = options.cycles.map(Cycles::new);
+    let mut guard = options.policy.map(Guard::new);
+    let mut filters = (!options.output_filters.is_empty())
+        .then(|| Pipeline::build(&options.output_filters, Clock::new(options.deterministic)).map_err(|e| MachineError::Io { pc, message: format!("cannot filter output: {e}") }))
+        .transpose()?;
+    let mut events = options
+        .event_log
+        .as_ref()
+        .map(|path| EventLog::create(path).map_err(|e| MachineError::Io { pc, message: format!("cannot write the event log to {}: {e}", path.display()) }))
+        .transpose()?;
+    let mut transcript = options.workdir.as_ref().and_then(|workdir| {
+        workdir.transcript().map_err(|e| eprintln!("rum: cannot write the output transcript in {}: {e}", workdir.path().display())).ok()
+    });
+    #[cfg(feature = "tracing")]
+    let _span = tracing::info_span!(target: "rum", "run", pc = state.program_counter, program_words = state.mapped_memory[0].len()).entered();
+    let mut execute = || -> Result<bool, MachineError> { loop {
+        if options.max_instructions == Some(count) {
+            return Ok(true);
+        }
+        if let Some(progress) = progress.as_mut() {
+            if count.is_multiple_of(CHECK_EVERY) {
+                progress.tick(count, state);
+            }
+        }
+        if let Some(inspector) = &options.inspect {
+            if count.is_multiple_of(PUBLISH_EVERY) {
+                inspector.publish(count, state);
+            }
+        }
+        if let Some(throttle) = throttle.as_mut() {
+            if count.is_multiple_of(throttle.every()) {
+                throttle.pace(count);
+            }
+        }
+        if let Some(every) = options.checkpoint_every {
+            if count > 0 && count.is_multiple_of(every) {
+                if let Err(e) = checkpoints.save(state) {
+                    warn!(error = %e, "checkpoint failed");
+                    eprintln!("rum: checkpoint failed: {e}");
+                }
+            }
+        }
+        heartbeat!(count, state);
+        count += 1;
+        let pc = state.program_counter;
+        let instruction = *state.mapped_memory[0].get(pc).ok_or(MachineError::ProgramCounterOutOfBounds { pc })?;
+        #[cfg(feature = "edge-coverage")]
+        crate::edges::record(state.program_counter);
+        #[cfg(feature = "opcode-histogram")]
+        histogram.record(instruction);
+        if let Some(coverage) = coverage.as_mut() {
+            coverage.record(state.program_counter, instruction, state);
+        }
+        if let Some(trace) = trace.as_mut() {
+            trace.before(count - 1, state.program_counter, instruction, state);
+        }
+        if let Some(events) = events.as_mut() {
+            events.before(count - 1, state.program_counter, instruction, state);
+        }
+        if let Some(progress) = progress.as_mut() {
+            if op(instruction) == Some(Opcode::Input) && state.pending_input() == 0 {
+                progress.waiting(count - 1, state);
+            }
+        }
+        if let Some(inspector) = &options.inspect {
+            if op(instruction) == Some(Opcode::Input) && state.pending_input() == 0 {
+                inspector.waiting(count - 1, state);
+            }
+        }
         state.program_counter += 1;
-        disassemble(*instruction, state)
+        if let Some(guard) = guard.as_mut() {
+            if let Err(violation) = guard.check(instruction) {
+                error!(instruction, pc, "{violation}");
+                return Err(MachineError::PolicyViolation { pc, message: violation });
+            }
+        }
+        match op(instruction) {
+            Some(Opcode::Input) if recorder.is_some() => {
+                disassemble(instruction, state)?;
+                let value = state.register(get(&RC, instruction));
+                if let Err(e) = recorder.as_mut().unwrap().input(count - 1, value) {
+                    eprintln!("rum: cannot record input: {e}");
+                }
+            }
+            Some(Opcode::Output) if digest.is_some() || transcript.is_some() || options.inspect.is_some() || filters.is_some() => {
+                let c = get(&RC, instruction);
+                let value = state.register(c);
+                let byte = u8::try_from(value).map_err(|_| MachineError::OutputOutOfRange { pc, value: value.into() })?;
+                if let Some(digest) = digest.as_mut() {
+                    digest.push(byte);
+                }
+                match filters.as_mut() {
+                    _ if digest.is_some() && !options.tee_output => state.usage.output_bytes += 1,
+                    Some(filters) => {
+                        std::io::stdout().write_all(filters.push(byte, count)).map_err(|e| MachineError::Io { pc, message: format!("cannot write output: {e}") })?;
+                        state.usage.output_bytes += 1;
+                    }
+                    None => state.output(c)?,
+                }
+                if let Some(transcript) = transcript.as_mut() {
+                    // A failed write shows up again when the transcript is flushed at the end.
+                    let _ = transcript.write_all(&[byte]);
+                }
+                if let Some(inspector) = &options.inspect {
+                    inspector.output(byte);
+                }
+            }
+            _ => {
+                if disassemble(instruction, state)? == StepResult::Halt {
+                    count -= 1;
+                    return Ok(false);
+                }
+            }
+        }
+        if let Some(trace) = trace.as_mut() {
+            trace.after(instruction, state);
+        }
+        if let Some(events) = events.as_mut() {
+            events.after(instruction, state);
+        }
+        if let Some(cycles) = cycles.as_mut() {
+            cycles.record(instruction);
+        }
+    } };
+    let outcome = execute();
+    if let Some(inspector) = &options.inspect {
+        inspector.close();
+    }
+    if let Some(filters) = filters.as_mut() {
+        let _ = std::io::stdout().write_all(&filters.finish());
+    }
+    // A faulting instruction was counted but not executed.
+    let executed = match outcome {
+        Ok(_) => count,
+        Err(_) => count.saturating_sub(1),
+    };
+    if let (Some(path), Some(coverage)) = (&options.coverage, &coverage) {
+        if let Err(e) = coverage.save(path) {
+            eprintln!("rum: cannot write coverage to {}: {e}", path.display());
+        }
+    }
+    if let (Some(path), Some(trace)) = (&options.chrome_trace, trace) {
+        if let Err(e) = trace.finish(executed) {
+            eprintln!("rum: cannot write trace to {}: {e}", path.display());
+        }
+    }
+    if let (Some(path), Some(events)) = (&options.event_log, events) {
+        let end = match &outcome {
+            Ok(true) => events.finish(count, state.program_counter, Kind::Limit),
+            Ok(false) => events.finish(count, state.program_counter - 1, Kind::Halt),
+            Err(fault) => events.finish(executed, fault.pc(), Kind::Fault(fault.message())),
+        };
+        if let Err(e) = end {
+            eprintln!("rum: cannot write the event log to {}: {e}", path.display());
+        }
+    }
+    if let Some(recorder) = recorder {
+        if let Err(e) = recorder.finish(executed) {
+            eprintln!("rum: cannot finish replay log: {e}");
+        }
+    }
+    #[cfg(feature = "opcode-histogram")]
+    {
+        if options.histogram {
+            eprint!("{}", crate::report::render(options.report_format, &histogram.tables(), || histogram.table()));
+        }
+        if let Some(path) = &options.histogram_json {
+            if let Err(e) = std::fs::write(path, format!("{}\n", histogram.to_json())) {
+                eprintln!("rum: cannot write histogram to {}: {e}", path.display());
+            }
+        }
+    }
+    info!(executed, limited = matches!(outcome, Ok(true)), "run finished");
+    if let Some(digest) = digest {
+        let bytes = digest.bytes();
+        eprintln!("rum: output sha256 {} ({bytes} bytes)", digest.finish());
+    }
+    if let Some(cycles) = &cycles {
+        eprintln!("{}", cycles.report(executed));
+    }
+    if let Some(summary) = &summary {
+        let exit = match &outcome {
+            Ok(true) => Exit::InstructionLimit,
+            Ok(false) => Exit::Halted,
+            Err(fault) => Exit::Fault { message: fault.message(), pc: fault.pc() },
+        };
+        let stats = summary.to_json(&exit, executed, state);
+        if options.summary {
+            eprintln!("{stats}");
+        }
+        if let Some(workdir) = &options.workdir {
+            // A faulted run keeps the machine for post-mortem inspection.
+            match outcome {
+                Ok(_) => finish_workdir(workdir, &stats, transcript.as_mut(), None, None),
+                Err(_) => finish_workdir(workdir, &stats, transcript.as_mut(), Some(state), options.snapshot_key.as_ref()),
+            }
+        }
+    }
+    match outcome {
+        Ok(true) => {
+            eprintln!("rum: instruction limit of {count} reached");
+            Ok(RunResult::InstructionLimit)
+        }
+        Ok(false) => Ok(RunResult::Halted),
+        Err(fault) => Err(fault),
+    }
+}
+
+/// Completes `workdir` at the end of a run, reporting where it is.
+fn finish_workdir(workdir: &Workdir, stats: &crate::json::Value, transcript: Option<&mut std::io::BufWriter<std::fs::File>>, core: Option<&UniversalMachine>, key: Option<&Key>) {
+    match workdir.finish(stats, transcript, core, key) {
+        Ok(()) => eprintln!("rum: run saved in {}", workdir.path().display()),
+        Err(e) => eprintln!("rum: cannot complete the run directory {}: {e}", workdir.path().display()),
     }
 }
 
-pub fn disassemble(inst: Umi, state: &mut UniversalMachine) {
+/// Executes `inst` on `state`, whose program counter already points past it,
+/// failing as the UM spec says a program fails.
+pub fn disassemble(inst: Umi, state: &mut UniversalMachine) -> Result<StepResult, MachineError> {
     match op(inst) {
         Some(Opcode::CMov) => {
             state.cmov(get(&RA, inst), get(&RB, inst), get(&RC, inst))
         }
         Some(Opcode::SegLoad) => {
-            state.load(get(&RA, inst), get(&RB, inst), get(&RC, inst))
+            state.load(get(&RA, inst), get(&RB, inst), get(&RC, inst))?
         }
         Some(Opcode::SegStore) => {
-            state.store(get(&RA, inst), get(&RB, inst), get(&RC, inst))
+            state.store(get(&RA, inst), get(&RB, inst), get(&RC, inst))?
         }
         Some(Opcode::Add) => {
             state.add(get(&RA, inst), get(&RB, inst), get(&RC, inst))
@@ -79,33 +469,58 @@ pub fn disassemble(inst: Umi, state: &mut UniversalMachine) {
             state.multiply(get(&RA, inst), get(&RB, inst), get(&RC, inst))
         }
         Some(Opcode::Div) => {
-            state.division(get(&RA, inst), get(&RB, inst), get(&RC, inst))
+            state.division(get(&RA, inst), get(&RB, inst), get(&RC, inst))?
         }
         Some(Opcode::BNand) => {
             state.nand( get(&RA, inst), get(&RB, inst), get(&RC, inst))
         }
         Some(Opcode::Halt) => {
-            //eprintln!("{}", count);
-            state.halt()
+            return Ok(state.halt());
         }
         Some(Opcode::MapSeg) => {
             state.map_seg(get(&RB, inst), get(&RC, inst))
         }
         Some(Opcode::UnmapSeg) => {
-            state.unmap_seg(get(&RC, inst))
+            state.unmap_seg(get(&RC, inst))?
         }
         Some(Opcode::Output) => {
-            state.output(get(&RC, inst))
+            state.output(get(&RC, inst))?
         }
         Some(Opcode::Input) => {
-            state.input(get(&RC, inst))
+            state.input(get(&RC, inst))?
         }
         Some(Opcode::LoadProg) => {
-            state.load_prog(get(&RB, inst), get(&RC, inst))
+            state.load_prog(get(&RB, inst), get(&RC, inst))?
         }
         Some(Opcode::LoadVal) => {
             state.load_value(get(&RL, inst), get(&VL, inst))
         }
-        None => panic!("Invalid Opcode")
+        None => {
+            error!(instruction = inst, pc = state.program_counter, "invalid opcode");
+            return Err(MachineError::InvalidOpcode { pc: state.program_counter.saturating_sub(1), instruction: inst });
+        }
+    }
+    Ok(StepResult::Continue)
+}
+
+/// Renders an instruction as assembly-like text, e.g. `add r1, r2, r3`.
+pub fn describe(inst: Umi) -> String {
+    let (a, b, c) = (get(&RA, inst), get(&RB, inst), get(&RC, inst));
+    match op(inst) {
+        Some(Opcode::CMov) => format!("cmov r{a}, r{b}, r{c}"),
+        Some(Opcode::SegLoad) => format!("load r{a}, r{b}[r{c}]"),
+        Some(Opcode::SegStore) => format!("store r{a}[r{b}], r{c}"),
+        Some(Opcode::Add) => format!("add r{a}, r{b}, r{c}"),
+        Some(Opcode::Mul) => format!("mul r{a}, r{b}, r{c}"),
+        Some(Opcode::Div) => format!("div r{a}, r{b}, r{c}"),
+        Some(Opcode::BNand) => format!("nand r{a}, r{b}, r{c}"),
+        Some(Opcode::Halt) => "halt".to_string(),
+        Some(Opcode::MapSeg) => format!("map r{b}, r{c}"),
+        Some(Opcode::UnmapSeg) => format!("unmap r{c}"),
+        Some(Opcode::Output) => format!("out r{c}"),
+        Some(Opcode::Input) => format!("in r{c}"),
+        Some(Opcode::LoadProg) => format!("loadprog r{b}, r{c}"),
+        Some(Opcode::LoadVal) => format!("loadval r{}, {}", get(&RL, inst), get(&VL, inst)),
+        None => format!(".word {inst:#010x}"),
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,140 @@
+//! `rum run --sandbox`: hardening for untrusted programs on Linux.
+//!
+//! Once the program is loaded and its I/O attached, `enter` takes away
+//! everything the interpreter no longer needs. Landlock (when the kernel has
+//! it) forbids opening any file, and a seccomp filter allows only the system
+//! calls used to read stdin, write stdout/stderr, manage memory and exit;
+//! anything else fails with `EPERM`. A bug in the interpreter can then do no
+//! more than the program itself could.
+
+/// Restricts the process for the rest of its life. Returns whether Landlock
+/// was applied as well as seccomp (older kernels lack it).
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn enter() -> Result<bool, String> {
+    // Both Landlock and an unprivileged seccomp filter require no_new_privs.
+    // SAFETY: prctl with these arguments only sets a flag on this process.
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(format!("no_new_privs: {}", std::io::Error::last_os_error()));
+    }
+    let landlock = landlock::deny_filesystem()?;
+    seccomp::install()?;
+    Ok(landlock)
+}
+
+#[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+pub fn enter() -> Result<bool, String> {
+    Err("the sandbox is only available on Linux (x86_64 and aarch64)".to_string())
+}
+
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod landlock {
+    // From linux/landlock.h; libc does not declare them.
+    const CREATE_RULESET_VERSION: u32 = 1;
+    /// Filesystem rights known to each Landlock ABI version (index = version).
+    const ACCESS_FS: [u64; 6] = [0, (1 << 13) - 1, (1 << 14) - 1, (1 << 15) - 1, (1 << 15) - 1, (1 << 16) - 1];
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    /// Applies a ruleset that handles every filesystem right and grants none.
+    /// Returns `false` when the kernel has no Landlock.
+    pub fn deny_filesystem() -> Result<bool, String> {
+        // SAFETY: asking for the ABI version takes no attribute pointer.
+        let version = unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, std::ptr::null::<RulesetAttr>(), 0, CREATE_RULESET_VERSION) };
+        if version < 1 {
+            return Ok(false);
+        }
+        let attr = RulesetAttr { handled_access_fs: ACCESS_FS[(version as usize).min(ACCESS_FS.len() - 1)] };
+        // SAFETY: `attr` outlives the call and its size is passed along.
+        let ruleset = unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, &attr, std::mem::size_of::<RulesetAttr>(), 0) };
+        if ruleset < 0 {
+            return Err(format!("landlock: {}", std::io::Error::last_os_error()));
+        }
+        // SAFETY: `ruleset` is the descriptor just created; it is closed right after.
+        let restricted = unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0) };
+        let error = std::io::Error::last_os_error();
+        // SAFETY: as above.
+        unsafe { libc::close(ruleset as i32) };
+        match restricted {
+            0 => Ok(true),
+            _ => Err(format!("landlock: {error}")),
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod seccomp {
+    use libc::{sock_filter, sock_fprog};
+
+    // Classic BPF opcodes and the layout of `struct seccomp_data`.
+    const LD_W_ABS: u16 = 0x20;
+    const JEQ_K: u16 = 0x15;
+    const RET_K: u16 = 0x06;
+    const NR: u32 = 0;
+    const ARCH: u32 = 4;
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xC000_003E;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+    /// Everything the interpreter calls once the program is running.
+    const ALLOWED: &[libc::c_long] = &[
+        libc::SYS_read,
+        libc::SYS_write,
+        libc::SYS_readv,
+        libc::SYS_writev,
+        libc::SYS_close,
+        libc::SYS_brk,
+        libc::SYS_mmap,
+        libc::SYS_munmap,
+        libc::SYS_mremap,
+        libc::SYS_mprotect,
+        libc::SYS_madvise,
+        libc::SYS_futex,
+        libc::SYS_clock_gettime,
+        libc::SYS_getrandom,
+        libc::SYS_sched_yield,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_sigaltstack,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+    ];
+
+    fn statement(code: u16, k: u32) -> sock_filter {
+        sock_filter { code, jt: 0, jf: 0, k }
+    }
+
+    fn jump(k: u32, jt: u8, jf: u8) -> sock_filter {
+        sock_filter { code: JEQ_K, jt, jf, k }
+    }
+
+    pub fn install() -> Result<(), String> {
+        let kill = libc::SECCOMP_RET_KILL_PROCESS;
+        let deny = libc::SECCOMP_RET_ERRNO | (libc::EPERM as u32 & libc::SECCOMP_RET_DATA);
+        let mut program = vec![
+            // A foreign architecture's syscall numbers mean something else entirely.
+            statement(LD_W_ABS, ARCH),
+            jump(AUDIT_ARCH, 1, 0),
+            statement(RET_K, kill),
+            statement(LD_W_ABS, NR),
+        ];
+        for &nr in ALLOWED {
+            // A match falls through to allow; anything else skips it to the next number.
+            program.push(jump(nr as u32, 0, 1));
+            program.push(statement(RET_K, libc::SECCOMP_RET_ALLOW));
+        }
+        program.push(statement(RET_K, deny));
+
+        let filter = sock_fprog { len: program.len() as u16, filter: program.as_mut_ptr() };
+        // SAFETY: `filter` points at `program`, which outlives the call; the kernel copies it.
+        let installed = unsafe { libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &filter as *const sock_fprog) };
+        match installed {
+            0 => Ok(()),
+            _ => Err(format!("seccomp: {}", std::io::Error::last_os_error())),
+        }
+    }
+}
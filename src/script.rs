@@ -0,0 +1,162 @@
+//! rhai hooks for the debugger (`rum debug --script FILE`).
+//!
+//! A script defines any of these functions, which the debugger calls as
+//! the program runs:
+//!
+//! ```text
+//! fn on_instruction(pc, word) { ... }   // before every instruction
+//! fn on_output(byte) { ... }            // before Output writes `byte`
+//! fn on_map(segment, words) { ... }     // after Map Segment
+//! ```
+//!
+//! Inside them the script can read the machine with `reg(r)`, `pc()`,
+//! `executed()`, `peek(segment, offset)` and `segment_len(segment)`, and
+//! steer the debugger with `break_at(addr)`, `clear_break(addr)` and
+//! `pause()`, which stops `continue` after the current instruction.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Scope, AST};
+
+use crate::state::UniversalMachine;
+
+/// What a script asked the debugger to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    Break(usize),
+    Clear(usize),
+    Pause,
+}
+
+/// What the script's functions see while a hook runs.
+#[derive(Default)]
+struct Shared {
+    machine: UniversalMachine,
+    executed: u64,
+    requests: Vec<Request>,
+}
+
+/// A compiled script and the hooks it defines.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    shared: Rc<RefCell<Shared>>,
+    on_instruction: bool,
+    on_output: bool,
+    on_map: bool,
+}
+
+type Fallible<T> = Result<T, Box<EvalAltResult>>;
+
+impl Script {
+    /// Compiles the script at `path` and runs its top level once.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::compile(&source)
+    }
+
+    pub fn compile(source: &str) -> Result<Self, String> {
+        let shared = Rc::new(RefCell::new(Shared::default()));
+        let mut engine = Engine::new();
+        register(&mut engine, &shared);
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        let mut scope = Scope::new();
+        engine.run_ast_with_scope(&mut scope, &ast).map_err(|e| e.to_string())?;
+        let defines = |name: &str, arity: usize| ast.iter_functions().any(|f| f.name == name && f.params.len() == arity);
+        Ok(Self {
+            on_instruction: defines("on_instruction", 2),
+            on_output: defines("on_output", 1),
+            on_map: defines("on_map", 2),
+            engine,
+            ast,
+            scope,
+            shared,
+        })
+    }
+
+    /// Runs `on_instruction` before the instruction at `pc`.
+    pub fn on_instruction(&mut self, state: &mut UniversalMachine, executed: u64, pc: usize, word: u32) -> Result<Vec<Request>, String> {
+        match self.on_instruction {
+            true => self.call(state, executed, "on_instruction", (pc as i64, word as i64)),
+            false => Ok(Vec::new()),
+        }
+    }
+
+    /// Runs `on_output` before `byte` is written.
+    pub fn on_output(&mut self, state: &mut UniversalMachine, executed: u64, byte: u8) -> Result<Vec<Request>, String> {
+        match self.on_output {
+            true => self.call(state, executed, "on_output", (byte as i64,)),
+            false => Ok(Vec::new()),
+        }
+    }
+
+    /// Runs `on_map` after `segment` was mapped with `words` words.
+    pub fn on_map(&mut self, state: &mut UniversalMachine, executed: u64, segment: u32, words: u32) -> Result<Vec<Request>, String> {
+        match self.on_map {
+            true => self.call(state, executed, "on_map", (segment as i64, words as i64)),
+            false => Ok(Vec::new()),
+        }
+    }
+
+    /// Lends the machine to the script for one call, then takes it back
+    /// with whatever the script requested.
+    fn call(&mut self, state: &mut UniversalMachine, executed: u64, name: &str, args: impl FuncArgs) -> Result<Vec<Request>, String> {
+        {
+            let mut shared = self.shared.borrow_mut();
+            shared.machine = std::mem::take(state);
+            shared.executed = executed;
+        }
+        // The top level already ran in `compile`.
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self.engine.call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, name, args);
+        let mut shared = self.shared.borrow_mut();
+        *state = std::mem::take(&mut shared.machine);
+        let requests = std::mem::take(&mut shared.requests);
+        result.map(|_| requests).map_err(|e| format!("{name}: {e}"))
+    }
+}
+
+/// Makes the machine functions available to scripts.
+fn register(engine: &mut Engine, shared: &Rc<RefCell<Shared>>) {
+    fn index(n: i64, what: &str) -> Fallible<usize> {
+        usize::try_from(n).map_err(|_| format!("bad {what} {n}").into())
+    }
+
+    let s = shared.clone();
+    engine.register_fn("reg", move |r: i64| -> Fallible<i64> {
+        match r {
+            0..=7 => Ok(s.borrow().machine.register(r as u32) as i64),
+            _ => Err(format!("no register {r}").into()),
+        }
+    });
+    let s = shared.clone();
+    engine.register_fn("pc", move || s.borrow().machine.program_counter as i64);
+    let s = shared.clone();
+    engine.register_fn("executed", move || s.borrow().executed as i64);
+    let s = shared.clone();
+    engine.register_fn("peek", move |segment: i64, offset: i64| -> Fallible<i64> {
+        let shared = s.borrow();
+        let words = shared.machine.mapped_memory.get(index(segment, "segment")?);
+        let word = words.and_then(|words| words.get(index(offset, "offset").ok()?));
+        word.map(|&w| w as i64).ok_or_else(|| format!("nothing at {segment}[{offset}]").into())
+    });
+    let s = shared.clone();
+    engine.register_fn("segment_len", move |segment: i64| -> Fallible<i64> {
+        let shared = s.borrow();
+        Ok(shared.machine.mapped_memory.get(index(segment, "segment")?).map_or(0, Vec::len) as i64)
+    });
+    let s = shared.clone();
+    engine.register_fn("break_at", move |addr: i64| -> Fallible<()> {
+        s.borrow_mut().requests.push(Request::Break(index(addr, "address")?));
+        Ok(())
+    });
+    let s = shared.clone();
+    engine.register_fn("clear_break", move |addr: i64| -> Fallible<()> {
+        s.borrow_mut().requests.push(Request::Clear(index(addr, "address")?));
+        Ok(())
+    });
+    let s = shared.clone();
+    engine.register_fn("pause", move || s.borrow_mut().requests.push(Request::Pause));
+}
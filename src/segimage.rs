@@ -0,0 +1,163 @@
+//! Rendering a segment as an image for `rum render-mem`, one pixel per
+//! word, so the structure of large data segments (tables, compressed
+//! blobs, framebuffers) shows at a glance.
+//!
+//! Each word becomes an intensity from 0 to 255. With `Scale::Log` it is
+//! the number of significant bits in the word, scaled up: zero is black
+//! and small counters, pointers and characters stand apart from hashes
+//! and packed data, which come out bright. `Scale::Linear` takes the top
+//! byte instead, for framebuffers of 32-bit values. The intensity is drawn
+//! as a shade of grey or through a black-red-yellow-white heat palette.
+//!
+//! Rows are `width` words long; the end of the last row is left black.
+//! The PNG is written uncompressed (stored deflate blocks), which keeps
+//! rum free of an image dependency at the price of larger files.
+
+/// How pixels are coloured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Palette {
+    Gray,
+    Heat,
+}
+
+impl Palette {
+    pub fn parse(text: &str) -> Result<Palette, String> {
+        match text {
+            "gray" | "grey" => Ok(Palette::Gray),
+            "heat" => Ok(Palette::Heat),
+            _ => Err(format!("--palette takes gray or heat, not `{text}`")),
+        }
+    }
+}
+
+/// How a word is turned into an intensity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scale {
+    Log,
+    Linear,
+}
+
+impl Scale {
+    pub fn parse(text: &str) -> Result<Scale, String> {
+        match text {
+            "log" => Ok(Scale::Log),
+            "linear" => Ok(Scale::Linear),
+            _ => Err(format!("--scale takes log or linear, not `{text}`")),
+        }
+    }
+}
+
+/// The intensity of `word` under `scale`.
+pub fn intensity(word: u32, scale: Scale) -> u8 {
+    match scale {
+        Scale::Log => ((32 - word.leading_zeros()) * 255 / 32) as u8,
+        Scale::Linear => (word >> 24) as u8,
+    }
+}
+
+/// The colour of `intensity` in the heat palette: black, red, yellow, white.
+pub fn heat(intensity: u8) -> [u8; 3] {
+    let i = intensity as u32 * 3;
+    let ramp = |from: u32| (i.saturating_sub(from).min(255)) as u8;
+    [ramp(0), ramp(255), ramp(510)]
+}
+
+/// The width `render` picks when none is given: a roughly square image.
+pub fn default_width(words: usize) -> usize {
+    (words as f64).sqrt().ceil().max(1.0) as usize
+}
+
+/// `words` as a PNG `width` pixels wide.
+pub fn render(words: &[u32], width: usize, palette: Palette, scale: Scale) -> Result<Vec<u8>, String> {
+    if words.is_empty() {
+        return Err("the segment is empty".to_string());
+    }
+    if width == 0 {
+        return Err("the image needs a width above 0".to_string());
+    }
+    let height = words.len().div_ceil(width);
+    let channels = match palette {
+        Palette::Gray => 1,
+        Palette::Heat => 3,
+    };
+    // Each row starts with its filter type, 0 (none).
+    let mut raw = Vec::with_capacity(height * (1 + width * channels));
+    for row in 0..height {
+        raw.push(0);
+        for column in 0..width {
+            let value = words.get(row * width + column).map_or(0, |&word| intensity(word, scale));
+            match palette {
+                Palette::Gray => raw.push(value),
+                Palette::Heat => raw.extend(heat(value)),
+            }
+        }
+    }
+    let color_type = if palette == Palette::Gray { 0 } else { 2 };
+    Ok(png(width as u32, height as u32, color_type, &raw))
+}
+
+/// A PNG of 8-bit samples with `color_type` from already filtered rows.
+fn png(width: u32, height: u32, color_type: u8, raw: &[u8]) -> Vec<u8> {
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut header = Vec::with_capacity(13);
+    header.extend(width.to_be_bytes());
+    header.extend(height.to_be_bytes());
+    // Bit depth, colour type, deflate, standard filtering, no interlacing.
+    header.extend([8, color_type, 0, 0, 0]);
+    chunk(&mut out, b"IHDR", &header);
+    chunk(&mut out, b"IDAT", &zlib_stored(raw));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(&out[start..]);
+    out.extend(crc.to_be_bytes());
+}
+
+/// `data` as a zlib stream of stored deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(u8::from(blocks.peek().is_none()));
+        let len = block.len() as u16;
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before the modulo without overflowing.
+    for block in data.chunks(5552) {
+        for &byte in block {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,190 @@
+//! Self-modifying code: Stores into segment 0 at addresses that have
+//! already executed (`rum profile --self-modifying`).
+//!
+//! Every such rewrite is logged with the instruction count, the storing
+//! PC, the address written and its old and new word, and rewritten
+//! addresses are summarized as regions of adjacent words: how often they
+//! were written, from where, and how often a rewritten word then executed
+//! again, which is what a JIT would have to invalidate. The log is plain
+//! text, one rewrite per line:
+//!
+//! ```text
+//! # rum self-modifying stores (executed pc target old new)
+//! 1843 120 4501 d0000041 d0000042
+//! ```
+//!
+//! Only segment 0 as loaded is watched: once Load Program replaces it,
+//! addresses no longer name the code that executed there.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::Path;
+
+use crate::barrier::{code_write, CodeWrite};
+use crate::report::Table;
+use crate::state::UniversalMachine;
+
+/// The first line of a rewrite log.
+pub const HEADER: &str = "# rum self-modifying stores (executed pc target old new)";
+
+/// Rewrites kept for the log; later ones are only counted.
+pub const LOG_LIMIT: usize = 1 << 20;
+
+/// One Store over a word that had executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rewrite {
+    /// Instructions executed before the Store.
+    pub executed: u64,
+    pub pc: usize,
+    pub target: usize,
+    pub old: u32,
+    pub new: u32,
+}
+
+/// Adjacent rewritten words.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Region {
+    /// The first address, and one past the last.
+    pub start: usize,
+    pub end: usize,
+    pub writes: u64,
+    /// Times a word of the region executed after being rewritten.
+    pub reexecuted: u64,
+    /// The PCs of the Stores that rewrote it.
+    pub writers: BTreeSet<usize>,
+    /// Instructions executed before its first rewrite.
+    pub first: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Target {
+    writes: u64,
+    reexecuted: u64,
+    writers: BTreeSet<usize>,
+    first: u64,
+}
+
+/// The rewrites of one run.
+#[derive(Debug, Clone, Default)]
+pub struct SelfModification {
+    log: Vec<Rewrite>,
+    rewrites: u64,
+    targets: BTreeMap<usize, Target>,
+    /// Rewritten addresses not executed since.
+    stale: HashSet<usize>,
+}
+
+impl SelfModification {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notes the instruction `inst` about to run from `pc` in `state`, the
+    /// `executed`th of the run; `counts` are the executions so far by
+    /// address, this one included.
+    pub fn before(&mut self, executed: u64, pc: usize, inst: u32, state: &UniversalMachine, counts: &[u64]) {
+        if self.stale.remove(&pc) {
+            self.targets.get_mut(&pc).unwrap().reexecuted += 1;
+        }
+        let Some(CodeWrite::Store { offset: target, old, new }) = code_write(inst, state) else {
+            return;
+        };
+        if counts.get(target).is_none_or(|&count| count == 0) {
+            return;
+        }
+        let rewrite = Rewrite { executed, pc, target, old, new };
+        self.rewrites += 1;
+        if self.log.len() < LOG_LIMIT {
+            self.log.push(rewrite);
+        }
+        let entry = self.targets.entry(target).or_insert_with(|| Target { first: executed, ..Target::default() });
+        entry.writes += 1;
+        entry.writers.insert(pc);
+        self.stale.insert(target);
+    }
+
+    /// Rewrites in all, logged or not.
+    pub fn total(&self) -> u64 {
+        self.rewrites
+    }
+
+    /// The first `LOG_LIMIT` rewrites, in order.
+    pub fn log(&self) -> &[Rewrite] {
+        &self.log
+    }
+
+    /// The rewritten regions, by address.
+    pub fn regions(&self) -> Vec<Region> {
+        let mut regions: Vec<Region> = Vec::new();
+        for (&address, target) in &self.targets {
+            let region = match regions.last_mut() {
+                Some(region) if region.end == address => region,
+                _ => {
+                    regions.push(Region { start: address, end: address, first: target.first, ..Region::default() });
+                    regions.last_mut().unwrap()
+                }
+            };
+            region.end = address + 1;
+            region.writes += target.writes;
+            region.reexecuted += target.reexecuted;
+            region.writers.extend(&target.writers);
+            region.first = region.first.min(target.first);
+        }
+        regions
+    }
+
+    /// Writes the log read by people and scripts (see the module documentation).
+    pub fn write(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(out, "{HEADER}")?;
+        for rewrite in &self.log {
+            writeln!(out, "{} {} {} {:08x} {:08x}", rewrite.executed, rewrite.pc, rewrite.target, rewrite.old, rewrite.new)?;
+        }
+        if self.rewrites > self.log.len() as u64 {
+            writeln!(out, "# {} more rewrites not logged", self.rewrites - self.log.len() as u64)?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()
+    }
+
+    /// The rewritten regions as a table, followed by a summary.
+    pub fn report(&self) -> String {
+        let mut text = format!("{:>10} {:>10} {:>13}  writers\n", "writes", "re-run", "region");
+        let regions = self.regions();
+        for region in &regions {
+            let writers: Vec<String> = region.writers.iter().map(usize::to_string).collect();
+            let _ = writeln!(text, "{:>10} {:>10} {:>13}  {}", region.writes, region.reexecuted, span(region), writers.join(" "));
+        }
+        let _ = writeln!(
+            text,
+            "{} stores rewrote {} executed words in {} regions",
+            self.rewrites,
+            self.targets.len(),
+            regions.len()
+        );
+        text
+    }
+
+    /// The regions as a table, `rewritten_regions`, for `--report-format`
+    /// (see `report`).
+    pub fn tables(&self) -> Vec<Table> {
+        let mut table = Table::new("rewritten_regions", &["start", "end", "writes", "reexecuted", "first", "writers"]);
+        for region in self.regions() {
+            let writers: Vec<String> = region.writers.iter().map(usize::to_string).collect();
+            table.row(vec![region.start.into(), (region.end - 1).into(), region.writes.into(), region.reexecuted.into(), region.first.into(), writers.join(" ").into()]);
+        }
+        vec![table]
+    }
+}
+
+fn span(region: &Region) -> String {
+    match region.end - region.start {
+        1 => region.start.to_string(),
+        _ => format!("{}-{}", region.start, region.end - 1),
+    }
+}
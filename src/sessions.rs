@@ -0,0 +1,179 @@
+//! Session management for `rum serve-rpc --listen`, so the server can be
+//! left running as a hosted service.
+//!
+//! Every TCP connection is a session holding its own machines (see `rpc`).
+//! The `Manager` admits sessions up to `Limits::max_sessions`, turning the
+//! rest away with a `SERVER_BUSY` error, and each session is closed once it
+//! has gone `Limits::idle_timeout` without a request. Either way the
+//! client is sent a `closing` notification before the connection closes:
+//!
+//! ```text
+//! {"jsonrpc":"2.0","method":"closing","params":{"reason":"idle","snapshots":[]}}
+//! ```
+//!
+//! `Manager::shutdown` (on SIGINT or SIGTERM with `shutdown_on_signals`)
+//! stops accepting connections and interrupts running machines, whose
+//! `run` or `step` returns with the stop reason `shutdown`. Each session
+//! then saves its machines that have neither halted nor faulted to
+//! `Limits::snapshot_dir`, lists them in its `closing` notification (reason
+//! `shutdown`) and closes, so clients can `create` them again from
+//! `snapshot` on the next server. Input fed but not yet read is not saved.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::seal::Key;
+
+/// How often sessions and the accept loop look for a shutdown or an idle timeout.
+pub const POLL: Duration = Duration::from_millis(50);
+
+/// The bounds a server puts on its sessions.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    /// Sessions open at once; `None` for no limit.
+    pub max_sessions: Option<usize>,
+    /// How long a session may go without a request before it is closed.
+    pub idle_timeout: Option<Duration>,
+    /// Where active machines are saved on shutdown; `None` to drop them.
+    pub snapshot_dir: Option<PathBuf>,
+    /// Seals the snapshots saved and opens the ones loaded (see `seal`).
+    pub snapshot_key: Option<Key>,
+}
+
+#[derive(Debug, Default)]
+struct Open {
+    count: usize,
+    next_id: u64,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    open: Mutex<Open>,
+    /// Signalled whenever a session closes.
+    closed: Condvar,
+    closing: Arc<AtomicBool>,
+}
+
+/// Admits, tracks and shuts down the sessions of a server.
+#[derive(Debug, Clone, Default)]
+pub struct Manager {
+    limits: Limits,
+    shared: Arc<Shared>,
+}
+
+impl Manager {
+    pub fn new(limits: Limits) -> Self {
+        Self { limits, shared: Arc::default() }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Opens a session, or says why the server cannot take one now.
+    pub fn admit(&self) -> Result<Ticket, String> {
+        if self.closing() {
+            return Err("server is shutting down".to_string());
+        }
+        let mut open = self.shared.open.lock().unwrap();
+        if self.limits.max_sessions.is_some_and(|max| open.count >= max) {
+            return Err(format!("server busy: {} sessions open", open.count));
+        }
+        open.count += 1;
+        open.next_id += 1;
+        Ok(Ticket { id: open.next_id, shared: Arc::clone(&self.shared) })
+    }
+
+    /// Sessions open now.
+    pub fn open(&self) -> usize {
+        self.shared.open.lock().unwrap().count
+    }
+
+    /// Starts a graceful shutdown: no new sessions, and open ones save their
+    /// machines and close.
+    pub fn shutdown(&self) {
+        self.shared.closing.store(true, Ordering::Relaxed);
+    }
+
+    pub fn closing(&self) -> bool {
+        self.shared.closing.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until every session has closed.
+    pub fn wait(&self) {
+        let mut open = self.shared.open.lock().unwrap();
+        while open.count > 0 {
+            open = self.shared.closed.wait(open).unwrap();
+        }
+    }
+}
+
+/// An admitted session; dropping it closes the session.
+#[derive(Debug)]
+pub struct Ticket {
+    id: u64,
+    shared: Arc<Shared>,
+}
+
+impl Ticket {
+    /// The session's number, unique for the life of the server.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// True once the server is shutting down.
+    pub fn closing(&self) -> bool {
+        self.shared.closing.load(Ordering::Relaxed)
+    }
+
+    /// The flag `closing` reads, for machines to poll as they run.
+    pub fn interrupt(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.shared.closing)
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        self.shared.open.lock().unwrap().count -= 1;
+        self.shared.closed.notify_all();
+    }
+}
+
+/// Where machine `machine` of session `session` is saved on shutdown.
+pub fn snapshot_path(dir: &Path, session: u64, machine: u64) -> PathBuf {
+    dir.join(format!("session-{session}-machine-{machine}.rumstate"))
+}
+
+#[cfg(unix)]
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn signalled(_: libc::c_int) {
+    // A second signal gives up on the graceful shutdown.
+    if SIGNALLED.swap(true, Ordering::Relaxed) {
+        unsafe { libc::_exit(130) };
+    }
+}
+
+/// Shuts `manager` down on the first SIGINT or SIGTERM; a second one exits at once.
+#[cfg(unix)]
+pub fn shutdown_on_signals(manager: &Manager) {
+    let handler = signalled as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+    let manager = manager.clone();
+    std::thread::spawn(move || {
+        while !SIGNALLED.load(Ordering::Relaxed) {
+            std::thread::sleep(POLL);
+        }
+        eprintln!("rum: shutting down {} sessions", manager.open());
+        manager.shutdown();
+    });
+}
+
+#[cfg(not(unix))]
+pub fn shutdown_on_signals(_: &Manager) {}
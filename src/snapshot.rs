@@ -0,0 +1,545 @@
+//! The `.rumstate` snapshot format.
+//!
+//! ```text
+//! magic       8 bytes   "RUMSTATE"
+//! version     u16       format version of the payload (see `VERSION`)
+//! flags       u16       bit 0: payload is compressed, bit 1: payload is a delta,
+//!                       bit 2: payload is encrypted
+//! length      u64       payload length in bytes
+//! checksum    32 bytes  SHA-256 of the payload as stored
+//! payload               zstd-compressed when flag bit 0 is set, then sealed
+//!                       when bit 2 is
+//! ```
+//!
+//! All integers are big-endian, like UM program files. The version 2 payload
+//! is a sequence of sections, each a one-byte tag, a u64 body length and the
+//! body:
+//!
+//! ```text
+//! 'M'  registers (8 x u32), program counter (u64)
+//! 'D'  checksum of the parent snapshot (deltas only)
+//! 'S'  segment count u32, carried count u32, then per carried segment:
+//!      index u32, length u32, words (unmapped segments are empty)
+//! 'F'  free list, one u32 per segment id
+//! 'P'  primed input bytes not read yet (omitted when there are none)
+//! ```
+//!
+//! Readers skip sections with a lowercase tag they do not know, so later
+//! versions can add optional data without breaking older crates; an unknown
+//! uppercase tag is an error. Counts and lengths are checked against the
+//! bytes left before anything is allocated for them, and a compressed
+//! payload may not expand past `MAX_PAYLOAD_BYTES`, so a corrupt or hostile
+//! file is an error rather than an allocation the size of its claims.
+//!
+//! A delta snapshot (flag bit 1) only carries the segments modified since
+//! its parent. Loading one means replaying the whole chain from a full
+//! snapshot (see `load_chain`).
+//!
+//! With a key (see `seal`) the payload is sealed after compression and the
+//! checksum covers the sealed bytes, so delta chains link up as before;
+//! such a snapshot only loads with the same key, and with a key only sealed
+//! snapshots load.
+//!
+//! Snapshots written by older versions still load: `parse` dispatches on the
+//! header version to a shim that reads the old layout into the same `Parts`.
+//! Version 1 had no sections, just the fields above in order.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::seal::{self, Key};
+use crate::state::UniversalMachine;
+
+pub const MAGIC: &[u8; 8] = b"RUMSTATE";
+
+/// Payload version written by this crate.
+pub const VERSION: u16 = 2;
+
+/// Header flag: the payload is compressed.
+pub const FLAG_COMPRESSED: u16 = 1;
+
+/// Header flag: the payload is a delta against a parent snapshot.
+pub const FLAG_DELTA: u16 = 2;
+
+/// Header flag: the payload is encrypted (see `seal`).
+pub const FLAG_ENCRYPTED: u16 = 4;
+
+/// zstd level used by `save_snapshot`, `encode` and checkpoints unless told otherwise.
+/// Machines running codex are hundreds of MB, which compress very well.
+pub const DEFAULT_LEVEL: i32 = 3;
+
+/// Largest decompressed payload a snapshot may have, so a corrupt or
+/// hostile one cannot make loading allocate without bound. A machine with
+/// this much memory is far beyond anything a UM program maps in practice.
+pub const MAX_PAYLOAD_BYTES: u64 = 4 << 30;
+
+/// Identifies a snapshot: the SHA-256 recorded in its header.
+pub type Checksum = [u8; 32];
+
+const HEADER_LEN: usize = 8 + 2 + 2 + 8 + 32;
+
+/// Why a snapshot could not be saved or loaded.
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    /// The file does not start with `MAGIC`.
+    NotASnapshot,
+    /// Written by a newer crate, or otherwise unknown.
+    UnsupportedVersion(u16),
+    UnsupportedFlags(u16),
+    /// The payload does not match its recorded checksum.
+    ChecksumMismatch,
+    /// The payload is shorter or longer than its contents claim.
+    Malformed(&'static str),
+    /// A delta was loaded without, or against the wrong, parent snapshot.
+    BrokenChain,
+    /// The snapshot is encrypted and no key was given.
+    NeedsKey,
+    /// The snapshot does not open with the key given: a different key, or
+    /// the file was tampered with.
+    WrongKey,
+    /// A key was given but the snapshot is not encrypted, so nothing shows
+    /// it was written by the key's holder.
+    NotSealed,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{e}"),
+            SnapshotError::NotASnapshot => write!(f, "not a rum snapshot"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {v}"),
+            SnapshotError::UnsupportedFlags(flags) => write!(f, "unsupported snapshot flags {flags:#x}"),
+            SnapshotError::ChecksumMismatch => write!(f, "snapshot is corrupt (checksum mismatch)"),
+            SnapshotError::Malformed(what) => write!(f, "snapshot is corrupt ({what})"),
+            SnapshotError::BrokenChain => write!(f, "delta snapshot does not apply to its base"),
+            SnapshotError::NeedsKey => write!(f, "snapshot is encrypted; give its key with --snapshot-key or {}", seal::KEY_ENV),
+            SnapshotError::WrongKey => write!(f, "snapshot does not open with this key (wrong key, or tampered with)"),
+            SnapshotError::NotSealed => write!(f, "snapshot is not encrypted, but a snapshot key is set"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+/// Writes `state` to `path` in the `.rumstate` format, compressed at
+/// `DEFAULT_LEVEL` and sealed with `key` if one is given.
+pub fn save_snapshot(state: &UniversalMachine, path: impl AsRef<Path>, key: Option<&Key>) -> Result<(), SnapshotError> {
+    std::fs::write(path, encode(state, key))?;
+    Ok(())
+}
+
+/// Like `save_snapshot`, with the zstd `level` chosen by the caller (`None` stores it uncompressed).
+pub fn save_snapshot_with(state: &UniversalMachine, path: impl AsRef<Path>, level: Option<i32>, key: Option<&Key>) -> Result<(), SnapshotError> {
+    std::fs::write(path, encode_with(state, level, key))?;
+    Ok(())
+}
+
+/// Reads a machine back from a `.rumstate` file, verifying its checksum
+/// and, given a `key`, that it was sealed with it.
+pub fn load_snapshot(path: impl AsRef<Path>, key: Option<&Key>) -> Result<UniversalMachine, SnapshotError> {
+    decode(&std::fs::read(path)?, key)
+}
+
+/// Serializes `state` into a complete snapshot, header included, sealed
+/// with `key` if one is given.
+pub fn encode(state: &UniversalMachine, key: Option<&Key>) -> Vec<u8> {
+    encode_with(state, Some(DEFAULT_LEVEL), key)
+}
+
+/// Like `encode`, compressing at zstd `level` (`None` for no compression).
+pub fn encode_with(state: &UniversalMachine, level: Option<i32>, key: Option<&Key>) -> Vec<u8> {
+    let mut payload = Vec::new();
+    put_machine(&mut payload, state, 0..state.mapped_memory.len(), None);
+    wrap(0, payload, level, key)
+}
+
+/// Serializes only what changed in `state` since the snapshot identified by
+/// `parent`, i.e. the segments still marked dirty.
+pub fn encode_delta(state: &UniversalMachine, parent: &Checksum, level: Option<i32>, key: Option<&Key>) -> Vec<u8> {
+    let mut payload = Vec::new();
+    put_machine(&mut payload, state, state.dirty_segments(), Some(parent));
+    wrap(FLAG_DELTA, payload, level, key)
+}
+
+/// Given a complete snapshot, return the checksum identifying it. The
+/// checksum covers the payload as stored, so no key is needed.
+pub fn checksum(snapshot: &[u8]) -> Result<Checksum, SnapshotError> {
+    header(snapshot).map(|(_, _, checksum, _)| checksum)
+}
+
+/// Given a snapshot, return the checksum of the snapshot it is a delta
+/// against, or `None` if it is a full snapshot.
+pub fn parent(snapshot: &[u8], key: Option<&Key>) -> Result<Option<Checksum>, SnapshotError> {
+    let (version, flags, _, payload) = unwrap(snapshot, key)?;
+    Ok(parse(version, flags, &payload)?.parent)
+}
+
+/// Parses a complete (non-delta) snapshot produced by `encode`, or by an
+/// older version of this crate.
+pub fn decode(bytes: &[u8], key: Option<&Key>) -> Result<UniversalMachine, SnapshotError> {
+    let (version, flags, _, payload) = unwrap(bytes, key)?;
+    let parts = parse(version, flags, &payload)?;
+    if parts.parent.is_some() {
+        return Err(SnapshotError::BrokenChain);
+    }
+    parts.build(Vec::new())
+}
+
+/// Applies a delta snapshot to `base`, the machine restored from the
+/// snapshot with checksum `parent`.
+pub fn apply_delta(base: UniversalMachine, parent: &Checksum, bytes: &[u8], key: Option<&Key>) -> Result<UniversalMachine, SnapshotError> {
+    let (version, flags, _, payload) = unwrap(bytes, key)?;
+    let parts = parse(version, flags, &payload)?;
+    if parts.parent.as_ref() != Some(parent) {
+        return Err(SnapshotError::BrokenChain);
+    }
+    parts.build(base.mapped_memory)
+}
+
+/// Restores a machine from a full snapshot followed by the deltas taken
+/// after it, in order.
+pub fn load_chain(paths: &[impl AsRef<Path>], key: Option<&Key>) -> Result<UniversalMachine, SnapshotError> {
+    let (first, rest) = paths.split_first().ok_or(SnapshotError::BrokenChain)?;
+    let bytes = std::fs::read(first)?;
+    let mut parent = checksum(&bytes)?;
+    let mut state = decode(&bytes, key)?;
+    for path in rest {
+        let bytes = std::fs::read(path)?;
+        state = apply_delta(state, &parent, &bytes, key)?;
+        parent = checksum(&bytes)?;
+    }
+    Ok(state)
+}
+
+/// Writes a full snapshot first, then deltas against the previous file,
+/// clearing the machine's dirty segments each time.
+#[derive(Debug)]
+pub struct DeltaChain {
+    parent: Option<Checksum>,
+    level: Option<i32>,
+    key: Option<Key>,
+}
+
+impl DeltaChain {
+    /// Starts a chain whose snapshots are compressed at zstd `level` and
+    /// sealed with `key`.
+    pub fn new(level: Option<i32>, key: Option<Key>) -> Self {
+        Self { parent: None, level, key }
+    }
+
+    /// Encodes the next snapshot in the chain for `state`.
+    pub fn next(&mut self, state: &mut UniversalMachine) -> Vec<u8> {
+        let bytes = match &self.parent {
+            Some(parent) => encode_delta(state, parent, self.level, self.key.as_ref()),
+            None => encode_with(state, self.level, self.key.as_ref()),
+        };
+        state.clear_dirty();
+        self.parent = checksum(&bytes).ok();
+        bytes
+    }
+
+    /// Starts over: the next snapshot will be a full one.
+    pub fn reset(&mut self) {
+        self.parent = None;
+    }
+}
+
+/// Appends the sections describing `state`, carrying only the given segments.
+fn put_machine(out: &mut Vec<u8>, state: &UniversalMachine, segments: impl IntoIterator<Item = usize>, parent: Option<&Checksum>) {
+    section(out, b'M', |out| {
+        for r in 0..8 {
+            out.extend(state.register(r).to_be_bytes());
+        }
+        out.extend((state.program_counter as u64).to_be_bytes());
+    });
+    if let Some(parent) = parent {
+        section(out, b'D', |out| out.extend(parent));
+    }
+    section(out, b'S', |out| {
+        let segments: Vec<usize> = segments.into_iter().collect();
+        out.extend((state.mapped_memory.len() as u32).to_be_bytes());
+        out.extend((segments.len() as u32).to_be_bytes());
+        for index in segments {
+            let segment = &state.mapped_memory[index];
+            out.extend((index as u32).to_be_bytes());
+            out.extend((segment.len() as u32).to_be_bytes());
+            out.extend(segment.iter().flat_map(|word| word.to_be_bytes()));
+        }
+    });
+    section(out, b'F', |out| out.extend(state.free_segments().iter().flat_map(|id| id.to_be_bytes())));
+    let primed = state.primed_input();
+    if !primed.is_empty() {
+        section(out, b'P', |out| out.extend(primed));
+    }
+}
+
+/// Appends a section tagged `tag` whose body is written by `body`.
+fn section(out: &mut Vec<u8>, tag: u8, body: impl FnOnce(&mut Vec<u8>)) {
+    out.push(tag);
+    let at = out.len();
+    out.extend(0_u64.to_be_bytes());
+    body(out);
+    let len = (out.len() - at - 8) as u64;
+    out[at..at + 8].copy_from_slice(&len.to_be_bytes());
+}
+
+/// A snapshot payload, independent of the version it was read from.
+struct Parts {
+    registers: [u32; 8],
+    program_counter: usize,
+    /// Set for delta snapshots.
+    parent: Option<Checksum>,
+    /// Number of segments the machine has, mapped or not.
+    count: usize,
+    /// The segments carried by the snapshot, by index.
+    segments: Vec<(usize, Vec<u32>)>,
+    free: Vec<u32>,
+    primed: Vec<u8>,
+}
+
+impl Parts {
+    /// Builds the machine, starting from the segments of the parent
+    /// snapshot for a delta (or none for a full snapshot).
+    fn build(self, mut mapped_memory: Vec<Vec<u32>>) -> Result<UniversalMachine, SnapshotError> {
+        // Every segment is either carried or already in the parent, so a
+        // count beyond both is a corrupt header, not memory to allocate.
+        if self.count > mapped_memory.len() + self.segments.len() {
+            return Err(SnapshotError::Malformed("segment count"));
+        }
+        mapped_memory.resize(self.count, Vec::new());
+        for (index, words) in self.segments {
+            *mapped_memory.get_mut(index).ok_or(SnapshotError::Malformed("segment index"))? = words;
+        }
+        let mut listed = vec![false; mapped_memory.len()];
+        for &id in &self.free {
+            match listed.get_mut(id as usize) {
+                Some(seen) if id != 0 && !*seen => *seen = true,
+                _ => return Err(SnapshotError::Malformed("free list names an invalid segment")),
+            }
+        }
+        Ok(UniversalMachine::from_parts(self.registers, mapped_memory, self.free, self.program_counter, self.primed))
+    }
+}
+
+/// Reads a payload written with format `version`. Older versions each get
+/// a shim here, so snapshots keep loading after the layout changes.
+fn parse(version: u16, flags: u16, payload: &[u8]) -> Result<Parts, SnapshotError> {
+    match version {
+        1 => parse_v1(flags, payload),
+        2 => parse_v2(payload),
+        _ => Err(SnapshotError::UnsupportedVersion(version)),
+    }
+}
+
+/// Version 1: fixed fields in order, with no room for additions. Deltas
+/// start with the parent checksum and prefix each segment with its index.
+fn parse_v1(flags: u16, payload: &[u8]) -> Result<Parts, SnapshotError> {
+    let mut r = Reader { bytes: payload };
+    let delta = flags & FLAG_DELTA != 0;
+    let parent = match delta {
+        true => Some(r.take(32)?.try_into().unwrap()),
+        false => None,
+    };
+    let (registers, program_counter) = get_machine(&mut r)?;
+    let count = r.u32()? as usize;
+    let carried = if delta { r.u32()? as usize } else { count };
+    let mut segments = Vec::with_capacity(carried.min(r.bytes.len() / 4));
+    for i in 0..carried {
+        let index = if delta { r.u32()? as usize } else { i };
+        let len = r.u32()? as usize;
+        segments.push((index, r.words(len)?));
+    }
+    let free = r.u32()? as usize;
+    let free = r.words(free)?;
+    let primed = r.u32()? as usize;
+    let primed = r.take(primed)?.to_vec();
+    if !r.bytes.is_empty() {
+        return Err(SnapshotError::Malformed("trailing bytes"));
+    }
+    Ok(Parts { registers, program_counter, parent, count, segments, free, primed })
+}
+
+/// Version 2: a sequence of tagged, length-prefixed sections (see the module docs).
+fn parse_v2(payload: &[u8]) -> Result<Parts, SnapshotError> {
+    let mut r = Reader { bytes: payload };
+    let mut machine = None;
+    let mut parent = None;
+    let mut memory = None;
+    let mut free = Vec::new();
+    let mut primed = Vec::new();
+    while !r.bytes.is_empty() {
+        let tag = r.take(1)?[0];
+        let len = r.u64()?;
+        let body = r.take(usize::try_from(len).map_err(|_| SnapshotError::Malformed("section length"))?)?;
+        let mut s = Reader { bytes: body };
+        match tag {
+            b'M' => machine = Some(get_machine(&mut s)?),
+            b'D' => parent = Some(s.take(32)?.try_into().unwrap()),
+            b'S' => {
+                let count = s.u32()? as usize;
+                let carried = s.u32()? as usize;
+                let mut segments = Vec::with_capacity(carried.min(s.bytes.len() / 8));
+                for _ in 0..carried {
+                    let index = s.u32()? as usize;
+                    let len = s.u32()? as usize;
+                    segments.push((index, s.words(len)?));
+                }
+                memory = Some((count, segments));
+            }
+            b'F' => {
+                if !s.bytes.len().is_multiple_of(4) {
+                    return Err(SnapshotError::Malformed("free list"));
+                }
+                free = s.words(s.bytes.len() / 4)?;
+            }
+            b'P' => primed = std::mem::take(&mut s.bytes).to_vec(),
+            // Lowercase sections are optional extras an older reader may skip.
+            tag if tag.is_ascii_lowercase() => continue,
+            _ => return Err(SnapshotError::Malformed("unknown section")),
+        }
+        if !s.bytes.is_empty() {
+            return Err(SnapshotError::Malformed("trailing bytes in section"));
+        }
+    }
+    let (registers, program_counter) = machine.ok_or(SnapshotError::Malformed("missing machine section"))?;
+    let (count, segments) = memory.ok_or(SnapshotError::Malformed("missing segment section"))?;
+    Ok(Parts { registers, program_counter, parent, count, segments, free, primed })
+}
+
+fn get_machine(r: &mut Reader) -> Result<([u32; 8], usize), SnapshotError> {
+    let mut registers = [0_u32; 8];
+    for reg in registers.iter_mut() {
+        *reg = r.u32()?;
+    }
+    let program_counter = r.u64()? as usize;
+    Ok((registers, program_counter))
+}
+
+/// Compresses `payload` if asked to, seals it with `key` if given and prepends the header.
+fn wrap(mut flags: u16, payload: Vec<u8>, level: Option<i32>, key: Option<&Key>) -> Vec<u8> {
+    let payload = match level {
+        Some(level) => {
+            flags |= FLAG_COMPRESSED;
+            zstd::bulk::compress(&payload, level).expect("zstd compression failed")
+        }
+        None => payload,
+    };
+    let mut out = Vec::with_capacity(HEADER_LEN + seal::OVERHEAD + payload.len());
+    out.extend(MAGIC);
+    out.extend(VERSION.to_be_bytes());
+    let payload = match key {
+        Some(key) => {
+            flags |= FLAG_ENCRYPTED;
+            seal::seal(key, &sealed_header(VERSION, flags), &payload)
+        }
+        None => payload,
+    };
+    out.extend(flags.to_be_bytes());
+    out.extend((payload.len() as u64).to_be_bytes());
+    out.extend(Sha256::digest(&payload));
+    out.extend(payload);
+    out
+}
+
+/// Header version, flags and checksum, followed by the verified, decompressed payload.
+type Unwrapped<'a> = (u16, u16, Checksum, Cow<'a, [u8]>);
+
+/// Checks the header and returns its fields with the payload, opened with
+/// `key` and decompressed.
+fn unwrap<'a>(bytes: &'a [u8], key: Option<&Key>) -> Result<Unwrapped<'a>, SnapshotError> {
+    let (version, flags, checksum, payload) = header(bytes)?;
+    let payload = match (flags & FLAG_ENCRYPTED != 0, key) {
+        (false, None) => Cow::Borrowed(payload),
+        (false, Some(_)) => return Err(SnapshotError::NotSealed),
+        (true, None) => return Err(SnapshotError::NeedsKey),
+        (true, Some(key)) => Cow::Owned(seal::open(key, &sealed_header(version, flags), payload).ok_or(SnapshotError::WrongKey)?),
+    };
+    if flags & FLAG_COMPRESSED == 0 {
+        return Ok((version, flags, checksum, payload));
+    }
+    let decoder = zstd::stream::read::Decoder::new(&payload[..]).map_err(|_| SnapshotError::Malformed("bad compressed payload"))?;
+    let mut decompressed = Vec::new();
+    decoder.take(MAX_PAYLOAD_BYTES + 1).read_to_end(&mut decompressed).map_err(|_| SnapshotError::Malformed("bad compressed payload"))?;
+    if decompressed.len() as u64 > MAX_PAYLOAD_BYTES {
+        return Err(SnapshotError::Malformed("payload too large"));
+    }
+    Ok((version, flags, checksum, Cow::Owned(decompressed)))
+}
+
+/// Checks the header and the checksum, returning the header's fields with
+/// the payload as stored.
+fn header(bytes: &[u8]) -> Result<(u16, u16, Checksum, &[u8]), SnapshotError> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(SnapshotError::NotASnapshot);
+    }
+    let mut header = Reader { bytes: &bytes[MAGIC.len()..] };
+    let version = header.u16()?;
+    let flags = header.u16()?;
+    let length = header.u64()? as usize;
+    let checksum: Checksum = header.take(32)?.try_into().unwrap();
+    let payload = header.bytes;
+
+    if version == 0 || version > VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+    if flags & !(FLAG_DELTA | FLAG_COMPRESSED | FLAG_ENCRYPTED) != 0 {
+        return Err(SnapshotError::UnsupportedFlags(flags));
+    }
+    if payload.len() != length {
+        return Err(SnapshotError::Malformed("payload length"));
+    }
+    if Sha256::digest(payload).as_slice() != checksum {
+        return Err(SnapshotError::ChecksumMismatch);
+    }
+    Ok((version, flags, checksum, payload))
+}
+
+/// The header fields a sealed payload authenticates: magic, version and
+/// flags. Length and checksum describe the sealed bytes themselves.
+fn sealed_header(version: u16, flags: u16) -> Vec<u8> {
+    [&MAGIC[..], &version.to_be_bytes(), &flags.to_be_bytes()].concat()
+}
+
+/// Cursor over big-endian fields that reports truncation as an error.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() < n {
+            return Err(SnapshotError::Malformed("truncated"));
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn words(&mut self, n: usize) -> Result<Vec<u32>, SnapshotError> {
+        let bytes = self.take(n.checked_mul(4).ok_or(SnapshotError::Malformed("length overflow"))?)?;
+        Ok(crate::load::words(bytes))
+    }
+}
@@ -1,6 +1,56 @@
-use std::io::{stdin, Read};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{stdin, stdout, Read, Write};
+use std::ops::{BitAnd, Not};
+use std::time::{Duration, Instant};
 
-#[derive(Debug, PartialEq, Clone)]
+use crate::eof::{reopen_terminal, AfterEof, Eof};
+use crate::error::MachineError;
+use crate::rumdis::{disassemble, get, op, Opcode, Step, StepResult, RA, RB, RC, RL};
+
+/// A machine word: `u32` for the UM, `u64` for its 64-bit variant (see
+/// `um64`). The instructions are implemented once, over any `Word`.
+pub trait Word: Copy + Eq + Not<Output = Self> + BitAnd<Output = Self> + fmt::Debug + 'static {
+    const ZERO: Self;
+    fn wrapping_add(self, other: Self) -> Self;
+    fn wrapping_mul(self, other: Self) -> Self;
+    /// `self / other`, with `other` not zero.
+    fn wrapping_div(self, other: Self) -> Self;
+    /// The word as a segment identifier, offset or length; one too large
+    /// to index anything becomes `usize::MAX`.
+    fn index(self) -> usize;
+    fn from_index(index: usize) -> Self;
+    fn widen(self) -> u64;
+}
+
+macro_rules! word {
+    ($t:ty) => {
+        impl Word for $t {
+            const ZERO: Self = 0;
+            fn wrapping_add(self, other: Self) -> Self {
+                <$t>::wrapping_add(self, other)
+            }
+            fn wrapping_mul(self, other: Self) -> Self {
+                <$t>::wrapping_mul(self, other)
+            }
+            fn wrapping_div(self, other: Self) -> Self {
+                <$t>::wrapping_div(self, other)
+            }
+            fn index(self) -> usize {
+                usize::try_from(self).unwrap_or(usize::MAX)
+            }
+            fn from_index(index: usize) -> Self {
+                index as $t
+            }
+            fn widen(self) -> u64 {
+                self as u64
+            }
+        }
+    };
+}
+
+word!(u32);
+word!(u64);
 
 /// Representation of Universal Machine
 /// Here are several invariants:
@@ -35,32 +85,183 @@ use std::io::{stdin, Read};
 /// ## Free Segments
 /// The UM has a vector called `unmapped_memory`, where each index represents an unmapped memory segment.
 /// Used to allocate and deallocate memory as needed during the execution of programs.
-pub struct UniversalMachine {
-    registers: [u32; 8], // Eight general-purpose registers holding one word each
-    pub mapped_memory: Vec<Vec<u32>>,
-    unmapped_memory: Vec<u32>, 
+///
+/// ## Dirty Segments
+/// Stores, Map, Unmap and Load Program flag the segments they change until `clear_dirty`
+/// is called, so delta snapshots and caches of segment contents know what to redo.
+/// Hosts writing through `mapped_memory` use `segment_mut` or `mark_dirty` to do the same.
+///
+/// ## Word Size
+/// The machine is generic over its `Word`; `UniversalMachine` alone is the
+/// 32-bit UM, and everything reading stdin and stdout directly is only defined for it.
+///
+/// ## Serialization
+/// With the `serde` feature the whole machine (registers, every segment, the free list,
+/// the program counter and any primed input) implements `Serialize`/`Deserialize`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UniversalMachine<W: Word = u32> {
+    registers: [W; 8], // Eight general-purpose registers holding one word each
+    pub mapped_memory: Vec<Vec<W>>,
+    unmapped_memory: Vec<W>,
+    free: Vec<bool>, // Whether each segment is on `unmapped_memory`; anything past the end is not
     pub program_counter: usize,
+    pending_input: VecDeque<u8>, // Bytes handed to `input` before falling back to stdin
+    #[cfg_attr(feature = "serde", serde(skip))]
+    dirty: Vec<bool>, // Segments modified since the last snapshot; anything past the end counts as dirty
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) usage: Usage, // Output, memory and input waits counted since `reset_usage`, for run summaries
+    #[cfg_attr(feature = "serde", serde(skip))]
+    eof: Eof, // What Input does at the end of stdin
+    #[cfg_attr(feature = "serde", serde(skip))]
+    reserved: Vec<W>, // Segments served by the host, which Map Segment never returns (see `reserve_segment`)
 }
 
-impl UniversalMachine{
+/// Output written, memory mapped and time spent waiting for input since `reset_usage`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub output_bytes: u64,
+    /// Words mapped (by Map Segment and Load Program), less words unmapped.
+    pub mapped_words: i64,
+    /// The highest `mapped_words` reached.
+    pub peak_words: i64,
+    /// Wall time spent blocked reading stdin for Input.
+    pub input_wait: Duration,
+}
 
-    /// Creates a new instance of the UniversalMachine with default values.
-    ///
-    /// Each register is initialized with the minimum value of u32, and the memory is empty.
-    pub fn new() -> Self {
+impl Usage {
+    fn map(&mut self, words: i64) {
+        self.mapped_words += words;
+        self.peak_words = self.peak_words.max(self.mapped_words);
+    }
+}
+
+/// Machines are equal when their architectural state is; snapshot bookkeeping is ignored.
+impl<W: Word> PartialEq for UniversalMachine<W> {
+    fn eq(&self, other: &Self) -> bool {
+        self.registers == other.registers
+            && self.mapped_memory == other.mapped_memory
+            && self.unmapped_memory == other.unmapped_memory
+            && self.program_counter == other.program_counter
+            && self.pending_input == other.pending_input
+    }
+}
+
+impl<W: Word> UniversalMachine<W> {
+    /// A machine with `program` in segment 0, ready to execute it from address 0.
+    pub fn with_program(program: Vec<W>) -> Self {
         Self {
-            registers: [u32::MIN; 8],
-            mapped_memory: Vec::new(),
+            registers: [W::ZERO; 8],
+            mapped_memory: vec![program],
             unmapped_memory: Vec::new(),
+            free: Vec::new(),
             program_counter: 0,
+            pending_input: VecDeque::new(),
+            dirty: Vec::new(),
+            usage: Usage::default(),
+            eof: Eof::default(),
+            reserved: Vec::new(),
+        }
+    }
+
+    /// Identifiers of unmapped segments waiting to be reused.
+    pub fn free_segments(&self) -> &[W] {
+        &self.unmapped_memory
+    }
+
+    /// True if segment `id` is in use: mapped (the program's segment 0
+    /// included) and not since unmapped, or reserved.
+    pub fn is_mapped(&self, id: W) -> bool {
+        let index = id.index();
+        (index < self.mapped_memory.len() && self.free.get(index) != Some(&true)) || self.reserved.contains(&id)
+    }
+
+    /// Keeps Map Segment from ever returning segment `id`, for a host that
+    /// serves Loads and Stores on it itself (see `device`). Unmap Segment on
+    /// it faults as on a segment never mapped.
+    pub fn reserve_segment(&mut self, id: W) {
+        self.unmapped_memory.retain(|&segment| segment != id);
+        if !self.reserved.contains(&id) {
+            self.reserved.push(id);
         }
     }
 
+    /// Primed input bytes not yet consumed by the program.
+    pub fn primed_input(&self) -> Vec<u8> {
+        self.pending_input.iter().copied().collect()
+    }
+
+    /// True if segment `seg` was modified since `clear_dirty` was last
+    /// called. Segments mapped since then, and every segment of a machine
+    /// never cleared, count as modified.
+    pub fn is_dirty(&self, seg: usize) -> bool {
+        self.dirty.get(seg).copied().unwrap_or(true)
+    }
+
+    /// Indices of segments modified since `clear_dirty` was last called.
+    pub fn dirty_segments(&self) -> Vec<usize> {
+        (0..self.mapped_memory.len()).filter(|&seg| self.is_dirty(seg)).collect()
+    }
+
+    /// Marks every segment clean, e.g. after writing a snapshot.
+    pub fn clear_dirty(&mut self) {
+        self.dirty = vec![false; self.mapped_memory.len()];
+    }
+
+    /// Marks segment `seg` modified, for a host that changed it through
+    /// `mapped_memory` directly.
+    pub fn mark_dirty(&mut self, seg: usize) {
+        if let Some(flag) = self.dirty.get_mut(seg) {
+            *flag = true;
+        }
+    }
+
+    /// Segment `seg` for writing, marked modified.
+    pub fn segment_mut(&mut self, seg: usize) -> Option<&mut Vec<W>> {
+        self.mark_dirty(seg);
+        self.mapped_memory.get_mut(seg)
+    }
+
+    /// Output, memory and input waits counted since `reset_usage` (or the start).
+    pub fn usage(&self) -> Usage {
+        self.usage
+    }
+
+    /// Bytes handed over for Input that it hasn't read yet; with none, it reads stdin.
+    pub fn pending_input(&self) -> usize {
+        self.pending_input.len()
+    }
+
+    /// Sets what Input does at the end of stdin (see `eof`).
+    pub fn set_eof(&mut self, eof: Eof) {
+        self.eof = eof;
+    }
+
+    /// Starts counting `usage` afresh from here.
+    pub fn reset_usage(&mut self) {
+        self.usage = Usage::default();
+    }
+
+    /// Value currently held in register `r`.
+    pub fn register(&self, r: u32) -> W {
+        self.registers[r as usize]
+    }
+
+    /// Queues `bytes` to be read by the Input instruction before stdin is consulted.
+    pub fn prime_input(&mut self, bytes: &[u8]) {
+        self.pending_input.extend(bytes);
+    }
+
+    /// Number of bytes currently held by mapped segments.
+    pub fn mapped_bytes(&self) -> usize {
+        self.mapped_memory.iter().map(|seg| seg.len() * std::mem::size_of::<W>()).sum()
+    }
+
     /// Conditional move instruction.
     ///
     /// Moves the value from register `b` to register `a` if the value in register `c` is not zero.
     pub fn cmov(&mut self, a: u32, b: u32, c: u32) {
-        if self.registers[c as usize] == 0 {
+        if self.registers[c as usize] == W::ZERO {
             return;
         }
 
@@ -71,20 +272,36 @@ impl UniversalMachine{
     ///
     /// Loads the value from the memory segment specified by registers `b` and `c`
     /// into register `a`.
-    pub fn load(&mut self, a: u32, b: u32, c: u32) {
-        let reg_b = self.registers[b as usize] as usize;
-        let reg_c = self.registers[c as usize] as usize;
+    pub fn load(&mut self, a: u32, b: u32, c: u32) -> Result<(), MachineError> {
+        let reg_b = self.registers[b as usize];
+        let reg_c = self.registers[c as usize];
 
-        self.registers[a as usize] = self.mapped_memory[reg_b][reg_c];
+        self.registers[a as usize] = *self.word(reg_b, reg_c)?;
+        Ok(())
     }
 
     /// Store instruction.
     ///
     /// Stores the value from register `c` into the memory segment specified by registers `a` and `b`.
-    pub fn store(&mut self, a: u32, b: u32, c: u32) {
-        let reg_a = self.registers[a as usize] as usize;
-        let reg_b = self.registers[b as usize] as usize;
-        self.mapped_memory[reg_a][reg_b] = self.registers[c as usize];
+    pub fn store(&mut self, a: u32, b: u32, c: u32) -> Result<(), MachineError> {
+        let reg_a = self.registers[a as usize];
+        let reg_b = self.registers[b as usize];
+        *self.word(reg_a, reg_b)? = self.registers[c as usize];
+        self.mark_dirty(reg_a.index());
+        Ok(())
+    }
+
+    /// The word at `offset` in `segment`, for Load and Store.
+    fn word(&mut self, segment: W, offset: W) -> Result<&mut W, MachineError> {
+        let pc = self.fault_pc();
+        let words = self.mapped_memory.get_mut(segment.index()).ok_or(MachineError::UnmappedSegment { pc, segment: segment.widen() })?;
+        words.get_mut(offset.index()).ok_or(MachineError::OutOfBounds { pc, segment: segment.widen(), offset: offset.widen() })
+    }
+
+    /// The address of the instruction being executed: the methods here run
+    /// with the program counter already past it.
+    fn fault_pc(&self) -> usize {
+        self.program_counter.saturating_sub(1)
     }
 
     /// Add instruction.
@@ -106,11 +323,14 @@ impl UniversalMachine{
     /// Divides the value in register `b` by the value in register `c`
     /// and stores the result in register `a`.
     ///
-    /// # Panics
-    ///
-    /// Panics if attempting to divide by zero.
-    pub fn division(&mut self, a: u32, b: u32, c: u32) {
+    /// Fails if attempting to divide by zero.
+    pub fn division(&mut self, a: u32, b: u32, c: u32) -> Result<(), MachineError> {
+        if self.registers[c as usize] == W::ZERO {
+            error!(pc = self.program_counter, "division by zero");
+            return Err(MachineError::DivisionByZero { pc: self.fault_pc() });
+        }
         self.registers[a as usize] = self.registers[b as usize].wrapping_div(self.registers[c as usize]);
+        Ok(())
     }
 
     /// NAND instruction.
@@ -123,9 +343,10 @@ impl UniversalMachine{
 
     /// Halt instruction.
     ///
-    /// Exits the program.
-    pub fn halt(&mut self) {
-        std::process::exit(0);
+    /// Stops the program: the caller sees `StepResult::Halt` and decides
+    /// what comes next, e.g. `rum` exiting.
+    pub fn halt(&mut self) -> StepResult {
+        StepResult::Halt
     }
 
     /// Map Segment instruction.
@@ -133,45 +354,49 @@ impl UniversalMachine{
     /// Creates a new memory segment with a capacity specified by the value in register `c`.
     /// The index of the newly mapped segment is stored in register `b`.
     pub fn map_seg(&mut self, b: u32, c: u32) {
-        let new_seg = vec![0_u32; self.registers[c as usize] as usize];
+        let new_seg = vec![W::ZERO; self.registers[c as usize].index()];
 
         let new_seg_idx = self.unmapped_memory.pop().unwrap_or_else(|| {
+            // A reserved identifier is passed over, left as an empty segment.
+            while self.reserved.contains(&W::from_index(self.mapped_memory.len())) {
+                self.mapped_memory.push(Vec::new());
+            }
             self.mapped_memory.push(new_seg.clone());
-            self.mapped_memory.len() as u32 - 1
+            W::from_index(self.mapped_memory.len() - 1)
         });
+        let index = new_seg_idx.index();
 
+        if let Some(flag) = self.free.get_mut(index) {
+            *flag = false;
+        }
         self.registers[b as usize] = new_seg_idx;
 
-        self.mapped_memory[new_seg_idx as usize] = new_seg;
+        self.usage.map(new_seg.len() as i64);
+        self.mapped_memory[index] = new_seg;
+        self.mark_dirty(index);
+        trace!(segment = index, words = self.mapped_memory[index].len(), "segment mapped");
     }
 
     /// Unmap Segment instruction.
     ///
     /// Frees the memory of the memory segment specified by the value in register `c`.
-    pub fn unmap_seg(&mut self, c: u32) {
+    /// Segment 0, segments never mapped, segments already unmapped and reserved ones are refused.
+    pub fn unmap_seg(&mut self, c: u32) -> Result<(), MachineError> {
         let free_seg = self.registers[c as usize];
-        self.mapped_memory[free_seg as usize].clear();
-        self.unmapped_memory.push(free_seg);
-    }
-
-    /// Output instruction.
-    ///
-    /// Prints the ASCII character corresponding to the value in register `c`.
-    pub fn output(&mut self, c: u32) {
-        let r = u8::try_from(self.registers[c as usize]).unwrap();
-        print!("{}", r as char);
-    }
-
-    /// Input instruction.
-    ///
-    /// Reads a character from standard input and stores its ASCII value in register `c`.
-    ///
-    /// If there is no input available, the register is set to the maximum value of u32.
-    pub fn input(&mut self, c: u32) {
-        match stdin().bytes().next() {
-            Some(input) => self.registers[c as usize] = input.unwrap() as u32,
-            None => self.registers[c as usize] = !0_u32,
+        let index = free_seg.index();
+        if free_seg == W::ZERO || index >= self.mapped_memory.len() || self.free.get(index) == Some(&true) || self.reserved.contains(&free_seg) {
+            return Err(MachineError::UnmappedSegment { pc: self.fault_pc(), segment: free_seg.widen() });
+        }
+        if self.free.len() <= index {
+            self.free.resize(index + 1, false);
         }
+        self.free[index] = true;
+        self.usage.map(-(self.mapped_memory[index].len() as i64));
+        self.mapped_memory[index].clear();
+        self.mark_dirty(index);
+        self.unmapped_memory.push(free_seg);
+        trace!(segment = index, "segment unmapped");
+        Ok(())
     }
 
     /// Load Program instruction.
@@ -179,22 +404,166 @@ impl UniversalMachine{
     /// Loads the memory segment specified by the value in register `b` into the program memory.
     ///
     /// If the location is 0, sets the program counter to the value in register `c`.
-    pub fn load_prog(&mut self, b: u32, c: u32){
-        let location = self.registers[b as usize] as usize;
+    pub fn load_prog(&mut self, b: u32, c: u32) -> Result<(), MachineError> {
+        let location = self.registers[b as usize].index();
         if location == 0 {
-            self.program_counter = self.registers[c as usize] as usize;
-            return
+            self.program_counter = self.registers[c as usize].index();
+            return Ok(());
+        }
+        if location >= self.mapped_memory.len() {
+            return Err(MachineError::UnmappedSegment { pc: self.fault_pc(), segment: self.registers[b as usize].widen() });
         }
+        self.usage.map(self.mapped_memory[location].len() as i64 - self.mapped_memory[0].len() as i64);
         self.mapped_memory[0] = self.mapped_memory[location].clone();
-        self.program_counter = self.registers[c as usize] as usize;
+        self.mark_dirty(0);
+        debug!(segment = location, words = self.mapped_memory[0].len(), "program replaced");
+        self.program_counter = self.registers[c as usize].index();
+        Ok(())
     }
 
     /// Load Value instruction.
     ///
     /// Loads the given value at the given register 'a'.
-    pub fn load_value(&mut self, a: u32, val: u32){
+    pub fn load_value(&mut self, a: u32, val: W) {
         self.registers[a as usize] = val
     }
+}
+
+impl UniversalMachine {
+    /// Creates a new instance of the UniversalMachine with default values.
+    ///
+    /// Each register is initialized with the minimum value of u32, and the memory is empty.
+    pub fn new() -> Self {
+        Self {
+            registers: [u32::MIN; 8],
+            mapped_memory: Vec::new(),
+            unmapped_memory: Vec::new(),
+            free: Vec::new(),
+            program_counter: 0,
+            pending_input: VecDeque::new(),
+            dirty: Vec::new(),
+            usage: Usage::default(),
+            eof: Eof::default(),
+            reserved: Vec::new(),
+        }
+    }
+
+    /// Rebuilds a machine from previously saved state (see `snapshot`).
+    pub fn from_parts(
+        registers: [u32; 8],
+        mapped_memory: Vec<Vec<u32>>,
+        unmapped_memory: Vec<u32>,
+        program_counter: usize,
+        pending_input: Vec<u8>,
+    ) -> Self {
+        let mut free = vec![false; mapped_memory.len()];
+        for &segment in &unmapped_memory {
+            if let Some(flag) = free.get_mut(segment as usize) {
+                *flag = true;
+            }
+        }
+        Self {
+            registers,
+            mapped_memory,
+            unmapped_memory,
+            free,
+            program_counter,
+            pending_input: pending_input.into(),
+            dirty: Vec::new(),
+            usage: Usage::default(),
+            eof: Eof::default(),
+            reserved: Vec::new(),
+        }
+    }
+
+    /// Output instruction.
+    ///
+    /// Writes the value in register `c` to standard output as a single byte.
+    pub fn output(&mut self, c: u32) -> Result<(), MachineError> {
+        let value = self.registers[c as usize];
+        let Ok(r) = u8::try_from(value) else {
+            error!(pc = self.program_counter, value, "output of a value above 255");
+            return Err(MachineError::OutputOutOfRange { pc: self.fault_pc(), value: value.into() });
+        };
+        stdout().write_all(&[r]).map_err(|e| MachineError::Io { pc: self.fault_pc(), message: format!("cannot write output: {e}") })?;
+        self.usage.output_bytes += 1;
+        Ok(())
+    }
 
+    /// Input instruction.
+    ///
+    /// Reads a character from standard input and stores its ASCII value in register `c`.
+    /// Any primed input (see `prime_input`) is consumed first.
+    ///
+    /// If there is no input available, the register is set to the maximum value of u32,
+    /// or whatever `set_eof` chose.
+    pub fn input(&mut self, c: u32) -> Result<(), MachineError> {
+        if let Some(primed) = self.pending_input.pop_front() {
+            self.registers[c as usize] = primed as u32;
+            return Ok(());
+        }
+        let mut read = self.read_stdin();
+        if read.is_none() && self.eof.after == AfterEof::Terminal && !self.eof.reached {
+            self.eof.reached = true;
+            if reopen_terminal().is_ok() {
+                read = self.read_stdin();
+            }
+        }
+        self.registers[c as usize] = match read {
+            Some(input) => input as u32,
+            None if self.eof.reached && self.eof.after == AfterEof::Fail => {
+                error!(pc = self.program_counter, "input after the end of input");
+                return Err(MachineError::InputAfterEnd { pc: self.fault_pc() });
+            }
+            None => {
+                self.eof.reached = true;
+                self.eof.value
+            }
+        };
+        Ok(())
+    }
 
+    /// One byte from stdin, timing the wait.
+    fn read_stdin(&mut self) -> Option<u8> {
+        let mut byte = [0_u8; 1];
+        let start = Instant::now();
+        let read = stdin().read(&mut byte).ok().filter(|&n| n == 1).map(|_| byte[0]);
+        self.usage.input_wait += start.elapsed();
+        read
+    }
+
+    /// Fetches, decodes and executes the one instruction at the program
+    /// counter, as `rumdis::run` would, and reports what it did.
+    ///
+    /// Output and Input use stdout and stdin as in `run`. Halt leaves the
+    /// program counter past it, as `run` does, so a caller stepping on
+    /// stops at `Step::halted`.
+    pub fn step(&mut self) -> Result<Step, MachineError> {
+        let pc = self.program_counter;
+        let instruction = *self.mapped_memory.first().and_then(|code| code.get(pc)).ok_or(MachineError::ProgramCounterOutOfBounds { pc })?;
+        let (a, b, c) = (get(&RA, instruction), get(&RB, instruction), get(&RC, instruction));
+        let moves = self.registers[c as usize] != 0;
+        self.program_counter += 1;
+        let halted = disassemble(instruction, self)? == StepResult::Halt;
+        // `disassemble` succeeded, so the opcode is a valid one.
+        let opcode = op(instruction).unwrap();
+        let (read, written) = match opcode {
+            Opcode::CMov => (vec![b, c], moves.then_some(a)),
+            Opcode::SegLoad | Opcode::Add | Opcode::Mul | Opcode::Div | Opcode::BNand => (vec![b, c], Some(a)),
+            Opcode::SegStore => (vec![a, b, c], None),
+            Opcode::Halt => (vec![], None),
+            Opcode::MapSeg => (vec![c], Some(b)),
+            Opcode::UnmapSeg | Opcode::Output => (vec![c], None),
+            Opcode::Input => (vec![], Some(c)),
+            Opcode::LoadProg => (vec![b, c], None),
+            Opcode::LoadVal => (vec![], Some(get(&RL, instruction))),
+        };
+        Ok(Step { pc, instruction, opcode, read, written, halted })
+    }
+}
+
+impl Default for UniversalMachine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -0,0 +1,122 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::state::UniversalMachine;
+
+/// Changed words of one segment mapped in both machines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentDiff {
+    pub index: usize,
+    /// Lengths before and after.
+    pub len: (usize, usize),
+    /// Half-open ranges of word offsets whose contents differ.
+    pub ranges: Vec<(usize, usize)>,
+}
+
+/// Everything that differs between two machine states.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StateDiff {
+    /// `(register, before, after)`
+    pub registers: Vec<(u32, u32, u32)>,
+    pub program_counter: Option<(usize, usize)>,
+    /// Segments mapped only in the second state.
+    pub added: Vec<usize>,
+    /// Segments mapped only in the first state.
+    pub removed: Vec<usize>,
+    pub changed: Vec<SegmentDiff>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == StateDiff::default()
+    }
+}
+
+/// Indices of the segments currently mapped in `state`.
+fn mapped(state: &UniversalMachine) -> Vec<bool> {
+    let free: HashSet<u32> = state.free_segments().iter().copied().collect();
+    (0..state.mapped_memory.len()).map(|i| !free.contains(&(i as u32))).collect()
+}
+
+/// Compares machine `a` (before) with machine `b` (after).
+pub fn diff(a: &UniversalMachine, b: &UniversalMachine) -> StateDiff {
+    let mut out = StateDiff::default();
+    for r in 0..8 {
+        if a.register(r) != b.register(r) {
+            out.registers.push((r, a.register(r), b.register(r)));
+        }
+    }
+    if a.program_counter != b.program_counter {
+        out.program_counter = Some((a.program_counter, b.program_counter));
+    }
+
+    let (in_a, in_b) = (mapped(a), mapped(b));
+    for index in 0..in_a.len().max(in_b.len()) {
+        match (in_a.get(index).copied().unwrap_or(false), in_b.get(index).copied().unwrap_or(false)) {
+            (false, true) => out.added.push(index),
+            (true, false) => out.removed.push(index),
+            (true, true) => {
+                let (old, new) = (&a.mapped_memory[index], &b.mapped_memory[index]);
+                let ranges = changed_ranges(old, new);
+                if !ranges.is_empty() {
+                    out.changed.push(SegmentDiff { index, len: (old.len(), new.len()), ranges });
+                }
+            }
+            (false, false) => {}
+        }
+    }
+    out
+}
+
+/// Ranges of offsets where `old` and `new` differ, counting words past the
+/// end of the shorter one as different.
+fn changed_ranges(old: &[u32], new: &[u32]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for offset in 0..old.len().max(new.len()) {
+        if old.get(offset) == new.get(offset) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some((_, end)) if *end == offset => *end += 1,
+            _ => ranges.push((offset, offset + 1)),
+        }
+    }
+    ranges
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "states are identical");
+        }
+        if let Some((before, after)) = self.program_counter {
+            writeln!(f, "pc: {before} -> {after}")?;
+        }
+        for (r, before, after) in &self.registers {
+            writeln!(f, "r{r}: {before:#010x} -> {after:#010x}")?;
+        }
+        if !self.added.is_empty() {
+            writeln!(f, "segments mapped: {}", list(&self.added))?;
+        }
+        if !self.removed.is_empty() {
+            writeln!(f, "segments unmapped: {}", list(&self.removed))?;
+        }
+        for seg in &self.changed {
+            let words: usize = seg.ranges.iter().map(|(start, end)| end - start).sum();
+            write!(f, "segment {}: {words} words changed", seg.index)?;
+            if seg.len.0 != seg.len.1 {
+                write!(f, " (length {} -> {})", seg.len.0, seg.len.1)?;
+            }
+            let shown: Vec<String> = seg.ranges.iter().take(8).map(|(s, e)| format!("{s}..{e}")).collect();
+            let more = if seg.ranges.len() > 8 { format!(" and {} more", seg.ranges.len() - 8) } else { String::new() };
+            writeln!(f, " at {}{more}", shown.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn list(indices: &[usize]) -> String {
+    let shown: Vec<String> = indices.iter().take(16).map(usize::to_string).collect();
+    let more = if indices.len() > 16 { format!(" and {} more", indices.len() - 16) } else { String::new() };
+    format!("{}{more}", shown.join(", "))
+}
@@ -0,0 +1,177 @@
+//! Driving a machine interactively, e.g. from an evcxr/Jupyter notebook.
+//!
+//! A `Stream` runs the machine on its own thread. Output arrives on a
+//! channel as the program produces it (flushed at every newline, whenever
+//! the program waits for input, and at Halt), and input is pulled from what
+//! the host has sent: when nothing is queued the machine blocks until the
+//! host sends more or closes the input.
+//!
+//! ```no_run
+//! let stream = rum::stream::Stream::spawn(rum::load::load(Some("bin/advent.um")).unwrap());
+//! print!("{}", stream.interact(""));          // everything up to the first prompt
+//! print!("{}", stream.interact("look\n"));    // the answer, up to the next prompt
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+
+use crate::cancel::CancellationToken;
+use crate::embed::{Machine, Status};
+
+/// Output is also flushed once this many bytes are pending without a newline.
+const CHUNK: usize = 4096;
+
+/// What the machine thread reports to the host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Bytes the program wrote since the previous `Output` event.
+    Output(Vec<u8>),
+    /// The program executed Input and nothing is queued; it waits for `send` or `close_input`.
+    WaitingForInput,
+    /// The program halted after executing this many instructions.
+    Halted(u64),
+    /// The program faulted with this message.
+    Faulted(String),
+}
+
+enum Feed {
+    Bytes(Vec<u8>),
+    Close,
+}
+
+/// A machine running on a background thread, fed and read through channels.
+pub struct Stream {
+    input: Sender<Feed>,
+    events: Receiver<Event>,
+    stop: CancellationToken,
+    thread: Option<JoinHandle<Machine>>,
+}
+
+impl Stream {
+    /// Starts executing `program` from address 0.
+    pub fn spawn(program: Vec<u32>) -> Self {
+        Self::from_machine(Machine::new(program))
+    }
+
+    /// Starts executing `machine` where it stands.
+    pub fn from_machine(mut machine: Machine) -> Self {
+        let (input, fed) = channel();
+        let (emit, events) = channel();
+        let stop = CancellationToken::new();
+        let stopped = stop.clone();
+        let thread = std::thread::spawn(move || {
+            drive(&mut machine, &fed, &emit, &stopped);
+            machine
+        });
+        Self { input, events, stop, thread: Some(thread) }
+    }
+
+    /// Queues `bytes` for the program's Input instructions.
+    pub fn send(&self, bytes: impl AsRef<[u8]>) {
+        let _ = self.input.send(Feed::Bytes(bytes.as_ref().to_vec()));
+    }
+
+    /// Once the queued bytes are read, Input sees end of input instead of waiting.
+    pub fn close_input(&self) {
+        let _ = self.input.send(Feed::Close);
+    }
+
+    /// Events in the order they happened; iterate it, or `recv`/`try_recv` one at a time.
+    pub fn events(&self) -> &Receiver<Event> {
+        &self.events
+    }
+
+    /// Sends `text`, then collects output until the program next waits for
+    /// input, halts or faults, which is what a notebook cell wants to show.
+    pub fn interact(&self, text: &str) -> String {
+        if !text.is_empty() {
+            self.send(text);
+        }
+        let mut output = Vec::new();
+        for event in self.events.iter() {
+            match event {
+                Event::Output(bytes) => output.extend(bytes),
+                Event::WaitingForInput => break,
+                Event::Halted(_) => break,
+                Event::Faulted(message) => {
+                    output.extend(format!("\n[fault: {message}]\n").bytes());
+                    break;
+                }
+            }
+        }
+        String::from_utf8_lossy(&output).into_owned()
+    }
+
+    /// Waits for the program to halt or fault and returns the machine.
+    /// Input that was never sent is seen as end of input.
+    pub fn join(mut self) -> Machine {
+        self.close_input();
+        self.thread.take().unwrap().join().expect("stream thread")
+    }
+}
+
+impl Drop for Stream {
+    /// Stops a machine that is still running; the thread exits at its next instruction.
+    fn drop(&mut self) {
+        self.stop.cancel();
+        let _ = self.input.send(Feed::Close);
+    }
+}
+
+fn drive(machine: &mut Machine, fed: &Receiver<Feed>, emit: &Sender<Event>, stop: &CancellationToken) {
+    let mut queued = VecDeque::new();
+    let mut closed = false;
+    let mut pending = Vec::new();
+    let flush = |pending: &mut Vec<u8>| {
+        if !pending.is_empty() {
+            let _ = emit.send(Event::Output(std::mem::take(pending)));
+        }
+    };
+    while !stop.is_cancelled() {
+        if machine.wants_input() {
+            while let Ok(feed) = fed.try_recv() {
+                closed |= take(feed, &mut queued);
+            }
+            if queued.is_empty() && !closed {
+                flush(&mut pending);
+                let _ = emit.send(Event::WaitingForInput);
+                // A dropped `Stream` closes the channel, which reads as end of input.
+                closed |= fed.recv().map_or(true, |feed| take(feed, &mut queued));
+                continue;
+            }
+        }
+        let mut line = false;
+        let step = machine.step(&mut || queued.pop_front(), &mut |byte| {
+            pending.push(byte);
+            line = byte == b'\n';
+        });
+        let status = match step {
+            Ok(status) => status,
+            Err(e) => {
+                flush(&mut pending);
+                let _ = emit.send(Event::Faulted(e.to_string()));
+                return;
+            }
+        };
+        if line || pending.len() >= CHUNK {
+            flush(&mut pending);
+        }
+        if status == Status::Halted {
+            flush(&mut pending);
+            let _ = emit.send(Event::Halted(machine.executed()));
+            return;
+        }
+    }
+}
+
+/// Adds fed bytes to the queue; returns whether the host closed the input.
+fn take(feed: Feed, queued: &mut VecDeque<u8>) -> bool {
+    match feed {
+        Feed::Bytes(bytes) => {
+            queued.extend(bytes);
+            false
+        }
+        Feed::Close => true,
+    }
+}
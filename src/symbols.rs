@@ -0,0 +1,107 @@
+//! Symbol files: named routines of segment 0, written by `rum routines`
+//! and read by `rum profile --symbols` to report by routine.
+//!
+//! Plain text, one routine per line, by address, as entry address, size in
+//! words and name; anything after `#` is a comment, where `rum routines`
+//! lists each routine's callers:
+//!
+//! ```text
+//! # rum symbols: routines of segment 0 (entry size name)
+//! 0 212 start
+//! 212 37 fn_212    # called from 55 130 198
+//! ```
+//!
+//! Names are free-form words, so a file can be edited by hand as routines
+//! are understood: renaming `fn_212` to `print_string` carries through to
+//! every later profile.
+
+use std::io::Write;
+use std::path::Path;
+
+/// The first line of a symbol file.
+pub const HEADER: &str = "# rum symbols: routines of segment 0 (entry size name)";
+
+/// A routine of segment 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Routine {
+    pub entry: usize,
+    /// Words from `entry` that belong to the routine.
+    pub size: usize,
+    pub name: String,
+    /// Addresses it is called from, where known.
+    pub callers: Vec<usize>,
+}
+
+impl Routine {
+    pub fn contains(&self, pc: usize) -> bool {
+        (self.entry..self.entry + self.size).contains(&pc)
+    }
+}
+
+/// The routines of a program, by entry address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    routines: Vec<Routine>,
+}
+
+impl Symbols {
+    pub fn new(mut routines: Vec<Routine>) -> Self {
+        routines.sort_by_key(|routine| routine.entry);
+        Self { routines }
+    }
+
+    pub fn routines(&self) -> &[Routine] {
+        &self.routines
+    }
+
+    /// The routine `pc` belongs to.
+    pub fn lookup(&self, pc: usize) -> Option<&Routine> {
+        self.position(pc).map(|i| &self.routines[i])
+    }
+
+    /// The index in `routines` of the routine `pc` belongs to.
+    pub fn position(&self, pc: usize) -> Option<usize> {
+        let next = self.routines.partition_point(|routine| routine.entry <= pc);
+        next.checked_sub(1).filter(|&i| self.routines[i].contains(pc))
+    }
+
+    /// Writes the file read back by `read`.
+    pub fn write(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        writeln!(out, "{HEADER}")?;
+        for routine in &self.routines {
+            write!(out, "{} {} {}", routine.entry, routine.size, routine.name)?;
+            if !routine.callers.is_empty() {
+                let callers: Vec<String> = routine.callers.iter().map(usize::to_string).collect();
+                write!(out, "    # called from {}", callers.join(" "))?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(&mut file)?;
+        file.flush()
+    }
+
+    /// Parses a symbol file. Blank lines and comments are skipped, so
+    /// callers are not read back.
+    pub fn read(text: &str) -> Result<Self, String> {
+        let mut routines = Vec::new();
+        for line in text.lines().map(|line| line.split('#').next().unwrap_or("").trim()).filter(|line| !line.is_empty()) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [entry, size, name] = fields[..] else {
+                return Err(format!("malformed line `{line}`"));
+            };
+            let number = |field: &str| field.parse().map_err(|_| format!("malformed line `{line}`"));
+            routines.push(Routine { entry: number(entry)?, size: number(size)?, name: name.to_string(), callers: Vec::new() });
+        }
+        Ok(Self::new(routines))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::read(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
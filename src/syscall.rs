@@ -0,0 +1,247 @@
+//! Host services for UM programs through a syscall opcode (`rum run
+//! --syscalls`).
+//!
+//! Opcode 15, undefined in the UM, becomes `syscall`: register A holds the
+//! function number and gets the result, registers B and C are the
+//! arguments. A `Syscalls` machine looks the number up in its service
+//! table; a number with no service faults, and without a `Syscalls`
+//! wrapper the opcode stays invalid, so standard binaries are unaffected.
+//! `register_standard` fills the table with:
+//!
+//! ```text
+//! 0  EXIT   B = exit code; the program stops there
+//! 1  TIME   seconds since the Unix epoch, by the `clock` given
+//! 2  ARGC   number of program arguments
+//! 3  ARG    copies argument B into segment C, a byte per word, as far as
+//!           it fits, and returns its length (FAILED past the last one)
+//! 4  OPEN   opens the file named by segment B, a byte per word, to read
+//!           (C = 0), write (1, truncating) or append (2); returns a handle
+//! 5  READ   the next byte from handle B, FAILED at end of file
+//! 6  WRITE  writes byte C to handle B
+//! 7  CLOSE  closes handle B
+//! ```
+//!
+//! Services that fail return `FAILED`. Files are opened relative to the
+//! host's working directory with the host's permissions, which is why the
+//! table is only there when asked for.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::rc::Rc;
+use crate::clock::Clock;
+use crate::embed::{Machine, Status, Stepper};
+use crate::error::MachineError;
+
+/// The syscall opcode.
+pub const OPCODE: u32 = 15;
+
+pub const EXIT: u32 = 0;
+pub const TIME: u32 = 1;
+pub const ARGC: u32 = 2;
+pub const ARG: u32 = 3;
+pub const OPEN: u32 = 4;
+pub const READ: u32 = 5;
+pub const WRITE: u32 = 6;
+pub const CLOSE: u32 = 7;
+
+/// What a failed service returns.
+pub const FAILED: u32 = u32::MAX;
+
+/// The word for `syscall` with register fields `a`, `b` and `c`.
+pub fn encode(a: u32, b: u32, c: u32) -> u32 {
+    OPCODE << 28 | (a & 7) << 6 | (b & 7) << 3 | (c & 7)
+}
+
+/// What a service hands back to the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Return {
+    /// Goes into register A.
+    Value(u32),
+    /// Stops the program with this exit code.
+    Exit(u32),
+}
+
+/// A host service, given the machine and the values of registers B and C.
+/// An `Err` faults the program.
+pub type Service = Box<dyn FnMut(&mut Machine, u32, u32) -> Result<Return, String>>;
+
+/// An embedded machine whose syscalls reach a service table.
+pub struct Syscalls {
+    pub machine: Machine,
+    services: BTreeMap<u32, Service>,
+    exit: Option<u32>,
+    /// Syscalls made so far.
+    pub calls: u64,
+}
+
+impl Syscalls {
+    /// Wraps `machine` with an empty service table.
+    pub fn new(machine: Machine) -> Self {
+        Self { machine, services: BTreeMap::new(), exit: None, calls: 0 }
+    }
+
+    /// Serves function `number` with `service`, replacing any service it had.
+    pub fn register(&mut self, number: u32, service: Service) {
+        self.services.insert(number, service);
+    }
+
+    /// Registers the standard services, with `args` as the program's
+    /// arguments and time from `clock`.
+    pub fn register_standard(&mut self, args: Vec<String>, clock: Clock) {
+        let args = Rc::new(args);
+        self.register(EXIT, Box::new(|_, code, _| Ok(Return::Exit(code))));
+        self.register(TIME, Box::new(move |machine, _, _| Ok(Return::Value(clock.unix_seconds(machine.executed()) as u32))));
+        let count = args.len() as u32;
+        self.register(ARGC, Box::new(move |_, _, _| Ok(Return::Value(count))));
+        self.register(
+            ARG,
+            Box::new(move |machine, index, segment| {
+                let Some(arg) = args.get(index as usize) else {
+                    return Ok(Return::Value(FAILED));
+                };
+                let buffer = machine.state.segment_mut(mapped(machine, segment)?).unwrap();
+                for (word, &byte) in buffer.iter_mut().zip(arg.as_bytes()) {
+                    *word = byte as u32;
+                }
+                Ok(Return::Value(arg.len() as u32))
+            }),
+        );
+        let files = Rc::new(RefCell::new(Vec::<Option<Handle>>::new()));
+        let table = files.clone();
+        self.register(
+            OPEN,
+            Box::new(move |machine, segment, mode| {
+                let path: String = machine.state.mapped_memory[mapped(machine, segment)?].iter().map(|&word| word as u8 as char).collect();
+                let file = match mode {
+                    0 => File::open(&path).map(|file| Handle::Read(BufReader::new(file))),
+                    1 => File::create(&path).map(|file| Handle::Write(BufWriter::new(file))),
+                    2 => OpenOptions::new().append(true).create(true).open(&path).map(|file| Handle::Write(BufWriter::new(file))),
+                    _ => return Ok(Return::Value(FAILED)),
+                };
+                let Ok(file) = file else {
+                    return Ok(Return::Value(FAILED));
+                };
+                let mut files = table.borrow_mut();
+                let handle = files.iter().position(Option::is_none).unwrap_or(files.len());
+                match files.get_mut(handle) {
+                    Some(slot) => *slot = Some(file),
+                    None => files.push(Some(file)),
+                }
+                Ok(Return::Value(handle as u32))
+            }),
+        );
+        let table = files.clone();
+        self.register(
+            READ,
+            Box::new(move |_, handle, _| {
+                let mut byte = [0];
+                let read = match table.borrow_mut().get_mut(handle as usize) {
+                    Some(Some(Handle::Read(file))) => file.read(&mut byte).ok() == Some(1),
+                    _ => false,
+                };
+                Ok(Return::Value(if read { byte[0] as u32 } else { FAILED }))
+            }),
+        );
+        let table = files.clone();
+        self.register(
+            WRITE,
+            Box::new(move |_, handle, byte| {
+                let written = match table.borrow_mut().get_mut(handle as usize) {
+                    Some(Some(Handle::Write(file))) => file.write_all(&[byte as u8]).is_ok(),
+                    _ => false,
+                };
+                Ok(Return::Value(if written { 0 } else { FAILED }))
+            }),
+        );
+        self.register(
+            CLOSE,
+            Box::new(move |_, handle, _| {
+                let closed = match files.borrow_mut().get_mut(handle as usize).and_then(Option::take) {
+                    Some(Handle::Write(mut file)) => file.flush().is_ok(),
+                    Some(Handle::Read(_)) => true,
+                    None => false,
+                };
+                Ok(Return::Value(if closed { 0 } else { FAILED }))
+            }),
+        );
+    }
+
+    /// The code the program exited with through `EXIT`.
+    pub fn exit_code(&self) -> Option<u32> {
+        self.exit
+    }
+
+    /// Executes one instruction like `Machine::step`, calling the service
+    /// for a syscall. Halted once the program exits. A syscall with no
+    /// service, or whose service returns `Err`, faults the program with
+    /// `MachineError::HostFault`.
+    pub fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        if self.status() == Status::Halted {
+            return Ok(Status::Halted);
+        }
+        let state = &self.machine.state;
+        let pc = state.program_counter;
+        let instruction = *state.mapped_memory.first().and_then(|program| program.get(pc)).ok_or(MachineError::ProgramCounterOutOfBounds { pc })?;
+        if instruction >> 28 != OPCODE {
+            return self.machine.step(input, output);
+        }
+        let (a, b, c) = (instruction >> 6 & 7, instruction >> 3 & 7, instruction & 7);
+        let number = state.register(a);
+        let (b, c) = (state.register(b), state.register(c));
+        let Some(service) = self.services.get_mut(&number) else {
+            return Err(MachineError::HostFault { pc, message: format!("no service for syscall {number}") });
+        };
+        let result = service(&mut self.machine, b, c).map_err(|e| MachineError::HostFault { pc, message: format!("syscall {number}: {e}") })?;
+        self.calls += 1;
+        self.machine.retire();
+        match result {
+            Return::Value(value) => self.machine.state.load_value(a, value),
+            Return::Exit(code) => {
+                self.exit = Some(code);
+                return Ok(Status::Halted);
+            }
+        }
+        Ok(Status::Running)
+    }
+
+    pub fn status(&self) -> Status {
+        match self.exit {
+            Some(_) => Status::Halted,
+            None => self.machine.status(),
+        }
+    }
+}
+
+impl Stepper for Syscalls {
+    fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        Syscalls::step(self, input, output)
+    }
+
+    fn status(&self) -> Status {
+        Syscalls::status(self)
+    }
+
+    fn executed(&self) -> u64 {
+        self.machine.executed()
+    }
+
+    fn cancelled(&self) -> bool {
+        self.machine.cancelled()
+    }
+}
+
+enum Handle {
+    Read(BufReader<File>),
+    Write(BufWriter<File>),
+}
+
+/// The index of `segment` if it is mapped, or an error for a service to fault with.
+fn mapped(machine: &Machine, segment: u32) -> Result<usize, String> {
+    let state = &machine.state;
+    match (segment as usize) < state.mapped_memory.len() && !state.free_segments().contains(&segment) {
+        true => Ok(segment as usize),
+        false => Err(format!("segment {segment} is not mapped")),
+    }
+}
@@ -0,0 +1,364 @@
+//! Input-to-output taint tracking for `rum taint`: which input bytes each
+//! output byte, and each branch, depends on.
+//!
+//! Every byte Input reads is tagged with its offset in the input, and tags
+//! follow the values they reach, shadowing every register and memory word
+//! with the set of input offsets its value was computed from:
+//!
+//! - Add, Multiply, Divide and NAND tag their result with both operands' tags;
+//! - Conditional Move tags its destination with the condition's tag too,
+//!   moved or not, since either way the value depends on it;
+//! - Load also takes the tags of the segment and offset it read from, so
+//!   lookups in tables indexed by input stay tainted; Store only moves the
+//!   value's tag;
+//! - Load Value, Map Segment's identifier and the words of a new segment
+//!   are clean, and Load Program copies the shadow of the segment it loads.
+//!
+//! A branch is a Load Program whose target (or segment) is tainted, or a
+//! Conditional Move whose condition is: the places where input decides
+//! what the program does next. Branches are keyed by address in segment 0
+//! at the time, as in `memprofile`.
+//!
+//! Tag sets are interned, so a shadow word is a `u32` label. Segments only
+//! get a shadow once something tainted is stored in them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::embed::{Machine, Status};
+use crate::error::MachineError;
+use crate::instruction::Instruction;
+use crate::report::Table;
+use crate::rumdis::describe;
+use crate::state::UniversalMachine;
+
+/// The label of an untainted value.
+pub const CLEAN: u32 = 0;
+
+/// Interned sets of input offsets, each named by a label.
+#[derive(Debug, Clone)]
+pub struct Labels {
+    sets: Vec<Vec<u32>>,
+    ids: HashMap<Vec<u32>, u32>,
+    unions: HashMap<(u32, u32), u32>,
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Self { sets: vec![Vec::new()], ids: HashMap::from([(Vec::new(), CLEAN)]), unions: HashMap::new() }
+    }
+}
+
+impl Labels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn intern(&mut self, set: Vec<u32>) -> u32 {
+        if let Some(&id) = self.ids.get(&set) {
+            return id;
+        }
+        let id = self.sets.len() as u32;
+        self.sets.push(set.clone());
+        self.ids.insert(set, id);
+        id
+    }
+
+    /// The label of the set holding just `offset`.
+    pub fn single(&mut self, offset: u32) -> u32 {
+        self.intern(vec![offset])
+    }
+
+    /// The label of the union of `a` and `b`.
+    pub fn union(&mut self, a: u32, b: u32) -> u32 {
+        let (a, b) = (a.min(b), a.max(b));
+        if a == b || a == CLEAN {
+            return b;
+        }
+        if let Some(&id) = self.unions.get(&(a, b)) {
+            return id;
+        }
+        let mut set: Vec<u32> = self.sets[a as usize].iter().chain(&self.sets[b as usize]).copied().collect();
+        set.sort_unstable();
+        set.dedup();
+        let id = self.intern(set);
+        self.unions.insert((a, b), id);
+        id
+    }
+
+    /// The input offsets of `label`, in order.
+    pub fn get(&self, label: u32) -> &[u32] {
+        &self.sets[label as usize]
+    }
+}
+
+/// The kind of a branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchKind {
+    /// A Load Program with a tainted target or segment.
+    Jump,
+    /// A Conditional Move with a tainted condition.
+    Condition,
+}
+
+impl BranchKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            BranchKind::Jump => "jump",
+            BranchKind::Condition => "condition",
+        }
+    }
+}
+
+/// A branch that depended on input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Branch {
+    pub pc: usize,
+    /// The instruction at `pc`.
+    pub word: u32,
+    pub kind: BranchKind,
+    /// Times it ran with a tainted operand.
+    pub count: u64,
+    /// Every input offset it depended on, as a label.
+    pub label: u32,
+}
+
+/// The taint state of a running program.
+#[derive(Debug, Clone, Default)]
+pub struct Tracker {
+    labels: Labels,
+    registers: [u32; 8],
+    /// Labels by segment and offset; an empty shadow is all clean.
+    shadow: Vec<Vec<u32>>,
+    /// Input bytes read so far.
+    read: u32,
+    /// Each output byte and its label.
+    outputs: Vec<(u8, u32)>,
+    branches: BTreeMap<usize, Branch>,
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn word(&self, segment: usize, offset: usize) -> u32 {
+        self.shadow.get(segment).and_then(|words| words.get(offset)).copied().unwrap_or(CLEAN)
+    }
+
+    fn branch(&mut self, pc: usize, word: u32, kind: BranchKind, label: u32) {
+        if label == CLEAN {
+            return;
+        }
+        let branch = self.branches.entry(pc).or_insert(Branch { pc, word, kind, count: 0, label: CLEAN });
+        branch.count += 1;
+        branch.label = self.labels.union(branch.label, label);
+    }
+
+    /// Propagates taint through the instruction `inst` about to run from
+    /// `pc` in `state`.
+    pub fn before(&mut self, pc: usize, inst: u32, state: &UniversalMachine) {
+        let r = |n: u32| n as usize;
+        match Instruction::decode(inst) {
+            Instruction::CMov { a, b, c } => {
+                let (condition, moved) = (self.registers[r(c)], state.register(c) != 0);
+                let value = if moved { self.registers[r(b)] } else { self.registers[r(a)] };
+                self.registers[r(a)] = self.labels.union(value, condition);
+                self.branch(pc, inst, BranchKind::Condition, condition);
+            }
+            Instruction::Load { a, b, c } => {
+                let word = self.word(state.register(b) as usize, state.register(c) as usize);
+                let address = self.labels.union(self.registers[r(b)], self.registers[r(c)]);
+                self.registers[r(a)] = self.labels.union(word, address);
+            }
+            Instruction::Store { a, b, c } => {
+                let (segment, offset, label) = (state.register(a) as usize, state.register(b) as usize, self.registers[r(c)]);
+                if self.shadow.len() <= segment {
+                    self.shadow.resize(segment + 1, Vec::new());
+                }
+                let shadow = &mut self.shadow[segment];
+                if shadow.is_empty() && label != CLEAN {
+                    shadow.resize(state.mapped_memory.get(segment).map_or(0, Vec::len), CLEAN);
+                }
+                if let Some(word) = shadow.get_mut(offset) {
+                    *word = label;
+                }
+            }
+            Instruction::Add { a, b, c } | Instruction::Mul { a, b, c } | Instruction::Div { a, b, c } | Instruction::Nand { a, b, c } => {
+                self.registers[r(a)] = self.labels.union(self.registers[r(b)], self.registers[r(c)]);
+            }
+            Instruction::Map { b, .. } => self.registers[r(b)] = CLEAN,
+            // Identifiers are reused only once unmapped, so new segments start clean.
+            Instruction::Unmap { c } => {
+                if let Some(shadow) = self.shadow.get_mut(state.register(c) as usize) {
+                    *shadow = Vec::new();
+                }
+            }
+            Instruction::Output { c } => self.outputs.push((state.register(c) as u8, self.registers[r(c)])),
+            Instruction::LoadProgram { b, c } => {
+                let label = self.labels.union(self.registers[r(b)], self.registers[r(c)]);
+                self.branch(pc, inst, BranchKind::Jump, label);
+                let segment = state.register(b) as usize;
+                if segment != 0 {
+                    let shadow = self.shadow.get(segment).cloned().unwrap_or_default();
+                    match self.shadow.first_mut() {
+                        Some(zero) => *zero = shadow,
+                        None => self.shadow.push(shadow),
+                    }
+                }
+            }
+            Instruction::Orthography { a, .. } => self.registers[r(a)] = CLEAN,
+            Instruction::Input { .. } | Instruction::Halt | Instruction::Invalid(_) => {}
+        }
+    }
+
+    /// Completes the instruction `inst` noted by `before` once it has run:
+    /// tags the byte an Input read (end of input is clean).
+    pub fn after(&mut self, inst: u32, state: &UniversalMachine) {
+        match Instruction::decode(inst) {
+            Instruction::Input { c } if state.register(c) != u32::MAX => {
+                self.registers[c as usize] = self.labels.single(self.read);
+                self.read += 1;
+            }
+            Instruction::Input { c } => self.registers[c as usize] = CLEAN,
+            _ => {}
+        }
+    }
+
+    pub fn labels(&self) -> &Labels {
+        &self.labels
+    }
+
+    /// The input offsets register `r` depends on.
+    pub fn register(&self, r: u32) -> &[u32] {
+        self.labels.get(self.registers[r as usize])
+    }
+
+    /// The input offsets word `offset` of `segment` depends on.
+    pub fn memory(&self, segment: usize, offset: usize) -> &[u32] {
+        self.labels.get(self.word(segment, offset))
+    }
+
+    /// Input bytes read.
+    pub fn read(&self) -> u32 {
+        self.read
+    }
+
+    /// Each byte output, with the input offsets it depends on.
+    pub fn outputs(&self) -> impl Iterator<Item = (u8, &[u32])> {
+        self.outputs.iter().map(|&(byte, label)| (byte, self.labels.get(label)))
+    }
+
+    /// The branches that depended on input, by address.
+    pub fn branches(&self) -> impl Iterator<Item = &Branch> {
+        self.branches.values()
+    }
+
+    /// A report of the tainted output bytes, runs of bytes with the same
+    /// dependencies together, and of the branches that depended on input.
+    pub fn report(&self) -> String {
+        let mut text = String::new();
+        let mut start = 0;
+        while start < self.outputs.len() {
+            let label = self.outputs[start].1;
+            let end = start + self.outputs[start..].iter().take_while(|&&(_, l)| l == label).count();
+            if label != CLEAN {
+                let bytes: Vec<u8> = self.outputs[start..end].iter().map(|&(byte, _)| byte).collect();
+                let _ = writeln!(text, "output {} {:?} <- input {}", span(start, end), String::from_utf8_lossy(&bytes), ranges(self.labels.get(label)));
+            }
+            start = end;
+        }
+        for branch in self.branches() {
+            let _ = writeln!(
+                text,
+                "{} at {} ({}), {} times <- input {}",
+                branch.kind.name(),
+                branch.pc,
+                describe(branch.word),
+                branch.count,
+                ranges(self.labels.get(branch.label))
+            );
+        }
+        let tainted = self.outputs.iter().filter(|&&(_, label)| label != CLEAN).count();
+        let _ = writeln!(
+            text,
+            "{} input bytes read; {tainted} of {} output bytes and {} branches depend on input",
+            self.read,
+            self.outputs.len(),
+            self.branches.len()
+        );
+        text
+    }
+
+    /// The report as tables for `--report-format` (see `report`):
+    /// `outputs`, one row per output byte, and `branches`.
+    pub fn tables(&self) -> Vec<Table> {
+        let mut outputs = Table::new("outputs", &["offset", "byte", "inputs"]);
+        for (offset, (byte, inputs)) in self.outputs().enumerate() {
+            outputs.row(vec![offset.into(), u64::from(byte).into(), ranges(inputs).into()]);
+        }
+        let mut branches = Table::new("branches", &["address", "kind", "count", "inputs", "instruction"]);
+        for branch in self.branches() {
+            branches.row(vec![
+                branch.pc.into(),
+                branch.kind.name().into(),
+                branch.count.into(),
+                ranges(self.labels.get(branch.label)).into(),
+                describe(branch.word).into(),
+            ]);
+        }
+        vec![outputs, branches]
+    }
+}
+
+fn span(start: usize, end: usize) -> String {
+    match end - start {
+        1 => start.to_string(),
+        _ => format!("{start}-{}", end - 1),
+    }
+}
+
+/// Sorted offsets as ranges: `0-3,7`.
+pub fn ranges(offsets: &[u32]) -> String {
+    let mut text = String::new();
+    let mut i = 0;
+    while i < offsets.len() {
+        let mut j = i;
+        while j + 1 < offsets.len() && offsets[j + 1] == offsets[j] + 1 {
+            j += 1;
+        }
+        if !text.is_empty() {
+            text.push(',');
+        }
+        text += &span(offsets[i] as usize, offsets[j] as usize + 1);
+        i = j + 1;
+    }
+    text
+}
+
+/// Runs `program` for at most `fuel` instructions (unbounded if `None`),
+/// tracking taint. Also returns how it stopped: `Err` if it faulted.
+pub fn track(
+    program: Vec<u32>,
+    fuel: Option<u64>,
+    input: &mut dyn FnMut() -> Option<u8>,
+    output: &mut dyn FnMut(u8),
+) -> (Tracker, Result<Status, MachineError>) {
+    let mut tracker = Tracker::new();
+    let mut machine = Machine::new(program);
+    let mut run = || {
+        while fuel.is_none_or(|fuel| machine.executed() < fuel) {
+            let state = &machine.state;
+            let pc = state.program_counter;
+            let inst = *state.mapped_memory[0].get(pc).ok_or(MachineError::ProgramCounterOutOfBounds { pc })?;
+            tracker.before(pc, inst, state);
+            if machine.step(input, output)? == Status::Halted {
+                return Ok(Status::Halted);
+            }
+            tracker.after(inst, &machine.state);
+        }
+        Ok(Status::Running)
+    };
+    let result = run();
+    (tracker, result)
+}
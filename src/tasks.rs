@@ -0,0 +1,185 @@
+//! Cooperative multitasking (`rum run --tasks`): several execution
+//! contexts, each with its own registers and PC, sharing one segment
+//! space, run round-robin by the host.
+//!
+//! Like `interrupt`, it is an opt-in extension driven through a control
+//! segment, identifier `SEGMENT` (1), that the host maps before the program
+//! starts. A context yields, spawns or exits by storing into it:
+//!
+//! ```text
+//! word 0  YIELD    a Store here lets the next context run
+//! word 1  SPAWN    a Store of an address here starts a context there, with
+//!                  a copy of the spawner's registers; the spawner goes on
+//! word 2  EXIT     a Store here ends the context
+//! word 3  SELF     the running context's identifier, kept up to date
+//! word 4  SPAWNED  the identifier of the last context spawned
+//! ```
+//!
+//! Halt also ends just the context that runs it, and the last context
+//! ending either way halts the machine. Contexts switch only when one
+//! yields or ends: one that never yields keeps the machine. Segment 0 is
+//! shared too, so a Load Program that replaces it changes the code every
+//! context runs.
+
+use std::collections::VecDeque;
+
+use crate::embed::{Machine, Status, Stepper};
+use crate::error::MachineError;
+use crate::instruction::Instruction;
+
+/// The control segment's identifier.
+pub const SEGMENT: u32 = 1;
+pub const YIELD: usize = 0;
+pub const SPAWN: usize = 1;
+pub const EXIT: usize = 2;
+pub const SELF: usize = 3;
+pub const SPAWNED: usize = 4;
+/// Words in the control segment.
+pub const LEN: usize = 5;
+
+/// A context waiting for its turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Context {
+    pub id: u32,
+    pub registers: [u32; 8],
+    pub pc: usize,
+}
+
+/// An embedded machine running several contexts in turn.
+pub struct Scheduler {
+    /// Holds the running context's registers and PC.
+    pub machine: Machine,
+    current: u32,
+    waiting: VecDeque<Context>,
+    next_id: u32,
+    /// The last context exited through `EXIT`.
+    exited: bool,
+    /// Context switches so far.
+    pub switches: u64,
+}
+
+impl Scheduler {
+    /// Wraps `machine`, whose registers and PC become context 0, mapping
+    /// the control segment and reserving it, so the program cannot unmap
+    /// it. Fails when the program has mapped segments already.
+    pub fn new(mut machine: Machine) -> Result<Self, String> {
+        if machine.state.mapped_memory.len() != SEGMENT as usize {
+            return Err(format!("the control segment must be the first one mapped, but {} segments are", machine.state.mapped_memory.len()));
+        }
+        machine.state.mapped_memory.push(vec![0; LEN]);
+        machine.state.reserve_segment(SEGMENT);
+        Ok(Self { machine, current: 0, waiting: VecDeque::new(), next_id: 1, exited: false, switches: 0 })
+    }
+
+    /// Starts a context at `pc` with `registers`, after those waiting, and
+    /// returns its identifier.
+    pub fn spawn(&mut self, pc: usize, registers: [u32; 8]) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.waiting.push_back(Context { id, registers, pc });
+        if let Some(control) = self.control() {
+            control[SPAWNED] = id;
+        }
+        id
+    }
+
+    /// The running context's identifier.
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+
+    /// Contexts alive, the running one included.
+    pub fn contexts(&self) -> usize {
+        self.waiting.len() + 1
+    }
+
+    /// The control segment; reserved, it stays mapped at its full length
+    /// unless the host changes it.
+    fn control(&mut self) -> Option<&mut [u32; LEN]> {
+        self.machine.state.segment_mut(SEGMENT as usize)?.get_mut(..LEN)?.try_into().ok()
+    }
+
+    fn registers(&self) -> [u32; 8] {
+        std::array::from_fn(|r| self.machine.state.register(r as u32))
+    }
+
+    /// Switches to the next waiting context, requeueing the running one
+    /// unless it has ended.
+    fn switch(&mut self, requeue: bool) {
+        let Some(next) = self.waiting.pop_front() else {
+            return;
+        };
+        if requeue {
+            let context = Context { id: self.current, registers: self.registers(), pc: self.machine.state.program_counter };
+            self.waiting.push_back(context);
+        }
+        for (r, &value) in next.registers.iter().enumerate() {
+            self.machine.state.load_value(r as u32, value);
+        }
+        self.machine.state.program_counter = next.pc;
+        self.current = next.id;
+        if let Some(control) = self.control() {
+            control[SELF] = next.id;
+        }
+        self.switches += 1;
+    }
+
+    /// Executes one instruction of the running context like `Machine::step`,
+    /// switching contexts where it yields or ends. Halted only once the
+    /// last context halts.
+    pub fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        if self.exited || self.machine.status() == Status::Halted {
+            return Ok(Status::Halted);
+        }
+        let state = &self.machine.state;
+        let pc = state.program_counter;
+        let word = *state.mapped_memory.first().and_then(|program| program.get(pc)).ok_or(MachineError::ProgramCounterOutOfBounds { pc })?;
+        let control = match Instruction::decode(word) {
+            Instruction::Store { a, b, c } if state.register(a) == SEGMENT && (state.register(b) as usize) < SELF => Some((state.register(b) as usize, state.register(c))),
+            Instruction::Halt if !self.waiting.is_empty() => Some((EXIT, 0)),
+            _ => None,
+        };
+        let Some((word, value)) = control else {
+            return self.machine.step(input, output);
+        };
+        self.machine.retire();
+        match word {
+            YIELD => self.switch(true),
+            SPAWN => {
+                let registers = self.registers();
+                self.spawn(value as usize, registers);
+            }
+            _ if self.waiting.is_empty() => {
+                self.exited = true;
+                return Ok(Status::Halted);
+            }
+            _ => self.switch(false),
+        }
+        Ok(Status::Running)
+    }
+
+    pub fn status(&self) -> Status {
+        match self.exited {
+            true => Status::Halted,
+            false => self.machine.status(),
+        }
+    }
+}
+
+impl Stepper for Scheduler {
+    fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        Scheduler::step(self, input, output)
+    }
+
+    fn status(&self) -> Status {
+        Scheduler::status(self)
+    }
+
+    fn executed(&self) -> u64 {
+        self.machine.executed()
+    }
+
+    fn cancelled(&self) -> bool {
+        self.machine.cancelled()
+    }
+}
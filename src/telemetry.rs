@@ -0,0 +1,59 @@
+//! `tracing` instrumentation, compiled out unless the `tracing` feature is on.
+//! The macros take the same arguments as their `tracing` namesakes and log
+//! under the `rum` target.
+
+/// Instructions between two heartbeat events of a running machine.
+#[cfg(feature = "tracing")]
+pub const HEARTBEAT_EVERY: u64 = 1 << 24;
+
+/// Given the instructions executed so far and the machine, emit a heartbeat
+/// every `HEARTBEAT_EVERY` instructions.
+macro_rules! heartbeat {
+    ($count:expr, $state:expr) => {
+        #[cfg(feature = "tracing")]
+        if $count > 0 && $count % crate::telemetry::HEARTBEAT_EVERY == 0 {
+            tracing::debug!(
+                target: "rum",
+                executed = $count,
+                pc = $state.program_counter,
+                mapped_bytes = $state.mapped_bytes(),
+                "heartbeat"
+            );
+        }
+    };
+}
+
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(target: "rum", $($arg)*);
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(target: "rum", $($arg)*);
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::info!(target: "rum", $($arg)*);
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(target: "rum", $($arg)*);
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::error!(target: "rum", $($arg)*);
+    };
+}
@@ -0,0 +1,434 @@
+//! Golden-output tests for UM programs, for use from `#[test]` functions.
+//!
+//! ```no_run
+//! #[test]
+//! fn greets() {
+//!     rum::testing::run_expect(include_bytes!("hello.um"), b"", b"Hello, world.\n");
+//! }
+//!
+//! #[test]
+//! fn golden() {
+//!     // Every tests/golden/NAME.um, fed NAME.in, must print NAME.expected.
+//!     rum::testing::assert_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden"));
+//! }
+//! ```
+//!
+//! Programs run in-process on an `embed::Machine`, so they are portable and
+//! need no `rum` binary. A run that does not halt within `FUEL` instructions
+//! fails, and so does one that fails an assertion (see `assertions`), so
+//! programs can check themselves.
+//!
+//! For finer control, `TestIo` scripts a run's input, including end of
+//! input or read errors partway through, and checks what it wrote:
+//!
+//! ```no_run
+//! use rum::testing::{run_io, End, TestIo};
+//!
+//! let program = std::fs::read("cat.um").unwrap();
+//! let mut io = TestIo::new("hello\n").eof_at(3);
+//! let (end, _) = run_io(&program, &mut io, rum::testing::FUEL);
+//! assert_eq!(end, End::Halted);
+//! io.assert_output("hel");
+//! ```
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::batch::{table, Outcome, Status};
+use crate::cache::ProgramCache;
+use crate::check::{diff, sha256_hex};
+use crate::assertions::Assertions;
+use crate::embed::{Io, Machine, Status as Running, Stepper};
+use crate::instruction::Instruction;
+use crate::plugin::Host;
+use crate::state::UniversalMachine;
+
+/// Instructions a program may execute before the test gives up on it.
+pub const FUEL: u64 = 1_000_000_000;
+
+/// The words of an assembled program, ready for `Machine::new`.
+pub fn words(program: &[Instruction]) -> Vec<u32> {
+    program.iter().map(|&inst| u32::from(inst)).collect()
+}
+
+/// An assembled program as the bytes of a `.um` file.
+pub fn bytes(program: &[Instruction]) -> Vec<u8> {
+    words(program).iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+/// How a test run ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum End {
+    Halted,
+    OutOfFuel,
+    /// The program faulted; the `MachineError` message.
+    Faulted(String),
+}
+
+/// A finished test run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    pub end: End,
+    pub output: Vec<u8>,
+    pub executed: u64,
+}
+
+/// Runs `program` (the contents of a `.um` file) with `input` as its whole
+/// input, for at most `fuel` instructions.
+pub fn run(program: &[u8], input: &[u8], fuel: u64) -> Run {
+    let mut io = TestIo::new(input);
+    let (end, executed) = run_io(program, &mut io, fuel);
+    Run { end, output: io.output, executed }
+}
+
+/// Runs `program` with its input and output through `io` for at most
+/// `fuel` instructions, returning how it ended and the instructions it executed.
+pub fn run_io(program: &[u8], io: &mut dyn Io, fuel: u64) -> (End, u64) {
+    let words = ProgramCache::global().words(program).to_vec();
+    let (end, machine) = execute(words, io, fuel);
+    (end, machine.executed())
+}
+
+fn execute(words: Vec<u32>, io: &mut dyn Io, fuel: u64) -> (End, Machine) {
+    let mut host = Host::new(Machine::new(words));
+    host.add(Box::new(Assertions::default()));
+    let io = RefCell::new(io);
+    let result = host.run(Some(fuel), &mut || io.borrow_mut().read().ok().flatten(), &mut |byte| io.borrow_mut().write(byte));
+    let end = match result {
+        Ok(Running::Halted) => End::Halted,
+        Ok(Running::Running) => End::OutOfFuel,
+        Err(e) => End::Faulted(e.to_string()),
+    };
+    (end, host.machine)
+}
+
+/// Fuel for the small programs of `um_test!`.
+pub const EXAMPLE_FUEL: u64 = 10_000;
+
+/// The final state a program must reach (see `check_state`). What is left
+/// out is not checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Expected {
+    /// Registers and the values they must hold.
+    pub registers: Vec<(u32, u32)>,
+    pub output: Option<Vec<u8>>,
+    /// Mapped segments at Halt, segment 0 included.
+    pub segments: Option<usize>,
+}
+
+/// Runs `program` on `input` and explains how it failed to halt within
+/// `fuel` instructions in the `expected` state, if it did.
+pub fn check_state(program: Vec<u32>, input: &[u8], fuel: u64, expected: &Expected) -> Result<(), String> {
+    let mut io = TestIo::new(input);
+    let (end, machine) = execute(program, &mut io, fuel);
+    match end {
+        End::Halted => {}
+        End::OutOfFuel => return Err(format!("did not halt within {fuel} instructions")),
+        End::Faulted(message) => return Err(format!("faulted after {} instructions: {message}", machine.executed())),
+    }
+    let state = &machine.state;
+    let mut problems = Vec::new();
+    for &(r, value) in &expected.registers {
+        if state.register(r) != value {
+            problems.push(format!("r{r} is {:#x}, expected {value:#x}", state.register(r)));
+        }
+    }
+    if let Some(output) = expected.output.as_deref().filter(|&output| output != io.output()) {
+        problems.push(diff(output, io.output()));
+    }
+    let segments = state.mapped_memory.len() - state.free_segments().len();
+    if let Some(want) = expected.segments.filter(|&want| want != segments) {
+        problems.push(format!("{segments} segments are mapped, expected {want}"));
+    }
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(problems.join("\n")),
+    }
+}
+
+/// Declares a `#[test]` that runs a small program and checks its final state
+/// (see `check_state`):
+///
+/// ```no_run
+/// use rum::instruction::Instruction::*;
+///
+/// rum::um_test!(nand_of_zero_is_all_ones,
+///     program: [Nand { a: 1, b: 0, c: 0 }, Add { a: 2, b: 1, c: 1 }, Halt],
+///     registers: { 1 => u32::MAX, 2 => u32::MAX - 1 },
+///     segments: 1,
+/// );
+/// ```
+///
+/// The program is a list of instructions or words. After it come, all
+/// optional but in this order, `input: b"..."`, `fuel: N` (default
+/// `EXAMPLE_FUEL`), `registers: { r => value, ... }`, `output: b"..."` and
+/// `segments: N`.
+#[macro_export]
+macro_rules! um_test {
+    (
+        $name:ident,
+        program: [$($word:expr),* $(,)?]
+        $(, input: $input:expr)?
+        $(, fuel: $fuel:expr)?
+        $(, registers: { $($r:literal => $value:expr),* $(,)? })?
+        $(, output: $output:expr)?
+        $(, segments: $segments:expr)?
+        $(,)?
+    ) => {
+        #[test]
+        fn $name() {
+            let program = vec![$(u32::from($word)),*];
+            let input: &[u8] = &[];
+            $(let input: &[u8] = $input;)?
+            let fuel = $crate::testing::EXAMPLE_FUEL;
+            $(let fuel: u64 = $fuel;)?
+            let expected = $crate::testing::Expected {
+                registers: vec![$($(($r, $value)),*)?],
+                output: None $(.or(Some($output.to_vec())))?,
+                segments: None $(.or(Some($segments)))?,
+            };
+            if let Err(e) = $crate::testing::check_state(program, input, fuel, &expected) {
+                panic!("{e}");
+            }
+        }
+    };
+}
+
+/// Set to re-record the snapshots `assert_snapshot` compares against.
+pub const UPDATE_SNAPSHOTS: &str = "RUM_UPDATE_SNAPSHOTS";
+
+/// `state` as stable text for snapshot tests: the PC, the registers, the
+/// free segment identifiers in reuse order, and each mapped segment's
+/// length and digest (the first 16 hex digits of the SHA-256 of its words,
+/// big-endian). Two states render alike exactly when a program could not
+/// tell them apart, however memory is laid out underneath.
+pub fn render_state(state: &UniversalMachine) -> String {
+    let mut text = format!("pc {}\n", state.program_counter);
+    let registers: Vec<String> = (0..8).map(|r| format!("{:08x}", state.register(r))).collect();
+    text += &format!("registers {}\n", registers.join(" "));
+    let free = state.free_segments();
+    let ids: Vec<String> = free.iter().rev().map(|id| id.to_string()).collect();
+    text += &format!("free [{}]\n", ids.join(", "));
+    let free: BTreeSet<u32> = free.iter().copied().collect();
+    for (id, segment) in state.mapped_memory.iter().enumerate().filter(|(id, _)| !free.contains(&(*id as u32))) {
+        let bytes: Vec<u8> = segment.iter().flat_map(|word| word.to_be_bytes()).collect();
+        text += &format!("segment {id}: {} words, sha256 {}\n", segment.len(), &sha256_hex(&bytes)[..16]);
+    }
+    text
+}
+
+/// Runs `program` (the contents of a `.um` file) on `input` for
+/// `instructions` instructions, or until it halts, and renders its state
+/// then (see `render_state`), after a line saying how it stopped.
+pub fn state_at(program: &[u8], input: &[u8], instructions: u64) -> String {
+    let words = ProgramCache::global().words(program).to_vec();
+    let (end, machine) = execute(words, &mut TestIo::new(input), instructions);
+    let stopped = match end {
+        End::Halted => "halted",
+        End::OutOfFuel => "running",
+        End::Faulted(message) => panic!("faulted after {} instructions: {message}", machine.executed()),
+    };
+    format!("{stopped} after {} instructions\n{}", machine.executed(), render_state(&machine.state))
+}
+
+/// Asserts that `actual` matches the snapshot recorded at `path`, the way
+/// `insta` does for Rust values:
+///
+/// ```no_run
+/// let program = std::fs::read("midmark.um").unwrap();
+/// let state = rum::testing::state_at(&program, b"", 100_000);
+/// rum::testing::assert_snapshot("tests/snapshots/midmark-100000.state", &state);
+/// ```
+///
+/// A missing snapshot is recorded and the assertion fails, so new
+/// snapshots are looked at before they are trusted. With
+/// `RUM_UPDATE_SNAPSHOTS=1` in the environment every snapshot is recorded
+/// afresh instead of compared.
+///
+/// # Panics
+///
+/// Panics with the difference (see `check::diff`) when they differ.
+#[track_caller]
+pub fn assert_snapshot(path: impl AsRef<Path>, actual: &str) {
+    let path = path.as_ref();
+    let update = std::env::var_os(UPDATE_SNAPSHOTS).is_some_and(|v| v != "0" && !v.is_empty());
+    let recorded = optional(path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+    match recorded {
+        Some(expected) if !update => {
+            if expected != actual.as_bytes() {
+                panic!("{} does not match (rerun with {UPDATE_SNAPSHOTS}=1 to accept):\n{}", path.display(), diff(&expected, actual.as_bytes()));
+            }
+        }
+        recorded => {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).unwrap_or_else(|e| panic!("{}: {e}", dir.display()));
+            }
+            std::fs::write(path, actual).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+            if recorded.is_none() && !update {
+                panic!("{} was missing and has been recorded; check it and rerun", path.display());
+            }
+        }
+    }
+}
+
+/// Scripted input and captured output for one run.
+///
+/// Input is read from the script in order. `eof_at(n)` makes the read
+/// after the first `n` bytes see end of input once; `error_at(n)` makes it
+/// fail once instead (which the program also sees as end of input). Later
+/// reads carry on with the script.
+#[derive(Debug, Clone, Default)]
+pub struct TestIo {
+    input: Vec<u8>,
+    consumed: usize,
+    eofs: BTreeSet<usize>,
+    errors: BTreeSet<usize>,
+    failed_reads: usize,
+    output: Vec<u8>,
+}
+
+impl TestIo {
+    pub fn new(input: impl AsRef<[u8]>) -> Self {
+        Self { input: input.as_ref().to_vec(), ..Self::default() }
+    }
+
+    /// Injects an end of input after `consumed` bytes.
+    pub fn eof_at(mut self, consumed: usize) -> Self {
+        self.eofs.insert(consumed);
+        self
+    }
+
+    /// Injects a read error after `consumed` bytes.
+    pub fn error_at(mut self, consumed: usize) -> Self {
+        self.errors.insert(consumed);
+        self
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Input bytes the program has read.
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Reads that ended in an injected error.
+    pub fn failed_reads(&self) -> usize {
+        self.failed_reads
+    }
+
+    /// Panics with the difference (see `check::diff`) unless the output is `expected`.
+    #[track_caller]
+    pub fn assert_output(&self, expected: impl AsRef<[u8]>) {
+        let expected = expected.as_ref();
+        if self.output != expected {
+            panic!("{}", diff(expected, &self.output));
+        }
+    }
+
+    /// Panics unless the program read all of its input.
+    #[track_caller]
+    pub fn assert_consumed(&self) {
+        assert_eq!(self.consumed, self.input.len(), "the program read {} of {} input bytes", self.consumed, self.input.len());
+    }
+}
+
+impl Io for TestIo {
+    fn read(&mut self) -> std::io::Result<Option<u8>> {
+        if self.errors.remove(&self.consumed) {
+            self.failed_reads += 1;
+            return Err(std::io::Error::other(format!("injected read error after {} bytes", self.consumed)));
+        }
+        if self.eofs.remove(&self.consumed) {
+            return Ok(None);
+        }
+        let byte = self.input.get(self.consumed).copied();
+        self.consumed += byte.is_some() as usize;
+        Ok(byte)
+    }
+
+    fn write(&mut self, byte: u8) {
+        self.output.push(byte);
+    }
+}
+
+/// Runs `program` on `input` and explains how it failed to halt with
+/// exactly `expected` as output, if it did.
+pub fn check(program: &[u8], input: &[u8], expected: &[u8]) -> Result<(), String> {
+    if !program.len().is_multiple_of(4) {
+        return Err(format!("the program is {} bytes, not a whole number of words", program.len()));
+    }
+    match verdict(&run(program, input, FUEL), Some(expected)) {
+        (Status::Pass, _) => Ok(()),
+        (_, detail) => Err(detail),
+    }
+}
+
+fn verdict(run: &Run, expected: Option<&[u8]>) -> (Status, String) {
+    match (&run.end, expected) {
+        (End::Halted, Some(expected)) if run.output != expected => (Status::Fail, diff(expected, &run.output)),
+        (End::Halted, _) => (Status::Pass, String::new()),
+        (End::OutOfFuel, _) => (Status::Limit, format!("did not halt within {FUEL} instructions")),
+        (End::Faulted(message), _) => (Status::Error, format!("faulted after {} instructions: {message}", run.executed)),
+    }
+}
+
+/// Asserts that `program` halts with `expected` as output given `input`.
+///
+/// # Panics
+///
+/// Panics with the difference (see `check::diff`) when it doesn't.
+#[track_caller]
+pub fn run_expect(program: &[u8], input: &[u8], expected: &[u8]) {
+    if let Err(e) = check(program, input, expected) {
+        panic!("{e}");
+    }
+}
+
+/// Runs every `NAME.um` in `dir` with `NAME.in` as input (none if missing)
+/// and compares its output with `NAME.expected`. A program without an
+/// expected file passes by halting. Outcomes are in name order.
+pub fn run_dir(dir: impl AsRef<Path>) -> std::io::Result<Vec<Outcome>> {
+    let mut programs: Vec<_> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .filter(|path| path.as_ref().map_or(true, |path| path.extension().is_some_and(|ext| ext == "um")))
+        .collect::<Result<_, _>>()?;
+    programs.sort();
+    programs.iter().map(|path| run_case(path)).collect()
+}
+
+fn run_case(path: &Path) -> std::io::Result<Outcome> {
+    let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let program = std::fs::read(path)?;
+    let input = optional(&path.with_extension("in"))?.unwrap_or_default();
+    let expected = optional(&path.with_extension("expected"))?;
+    let start = Instant::now();
+    let run = run(&program, &input, FUEL);
+    let (status, detail) = verdict(&run, expected.as_deref());
+    Ok(Outcome { name, status, elapsed: start.elapsed(), output_bytes: run.output.len(), detail })
+}
+
+fn optional(path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Runs `run_dir` and panics with the results table and every failure's
+/// details unless all programs pass.
+#[track_caller]
+pub fn assert_dir(dir: impl AsRef<Path>) {
+    let dir = dir.as_ref();
+    let outcomes = run_dir(dir).unwrap_or_else(|e| panic!("{}: {e}", dir.display()));
+    assert!(!outcomes.is_empty(), "no .um programs in {}", dir.display());
+    let failed: Vec<_> = outcomes.iter().filter(|o| o.status != Status::Pass).collect();
+    if !failed.is_empty() {
+        let details: String = failed.iter().map(|o| format!("\n{}: {}\n", o.name, o.detail)).collect();
+        panic!("{} of {} programs failed\n{}{details}", failed.len(), outcomes.len(), table(&outcomes));
+    }
+}
@@ -0,0 +1,48 @@
+//! Deliberately slow execution (`rum run --max-mips N`), so interactive
+//! demos and terminal-art programs render at a watchable speed.
+//!
+//! The run checks in every `every()` instructions, about a hundredth of a
+//! second's worth, and `pace` sleeps until the wall clock catches up with
+//! the instruction count. Time lost waiting for input is forgiven rather
+//! than made up in a burst.
+
+use std::time::{Duration, Instant};
+
+/// How often the run checks in, in seconds of execution at the target rate.
+const BATCH_SECONDS: f64 = 0.01;
+/// Falling further behind than this resets the schedule.
+const SLACK: Duration = Duration::from_millis(100);
+
+pub struct Throttle {
+    /// Instructions per second.
+    rate: f64,
+    every: u64,
+    start: Instant,
+    /// Instructions executed when `start` was taken.
+    base: u64,
+}
+
+impl Throttle {
+    /// Paces a run at `mips` million instructions per second at most.
+    pub fn new(mips: f64) -> Self {
+        let rate = mips * 1e6;
+        Self { rate, every: ((rate * BATCH_SECONDS) as u64).max(1), start: Instant::now(), base: 0 }
+    }
+
+    /// Instructions between calls to `pace`.
+    pub fn every(&self) -> u64 {
+        self.every
+    }
+
+    /// Given the number of instructions executed so far, sleeps until the
+    /// run is no faster than the rate.
+    pub fn pace(&mut self, count: u64) {
+        let due = self.start + Duration::from_secs_f64((count - self.base) as f64 / self.rate);
+        let now = Instant::now();
+        match due.checked_duration_since(now) {
+            Some(ahead) => std::thread::sleep(ahead),
+            None if now - due > SLACK => (self.start, self.base) = (now, count),
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! `rum run --io`: where the program's input and output go.
+//!
+//! `stdio` (the default) leaves them on the terminal. `unix:PATH` listens on
+//! a Unix domain socket at PATH, waits for one client and makes its
+//! connection the program's stdin and stdout, so a supervisor or GUI gets a
+//! plain bidirectional byte stream. rum's own messages stay on stderr.
+//! `nats://HOST:PORT/INPUT/OUTPUT` puts the program on a message bus; the
+//! `bus` bridge runs it rather than redirecting stdio.
+
+use std::path::PathBuf;
+
+use crate::bus::Endpoint;
+
+/// A destination for the program's I/O.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Io {
+    Stdio,
+    Unix(PathBuf),
+    Nats(Endpoint),
+}
+
+impl Io {
+    /// Given `stdio`, `unix:PATH` or `nats://HOST:PORT/INPUT/OUTPUT`, the transport it names.
+    pub fn parse(spec: &str) -> Result<Io, String> {
+        match spec.split_once(':') {
+            _ if spec == "stdio" => Ok(Io::Stdio),
+            Some(("unix", path)) if !path.is_empty() => Ok(Io::Unix(path.into())),
+            Some(("nats", _)) => Endpoint::parse(spec).map(Io::Nats),
+            _ => Err(format!("unknown I/O transport `{spec}` (expected stdio, unix:PATH or nats://HOST:PORT/INPUT/OUTPUT)")),
+        }
+    }
+
+    /// Connects the process's stdin and stdout to this transport. For a
+    /// socket this blocks until a client connects.
+    pub fn attach(&self) -> std::io::Result<()> {
+        match self {
+            // The bus bridge does the program's I/O itself.
+            Io::Stdio | Io::Nats(_) => Ok(()),
+            Io::Unix(path) => attach_unix(path),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn attach_unix(path: &std::path::Path) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+
+    // A socket left behind by an earlier run would make bind fail.
+    if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    eprintln!("rum: waiting for a connection on {}", path.display());
+    let accepted = listener.accept();
+    // Nobody else can connect once the path is gone; the connection stays up.
+    let _ = std::fs::remove_file(path);
+    let (stream, _) = accepted?;
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO] {
+        // SAFETY: both descriptors are open; dup2 only replaces `fd`.
+        if unsafe { libc::dup2(stream.as_raw_fd(), fd) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn attach_unix(_: &std::path::Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Unix domain sockets need a Unix system"))
+}
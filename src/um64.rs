@@ -0,0 +1,149 @@
+//! The 64-bit UM variant (`rum run --arch um64`): 64-bit registers,
+//! segment words and identifiers, for programs that need 64-bit values or
+//! arithmetic on addresses past 4 GiB.
+//!
+//! An instruction is a 64-bit word laid out like a UM one moved to the top
+//! and bottom of the wider word: the opcode in bits 60 to 63 and registers
+//! A, B and C in bits 6-8, 3-5 and 0-2, so `decode` reuses the UM decoder.
+//! Load Value has A in bits 57 to 59 and a 57-bit immediate below it. The
+//! operations are the UM's, on 64-bit values; Input at end of input sets
+//! all 64 bits. A `.um64` file is the program's words, 8 bytes each, big
+//! endian.
+
+use std::convert::TryInto;
+
+use crate::embed::{Status, Stepper};
+use crate::error::MachineError;
+use crate::instruction::Instruction;
+use crate::state::UniversalMachine;
+
+/// The Load Value immediate's mask.
+pub const IMMEDIATE: u64 = (1 << 57) - 1;
+
+/// The instruction `word` holds. For Load Value the immediate is
+/// `word & IMMEDIATE`, and the `value` field is zero.
+pub fn decode(word: u64) -> Instruction {
+    match word >> 60 {
+        13 => Instruction::Orthography { a: (word >> 57 & 7) as u32, value: 0 },
+        _ => Instruction::decode((word >> 32) as u32 & 0xf000_0000 | word as u32 & 0x1ff),
+    }
+}
+
+/// The word for `instruction`; Load Value takes its immediate from `value`.
+pub fn encode(instruction: Instruction) -> u64 {
+    match instruction {
+        Instruction::Orthography { a, value } => load_value(a, value as u64),
+        _ => {
+            let word = instruction.encode();
+            (word as u64 & 0xf000_0000) << 32 | (word & 0x1ff) as u64
+        }
+    }
+}
+
+/// The word loading `value`, modulo 2^57, into register `a`.
+pub fn load_value(a: u32, value: u64) -> u64 {
+    13 << 60 | ((a & 7) as u64) << 57 | (value & IMMEDIATE)
+}
+
+/// Parses a `.um64` file's bytes.
+pub fn parse(bytes: &[u8]) -> Result<Vec<u64>, String> {
+    if !bytes.len().is_multiple_of(8) {
+        return Err(format!("{} bytes is not a whole number of 64-bit words", bytes.len()));
+    }
+    Ok(bytes.chunks_exact(8).map(|word| u64::from_be_bytes(word.try_into().unwrap())).collect())
+}
+
+/// Reads the `.um64` program at `path`.
+pub fn load(path: &str) -> Result<Vec<u64>, String> {
+    parse(&std::fs::read(path).map_err(|e| format!("{path}: {e}"))?).map_err(|e| format!("{path}: {e}"))
+}
+
+/// Writes `program` to `path` in the `.um64` format.
+pub fn store(path: &str, program: &[u64]) -> std::io::Result<()> {
+    std::fs::write(path, program.iter().flat_map(|word| word.to_be_bytes()).collect::<Vec<u8>>())
+}
+
+/// A 64-bit machine, driven like `embed::Machine`: the UM's
+/// `UniversalMachine` core over 64-bit words.
+#[derive(Debug, Clone)]
+pub struct Machine64 {
+    pub state: UniversalMachine<u64>,
+    halted: bool,
+    executed: u64,
+}
+
+impl Machine64 {
+    /// Given program words, create a machine ready to execute them from address 0.
+    pub fn new(program: Vec<u64>) -> Self {
+        Self { state: UniversalMachine::with_program(program), halted: false, executed: 0 }
+    }
+
+    /// Instructions executed so far (Halt not included).
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    pub fn status(&self) -> Status {
+        match self.halted {
+            true => Status::Halted,
+            false => Status::Running,
+        }
+    }
+
+    /// Executes one instruction, with Input and Output as in
+    /// `embed::Machine::step`. A program that faults returns the `MachineError`.
+    pub fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        if self.halted {
+            return Ok(Status::Halted);
+        }
+        let state = &mut self.state;
+        let pc = state.program_counter;
+        let word = *state.mapped_memory.first().and_then(|program| program.get(pc)).ok_or(MachineError::ProgramCounterOutOfBounds { pc })?;
+        state.program_counter += 1;
+        match decode(word) {
+            Instruction::CMov { a, b, c } => state.cmov(a, b, c),
+            Instruction::Load { a, b, c } => state.load(a, b, c)?,
+            Instruction::Store { a, b, c } => state.store(a, b, c)?,
+            Instruction::Add { a, b, c } => state.add(a, b, c),
+            Instruction::Mul { a, b, c } => state.multiply(a, b, c),
+            Instruction::Div { a, b, c } => state.division(a, b, c)?,
+            Instruction::Nand { a, b, c } => state.nand(a, b, c),
+            Instruction::Halt => {
+                state.program_counter = pc;
+                self.halted = true;
+                return Ok(Status::Halted);
+            }
+            Instruction::Map { b, c } => state.map_seg(b, c),
+            Instruction::Unmap { c } => state.unmap_seg(c)?,
+            Instruction::Output { c } => {
+                let value = state.register(c);
+                output(u8::try_from(value).map_err(|_| MachineError::OutputOutOfRange { pc, value })?);
+            }
+            Instruction::Input { c } => state.load_value(c, input().map_or(!0, u64::from)),
+            Instruction::LoadProgram { b, c } => state.load_prog(b, c)?,
+            Instruction::Orthography { a, .. } => state.load_value(a, word & IMMEDIATE),
+            Instruction::Invalid(instruction) => return Err(MachineError::InvalidOpcode { pc, instruction }),
+        }
+        self.executed += 1;
+        Ok(Status::Running)
+    }
+}
+
+impl Stepper for Machine64 {
+    fn step(&mut self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8)) -> Result<Status, MachineError> {
+        Machine64::step(self, input, output)
+    }
+
+    fn status(&self) -> Status {
+        Machine64::status(self)
+    }
+
+    fn executed(&self) -> u64 {
+        Machine64::executed(self)
+    }
+
+    /// A `Machine64` takes no cancellation token.
+    fn cancelled(&self) -> bool {
+        false
+    }
+}
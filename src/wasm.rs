@@ -0,0 +1,147 @@
+//! JavaScript API for running the interpreter in a browser, built with the
+//! `wasm` feature for `wasm32-unknown-unknown`.
+//!
+//! ```js
+//! const m = new Machine(new Uint8Array(await (await fetch("hello.um")).arrayBuffer()));
+//! m.onOutput(bytes => term.write(bytes));
+//! while (m.run(100000) === "running") await nextFrame();
+//! ```
+//!
+//! Input is queued with `feed`. When the program wants input and the queue
+//! is empty, `run` returns `"input"` so the page can wait for the user;
+//! after `closeInput` the program sees end of input instead. A program
+//! fault is thrown as an `Error` giving the fault and its PC, after the
+//! output produced before it; the machine then refuses to run again.
+
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+
+use crate::embed::{self, Status};
+
+#[wasm_bindgen]
+pub struct Machine {
+    machine: embed::Machine,
+    input: VecDeque<u8>,
+    input_closed: bool,
+    on_output: Option<js_sys::Function>,
+    faulted: bool,
+}
+
+#[wasm_bindgen]
+impl Machine {
+    /// Given the bytes of a `.um` file, create a machine ready to run it.
+    #[wasm_bindgen(constructor)]
+    pub fn new(program: &[u8]) -> Result<Machine, JsError> {
+        if !program.len().is_multiple_of(4) {
+            return Err(JsError::new("program length is not a whole number of words"));
+        }
+        let words = crate::load::words(program);
+        Ok(Machine { machine: embed::Machine::new(words), input: VecDeque::new(), input_closed: false, on_output: None, faulted: false })
+    }
+
+    /// Calls `callback` with a `Uint8Array` of the output produced by each `run`/`step`.
+    #[wasm_bindgen(js_name = onOutput)]
+    pub fn on_output(&mut self, callback: js_sys::Function) {
+        self.on_output = Some(callback);
+    }
+
+    /// Queues bytes for the program's Input instruction.
+    pub fn feed(&mut self, input: &[u8]) {
+        self.input.extend(input);
+    }
+
+    /// Once the queue is drained, the program reads end of input instead of waiting.
+    #[wasm_bindgen(js_name = closeInput)]
+    pub fn close_input(&mut self) {
+        self.input_closed = true;
+    }
+
+    /// Executes up to `fuel` instructions and returns `"running"` (fuel ran
+    /// out), `"input"` (waiting for `feed`) or `"halted"`.
+    pub fn run(&mut self, fuel: u32) -> Result<String, JsError> {
+        self.drive(fuel)
+    }
+
+    /// Executes a single instruction; returns like `run`.
+    pub fn step(&mut self) -> Result<String, JsError> {
+        self.drive(1)
+    }
+
+    pub fn halted(&self) -> bool {
+        self.machine.status() == Status::Halted
+    }
+
+    /// Instructions executed so far, as a float since JS numbers are.
+    pub fn executed(&self) -> f64 {
+        self.machine.executed() as f64
+    }
+
+    pub fn pc(&self) -> usize {
+        self.machine.state.program_counter
+    }
+
+    pub fn registers(&self) -> Vec<u32> {
+        (0..8).map(|r| self.machine.state.register(r)).collect()
+    }
+
+    /// Words of segment `index` (empty if unmapped or unknown).
+    pub fn segment(&self, index: usize) -> Vec<u32> {
+        self.machine.state.mapped_memory.get(index).cloned().unwrap_or_default()
+    }
+
+    #[wasm_bindgen(js_name = segmentCount)]
+    pub fn segment_count(&self) -> usize {
+        self.machine.state.mapped_memory.len()
+    }
+}
+
+impl Machine {
+    /// True when the next instruction is Input and there is nothing to give it yet.
+    fn starved(&self) -> bool {
+        self.input.is_empty() && !self.input_closed && self.machine.wants_input()
+    }
+
+    fn drive(&mut self, fuel: u32) -> Result<String, JsError> {
+        if self.faulted {
+            return Err(JsError::new("the machine faulted earlier"));
+        }
+        let mut output = Vec::new();
+        let mut status = self.machine.status();
+        let mut waiting = false;
+        let mut fault = None;
+        for _ in 0..fuel {
+            if self.starved() {
+                waiting = true;
+                break;
+            }
+            let queue = &mut self.input;
+            match self.machine.step(&mut || queue.pop_front(), &mut |byte| output.push(byte)) {
+                Ok(Status::Halted) => {
+                    status = Status::Halted;
+                    break;
+                }
+                Ok(Status::Running) => {}
+                Err(e) => {
+                    fault = Some(e);
+                    break;
+                }
+            }
+        }
+        if !output.is_empty() {
+            if let Some(callback) = &self.on_output {
+                callback.call1(&JsValue::NULL, &js_sys::Uint8Array::from(&output[..])).map_err(|_| JsError::new("output callback threw"))?;
+            }
+        }
+        if let Some(e) = fault {
+            self.faulted = true;
+            return Err(JsError::new(&format!("program faulted after {} instructions: {e}", self.machine.executed())));
+        }
+        Ok(match (status, waiting) {
+            (Status::Halted, _) => "halted",
+            (_, true) => "input",
+            _ => "running",
+        }
+        .to_string())
+    }
+}
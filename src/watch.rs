@@ -0,0 +1,71 @@
+use std::convert::Infallible;
+use std::path::Path;
+use std::process::{Child, Command};
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+/// How often the program file is polled for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Given a `path` to a program, return its last modification time, if the
+/// file currently exists (assemblers often truncate then rewrite their output).
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// The `rum run` command for one run of `program`, passing it `options`
+/// (the `rum run` options given with `--watch`). The program goes before
+/// a `--` and the program arguments after it.
+pub fn command(exe: &Path, program: &Path, options: &[String]) -> Command {
+    let split = options.iter().position(|option| option == "--").unwrap_or(options.len());
+    let mut command = Command::new(exe);
+    command.arg("run").args(&options[..split]).arg(program).args(&options[split..]);
+    command
+}
+
+/// Starts a fresh machine on `program` as a child `rum run` process.
+///
+/// The child inherits our stdin/stdout/stderr, so output streams straight
+/// to the terminal and a restart is simply killing the old process.
+fn spawn(program: &Path, options: &[String]) -> Result<Child, String> {
+    let exe = std::env::current_exe().map_err(|e| format!("cannot locate the rum executable: {e}"))?;
+    command(&exe, program, options).spawn().map_err(|e| format!("cannot start {}: {e}", exe.display()))
+}
+
+/// Runs `program` with `options` and restarts the machine every time the
+/// file changes.
+///
+/// Returns only when a run cannot be started; stop it with Ctrl-C like
+/// any other foreground command.
+pub fn watch(program: &str, options: &[String]) -> Result<Infallible, String> {
+    let path = Path::new(program);
+    let mut last = modified(path);
+    eprintln!("[rum] watching {}", path.display());
+    let mut child = last.map(|_| spawn(path, options)).transpose()?;
+
+    loop {
+        sleep(POLL_INTERVAL);
+
+        // Report a finished run once, then keep waiting for the next edit.
+        if let Some(status) = child.as_mut().and_then(|c| c.try_wait().ok().flatten()) {
+            eprintln!("[rum] machine exited ({status}), waiting for changes");
+            child = None;
+        }
+
+        let current = modified(path);
+        if current.is_none() || current == last {
+            continue;
+        }
+
+        // Give the writer a moment to finish before reading the new program.
+        sleep(POLL_INTERVAL);
+        last = modified(path);
+
+        if let Some(mut old) = child.take() {
+            let _ = old.kill();
+            let _ = old.wait();
+        }
+        eprintln!("[rum] {} changed, restarting", path.display());
+        child = Some(spawn(path, options)?);
+    }
+}
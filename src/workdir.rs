@@ -0,0 +1,107 @@
+//! Run directories (`rum run --workdir DIR`): everything about one run
+//! collected in a fresh directory under `DIR`, named after the time it
+//! started, so reporting a run is a matter of zipping a folder.
+//!
+//! ```text
+//! DIR/run-20261016T093012Z/
+//!   command             the rum command line
+//!   input.rumreplay     replay log: the starting state and every byte read
+//!   output              everything the program wrote
+//!   stats.json          the `--summary json` object
+//!   checkpoint.*        `--checkpoint-every` checkpoints
+//!   core.rumstate       snapshot of the machine, if it faulted
+//! ```
+//!
+//! `rum replay DIR/run-.../input.rumreplay` re-executes the run, and
+//! `rum debug --resume core.rumstate` looks at the state it failed in.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::json::Value;
+use crate::seal::Key;
+use crate::snapshot::save_snapshot;
+use crate::state::UniversalMachine;
+
+/// The directory of one run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workdir {
+    path: PathBuf,
+}
+
+impl Workdir {
+    /// Creates the directory for a run starting now under `base`, recording
+    /// `command` (rum's arguments) in it. Runs started in the same second get
+    /// `-2`, `-3`, ... appended.
+    pub fn create(base: impl AsRef<Path>, command: &[String]) -> std::io::Result<Self> {
+        let base = base.as_ref();
+        std::fs::create_dir_all(base)?;
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+        let name = format!("run-{}", timestamp(seconds));
+        let mut path = base.join(&name);
+        let mut attempt = 1;
+        loop {
+            match std::fs::create_dir(&path) {
+                Ok(()) => break,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    attempt += 1;
+                    path = base.join(format!("{name}-{attempt}"));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        std::fs::write(path.join("command"), format!("rum {}\n", command.join(" ")))?;
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where the replay log goes.
+    pub fn record(&self) -> PathBuf {
+        self.path.join("input.rumreplay")
+    }
+
+    /// Base path of the checkpoints.
+    pub fn checkpoints(&self) -> PathBuf {
+        self.path.join("checkpoint")
+    }
+
+    /// Opens the output transcript.
+    pub fn transcript(&self) -> std::io::Result<BufWriter<File>> {
+        Ok(BufWriter::new(File::create(self.path.join("output"))?))
+    }
+
+    /// Completes the directory when the run ends: writes `stats`, flushes
+    /// `transcript`, and if the machine faulted, dumps its state `core`,
+    /// sealed with `key` if one is given.
+    pub fn finish(&self, stats: &Value, transcript: Option<&mut BufWriter<File>>, core: Option<&UniversalMachine>, key: Option<&Key>) -> std::io::Result<()> {
+        std::fs::write(self.path.join("stats.json"), format!("{stats}\n"))?;
+        if let Some(transcript) = transcript {
+            transcript.flush()?;
+        }
+        if let Some(state) = core {
+            save_snapshot(state, self.path.join("core.rumstate"), key).map_err(|e| std::io::Error::other(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// `seconds` since the Unix epoch as a compact UTC timestamp, `20261016T093012Z`.
+fn timestamp(seconds: u64) -> String {
+    let (days, rest) = (seconds / 86_400, seconds % 86_400);
+    // Days to a civil date, after Howard Hinnant's `civil_from_days`.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z", rest / 3600, rest / 60 % 60, rest % 60)
+}
@@ -0,0 +1,77 @@
+//! Run with `cargo test --features async`.
+#![cfg(feature = "async")]
+
+use rum::aio::{run_async, AsyncIo};
+use rum::cancel::CancellationToken;
+use rum::embed::{Machine, Status};
+use rum::error::MachineError;
+use rum::instruction::Instruction::*;
+
+/// Echoes input until end of input, then halts.
+fn echo() -> Vec<u32> {
+    [
+        Orthography { a: 5, value: 1 },
+        Orthography { a: 3, value: 7 },
+        Input { c: 0 },
+        // r2 is 0 at end of input: jump to the Halt, otherwise to the Output.
+        Add { a: 2, b: 0, c: 5 },
+        Orthography { a: 4, value: 10 },
+        CMov { a: 4, b: 3, c: 2 },
+        LoadProgram { b: 7, c: 4 },
+        Output { c: 0 },
+        Orthography { a: 4, value: 2 },
+        LoadProgram { b: 7, c: 4 },
+        Halt,
+    ]
+    .into_iter()
+    .map(u32::from)
+    .collect()
+}
+
+#[tokio::test]
+async fn runs_with_async_io_in_small_batches() {
+    let (mut client, server) = tokio::io::duplex(64);
+    let (reader, writer) = tokio::io::split(server);
+    let mut machine = Machine::new(echo());
+    let task = tokio::spawn(async move {
+        let mut io = AsyncIo::new(reader, writer).yield_every(3);
+        let status = run_async(&mut machine, None, &mut io).await.unwrap().unwrap();
+        (status, machine.executed())
+    });
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    client.write_all(b"hi").await.unwrap();
+    client.shutdown().await.unwrap();
+    let mut echoed = Vec::new();
+    client.read_to_end(&mut echoed).await.unwrap();
+    let (status, executed) = task.await.unwrap();
+    assert_eq!((status, echoed), (Status::Halted, b"hi".to_vec()));
+    assert!(executed > 20);
+}
+
+#[tokio::test]
+async fn fuel_and_cancellation_stop_the_run() {
+    let spin = vec![u32::from(LoadProgram { b: 0, c: 0 })];
+    let mut machine = Machine::new(spin.clone());
+    let mut io = AsyncIo::new(tokio::io::empty(), tokio::io::sink()).yield_every(7);
+    assert_eq!(run_async(&mut machine, Some(100), &mut io).await.unwrap(), Ok(Status::Running));
+    assert_eq!(machine.executed(), 100);
+
+    let token = CancellationToken::new();
+    let mut machine = Machine::new(spin).with_cancellation(token.clone());
+    token.cancel();
+    assert_eq!(run_async(&mut machine, None, &mut io).await.unwrap(), Ok(Status::Running));
+    assert_eq!(machine.executed(), 0);
+}
+
+#[tokio::test]
+async fn a_fault_is_returned_after_the_output_before_it() {
+    let program = [Orthography { a: 0, value: 65 }, Output { c: 0 }, Div { a: 0, b: 0, c: 1 }]
+        .into_iter()
+        .map(u32::from)
+        .collect();
+    let mut machine = Machine::new(program);
+    let mut io = AsyncIo::new(tokio::io::empty(), Vec::new());
+    let result = run_async(&mut machine, None, &mut io).await.unwrap();
+    assert_eq!(result, Err(MachineError::DivisionByZero { pc: 2 }));
+    assert_eq!(io.into_inner().1, b"A");
+}
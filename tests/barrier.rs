@@ -0,0 +1,33 @@
+use rum::barrier::CodeWrite;
+use rum::embed::{Machine, Status};
+use rum::instruction::Instruction::*;
+use rum::testing::words;
+
+#[test]
+fn barrier_sees_stores_into_segment_zero_and_its_replacement() {
+    // Puts Halt in a new 2-word segment and at word 9, then loads the segment.
+    let program = [
+        Orthography { a: 1, value: 2 },
+        Map { b: 5, c: 1 },
+        Orthography { a: 2, value: 7 << 20 },
+        Orthography { a: 3, value: 1 << 8 },
+        Mul { a: 2, b: 2, c: 3 },
+        Store { a: 5, b: 0, c: 2 },
+        Orthography { a: 4, value: 9 },
+        Store { a: 0, b: 4, c: 2 },
+        LoadProgram { b: 5, c: 0 },
+        Orthography { a: 0, value: 0 },
+    ];
+    let mut machine = Machine::new(words(&program));
+    let mut writes = Vec::new();
+    while machine.step_with_barrier(&mut || None, &mut |_| {}, &mut |write| writes.push(write.clone())) == Ok(Status::Running) {}
+    assert_eq!(
+        writes,
+        [
+            CodeWrite::Store { offset: 9, old: u32::from(Orthography { a: 0, value: 0 }), new: 7 << 28 },
+            CodeWrite::Replace { source: 1, old: 0..10, new: 0..2 },
+        ]
+    );
+    assert_eq!(writes[0].invalidated(), 9..10);
+    assert_eq!(writes[1].invalidated(), 0..10);
+}
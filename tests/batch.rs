@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+
+use rum::batch::{execute, json, parse_manifest, run_all, table, Case, Exit, Status};
+use rum::check::diff;
+use rum::error::MachineError;
+use rum::instruction::Instruction::{self, *};
+use rum::testing::bytes;
+
+/// A fresh directory for one test's files.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rum-batch-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write(dir: &Path, name: &str, program: &[Instruction]) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, bytes(program)).unwrap();
+    path
+}
+
+fn cat() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("bin/cat.um")
+}
+
+#[test]
+fn manifests_parse_relative_to_their_directory() {
+    let text = r#"
+        # the echo case
+        [[program]]
+        name = "echo"
+        program = "cat.um"   # next to the manifest
+        input = "in/echo.txt"
+        expected = "echo.expected"
+        timeout = 2.5
+        max_instructions = 1_000_000
+
+        [[program]]
+        program = "hello.um"
+    "#;
+    let cases = parse_manifest(text, Path::new("suite")).unwrap();
+    let echo = Case {
+        name: "echo".to_string(),
+        program: "suite/cat.um".into(),
+        input: Some("suite/in/echo.txt".into()),
+        expected: Some("suite/echo.expected".into()),
+        timeout: Some(2.5),
+        max_instructions: Some(1_000_000),
+    };
+    assert_eq!(cases, [echo, Case { name: "suite/hello.um".to_string(), program: "suite/hello.um".into(), ..Case::default() }]);
+
+    assert_eq!(parse_manifest("name = \"x\"\n", Path::new(".")).unwrap_err(), "manifest line 1: key outside of a [[program]] table");
+    assert_eq!(parse_manifest("[[program]]\nname = \"x\"\n", Path::new(".")).unwrap_err(), "manifest program #1 has no `program` path");
+    assert_eq!(parse_manifest("[[program]]\nspeed = 3\n", Path::new(".")).unwrap_err(), "manifest line 2: unknown key `speed`");
+}
+
+#[test]
+fn cases_run_in_process_and_are_judged() {
+    let dir = scratch("judged");
+    std::fs::write(dir.join("echo.txt"), "hello\n").unwrap();
+    std::fs::write(dir.join("echo.expected"), "hello\n").unwrap();
+    std::fs::write(dir.join("wrong.expected"), "help\n").unwrap();
+    let spin = write(&dir, "spin.um", &[LoadProgram { b: 0, c: 0 }]);
+    let fault = write(&dir, "fault.um", &[Div { a: 0, b: 0, c: 1 }]);
+    let case = |name: &str, program: &Path| Case { name: name.to_string(), program: program.to_path_buf(), input: Some(dir.join("echo.txt")), ..Case::default() };
+    let cases = [
+        Case { expected: Some(dir.join("echo.expected")), ..case("pass", &cat()) },
+        Case { expected: Some(dir.join("wrong.expected")), ..case("fail", &cat()) },
+        case("fault", &fault),
+        Case { max_instructions: Some(1000), ..case("limit", &spin) },
+        Case { timeout: Some(0.05), ..case("timeout", &spin) },
+        case("missing", &dir.join("missing.um")),
+    ];
+    let outcomes = run_all(&cases, 1);
+    let judged: Vec<_> = outcomes.iter().map(|o| (o.name.as_str(), o.status, o.detail.as_str())).collect();
+    assert_eq!(judged[..5], [
+        ("pass", Status::Pass, ""),
+        ("fail", Status::Fail, diff(b"help\n", b"hello\n").as_str()),
+        ("fault", Status::Error, "faulted after 0 instructions: division by zero at pc 0"),
+        ("limit", Status::Limit, "instruction limit reached"),
+        ("timeout", Status::Limit, "timed out after 0.05s"),
+    ]);
+    assert_eq!(judged[5].1, Status::Error);
+    assert!(judged[5].2.starts_with("cannot read program "), "{}", judged[5].2);
+    assert_eq!(outcomes[0].output_bytes, 6);
+
+    let run = execute(&Case { max_instructions: Some(1000), ..case("limit", &spin) }).unwrap();
+    assert_eq!((run.exit, run.executed), (Exit::InstructionLimit, 1000));
+    let run = execute(&case("fault", &fault)).unwrap();
+    assert_eq!(run.exit, Exit::Faulted(MachineError::DivisionByZero { pc: 0 }));
+
+    let report = json(&outcomes);
+    assert!(report.starts_with("{\"total\":6,\"passed\":1,\"failed\":5,\"results\":[{\"name\":\"pass\",\"status\":\"pass\","), "{report}");
+    assert!(table(&outcomes).ends_with("\n1/6 passed\n"));
+    // The table keeps to the first line of the diff.
+    assert!(table(&outcomes).contains("output differs at byte 3 (line 1, column 4); expected 5 bytes, got 6\n"));
+    assert!(!table(&outcomes).contains("--- expected"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn parallel_jobs_keep_manifest_order_and_their_own_io() {
+    let dir = scratch("jobs");
+    let cases: Vec<Case> = (0..12)
+        .map(|i| {
+            let input = dir.join(format!("{i}.in"));
+            let expected = dir.join(format!("{i}.expected"));
+            std::fs::write(&input, format!("case {i}\n")).unwrap();
+            std::fs::write(&expected, format!("case {i}\n")).unwrap();
+            Case { name: format!("case {i}"), program: cat(), input: Some(input), expected: Some(expected), ..Case::default() }
+        })
+        .collect();
+    for jobs in [1, 4, 64] {
+        let outcomes = run_all(&cases, jobs);
+        let names: Vec<_> = outcomes.iter().map(|o| o.name.clone()).collect();
+        assert_eq!(names, (0..12).map(|i| format!("case {i}")).collect::<Vec<_>>());
+        assert!(outcomes.iter().all(|o| o.status == Status::Pass), "{}", table(&outcomes));
+    }
+    assert!(run_all(&[], 4).is_empty());
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+
+use rum::bisect::{bisect, engine, Engine, View};
+use rum::instruction::Instruction::*;
+
+/// Counts r1 down from 3000, about 12,000 instructions.
+fn countdown() -> Vec<u32> {
+    [
+        Nand { a: 7, b: 0, c: 0 },
+        Orthography { a: 1, value: 3000 },
+        Orthography { a: 3, value: 3 },
+        Add { a: 1, b: 1, c: 7 },
+        Orthography { a: 4, value: 7 },
+        CMov { a: 4, b: 3, c: 1 },
+        LoadProgram { b: 0, c: 4 },
+        Orthography { a: 2, value: b'!' as u32 },
+        Output { c: 2 },
+        Halt,
+    ]
+    .into_iter()
+    .map(u32::from)
+    .collect()
+}
+
+/// rum with a bug: r5 reads 1 once `at` instructions have executed.
+struct Buggy {
+    inner: Box<dyn Engine>,
+    at: u64,
+}
+
+impl Engine for Buggy {
+    fn advance(&mut self, n: u64) {
+        self.inner.advance(n)
+    }
+
+    fn view(&self) -> View {
+        let mut view = self.inner.view();
+        if view.executed >= self.at {
+            view.registers[5] = 1;
+        }
+        view
+    }
+
+    fn memory(&self) -> BTreeMap<u32, Vec<u32>> {
+        self.inner.memory()
+    }
+
+    fn word(&self, pc: usize) -> Option<u32> {
+        self.inner.word(pc)
+    }
+
+    fn fork(&self) -> Box<dyn Engine> {
+        Box::new(Buggy { inner: self.inner.fork(), at: self.at })
+    }
+}
+
+#[test]
+fn rum_and_the_reference_agree() {
+    let program = countdown();
+    let [left, right] = ["rum", "naive"].map(|name| engine(name, &program, b"").unwrap());
+    assert_eq!(bisect(["rum", "naive"], left, right, 1000, u64::MAX, true), None);
+    assert!(engine("jit", &program, b"").is_none());
+}
+
+#[test]
+fn divergences_are_bisected_to_one_instruction() {
+    let program = countdown();
+    let buggy = Box::new(Buggy { inner: engine("rum", &program, b"").unwrap(), at: 5001 });
+    let divergence = bisect(["buggy", "naive"], buggy, engine("naive", &program, b"").unwrap(), 4096, u64::MAX, false).unwrap();
+    assert_eq!(divergence.executed, 5000);
+    assert_eq!(divergence.reason, "r5 is 0x1 in buggy but 0x0 in naive");
+    // 5000 instructions in: three of setup, then 1249 trips round the 4-instruction loop, then 1 more.
+    assert_eq!((divergence.pc, divergence.word), (4, Some(u32::from(Orthography { a: 4, value: 7 }))));
+    assert!(divergence.to_string().starts_with("diverged on instruction 5001 (pc 4: loadval r4, 7)"));
+}
@@ -0,0 +1,23 @@
+use rum::cache::{CacheStats, ProgramCache};
+
+#[test]
+fn programs_seen_before_skip_decoding() {
+    let cache = ProgramCache::new(2);
+    let cat = [0xd0, 0, 0, 0, 0x70, 0, 0, 0];
+    assert_eq!(&cache.decode(&cat).unwrap()[..], &[0xd000_0000, 0x7000_0000]);
+    assert_eq!(&cache.decode(&cat).unwrap()[..], &[0xd000_0000, 0x7000_0000]);
+    assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1, programs: 1 });
+
+    assert_eq!(cache.decode(&[0x70, 0, 0]).unwrap_err(), "not a whole number of words");
+    assert_eq!(cache.stats().programs, 1);
+
+    // The oldest program makes way for the third.
+    cache.decode(&[0x70, 0, 0, 1]).unwrap();
+    cache.decode(&[0x70, 0, 0, 2]).unwrap();
+    cache.decode(&cat).unwrap();
+    assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 4, programs: 2 });
+
+    // Loaded as `rum run` loads it, a trailing partial word is dropped.
+    assert_eq!(&cache.words(&[0x70, 0, 0, 2, 0x70])[..], &rum::load::words(&[0x70, 0, 0, 2]));
+    assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 4, programs: 2 });
+}
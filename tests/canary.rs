@@ -0,0 +1,37 @@
+//! Segment guard words: `cargo test --features canary`.
+#![cfg(feature = "canary")]
+
+use rum::canary::{Checked, Overrun, PATTERN, WORDS};
+use rum::embed::{Machine, Status, Stepper};
+use rum::error::MachineError;
+use rum::instruction::Instruction::*;
+use rum::testing::words;
+
+fn guarded(program: &[rum::instruction::Instruction]) -> Checked {
+    Checked::new(Machine::new(words(program)))
+}
+
+#[test]
+fn programs_run_unchanged_with_guarded_segments() {
+    let mut checked = guarded(&[Orthography { a: 1, value: 3 }, Map { b: 2, c: 1 }, Orthography { a: 3, value: 2 }, Store { a: 2, b: 3, c: 1 }, Load { a: 4, b: 2, c: 3 }, Unmap { c: 2 }, Halt]);
+    assert_eq!(checked.len(0), Some(7));
+    checked.run(Some(2), &mut || None, &mut |_| {}).unwrap();
+    assert_eq!((checked.len(1), &checked.machine.state.mapped_memory[1][3..]), (Some(3), &[PATTERN; WORDS][..]));
+    assert_eq!(checked.run(None, &mut || None, &mut |_| {}), Ok(Status::Halted));
+    assert_eq!((checked.machine.state.register(4), checked.len(1)), (3, None));
+}
+
+#[test]
+fn writes_past_the_end_are_caught() {
+    let program = [Orthography { a: 1, value: 3 }, Map { b: 2, c: 1 }, Unmap { c: 2 }, Halt];
+    let mut checked = guarded(&program);
+    checked.run(Some(2), &mut || None, &mut |_| {}).unwrap();
+    checked.machine.state.mapped_memory[1][4] = 7;
+    assert_eq!(checked.verify(), Err(Overrun { segment: 1, offset: 4, found: Some(7) }));
+    let unmap = checked.step(&mut || None, &mut |_| {}).unwrap_err();
+    assert_eq!(unmap, MachineError::HostFault { pc: 2, message: "segment 1 overrun: guard word at offset 4 is 0x00000007".to_string() });
+    // A program's own Load past the end faults as it always has.
+    let mut checked = guarded(&[Orthography { a: 1, value: 3 }, Map { b: 2, c: 1 }, Load { a: 4, b: 2, c: 1 }, Halt]);
+    let load = checked.run(None, &mut || None, &mut |_| {});
+    assert_eq!(load, Err(MachineError::OutOfBounds { pc: 2, segment: 1, offset: 3 }));
+}
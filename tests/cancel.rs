@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use rum::cancel::CancellationToken;
+use rum::embed::{Machine, Status, Stepper};
+use rum::instruction::Instruction::*;
+use rum::interrupt::{Controller, Delivery};
+use rum::plugin::Host;
+use rum::syscall::Syscalls;
+use rum::tasks::Scheduler;
+
+/// Jumps to itself forever.
+fn spin() -> Vec<u32> {
+    vec![u32::from(LoadProgram { b: 0, c: 0 })]
+}
+
+#[test]
+fn another_thread_stops_a_runaway_run() {
+    let token = CancellationToken::new();
+    let mut machine = Machine::new(spin()).with_cancellation(token.clone());
+    let run = std::thread::spawn(move || {
+        let status = machine.run(None, &mut || None, &mut |_| {});
+        (status, machine)
+    });
+    std::thread::sleep(Duration::from_millis(20));
+    token.cancel();
+    let (status, machine) = run.join().unwrap();
+    assert_eq!(status, Ok(Status::Running));
+    assert!(machine.executed() > 0 && machine.cancelled());
+}
+
+#[test]
+fn cancelled_runs_stop_before_starting_until_reset() {
+    let token = CancellationToken::new();
+    token.cancel();
+    let mut machine = Machine::new(spin()).with_cancellation(token.clone());
+    assert_eq!(machine.run(None, &mut || None, &mut |_| {}), Ok(Status::Running));
+    assert_eq!(machine.executed(), 0);
+    token.reset();
+    assert_eq!(machine.run(Some(10), &mut || None, &mut |_| {}), Ok(Status::Running));
+    assert_eq!(machine.executed(), 10);
+}
+
+#[test]
+fn hosts_around_a_machine_stop_too() {
+    let token = CancellationToken::new();
+    token.cancel();
+    let machine = || Machine::new(spin()).with_cancellation(token.clone());
+    let hosts: Vec<Box<dyn Stepper>> = vec![
+        Box::new(Host::new(machine())),
+        Box::new(Controller::new(machine(), Delivery::Flag).unwrap()),
+        Box::new(Scheduler::new(machine()).unwrap()),
+        Box::new(Syscalls::new(machine())),
+    ];
+    for mut host in hosts {
+        assert_eq!(host.run(None, &mut || None, &mut |_| {}), Ok(Status::Running));
+        assert_eq!(host.executed(), 0);
+    }
+}
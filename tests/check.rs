@@ -0,0 +1,25 @@
+use rum::check::{sha256_hex, verify, Expected, OutputDigest};
+
+#[test]
+fn streamed_digest_matches_digest_of_whole_output() {
+    // Longer than the digest's buffer, so it is hashed in pieces.
+    let output: Vec<u8> = (0..200_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    let mut digest = OutputDigest::default();
+    output.iter().for_each(|&byte| digest.push(byte));
+    assert_eq!(digest.bytes(), output.len() as u64);
+    assert_eq!(digest.finish(), sha256_hex(&output));
+}
+
+#[test]
+fn verify_accepts_matching_output_and_explains_a_mismatch() {
+    assert_eq!(verify(b"one\ntwo\n", &Expected::Output(b"one\ntwo\n".to_vec())), Ok(()));
+    let report = verify(b"one\ntoo\n", &Expected::Output(b"one\ntwo\n".to_vec())).unwrap_err();
+    assert_eq!(report, "output differs at byte 5 (line 2, column 2); expected 8 bytes, got 8\n--- expected\n+++ actual\n  one\n- two\n+ too\n");
+    let report = verify(b"one\n", &Expected::Output(b"one\ntwo\n".to_vec())).unwrap_err();
+    assert!(report.ends_with("- two\n+ <end of output>\n"), "{report}");
+
+    let digest = sha256_hex(b"hello\n");
+    assert_eq!(verify(b"hello\n", &Expected::Sha256(format!("{}\n", digest.to_uppercase()))), Ok(()));
+    let report = verify(b"hello", &Expected::Sha256(digest.clone())).unwrap_err();
+    assert!(report.starts_with("output digest mismatch (5 bytes of output)\n") && report.contains(&digest), "{report}");
+}
@@ -0,0 +1,95 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rum::checkpoint::{latest, latest_chain, Checkpoints};
+use rum::seal::Key;
+use rum::snapshot::{load_chain, load_snapshot};
+use rum::state::UniversalMachine;
+
+fn base(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rum-checkpoint-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("run")
+}
+
+fn machine() -> UniversalMachine {
+    UniversalMachine::from_parts([0; 8], vec![vec![0x7000_0000; 4], vec![0; 64]], Vec::new(), 0, Vec::new())
+}
+
+/// Moves the machine on: a new register value and one changed word.
+fn advance(state: &mut UniversalMachine, step: u32) {
+    state.load_value(1, step);
+    state.segment_mut(1).unwrap()[step as usize] = step;
+}
+
+#[test]
+fn the_ring_overwrites_its_oldest_slot() {
+    let base = base("ring");
+    let mut ring = Checkpoints::new(&base, 3).compression(None);
+    let mut state = machine();
+    let mut written = Vec::new();
+    for step in 1..=4 {
+        advance(&mut state, step);
+        written.push(ring.save(&mut state).unwrap());
+        // Modification times order the slots.
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(written, [ring.slot_path(0), ring.slot_path(1), ring.slot_path(2), ring.slot_path(0)]);
+    assert!(!ring.slot_path(3).exists());
+    assert_eq!(latest(&base, 3), Some(ring.slot_path(0)));
+    assert_eq!(load_snapshot(ring.slot_path(0), None).unwrap(), state);
+    assert_eq!(load_snapshot(ring.slot_path(2), None).unwrap().register(1), 3);
+    assert_eq!(latest(&base.with_file_name("other"), 3), None);
+    std::fs::remove_dir_all(base.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn delta_chains_skip_stale_and_corrupt_slots() {
+    let base = base("chain");
+    let mut ring = Checkpoints::with_deltas(&base, 3);
+    let mut state = machine();
+    for step in 1..=3 {
+        advance(&mut state, step);
+        ring.save(&mut state).unwrap();
+    }
+    let chain = latest_chain(&base, 3, None);
+    assert_eq!(chain.len(), 3);
+    assert_eq!(load_chain(&chain, None).unwrap(), state);
+
+    // A new chain starts in slot 0; slots 1 and 2 belong to the old one.
+    advance(&mut state, 4);
+    ring.save(&mut state).unwrap();
+    assert_eq!(latest_chain(&base, 3, None), [ring.slot_path(0)]);
+    assert_eq!(load_chain(&latest_chain(&base, 3, None), None).unwrap(), state);
+
+    advance(&mut state, 5);
+    ring.save(&mut state).unwrap();
+    let good = state.clone();
+    advance(&mut state, 6);
+    ring.save(&mut state).unwrap();
+    let mut bytes = std::fs::read(ring.slot_path(2)).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    std::fs::write(ring.slot_path(2), bytes).unwrap();
+    let chain = latest_chain(&base, 3, None);
+    assert_eq!(chain, [ring.slot_path(0), ring.slot_path(1)]);
+    assert_eq!(load_chain(&chain, None).unwrap(), good);
+    std::fs::remove_dir_all(base.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn sealed_chains_need_their_key() {
+    let base = base("sealed");
+    let key = Key::new([7; 32]);
+    let mut ring = Checkpoints::with_deltas(&base, 2).key(Some(key.clone()));
+    let mut state = machine();
+    for step in 1..=2 {
+        advance(&mut state, step);
+        ring.save(&mut state).unwrap();
+    }
+    let chain = latest_chain(&base, 2, Some(&key));
+    assert_eq!(load_chain(&chain, Some(&key)).unwrap(), state);
+    assert!(load_chain(&chain, None).is_err());
+    assert!(latest_chain(&base, 2, Some(&Key::new([8; 32]))).is_empty());
+    std::fs::remove_dir_all(base.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,37 @@
+use rum::chrometrace::ChromeTrace;
+use rum::clock::Clock;
+use rum::embed::{Machine, Status};
+use rum::instruction::Instruction::*;
+use rum::json::{self, Value};
+use rum::testing::words;
+
+#[test]
+fn the_trace_is_valid_json_with_every_track() {
+    let program = [Orthography { a: 1, value: 4 }, Map { b: 2, c: 1 }, Unmap { c: 2 }, Halt];
+    let mut machine = Machine::new(words(&program));
+    let path = std::env::temp_dir().join(format!("rum-chrometrace-{}.json", std::process::id()));
+    let mut trace = ChromeTrace::with_clock(&path, Clock::Virtual).unwrap();
+    loop {
+        let pc = machine.state.program_counter;
+        let inst = machine.state.mapped_memory[0][pc];
+        trace.before(machine.executed(), pc, inst, &machine.state);
+        let status = machine.step(&mut || None, &mut |_| {}).unwrap();
+        trace.after(inst, &machine.state);
+        if status == Status::Halted {
+            break;
+        }
+    }
+    trace.finish(machine.executed()).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let document = json::parse(&text).unwrap();
+    let events = document.get("traceEvents").and_then(Value::as_array).unwrap();
+    let named = |name: &str| events.iter().find(|event| event.get("name").and_then(Value::as_str) == Some(name)).unwrap();
+    let args = |name: &str, arg: &str| named(name).get("args").and_then(|args| args.get(arg)).and_then(Value::as_u64);
+    assert_eq!(named("process_name").get("args").and_then(|args| args.get("name")).and_then(Value::as_str), Some("rum"));
+    assert_eq!((args("map", "segment"), args("map", "words")), (Some(1), Some(4)));
+    assert_eq!(args("unmap", "segment"), Some(1));
+    assert_eq!(args("0x0-0x100", "instructions"), Some(3));
+    assert!(matches!(named("0x0-0x100").get("dur"), Some(&Value::Number(us)) if us > 0.0));
+}
@@ -0,0 +1,11 @@
+//! The UM spec conformance suite: `cargo test --features conformance`.
+#![cfg(feature = "conformance")]
+
+#[test]
+fn every_case_conforms() {
+    let failures: Vec<String> = rum::conformance::run_all()
+        .into_iter()
+        .filter_map(|(name, result)| result.err().map(|e| format!("{name}: {e}")))
+        .collect();
+    assert!(failures.is_empty(), "{} cases failed:\n{}", failures.len(), failures.join("\n"));
+}
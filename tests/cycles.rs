@@ -0,0 +1,20 @@
+use rum::cycles::{CostModel, Cycles};
+use rum::instruction::Instruction::*;
+
+#[test]
+fn cycles_charge_each_opcode_its_cost() {
+    let model = CostModel::parse("mul=4, div=20").unwrap();
+    assert_eq!(model.cost(4), 4);
+    assert_eq!(model.cost(5), 20);
+    assert_eq!(model.cost(3), 1);
+    assert_eq!(CostModel::parse("unit").unwrap(), CostModel::default());
+    assert!(CostModel::parse("op14=3").is_err());
+    assert!(CostModel::parse("mul").is_err());
+
+    let mut cycles = Cycles::new(model);
+    for inst in [Orthography { a: 1, value: 6 }, Mul { a: 2, b: 1, c: 1 }, Div { a: 3, b: 2, c: 1 }] {
+        cycles.record(inst.into());
+    }
+    assert_eq!(cycles.total(), 1 + 4 + 20);
+    assert_eq!(cycles.report(3), "rum: 25 cycles (3 instructions, 8.33 cycles per instruction)");
+}
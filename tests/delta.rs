@@ -0,0 +1,76 @@
+use rum::snapshot::{apply_delta, checksum, decode, encode, encode_delta, parent, DeltaChain, SnapshotError};
+use rum::state::UniversalMachine;
+
+fn machine() -> UniversalMachine {
+    let segments = vec![vec![0x7000_0000; 4], vec![1; 4096], vec![2; 4096], vec![3; 16]];
+    let mut state = UniversalMachine::from_parts([0; 8], segments, Vec::new(), 0, Vec::new());
+    state.clear_dirty();
+    state
+}
+
+#[test]
+fn deltas_carry_only_modified_segments() {
+    let mut state = machine();
+    let full = encode(&state, None);
+    state.segment_mut(2).unwrap()[7] = 99;
+    state.load_value(3, 2);
+    state.unmap_seg(3).unwrap();
+    state.load_value(5, 8);
+    state.map_seg(4, 5);
+    state.program_counter = 2;
+    let delta = encode_delta(&state, &checksum(&full).unwrap(), None, None);
+    assert!(delta.len() < 4096, "delta of {} bytes", delta.len());
+    assert_eq!(parent(&delta, None).unwrap(), Some(checksum(&full).unwrap()));
+    assert_eq!(parent(&full, None).unwrap(), None);
+
+    let restored = apply_delta(decode(&full, None).unwrap(), &checksum(&full).unwrap(), &delta, None).unwrap();
+    assert_eq!(restored, state);
+    assert_eq!(restored.free_segments(), state.free_segments());
+}
+
+#[test]
+fn a_chain_replays_to_the_latest_state() {
+    let mut state = machine();
+    let mut chain = DeltaChain::new(Some(3), None);
+    let full = chain.next(&mut state);
+    let mut restored = decode(&full, None).unwrap();
+    let mut previous = checksum(&full).unwrap();
+    for step in 1..=5 {
+        state.segment_mut(step % 4).unwrap()[0] = step as u32;
+        state.load_value(0, step as u32);
+        let delta = chain.next(&mut state);
+        assert!(state.dirty_segments().is_empty());
+        restored = apply_delta(restored, &previous, &delta, None).unwrap();
+        previous = checksum(&delta).unwrap();
+        assert_eq!(restored, state, "after delta {step}");
+    }
+}
+
+#[test]
+fn deltas_only_apply_to_their_parent() {
+    let mut state = machine();
+    let full = encode(&state, None);
+    state.segment_mut(1).unwrap()[0] = 5;
+    let delta = encode_delta(&state, &checksum(&full).unwrap(), None, None);
+    let other = encode(&state, None);
+    assert!(matches!(apply_delta(machine(), &checksum(&other).unwrap(), &delta, None), Err(SnapshotError::BrokenChain)));
+    assert!(matches!(apply_delta(machine(), &checksum(&full).unwrap(), &full, None), Err(SnapshotError::BrokenChain)));
+    assert!(matches!(decode(&delta, None), Err(SnapshotError::BrokenChain)));
+}
+
+#[test]
+fn truncated_and_corrupt_deltas_are_refused() {
+    let mut state = machine();
+    let full = encode(&state, None);
+    let sum = checksum(&full).unwrap();
+    state.segment_mut(3).unwrap()[1] = 5;
+    let delta = encode_delta(&state, &sum, Some(3), None);
+    for cut in 0..delta.len() {
+        assert!(apply_delta(machine(), &sum, &delta[..cut], None).is_err(), "cut at {cut}");
+    }
+    for at in 52..delta.len() {
+        let mut corrupt = delta.clone();
+        corrupt[at] ^= 0x10;
+        assert!(matches!(apply_delta(machine(), &sum, &corrupt, None), Err(SnapshotError::ChecksumMismatch)), "byte {at}");
+    }
+}
@@ -0,0 +1,73 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rum::embed::{Machine, Status, Stepper};
+use rum::error::MachineError;
+use rum::instruction::Instruction::{self, *};
+use rum::plugin::Host;
+use rum::testing::words;
+
+fn machine(program: &[Instruction]) -> Machine {
+    Machine::new(words(program))
+}
+
+#[test]
+fn loads_and_stores_on_the_device_reach_the_closures() {
+    let program = [
+        Orthography { a: 1, value: 5000 },
+        Orthography { a: 2, value: 3 },
+        Orthography { a: 3, value: 42 },
+        Store { a: 1, b: 2, c: 3 },
+        Load { a: 4, b: 1, c: 2 },
+        // Memory still works beside the device.
+        Map { b: 5, c: 2 },
+        Store { a: 5, b: 0, c: 3 },
+        Load { a: 6, b: 5, c: 0 },
+        Halt,
+    ];
+    let stores = Rc::new(RefCell::new(Vec::new()));
+    let seen = stores.clone();
+    let mut host = Host::new(machine(&program)).with_device(5000, |offset| offset * 100, move |offset, value| seen.borrow_mut().push((offset, value))).unwrap();
+    assert_eq!(host.run(None, &mut || None, &mut |_| {}), Ok(Status::Halted));
+    assert_eq!(*stores.borrow(), [(3, 42)]);
+    assert_eq!((host.machine.state.register(4), host.machine.state.register(6)), (300, 42));
+    assert_eq!(host.machine.executed(), 8);
+}
+
+#[test]
+fn map_segment_never_returns_the_device_segment() {
+    let program = [
+        Orthography { a: 1, value: 4 },
+        Map { b: 2, c: 1 },
+        Map { b: 3, c: 1 },
+        Map { b: 4, c: 1 },
+        // Freed and mapped again, segment 1 comes back rather than the device.
+        Unmap { c: 2 },
+        Map { b: 5, c: 1 },
+        Halt,
+    ];
+    let mut host = Host::new(machine(&program)).with_device(2, |_| 7, |_, _| {}).unwrap();
+    assert_eq!(host.run(None, &mut || None, &mut |_| {}), Ok(Status::Halted));
+    let registers: Vec<u32> = (2..6).map(|r| host.machine.state.register(r)).collect();
+    assert_eq!(registers, [1, 3, 4, 1]);
+    assert!(host.machine.state.mapped_memory[2].is_empty());
+
+    // The program cannot free the device either.
+    let program = [Orthography { a: 1, value: 2 }, Unmap { c: 1 }, Halt];
+    let mut host = Host::new(machine(&program)).with_device(2, |_| 7, |_, _| {}).unwrap();
+    assert_eq!(host.run(None, &mut || None, &mut |_| {}), Err(MachineError::UnmappedSegment { pc: 1, segment: 2 }));
+}
+
+#[test]
+fn the_program_segment_and_mapped_segments_cannot_be_devices() {
+    let host = || Host::new(machine(&[Halt]));
+    assert!(host().with_device(0, |_| 7, |_, _| {}).is_err());
+
+    let mut mapped = host();
+    mapped.machine.state.mapped_memory.push(vec![0; 4]);
+    assert_eq!(mapped.with_device(1, |_| 7, |_, _| {}).err(), Some("segment 1 is already mapped".to_string()));
+
+    let twice = host().with_device(3, |_| 7, |_, _| {}).unwrap();
+    assert!(twice.with_device(3, |_| 7, |_, _| {}).is_err());
+    assert!(host().with_device(1, |_| 7, |_, _| {}).is_ok());
+}
@@ -0,0 +1,21 @@
+//! `rum::differential` against the naive reference interpreter.
+
+use rum::differential::{compare, Reference};
+use rum::gen::{generate, Config};
+
+#[test]
+fn generated_programs_agree() {
+    let config = Config::default();
+    for seed in 0..50 {
+        let divergence = compare(&generate(seed, &config), b"input", &Reference::Naive, config.fuel()).unwrap();
+        assert!(divergence.is_none(), "seed {seed}: {}", divergence.unwrap());
+    }
+}
+
+#[test]
+fn agrees_on_unmapping_segment_zero() {
+    // loadval r0, 0; unmap r0; halt. Both fault on the Unmap.
+    let program = [0xD000_0000, 0x9000_0000, 0x7000_0000];
+    let divergence = compare(&program, b"", &Reference::Naive, 100).unwrap();
+    assert!(divergence.is_none(), "{}", divergence.unwrap());
+}
@@ -0,0 +1,24 @@
+use rum::embed::Machine;
+use rum::instruction::Instruction::*;
+use rum::testing::words;
+
+#[test]
+fn stores_maps_and_host_writes_mark_segments_dirty() {
+    let program = [Orthography { a: 1, value: 4 }, Map { b: 2, c: 1 }, Map { b: 3, c: 1 }, Store { a: 2, b: 0, c: 1 }, Unmap { c: 3 }, Map { b: 3, c: 1 }, Halt];
+    let mut machine = Machine::new(words(&program));
+    // Never cleared, so everything counts as modified.
+    assert!(machine.state.is_dirty(0));
+    machine.state.clear_dirty();
+    machine.run(Some(3), &mut || None, &mut |_| {}).unwrap();
+    // Segments mapped after the clear are past the flags, so dirty.
+    assert_eq!(machine.state.dirty_segments(), [1, 2]);
+    machine.state.clear_dirty();
+    machine.run(Some(1), &mut || None, &mut |_| {}).unwrap();
+    assert_eq!(machine.state.dirty_segments(), [1]);
+    machine.state.clear_dirty();
+    machine.run(Some(2), &mut || None, &mut |_| {}).unwrap();
+    assert_eq!(machine.state.dirty_segments(), [2]);
+    machine.state.clear_dirty();
+    machine.state.segment_mut(0).unwrap()[6] = u32::from(Halt);
+    assert_eq!((machine.state.dirty_segments(), machine.state.is_dirty(1)), (vec![0], false));
+}
@@ -0,0 +1,24 @@
+//! The edge coverage map: `cargo test --features edge-coverage`.
+#![cfg(feature = "edge-coverage")]
+
+use rum::{edges, fuzz};
+
+const CAT: &[u8] = include_bytes!("golden/cat.um");
+
+fn program() -> Vec<u32> {
+    rum::load::words(CAT)
+}
+
+// One test, as the map is shared by the whole process.
+#[test]
+fn more_input_reaches_more_edges() {
+    fuzz::run_bounded(program(), b"");
+    let quiet = edges::edges_hit();
+    assert!(quiet > 0);
+    fuzz::run_bounded(program(), b"some input\n");
+    assert!(edges::edges_hit() > quiet);
+    let map = edges::snapshot();
+    assert_eq!(map.len(), edges::MAP_SIZE);
+    edges::reset();
+    assert_eq!(edges::edges_hit(), 0);
+}
@@ -0,0 +1,24 @@
+use rum::encoding::{encode, encode_value, get, set, InstructionWord, OP, RA, RC, VL};
+use rum::instruction::Instruction::*;
+use rum::rumdis::Opcode;
+
+#[test]
+fn typed_accessors_read_every_field() {
+    let word = InstructionWord::from(u32::from(Add { a: 1, b: 2, c: 3 }));
+    assert_eq!((word.opcode(), word.opcode_bits(), word.ra(), word.rb(), word.rc()), (Some(Opcode::Add), 3, 1, 2, 3));
+    let word = InstructionWord(u32::from(Orthography { a: 5, value: 0x1ff_ffff }));
+    assert_eq!((word.opcode(), word.rl(), word.value()), (Some(Opcode::LoadVal), 5, 0x1ff_ffff));
+    assert_eq!(InstructionWord(14 << 28).opcode(), None);
+}
+
+#[test]
+fn encoding_matches_instructions_and_wraps_operands() {
+    assert_eq!(encode(Opcode::BNand, 7, 0, 4), u32::from(Nand { a: 7, b: 0, c: 4 }));
+    assert_eq!(encode(Opcode::Output, 0, 0, 9), u32::from(Output { c: 1 }));
+    assert_eq!(encode_value(2, 65), u32::from(Orthography { a: 2, value: 65 }));
+    assert_eq!(get(&VL, encode_value(0, 1 << 25 | 7)), 7);
+
+    let word = set(&RA, !0, 0);
+    assert_eq!((get(&RA, word), get(&RC, word), get(&OP, word)), (0, 7, 15));
+    assert_eq!((OP.width(), OP.lsb(), OP.max(), OP.mask()), (4, 28, 15, 0xf000_0000));
+}
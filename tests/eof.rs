@@ -0,0 +1,12 @@
+use rum::eof::{parse_value, AfterEof, Eof};
+
+#[test]
+fn eof_options_parse() {
+    assert_eq!(parse_value("0xffffffff"), Ok(!0));
+    assert_eq!(parse_value("0"), Ok(0));
+    assert!(parse_value("-1").is_err());
+    assert_eq!(AfterEof::parse("terminal"), Ok(AfterEof::Terminal));
+    assert!(AfterEof::parse("again").is_err());
+    assert_eq!(Eof::default().value, 0xffff_ffff);
+    assert_eq!(Eof::default().after, AfterEof::Repeat);
+}
@@ -0,0 +1,65 @@
+use rum::embed::{Machine, Status};
+use rum::events::{Event, EventLog, Kind, Query, Reader};
+use rum::instruction::Instruction::*;
+use rum::testing::words;
+
+/// Runs `program` the way `rum run --event-log` does, reading `x` for
+/// input, and reads its log back.
+fn logged(name: &str, program: &[rum::instruction::Instruction]) -> (Vec<Event>, std::path::PathBuf) {
+    let path = std::env::temp_dir().join(format!("rum-events-{name}-{}.evl", std::process::id()));
+    let mut log = EventLog::create(&path).unwrap();
+    let mut machine = Machine::new(words(program));
+    let (end, pc) = loop {
+        let pc = machine.state.program_counter;
+        let inst = machine.state.mapped_memory[0][pc];
+        log.before(machine.executed(), pc, inst, &machine.state);
+        match machine.step(&mut || Some(b'x'), &mut |_| {}) {
+            Err(e) => break (Kind::Fault(e.message()), pc),
+            Ok(Status::Halted) => break (Kind::Halt, pc),
+            Ok(_) => log.after(inst, &machine.state),
+        }
+    };
+    log.finish(machine.executed(), pc, end).unwrap();
+    let events = Reader::open(&path).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+    (events, path)
+}
+
+#[test]
+fn runs_log_memory_output_and_their_end() {
+    let program = [Orthography { a: 1, value: 300 }, Map { b: 2, c: 1 }, Input { c: 3 }, Output { c: 3 }, Unmap { c: 2 }, Halt];
+    let (events, path) = logged("halt", &program);
+    let kinds: Vec<Kind> = events.iter().map(|event| event.kind.clone()).collect();
+    assert_eq!(kinds, vec![Kind::Map { segment: 1, words: 300 }, Kind::Input(120), Kind::Output(120), Kind::Unmap { segment: 1 }, Kind::Halt]);
+    assert_eq!((events[0].executed, events[0].pc), (1, 1));
+    assert_eq!((events[4].executed, events[4].pc), (5, 5));
+    assert_eq!(events[2].describe(), "             3  pc 3         output  0x78 'x'");
+
+    let big = Query { larger_than: Some(1000), ..Query::default() };
+    assert_eq!(events.iter().filter(|event| big.matches(event)).count(), 1);
+    let late = Query { kinds: vec!["map".to_string(), "unmap".to_string()], after: Some(2), ..Query::default() };
+    assert_eq!(events.iter().filter(|event| late.matches(event)).map(|event| event.executed).collect::<Vec<_>>(), vec![4]);
+    assert_eq!(events[0].describe(), "             1  pc 1         map     segment 1, 300 words (1200 bytes)");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn faults_end_the_log_with_their_message() {
+    let (events, path) = logged("fault", &[Orthography { a: 1, value: 1 }, Div { a: 2, b: 1, c: 0 }, Halt]);
+    let last = events.last().unwrap();
+    assert_eq!((last.executed, last.pc), (1, 1));
+    assert_eq!(last.kind, Kind::Fault("division by zero".to_string()));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn cut_short_logs_are_read_up_to_the_damage() {
+    let program = [Orthography { a: 1, value: 4 }, Map { b: 2, c: 1 }, Map { b: 3, c: 1 }, Halt];
+    let (_, path) = logged("cut", &program);
+    let bytes = std::fs::read(&path).unwrap();
+    let mut reader = Reader::new(&bytes[..bytes.len() - 20]).unwrap();
+    assert_eq!(reader.next().unwrap().unwrap().kind, Kind::Map { segment: 1, words: 4 });
+    assert!(reader.next().unwrap().unwrap_err().contains("cut short"));
+    assert!(reader.next().is_none());
+    assert_eq!(Reader::new(&b"RUMSTATE.."[..]).err().unwrap(), "not a rum event log");
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,21 @@
+use rum::embed::Machine;
+use rum::explain::Explain;
+use rum::instruction::Instruction::*;
+use rum::testing::words;
+
+#[test]
+fn explanations_show_values_before_and_after() {
+    let program = [Orthography { a: 1, value: 0xff }, Orthography { a: 2, value: 0x41 }, Nand { a: 3, b: 1, c: 2 }, Map { b: 4, c: 2 }, Store { a: 4, b: 0, c: 1 }];
+    let mut machine = Machine::new(words(&program));
+    let mut explained = Vec::new();
+    for _ in program {
+        let state = &machine.state;
+        let explain = Explain::before(state.mapped_memory[0][state.program_counter], state);
+        machine.step(&mut || None, &mut |_| {}).unwrap();
+        explained.push(explain.after(&machine.state));
+    }
+    assert_eq!(explained[0], "r1 := 255 = 0xff (was 0x0)");
+    assert_eq!(explained[2], "r3 := NAND(r1, r2) = NAND(0x000000ff, 0x00000041) = 0xffffffbe (was 0x0)");
+    assert_eq!(explained[3], "map a new segment of r2 = 65 words, all 0; its identifier goes in r4 = 0x1 (was 0x0)");
+    assert_eq!(explained[4], "segment r4 at offset r0 := r1: [1][0] = 0xff (was 0x0)");
+}
@@ -0,0 +1,139 @@
+use std::collections::VecDeque;
+use std::ffi::{c_int, c_void, CStr};
+
+use rum::ffi::*;
+use rum::instruction::Instruction::{self, *};
+use rum::testing::bytes;
+
+/// The host side of the I/O callbacks, passed to them as `user`.
+#[derive(Default)]
+struct Io {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+unsafe extern "C" fn read(user: *mut c_void) -> c_int {
+    (*(user as *mut Io)).input.pop_front().map_or(-1, c_int::from)
+}
+
+unsafe extern "C" fn write(user: *mut c_void, byte: u8) {
+    (*(user as *mut Io)).output.push(byte);
+}
+
+/// A machine loaded with `program`, its I/O going through `io`.
+unsafe fn machine(program: &[Instruction], io: &mut Io) -> *mut RumMachine {
+    let m = rum_machine_new();
+    let program = bytes(program);
+    assert_eq!(rum_machine_load(m, program.as_ptr(), program.len()), 0);
+    rum_machine_set_io(m, Some(read), Some(write), io as *mut Io as *mut c_void);
+    m
+}
+
+#[test]
+fn programs_run_with_host_io() {
+    let mut io = Io { input: b"ok".iter().copied().collect(), ..Io::default() };
+    unsafe {
+        let m = machine(&[Input { c: 1 }, Output { c: 1 }, Input { c: 1 }, Output { c: 1 }, Input { c: 2 }, Halt], &mut io);
+        assert_eq!(rum_machine_step(m), RUM_RUNNING);
+        assert_eq!((rum_machine_pc(m), rum_machine_register(m, 1)), (1, u32::from(b'o')));
+        assert_eq!(rum_machine_run(m, 2), RUM_RUNNING);
+        assert_eq!(rum_machine_executed(m), 3);
+        assert_eq!(rum_machine_run(m, 0), RUM_HALTED);
+        assert_eq!(rum_machine_executed(m), 5);
+        // Input past the end reads all ones.
+        assert_eq!(rum_machine_register(m, 2), u32::MAX);
+        assert_eq!(rum_machine_step(m), RUM_HALTED);
+        rum_machine_free(m);
+    }
+    assert_eq!(io.output, b"ok");
+}
+
+#[test]
+fn registers_and_memory_are_reachable() {
+    let mut io = Io::default();
+    unsafe {
+        let m = machine(&[Orthography { a: 1, value: 3 }, Map { b: 2, c: 1 }, Halt], &mut io);
+        assert_eq!(rum_machine_run(m, 0), RUM_HALTED);
+        assert_eq!(rum_machine_register(m, 2), 1);
+        assert_eq!((rum_machine_segment_len(m, 1), rum_machine_segment_len(m, 9)), (3, 0));
+        assert_eq!(rum_machine_write(m, 1, 2, 0xabcd), 0);
+        assert_eq!(rum_machine_write(m, 1, 3, 0), -1);
+        let mut word = 0;
+        assert_eq!(rum_machine_read(m, 1, 2, &mut word), 0);
+        assert_eq!(word, 0xabcd);
+        assert_eq!(rum_machine_read(m, 1, 3, &mut word), -1);
+        assert_eq!(rum_machine_read(m, 1, 2, std::ptr::null_mut()), -1);
+
+        assert_eq!(rum_machine_set_register(m, 7, 42), 0);
+        assert_eq!(rum_machine_register(m, 7), 42);
+        assert_eq!(rum_machine_set_register(m, 8, 1), -1);
+        assert_eq!(rum_machine_register(m, 8), 0);
+        rum_machine_free(m);
+    }
+}
+
+#[test]
+fn a_fault_leaves_the_machine_unusable_until_reloaded() {
+    let mut io = Io::default();
+    unsafe {
+        let m = machine(&[Orthography { a: 1, value: 65 }, Output { c: 1 }, Div { a: 1, b: 1, c: 2 }, Halt], &mut io);
+        assert_eq!(rum_machine_run(m, 0), RUM_FAULT);
+        assert_eq!(rum_machine_executed(m), 2);
+        assert_eq!(rum_machine_step(m), RUM_FAULT);
+        assert_eq!(rum_machine_run(m, 10), RUM_FAULT);
+
+        let halt = bytes(&[Halt]);
+        assert_eq!(rum_machine_load(m, halt.as_ptr(), 3), -1);
+        assert_eq!(rum_machine_load(m, halt.as_ptr(), halt.len()), 0);
+        assert_eq!(rum_machine_run(m, 0), RUM_HALTED);
+        assert!(rum_machine_fault_message(m).is_null());
+        rum_machine_free(m);
+    }
+    assert_eq!(io.output, b"A");
+}
+
+#[test]
+fn a_fault_reports_where_and_what() {
+    let mut io = Io::default();
+    unsafe {
+        let m = machine(&[Orthography { a: 1, value: 65 }, Output { c: 1 }, Div { a: 1, b: 1, c: 2 }, Halt], &mut io);
+        let mut pc = u64::MAX;
+        assert_eq!(rum_machine_fault_pc(m, &mut pc), -1);
+        assert!(rum_machine_fault_message(m).is_null());
+
+        assert_eq!(rum_machine_run(m, 0), RUM_FAULT);
+        assert_eq!(rum_machine_fault_pc(m, &mut pc), 0);
+        assert_eq!(pc, 2);
+        assert_eq!(CStr::from_ptr(rum_machine_fault_message(m)).to_str(), Ok("division by zero"));
+        // Later calls refused on the faulted machine keep the original fault.
+        assert_eq!(rum_machine_step(m), RUM_FAULT);
+        assert_eq!(CStr::from_ptr(rum_machine_fault_message(m)).to_str(), Ok("division by zero"));
+        assert_eq!(rum_machine_fault_pc(m, std::ptr::null_mut()), -1);
+        rum_machine_free(m);
+
+        assert_eq!(rum_machine_fault_pc(std::ptr::null(), &mut pc), -1);
+        assert!(rum_machine_fault_message(std::ptr::null()).is_null());
+    }
+}
+
+#[test]
+fn null_handles_and_missing_callbacks_are_tolerated() {
+    let null = std::ptr::null_mut();
+    unsafe {
+        assert_eq!(rum_machine_step(null), RUM_FAULT);
+        assert_eq!(rum_machine_run(null, 0), RUM_FAULT);
+        assert_eq!(rum_machine_load(null, std::ptr::null(), 0), -1);
+        assert_eq!((rum_machine_executed(null), rum_machine_pc(null), rum_machine_segment_len(null, 0)), (0, 0, 0));
+        rum_machine_free(null);
+
+        // No program: running off the end of an empty segment 0 is a fault.
+        let m = rum_machine_new();
+        assert_eq!(rum_machine_step(m), RUM_FAULT);
+        // No callbacks: input is at its end and output goes nowhere.
+        let program = bytes(&[Input { c: 1 }, Orthography { a: 2, value: 33 }, Output { c: 2 }, Halt]);
+        assert_eq!(rum_machine_load(m, program.as_ptr(), program.len()), 0);
+        assert_eq!(rum_machine_run(m, 0), RUM_HALTED);
+        assert_eq!(rum_machine_register(m, 1), u32::MAX);
+        rum_machine_free(m);
+    }
+}
@@ -0,0 +1,59 @@
+use rum::clock::Clock;
+use rum::filters::{OutputFilter, Pipeline, Spec, StripAnsi, Uppercase};
+
+fn run(pipeline: &mut Pipeline, text: &[u8], executed: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    for &byte in text {
+        out.extend_from_slice(pipeline.push(byte, executed));
+    }
+    out.extend(pipeline.finish());
+    out
+}
+
+#[test]
+fn escapes_are_stripped() {
+    let mut pipeline = Pipeline::new().with(StripAnsi::default());
+    let text = b"\x1b[1;31mred\x1b[0m \x1b]0;title\x07plain \x1b]8;;url\x1b\\link\x1bc!";
+    assert_eq!(run(&mut pipeline, text, 0), b"red plain link!");
+}
+
+#[test]
+fn filters_apply_in_order() {
+    let specs = Spec::parse_list("strip-ansi, timestamp,uppercase").unwrap();
+    assert_eq!(specs, vec![Spec::StripAnsi, Spec::Timestamp, Spec::Uppercase]);
+    // 150 million instructions are 1.5 virtual seconds.
+    let mut pipeline = Pipeline::build(&specs, Clock::Virtual).unwrap();
+    assert_eq!(run(&mut pipeline, b"\x1b[2Jok\nbye\n", 150_000_000), b"[   1.500] OK\n[   1.500] BYE\n");
+    assert!(Spec::parse("lowercase").unwrap_err().starts_with("unknown output filter `lowercase`"));
+    assert!(Spec::parse("tee:").is_err());
+}
+
+#[test]
+fn tee_keeps_what_reaches_it() {
+    let path = std::env::temp_dir().join(format!("rum-filters-{}.txt", std::process::id()));
+    let specs = vec![Spec::Tee(path.clone()), Spec::StripAnsi];
+    let mut pipeline = Pipeline::build(&specs, Clock::Virtual).unwrap();
+    assert_eq!(run(&mut pipeline, b"\x1b[32mgo\x1b[0m\n", 0), b"go\n");
+    assert_eq!(std::fs::read(&path).unwrap(), b"\x1b[32mgo\x1b[0m\n");
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Holds everything back until the run ends, then releases it reversed.
+#[derive(Default)]
+struct Reverse(Vec<u8>);
+
+impl OutputFilter for Reverse {
+    fn filter(&mut self, byte: u8, _: u64, _: &mut Vec<u8>) {
+        self.0.push(byte);
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        out.extend(self.0.drain(..).rev());
+    }
+}
+
+#[test]
+fn bytes_held_back_still_pass_through_later_filters() {
+    let mut pipeline = Pipeline::new().with(Reverse::default()).with(Uppercase);
+    assert_eq!(run(&mut pipeline, b"abc", 0), b"CBA");
+}
@@ -0,0 +1,54 @@
+use rum::flame::{CallStack, Samples};
+
+#[test]
+fn calls_are_known_once_their_return_site_is() {
+    let mut stack = CallStack::new();
+    // The first call from 5 looks like a branch until something returns to 6.
+    stack.jump(5, 20, false);
+    stack.jump(25, 6, true);
+    assert!(stack.entries().is_empty());
+    stack.jump(5, 20, false);
+    assert_eq!(stack.entries(), [20]);
+    // Branches and loops inside the callee leave the stack alone.
+    stack.jump(21, 24, false);
+    stack.jump(24, 20, false);
+    assert_eq!(stack.entries(), [20]);
+    stack.jump(25, 6, true);
+    assert!(stack.entries().is_empty());
+}
+
+#[test]
+fn returns_pop_to_the_matching_frame() {
+    let mut stack = CallStack::new();
+    for ret in [6, 41] {
+        stack.jump(0, ret, true);
+    }
+    stack.jump(5, 20, false);
+    stack.jump(40, 60, false);
+    stack.jump(40, 60, false);
+    assert_eq!(stack.names(), ["start", "fn_20", "fn_60", "fn_60"]);
+    stack.jump(70, 41, true);
+    assert_eq!(stack.entries(), [20, 60]);
+    stack.jump(70, 6, true);
+    assert!(stack.entries().is_empty());
+    stack.replace();
+    assert_eq!(stack.names(), ["replaced"]);
+}
+
+#[test]
+fn folded_output() {
+    let mut stack = CallStack::new();
+    let mut samples = Samples::new(2);
+    stack.jump(0, 6, true);
+    for _ in 0..4 {
+        samples.tick(&stack);
+    }
+    stack.jump(5, 20, false);
+    for _ in 0..6 {
+        samples.tick(&stack);
+    }
+    let mut folded = Vec::new();
+    samples.write_folded(&mut folded).unwrap();
+    assert_eq!(samples.total(), 5);
+    assert_eq!(String::from_utf8(folded).unwrap(), "start 2\nstart;fn_20 3\n");
+}
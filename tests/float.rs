@@ -0,0 +1,45 @@
+//! Float extension opcodes: `cargo test --features extensions`.
+#![cfg(feature = "extensions")]
+
+use rum::embed::{Machine, Status, Stepper};
+use rum::error::MachineError;
+use rum::float::{self, Float, Op};
+use rum::instruction::Instruction::*;
+use rum::plugin::Host;
+
+#[test]
+fn divides_register_pairs_and_converts_back() {
+    let program = vec![
+        u32::from(Orthography { a: 6, value: 7 }),
+        u32::from(Orthography { a: 7, value: 2 }),
+        float::encode(Op::FromInt, 0, 6, 0),
+        float::encode(Op::FromInt, 2, 7, 0),
+        float::encode(Op::Div, 4, 0, 2),
+        float::encode(Op::Mul, 0, 4, 4),
+        float::encode(Op::ToInt, 1, 0, 0),
+        u32::from(Halt),
+    ];
+    let mut host = Host::new(Machine::new(program.clone()));
+    host.add(Box::new(Float));
+    assert_eq!(host.run(None, &mut || None, &mut |_| {}), Ok(Status::Halted));
+    let registers: [u32; 8] = std::array::from_fn(|r| host.machine.state.register(r as u32));
+    assert_eq!((float::pair(&registers, 4), registers[1]), (3.5, 12));
+    // Without the plugin the escape opcode stays invalid.
+    let mut machine = Machine::new(program);
+    let fault = machine.run(None, &mut || None, &mut |_| {}).unwrap_err();
+    assert!(matches!(fault, MachineError::InvalidOpcode { pc: 2, .. }));
+}
+
+#[test]
+fn odd_pairs_and_unknown_operations_fault() {
+    let mut registers = [0; 8];
+    assert!(float::execute(float::encode(Op::Add, 1, 0, 0), &mut registers).unwrap().is_err());
+    assert!(float::execute(15 << 28 | 9 << 24, &mut registers).unwrap().is_err());
+    assert!(float::execute(u32::from(Halt), &mut registers).is_none());
+    // ftoi truncates and saturates, with NaN as 0.
+    for (value, int) in [(f64::NAN, 0), (-1.0, 0), (1e12, u32::MAX), (2.9, 2)] {
+        float::set_pair(&mut registers, 0, value);
+        float::execute(float::encode(Op::ToInt, 7, 0, 0), &mut registers).unwrap().unwrap();
+        assert_eq!(registers[7], int, "{value}");
+    }
+}
@@ -0,0 +1,13 @@
+use rum::fuzz::{fuzz_program, run_bounded, Outcome};
+use rum::instruction::Instruction::*;
+
+#[test]
+fn runs_end_in_an_outcome() {
+    let halt = u32::from(Halt).to_be_bytes();
+    assert_eq!(fuzz_program(&halt), Outcome::Halted);
+    assert_eq!(run_bounded(vec![u32::from(Div { a: 0, b: 0, c: 1 })], b""), Outcome::Faulted);
+    assert_eq!(run_bounded(vec![u32::from(LoadProgram { b: 0, c: 0 })], b""), Outcome::OutOfFuel);
+    // 128 MiB, over the 64 MiB bound.
+    let map = [Orthography { a: 1, value: (1 << 25) - 1 }, Map { b: 2, c: 1 }, Halt].map(u32::from);
+    assert_eq!(run_bounded(map.to_vec(), b""), Outcome::OutOfMemory);
+}
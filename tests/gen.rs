@@ -0,0 +1,22 @@
+//! Programs from `rum::gen`: they halt within their fuel bound and depend only on the seed.
+
+use rum::embed::{Machine, Status};
+use rum::gen::{generate, Config};
+
+#[test]
+fn generated_programs_halt_within_fuel() {
+    let config = Config::default();
+    for seed in 0..500 {
+        let mut machine = Machine::new(generate(seed, &config));
+        let mut input = (0..=255u8).cycle().skip(seed as usize);
+        let status = machine.run(Some(config.fuel()), &mut || input.next(), &mut |_| {});
+        assert_eq!(status, Ok(Status::Halted), "seed {seed}");
+    }
+}
+
+#[test]
+fn seeds_are_reproducible() {
+    let config = Config { blocks: 4, ..Config::default() };
+    assert_eq!(generate(7, &config), generate(7, &config));
+    assert_ne!(generate(7, &config), generate(8, &config));
+}
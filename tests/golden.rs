@@ -0,0 +1,47 @@
+//! The golden-output runner, on the programs in tests/golden.
+
+use rum::testing::{self, End};
+
+const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+const HELLO: &[u8] = include_bytes!("golden/hello.um");
+
+#[test]
+fn directory_passes() {
+    testing::assert_dir(GOLDEN);
+}
+
+#[test]
+fn run_expect_accepts_the_right_output() {
+    testing::run_expect(HELLO, b"", b"Hello, world.\n");
+}
+
+#[test]
+fn check_explains_a_mismatch() {
+    let report = testing::check(HELLO, b"", b"Hello, there.\n").unwrap_err();
+    assert!(report.contains("output differs at byte 7"), "{report}");
+}
+
+#[test]
+fn runs_out_of_fuel() {
+    // 0: loadval r0, 0; 1: loadprog r0, r0 (jump to 0).
+    let spin: Vec<u8> = [0xD000_0000u32, 0xC000_0000].iter().flat_map(|w| w.to_be_bytes()).collect();
+    assert_eq!(testing::run(&spin, b"", 1000).end, End::OutOfFuel);
+}
+
+#[test]
+fn test_io_injects_end_of_input() {
+    let mut io = testing::TestIo::new("hello\n").eof_at(3);
+    let (end, _) = testing::run_io(include_bytes!("golden/cat.um"), &mut io, testing::FUEL);
+    assert_eq!(end, End::Halted);
+    io.assert_output("hel");
+    assert_eq!(io.consumed(), 3);
+}
+
+#[test]
+fn failed_assertions_report_the_pc() {
+    // loadval r3, 5; assert r3 == 6; halt
+    let words = [0xD000_0000u32 | 3 << 25 | 5, rum::assertions::assert_eq(3, 6), 0x7000_0000];
+    let program: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+    let End::Faulted(message) = testing::run(&program, b"", 100).end else { panic!("the assertion passed") };
+    assert!(message.contains("pc 1: assertion failed: r3 is 0x5, expected 0x6"), "{message}");
+}
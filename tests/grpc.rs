@@ -0,0 +1,123 @@
+//! Run with `cargo test --features grpc`.
+#![cfg(feature = "grpc")]
+
+use std::net::{SocketAddr, TcpListener};
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::{Channel, Server};
+use tonic::Code;
+
+use rum::grpc::proto::executor_client::ExecutorClient;
+use rum::grpc::proto::executor_server::ExecutorServer;
+use rum::grpc::proto::{execute_request, execute_response, ExecuteRequest, Finished, Reason, Start};
+use rum::grpc::{Limits, Service};
+use rum::instruction::Instruction::{self, *};
+use rum::testing::bytes;
+
+/// Serves `limits` on a free port and returns a client connected to it.
+async fn server(limits: Limits) -> ExecutorClient<Channel> {
+    let addr: SocketAddr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    tokio::spawn(Server::builder().add_service(ExecutorServer::new(Service::new(limits))).serve(addr));
+    for _ in 0..100 {
+        if let Ok(client) = ExecutorClient::connect(format!("http://{addr}")).await {
+            return client;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    panic!("the server did not start");
+}
+
+fn start(program: Vec<u8>, max_instructions: u64) -> ExecuteRequest {
+    let start = Start { program, max_instructions, ..Start::default() };
+    ExecuteRequest { kind: Some(execute_request::Kind::Start(start)) }
+}
+
+fn input(bytes: &[u8]) -> ExecuteRequest {
+    ExecuteRequest { kind: Some(execute_request::Kind::Input(bytes.to_vec())) }
+}
+
+/// Sends `requests` and keeps the stream open until the call ends,
+/// returning the output and the `Finished` message.
+async fn execute(client: &mut ExecutorClient<Channel>, requests: Vec<ExecuteRequest>) -> Result<(Vec<u8>, Finished), tonic::Status> {
+    let (tx, rx) = mpsc::channel(16);
+    for request in requests {
+        tx.send(request).await.unwrap();
+    }
+    let mut responses = client.execute(ReceiverStream::new(rx)).await?.into_inner();
+    let mut output = Vec::new();
+    while let Some(response) = responses.message().await? {
+        match response.kind {
+            Some(execute_response::Kind::Output(bytes)) => output.extend(bytes),
+            Some(execute_response::Kind::Finished(finished)) => {
+                assert!(responses.message().await?.is_none(), "messages after finished");
+                drop(tx);
+                return Ok((output, finished));
+            }
+            None => panic!("empty response"),
+        }
+    }
+    panic!("the call ended without finished");
+}
+
+const ECHO: [Instruction; 5] = [Input { c: 1 }, Output { c: 1 }, Input { c: 1 }, Output { c: 1 }, Halt];
+
+#[tokio::test(flavor = "multi_thread")]
+async fn input_streams_in_and_output_streams_out() {
+    let mut client = server(Limits::default()).await;
+    let (output, finished) = execute(&mut client, vec![start(bytes(&ECHO), 0), input(b"h"), input(b"i")]).await.unwrap();
+    assert_eq!(output, b"hi");
+    assert_eq!(finished.reason(), Reason::Halted);
+    assert_eq!(finished.executed, 4);
+    assert!(finished.message.is_empty());
+
+    let close = ExecuteRequest { kind: Some(execute_request::Kind::CloseInput(true)) };
+    let eof = [Input { c: 1 }, Halt];
+    let (_, finished) = execute(&mut client, vec![start(bytes(&eof), 0), close]).await.unwrap();
+    assert_eq!(finished.reason(), Reason::Halted);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_can_only_tighten_the_limits() {
+    // Jumps to itself forever.
+    let spin = bytes(&[LoadProgram { b: 0, c: 0 }]);
+    let mut client = server(Limits { max_instructions: Some(500), ..Limits::default() }).await;
+    let (_, finished) = execute(&mut client, vec![start(spin.clone(), 100)]).await.unwrap();
+    assert_eq!((finished.reason(), finished.executed), (Reason::InstructionLimit, 100));
+    let (_, finished) = execute(&mut client, vec![start(spin, 100_000)]).await.unwrap();
+    assert_eq!((finished.reason(), finished.executed), (Reason::InstructionLimit, 500));
+    assert_eq!(finished.message, "stopped after 500 instructions");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn memory_and_time_limits_stop_the_program() {
+    let limits = Limits { max_memory_bytes: Some(1 << 16), timeout: Some(Duration::from_millis(200)), ..Limits::default() };
+    let mut client = server(limits).await;
+    let map = [Orthography { a: 1, value: 1 << 20 }, Map { b: 2, c: 1 }, Halt];
+    let (_, finished) = execute(&mut client, vec![start(bytes(&map), 0)]).await.unwrap();
+    assert_eq!((finished.reason(), finished.executed), (Reason::MemoryLimit, 1));
+    assert!(finished.peak_memory_bytes < 1 << 16);
+
+    // Waits for input the client never sends.
+    let (output, finished) = execute(&mut client, vec![start(bytes(&ECHO), 0), input(b"h")]).await.unwrap();
+    assert_eq!(output, b"h");
+    assert_eq!(finished.reason(), Reason::Timeout);
+    assert_eq!(finished.message, "timed out waiting for input");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn faults_and_bad_requests() {
+    let mut client = server(Limits::default()).await;
+    let divide = [Orthography { a: 1, value: 65 }, Output { c: 1 }, Div { a: 1, b: 1, c: 2 }, Halt];
+    let (output, finished) = execute(&mut client, vec![start(bytes(&divide), 0)]).await.unwrap();
+    assert_eq!(output, b"A");
+    assert_eq!((finished.reason(), finished.executed), (Reason::Fault, 2));
+    assert!(finished.message.starts_with("program faulted after 2 instructions: "), "{}", finished.message);
+
+    let refused = execute(&mut client, vec![input(b"x")]).await.unwrap_err();
+    assert_eq!(refused.code(), Code::InvalidArgument);
+    let refused = execute(&mut client, vec![start(vec![0; 5], 0)]).await.unwrap_err();
+    assert_eq!(refused.code(), Code::InvalidArgument);
+    assert_eq!(refused.message(), "program length is not a whole number of words");
+}
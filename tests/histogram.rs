@@ -0,0 +1,24 @@
+//! Executed-opcode counts: `cargo test --features opcode-histogram`.
+#![cfg(feature = "opcode-histogram")]
+
+use rum::histogram::{Class, Histogram};
+use rum::instruction::Instruction::*;
+
+#[test]
+fn counts_by_opcode_and_class() {
+    let mut histogram = Histogram::default();
+    for inst in [Orthography { a: 1, value: 65 }, Output { c: 1 }, Output { c: 1 }, Add { a: 1, b: 1, c: 1 }, Map { b: 2, c: 1 }, Halt] {
+        histogram.record(inst.into());
+    }
+    assert_eq!((histogram.count(10), histogram.count(13), histogram.total()), (2, 1, 6));
+    assert_eq!(Class::ALL.map(|class| histogram.class(class)), [2, 1, 1, 2, 0]);
+    let table = histogram.table();
+    assert!(table.lines().nth(1).unwrap().starts_with("out"), "{table}");
+    assert!(!table.contains("cmov"), "{table}");
+    let json = histogram.to_json();
+    assert_eq!(json.get("executed"), Some(&rum::json::Value::Number(6.0)));
+    assert_eq!(json.get("classes").and_then(|c| c.get("io")), Some(&rum::json::Value::Number(2.0)));
+    let csv = rum::report::csv(&histogram.tables());
+    assert!(csv.contains("# opcodes\nopcode,mnemonic,class,count,share\n0,cmov,arithmetic,0,0\n"), "{csv}");
+    assert!(csv.contains("\n10,out,io,2,0.3333333333333333\n"), "{csv}");
+}
@@ -0,0 +1,117 @@
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use rum::http::{self, run_limited, Limits};
+use rum::instruction::Instruction::{self, *};
+use rum::json::{self, Value};
+use rum::testing::{bytes, words};
+
+/// Jumps to itself forever.
+const SPIN: [Instruction; 1] = [LoadProgram { b: 0, c: 0 }];
+const ECHO: [Instruction; 5] = [Input { c: 1 }, Output { c: 1 }, Input { c: 1 }, Output { c: 1 }, Halt];
+
+/// The address of a server shared by the tests, limited to 100000
+/// instructions and 64-byte programs.
+fn server() -> &'static str {
+    static ADDR: OnceLock<String> = OnceLock::new();
+    ADDR.get_or_init(|| {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let limits = Limits { max_instructions: Some(100_000), max_program_bytes: 64, ..Limits::default() };
+        let serving = addr.clone();
+        std::thread::spawn(move || http::serve(serving, limits));
+        while TcpStream::connect(&addr).is_err() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        addr
+    })
+}
+
+/// Sends `head` (and `body`) and returns the response's status line and body.
+fn request(head: &str, body: &[u8]) -> (String, String) {
+    let mut stream = TcpStream::connect(server()).unwrap();
+    stream.write_all(head.as_bytes()).unwrap();
+    stream.write_all(body).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.lines().next().unwrap().to_string(), body.to_string())
+}
+
+fn run(query: &str, program: &[u8]) -> Value {
+    let (status, body) = request(&format!("POST /run{query} HTTP/1.1\r\nContent-Length: {}\r\n\r\n", program.len()), program);
+    assert_eq!(status, "HTTP/1.1 200 OK", "{body}");
+    json::parse(&body).unwrap()
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> &'a Value {
+    value.get(key).unwrap_or_else(|| panic!("no {key} in {value}"))
+}
+
+#[test]
+fn runs_stop_at_each_limit() {
+    let report = run_limited(words(&ECHO), b"hi", &Limits::default());
+    assert_eq!((report.status, report.output.as_slice(), report.executed), ("halted", &b"hi"[..], 4));
+    assert!(report.message.is_empty());
+
+    let limits = Limits { max_instructions: Some(1000), ..Limits::default() };
+    let report = run_limited(words(&SPIN), b"", &limits);
+    assert_eq!((report.status, report.executed), ("instruction_limit", 1000));
+    assert_eq!(report.message, "stopped after 1000 instructions");
+
+    let limits = Limits { timeout: Some(Duration::from_millis(50)), ..Limits::default() };
+    assert_eq!(run_limited(words(&SPIN), b"", &limits).status, "timeout");
+
+    let limits = Limits { max_memory_bytes: Some(1 << 16), ..Limits::default() };
+    let report = run_limited(words(&[Orthography { a: 1, value: 1 << 20 }, Map { b: 2, c: 1 }, Halt]), b"", &limits);
+    assert_eq!((report.status, report.executed), ("memory_limit", 1));
+    assert!(report.peak_memory_bytes < 1 << 16);
+
+    let report = run_limited(words(&[Orthography { a: 1, value: 300 }, Output { c: 1 }, Halt]), b"", &Limits::default());
+    assert_eq!((report.status, report.executed), ("fault", 1));
+    assert!(report.message.starts_with("program faulted after 1 instructions: "), "{}", report.message);
+}
+
+#[test]
+fn post_run_answers_with_a_json_report() {
+    let report = run("?input=h%69+", &bytes(&ECHO));
+    assert_eq!(field(&report, "status").as_str(), Some("halted"));
+    assert_eq!(field(&report, "output").as_str(), Some("hi"));
+    assert_eq!(field(&report, "executed").as_u64(), Some(4));
+    assert_eq!(field(&report, "message").as_str(), Some(""));
+
+    // The query may tighten the server's limits, not loosen them.
+    let report = run("?max_instructions=10", &bytes(&SPIN));
+    assert_eq!((field(&report, "status").as_str(), field(&report, "executed").as_u64()), (Some("instruction_limit"), Some(10)));
+    let report = run("?max_instructions=99999999", &bytes(&SPIN));
+    assert_eq!(field(&report, "executed").as_u64(), Some(100_000));
+
+    let report = run("", &bytes(&[Div { a: 1, b: 1, c: 2 }]));
+    assert_eq!(field(&report, "status").as_str(), Some("fault"));
+}
+
+#[test]
+fn bad_requests_get_http_errors() {
+    let error = |head: &str, body: &[u8]| {
+        let (status, body) = request(head, body);
+        (status, field(&json::parse(&body).unwrap(), "error").as_str().unwrap().to_string())
+    };
+    let halt = bytes(&[Halt]);
+    assert_eq!(error("GET /nowhere HTTP/1.1\r\n\r\n", b"").0, "HTTP/1.1 404 Not Found");
+    assert_eq!(error("GET /run HTTP/1.1\r\n\r\n", b"").0, "HTTP/1.1 405 Method Not Allowed");
+    assert_eq!(error("POST /run HTTP/1.1\r\n\r\n", b""), ("HTTP/1.1 411 Length Required".to_string(), "Content-Length is required".to_string()));
+    assert_eq!(error("POST /run HTTP/1.1\r\nContent-Length: 68\r\n\r\n", b""), ("HTTP/1.1 413 Payload Too Large".to_string(), "programs are limited to 64 bytes".to_string()));
+    // Refused from the headers alone: a body left unread would reset the connection.
+    assert_eq!(error("POST /run HTTP/1.1\r\nContent-Length: 5\r\n\r\n", b"").1, "program length is not a whole number of words");
+    assert_eq!(error("POST /run?color=red HTTP/1.1\r\nContent-Length: 4\r\n\r\n", &halt).1, "unknown parameter `color`");
+    assert_eq!(error("POST /run?input=%zz HTTP/1.1\r\nContent-Length: 4\r\n\r\n", &halt).1, "bad percent-encoding in `input`");
+    assert_eq!(error("POST /run?timeout_ms=soon HTTP/1.1\r\nContent-Length: 4\r\n\r\n", &halt).1, "timeout_ms needs a number");
+    // The body ends before its Content-Length.
+    assert_eq!(error("POST /run HTTP/1.1\r\nContent-Length: 8\r\n\r\n", &halt).0, "HTTP/1.1 400 Bad Request");
+
+    let (status, body) = request("GET /metrics HTTP/1.1\r\n\r\n", b"");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(body.contains("rum_"), "{body}");
+}
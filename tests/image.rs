@@ -0,0 +1,54 @@
+use rum::image::{is_image, Image};
+use rum::instruction::Instruction::*;
+use rum::snapshot::{decode, encode};
+use rum::state::UniversalMachine;
+
+fn strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+fn sample() -> Image {
+    let program = vec![u32::from(Orthography { a: 1, value: 72 }), u32::from(Output { c: 1 }), u32::from(Halt)];
+    let mut state = UniversalMachine::new();
+    state.mapped_memory.push(program.clone());
+    state.program_counter = 1;
+    Image {
+        program,
+        options: strings(&["--syscalls", "--max-instructions", "1000", "--", "key.txt"]),
+        input: b"guest\n".to_vec(),
+        snapshot: Some(encode(&state, None)),
+        description: "UMIX, logged in as guest".to_string(),
+    }
+}
+
+#[test]
+fn images_round_trip() {
+    let image = sample();
+    let decoded = Image::decode(&image.encode()).unwrap();
+    assert_eq!(decoded, image);
+    assert_eq!(decode(decoded.snapshot.as_ref().unwrap(), None).unwrap().program_counter, 1);
+
+    let bare = Image { program: vec![u32::from(Halt)], ..Image::default() };
+    assert_eq!(Image::decode(&bare.encode()).unwrap(), bare);
+    assert!(is_image("share/umix.rumimg"));
+    assert!(!is_image("umix.um"));
+}
+
+#[test]
+fn damaged_images_are_refused() {
+    let mut bytes = sample().encode();
+    assert_eq!(Image::decode(&bytes[..bytes.len() - 3]).unwrap_err(), "image is corrupt (truncated)");
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    assert_eq!(Image::decode(&bytes).unwrap_err(), "image is corrupt (checksum mismatch)");
+    assert_eq!(Image::decode(b"RUMSTATE and more besides, long enough for a header").unwrap_err(), "not a rum machine image");
+}
+
+#[test]
+fn command_line_options_follow_the_images() {
+    let image = sample();
+    let merged = image.arguments(&strings(&["--max-instructions", "50", "--", "extra"]));
+    assert_eq!(merged, strings(&["--syscalls", "--max-instructions", "1000", "--max-instructions", "50", "--", "key.txt", "extra"]));
+    let plain = Image { options: strings(&["--progress"]), ..Image::default() };
+    assert_eq!(plain.arguments(&[]), strings(&["--progress"]));
+}
@@ -0,0 +1,51 @@
+#![cfg(unix)]
+
+use rum::inspect::{query, table, Inspector, RECENT_OUTPUT};
+use rum::state::UniversalMachine;
+
+#[test]
+fn clients_see_the_latest_report() {
+    let path = std::env::temp_dir().join(format!("rum-inspect-test-{}.sock", std::process::id()));
+    let inspector = Inspector::serve_at(&path, "bin/test.um").unwrap();
+    let mut state = UniversalMachine::new();
+    state.mapped_memory.push(vec![0; 100]);
+    state.mapped_memory.push(vec![0; 28]);
+    state.program_counter = 42;
+    inspector.publish(65536, &state);
+    for &byte in b"hello" {
+        inspector.output(byte);
+    }
+
+    let run = query(&path).unwrap();
+    assert_eq!(run.get("pid").and_then(|v| v.as_u64()), Some(u64::from(std::process::id())));
+    assert_eq!(run.get("program").and_then(|v| v.as_str()), Some("bin/test.um"));
+    assert_eq!(run.get("pc").and_then(|v| v.as_u64()), Some(42));
+    assert_eq!(run.get("instructions").and_then(|v| v.as_u64()), Some(65536));
+    assert_eq!(run.get("mapped_bytes").and_then(|v| v.as_u64()), Some(state.mapped_bytes() as u64));
+    assert_eq!(run.get("segments").and_then(|v| v.as_u64()), Some(2));
+    assert_eq!(run.get("waiting").and_then(|v| v.as_bool()), Some(false));
+    assert_eq!(run.get("output").and_then(|v| v.as_str()), Some("hello"));
+    assert!(table(&[run]).lines().nth(1).unwrap().contains("bin/test.um"));
+
+    inspector.waiting(65540, &state);
+    let run = query(&path).unwrap();
+    assert_eq!(run.get("waiting").and_then(|v| v.as_bool()), Some(true));
+    assert_eq!(run.get("instructions").and_then(|v| v.as_u64()), Some(65540));
+
+    inspector.close();
+    assert!(!path.exists());
+    assert!(query(&path).is_err());
+}
+
+#[test]
+fn only_recent_output_is_kept() {
+    let path = std::env::temp_dir().join(format!("rum-inspect-output-{}.sock", std::process::id()));
+    let inspector = Inspector::serve_at(&path, "stdin").unwrap();
+    for i in 0..RECENT_OUTPUT + 10 {
+        inspector.output(b'a' + (i % 26) as u8);
+    }
+    let output = query(&path).unwrap().get("output").and_then(|v| v.as_str()).unwrap().to_string();
+    inspector.close();
+    assert_eq!(output.len(), RECENT_OUTPUT);
+    assert!(output.starts_with('k'));
+}
@@ -0,0 +1,97 @@
+use rum::embed::{Machine, Status, Stepper};
+use rum::error::MachineError;
+use rum::instruction::Instruction::*;
+use rum::interrupt::{self, Controller, Delivery, EVENTS, INPUT, SEGMENT, TIMER};
+use rum::testing::words;
+
+#[test]
+fn vectored_handler_runs_and_returns() {
+    let program = words(&[
+        // Spins at 0-1.
+        Orthography { a: 2, value: 0 },
+        LoadProgram { b: 0, c: 2 },
+        // The handler at 2 outputs '0' plus the events and returns.
+        Orthography { a: 3, value: SEGMENT },
+        Orthography { a: 4, value: EVENTS as u32 },
+        Load { a: 5, b: 3, c: 4 },
+        Orthography { a: 6, value: 48 },
+        Add { a: 5, b: 5, c: 6 },
+        Output { c: 5 },
+        Orthography { a: 4, value: interrupt::RETURN as u32 },
+        Store { a: 3, b: 4, c: 0 },
+    ]);
+    let mut controller = Controller::new(Machine::new(program), Delivery::Vector(2)).unwrap();
+    let raiser = controller.raiser();
+    let mut output = Vec::new();
+    controller.run(Some(5), &mut || None, &mut |byte| output.push(byte)).unwrap();
+    let interrupted = controller.machine.state.program_counter;
+    raiser.raise(TIMER);
+    controller.run(Some(1), &mut || None, &mut |byte| output.push(byte)).unwrap();
+    assert!(controller.handling());
+    // Raised during the handler, so it waits for the return.
+    raiser.raise(INPUT);
+    controller.run(Some(7), &mut || None, &mut |byte| output.push(byte)).unwrap();
+    assert!(!controller.handling());
+    assert_eq!(controller.machine.state.program_counter, interrupted);
+    assert_eq!((3..7).map(|r| controller.machine.state.register(r)).collect::<Vec<_>>(), [0; 4]);
+    controller.run(Some(8), &mut || None, &mut |byte| output.push(byte)).unwrap();
+    assert_eq!((output, controller.delivered), (b"12".to_vec(), 2));
+}
+
+#[test]
+fn flag_delivery_sets_the_events_word() {
+    // Polls the events word until it is nonzero, then outputs it and halts.
+    let program = words(&[
+        Orthography { a: 3, value: SEGMENT },
+        Orthography { a: 4, value: EVENTS as u32 },
+        Load { a: 5, b: 3, c: 4 },
+        Orthography { a: 6, value: 2 },
+        Orthography { a: 7, value: 7 },
+        CMov { a: 6, b: 7, c: 5 },
+        LoadProgram { b: 0, c: 6 },
+        Output { c: 5 },
+        Halt,
+    ]);
+    let mut controller = Controller::new(Machine::new(program), Delivery::Flag).unwrap();
+    let mut output = Vec::new();
+    assert_eq!(controller.run(Some(100), &mut || None, &mut |byte| output.push(byte)), Ok(Status::Running));
+    controller.raiser().raise(TIMER | INPUT);
+    assert_eq!(controller.run(Some(100), &mut || None, &mut |byte| output.push(byte)), Ok(Status::Halted));
+    assert_eq!(output, [3]);
+}
+
+#[test]
+fn input_arrives_through_a_reader_thread() {
+    let controller = Controller::new(Machine::new(vec![u32::from(Halt)]), Delivery::Flag).unwrap();
+    let mut input = interrupt::input_events(std::io::Cursor::new(b"hi".to_vec()), controller.raiser());
+    assert_eq!([input(), input(), input()], [Some(b'h'), Some(b'i'), None]);
+    assert_eq!(Delivery::parse("flag"), Ok(Delivery::Flag));
+    assert_eq!(Delivery::parse("4096"), Ok(Delivery::Vector(4096)));
+    assert!(Delivery::parse("soon").is_err());
+}
+
+#[test]
+fn virtual_timer_fires_at_fixed_instruction_counts() {
+    // Spins at 0-1.
+    let program = words(&[Orthography { a: 2, value: 0 }, LoadProgram { b: 0, c: 2 }]);
+    let mut controller = Controller::new(Machine::new(program), Delivery::Flag).unwrap();
+    // 10 instructions at 10ns each.
+    controller.virtual_timer(std::time::Duration::from_nanos(100));
+    controller.run(Some(10), &mut || None, &mut |_| {}).unwrap();
+    assert_eq!(controller.machine.state.mapped_memory[1][EVENTS], 0);
+    controller.run(Some(1), &mut || None, &mut |_| {}).unwrap();
+    assert_eq!((controller.machine.state.mapped_memory[1][EVENTS], controller.delivered), (TIMER, 1));
+    controller.run(Some(20), &mut || None, &mut |_| {}).unwrap();
+    assert_eq!(controller.delivered, 3);
+}
+
+#[test]
+fn the_control_segment_cannot_be_unmapped() {
+    let program = words(&[Orthography { a: 1, value: SEGMENT }, Unmap { c: 1 }, Halt]);
+    let mut controller = Controller::new(Machine::new(program), Delivery::Flag).unwrap();
+    controller.raiser().raise(TIMER);
+    assert_eq!(controller.run(None, &mut || None, &mut |_| {}), Err(MachineError::UnmappedSegment { pc: 1, segment: SEGMENT.into() }));
+    let mut mapped = Machine::new(vec![u32::from(Halt)]);
+    mapped.state.mapped_memory.push(Vec::new());
+    assert!(Controller::new(mapped, Delivery::Flag).is_err());
+}
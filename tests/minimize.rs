@@ -0,0 +1,49 @@
+use rum::instruction::Instruction::*;
+use rum::minimize::{minimize, run, Outcome, Target, FUEL};
+
+/// Echoes input, dividing by zero on reading `!`.
+fn crashes_on_bang() -> Vec<u32> {
+    [
+        Orthography { a: 5, value: 1 },
+        Orthography { a: 6, value: b'!' as u32 },
+        Input { c: 0 },
+        // r2 = r0 - '!' is 0 on `!`: then divide by it.
+        Nand { a: 3, b: 6, c: 6 },
+        Add { a: 3, b: 3, c: 5 },
+        Add { a: 2, b: 0, c: 3 },
+        Orthography { a: 4, value: 10 },
+        Orthography { a: 7, value: 12 },
+        CMov { a: 4, b: 7, c: 2 },
+        LoadProgram { b: 1, c: 4 },
+        Div { a: 0, b: 0, c: 2 },
+        Halt,
+        Output { c: 0 },
+        Orthography { a: 4, value: 2 },
+        LoadProgram { b: 1, c: 4 },
+    ]
+    .into_iter()
+    .map(u32::from)
+    .collect()
+}
+
+#[test]
+fn faults_shrink_to_the_byte_that_causes_them() {
+    let program = crashes_on_bang();
+    let input = b"hello, world! and more after it";
+    assert!(matches!(run(&program, input, FUEL).outcome, Outcome::Fault { pc: 10, .. }));
+    let minimized = minimize(&program, input, &Target::Fault, FUEL).unwrap();
+    // Without the `!` it faults too, but at the end of input, on Output: not the same fault.
+    assert!(matches!(run(&program, b"", FUEL).outcome, Outcome::Fault { pc: 12, .. }));
+    assert_eq!(minimized.input, b"!");
+    assert!(minimized.runs > 1);
+    let halts = vec![u32::from(Halt)];
+    assert!(minimize(&halts, b"fine", &Target::Fault, FUEL).unwrap_err().contains("doesn't fault"));
+}
+
+#[test]
+fn output_targets_keep_what_produces_the_text() {
+    let program = crashes_on_bang();
+    let minimized = minimize(&program, b"abcxyzdef", &Target::OutputContains(b"yz".to_vec()), FUEL).unwrap();
+    assert_eq!(minimized.input, b"yz");
+    assert_eq!(minimized.run.output, b"yz");
+}
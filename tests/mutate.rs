@@ -0,0 +1,22 @@
+//! `rum::mutate` on tests/golden/cat.um.
+
+use rum::mutate::{mutants_of, run, Case, Operator};
+
+const CAT: &[u8] = include_bytes!("golden/cat.um");
+
+#[test]
+fn add_has_opcode_and_register_mutants() {
+    // add r1, r2, r3
+    let mutants = mutants_of(0, 3 << 28 | 1 << 6 | 2 << 3 | 3);
+    assert_eq!(mutants.iter().filter(|m| m.operator == Operator::Opcode).count(), 6);
+    assert_eq!(mutants.iter().filter(|m| m.operator == Operator::Registers).count(), 3);
+}
+
+#[test]
+fn echoing_input_kills_most_mutants() {
+    let program: Vec<u32> = rum::load::words(CAT);
+    let cases = [Case { name: "echo".into(), input: b"hello\n".to_vec(), expected: b"hello\n".to_vec() }];
+    let report = run(&program, &cases, &mut |_, _| {}).unwrap();
+    assert_eq!(report.uncovered, 0);
+    assert!(report.killed > report.survivors.len(), "{report:?}");
+}
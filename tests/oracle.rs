@@ -0,0 +1,9 @@
+use rum::oracle::{check, VALUES};
+
+#[test]
+fn every_instruction_matches_the_spec_over_a_tiny_machine() {
+    match check(&VALUES) {
+        Ok(cases) => assert!(cases > 13 * 512 * 200),
+        Err(mismatch) => panic!("{mismatch}"),
+    }
+}
@@ -0,0 +1,28 @@
+use rum::instruction::Instruction::*;
+use rum::policy::{Guard, Policy};
+
+#[test]
+fn rules_deny_opcodes_or_limit_them() {
+    let mut policy = Policy::default();
+    policy.deny("input").unwrap();
+    policy.deny("map-over=2, out-over=5").unwrap();
+    policy.deny("out-over=9").unwrap();
+    assert_eq!(policy.quota(11), Some(0));
+    assert_eq!(policy.quota(8), Some(2));
+    // A later, looser rule doesn't lift an earlier one.
+    assert_eq!(policy.quota(10), Some(5));
+    assert_eq!(policy.quota(3), None);
+    assert!(policy.deny("jump").is_err());
+    assert!(policy.deny("map-over=lots").is_err());
+    assert!(policy.deny("op14").is_err());
+
+    let mut guard = Guard::new(policy);
+    let map = u32::from(Map { b: 1, c: 2 });
+    assert_eq!(guard.check(map), Ok(()));
+    assert_eq!(guard.check(map), Ok(()));
+    assert_eq!(guard.check(map), Err("policy violation: more than 2 `map` instructions".to_string()));
+    assert_eq!(guard.check(u32::from(Input { c: 1 })), Err("policy violation: `in` is denied".to_string()));
+    for _ in 0..1000 {
+        assert_eq!(guard.check(u32::from(Add { a: 1, b: 2, c: 3 })), Ok(()));
+    }
+}
@@ -0,0 +1,173 @@
+use rum::embed::Status;
+use rum::instruction::Instruction::*;
+use rum::profile::{self, report};
+use rum::testing::words;
+
+#[test]
+fn counts_each_address() {
+    // Counts r1 down from 3, running words 2 to 6 once per pass.
+    let program = words(&[
+        Orthography { a: 1, value: 3 },
+        Nand { a: 6, b: 0, c: 0 },
+        Add { a: 1, b: 1, c: 6 },
+        Orthography { a: 2, value: 2 },
+        Orthography { a: 3, value: 7 },
+        CMov { a: 3, b: 2, c: 1 },
+        LoadProgram { b: 0, c: 3 },
+        Halt,
+    ]);
+    let (profile, result) = profile::profile(program, None, &mut || None, &mut |_| {});
+    assert_eq!(result, Ok(Status::Halted));
+    assert_eq!(profile.executed, 18);
+    assert_eq!((0..8).map(|pc| profile.count(pc)).collect::<Vec<_>>(), [1, 1, 3, 3, 3, 3, 3, 1]);
+    assert_eq!(profile.hottest(2), [(2, 3), (3, 3)]);
+    assert_eq!(profile.words_executed(), 8);
+    assert!(report(&profile, 1).contains("add r1, r1, r6"));
+}
+
+#[test]
+fn stops_attributing_once_segment_zero_is_replaced() {
+    let program = words(&[
+        Orthography { a: 1, value: 1 },
+        Map { b: 2, c: 1 },
+        Orthography { a: 3, value: 6 },
+        Load { a: 4, b: 0, c: 3 },
+        Store { a: 2, b: 0, c: 4 },
+        LoadProgram { b: 2, c: 0 },
+        Halt,
+    ]);
+    let (profile, result) = profile::profile(program, None, &mut || None, &mut |_| {});
+    assert_eq!(result, Ok(Status::Halted));
+    assert_eq!((profile.executed, profile.replaced), (7, 1));
+    assert_eq!(profile.count(0), 1);
+    assert_eq!(profile.count(6), 0);
+}
+
+#[test]
+fn reports_faults_and_fuel() {
+    let (profile, result) = profile::profile(vec![0xe000_0000], None, &mut || None, &mut |_| {});
+    assert!(result.is_err());
+    assert_eq!(profile.executed, 1);
+    let looping = words(&[LoadProgram { b: 0, c: 0 }]);
+    let (profile, result) = profile::profile(looping, Some(100), &mut || None, &mut |_| {});
+    assert_eq!((result, profile.count(0)), (Ok(Status::Running), 100));
+}
+
+#[test]
+fn sampling_reads_the_pc_on_a_timer() {
+    // Loops on words 3 and 4 for as long as the fuel lasts.
+    let program = words(&[
+        Nand { a: 6, b: 0, c: 0 },
+        Orthography { a: 7, value: 3 },
+        Orthography { a: 1, value: 1 << 24 },
+        Add { a: 1, b: 1, c: 6 },
+        LoadProgram { b: 0, c: 7 },
+    ]);
+    let (profile, result) = profile::sample(program, Some(2_000_000), std::time::Duration::from_micros(20), &mut || None, &mut |_| {});
+    assert_eq!((result, profile.executed), (Ok(Status::Running), 2_000_000));
+    assert!(profile.total() > 0);
+    assert!(matches!(profile.hottest(1)[..], [(3 | 4, _)]), "{:?}", profile.hottest(5));
+    assert!(report(&profile, 5).contains("samples, one about every 20 µs"));
+}
+
+#[test]
+fn memory_by_allocation_site() {
+    let program = words(&[
+        Orthography { a: 1, value: 4 },
+        Map { b: 2, c: 1 },
+        Map { b: 3, c: 1 },
+        Unmap { c: 2 },
+        Map { b: 4, c: 1 },
+        Halt,
+    ]);
+    let recording = profile::Profile::new(&program).track_memory();
+    let (profile, result) = profile::profile_with(recording, program, None, &mut || None, &mut |_| {});
+    assert_eq!(result, Ok(Status::Halted));
+    let memory = profile.memory().unwrap();
+    let sites: Vec<_> = memory.sites().iter().map(|site| (site.pc, site.maps, site.reused, site.freed, site.mean_lifetime())).collect();
+    assert_eq!(sites, [(1, 1, 0, 1, Some(2)), (2, 1, 0, 0, None), (4, 1, 1, 0, None)]);
+    assert_eq!((memory.peak_bytes, memory.peak_at, memory.unmaps, memory.live_segments()), (32, 3, 1, 2));
+    assert!(memory.report(10).contains("3 maps from 3 sites, 1 unmaps; free list hit rate 33.3%"));
+}
+
+#[test]
+fn jump_edges_round_trip() {
+    // Jumps from 6 back to 3 until r1 counts down to 0, then on to 7.
+    let program = words(&[
+        Nand { a: 6, b: 0, c: 0 },
+        Orthography { a: 1, value: 3 },
+        Orthography { a: 2, value: 3 },
+        Add { a: 1, b: 1, c: 6 },
+        Orthography { a: 3, value: 7 },
+        CMov { a: 3, b: 2, c: 1 },
+        LoadProgram { b: 0, c: 3 },
+        Halt,
+    ]);
+    let (profile, _) = profile::profile_with(profile::Profile::new(&program).track_jumps(), program, Some(1000), &mut || None, &mut |_| {});
+    let jumps = profile.jumps().unwrap();
+    assert_eq!(jumps.edges(), [(6, 3, 2), (6, 7, 1)]);
+    assert_eq!(jumps.targets(6), [(3, 2), (7, 1)]);
+    let mut text = Vec::new();
+    jumps.write(&mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert_eq!(text, format!("{}\n6 3 2\n6 7 1\n", rum::jumps::HEADER));
+    assert_eq!(&rum::jumps::JumpProfile::read(&text).unwrap(), jumps);
+    assert!(rum::jumps::JumpProfile::read("6 3").is_err());
+}
+
+#[test]
+fn heatmap_page_embeds_the_counts() {
+    let program = words(&[Orthography { a: 1, value: 2 }, Output { c: 1 }, Halt]);
+    let (profile, _) = profile::profile(program, None, &mut || None, &mut |_| {});
+    let page = rum::heatmap::html(&profile, "<BORROW>.um");
+    assert!(page.contains("const counts = [1,1,1];"));
+    assert!(page.contains(r#"const code = ["loadval r1, 2","out r1","halt"];"#));
+    assert!(page.contains("<title>rum heatmap: &lt;BORROW&gt;.um</title>"));
+    assert!(!page.contains("SUMMARY") && !page.contains("TOTAL"));
+}
+
+#[test]
+fn report_as_json_and_csv() {
+    let program = words(&[Orthography { a: 1, value: 2 }, Map { b: 2, c: 1 }, Output { c: 1 }, Halt]);
+    let recording = profile::Profile::new(&program).track_memory();
+    let (profile, _) = profile::profile_with(recording, program, None, &mut || None, &mut |_| {});
+    let tables = profile::tables(&profile, Some(2));
+    let json = rum::report::json(&tables).to_string();
+    assert!(json.starts_with(r#"{"hot":[{"address":0,"count":1,"share":0.25,"cumulative_share":0.25,"instruction":"loadval r1, 2"},"#));
+    assert!(json.contains(r#""summary":[{"unit":"instructions","executed":4,"counted":4,"words":4,"words_executed":4,"replaced":0,"interval_us":null}]"#));
+    assert!(json.contains(r#""allocation_sites":[{"address":1,"maps":1,"reused":0,"words":2,"#));
+    let csv = rum::report::csv(&tables);
+    assert!(csv.starts_with("# hot\naddress,count,share,cumulative_share,instruction\n0,1,0.25,0.25,\"loadval r1, 2\"\n1,1,0.25,0.5,\"map r2, r1\"\n\n# summary\n"));
+    assert!(csv.contains("instructions,4,4,4,4,0,\n"));
+    assert_eq!(rum::report::Format::parse("csv"), Ok(rum::report::Format::Csv));
+    assert!(rum::report::Format::parse("xml").is_err());
+}
+
+#[test]
+fn stores_over_executed_code_are_logged() {
+    // Overwrites word 4, once executed, with the Halt at 7 and jumps back to it;
+    // the earlier Store over 7 is not a rewrite, as 7 has not run.
+    let program = words(&[
+        Orthography { a: 3, value: 4 },
+        Orthography { a: 5, value: 7 },
+        Load { a: 4, b: 0, c: 5 },
+        Store { a: 0, b: 5, c: 4 },
+        Orthography { a: 6, value: 65 },
+        Store { a: 0, b: 3, c: 4 },
+        LoadProgram { b: 0, c: 3 },
+        Halt,
+    ]);
+    let recording = profile::Profile::new(&program).track_self_modification();
+    let (profile, result) = profile::profile_with(recording, program, None, &mut || None, &mut |_| {});
+    assert_eq!(result, Ok(Status::Halted));
+    let rewrites = profile.self_modification().unwrap();
+    assert_eq!(rewrites.total(), 1);
+    let logged = rewrites.log()[0];
+    assert_eq!((logged.executed, logged.pc, logged.target, logged.new), (5, 5, 4, u32::from(Halt)));
+    let region = &rewrites.regions()[0];
+    assert_eq!((region.start, region.end, region.writes, region.reexecuted, region.first), (4, 5, 1, 1, 5));
+    let mut log = Vec::new();
+    rewrites.write(&mut log).unwrap();
+    assert_eq!(String::from_utf8(log).unwrap(), format!("{}\n5 5 4 dc000041 70000000\n", rum::selfmod::HEADER));
+    assert!(rewrites.report().contains("1 stores rewrote 1 executed words in 1 regions"));
+}
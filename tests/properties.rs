@@ -0,0 +1,27 @@
+#![cfg(feature = "proptest")]
+
+use proptest::prelude::*;
+use rum::embed::{Machine, Status};
+use rum::instruction::{strategy, Instruction};
+
+proptest! {
+    #[test]
+    fn instructions_round_trip(instruction in strategy::instruction()) {
+        prop_assert_eq!(Instruction::decode(instruction.encode()), instruction);
+    }
+
+    #[test]
+    fn words_round_trip(word in any::<u32>()) {
+        let decoded = Instruction::decode(word);
+        prop_assert_eq!(Instruction::decode(decoded.encode()), decoded);
+    }
+
+    #[test]
+    fn well_formed_programs_halt(program in strategy::well_formed(64), input in proptest::collection::vec(any::<u8>(), 0..8)) {
+        let fuel = program.len() as u64;
+        let mut machine = Machine::new(program);
+        let mut input = input.into_iter();
+        let status = machine.run(Some(fuel), &mut || input.next(), &mut |_| {});
+        prop_assert!(matches!(status, Ok(Status::Halted)), "{status:?}");
+    }
+}
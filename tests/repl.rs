@@ -0,0 +1,35 @@
+use rum::instruction::Instruction;
+use rum::repl::Repl;
+use rum::rumdis::describe;
+
+#[test]
+fn instructions_parse_as_the_disassembler_writes_them() {
+    for text in ["cmov r1, r2, r3", "load r1, r2[r3]", "store r4[r5], r6", "nand r7, r0, r0", "halt", "map r1, r2", "in r3", "loadprog r0, r7", "loadval r2, 33554431", ".word 0xe0000000"] {
+        let instruction: Instruction = text.parse().unwrap();
+        assert_eq!(describe(instruction.encode()), text);
+    }
+    assert_eq!("loadval r1, 0x41".parse(), Ok(Instruction::Orthography { a: 1, value: 65 }));
+    assert!("add r1, r2".parse::<Instruction>().is_err());
+    assert!("add r1, r2, r8".parse::<Instruction>().is_err());
+    assert!("loadval r1, 33554432".parse::<Instruction>().is_err());
+}
+
+#[test]
+fn repl_executes_typed_instructions_and_survives_faults() {
+    let session = "loadval r1, 72\nout r1\nfeed hi\nin r2\nloadval r3, 4\nmap r4, r3\nload r5, r4[r3]\nmem 1\nhalt\nadd r1, r1, r1\n";
+    let mut out = Vec::new();
+    let mut repl = Repl::default();
+    repl.run(session.as_bytes(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("output: \"H\""));
+    assert!(out.contains("r2=0x68"));
+    assert!(out.contains("segment 1: 4 words"));
+    assert!(out.contains("fault: "));
+    assert!(out.contains("1[3] = 0x00000000"));
+    assert!(out.contains("halted after 5 instructions"));
+    assert!(out.contains("`reset` to start again"));
+    // The faulting Load was rolled back; Halt was appended.
+    let state = repl.state();
+    assert_eq!(state.mapped_memory[0].len(), 6);
+    assert_eq!(state.register(5), 0);
+}
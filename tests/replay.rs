@@ -0,0 +1,118 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rum::instruction::Instruction::{self, *};
+use rum::replay::{Recorder, Replay, ReplayError, ReplayLog};
+use rum::rumdis::{run_with, MachineError, RunOptions, RunResult};
+use rum::snapshot::SnapshotError;
+use rum::state::UniversalMachine;
+use rum::testing::words;
+
+fn machine(program: &[Instruction]) -> UniversalMachine {
+    UniversalMachine::from_parts([0; 8], vec![words(program)], Vec::new(), 0, Vec::new())
+}
+
+/// A log of `program` reading `inputs`, ending after `end` instructions.
+fn record(program: &[Instruction], inputs: &[(u64, u32)], end: Option<u64>) -> Vec<u8> {
+    static LOGS: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!("rum-replay-{}-{}.rumreplay", std::process::id(), LOGS.fetch_add(1, Ordering::Relaxed)));
+    let mut recorder = Recorder::create(&path, &machine(program), None).unwrap();
+    for &(index, value) in inputs {
+        recorder.input(index, value).unwrap();
+    }
+    match end {
+        Some(count) => recorder.finish(count).unwrap(),
+        None => drop(recorder),
+    }
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    bytes
+}
+
+const ECHO: [Instruction; 5] = [Input { c: 1 }, Output { c: 1 }, Input { c: 1 }, Output { c: 1 }, Halt];
+
+#[test]
+fn a_recorded_run_replays_the_same_way() {
+    let path = std::env::temp_dir().join(format!("rum-replay-{}.rumreplay", std::process::id()));
+    let program = words(&[Input { c: 1 }, Input { c: 2 }, Add { a: 3, b: 1, c: 2 }, Halt]);
+    let mut state = UniversalMachine::new();
+    state.prime_input(b"ab");
+    let options = RunOptions { record: Some(path.clone()), ..RunOptions::default() };
+    assert_eq!(run_with(&mut state, program, &options), Ok(RunResult::Halted));
+
+    let log = ReplayLog::load(&path, None).unwrap();
+    assert_eq!(log.inputs, [(0, u32::from(b'a')), (1, u32::from(b'b'))]);
+    assert_eq!(log.end, Some(3));
+    let mut replay = Replay::new(&log);
+    replay.run_to(u64::MAX).unwrap();
+    assert!(replay.halted());
+    assert_eq!(replay.state().register(3), state.register(3));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn replays_stop_at_any_instruction_and_go_back() {
+    let log = ReplayLog::parse(&record(&ECHO, &[(0, 104), (2, 105)], Some(4)), None).unwrap();
+    let mut replay = Replay::new(&log);
+    replay.run_to(2).unwrap();
+    assert_eq!((replay.count(), replay.output.as_slice()), (2, &b"h"[..]));
+    replay.run_to(10).unwrap();
+    assert!(replay.halted());
+    assert_eq!(replay.output, b"hi");
+    replay.run_to(1).unwrap();
+    assert_eq!((replay.count(), replay.state().register(1), replay.output.len()), (1, 104, 0));
+}
+
+#[test]
+fn a_log_cut_short_replays_to_its_last_input() {
+    let bytes = record(&ECHO, &[(0, 104), (2, 105)], Some(4));
+    // Drop the end record and half of the second input.
+    let log = ReplayLog::parse(&bytes[..bytes.len() - 9 - 6], None).unwrap();
+    assert_eq!((log.inputs.as_slice(), log.end), (&[(0, 104)][..], None));
+    let mut replay = Replay::new(&log);
+    assert!(matches!(replay.run_to(10), Err(ReplayError::Diverged { index: 2 })));
+    assert_eq!(replay.output, b"h");
+}
+
+#[test]
+fn damaged_logs_are_refused() {
+    let bytes = record(&ECHO, &[(0, 104)], Some(4));
+    assert!(matches!(ReplayLog::parse(b"RUMSTATE", None), Err(ReplayError::Malformed("not a replay log"))));
+    assert!(matches!(ReplayLog::parse(&bytes[..12], None), Err(ReplayError::Malformed("truncated header"))));
+    assert!(matches!(ReplayLog::parse(&bytes[..40], None), Err(ReplayError::Malformed("truncated snapshot"))));
+    for cut in 0..bytes.len() {
+        let _ = ReplayLog::parse(&bytes[..cut], None);
+    }
+
+    let mut corrupt = bytes.clone();
+    corrupt[16 + 60] ^= 1;
+    assert!(matches!(ReplayLog::parse(&corrupt, None), Err(ReplayError::Snapshot(SnapshotError::ChecksumMismatch))));
+    let mut unknown = bytes;
+    let end = unknown.len() - 9;
+    unknown[end] = b'X';
+    assert!(matches!(ReplayLog::parse(&unknown, None), Err(ReplayError::Malformed("unknown record"))));
+}
+
+#[test]
+fn faults_in_a_replay_are_errors() {
+    let log = ReplayLog::parse(&record(&[Orthography { a: 1, value: 65 }], &[], None), None).unwrap();
+    let mut replay = Replay::new(&log);
+    assert!(matches!(replay.run_to(5), Err(ReplayError::Fault(MachineError::ProgramCounterOutOfBounds { pc: 1 }))));
+
+    let program = [Orthography { a: 1, value: 256 }, Output { c: 1 }, Halt];
+    let log = ReplayLog::parse(&record(&program, &[], None), None).unwrap();
+    let mut replay = Replay::new(&log);
+    assert!(matches!(replay.run_to(5), Err(ReplayError::Fault(MachineError::OutputOutOfRange { pc: 1, value: 256 }))));
+    assert!(replay.output.is_empty());
+}
+
+#[test]
+fn a_faulting_run_still_finishes_its_log() {
+    let path = std::env::temp_dir().join(format!("rum-replay-fault-{}.rumreplay", std::process::id()));
+    let program = words(&[Input { c: 1 }, Div { a: 2, b: 1, c: 3 }]);
+    let mut state = UniversalMachine::new();
+    state.prime_input(b"a");
+    let options = RunOptions { record: Some(path.clone()), ..RunOptions::default() };
+    assert_eq!(run_with(&mut state, program, &options), Err(MachineError::DivisionByZero { pc: 1 }));
+    assert_eq!(ReplayLog::load(&path, None).unwrap().end, Some(1));
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,43 @@
+use rum::requires::{arguments, check, command, parse, read, read_arguments, sidecar, Extension};
+
+#[test]
+fn declarations_parse_with_comments_and_report_bad_lines() {
+    let text = "# needs the host\nsyscalls\n\nfloat   # for physics\nplugin:framebuffer\n";
+    assert_eq!(parse(text), Ok(vec![Extension::Syscalls, Extension::Float, Extension::Plugin("framebuffer".to_string())]));
+    assert!(parse("tasks\nquantum\n").unwrap_err().starts_with("line 2: unknown extension `quantum`"));
+    assert_eq!(Extension::plugin("plugins/libframebuffer.so"), Extension::Plugin("framebuffer".to_string()));
+    assert_eq!(Extension::Plugin("fb".to_string()).to_string(), "plugin:fb");
+}
+
+#[test]
+fn programs_are_checked_against_their_sidecar() {
+    let dir = std::env::temp_dir().join(format!("rum-requires-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("game.um");
+    assert_eq!(sidecar(&program), dir.join("game.requires"));
+    assert_eq!(read(&program), Ok(Vec::new()));
+    assert_eq!(check(&program, &[]), Ok(()));
+
+    std::fs::write(sidecar(&program), "syscalls\nfloat\n").unwrap();
+    assert_eq!(check(&program, &[Extension::Float, Extension::Syscalls]), Ok(()));
+    let error = check(&program, &[Extension::Float]).unwrap_err();
+    assert!(error.contains("requires syscalls") && error.ends_with("run it with --syscalls"), "{error}");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn argument_declarations_imply_syscalls_and_shape_the_command() {
+    let text = "args INPUT [OUTPUT]   # files\nsyscalls\nplugin:fb\n";
+    assert_eq!(parse(text), Ok(vec![Extension::Syscalls, Extension::Plugin("fb".to_string())]));
+    assert_eq!(arguments(text), Some("INPUT [OUTPUT]".to_string()));
+    assert_eq!(arguments("args\n"), Some(String::new()));
+    assert_eq!(arguments("syscalls\n"), None);
+    assert!(parse("argsv\n").is_err());
+
+    let extensions = parse(text).unwrap();
+    assert_eq!(command("tool.um", &extensions, arguments(text).as_deref()), "rum run --syscalls --plugin libfb.so tool.um -- INPUT [OUTPUT]");
+    assert_eq!(command("tool.um", &[], Some("")), "rum run tool.um -- ARGS...");
+    assert_eq!(command("hello.um", &[], None), "rum run hello.um");
+
+    assert_eq!(read_arguments("no/such/dir/tool.um"), Ok(None));
+}
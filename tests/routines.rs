@@ -0,0 +1,71 @@
+use rum::instruction::Instruction::*;
+use rum::jumps::JumpProfile;
+use rum::profile::{by_routine, profile_with, Profile};
+use rum::routines::infer;
+use rum::symbols::{Routine, Symbols};
+
+/// Calls the routine at 7, which prints `A`, from 2 and 5, passing the
+/// return address in r1.
+fn program() -> Vec<u32> {
+    [
+        Orthography { a: 1, value: 3 },
+        Orthography { a: 2, value: 7 },
+        LoadProgram { b: 0, c: 2 },
+        Orthography { a: 1, value: 6 },
+        Orthography { a: 2, value: 7 },
+        LoadProgram { b: 0, c: 2 },
+        Halt,
+        Orthography { a: 3, value: 65 },
+        Output { c: 3 },
+        LoadProgram { b: 0, c: 1 },
+    ]
+    .into_iter()
+    .map(u32::from)
+    .collect()
+}
+
+fn routine(entry: usize, size: usize, name: &str, callers: &[usize]) -> Routine {
+    Routine { entry, size, name: name.to_string(), callers: callers.to_vec() }
+}
+
+#[test]
+fn calls_are_found_statically_and_in_jump_profiles() {
+    let program = program();
+    // Statically the routine returns to 3 or somewhere unknown, so the Halt after the second call looks unreachable.
+    let symbols = infer(&program, None);
+    assert_eq!(symbols.routines(), [routine(0, 6, "start", &[]), routine(7, 3, "fn_7", &[2, 5])]);
+
+    let (profile, _) = profile_with(Profile::new(&program).track_jumps(), program.clone(), None, &mut || None, &mut |_| {});
+    let symbols = infer(&program, profile.jumps());
+    assert_eq!(symbols.routines(), [routine(0, 7, "start", &[]), routine(7, 3, "fn_7", &[2, 5])]);
+    assert_eq!(symbols.lookup(8).map(|routine| routine.name.as_str()), Some("fn_7"));
+    assert_eq!(by_routine(&profile, &symbols), [(Some(&symbols.routines()[0]), 7), (Some(&symbols.routines()[1]), 6)]);
+}
+
+#[test]
+fn loops_are_not_calls() {
+    let mut jumps = JumpProfile::new();
+    // A call from 5 to 12 that returned every time, and a loop at 16..20 left once.
+    jumps.record(5, 12);
+    jumps.record(14, 6);
+    for _ in 0..99 {
+        jumps.record(20, 16);
+    }
+    jumps.record(18, 21);
+    let symbols = infer(&[0; 30], Some(&jumps));
+    assert_eq!(symbols.routines(), [routine(0, 12, "start", &[]), routine(12, 18, "fn_12", &[5])]);
+}
+
+#[test]
+fn symbol_files_round_trip_without_callers() {
+    let symbols = Symbols::new(vec![routine(40, 10, "print_string", &[3, 9]), routine(0, 40, "start", &[])]);
+    let mut text = Vec::new();
+    symbols.write(&mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert_eq!(text, "# rum symbols: routines of segment 0 (entry size name)\n0 40 start\n40 10 print_string    # called from 3 9\n");
+    let read = Symbols::read(&text).unwrap();
+    assert_eq!(read.routines(), [routine(0, 40, "start", &[]), routine(40, 10, "print_string", &[])]);
+    assert_eq!(read.lookup(49).map(|routine| routine.entry), Some(40));
+    assert_eq!(read.lookup(50), None);
+    assert!(Symbols::read("12 fn_12").is_err());
+}
@@ -0,0 +1,161 @@
+use rum::instruction::Instruction::{self, *};
+use rum::json::{self, Value};
+use rum::rpc::{self, Session};
+use rum::testing::words;
+
+/// `instructions` as the JSON array of words the server takes.
+fn program(instructions: &[Instruction]) -> String {
+    Value::from(words(instructions)).to_string()
+}
+
+/// Sends `method` with `params` and returns the response.
+fn call(session: &mut Session, method: &str, params: &str) -> Value {
+    let replies = session.handle_line(&format!(r#"{{"jsonrpc":"2.0","id":7,"method":"{method}","params":{params}}}"#));
+    let response = replies.last().unwrap().clone();
+    assert_eq!(response.get("id"), Some(&Value::Number(7.0)));
+    response
+}
+
+fn result(session: &mut Session, method: &str, params: &str) -> Value {
+    let response = call(session, method, params);
+    response.get("result").unwrap_or_else(|| panic!("{method} failed: {response}")).clone()
+}
+
+/// The code and message of an error response.
+fn error(session: &mut Session, method: &str, params: &str) -> (i64, String) {
+    let response = call(session, method, params);
+    let error = response.get("error").unwrap_or_else(|| panic!("{method} succeeded: {response}"));
+    let code = match error.get("code") {
+        Some(Value::Number(code)) => *code as i64,
+        other => panic!("bad code {other:?}"),
+    };
+    (code, error.get("message").and_then(Value::as_str).unwrap().to_string())
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> &'a Value {
+    value.get(key).unwrap_or_else(|| panic!("no {key} in {value}"))
+}
+
+const ECHO: [Instruction; 4] = [Input { c: 1 }, Output { c: 1 }, LoadProgram { b: 0, c: 0 }, Halt];
+
+#[test]
+fn machines_run_on_fed_input() {
+    let mut session = Session::new();
+    let created = result(&mut session, "create", &format!(r#"{{"program":{}}}"#, program(&ECHO)));
+    assert_eq!(field(&created, "machine").as_u64(), Some(1));
+
+    let stopped = result(&mut session, "run", r#"{"machine":1}"#);
+    assert_eq!(field(&stopped, "reason").as_str(), Some("input"));
+    assert_eq!(field(&result(&mut session, "getState", r#"{"machine":1}"#), "status").as_str(), Some("waiting"));
+
+    result(&mut session, "feed", r#"{"machine":1,"input":"hi"}"#);
+    let stopped = result(&mut session, "run", r#"{"machine":1}"#);
+    assert_eq!(field(&stopped, "reason").as_str(), Some("input"));
+    assert_eq!(field(&stopped, "output").as_str(), Some("hi"));
+    assert_eq!(field(&stopped, "executed").as_u64(), Some(6));
+
+    let stepped = result(&mut session, "step", r#"{"machine":1,"count":0}"#);
+    assert_eq!((field(&stepped, "reason").as_str(), field(&stepped, "pc").as_u64()), (Some("fuel"), Some(0)));
+    let registers = result(&mut session, "getRegisters", r#"{"machine":1}"#);
+    assert_eq!(registers.as_array().unwrap()[1].as_u64(), Some(u64::from(b'i')));
+    result(&mut session, "destroy", r#"{"machine":1}"#);
+    assert_eq!(error(&mut session, "getState", r#"{"machine":1}"#), (-32602, "no machine 1".to_string()));
+}
+
+#[test]
+fn breakpoints_watchpoints_and_slots() {
+    let mut session = Session::new();
+    let code = [Orthography { a: 1, value: 7 }, Orthography { a: 2, value: 3 }, Store { a: 0, b: 2, c: 1 }, Orthography { a: 1, value: 8 }, Halt];
+    result(&mut session, "create", &format!(r#"{{"program":{}}}"#, program(&code)));
+    result(&mut session, "setBreakpoint", r#"{"machine":1,"address":1}"#);
+    result(&mut session, "setWatchpoint", r#"{"machine":1,"segment":0,"offset":3}"#);
+    let listed = result(&mut session, "listBreakpoints", r#"{"machine":1}"#);
+    assert_eq!(field(&listed, "breakpoints").as_array().unwrap().len(), 1);
+    assert_eq!(field(&field(&listed, "watchpoints").as_array().unwrap()[0], "access").as_str(), Some("write"));
+
+    let stopped = result(&mut session, "run", r#"{"machine":1}"#);
+    assert_eq!((field(&stopped, "reason").as_str(), field(&stopped, "pc").as_u64()), (Some("breakpoint"), Some(1)));
+    result(&mut session, "saveState", r#"{"machine":1,"name":"before"}"#);
+
+    let stopped = result(&mut session, "run", r#"{"machine":1}"#);
+    assert_eq!(field(&stopped, "reason").as_str(), Some("watchpoint"));
+    let watch = field(&stopped, "watch");
+    assert_eq!((field(watch, "offset").as_u64(), field(watch, "new").as_u64()), (Some(3), Some(7)));
+    assert_eq!(result(&mut session, "readMemory", r#"{"machine":1,"segment":0,"offset":3,"count":1}"#).as_array().unwrap()[0].as_u64(), Some(7));
+
+    // Word 3 is now Conditional Move r0, r0, r7.
+    let stopped = result(&mut session, "run", r#"{"machine":1}"#);
+    assert_eq!((field(&stopped, "reason").as_str(), field(&stopped, "executed").as_u64()), (Some("halted"), Some(4)));
+    let restored = result(&mut session, "restoreState", r#"{"machine":1,"name":"before"}"#);
+    assert_eq!((field(&restored, "status").as_str(), field(&restored, "pc").as_u64()), (Some("running"), Some(1)));
+    assert_eq!(result(&mut session, "readMemory", r#"{"machine":1,"segment":0,"offset":3,"count":1}"#).as_array().unwrap()[0].as_u64(), Some(u64::from(u32::from(code[3]))));
+}
+
+#[test]
+fn faults_are_machine_errors_until_restored() {
+    let mut session = Session::new();
+    let code = [Orthography { a: 1, value: 7 }, Div { a: 1, b: 1, c: 2 }, Halt];
+    result(&mut session, "create", &format!(r#"{{"program":{}}}"#, program(&code)));
+    result(&mut session, "saveState", r#"{"machine":1,"name":"start"}"#);
+    let (code, message) = error(&mut session, "run", r#"{"machine":1}"#);
+    assert_eq!(code, -32000);
+    assert!(message.starts_with("program faulted after 1 instructions: "), "{message}");
+    assert_eq!(error(&mut session, "step", r#"{"machine":1}"#), (-32000, "the machine faulted earlier".to_string()));
+    assert_eq!(error(&mut session, "saveState", r#"{"machine":1,"name":"later"}"#).0, -32000);
+    assert_eq!(field(&result(&mut session, "getState", r#"{"machine":1}"#), "status").as_str(), Some("faulted"));
+
+    result(&mut session, "restoreState", r#"{"machine":1,"name":"start"}"#);
+    result(&mut session, "writeMemory", r#"{"machine":1,"segment":0,"offset":1,"words":[1879048192]}"#);
+    let stopped = result(&mut session, "run", r#"{"machine":1}"#);
+    assert_eq!(field(&stopped, "reason").as_str(), Some("halted"));
+}
+
+#[test]
+fn subscribed_output_arrives_as_notifications() {
+    let mut session = Session::new();
+    let code = [Orthography { a: 1, value: 79 }, Output { c: 1 }, Output { c: 1 }, Halt];
+    result(&mut session, "create", &format!(r#"{{"program":{}}}"#, program(&code)));
+    result(&mut session, "subscribe", r#"{"machine":1}"#);
+    let replies = session.handle_line(r#"{"jsonrpc":"2.0","id":1,"method":"run","params":{"machine":1}}"#);
+    assert_eq!(replies.len(), 2);
+    assert_eq!(field(&replies[0], "method").as_str(), Some("output"));
+    assert_eq!(field(field(&replies[0], "params"), "data").as_str(), Some("OO"));
+    let stopped = field(&replies[1], "result");
+    assert_eq!((field(stopped, "reason").as_str(), field(stopped, "output").as_str()), (Some("halted"), Some("")));
+}
+
+#[test]
+fn bad_requests_get_json_rpc_errors() {
+    let mut session = Session::new();
+    let parse_error = session.handle_line("{not json");
+    assert_eq!(field(&parse_error[0], "id"), &Value::Null);
+    assert_eq!(field(field(&parse_error[0], "error"), "code"), &Value::Number(-32700.0));
+    let missing = session.handle_line(r#"{"jsonrpc":"2.0","id":1}"#);
+    assert_eq!(field(field(&missing[0], "error"), "code"), &Value::Number(-32600.0));
+    assert_eq!(error(&mut session, "teleport", "{}"), (-32601, "unknown method `teleport`".to_string()));
+
+    assert_eq!(error(&mut session, "create", "{}").0, -32602);
+    assert_eq!(error(&mut session, "create", r#"{"program":[4294967296]}"#).0, -32602);
+    result(&mut session, "create", r#"{"program":[0]}"#);
+    assert_eq!(error(&mut session, "setRegister", r#"{"machine":1,"register":8,"value":0}"#), (-32602, "registers are numbered 0-7".to_string()));
+    assert_eq!(error(&mut session, "readMemory", r#"{"machine":1,"segment":0,"offset":0,"count":2}"#).1, "range is outside the segment");
+    assert_eq!(error(&mut session, "step", r#"{"machine":1,"count":-1}"#).1, "count must be a whole number");
+    assert_eq!(error(&mut session, "setWatchpoint", r#"{"machine":1,"segment":0,"offset":0,"access":"poke"}"#).1, "access must be read, write or access");
+    assert_eq!(error(&mut session, "restoreState", r#"{"machine":1,"name":"none"}"#).1, "no slot named none");
+
+    // Notifications get no response, even when they fail.
+    assert!(session.handle_line(r#"{"jsonrpc":"2.0","method":"destroy","params":{"machine":9}}"#).is_empty());
+}
+
+#[test]
+fn serve_answers_one_line_per_request() {
+    let requests = format!(
+        "{}\n\n{}\n",
+        r#"{"jsonrpc":"2.0","id":1,"method":"create","params":{"program":[1879048192]}}"#, r#"{"jsonrpc":"2.0","id":2,"method":"run","params":{"machine":1}}"#
+    );
+    let mut output = Vec::new();
+    rpc::serve(requests.as_bytes(), &mut output, None).unwrap();
+    let lines: Vec<Value> = String::from_utf8(output).unwrap().lines().map(|line| json::parse(line).unwrap()).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(field(field(&lines[1], "result"), "reason").as_str(), Some("halted"));
+}
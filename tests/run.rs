@@ -0,0 +1,99 @@
+use rum::instruction::Instruction::*;
+use rum::rumdis::{disassemble, run, run_with, MachineError, Opcode, RunOptions, RunResult, StepResult};
+use rum::state::UniversalMachine;
+use rum::testing::words;
+
+#[test]
+fn halting_returns_to_the_caller() {
+    let mut state = UniversalMachine::new();
+    let result = run(&mut state, words(&[Orthography { a: 1, value: 6 }, Orthography { a: 2, value: 7 }, Mul { a: 3, b: 1, c: 2 }, Halt])).unwrap();
+    assert_eq!(result, RunResult::Halted);
+    assert_eq!(result.exit_code(), 0);
+    assert_eq!(state.register(3), 42);
+    assert_eq!(state.program_counter, 4);
+
+    let mut state = UniversalMachine::new();
+    assert_eq!(disassemble(u32::from(Orthography { a: 1, value: 5 }), &mut state), Ok(StepResult::Continue));
+    assert_eq!(disassemble(u32::from(Halt), &mut state), Ok(StepResult::Halt));
+}
+
+#[test]
+fn the_instruction_limit_returns_too() {
+    // Jumps to itself forever.
+    let program = words(&[LoadProgram { b: 0, c: 0 }]);
+    let options = RunOptions { max_instructions: Some(1000), ..RunOptions::default() };
+    let result = run_with(&mut UniversalMachine::new(), program, &options).unwrap();
+    assert_eq!(result, RunResult::InstructionLimit);
+    assert_eq!(result.exit_code(), rum::rumdis::LIMIT_EXIT_CODE);
+}
+
+#[test]
+fn failures_come_back_with_their_pc() {
+    let mut state = UniversalMachine::new();
+    let result = run(&mut state, words(&[Orthography { a: 1, value: 1 }, Div { a: 2, b: 1, c: 0 }, Halt]));
+    assert_eq!(result, Err(MachineError::DivisionByZero { pc: 1 }));
+    assert_eq!(result.unwrap_err().to_string(), "division by zero at pc 1");
+
+    let result = run(&mut UniversalMachine::new(), vec![u32::from(Orthography { a: 1, value: 3 }), 0xe000_0000]);
+    assert_eq!(result, Err(MachineError::InvalidOpcode { pc: 1, instruction: 0xe000_0000 }));
+
+    // Segment 4 was never mapped.
+    let result = run(&mut UniversalMachine::new(), words(&[Orthography { a: 1, value: 4 }, Load { a: 2, b: 1, c: 0 }, Halt]));
+    assert_eq!(result.unwrap_err().pc(), 1);
+
+    // `disassemble` expects the program counter already past the instruction.
+    let mut state = UniversalMachine::new();
+    state.program_counter = 1;
+    assert_eq!(disassemble(u32::from(Unmap { c: 0 }), &mut state), Err(MachineError::UnmappedSegment { pc: 0, segment: 0 }));
+}
+
+#[test]
+fn unmapping_segment_zero_faults() {
+    let result = run(&mut UniversalMachine::new(), words(&[Unmap { c: 0 }, Halt]));
+    assert_eq!(result, Err(MachineError::UnmappedSegment { pc: 0, segment: 0 }));
+}
+
+#[test]
+fn unmapping_a_segment_twice_faults() {
+    // Map, Unmap, Unmap: a second Unmap would hand the segment out twice.
+    let program = words(&[Orthography { a: 1, value: 2 }, Map { b: 2, c: 1 }, Unmap { c: 2 }, Unmap { c: 2 }, Map { b: 3, c: 1 }, Map { b: 4, c: 1 }, Halt]);
+    let mut state = UniversalMachine::new();
+    assert_eq!(run(&mut state, program), Err(MachineError::UnmappedSegment { pc: 3, segment: 1 }));
+
+    // Once mapped again, the identifier can be unmapped again.
+    let program = words(&[Orthography { a: 1, value: 2 }, Map { b: 2, c: 1 }, Unmap { c: 2 }, Map { b: 3, c: 1 }, Unmap { c: 3 }, Map { b: 4, c: 1 }, Map { b: 5, c: 1 }, Halt]);
+    let mut state = UniversalMachine::new();
+    assert_eq!(run(&mut state, program), Ok(RunResult::Halted));
+    assert_ne!(state.register(4), state.register(5));
+}
+
+#[test]
+fn stepping_reports_each_instruction() {
+    let mut state = UniversalMachine::new();
+    state.mapped_memory.push(words(&[Orthography { a: 1, value: 6 }, CMov { a: 2, b: 1, c: 0 }, CMov { a: 3, b: 1, c: 1 }, Store { a: 0, b: 4, c: 1 }, Halt]));
+    let first = state.step().unwrap();
+    assert_eq!((first.pc, first.opcode, first.read.as_slice(), first.written, first.halted), (0, Opcode::LoadVal, &[][..], Some(1), false));
+    // Register 0 is zero, so nothing moves.
+    assert_eq!(state.step().unwrap().written, None);
+    let moved = state.step().unwrap();
+    assert_eq!((moved.read.as_slice(), moved.written), (&[1, 1][..], Some(3)));
+    assert_eq!(state.register(3), 6);
+    assert_eq!(state.step().unwrap().read, vec![0, 4, 1]);
+    assert_eq!(state.mapped_memory[0][0], 6);
+    let halt = state.step().unwrap();
+    assert_eq!((halt.pc, halt.opcode, halt.halted), (4, Opcode::Halt, true));
+    assert_eq!(state.step(), Err(MachineError::ProgramCounterOutOfBounds { pc: 5 }));
+
+    let mut state = UniversalMachine::new();
+    state.mapped_memory.push(words(&[Div { a: 1, b: 2, c: 3 }]));
+    assert_eq!(state.step(), Err(MachineError::DivisionByZero { pc: 0 }));
+}
+
+#[test]
+fn a_log_that_cannot_be_opened_is_an_error_not_a_panic() {
+    let options = RunOptions { event_log: Some("/nonexistent/dir/run.evl".into()), ..RunOptions::default() };
+    let result = run_with(&mut UniversalMachine::new(), words(&[Halt]), &options);
+    let Err(MachineError::Io { pc: 0, message }) = result else { panic!("{result:?}") };
+    assert!(message.starts_with("cannot write the event log to /nonexistent/dir/run.evl: "), "{message}");
+    assert!(!MachineError::Io { pc: 0, message: "x".into() }.to_string().contains("at pc"));
+}
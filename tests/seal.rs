@@ -0,0 +1,54 @@
+use sha2::{Digest, Sha256};
+
+use rum::seal::Key;
+use rum::snapshot::{decode, encode, SnapshotError, FLAG_ENCRYPTED};
+use rum::state::UniversalMachine;
+
+fn machine() -> UniversalMachine {
+    let mut state = UniversalMachine::new();
+    state.mapped_memory.push(vec![0x7000_0000; 64]);
+    state.mapped_memory.push(b"secret answer".iter().map(|&b| b as u32).collect());
+    state.load_value(3, 42);
+    state
+}
+
+#[test]
+fn sealed_snapshots_only_open_with_their_key() {
+    let key = Key::parse(&"0123456789abcdef".repeat(4)).unwrap();
+    assert!(Key::parse("0123").is_err());
+    assert_eq!(format!("{key:?}"), "Key(..)");
+
+    let sealed = encode(&machine(), Some(&key));
+    assert_eq!(u16::from_be_bytes([sealed[10], sealed[11]]) & FLAG_ENCRYPTED, FLAG_ENCRYPTED);
+    assert_eq!(decode(&sealed, Some(&key)).unwrap().register(3), 42);
+
+    // Changed bytes are refused even with the checksum made to match.
+    let mut tampered = sealed.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    let digest = Sha256::digest(&tampered[52..]);
+    tampered[20..52].copy_from_slice(&digest);
+    assert!(matches!(decode(&tampered, Some(&key)), Err(SnapshotError::WrongKey)));
+    // So is a sealed payload passed off as uncompressed.
+    let mut relabelled = sealed.clone();
+    relabelled[11] &= !1;
+    assert!(matches!(decode(&relabelled, Some(&key)), Err(SnapshotError::WrongKey)));
+
+    assert!(matches!(decode(&sealed, Some(&Key::new([7; 32]))), Err(SnapshotError::WrongKey)));
+    assert!(matches!(decode(&sealed, None), Err(SnapshotError::NeedsKey)));
+}
+
+#[test]
+fn unsealed_snapshots_are_refused_when_a_key_is_set() {
+    let key = Key::new([7; 32]);
+    let plain = encode(&machine(), None);
+    assert_eq!(decode(&plain, None).unwrap().register(3), 42);
+    assert!(matches!(decode(&plain, Some(&key)), Err(SnapshotError::NotSealed)));
+
+    // Clearing the encrypted flag of a sealed snapshot does not get it past the check either.
+    let mut forged = encode(&machine(), Some(&key));
+    forged[11] &= !(FLAG_ENCRYPTED as u8);
+    let digest = Sha256::digest(&forged[52..]);
+    forged[20..52].copy_from_slice(&digest);
+    assert!(matches!(decode(&forged, Some(&key)), Err(SnapshotError::NotSealed)));
+}
@@ -0,0 +1,61 @@
+use rum::segimage::{default_width, heat, intensity, render, Palette, Scale};
+
+/// The chunks of a PNG by type, checking the signature.
+fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    let mut chunks = Vec::new();
+    let mut at = 8;
+    while at < png.len() {
+        let len = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+        chunks.push((png[at + 4..at + 8].try_into().unwrap(), png[at + 8..at + 8 + len].to_vec()));
+        at += 12 + len;
+    }
+    chunks
+}
+
+/// The data of a zlib stream made of stored blocks.
+fn unstore(zlib: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut at = 2;
+    loop {
+        let last = zlib[at] & 1 == 1;
+        let len = u16::from_le_bytes([zlib[at + 1], zlib[at + 2]]) as usize;
+        data.extend(&zlib[at + 5..at + 5 + len]);
+        at += 5 + len;
+        if last {
+            return data;
+        }
+    }
+}
+
+#[test]
+fn words_become_rows_of_pixels() {
+    let words = [0, 1, 0xff, 0xffff_ffff, 0x8000_0000];
+    let png = render(&words, 2, Palette::Gray, Scale::Log).unwrap();
+    let parts = chunks(&png);
+    let kinds: Vec<&[u8; 4]> = parts.iter().map(|(kind, _)| kind).collect();
+    assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+    assert_eq!(parts[0].1, [0, 0, 0, 2, 0, 0, 0, 3, 8, 0, 0, 0, 0]);
+    // Three rows of a filter byte and two pixels, the last one padded with black.
+    assert_eq!(unstore(&parts[1].1), [0, 0, 7, 0, 63, 255, 0, 255, 0]);
+
+    let png = render(&words[..1], 1, Palette::Heat, Scale::Linear).unwrap();
+    assert_eq!(chunks(&png)[0].1[9], 2);
+    assert!(render(&[], 4, Palette::Gray, Scale::Log).is_err());
+    assert!(render(&words, 0, Palette::Gray, Scale::Log).is_err());
+}
+
+#[test]
+fn intensities_and_palettes() {
+    assert_eq!(intensity(0, Scale::Log), 0);
+    assert_eq!(intensity(!0, Scale::Log), 255);
+    assert_eq!(intensity(0x1234_5678, Scale::Linear), 0x12);
+    assert_eq!(heat(0), [0, 0, 0]);
+    assert_eq!(heat(85), [255, 0, 0]);
+    assert_eq!(heat(170), [255, 255, 0]);
+    assert_eq!(heat(255), [255, 255, 255]);
+    assert_eq!(default_width(10_000), 100);
+    assert_eq!(default_width(10_001), 101);
+    assert_eq!(default_width(0), 1);
+    assert!(Palette::parse("rainbow").is_err());
+}
@@ -0,0 +1,151 @@
+//! Interpreter semantics pinned by small programs and their final state (`um_test!`).
+
+use rum::instruction::Instruction::*;
+use rum::um_test;
+
+const MAX: u32 = (1 << 25) - 1;
+
+um_test!(registers_start_at_zero,
+    program: [Halt],
+    registers: { 0 => 0, 1 => 0, 2 => 0, 3 => 0, 4 => 0, 5 => 0, 6 => 0, 7 => 0 },
+    output: b"",
+    segments: 1,
+);
+
+um_test!(load_value_takes_25_bits,
+    program: [Orthography { a: 7, value: MAX }, Halt],
+    registers: { 7 => MAX },
+);
+
+um_test!(conditional_move_on_nonzero,
+    program: [Orthography { a: 1, value: 5 }, Orthography { a: 2, value: 1 }, CMov { a: 3, b: 1, c: 2 }, Halt],
+    registers: { 3 => 5 },
+);
+
+um_test!(conditional_move_on_zero_does_nothing,
+    program: [Orthography { a: 1, value: 5 }, Orthography { a: 3, value: 9 }, CMov { a: 3, b: 1, c: 0 }, Halt],
+    registers: { 3 => 9 },
+);
+
+um_test!(add_wraps,
+    program: [Nand { a: 1, b: 0, c: 0 }, Orthography { a: 2, value: 2 }, Add { a: 3, b: 1, c: 2 }, Halt],
+    registers: { 3 => 1 },
+);
+
+um_test!(multiply_wraps,
+    program: [Orthography { a: 1, value: 1 << 20 }, Mul { a: 2, b: 1, c: 1 }, Orthography { a: 3, value: 3 }, Mul { a: 4, b: 3, c: 3 }, Halt],
+    registers: { 2 => 0, 4 => 9 },
+);
+
+um_test!(division_truncates,
+    program: [Orthography { a: 1, value: 7 }, Orthography { a: 2, value: 2 }, Div { a: 3, b: 1, c: 2 }, Halt],
+    registers: { 3 => 3 },
+);
+
+um_test!(division_is_unsigned,
+    program: [Nand { a: 1, b: 0, c: 0 }, Orthography { a: 2, value: 2 }, Div { a: 3, b: 1, c: 2 }, Halt],
+    registers: { 3 => u32::MAX / 2 },
+);
+
+um_test!(nand_of_zero_is_all_ones,
+    program: [Nand { a: 1, b: 0, c: 0 }, Halt],
+    registers: { 1 => u32::MAX },
+);
+
+um_test!(nand_twice_is_and,
+    program: [Orthography { a: 1, value: 0b1100 }, Orthography { a: 2, value: 0b1010 }, Nand { a: 3, b: 1, c: 2 }, Nand { a: 3, b: 3, c: 3 }, Halt],
+    registers: { 3 => 0b1000 },
+);
+
+um_test!(output_writes_one_byte,
+    program: [Orthography { a: 1, value: b'U' as u32 }, Output { c: 1 }, Output { c: 1 }, Halt],
+    output: b"UU",
+);
+
+um_test!(output_writes_bytes_above_127_raw,
+    program: [Orthography { a: 1, value: 0xff }, Output { c: 1 }, Halt],
+    output: &[0xff],
+);
+
+um_test!(input_reads_one_byte,
+    program: [Input { c: 1 }, Input { c: 2 }, Halt],
+    input: b"ab",
+    registers: { 1 => b'a' as u32, 2 => b'b' as u32 },
+);
+
+um_test!(end_of_input_is_all_ones,
+    program: [Input { c: 1 }, Halt],
+    registers: { 1 => u32::MAX },
+);
+
+um_test!(map_gives_a_zeroed_segment,
+    program: [Orthography { a: 1, value: 4 }, Map { b: 2, c: 1 }, Orthography { a: 3, value: 3 }, Load { a: 4, b: 2, c: 3 }, Halt],
+    registers: { 2 => 1, 4 => 0 },
+    segments: 2,
+);
+
+um_test!(store_then_load,
+    program: [
+        Orthography { a: 1, value: 2 },
+        Map { b: 2, c: 1 },
+        Orthography { a: 3, value: 1 },
+        Orthography { a: 4, value: 42 },
+        Store { a: 2, b: 3, c: 4 },
+        Load { a: 5, b: 2, c: 3 },
+        Halt,
+    ],
+    registers: { 5 => 42 },
+);
+
+um_test!(unmap_frees_the_segment,
+    program: [Orthography { a: 1, value: 1 }, Map { b: 2, c: 1 }, Unmap { c: 2 }, Halt],
+    segments: 1,
+);
+
+um_test!(unmapped_identifiers_are_reused,
+    program: [Orthography { a: 1, value: 1 }, Map { b: 2, c: 1 }, Unmap { c: 2 }, Map { b: 3, c: 1 }, Halt],
+    registers: { 2 => 1, 3 => 1 },
+    segments: 2,
+);
+
+um_test!(mapping_zero_words_is_allowed,
+    program: [Map { b: 2, c: 0 }, Halt],
+    registers: { 2 => 1 },
+    segments: 2,
+);
+
+um_test!(load_from_segment_zero_reads_the_program,
+    program: [Load { a: 1, b: 0, c: 0 }, Halt],
+    registers: { 1 => u32::from(Load { a: 1, b: 0, c: 0 }) },
+);
+
+um_test!(load_program_from_zero_jumps,
+    program: [Orthography { a: 1, value: 3 }, LoadProgram { b: 0, c: 1 }, Orthography { a: 2, value: 1 }, Halt],
+    registers: { 2 => 0 },
+);
+
+um_test!(load_program_replaces_segment_zero,
+    program: [
+        // Copy the Halt at word 6 into a new one-word segment and run it.
+        Orthography { a: 1, value: 1 },
+        Map { b: 2, c: 1 },
+        Orthography { a: 3, value: 6 },
+        Load { a: 4, b: 0, c: 3 },
+        Store { a: 2, b: 0, c: 4 },
+        LoadProgram { b: 2, c: 0 },
+        Halt,
+    ],
+    registers: { 2 => 1 },
+    segments: 2,
+);
+
+um_test!(stores_into_segment_zero_change_the_program,
+    program: [
+        // Overwrite word 2 with 0, a Conditional Move that does nothing, before reaching it.
+        Orthography { a: 1, value: 2 },
+        Store { a: 0, b: 1, c: 0 },
+        Orthography { a: 5, value: 1 },
+        Halt,
+    ],
+    registers: { 5 => 0 },
+);
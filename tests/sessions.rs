@@ -0,0 +1,129 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use rum::instruction::Instruction::*;
+use rum::json::{self, Value};
+use rum::rpc;
+use rum::sessions::{Limits, Manager};
+use rum::snapshot::load_snapshot;
+
+/// A server for `limits` on a free port, with its address and accept thread.
+fn server(limits: Limits) -> (Manager, String, JoinHandle<std::io::Result<()>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let manager = Manager::new(limits);
+    let serving = manager.clone();
+    let thread = std::thread::spawn(move || rpc::accept(listener, &serving));
+    (manager, addr, thread)
+}
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn connect(addr: &str) -> Client {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        Client { reader: BufReader::new(stream.try_clone().unwrap()), writer: stream }
+    }
+
+    fn send(&mut self, request: &str) {
+        writeln!(self.writer, "{request}").unwrap();
+    }
+
+    /// The next line from the server, `None` once it has closed the connection.
+    fn receive(&mut self) -> Option<Value> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line).unwrap() {
+            0 => None,
+            _ => Some(json::parse(&line).unwrap()),
+        }
+    }
+
+    fn call(&mut self, request: &str) -> Value {
+        self.send(request);
+        self.receive().unwrap()
+    }
+}
+
+fn param<'a>(message: &'a Value, key: &str) -> &'a Value {
+    message.get("params").and_then(|params| params.get(key)).unwrap()
+}
+
+#[test]
+fn connections_beyond_the_limit_are_turned_away() {
+    let (manager, addr, _) = server(Limits { max_sessions: Some(1), ..Limits::default() });
+    let mut first = Client::connect(&addr);
+    let created = first.call(r#"{"jsonrpc":"2.0","id":1,"method":"create","params":{"program":[0]}}"#);
+    assert!(created.get("result").is_some());
+
+    let mut second = Client::connect(&addr);
+    let refused = second.receive().unwrap();
+    let error = refused.get("error").unwrap();
+    assert_eq!(error.get("code"), Some(&Value::Number(-32001.0)));
+    assert_eq!(error.get("message").and_then(Value::as_str), Some("server busy: 1 sessions open"));
+    assert!(second.receive().is_none());
+
+    drop(first);
+    while manager.open() > 0 {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let mut third = Client::connect(&addr);
+    let created = third.call(r#"{"jsonrpc":"2.0","id":1,"method":"create","params":{"program":[0]}}"#);
+    assert!(created.get("result").is_some());
+}
+
+#[test]
+fn idle_sessions_are_closed() {
+    let (_, addr, _) = server(Limits { idle_timeout: Some(Duration::from_millis(200)), ..Limits::default() });
+    let mut client = Client::connect(&addr);
+    client.call(r#"{"jsonrpc":"2.0","id":1,"method":"create","params":{"program":[0]}}"#);
+    let closing = client.receive().unwrap();
+    assert_eq!(closing.get("method").and_then(Value::as_str), Some("closing"));
+    assert_eq!(param(&closing, "reason").as_str(), Some("idle"));
+    assert_eq!(param(&closing, "snapshots").as_array().map(<[Value]>::len), Some(0));
+    assert!(client.receive().is_none());
+}
+
+#[test]
+fn shutdown_interrupts_and_saves_running_machines() {
+    let dir = std::env::temp_dir().join(format!("rum-sessions-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (manager, addr, thread) = server(Limits { snapshot_dir: Some(dir.clone()), ..Limits::default() });
+    // Jumps to 0 forever, as r0 and r1 are 0.
+    let spin = u32::from(LoadProgram { b: 0, c: 1 });
+    let halt = u32::from(Halt);
+    let mut client = Client::connect(&addr);
+    client.call(&format!(r#"{{"jsonrpc":"2.0","id":1,"method":"create","params":{{"program":[{spin}]}}}}"#));
+    client.call(&format!(r#"{{"jsonrpc":"2.0","id":2,"method":"create","params":{{"program":[{halt}]}}}}"#));
+    client.call(r#"{"jsonrpc":"2.0","id":3,"method":"run","params":{"machine":2}}"#);
+    client.send(r#"{"jsonrpc":"2.0","id":4,"method":"run","params":{"machine":1}}"#);
+    std::thread::sleep(Duration::from_millis(100));
+
+    manager.shutdown();
+    let stopped = client.receive().unwrap();
+    let result = stopped.get("result").unwrap();
+    assert_eq!(result.get("reason").and_then(Value::as_str), Some("shutdown"));
+    assert!(result.get("executed").and_then(Value::as_u64).unwrap() > 0);
+
+    let closing = client.receive().unwrap();
+    assert_eq!(param(&closing, "reason").as_str(), Some("shutdown"));
+    // The halted machine is not saved.
+    let snapshots = param(&closing, "snapshots").as_array().unwrap();
+    assert_eq!(snapshots.len(), 1);
+    assert_eq!(snapshots[0].get("machine").and_then(Value::as_u64), Some(1));
+    let path = snapshots[0].get("path").and_then(Value::as_str).unwrap();
+    assert!(path.ends_with("machine-1.rumstate"));
+    let state = load_snapshot(path, None).unwrap();
+    assert_eq!(state.mapped_memory[0], vec![spin]);
+    assert!(client.receive().is_none());
+
+    thread.join().unwrap().unwrap();
+    assert_eq!(manager.open(), 0);
+    assert!(manager.admit().is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,119 @@
+use sha2::{Digest, Sha256};
+
+use rum::snapshot::{decode, encode, encode_with, load_snapshot, save_snapshot, SnapshotError, MAGIC, VERSION};
+use rum::state::UniversalMachine;
+
+/// A machine with every part a snapshot carries: registers, PC, a mapped
+/// segment, a free one and primed input.
+fn machine() -> UniversalMachine {
+    let program = vec![0x7000_0000; 16];
+    let data = (0..100).map(|i| i * 0x0101_0101).collect();
+    let mut state = UniversalMachine::from_parts([1, 2, 3, 4, 5, 6, 7, 0xffff_ffff], vec![program, data, Vec::new()], vec![2], 9, b"rest".to_vec());
+    state.load_value(4, 0x1ff_ffff);
+    state
+}
+
+/// A version 2 snapshot around `payload`, with a checksum that matches it.
+fn forge(flags: u16, payload: &[u8]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.extend(VERSION.to_be_bytes());
+    out.extend(flags.to_be_bytes());
+    out.extend((payload.len() as u64).to_be_bytes());
+    out.extend(Sha256::digest(payload));
+    out.extend(payload);
+    out
+}
+
+fn section(tag: u8, body: &[u8]) -> Vec<u8> {
+    [&[tag][..], &(body.len() as u64).to_be_bytes(), body].concat()
+}
+
+fn words(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+#[test]
+fn snapshots_round_trip() {
+    let state = machine();
+    for bytes in [encode(&state, None), encode_with(&state, None, None)] {
+        let loaded = decode(&bytes, None).unwrap();
+        assert_eq!(loaded, state);
+        assert_eq!(loaded.free_segments(), [2]);
+        assert_eq!(loaded.primed_input(), b"rest");
+        assert_eq!((loaded.program_counter, loaded.register(7)), (9, 0xffff_ffff));
+    }
+
+    let path = std::env::temp_dir().join(format!("rum-snapshot-{}.rumstate", std::process::id()));
+    save_snapshot(&state, &path, None).unwrap();
+    assert_eq!(load_snapshot(&path, None).unwrap(), state);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn truncated_snapshots_are_refused() {
+    for bytes in [encode(&machine(), None), encode_with(&machine(), None, None)] {
+        for cut in 0..bytes.len() {
+            assert!(decode(&bytes[..cut], None).is_err(), "cut at {cut} of {}", bytes.len());
+        }
+    }
+}
+
+#[test]
+fn corrupt_snapshots_are_refused() {
+    let bytes = encode_with(&machine(), None, None);
+    for at in 52..bytes.len() {
+        let mut corrupt = bytes.clone();
+        corrupt[at] ^= 0x40;
+        assert!(matches!(decode(&corrupt, None), Err(SnapshotError::ChecksumMismatch)), "byte {at}");
+    }
+    let mut corrupt = bytes.clone();
+    corrupt[0] = b'X';
+    assert!(matches!(decode(&corrupt, None), Err(SnapshotError::NotASnapshot)));
+    let mut corrupt = bytes.clone();
+    corrupt[9] = 9;
+    assert!(matches!(decode(&corrupt, None), Err(SnapshotError::UnsupportedVersion(9))));
+    let mut corrupt = bytes;
+    corrupt[10] = 0x80;
+    assert!(matches!(decode(&corrupt, None), Err(SnapshotError::UnsupportedFlags(0x8000))));
+}
+
+#[test]
+fn counts_are_checked_before_allocating() {
+    let registers = section(b'M', &[0; 40]);
+    // Four billion segments claimed, none carried.
+    let payload = [registers.clone(), section(b'S', &words(&[u32::MAX, 0]))].concat();
+    assert!(matches!(decode(&forge(0, &payload), None), Err(SnapshotError::Malformed("segment count"))));
+    // A segment longer than the bytes left.
+    let payload = [registers.clone(), section(b'S', &words(&[1, 1, 0, u32::MAX]))].concat();
+    assert!(matches!(decode(&forge(0, &payload), None), Err(SnapshotError::Malformed("truncated"))));
+    // Segment 2 on the free list twice would be handed out twice.
+    let memory = section(b'S', &words(&[3, 3, 0, 0, 1, 0, 2, 0]));
+    let payload = [registers.clone(), memory.clone(), section(b'F', &words(&[2, 2]))].concat();
+    assert!(matches!(decode(&forge(0, &payload), None), Err(SnapshotError::Malformed("free list names an invalid segment"))));
+    let payload = [registers, memory, section(b'F', &words(&[2]))].concat();
+    assert_eq!(decode(&forge(0, &payload), None).unwrap().free_segments(), [2]);
+}
+
+#[test]
+fn compressed_and_plain_snapshots_load_alike() {
+    let mut state = machine();
+    state.load_value(1, 0);
+    state.map_seg(2, 1);
+    state.load_value(3, 1 << 20);
+    state.map_seg(4, 3);
+    let compressed = encode(&state, None);
+    let plain = encode_with(&state, None, None);
+    assert_eq!(u16::from_be_bytes([compressed[10], compressed[11]]) & 1, 1);
+    assert_eq!(u16::from_be_bytes([plain[10], plain[11]]) & 1, 0);
+    assert!(compressed.len() * 100 < plain.len(), "{} against {}", compressed.len(), plain.len());
+    assert_eq!(decode(&compressed, None).unwrap(), decode(&plain, None).unwrap());
+    assert_eq!(decode(&encode_with(&state, Some(19), None), None).unwrap(), state);
+}
+
+#[test]
+fn a_bad_compressed_payload_is_malformed() {
+    let bytes = encode(&machine(), None);
+    let payload = &bytes[52..bytes.len() - 4];
+    assert!(matches!(decode(&forge(1, payload), None), Err(SnapshotError::Malformed("bad compressed payload"))));
+    assert!(matches!(decode(&forge(1, b"not zstd at all"), None), Err(SnapshotError::Malformed("bad compressed payload"))));
+}
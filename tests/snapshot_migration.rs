@@ -0,0 +1,74 @@
+//! Snapshots written by older versions of the crate must keep loading.
+//! The fixtures were saved with format version 1 and are never regenerated.
+
+use rum::replay::{Replay, ReplayLog};
+use rum::snapshot::{self, load_chain, load_snapshot, VERSION};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+const BIN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/bin");
+
+fn fixture(name: &str) -> String {
+    format!("{FIXTURES}/{name}")
+}
+
+fn version_of(path: &str) -> u16 {
+    let bytes = std::fs::read(path).unwrap();
+    u16::from_be_bytes([bytes[8], bytes[9]])
+}
+
+#[test]
+fn fixtures_are_version_1() {
+    for name in [
+        "v1-hello-start.rumstate",
+        "v1-hello-step10.rumstate",
+        "v1-midmark-100000.rumstate",
+        "v1-midmark-100000-raw.rumstate",
+        "v1-midmark-200000-delta.rumstate",
+    ] {
+        assert_eq!(version_of(&fixture(name)), 1, "{name}");
+    }
+}
+
+#[test]
+fn v1_program_loads() {
+    let state = load_snapshot(fixture("v1-hello-start.rumstate"), None).unwrap();
+    let program = rum::load::load(Some(&format!("{BIN}/hello.um"))).unwrap();
+    assert_eq!(state.program_counter, 0);
+    assert_eq!(state.mapped_memory, vec![program]);
+    assert!(state.free_segments().is_empty());
+}
+
+#[test]
+fn v1_registers_and_pc_load() {
+    let state = load_snapshot(fixture("v1-hello-step10.rumstate"), None).unwrap();
+    assert_eq!(state.program_counter, 10);
+    let registers: Vec<u32> = (0..8).map(|r| state.register(r)).collect();
+    assert_eq!(registers, [0, 111, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn v1_compressed_and_raw_agree() {
+    let compressed = load_snapshot(fixture("v1-midmark-100000.rumstate"), None).unwrap();
+    let raw = load_snapshot(fixture("v1-midmark-100000-raw.rumstate"), None).unwrap();
+    assert_eq!(compressed, raw);
+}
+
+#[test]
+fn v1_delta_chain_matches_replay() {
+    let chained = load_chain(&[fixture("v1-midmark-100000.rumstate"), fixture("v1-midmark-200000-delta.rumstate")], None).unwrap();
+
+    let program = rum::load::load(Some(&format!("{BIN}/midmark.um"))).unwrap();
+    let start = rum::state::UniversalMachine::from_parts([0; 8], vec![program], Vec::new(), 0, Vec::new());
+    let log = ReplayLog { start, inputs: Vec::new(), end: None };
+    let mut replay = Replay::new(&log);
+    replay.run_to(200_000).unwrap();
+    assert_eq!(&chained, replay.state());
+}
+
+#[test]
+fn migrated_state_saves_as_current_version() {
+    let state = load_snapshot(fixture("v1-midmark-100000.rumstate"), None).unwrap();
+    let bytes = snapshot::encode(&state, None);
+    assert_eq!(u16::from_be_bytes([bytes[8], bytes[9]]), VERSION);
+    assert_eq!(snapshot::decode(&bytes, None).unwrap(), state);
+}
@@ -0,0 +1,32 @@
+//! Machine states at fixed instruction counts, recorded in tests/snapshots.
+//! Changes to how the interpreter stores memory must leave them alone;
+//! rerun with RUM_UPDATE_SNAPSHOTS=1 only when the program's behaviour is
+//! meant to change.
+
+use rum::testing::{assert_snapshot, state_at};
+
+const BIN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/bin");
+const SNAPSHOTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots");
+
+fn check(program: &str, input: &[u8], instructions: u64) {
+    let bytes = std::fs::read(format!("{BIN}/{program}.um")).unwrap();
+    assert_snapshot(format!("{SNAPSHOTS}/{program}-{instructions}.state"), &state_at(&bytes, input, instructions));
+}
+
+#[test]
+fn hello() {
+    check("hello", b"", 10);
+    check("hello", b"", 1_000_000);
+}
+
+#[test]
+fn cat() {
+    check("cat", b"snapshot\n", 20);
+}
+
+#[test]
+fn midmark() {
+    for instructions in [0, 1_000, 100_000] {
+        check("midmark", b"", instructions);
+    }
+}
@@ -0,0 +1,59 @@
+use rum::state::UniversalMachine;
+use rum::statediff::{diff, SegmentDiff};
+
+fn machine() -> UniversalMachine {
+    UniversalMachine::from_parts([0; 8], vec![vec![0x7000_0000; 4], vec![0; 32], vec![5; 4], Vec::new()], vec![3], 0, Vec::new())
+}
+
+#[test]
+fn identical_states_have_no_differences() {
+    let state = machine();
+    let same = diff(&state, &state.clone());
+    assert!(same.is_empty());
+    assert_eq!(same.to_string(), "states are identical\n");
+}
+
+#[test]
+fn every_kind_of_change_is_reported() {
+    let before = machine();
+    let mut after = before.clone();
+    after.load_value(2, 0xdead);
+    after.program_counter = 3;
+    let segment = after.segment_mut(1).unwrap();
+    segment[4] = 1;
+    segment[5] = 1;
+    segment[20] = 1;
+    segment.push(0);
+    after.load_value(7, 1);
+    after.map_seg(5, 7);
+    after.load_value(6, 2);
+    after.unmap_seg(6).unwrap();
+
+    let changes = diff(&before, &after);
+    assert_eq!(changes.registers, [(2, 0, 0xdead), (5, 0, 3), (6, 0, 2), (7, 0, 1)]);
+    assert_eq!(changes.program_counter, Some((0, 3)));
+    assert_eq!((changes.added.as_slice(), changes.removed.as_slice()), (&[3][..], &[2][..]));
+    assert_eq!(changes.changed, [SegmentDiff { index: 1, len: (32, 33), ranges: vec![(4, 6), (20, 21), (32, 33)] }]);
+    assert_eq!(
+        changes.to_string(),
+        "pc: 0 -> 3\n\
+         r2: 0x00000000 -> 0x0000dead\n\
+         r5: 0x00000000 -> 0x00000003\n\
+         r6: 0x00000000 -> 0x00000002\n\
+         r7: 0x00000000 -> 0x00000001\n\
+         segments mapped: 3\n\
+         segments unmapped: 2\n\
+         segment 1: 4 words changed (length 32 -> 33) at 4..6, 20..21, 32..33\n"
+    );
+}
+
+#[test]
+fn long_lists_are_cut_short() {
+    let before = machine();
+    let mut after = before.clone();
+    for offset in (0..32).step_by(2) {
+        after.segment_mut(1).unwrap()[offset] = 1;
+    }
+    let text = diff(&before, &after).to_string();
+    assert_eq!(text, "segment 1: 16 words changed at 0..1, 2..3, 4..5, 6..7, 8..9, 10..11, 12..13, 14..15 and 8 more\n");
+}
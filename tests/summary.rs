@@ -0,0 +1,27 @@
+use rum::clock::Clock;
+use rum::embed::Machine;
+use rum::instruction::Instruction::*;
+use rum::summary::{Exit, Summary};
+use rum::testing::words;
+
+#[test]
+fn summary_counts_output_and_peak_memory() {
+    let program = [Orthography { a: 1, value: 4 }, Map { b: 2, c: 1 }, Orthography { a: 3, value: 65 }, Output { c: 3 }, Unmap { c: 2 }, Map { b: 2, c: 3 }, Halt];
+    let mut machine = Machine::new(words(&program));
+    let summary = Summary::new(&mut machine.state, Clock::Virtual);
+    machine.run(None, &mut || None, &mut |_| {}).unwrap();
+    let json = summary.to_json(&Exit::Halted, machine.executed(), &machine.state);
+    assert_eq!(json.get("exit").and_then(|v| v.as_str()), Some("halted"));
+    assert_eq!(json.get("exit_code").and_then(|v| v.as_u64()), Some(0));
+    assert_eq!(json.get("instructions").and_then(|v| v.as_u64()), Some(6));
+    assert_eq!(json.get("output_bytes").and_then(|v| v.as_u64()), Some(1));
+    // The program's 7 words and the 65-word segment, mapped once the 4-word one was unmapped.
+    assert_eq!(json.get("peak_memory_bytes").and_then(|v| v.as_u64()), Some((7 + 65) * 4));
+    // The virtual clock doesn't run while waiting for input.
+    assert_eq!(json.get("input_wait_seconds").and_then(|v| v.as_u64()), Some(0));
+
+    let fault = Exit::Fault { message: "boom".into(), pc: 3 };
+    let json = summary.to_json(&fault, 3, &machine.state);
+    assert_eq!(json.get("exit_code").and_then(|v| v.as_u64()), Some(101));
+    assert_eq!(json.get("error").and_then(|e| e.get("pc")).and_then(|v| v.as_u64()), Some(3));
+}
@@ -0,0 +1,74 @@
+use rum::clock::Clock;
+use rum::embed::{Machine, Status, Stepper};
+use rum::error::MachineError;
+use rum::instruction::Instruction::*;
+use rum::syscall::{self, Return, Syscalls, ARG, ARGC, CLOSE, EXIT, OPEN, READ, WRITE};
+
+#[test]
+fn arguments_reach_the_program_and_exit_sets_the_code() {
+    let program = vec![
+        u32::from(Orthography { a: 1, value: ARGC }),
+        syscall::encode(1, 0, 0),
+        // Segment 1 gets argument 1 ("hey").
+        u32::from(Orthography { a: 2, value: 3 }),
+        u32::from(Map { b: 3, c: 2 }),
+        u32::from(Orthography { a: 2, value: 1 }),
+        u32::from(Orthography { a: 4, value: ARG }),
+        syscall::encode(4, 2, 3),
+        u32::from(Orthography { a: 5, value: 1 }),
+        u32::from(Load { a: 6, b: 3, c: 5 }),
+        u32::from(Output { c: 6 }),
+        u32::from(Orthography { a: 7, value: EXIT }),
+        syscall::encode(7, 1, 0),
+        u32::from(Halt),
+    ];
+    let mut syscalls = Syscalls::new(Machine::new(program));
+    syscalls.register_standard(vec!["first".into(), "hey".into()], Clock::Virtual);
+    let mut output = Vec::new();
+    assert_eq!(syscalls.run(None, &mut || None, &mut |byte| output.push(byte)), Ok(Status::Halted));
+    // ARGC left 2 in r1, ARG the length 3 in r4; EXIT stopped before the Halt.
+    assert_eq!((output, syscalls.machine.state.register(4), syscalls.exit_code(), syscalls.calls), (b"e".to_vec(), 3, Some(2), 3));
+    assert_eq!(syscalls.machine.state.program_counter, 12);
+}
+
+#[test]
+fn files_open_read_write_and_close() {
+    let path = std::env::temp_dir().join(format!("rum-syscall-{}", std::process::id()));
+    let name: Vec<u32> = path.to_str().unwrap().bytes().map(u32::from).collect();
+    let mut machine = Machine::new(vec![u32::from(Halt)]);
+    machine.state.mapped_memory.push(name);
+    let mut syscalls = Syscalls::new(machine);
+    syscalls.register_standard(Vec::new(), Clock::Virtual);
+    let call = |syscalls: &mut Syscalls, number, b, c| {
+        syscalls.machine.state.load_value(0, number);
+        syscalls.machine.state.load_value(1, b);
+        syscalls.machine.state.load_value(2, c);
+        syscalls.machine.state.mapped_memory[0][0] = syscall::encode(0, 1, 2);
+        syscalls.machine.state.program_counter = 0;
+        syscalls.step(&mut || None, &mut |_| {}).unwrap();
+        syscalls.machine.state.register(0)
+    };
+    let handle = call(&mut syscalls, OPEN, 1, 1);
+    assert_eq!(call(&mut syscalls, WRITE, handle, b'!' as u32), 0);
+    assert_eq!(call(&mut syscalls, CLOSE, handle, 0), 0);
+    let handle = call(&mut syscalls, OPEN, 1, 0);
+    assert_eq!([READ, READ].map(|number| call(&mut syscalls, number, handle, 0)), [b'!' as u32, syscall::FAILED]);
+    assert_eq!(call(&mut syscalls, WRITE, handle, 0), syscall::FAILED);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn hosts_add_services_and_unknown_ones_fault() {
+    let program = vec![u32::from(Orthography { a: 0, value: 100 }), u32::from(Orthography { a: 1, value: 20 }), syscall::encode(0, 1, 1), syscall::encode(1, 1, 1)];
+    let mut syscalls = Syscalls::new(Machine::new(program));
+    syscalls.register(100, Box::new(|_, b, c| Ok(Return::Value(b + c))));
+    syscalls.run(Some(3), &mut || None, &mut |_| {}).unwrap();
+    assert_eq!(syscalls.machine.state.register(0), 40);
+    let fault = syscalls.step(&mut || None, &mut |_| {}).unwrap_err();
+    assert_eq!(fault, MachineError::HostFault { pc: 3, message: "no service for syscall 20".to_string() });
+    // The faulting word again, asking for the virtual time.
+    syscalls.register_standard(Vec::new(), Clock::Virtual);
+    syscalls.machine.state.load_value(1, rum::syscall::TIME);
+    syscalls.step(&mut || None, &mut |_| {}).unwrap();
+    assert_eq!(syscalls.machine.state.register(1), rum::clock::EPOCH as u32);
+}
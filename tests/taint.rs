@@ -0,0 +1,49 @@
+use rum::embed::Status;
+use rum::instruction::Instruction::*;
+use rum::taint::{self, BranchKind};
+use rum::testing::words;
+
+#[test]
+fn output_depends_on_the_input_it_was_computed_from() {
+    // Outputs in[0] + in[1], then in[2] after a round trip through a segment, then a constant.
+    let program = words(&[
+        Input { c: 1 },
+        Input { c: 2 },
+        Add { a: 3, b: 1, c: 2 },
+        Output { c: 3 },
+        Input { c: 4 },
+        Orthography { a: 5, value: 1 },
+        Map { b: 6, c: 5 },
+        Store { a: 6, b: 0, c: 4 },
+        Load { a: 7, b: 6, c: 0 },
+        Output { c: 7 },
+        Output { c: 5 },
+        Halt,
+    ]);
+    let mut input = b"abc".iter().copied();
+    let (tracker, result) = taint::track(program, None, &mut || input.next(), &mut |_| {});
+    assert_eq!(result, Ok(Status::Halted));
+    let outputs: Vec<_> = tracker.outputs().map(|(byte, inputs)| (byte, inputs.to_vec())).collect();
+    assert_eq!(outputs, [(b'a' + b'b', vec![0, 1]), (b'c', vec![2]), (1, vec![])]);
+    assert_eq!((tracker.read(), tracker.memory(1, 0), tracker.register(6)), (3, &[2][..], &[][..]));
+    assert!(tracker.report().contains("3 input bytes read; 2 of 3 output bytes and 0 branches depend on input"));
+}
+
+#[test]
+fn branches_on_input_are_reported() {
+    // Jumps to 6 (skipping the Output at 5) if the input byte is nonzero.
+    let program = words(&[
+        Input { c: 1 },
+        Orthography { a: 2, value: 5 },
+        Orthography { a: 3, value: 6 },
+        CMov { a: 2, b: 3, c: 1 },
+        LoadProgram { b: 0, c: 2 },
+        Output { c: 1 },
+        Halt,
+    ]);
+    let mut input = Some(b'x');
+    let (tracker, _) = taint::track(program, None, &mut || input.take(), &mut |_| {});
+    let branches: Vec<_> = tracker.branches().map(|branch| (branch.pc, branch.kind, branch.count, tracker.labels().get(branch.label).to_vec())).collect();
+    assert_eq!(branches, [(3, BranchKind::Condition, 1, vec![0]), (4, BranchKind::Jump, 1, vec![0])]);
+    assert_eq!(taint::ranges(&[0, 1, 2, 5, 7, 8]), "0-2,5,7-8");
+}
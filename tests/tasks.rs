@@ -0,0 +1,70 @@
+use rum::embed::{Machine, Status, Stepper};
+use rum::error::MachineError;
+use rum::instruction::Instruction::{self, *};
+use rum::tasks::{Scheduler, SEGMENT, SELF, SPAWN, SPAWNED, YIELD};
+use rum::testing::words;
+
+/// Outputs `byte`, yields, outputs it again and halts.
+fn task(byte: u32) -> Vec<Instruction> {
+    vec![
+        Orthography { a: 1, value: SEGMENT },
+        Orthography { a: 2, value: byte },
+        Output { c: 2 },
+        Orthography { a: 3, value: YIELD as u32 },
+        Store { a: 1, b: 3, c: 0 },
+        Output { c: 2 },
+        Halt,
+    ]
+}
+
+#[test]
+fn contexts_take_turns_until_the_last_halts() {
+    let program = words(&[task(b'a' as u32), task(b'b' as u32)].concat());
+    let mut scheduler = Scheduler::new(Machine::new(program)).unwrap();
+    assert_eq!(scheduler.spawn(7, [0; 8]), 1);
+    let mut output = Vec::new();
+    assert_eq!(scheduler.run(None, &mut || None, &mut |byte| output.push(byte)), Ok(Status::Halted));
+    assert_eq!((output, scheduler.switches, scheduler.current()), (b"abab".to_vec(), 3, 1));
+}
+
+#[test]
+fn programs_spawn_contexts_with_their_registers() {
+    let mut program = vec![
+        Orthography { a: 1, value: SEGMENT },
+        Orthography { a: 2, value: b'x' as u32 },
+        Orthography { a: 3, value: SPAWN as u32 },
+        Orthography { a: 4, value: 8 },
+        Store { a: 1, b: 3, c: 4 },
+        Orthography { a: 3, value: YIELD as u32 },
+        Store { a: 1, b: 3, c: 0 },
+        Halt,
+    ];
+    // At 8: outputs the spawner's r2 and its own identifier, then exits.
+    program.extend([
+        Output { c: 2 },
+        Orthography { a: 3, value: SELF as u32 },
+        Load { a: 5, b: 1, c: 3 },
+        Output { c: 5 },
+        Orthography { a: 3, value: rum::tasks::EXIT as u32 },
+        Store { a: 1, b: 3, c: 0 },
+    ]);
+    let mut scheduler = Scheduler::new(Machine::new(words(&program))).unwrap();
+    let mut output = Vec::new();
+    scheduler.run(Some(7), &mut || None, &mut |byte| output.push(byte)).unwrap();
+    assert_eq!((scheduler.contexts(), scheduler.machine.state.mapped_memory[1][SPAWNED]), (2, 1));
+    assert_eq!(scheduler.run(Some(6), &mut || None, &mut |byte| output.push(byte)), Ok(Status::Running));
+    // Context 1 exited, so context 0 resumed after its yield, at its Halt.
+    assert_eq!((&output[..], scheduler.contexts(), scheduler.current()), (&[b'x', 1][..], 1, 0));
+    assert_eq!(scheduler.run(None, &mut || None, &mut |byte| output.push(byte)), Ok(Status::Halted));
+}
+
+#[test]
+fn the_control_segment_cannot_be_unmapped() {
+    let program = words(&[Orthography { a: 1, value: SEGMENT }, Unmap { c: 1 }, Orthography { a: 3, value: YIELD as u32 }, Store { a: 1, b: 3, c: 0 }, Halt]);
+    let mut scheduler = Scheduler::new(Machine::new(program)).unwrap();
+    scheduler.spawn(4, [0; 8]);
+    assert_eq!(scheduler.run(None, &mut || None, &mut |_| {}), Err(MachineError::UnmappedSegment { pc: 1, segment: SEGMENT.into() }));
+    let mut mapped = Machine::new(vec![u32::from(Halt)]);
+    mapped.state.mapped_memory.push(Vec::new());
+    assert!(Scheduler::new(mapped).is_err());
+}
@@ -0,0 +1,15 @@
+use std::time::{Duration, Instant};
+
+use rum::throttle::Throttle;
+
+#[test]
+fn throttle_holds_the_rate_down() {
+    let mut throttle = Throttle::new(0.1);
+    assert_eq!(throttle.every(), 1000);
+    let start = Instant::now();
+    for count in (0..=5000).step_by(throttle.every() as usize) {
+        throttle.pace(count);
+    }
+    // 5000 instructions at 100,000 a second.
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
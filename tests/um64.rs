@@ -0,0 +1,52 @@
+use rum::embed::{Status, Stepper};
+use rum::error::MachineError;
+use rum::instruction::Instruction::{self, *};
+use rum::um64::{self, Machine64};
+
+#[test]
+fn registers_and_segments_hold_64_bit_values() {
+    let program = vec![
+        um64::load_value(1, 1 << 40),
+        um64::encode(Orthography { a: 2, value: 3 }),
+        um64::encode(Mul { a: 3, b: 1, c: 2 }),
+        um64::encode(Map { b: 4, c: 2 }),
+        um64::encode(Orthography { a: 2, value: 2 }),
+        um64::encode(Store { a: 4, b: 2, c: 3 }),
+        um64::encode(Load { a: 5, b: 4, c: 2 }),
+        // (3 << 40) >> 40 by division, then '0' + 3.
+        um64::encode(Div { a: 6, b: 5, c: 1 }),
+        um64::load_value(7, 48),
+        um64::encode(Add { a: 6, b: 6, c: 7 }),
+        um64::encode(Output { c: 6 }),
+        um64::encode(Halt),
+    ];
+    let mut machine = Machine64::new(program);
+    let mut output = Vec::new();
+    assert_eq!(machine.run(None, &mut || None, &mut |byte| output.push(byte)), Ok(Status::Halted));
+    assert_eq!((output, machine.state.mapped_memory[1][2], machine.executed()), (b"3".to_vec(), 3 << 40, 11));
+}
+
+#[test]
+fn faults_are_returned_with_64_bit_operands() {
+    let run = |program: Vec<u64>| Machine64::new(program).run(None, &mut || None, &mut |_| {});
+    assert_eq!(run(vec![um64::encode(Div { a: 1, b: 1, c: 2 })]), Err(MachineError::DivisionByZero { pc: 0 }));
+    assert_eq!(run(vec![um64::load_value(1, 1 << 40), um64::encode(Output { c: 1 })]), Err(MachineError::OutputOutOfRange { pc: 1, value: 1 << 40 }));
+    assert_eq!(run(vec![um64::load_value(1, 1 << 40), um64::encode(Load { a: 2, b: 1, c: 0 })]), Err(MachineError::UnmappedSegment { pc: 1, segment: 1 << 40 }));
+    assert_eq!(run(vec![um64::encode(Unmap { c: 0 })]), Err(MachineError::UnmappedSegment { pc: 0, segment: 0 }));
+    assert!(matches!(run(vec![14 << 60]), Err(MachineError::InvalidOpcode { pc: 0, .. })));
+    assert_eq!(run(vec![um64::encode(Nand { a: 1, b: 1, c: 1 })]), Err(MachineError::ProgramCounterOutOfBounds { pc: 1 }));
+}
+
+#[test]
+fn encoding_round_trips_through_the_um_decoder() {
+    let instructions = [CMov { a: 1, b: 2, c: 3 }, Nand { a: 7, b: 0, c: 5 }, Halt, Map { b: 1, c: 2 }, Unmap { c: 4 }, Input { c: 6 }, LoadProgram { b: 3, c: 1 }];
+    for inst in instructions {
+        assert_eq!(um64::decode(um64::encode(inst)), inst);
+    }
+    let word = um64::load_value(5, u64::MAX);
+    assert_eq!((um64::decode(word), word & um64::IMMEDIATE), (Orthography { a: 5, value: 0 }, um64::IMMEDIATE));
+    assert!(matches!(um64::decode(14 << 60), Instruction::Invalid(_)));
+    let bytes: Vec<u8> = [um64::encode(Halt)].iter().flat_map(|word| word.to_be_bytes()).collect();
+    assert_eq!(um64::parse(&bytes), Ok(vec![7 << 60]));
+    assert!(um64::parse(&bytes[..4]).is_err());
+}
@@ -0,0 +1,18 @@
+use std::path::Path;
+
+use rum::watch::command;
+
+#[test]
+fn each_run_gets_the_watched_options() {
+    let options = ["--max-instructions", "1000", "--progress", "--stdin", "y\\n"].map(String::from);
+    let command = command(Path::new("rum"), Path::new("prog.um"), &options);
+    assert_eq!(command.get_program(), "rum");
+    assert_eq!(command.get_args().collect::<Vec<_>>(), ["run", "--max-instructions", "1000", "--progress", "--stdin", "y\\n", "prog.um"]);
+}
+
+#[test]
+fn the_program_goes_before_its_arguments() {
+    let options = ["--syscalls", "--coverage", "cov.txt", "--", "in.txt"].map(String::from);
+    let command = command(Path::new("rum"), Path::new("prog.um"), &options);
+    assert_eq!(command.get_args().collect::<Vec<_>>(), ["run", "--syscalls", "--coverage", "cov.txt", "prog.um", "--", "in.txt"]);
+}
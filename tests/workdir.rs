@@ -0,0 +1,34 @@
+use std::io::Write;
+
+use rum::json::Value;
+use rum::snapshot::load_snapshot;
+use rum::state::UniversalMachine;
+use rum::workdir::Workdir;
+
+#[test]
+fn runs_get_their_own_directories_with_everything_in_them() {
+    let base = std::env::temp_dir().join(format!("rum-workdir-{}", std::process::id()));
+    let command = vec!["run".to_string(), "--workdir".to_string(), "runs".to_string()];
+    let first = Workdir::create(&base, &command).unwrap();
+    let second = Workdir::create(&base, &command).unwrap();
+    assert_ne!(first.path(), second.path());
+    let name = first.path().file_name().unwrap().to_str().unwrap();
+    assert!(name.starts_with("run-20") && name[4..].starts_with(|c: char| c.is_ascii_digit()), "{name}");
+    assert_eq!(std::fs::read_to_string(first.path().join("command")).unwrap(), "rum run --workdir runs\n");
+    assert_eq!(first.record(), first.path().join("input.rumreplay"));
+
+    let mut transcript = first.transcript().unwrap();
+    transcript.write_all(b"Hello").unwrap();
+    let mut state = UniversalMachine::new();
+    state.mapped_memory.push(vec![7 << 28]);
+    state.load_value(3, 42);
+    let stats = Value::object([("exit", Value::String("fault".to_string()))]);
+    first.finish(&stats, Some(&mut transcript), Some(&state), None).unwrap();
+    assert_eq!(std::fs::read(first.path().join("output")).unwrap(), b"Hello");
+    assert_eq!(std::fs::read_to_string(first.path().join("stats.json")).unwrap(), "{\"exit\":\"fault\"}\n");
+    assert_eq!(load_snapshot(first.path().join("core.rumstate"), None).unwrap().register(3), 42);
+
+    second.finish(&stats, None, None, None).unwrap();
+    assert!(!second.path().join("core.rumstate").exists());
+    std::fs::remove_dir_all(base).unwrap();
+}